@@ -11,5 +11,28 @@ fn main() -> Result<(), Box<dyn Error>> {
           .git_commit_timestamp()
           .git_describe(true, true, None)
           .emit()?;
+
+    emit_build_info()?;
+    Ok(())
+}
+
+/// Emit additional build information that is picked up by src/build_info.rs
+fn emit_build_info() -> Result<(), Box<dyn Error>> {
+    let mut features = std::env::vars()
+        .filter_map(|(k, _)| k.strip_prefix("CARGO_FEATURE_").map(|f| f.to_lowercase().replace('_', "-")))
+        .collect::<Vec<_>>();
+    features.sort();
+    println!("cargo:rustc-env=BUTIDO_BUILD_FEATURES={}", features.join(","));
+    println!("cargo:rustc-env=BUTIDO_BUILD_TARGET={}", std::env::var("TARGET")?);
+
+    // The schema version is the name of the latest migration
+    println!("cargo:rerun-if-changed=migrations");
+    let schema_version = std::fs::read_dir("migrations")?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_dir())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .max()
+        .unwrap_or_default();
+    println!("cargo:rustc-env=BUTIDO_SCHEMA_VERSION={schema_version}");
     Ok(())
 }
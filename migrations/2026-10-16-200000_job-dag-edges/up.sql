@@ -0,0 +1,20 @@
+--
+-- Copyright (c) 2020-2022 science+computing ag and other contributors
+--
+-- This program and the accompanying materials are made
+-- available under the terms of the Eclipse Public License 2.0
+-- which is available at https://www.eclipse.org/legal/epl-2.0/
+--
+-- SPDX-License-Identifier: EPL-2.0
+--
+
+-- Your SQL goes here
+CREATE TABLE job_dag_edges (
+    id SERIAL PRIMARY KEY NOT NULL,
+    submit_id INTEGER REFERENCES submits(id) NOT NULL,
+    package_id INTEGER REFERENCES packages(id) NOT NULL,
+    job_uuid UUID NOT NULL,
+    dependency_uuid UUID DEFAULT NULL,
+
+    CONSTRAINT UC_submitid_jobuuid_dependencyuuid UNIQUE (submit_id, job_uuid, dependency_uuid)
+)
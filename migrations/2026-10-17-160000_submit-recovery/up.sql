@@ -0,0 +1,27 @@
+--
+-- Copyright (c) 2020-2022 science+computing ag and other contributors
+--
+-- This program and the accompanying materials are made
+-- available under the terms of the Eclipse Public License 2.0
+-- which is available at https://www.eclipse.org/legal/epl-2.0/
+--
+-- SPDX-License-Identifier: EPL-2.0
+--
+
+-- Your SQL goes here
+CREATE TABLE submit_plans (
+    id SERIAL PRIMARY KEY NOT NULL,
+    submit_id INTEGER REFERENCES submits(id) NOT NULL UNIQUE,
+    plan TEXT NOT NULL
+);
+
+CREATE TABLE job_states (
+    id SERIAL PRIMARY KEY NOT NULL,
+    submit_id INTEGER REFERENCES submits(id) NOT NULL,
+    job_uuid UUID NOT NULL,
+    state VARCHAR NOT NULL,
+    endpoint_id INTEGER REFERENCES endpoints(id),
+    container_hash VARCHAR,
+
+    CONSTRAINT UC_submitid_jobuuid UNIQUE (submit_id, job_uuid)
+);
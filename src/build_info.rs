@@ -0,0 +1,101 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Information about the butido build, populated by the build script
+
+use std::io::Write;
+
+use anyhow::Result;
+use serde::Serialize;
+
+/// The container runtime backends butido can talk to
+const RUNTIME_BACKENDS: &[&str] = &["docker", "kubernetes", "local"];
+
+#[derive(Debug, Serialize)]
+pub struct BuildInfo {
+    version: &'static str,
+    git_describe: &'static str,
+    git_sha: &'static str,
+    git_commit_timestamp: &'static str,
+    build_timestamp: &'static str,
+    debug_build: bool,
+    target: &'static str,
+    features: Vec<&'static str>,
+    runtime_backends: &'static [&'static str],
+    schema_version: &'static str,
+}
+
+impl BuildInfo {
+    pub fn get() -> Self {
+        BuildInfo {
+            version: env!("CARGO_PKG_VERSION"),
+            git_describe: env!("VERGEN_GIT_DESCRIBE"),
+            git_sha: env!("VERGEN_GIT_SHA"),
+            git_commit_timestamp: env!("VERGEN_GIT_COMMIT_TIMESTAMP"),
+            build_timestamp: env!("VERGEN_BUILD_TIMESTAMP"),
+            debug_build: env!("VERGEN_CARGO_DEBUG") == "true",
+            target: env!("BUTIDO_BUILD_TARGET"),
+            features: env!("BUTIDO_BUILD_FEATURES")
+                .split(',')
+                .filter(|f| !f.is_empty())
+                .collect(),
+            runtime_backends: RUNTIME_BACKENDS,
+            schema_version: env!("BUTIDO_SCHEMA_VERSION"),
+        }
+    }
+
+    /// Print the build information, either human readable or as JSON
+    pub fn print(&self, verbose: bool, json: bool) -> Result<()> {
+        let mut out = std::io::stdout();
+        if json {
+            return serde_json::to_writer_pretty(&mut out, self)
+                .map_err(anyhow::Error::from)
+                .and_then(|_| writeln!(out).map_err(anyhow::Error::from));
+        }
+
+        writeln!(out, "butido {}", self.git_describe)?;
+        writeln!(out, "Git SHA:              {}", self.git_sha)?;
+        writeln!(out, "Git Commit Timestamp: {}", self.git_commit_timestamp)?;
+        writeln!(out, "Build Timestamp:      {}", self.build_timestamp)?;
+        writeln!(out, "Debug Build:          {}", self.debug_build)?;
+
+        if verbose {
+            let features = if self.features.is_empty() {
+                String::from("none")
+            } else {
+                self.features.join(", ")
+            };
+
+            writeln!(out, "Version:              {}", self.version)?;
+            writeln!(out, "Target:               {}", self.target)?;
+            writeln!(out, "Features:             {features}")?;
+            writeln!(out, "Runtime Backends:     {}", self.runtime_backends.join(", "))?;
+            writeln!(out, "Schema Version:       {}", self.schema_version)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_schema_version_is_latest_migration() {
+        let latest = std::fs::read_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/migrations"))
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .max()
+            .unwrap();
+
+        assert_eq!(BuildInfo::get().schema_version, latest);
+    }
+}
@@ -42,6 +42,22 @@ pub fn cli() -> Command {
             .help("Detailed version output with build information")
         )
 
+        .arg(Arg::new("verbose")
+            .action(ArgAction::SetTrue)
+            .required(false)
+            .long("verbose")
+            .requires("version")
+            .help("Also print target, enabled features, runtime backends and expected database schema version")
+        )
+
+        .arg(Arg::new("json")
+            .action(ArgAction::SetTrue)
+            .required(false)
+            .long("json")
+            .requires("version")
+            .help("Print the complete version information as JSON")
+        )
+
         .arg(Arg::new("hide_bars")
             .action(ArgAction::SetTrue)
             .required(false)
@@ -49,6 +65,60 @@ pub fn cli() -> Command {
             .help("Hide all progress bars")
         )
 
+        .arg(Arg::new("verbosity")
+            .required(false)
+            .long("verbosity")
+            .value_name("LEVEL")
+            .value_parser(["silent", "summary", "full"])
+            .conflicts_with("hide_bars")
+            .help("How many progress bars are shown (default: full)")
+            .long_help(indoc::indoc!(r#"
+                How many progress bars are shown:
+
+                    silent:  no progress bars, like --hide-bars
+                    summary: only the bars that show the overall progress of a command
+                    full:    all bars, including one bar per job or download (default)
+            "#))
+        )
+
+        .arg(Arg::new("profile")
+            .required(false)
+            .long("profile")
+            .value_name("NAME")
+            .help("Apply the settings of a configuration profile ([profiles.NAME] in the configuration)")
+        )
+
+        .arg(Arg::new("output")
+            .required(false)
+            .long("output")
+            .value_name("MODE")
+            .value_parser(["plain", "fancy", "json"])
+            .help("How output is presented (default: fancy if stdout is a terminal, plain otherwise)")
+            .long_help(indoc::indoc!(r#"
+                How output is presented:
+
+                    fancy: progress bars, colors and tables (default if stdout is a terminal)
+                    plain: no progress bars and colors, tables as whitespace-separated lines
+                    json:  no progress bars and colors, tables as JSON
+            "#))
+        )
+
+        .arg(Arg::new("color")
+            .required(false)
+            .long("color")
+            .value_name("WHEN")
+            .value_parser(["auto", "always", "never"])
+            .default_value("auto")
+            .help("When output is colored (default: auto)")
+            .long_help(indoc::indoc!(r#"
+                When output, including highlighted scripts, is colored:
+
+                    auto:   with the fancy output, unless the NO_COLOR environment variable is set (default)
+                    always: regardless of the output mode, e.g. for CI logs that are not a terminal
+                    never:  never, e.g. for logs that are piped into files
+            "#))
+        )
+
         .arg(Arg::new("database_host")
             .required(false)
             .long("db-url")
@@ -165,6 +235,45 @@ pub fn cli() -> Command {
                     .value_name("JOB UUID")
                     .help("Print only artifacts for a certain job")
                 )
+                .arg(Arg::new("for_package")
+                    .required(false)
+                    .long("for-package")
+                    .num_args(1..=2)
+                    .value_names(["NAME", "VERSION"])
+                    .conflicts_with_all(["job_uuid", "metadata"])
+                    .help("List all artifacts that were ever built for package NAME (of VERSION)")
+                    .long_help(indoc::indoc!(r#"
+                        List all artifacts that were ever built for package NAME, optionally only for VERSION, with the
+                        submit and the job that built them, their path, their SHA256 hash and whether they were released.
+
+                        The path is the path in the release store for released artifacts, otherwise the path in the
+                        staging directory of the submit, which might have been removed since.
+                        The hash is only known for artifacts that were built after butido started recording it.
+                    "#))
+                )
+                .arg(arg_user_filter("Print only artifacts of submits by USER"))
+                .arg(Arg::new("kind")
+                    .required(false)
+                    .action(ArgAction::Append)
+                    .long("kind")
+                    .value_name("KIND")
+                    .value_delimiter(',')
+                    .help("Print only artifacts of KIND, e.g. 'package'")
+                    .long_help(indoc::indoc!(r#"
+                        Print only artifacts of KIND, can be passed multiple times.
+                        Installable packages are of the kind "package", the artifacts from additional outputs are of
+                        the kind of their output category, unless they are classified otherwise by the "artifact_kinds"
+                        rules in the configuration.
+                    "#))
+                )
+                .arg(Arg::new("metadata")
+                    .required(false)
+                    .action(ArgAction::Append)
+                    .long("metadata")
+                    .value_name("NAME")
+                    .value_delimiter(',')
+                    .help("Show the metadata NAME (the output of a capture command) of the jobs of the artifacts")
+                )
             )
 
             .subcommand(Command::new("envvars")
@@ -175,6 +284,35 @@ pub fn cli() -> Command {
                     .long("csv")
                     .help("Format output as CSV")
                 )
+                .arg(Arg::new("name")
+                    .required(false)
+                    .long("name")
+                    .value_name("NAME")
+                    .help("Only list variables named NAME, or NAME=VALUE for a specific value")
+                )
+                .arg(Arg::new("used_by_package")
+                    .required(false)
+                    .long("used-by-package")
+                    .value_name("PACKAGE")
+                    .help("Only list variables that jobs of PACKAGE ran with")
+                )
+                .arg(Arg::new("submit")
+                    .required(false)
+                    .long("submit")
+                    .value_name("UUID")
+                    .value_parser(uuid::Uuid::parse_str)
+                    .help("Only list variables that jobs of the submit UUID ran with")
+                )
+                .arg(Arg::new("jobs")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("jobs")
+                    .help("List the jobs that ran with the variables, one row per variable and job")
+                    .long_help(indoc::indoc!(r#"
+                        List the jobs that ran with the variables, one row per variable and job.
+                        Together with --name NAME=VALUE, this answers which jobs ran with a certain value.
+                    "#))
+                )
             )
 
             .subcommand(Command::new("images")
@@ -185,6 +323,31 @@ pub fn cli() -> Command {
                     .long("csv")
                     .help("Format output as CSV")
                 )
+                .arg(Arg::new("name")
+                    .required(false)
+                    .long("name")
+                    .value_name("NAME")
+                    .help("Only list the image NAME")
+                )
+                .arg(Arg::new("used_by_package")
+                    .required(false)
+                    .long("used-by-package")
+                    .value_name("PACKAGE")
+                    .help("Only list images that jobs of PACKAGE ran on")
+                )
+                .arg(Arg::new("submit")
+                    .required(false)
+                    .long("submit")
+                    .value_name("UUID")
+                    .value_parser(uuid::Uuid::parse_str)
+                    .help("Only list images that jobs of the submit UUID ran on")
+                )
+                .arg(Arg::new("jobs")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("jobs")
+                    .help("List the jobs that ran on the images, one row per image and job")
+                )
             )
 
             .subcommand(Command::new("submit")
@@ -195,6 +358,89 @@ pub fn cli() -> Command {
                     .value_name("SUBMIT")
                     .help("The Submit to show details about")
                 )
+                .arg(Arg::new("definitions")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("definitions")
+                    .conflicts_with("tree")
+                    .help("Show the definitions of all packages as they were at submit time")
+                )
+                .arg(Arg::new("tree")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("tree")
+                    .help("Show the job DAG of the submit as it was resolved at submit time")
+                    .long_help(indoc::indoc!(r#"
+                        Show the job DAG of the submit as it was resolved at submit time, with the result of
+                        each job. The DAG is loaded from the database, so this works even if the repository
+                        changed since the submit.
+                    "#))
+                )
+            )
+
+            .subcommand(Command::new("submit-diff")
+                .about("Compare the jobs of two submits")
+                .long_about(indoc::indoc!(r#"
+                    Compare the jobs of two submits
+
+                    For each package, the versions, results and durations of the jobs in both submits
+                    are listed, and whether the package was added or removed, whether its job was
+                    fixed or broken and whether the environment or the script of the job changed.
+                    Use "--output json" to get the comparison as JSON.
+                "#))
+                .arg(Arg::new("csv")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("csv")
+                    .help("Format output as CSV")
+                )
+                .arg(Arg::new("submit_a")
+                    .required(true)
+                    .index(1)
+                    .value_name("SUBMIT_A")
+                    .help("The submit to compare against")
+                )
+                .arg(Arg::new("submit_b")
+                    .required(true)
+                    .index(2)
+                    .value_name("SUBMIT_B")
+                    .help("The submit to compare")
+                )
+            )
+
+            .subcommand(Command::new("job-diff")
+                .about("Compare the scripts and environments of two jobs")
+                .long_about(indoc::indoc!(r#"
+                    Compare the scripts and environments of two jobs
+
+                    The scripts are shown as unified diff. Tabs are expanded and trailing whitespace is
+                    removed before comparing, so changes in indentation style only do not show up as
+                    changed lines. The environment variables that were added or removed are listed
+                    below the diff.
+                "#))
+                .arg(Arg::new("job_a")
+                    .required(true)
+                    .index(1)
+                    .value_name("JOB_A")
+                    .help("The job to compare against")
+                )
+                .arg(Arg::new("job_b")
+                    .required(true)
+                    .index(2)
+                    .value_name("JOB_B")
+                    .help("The job to compare")
+                )
+                .arg(Arg::new("context")
+                    .required(false)
+                    .long("context")
+                    .short('U')
+                    .value_name("LINES")
+                    .default_value("3")
+                    .value_parser(clap::value_parser!(usize))
+                    .help("Number of unchanged lines to show around each change")
+                )
+                .arg(script_arg_highlight())
+                .arg(script_arg_no_highlight())
             )
 
             .subcommand(Command::new("submits")
@@ -237,6 +483,15 @@ pub fn cli() -> Command {
                     .value_name("IMAGE")
                     .help("Limit listed submits to submits on IMAGE")
                 )
+                .arg(arg_user_filter("Limit listed submits to submits by USER"))
+                .arg(Arg::new("label")
+                    .required(false)
+                    .action(ArgAction::Append)
+                    .long("label")
+                    .value_name("KEY=VALUE")
+                    .value_parser(label_validator)
+                    .help("Limit listed submits to submits with this label (multiple labels must all match)")
+                )
             )
 
             .subcommand(Command::new("jobs")
@@ -291,6 +546,22 @@ pub fn cli() -> Command {
                     .help("Only show jobs for PKG")
                 )
 
+                .arg(arg_user_filter("Only show jobs of submits by USER"))
+
+                .arg(Arg::new("format")
+                    .required(false)
+                    .long("format")
+                    .value_name("FORMAT")
+                    .conflicts_with("csv")
+                    .help("Print each job with this handlebars format instead of a table")
+                    .long_help(indoc::indoc!(r#"
+                        Print each job with this handlebars format instead of a table.
+                        The variables available are: id, uuid, package, version, endpoint, success and duration,
+                        e.g. '{{package}} {{version}} on {{endpoint}}: {{success}} ({{duration}}s)'.
+                        'success' is "yes", "no" or "?", 'duration' is in seconds and empty if unknown.
+                    "#))
+                )
+
             )
 
             .subcommand(Command::new("job")
@@ -333,6 +604,28 @@ pub fn cli() -> Command {
                     .help("Show the environment of the job")
                 )
 
+                .arg(Arg::new("show_manifest")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("manifest")
+                    .short('M')
+                    .help("Show the manifest of the inputs of the job")
+                )
+
+                .arg(Arg::new("show_metadata")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("metadata")
+                    .help("Show the metadata of the job (the output of the capture commands of the package)")
+                )
+
+                .arg(Arg::new("show_definition")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("definition")
+                    .help("Show the definition of the package as it was when the job was submitted")
+                )
+
                 .arg(script_arg_line_numbers())
                 .arg(script_arg_no_line_numbers())
                 .arg(script_arg_highlight())
@@ -373,6 +666,28 @@ pub fn cli() -> Command {
                     .value_name("PKG")
                     .help("Only list releases for package PKG")
                 )
+
+                .arg(arg_user_filter("Only list releases of artifacts from submits by USER"))
+            )
+            .subcommand(Command::new("stats")
+                .about("Show the resource usage of the jobs, per package")
+                .arg(Arg::new("csv")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("csv")
+                    .help("Format output as CSV")
+                )
+
+                .arg(arg_older_than_date("Only include jobs older than DATE"))
+                .arg(arg_newer_than_date("Only include jobs newer than DATE"))
+
+                .arg(Arg::new("package")
+                    .required(false)
+                    .long("package")
+                    .short('p')
+                    .value_name("PKG")
+                    .help("Only show the resource usage of package PKG")
+                )
             )
         )
 
@@ -380,7 +695,7 @@ pub fn cli() -> Command {
             .about("Build packages in containers")
 
             .arg(Arg::new("package_name")
-                .required(true)
+                .required_unless_present("recover")
                 .index(1)
                 .value_name("NAME")
             )
@@ -390,6 +705,23 @@ pub fn cli() -> Command {
                 .value_name("VERSION")
                 .help("Exact package version to build (string match)")
             )
+            .arg(Arg::new("recover")
+                .required(false)
+                .long("recover")
+                .value_name("SUBMIT")
+                .conflicts_with_all(["package_name", "package_version", "image", "all_images", "staging_dir", "env", "phases", "skip_phase", "shebang"])
+                .help("Continue the submit SUBMIT, which was interrupted (e.g. because butido was killed)")
+                .long_help(indoc::indoc!(r#"
+                    Continue the submit SUBMIT, which was interrupted (e.g. because butido was killed).
+
+                    The package, image, environment, phases, shebang and staging directory are taken from the
+                    recorded plan of the submit, the repository has to be at the commit of the submit.
+                    Jobs that succeeded are not run again, their artifacts are reused from the staging directory.
+                    Failed jobs and jobs that did not finish run again. The containers of the jobs that were running
+                    when the submit was interrupted are stopped and, if the container cleanup policy removes the
+                    containers of failed jobs, removed.
+                "#))
+            )
 
             .arg(Arg::new("no_verification")
                 .action(ArgAction::SetTrue)
@@ -407,6 +739,93 @@ pub fn cli() -> Command {
                 .help("Skip linting")
                 .long_help(indoc::indoc!(r#"
                     Do not perform script linting before starting the build.
+                This also skips checking the scripts with shellcheck, if `shellcheck.pre_submit` is configured.
+                "#))
+            )
+            .arg(Arg::new("phases")
+                .required(false)
+                .action(ArgAction::Append)
+                .long("phases")
+                .value_name("PHASE")
+                .value_delimiter(',')
+                .help("Only run these phases, e.g. 'unpack,configure'")
+                .long_help(indoc::indoc!(r#"
+                    Only compile these phases into the packaging scripts, for example for debugging a build.
+                    The phases are run in the order of the configured "available_phases", regardless of the
+                    order they are passed in.
+                "#))
+            )
+            .arg(Arg::new("skip_phase")
+                .required(false)
+                .action(ArgAction::Append)
+                .long("skip-phase")
+                .value_name("PHASE")
+                .help("Do not run this phase, can be passed multiple times")
+            )
+            .arg(Arg::new("no_deps")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .long("no-deps")
+                .conflicts_with("deps_only")
+                .help("Only build the package, reuse the artifacts of its dependencies")
+                .long_help(indoc::indoc!(r#"
+                    Only build the package itself.
+                    The artifacts of all dependencies must already exist in the staging store (see --staging-dir)
+                    or the release stores, otherwise the build fails.
+                "#))
+            )
+            .arg(Arg::new("deps_only")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .long("deps-only")
+                .help("Only build the dependencies of the package, but not the package itself")
+            )
+            .arg(Arg::new("quiet_progress")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .long("quiet-progress")
+                .help("Print the overall status of the build as plain text line periodically instead of showing progress bars")
+                .long_help(indoc::indoc!(r#"
+                    Do not show progress bars, but print the overall status of the build (number of
+                    running, done and failed jobs) as plain text line periodically.
+                    This is useful in CI logs, where progress bars cannot be displayed.
+                "#))
+            )
+            .arg(Arg::new("max_parallel")
+                .required(false)
+                .long("max-parallel")
+                .value_name("N")
+                .value_parser(clap::value_parser!(usize))
+                .help("Maximum number of jobs that run on the endpoints at the same time (overrides 'build_max_parallel' from the configuration)")
+                .long_help(indoc::indoc!(r#"
+                    Maximum number of jobs of this submit that run on the endpoints at the same time
+                    (overrides 'build_max_parallel' from the configuration).
+                    This limit applies in addition to the "maxjobs" setting of each endpoint.
+                "#))
+            )
+            .arg(Arg::new("network_mode")
+                .required(false)
+                .long("network-mode")
+                .value_name("MODE")
+                .help("Network mode of the containers of this submit, e.g. 'none' (overrides the network mode of packages and endpoints)")
+                .long_help(indoc::indoc!(r#"
+                    Network mode of the containers of all jobs of this submit (overrides the network
+                    mode configured for the packages and the endpoints).
+                    Use "none" to build without any network access: the sources are copied into the
+                    containers before the build starts, so they do not have to be downloaded.
+                    Any other value is the name of a Docker network.
+                "#))
+            )
+            .arg(Arg::new("strict_versions")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .long("strict-versions")
+                .help("Fail if the dependency tree contains multiple versions of a package")
+                .long_help(indoc::indoc!(r#"
+                    By default, a dependency tree may contain several versions of the same package, if
+                    packages depend on different versions of it. Each dependent gets the artifacts of
+                    the version it depends on.
+                    With this flag, such a tree is an error.
                 "#))
             )
 
@@ -435,15 +854,92 @@ pub fn cli() -> Command {
                 .long_help(indoc::indoc!(r#"
                     Pass these variables to each build job.
                     This argument expects \"key=value\" or name of variable available in ENV
+
+                    Packages that declare the variables they accept with "allowed_env" only get these
+                    variables. Passing a variable that no package in the tree accepts is an error.
+                "#))
+            )
+
+            .arg(Arg::new("label")
+                .required(false)
+                .action(ArgAction::Append)
+                .long("label")
+                .value_name("KEY=VALUE")
+                .value_parser(label_validator)
+                .help("Tag the submits with a label")
+                .long_help(indoc::indoc!(r#"
+                    Tag the submits of this build with a free-form "key=value" label, e.g. a ticket number,
+                    a release train or the ID of a CI pipeline.
+                    Can be passed multiple times. Submits can be filtered by label with "db submits --label".
+                "#))
+            )
+
+            .arg(Arg::new("yes")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .short('y')
+                .long("yes")
+                .help("Do not ask for confirmation before starting the build")
+                .long_help(indoc::indoc!(r#"
+                    Do not ask for confirmation before starting the build.
+
+                    Without this flag, butido prints the plan of the build (the packages that are built for every image, in
+                    order, the endpoints and the environment) and asks for confirmation before starting any container.
+                    Without a terminal, butido does not ask and starts the build right away.
                 "#))
             )
 
+            .arg(Arg::new("endpoint_status")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .long("endpoint-status")
+                .help("Show the utilization of the endpoints while the jobs run")
+                .long_help(indoc::indoc!(r#"
+                    Show the utilization of the endpoints while the jobs run.
+
+                    One progress bar per endpoint shows the jobs of this submit running on the endpoint (of the maximum number
+                    of jobs of the endpoint), the containers running on the endpoint as reported by docker (including
+                    containers of other submits), the jobs that failed on the endpoint and the jobs that wait for a free
+                    endpoint.
+                "#))
+            )
+
+            .arg(Arg::new("lenient")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .long("lenient")
+                .help("Ignore variables passed with --env that no package accepts, instead of failing")
+            )
+
             .arg(Arg::new("image")
-                .required(true)
+                .required_unless_present_any(["all_images", "recover"])
+                .action(ArgAction::Append)
                 .value_name("IMAGE NAME")
                 .short('I')
                 .long("image")
                 .help("Name of the Docker image to use")
+                .long_help(indoc::indoc!(r#"
+                    Name of the Docker image to use.
+
+                    Can be passed multiple times to build the package for multiple images. In this case, the
+                    package is built for all images in one submit and the artifacts of each image are written to a
+                    directory named after the short name of the image in the staging directory.
+                "#))
+            )
+            .arg(Arg::new("all_images")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .long("all-images")
+                .conflicts_with("image")
+                .help("Build the package for all images it can be built on")
+                .long_help(indoc::indoc!(r#"
+                    Build the package for all images it can be built on: the images in "allowed_images" of the
+                    package, or all configured images if the package does not set "allowed_images", without the
+                    images in "denied_images".
+
+                    Like with multiple --image, all images are built in one submit and the artifacts of each image
+                    are written to a directory named after the short name of the image in the staging directory.
+                "#))
             )
 
             .arg(Arg::new("write-log-file")
@@ -459,6 +955,45 @@ pub fn cli() -> Command {
                     The log of a build is written to `<log_dir>/<build id>.log`.
                 "#))
             )
+
+            .arg(Arg::new("summary_file")
+                .required(false)
+                .long("summary-file")
+                .value_name("PATH")
+                .help("Write a machine-readable summary of the submit to this file")
+                .long_help(indoc::indoc!(r#"
+                    Write a machine-readable summary of the submit (jobs with status, durations, artifacts and
+                    error excerpts) to this file. The summary is written as TOML if the file has the extension
+                    "toml", as JSON otherwise.
+
+                    Overrides the 'submit_summary_file' setting from the configuration.
+                "#))
+            )
+
+            .arg(Arg::new("print_failure_report")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .long("print-failure-report")
+                .help("Print the report of the failed jobs as JSON")
+                .long_help(indoc::indoc!(r#"
+                    Print the report of the failed jobs (package, endpoint, container, failed phase, last log lines
+                    and error) as JSON if the submit fails.
+                    The report is always written to "<submit>-report.json" in the log directory.
+                "#))
+            )
+
+            .arg(Arg::new("metrics_file")
+                .required(false)
+                .long("metrics-file")
+                .value_name("PATH")
+                .help("Write Prometheus metrics of the build to this file")
+                .long_help(indoc::indoc!(r#"
+                    Write metrics of the build (jobs by status, failures and build durations by package, jobs and
+                    busy time by endpoint, written artifacts) to this file, in the Prometheus text format.
+
+                    Overrides the 'metrics_textfile' setting from the configuration.
+                "#))
+            )
         )
 
         .subcommand(Command::new("what-depends")
@@ -484,6 +1019,17 @@ pub fn cli() -> Command {
                 ])
                 .help("Specify which dependency types are to be checked. By default, all are checked")
             )
+            .arg(Arg::new("transitive")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .long("transitive")
+                .help("Also list packages that depend on the package indirectly, via other packages")
+                .long_help(indoc::indoc!(r#"
+                    List all packages whose dependency tree eventually contains the package, not only
+                    the packages that depend on it directly.
+                    Only dependencies of the types selected with --type are followed.
+                "#))
+            )
         )
         .subcommand(Command::new("dependencies-of")
             .alias("depsof")
@@ -544,6 +1090,47 @@ pub fn cli() -> Command {
             )
         )
 
+        .subcommand(Command::new("verify-sources-of-submit")
+            .about("Verify that the sources used by a submit still match the recorded hashes")
+            .long_about(indoc::indoc!(r#"
+                Verify that the sources used by the jobs of a submit still match the hashes that were recorded
+                in the input manifests of the jobs when the submit was built.
+
+                The sources are read from the source cache, or downloaded from the URLs (and mirrors) in the
+                current package definitions if they are not cached or --redownload is passed. Downloaded sources
+                are not written to the source cache. Sources whose upstream files were rewritten, and sources
+                whose hash in the repository differs from the recorded hash, are reported.
+
+                Exits with a nonzero exit code if any source could not be verified.
+            "#))
+            .arg(Arg::new("submit")
+                .required(true)
+                .index(1)
+                .value_name("SUBMIT")
+                .value_parser(uuid::Uuid::parse_str)
+                .help("The UUID of the submit")
+            )
+            .arg(Arg::new("redownload")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .long("redownload")
+                .help("Download all sources instead of reading them from the source cache")
+            )
+            .arg(Arg::new("timeout")
+                .required(false)
+                .long("timeout")
+                .value_name("TIMEOUT")
+                .value_parser(clap::value_parser!(u64))
+                .help("Set timeout for download in seconds")
+            )
+            .arg(Arg::new("csv")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .long("csv")
+                .help("Format output as CSV")
+            )
+        )
+
         .subcommand(Command::new("find-artifact")
             .about("Find artifacts for packages")
             .arg(Arg::new("package_name_regex")
@@ -605,14 +1192,34 @@ pub fn cli() -> Command {
                 .help("A version constraint to search for (optional), E.G. '=1.0.0'")
             )
 
+            .arg(Arg::new("glob")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .long("glob")
+                .short('g')
+                .help("Interpret REGEX as a glob that has to match the whole package name, e.g. 'lib*'")
+            )
+
             .arg(Arg::new("terse")
                 .action(ArgAction::SetTrue)
                 .required(false)
                 .long("terse")
                 .short('t')
+                .conflicts_with("format")
                 .help("Do not use the fancy format, but simply <name> <version>")
             )
 
+            .arg(Arg::new("format")
+                .required(false)
+                .long("format")
+                .value_name("FORMAT")
+                .help("Print the packages with this handlebars format instead of the configured 'package_print_format'")
+                .long_help(indoc::indoc!(r#"
+                    Print the packages with this handlebars format instead of the configured 'package_print_format'.
+                    The variables available are the same, e.g. '{{p.name}} {{p.version}}: {{#each p.sources}}{{this.url}} {{/each}}'
+                "#))
+            )
+
             .arg(Arg::new("show_all")
                 .action(ArgAction::SetTrue)
                 .required(false)
@@ -799,6 +1406,46 @@ pub fn cli() -> Command {
                     .value_name("TIMEOUT")
                     .help("Set timeout for download in seconds")
                 )
+
+                .arg(Arg::new("jobs")
+                    .required(false)
+                    .short('j')
+                    .long("jobs")
+                    .value_name("N")
+                    .value_parser(clap::value_parser!(usize))
+                    .help("Number of parallel downloads (overrides 'source_download_parallelism' from the configuration)")
+                )
+
+                .arg(Arg::new("with_dependencies")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .short('d')
+                    .long("with-dependencies")
+                    .help("Also download the sources of all (transitive) dependencies of the packages")
+                    .long_help(indoc::indoc!(r#"
+                        Also download the sources of all (transitive) dependencies of the packages.
+
+                        Sources that are already in the cache are skipped (unless --force is passed).
+                        Conditions on dependencies are evaluated with the --image and --env arguments.
+                    "#))
+                )
+                .arg(Arg::new("image")
+                    .required(false)
+                    .value_name("IMAGE NAME")
+                    .short('I')
+                    .long("image")
+                    .requires("with_dependencies")
+                    .help("Name of the Docker image to evaluate conditions on dependencies for")
+                )
+                .arg(Arg::new("env")
+                    .required(false)
+                    .action(ArgAction::Append)
+                    .short('E')
+                    .long("env")
+                    .requires("with_dependencies")
+                    .value_parser(env_pass_validator)
+                    .help("Additional env to evaluate conditions on dependencies for")
+                )
             )
             .subcommand(Command::new("of")
                 .about("Get the pathes of the sources of a package")
@@ -905,6 +1552,37 @@ pub fn cli() -> Command {
                     .help("Dont be interactive (only with --update at the moment)")
                     .requires("package_do_update")
                 )
+                .arg(Arg::new("force")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("force")
+                    .help("Overwrite artifacts that already exist in the release store without asking")
+                    .conflicts_with_all(["package_do_update", "noninteractive"])
+                )
+                .arg(Arg::new("move")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("move")
+                    .help("Move the artifacts from the staging store instead of copying them")
+                    .conflicts_with("hard_link")
+                )
+                .arg(Arg::new("hard_link")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("hard-link")
+                    .help("Hard-link the artifacts from the staging store instead of copying them")
+                    .long_help(indoc::indoc!(r#"
+                        Hard-link the artifacts from the staging store instead of copying them.
+                        This only works if the staging store and the release store are on the same filesystem.
+                    "#))
+                    .conflicts_with("move")
+                )
+                .arg(Arg::new("operator")
+                    .required(false)
+                    .long("operator")
+                    .value_name("NAME")
+                    .help("The name that is recorded as the releasing operator (defaults to the configured user or $USER)")
+                )
                 .arg(Arg::new("quiet")
                     .action(ArgAction::SetTrue)
                     .required(false)
@@ -916,8 +1594,100 @@ pub fn cli() -> Command {
 
         )
 
+        .subcommand(Command::new("store")
+            .about("Manage the artifact stores")
+            .subcommand(Command::new("dedup")
+                .about("Deduplicate the artifacts in the stores in the content store")
+                .long_about(indoc::indoc!(r#"
+                    Moves the content of all artifacts in the staging and release stores into the
+                    configured content store ("content_store" in the configuration) and replaces
+                    artifacts with identical content by links to the same object.
+
+                    Artifacts that are written after the content store was configured are
+                    deduplicated automatically, this is only needed once for existing stores.
+                "#))
+                .arg(Arg::new("dry_run")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("dry-run")
+                    .help("Only print which artifacts would be linked")
+                )
+            )
+            .subcommand(Command::new("gc")
+                .about("Remove staged artifacts that are not needed anymore")
+                .long_about(indoc::indoc!(r#"
+                    Removes the staging directories of submits that are older than the passed DATE
+                    and the staging directories that do not belong to any submit in the database.
+                    If the staging directories are namespaced per user, all users are considered.
+
+                    Without --older-than, only the staging directories that are not referenced in
+                    the database are removed.
+
+                    If a content store with hard links is configured, objects that are not linked
+                    from any store anymore are removed as well.
+                "#))
+                .arg(arg_older_than_date("Remove staged artifacts of submits older than DATE"))
+                .arg(Arg::new("dry_run")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("dry-run")
+                    .help("Only print what would be removed")
+                )
+            )
+            .subcommand(Command::new("import")
+                .about("Import artifacts from a bundle into the staging or a release store")
+                .long_about(indoc::indoc!(r#"
+                    Import artifacts from a bundle into the staging store or a release store and
+                    register them in the database.
+
+                    A bundle is a directory or a tar archive (optionally gzip compressed) with a
+                    "manifest.json" that lists the artifacts with their path in the bundle, their
+                    SHA256 hash and the package, version and image they were built for:
+
+                        { "artifacts": [ { "path": "a-1.0.tar.gz", "sha256": "...",
+                                           "package": "a", "version": "1.0",
+                                           "image": "debian:bullseye" } ] }
+
+                    The hashes of all artifacts are verified before anything is imported.
+                    The artifacts are recorded as a new submit, which is printed at the end. When
+                    importing into the staging store, the artifacts are written to the staging
+                    directory of that submit, which can be passed to `butido build --staging-dir`.
+                "#))
+                .arg(Arg::new("bundle")
+                    .required(true)
+                    .index(1)
+                    .value_name("BUNDLE")
+                    .help("The bundle directory or tar archive")
+                )
+                .arg(Arg::new("release_store_name")
+                    .required(false)
+                    .long("release-store")
+                    .value_name("NAME")
+                    .help("Import into this release store instead of the staging store")
+                )
+            )
+        )
+
         .subcommand(Command::new("lint")
-            .about("Lint the package script of one or multiple packages")
+            .about("Lint the definitions and package scripts of one or multiple packages")
+            .long_about(indoc::indoc!(r#"
+                Lint the definitions and package scripts of one or multiple packages.
+
+                The package definitions are checked for duplicate or empty versions, unparsable or unresolvable
+                dependencies, phases that are not available or are referenced without being available, and invalid
+                environment variable names. The package scripts are linted with the configured linter, if any.
+
+                With `--scripts`, only the package scripts are checked: with the configured linter, if any, and with
+                shellcheck (see the `shellcheck` section of the configuration).
+
+                Exits with a nonzero exit code if any problem was found.
+            "#))
+            .arg(Arg::new("scripts")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .long("scripts")
+                .help("Only check the package scripts, with the configured linter and shellcheck")
+            )
             .arg(Arg::new("package_name")
                 .required(false)
                 .index(1)
@@ -933,6 +1703,7 @@ pub fn cli() -> Command {
         )
 
         .subcommand(Command::new("tree-of")
+            .alias("tree")
             .about("Print the dependency tree of one or multiple packages")
             .arg(Arg::new("package_name")
                 .required(true)
@@ -973,12 +1744,116 @@ pub fn cli() -> Command {
                     conditions on dependencies.
                 "#))
             )
+            .arg(Arg::new("dot")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .long("dot")
+                .conflicts_with("json")
+                .help("Print the tree as graph in the DOT language")
+            )
+            .arg(Arg::new("json")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .long("json")
+                .help("Print the tree as JSON")
+            )
         )
 
         .subcommand(Command::new("metrics")
             .about("Print metrics about butido")
         )
 
+        .subcommand(Command::new("rerun-job")
+            .about("Run a job again with its recorded script and environment")
+            .long_about(indoc::indoc!(r#"
+                Run a job again with its recorded script and environment
+
+                The script, image and environment variables that are recorded in the database for the job
+                are used to run the job again, as a new submit with only this job. This can be used to
+                reproduce old failures.
+
+                The package definition (e.g. the sources) is taken from the repository. The dependency
+                artifacts of the job are copied from the staging directory of the original submit, or
+                have to be in one of the release stores.
+            "#))
+            .arg(Arg::new("job_uuid")
+                .required(true)
+                .index(1)
+                .value_name("JOB_UUID")
+                .help("The UUID of the job to run again")
+            )
+            .arg(Arg::new("endpoint")
+                .required(false)
+                .long("endpoint")
+                .value_name("ENDPOINT_NAME")
+                .help("Run the job on this endpoint")
+            )
+        )
+
+        .subcommand(Command::new("daemon")
+            .about("Run butido as a daemon that accepts builds via HTTP")
+            .long_about(indoc::indoc!(r#"
+                Run butido as a daemon that accepts builds via HTTP
+
+                The daemon provides the following endpoints:
+
+                    POST /submits             Queue a build, e.g. with the JSON body
+                                              {"package": "foo", "version": "1.0", "image": "debian:bullseye", "env": {"KEY": "value"}}
+                                              and respond with the UUID of the submit.
+                                              "priority" (default 0) and "weight" (default 1) are optional
+                    GET  /submits/<uuid>      Report the state of a submit and its jobs as JSON
+                    GET  /jobs/<uuid>/log     Return the log of a job as plain text
+                    GET  /jobs/<uuid>/log/stream
+                                              Relay the log of a running job while it is produced
+
+                Builds run at the same time, with the same configuration, database and stores as
+                "butido build". All builds share the endpoints: the jobs of submits with a higher priority
+                are scheduled first, submits with the same priority share the endpoints by their weight.
+            "#))
+            .arg(Arg::new("listen")
+                .required(false)
+                .long("listen")
+                .value_name("ADDR")
+                .default_value("127.0.0.1:8080")
+                .value_parser(clap::value_parser!(std::net::SocketAddr))
+                .help("Address to listen on for HTTP requests")
+            )
+            .arg(Arg::new("max_submits")
+                .required(false)
+                .long("max-submits")
+                .value_name("N")
+                .value_parser(clap::value_parser!(usize))
+                .help("Maximum number of builds that run at the same time, further builds are queued")
+                .long_help(indoc::indoc!(r#"
+                    Maximum number of builds that run at the same time (default: no limit).
+                    Further builds are queued and started by their priority, then in the order they were submitted.
+                "#))
+            )
+        )
+
+        .subcommand(Command::new("repo")
+            .about("Repository maintenance commands")
+            .subcommand(Command::new("stats")
+                .about("Print statistics about the packages in the repository")
+                .long_about(indoc::indoc!(r#"
+                    Print statistics about the packages in the repository
+
+                    This includes the number of versions per package, the number of dependency
+                    edges by type, packages without "maintainer" or "license" in their "meta"
+                    table and the packages with the largest dependency closures.
+                    Conditions on dependencies are ignored when computing the closures.
+                "#))
+                .arg(Arg::new("top")
+                    .required(false)
+                    .long("top")
+                    .value_name("N")
+                    .default_value("10")
+                    .value_parser(clap::value_parser!(usize))
+                    .help("Number of packages to list with their dependency closure size")
+                )
+            )
+        )
+
         .subcommand(Command::new("endpoint")
             .about("Endpoint maintentance commands")
             .arg(Arg::new("endpoint_name")
@@ -1136,9 +2011,45 @@ pub fn cli() -> Command {
                     )
                 )
 
+                .subcommand(Command::new("logs")
+                    .about("Print the logs (stdout and stderr) of the container")
+                    .long_about(indoc::indoc!(r#"
+                        Print the logs (stdout and stderr) of the container, as recorded by Docker.
+
+                        The output of the script of the job is copied to the logs of the container, so this can be used
+                        to follow a job that is running. Use `butido db log-of` for the logs of finished jobs.
+                    "#))
+                    .arg(Arg::new("follow")
+                        .action(ArgAction::SetTrue)
+                        .required(false)
+                        .long("follow")
+                        .short('f')
+                        .help("Follow the logs until the container stops")
+                    )
+                    .arg(Arg::new("tail")
+                        .required(false)
+                        .long("tail")
+                        .value_name("N")
+                        .value_parser(clap::value_parser!(u64))
+                        .help("Only print the last N lines")
+                    )
+                    .arg(Arg::new("timestamps")
+                        .action(ArgAction::SetTrue)
+                        .required(false)
+                        .long("timestamps")
+                        .help("Prefix each line with its timestamp")
+                    )
+                )
+
                 .subcommand(Command::new("inspect")
                     .about("Display details about the container")
                     .long_about("Display details about the container. Do not assume the output format to be stable.")
+                    .arg(Arg::new("json")
+                        .action(ArgAction::SetTrue)
+                        .required(false)
+                        .long("json")
+                        .help("Print the details as JSON, as returned by the Docker API")
+                    )
                 )
             )
             .subcommand(Command::new("images")
@@ -1162,6 +2073,185 @@ pub fn cli() -> Command {
                     )
                 )
             )
+            .subcommand(Command::new("add")
+                .about("Define an endpoint in the database")
+                .long_about(indoc::indoc!(r#"
+                    Define an endpoint in the database.
+
+                    Endpoints that are defined in the database are used in addition to the configured ones if
+                    'docker.database_definitions' is enabled in the configuration.
+                "#))
+                .arg(Arg::new("name")
+                    .required(true)
+                    .index(1)
+                    .value_name("NAME")
+                    .help("The name of the endpoint")
+                )
+                .arg(Arg::new("uri")
+                    .required(true)
+                    .long("uri")
+                    .value_name("URI")
+                    .help("The URI where the endpoint is reachable")
+                )
+                .arg(Arg::new("endpoint_type")
+                    .required(false)
+                    .long("type")
+                    .value_name("TYPE")
+                    .value_parser(["socket", "http"])
+                    .default_value("http")
+                    .help("The type of the endpoint")
+                )
+                .arg(Arg::new("maxjobs")
+                    .required(true)
+                    .long("maxjobs")
+                    .value_name("N")
+                    .value_parser(parse_usize)
+                    .help("Maximum number of jobs which are allowed on the endpoint")
+                )
+                .arg(Arg::new("network_mode")
+                    .required(false)
+                    .long("network-mode")
+                    .value_name("MODE")
+                    .help("The network mode for the containers on the endpoint")
+                )
+            )
+            .subcommand(Command::new("discover")
+                .about("Discover endpoints and print their configuration")
+                .long_about(indoc::indoc!(r#"
+                    Discover endpoints and print their configuration.
+
+                    Endpoints are discovered from the Docker contexts of the user (in $DOCKER_CONFIG or ~/.docker)
+                    and from the output of 'docker.endpoint_inventory_command', if configured. Endpoints that are
+                    already configured (by name or URI) are skipped.
+
+                    The configuration stanzas for the discovered endpoints are printed, so they can be added to
+                    the configuration file. With --add, the endpoints are defined in the database instead.
+                "#))
+                .arg(Arg::new("no_contexts")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("no-contexts")
+                    .help("Do not discover endpoints from the Docker contexts")
+                )
+                .arg(Arg::new("maxjobs")
+                    .required(false)
+                    .long("maxjobs")
+                    .value_name("N")
+                    .value_parser(parse_usize)
+                    .default_value("1")
+                    .help("Maximum number of jobs for endpoints the source does not know it for")
+                )
+                .arg(Arg::new("all")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("all")
+                    .help("Also show endpoints that are already configured")
+                )
+                .arg(Arg::new("add")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("add")
+                    .conflicts_with("all")
+                    .help("Define the discovered endpoints in the database instead of printing their configuration")
+                )
+            )
+            .subcommand(Command::new("remove")
+                .about("Remove an endpoint definition from the database")
+                .arg(Arg::new("name")
+                    .required(true)
+                    .index(1)
+                    .value_name("NAME")
+                    .help("The name of the endpoint")
+                )
+            )
+            .subcommand(Command::new("list")
+                .about("List the endpoints that are defined in the database")
+                .arg(Arg::new("csv")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("csv")
+                    .help("Format output as CSV")
+                )
+            )
+        )
+
+        .subcommand(Command::new("image")
+            .about("Manage the images that are allowed in the database")
+            .long_about(indoc::indoc!(r#"
+                Manage the images that are allowed in the database.
+
+                Images that are allowed in the database are used in addition to the configured ones, denied images
+                cannot be used even if they are configured. The definitions are only used if
+                'docker.database_definitions' is enabled in the configuration.
+            "#))
+            .subcommand(Command::new("allow")
+                .about("Allow an image")
+                .arg(Arg::new("image_name")
+                    .required(true)
+                    .index(1)
+                    .value_name("IMAGE")
+                    .help("The name of the image")
+                )
+                .arg(Arg::new("short_name")
+                    .required(false)
+                    .long("short-name")
+                    .value_name("NAME")
+                    .help("The short name of the image (defaults to the name)")
+                )
+            )
+            .subcommand(Command::new("deny")
+                .about("Deny an image")
+                .arg(Arg::new("image_name")
+                    .required(true)
+                    .index(1)
+                    .value_name("IMAGE")
+                    .help("The name of the image")
+                )
+            )
+            .subcommand(Command::new("remove")
+                .about("Remove the definition of an image from the database")
+                .arg(Arg::new("image_name")
+                    .required(true)
+                    .index(1)
+                    .value_name("IMAGE")
+                    .help("The name of the image")
+                )
+            )
+            .subcommand(Command::new("list")
+                .about("List the images that are allowed or denied in the database")
+                .arg(Arg::new("csv")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("csv")
+                    .help("Format output as CSV")
+                )
+            )
+        )
+
+        .subcommand(Command::new("config")
+            .about("Inspect the configuration")
+            .subcommand(Command::new("themes")
+                .about("List the themes available for highlighting scripts")
+                .long_about(indoc::indoc!(r#"
+                    List the themes available for highlighting scripts.
+
+                    These are the themes that come with butido and the themes from the configured
+                    'script_highlight_theme_dir'. The configured 'script_highlight_theme' is marked.
+                "#))
+            )
+        )
+
+        .subcommand(Command::new("validate-config")
+            .about("Check the configuration and print a diagnostic report")
+            .long_about(indoc::indoc!(r#"
+                Check the configuration and print a diagnostic report.
+
+                The configured directories, the endpoint URIs, the script highlighting theme and the connection to the
+                database are checked. In contrast to the checks that run on every start of butido, all problems are
+                reported, not only the first one.
+
+                Exits with a nonzero exit code if any problem was found.
+            "#))
         )
 }
 
@@ -1232,6 +2322,14 @@ fn env_pass_validator(s: &str) -> Result<String, String> {
     }
 }
 
+/// Check whether 's' is a 'key=value' label with a non-empty key
+fn label_validator(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((key, value)) if !key.trim().is_empty() => Ok((key.trim().to_owned(), value.to_owned())),
+        _ => Err(format!("Not a 'key=value' label: {s}")),
+    }
+}
+
 fn dir_exists_validator(s: &str) -> Result<String, String> {
     if PathBuf::from(&s).is_dir() {
         Ok(s.to_owned())
@@ -1240,6 +2338,15 @@ fn dir_exists_validator(s: &str) -> Result<String, String> {
     }
 }
 
+fn arg_user_filter(about: &str) -> Arg {
+    Arg::new("user")
+        .required(false)
+        .long("user")
+        .short('u')
+        .value_name("USER")
+        .help(about.to_owned())
+}
+
 fn arg_older_than_date(about: &str) -> Arg {
     Arg::new("older_than")
         .required(false)
@@ -1325,6 +2432,7 @@ fn parse_u64(s: &str) -> std::result::Result<String, String> {
 #[cfg(test)]
 mod tests {
     use super::env_pass_validator;
+    use super::label_validator;
 
     #[test]
     fn test_env_pass_validator_1() {
@@ -1400,4 +2508,26 @@ mod tests {
     fn test_env_pass_validator_15() {
         assert!(env_pass_validator("123").is_err());
     }
+
+    #[test]
+    fn test_label_validator() {
+        assert_eq!(label_validator("ticket=ABC-1"), Ok((String::from("ticket"), String::from("ABC-1"))));
+        assert_eq!(label_validator("url=a=b"), Ok((String::from("url"), String::from("a=b"))));
+        assert_eq!(label_validator("train="), Ok((String::from("train"), String::new())));
+        assert!(label_validator("=a").is_err());
+        assert!(label_validator("a").is_err());
+    }
+
+    #[test]
+    fn test_db_artifacts_for_package() {
+        let matches = super::cli()
+            .try_get_matches_from(["butido", "db", "artifacts", "--for-package", "foo", "1.0"])
+            .unwrap();
+        let artifacts = matches.subcommand_matches("db").and_then(|m| m.subcommand_matches("artifacts")).unwrap();
+        let package = artifacts.get_many::<String>("for_package").unwrap().collect::<Vec<_>>();
+        assert_eq!(package, ["foo", "1.0"]);
+
+        assert!(super::cli().try_get_matches_from(["butido", "db", "artifacts", "--for-package", "foo"]).is_ok());
+        assert!(super::cli().try_get_matches_from(["butido", "db", "artifacts", "--for-package"]).is_err());
+    }
 }
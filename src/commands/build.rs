@@ -10,6 +10,7 @@
 
 //! Implementation of the 'build' subcommand
 
+use std::collections::HashMap;
 use std::io::Write;
 use std::path::Path;
 use std::path::PathBuf;
@@ -35,15 +36,33 @@ use tokio_stream::StreamExt;
 use uuid::Uuid;
 
 use crate::config::*;
+use crate::db::DurationEstimates;
+use crate::endpoint::SharedEndpoints;
+use crate::filestore::ArtifactKindRules;
+use crate::filestore::CompressionRules;
+use crate::filestore::ContentStore;
 use crate::filestore::ReleaseStore;
 use crate::filestore::StagingStore;
+use crate::filestore::StoreBackend;
 use crate::filestore::path::StoreRoot;
+use crate::filestore::path::UnpackLimits;
 use crate::job::JobResource;
 use crate::log::LogItem;
+use crate::log::LogLimit;
+use crate::log::LogStreams;
+use crate::log::TriageRules;
+use crate::commands::recovery::BuildPlan;
+use crate::commands::submit_metrics::SubmitMetrics;
+use crate::commands::submit_summary::FailureReport;
+use crate::commands::submit_summary::JobReport;
+use crate::commands::submit_summary::SubmitReport;
+use crate::commands::submit_summary::SummaryFile;
+use crate::orchestrator::BuildScope;
 use crate::orchestrator::OrchestratorSetup;
 use crate::package::Dag;
 use crate::package::PackageName;
 use crate::package::PackageVersion;
+use crate::package::PhaseName;
 use crate::package::Shebang;
 use crate::package::condition::ConditionData;
 use crate::repository::Repository;
@@ -53,6 +72,9 @@ use crate::util::EnvironmentVariableName;
 use crate::util::docker::ImageName;
 use crate::util::progress::ProgressBars;
 
+/// The interval in which the status of a submit is printed with `--quiet-progress`
+const STATUS_LINE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+
 /// Implementation of the "build" subcommand
 #[allow(clippy::too_many_arguments)]
 pub async fn build(
@@ -63,86 +85,98 @@ pub async fn build(
     config: &Configuration,
     repo: Repository,
     repo_path: &Path,
+    log_streams: Option<Arc<LogStreams>>,
+    shared_endpoints: Option<Arc<SharedEndpoints>>,
 ) -> Result<()> {
-    use crate::db::models::{EnvVar, GitHash, Image, Job, Package, Submit};
+    use crate::db::models::{EnvVar, GitHash, Package};
+
+    let progressbars = if matches.get_flag("quiet_progress") {
+        progressbars.hidden()
+    } else {
+        progressbars
+    };
 
     let git_repo = git2::Repository::open(repo_path)
         .with_context(|| anyhow!("Opening repository at {}", repo_path.display()))?;
 
-    let now = chrono::offset::Local::now().naive_local();
+    let triage_rules = config
+        .triage_rules()
+        .as_ref()
+        .map(|path| TriageRules::load(&repo_root.join(path)))
+        .transpose()?
+        .unwrap_or_default();
+    let triage_rules = Arc::new(triage_rules);
+
+    debug!("Getting repository HEAD");
+    let hash_str = crate::util::git::get_repo_head_commit_hash(&git_repo)?;
+    trace!("Repository HEAD = {}", hash_str);
+
+    // The plan of the submit that is recovered, it overrides the parameters from the command line
+    let recovered_plan = matches
+        .get_one::<String>("recover")
+        .map(|submit_id| load_recovered_plan(&mut *database_pool.get()?, submit_id, &hash_str))
+        .transpose()?;
 
     let shebang = Shebang::from({
-        matches
-            .get_one::<String>("shebang")
-            .map(|s| s.to_owned())
-            .unwrap_or_else(|| config.shebang().clone())
+        match recovered_plan.as_ref() {
+            Some(plan) => plan.shebang.clone(),
+            None => matches
+                .get_one::<String>("shebang")
+                .map(|s| s.to_owned())
+                .unwrap_or_else(|| config.shebang().clone()),
+        }
     });
 
-    let image_name = matches
-        .get_one::<String>("image")
-        .map(|s| s.to_owned())
-        .map(ImageName::from)
-        .unwrap(); // safe by clap
-    if config.docker().verify_images_present()
-        && !config
-            .docker()
-            .images()
-            .iter()
-            .any(|img| image_name == img.name)
-    {
-        return Err(anyhow!(
-            "Requested build image {} is not in the configured images", image_name
-        ))
-        .with_context(|| anyhow!("Available images: {}", config.docker().images().iter().map(|img| img.name.clone()).join(", ")))
-        .with_context(|| anyhow!("Image present verification failed"))
-        .map_err(Error::from);
-    }
+    let summary_file = matches
+        .get_one::<String>("summary_file")
+        .map(PathBuf::from)
+        .or_else(|| config.submit_summary_file().clone());
 
-    debug!("Getting repository HEAD");
-    let hash_str = crate::util::git::get_repo_head_commit_hash(&git_repo)?;
-    trace!("Repository HEAD = {}", hash_str);
-    let phases = config.available_phases();
+    let metrics_file = matches
+        .get_one::<String>("metrics_file")
+        .map(PathBuf::from)
+        .or_else(|| config.metrics_textfile().clone());
 
-    let mut endpoint_configurations = config
-        .docker()
-        .endpoints()
-        .iter()
-        .map(|(ep_name, ep_cfg)| {
-            crate::endpoint::EndpointConfiguration::builder()
-                .endpoint_name(ep_name.clone())
-                .endpoint(ep_cfg.clone())
-                .required_images(config.docker().images().iter().map(|img| img.name.clone()).collect::<Vec<_>>())
-                .required_docker_versions(config.docker().docker_versions().clone())
-                .required_docker_api_versions(config.docker().docker_api_versions().clone())
-                .build()
-        })
-        .collect::<Vec<_>>();
-    {
-        // Because we're loading always sequencially, to have a bit more spread over the endpoints,
-        // shuffle the endpoints here. Not a perfect solution, but a working one.
-        use rand::seq::SliceRandom;
-        let mut rng = rand::thread_rng();
-        endpoint_configurations.shuffle(&mut rng);
-    }
-    info!("Endpoint config build");
+    let staging_dir = match recovered_plan.as_ref() {
+        Some(plan) => Some(plan.staging_dir.clone()),
+        None => matches.get_one::<String>("staging_dir").map(PathBuf::from),
+    };
 
-    let pname = matches
-        .get_one::<String>("package_name")
-        .map(|s| s.to_owned())
-        .map(PackageName::from)
-        .unwrap(); // safe by clap
+    let (pname, pvers) = match recovered_plan.as_ref() {
+        Some(plan) => (plan.package_name.clone(), Some(plan.package_version.clone())),
+        None => {
+            let pname = matches
+                .get_one::<String>("package_name")
+                .map(|s| s.to_owned())
+                .map(PackageName::from)
+                .unwrap(); // safe by clap
 
-    let pvers = matches
-        .get_one::<String>("package_version")
-        .map(|s| s.to_owned())
-        .map(PackageVersion::from);
+            let pvers = matches
+                .get_one::<String>("package_version")
+                .map(|s| s.to_owned())
+                .map(PackageVersion::from);
+            (pname, pvers)
+        },
+    };
     info!("We want {} ({:?})", pname, pvers);
 
-    let additional_env = matches
-        .get_many::<String>("env")
-        .unwrap_or_default()
-        .map(|s| crate::util::env::parse_to_env(s.as_ref()))
-        .collect::<Result<Vec<(EnvironmentVariableName, String)>>>()?;
+    let additional_env = match recovered_plan.as_ref() {
+        Some(plan) => plan.env.clone(),
+        None => matches
+            .get_many::<String>("env")
+            .unwrap_or_default()
+            .map(|s| crate::util::env::parse_to_env(s.as_ref()))
+            .collect::<Result<Vec<(EnvironmentVariableName, String)>>>()?,
+    };
+
+    let phases = match recovered_plan.as_ref() {
+        Some(plan) => plan.phases.clone(),
+        None => select_phases(
+            config.available_phases(),
+            matches.get_many::<String>("phases").map(|p| p.map(String::as_str).collect()),
+            &matches.get_many::<String>("skip_phase").unwrap_or_default().map(String::as_str).collect::<Vec<_>>(),
+        )?,
+    };
 
     let packages = if let Some(pvers) = pvers {
         debug!("Searching for package with version: '{}' '{}'", pname, pvers);
@@ -165,78 +199,98 @@ pub async fn build(
         .get(0)
         .ok_or_else(|| anyhow!("Found no package."))?;
 
-    let release_stores = config
-        .release_stores()
-        .iter()
-        .map(|storename| {
-            let bar_release_loading = progressbars.bar()?;
-
-            let p = config.releases_directory().join(storename);
-            let p_str = p.to_string_lossy();
-            debug!("Loading release directory: {}", p_str);
-            let r = ReleaseStore::load(StoreRoot::new(p.clone())?, &bar_release_loading);
-            if r.is_ok() {
-                bar_release_loading.finish_with_message(format!("Loaded releases in {p_str} successfully"));
-            } else {
-                bar_release_loading.finish_with_message(format!("Failed to load releases in {p_str}"));
-            }
-            r.map(Arc::new)
-        })
-        .collect::<Result<Vec<_>>>()?;
-
-    let (staging_store, staging_dir, submit_id) = {
-        let bar_staging_loading = progressbars.bar()?;
-
-        let (submit_id, p) = if let Some(staging_dir) = matches.get_one::<String>("staging_dir").map(PathBuf::from) {
-            info!(
-                "Setting staging dir to {} for this run",
-                staging_dir.display()
-            );
+    let image_names = if let Some(plan) = recovered_plan.as_ref() {
+        plan.images.clone()
+    } else if matches.get_flag("all_images") {
+        let image_names = package.compatible_images(config.docker().images().iter().map(|img| &img.name));
+        if image_names.is_empty() {
+            return Err(anyhow!("No configured image is allowed for {} {}", package.name(), package.version()))
+        }
+        info!("Building for images: {}", image_names.iter().join(", "));
+        image_names
+    } else {
+        matches
+            .get_many::<String>("image")
+            .unwrap() // safe by clap
+            .map(|s| ImageName::from(s.to_owned()))
+            .unique()
+            .collect::<Vec<_>>()
+    };
+    for image_name in image_names.iter() {
+        if config.docker().verify_images_present()
+            && !config
+                .docker()
+                .images()
+                .iter()
+                .any(|img| *image_name == img.name)
+        {
+            return Err(anyhow!(
+                "Requested build image {} is not in the configured images", image_name
+            ))
+            .with_context(|| anyhow!("Available images: {}", config.docker().images().iter().map(|img| img.name.clone()).join(", ")))
+            .with_context(|| anyhow!("Image present verification failed"))
+            .map_err(Error::from);
+        }
+    }
 
-            let uuid = staging_dir.file_name()
-                .ok_or_else(|| anyhow!("Seems not to be a directory: {}", staging_dir.display()))?
-                .to_owned()
-                .into_string()
-                .map_err(|_| anyhow!("Type conversion of staging dir name to UTF8 String"))
-                .context("Parsing staging dir name to UUID")?;
-            let uuid = Uuid::parse_str(&uuid)
-                .context("Parsing directory name as UUID")
-                .with_context(|| anyhow!("Seems not to be a submit UUID: {}", uuid))?;
+    let release_stores = crate::commands::util::load_release_stores(config, &progressbars).await?;
 
-            (uuid, staging_dir)
-        } else {
-            let submit_id = uuid::Uuid::new_v4();
-            let staging_dir = config
-                .staging_directory()
-                .join(submit_id.hyphenated().to_string());
+    // The dependency tree might look different on each image because of conditions on
+    // dependencies, so it is resolved for every image
+    let dags = image_names
+        .iter()
+        .map(|image_name| {
+            let bar_tree_building = progressbars.bar()?;
+            let condition_data = ConditionData {
+                image_name: Some(image_name),
+                env: &additional_env,
+            };
 
-            (submit_id, staging_dir)
-        };
+            let dag = Dag::for_root_package(package.clone(), &repo, Some(&bar_tree_building), &condition_data)?;
+            bar_tree_building.finish_with_message(format!("Finished loading Dag for {image_name}"));
+            Ok(dag)
+        })
+        .collect::<Result<Vec<_>>>()?;
 
-        if !p.is_dir() {
-            tokio::fs::create_dir_all(&p).await?;
+    for (image_name, dag) in image_names.iter().zip(dags.iter()) {
+        for (name, versions) in dag.packages_with_multiple_versions() {
+            let versions = versions.iter().join(", ");
+            if matches.get_flag("strict_versions") {
+                return Err(anyhow!(
+                    "Dependency tree for {} contains multiple versions of {}: {}",
+                    image_name,
+                    name,
+                    versions
+                ));
+            }
+            info!("Dependency tree for {} contains multiple versions of {}: {}", image_name, name, versions);
         }
+    }
 
-        debug!("Loading staging directory: {}", p.display());
-        let r = StagingStore::load(StoreRoot::new(p.clone())?, &bar_staging_loading);
-        if r.is_ok() {
-            bar_staging_loading.finish_with_message("Loaded staging successfully");
-        } else {
-            bar_staging_loading.finish_with_message("Failed to load staging");
-        }
-        r.map(RwLock::new).map(Arc::new).map(|store| (store, p, submit_id))?
-    };
+    // Sources are shared between the images, so all packages of all trees are only verified and
+    // linted once
+    let all_packages = dags
+        .iter()
+        .flat_map(Dag::all_packages)
+        .unique_by(|pkg| (pkg.name().clone(), pkg.version().clone()))
+        .collect::<Vec<_>>();
 
-    let dag = {
-        let bar_tree_building = progressbars.bar()?;
-        let condition_data = ConditionData {
-            image_name: Some(&image_name),
-            env: &additional_env,
-        };
+    // Every package only gets the variables it accepts, but each variable has to be accepted by
+    // at least one package
+    let additional_env = {
+        let (accepted, rejected): (Vec<_>, Vec<_>) = additional_env
+            .into_iter()
+            .partition(|(name, _)| all_packages.iter().any(|pkg| pkg.accepts_env(name)));
 
-        let dag = Dag::for_root_package(package.clone(), &repo, Some(&bar_tree_building), &condition_data)?;
-        bar_tree_building.finish_with_message("Finished loading Dag");
-        dag
+        if !rejected.is_empty() {
+            let names = rejected.iter().map(|(name, _)| name).join(", ");
+            if matches.get_flag("lenient") {
+                warn!("Ignoring environment variables no package accepts: {}", names);
+            } else {
+                return Err(anyhow!("Environment variables not accepted by any package: {}", names));
+            }
+        }
+        accepted
     };
 
     let source_cache = SourceCache::new(config.source_cache_root().clone());
@@ -245,7 +299,7 @@ pub async fn build(
         warn!("No hash verification will be performed");
     } else {
         crate::commands::source::verify_impl(
-            dag.all_packages().into_iter(),
+            all_packages.iter().copied(),
             &source_cache,
             &progressbars,
         )
@@ -256,22 +310,31 @@ pub async fn build(
     if matches.get_flag("no_lint") {
         warn!("No script linting will be performed!");
     } else if let Some(linter) = crate::ui::find_linter_command(repo_root, config)? {
-        let all_packages = dag.all_packages();
         let bar = progressbars.bar()?;
         bar.set_length(all_packages.len() as u64);
         bar.set_message("Linting package scripts...");
 
-        let iter = all_packages.into_iter();
+        let iter = all_packages.iter().copied();
         crate::commands::util::lint_packages(iter, &linter, config, bar).await?;
     } else {
         warn!("No linter set in configuration, no script linting will be performed!");
     } // linting
 
-    dag.all_packages()
-        .into_iter()
-        .map(|pkg| {
+    if config.shellcheck().pre_submit() && !matches.get_flag("no_lint") {
+        let bar = progressbars.bar()?;
+        bar.set_message("Checking package scripts with shellcheck...");
+
+        let iter = all_packages.iter().copied();
+        crate::commands::util::shellcheck_packages(iter, config.shellcheck(), config, bar).await?;
+    }
+
+    image_names
+        .iter()
+        .zip(dags.iter())
+        .flat_map(|(image_name, dag)| dag.all_packages().into_iter().map(move |pkg| (image_name, pkg)))
+        .map(|(image_name, pkg)| {
             if let Some(allowlist) = pkg.allowed_images() {
-                if !allowlist.contains(&image_name) {
+                if !allowlist.contains(image_name) {
                     return Err(anyhow!(
                         "Package {} {} is only allowed on: {}",
                         pkg.name(),
@@ -282,7 +345,7 @@ pub async fn build(
             }
 
             if let Some(deniedlist) = pkg.denied_images() {
-                if deniedlist.iter().any(|denied| image_name == *denied) {
+                if deniedlist.iter().any(|denied| image_name == denied) {
                     return Err(anyhow!(
                         "Package {} {} is not allowed to be built on {}",
                         pkg.name(),
@@ -296,10 +359,47 @@ pub async fn build(
         })
         .collect::<Result<Vec<()>>>()?;
 
-    trace!("Setting up database jobs for Package, GitHash, Image");
+    // The estimates are only informational, so the build does not fail if they cannot be loaded
+    let estimates = database_pool
+        .get()
+        .map_err(Error::from)
+        .and_then(|mut conn| DurationEstimates::load(&mut conn, all_packages.iter().copied()))
+        .unwrap_or_else(|e| {
+            warn!("Cannot estimate the durations of the jobs: {:#}", e);
+            DurationEstimates::default()
+        });
+
+    if !matches.get_flag("yes") && !atty::is(atty::Stream::Stdin) {
+        debug!("Not asking for confirmation, stdin is not a terminal");
+    } else if !matches.get_flag("yes") {
+        let plan = image_names
+            .iter()
+            .zip(dags.iter())
+            .map(|(image_name, dag)| (image_name, dag.build_order()))
+            .collect::<Vec<_>>();
+        let endpoints = config
+            .docker()
+            .endpoints()
+            .iter()
+            .map(|(name, endpoint)| (name.as_ref(), endpoint.maxjobs()))
+            .sorted()
+            .collect::<Vec<_>>();
+        let parallelism = matches
+            .get_one::<usize>("max_parallel")
+            .copied()
+            .or(*config.build_max_parallel())
+            .unwrap_or_else(|| endpoints.iter().map(|(_, maxjobs)| maxjobs).sum());
+        write_plan(&mut std::io::stderr(), package, &plan, &endpoints, &additional_env, &estimates, parallelism)?;
+
+        if !dialoguer::Confirm::new().with_prompt("Start building?").interact()? {
+            info!("Build aborted");
+            return Ok(())
+        }
+    }
+
+    trace!("Setting up database jobs for Package, GitHash");
     let db_package = async { Package::create_or_fetch(&mut database_pool.get().unwrap(), package) };
     let db_githash = async { GitHash::create_or_fetch(&mut database_pool.get().unwrap(), &hash_str) };
-    let db_image = async { Image::create_or_fetch(&mut database_pool.get().unwrap(), &image_name) };
     let db_envs = async {
         additional_env
             .clone()
@@ -314,27 +414,326 @@ pub async fn build(
             .await
     };
 
-    trace!("Running database jobs for Package, GitHash, Image");
-    let (db_package, db_githash, db_image, db_envs) =
-        tokio::join!(db_package, db_githash, db_image, db_envs);
+    trace!("Running database jobs for Package, GitHash");
+    let (db_package, db_githash, db_envs) = tokio::join!(db_package, db_githash, db_envs);
+    let (db_package, db_githash, _) = (db_package?, db_githash?, db_envs?);
+    trace!("Database jobs for Package, GitHash finished successfully");
+
+    let git_repo = git2::Repository::open(repo_path)
+        .with_context(|| anyhow!("Opening repository at {}", repo_path.display()))?;
 
-    let (db_package, db_githash, db_image, _) = (db_package?, db_githash?, db_image?, db_envs?);
+    let summary = submit(
+        matches,
+        progressbars,
+        database_pool,
+        config,
+        git_repo,
+        &db_package,
+        &db_githash,
+        image_names.into_iter().zip(dags).collect(),
+        shebang,
+        &phases,
+        additional_env,
+        staging_dir,
+        release_stores,
+        source_cache,
+        triage_rules,
+        log_streams,
+        shared_endpoints,
+        estimates,
+        summary_file.is_some() || metrics_file.is_some() || !config.notifications().is_empty(),
+        recovered_plan.is_some(),
+    )
+    .await?;
+
+    if let Some(metrics_file) = metrics_file {
+        SubmitMetrics::from_reports(summary.report.iter())
+            .write_to(&metrics_file)?;
+        info!("Metrics written to {}", metrics_file.display());
+    }
+
+    crate::commands::notify::notify_failures(config.notifications(), summary.report.iter()).await;
+
+    if let Some(summary_file) = summary_file {
+        SummaryFile {
+            submits: summary.report.into_iter().collect(),
+        }
+        .write_to(&summary_file)?;
+        info!("Submit summary written to {}", summary_file.display());
+    }
+
+    if summary.n_errors > 0 {
+        Err(anyhow!("One or multiple errors during build"))
+    } else {
+        Ok(())
+    }
+}
+
+/// Load the plan of the submit `submit_id` for recovering it
+///
+/// The repository has to be at the commit of the submit, otherwise the recovered jobs would not
+/// match the recorded jobs.
+fn load_recovered_plan(database_connection: &mut PgConnection, submit_id: &str, hash_str: &str) -> Result<BuildPlan> {
+    use crate::db::models::{GitHash, Submit};
+
+    let submit_id = Uuid::parse_str(submit_id).with_context(|| anyhow!("Parsing submit UUID: {}", submit_id))?;
+    let submit = Submit::with_id(database_connection, &submit_id)?;
+    let githash = GitHash::with_id(database_connection, submit.repo_hash_id)?;
+    if githash.hash != hash_str {
+        return Err(anyhow!(
+            "Submit {} was made at commit {}, but the repository is at {}. Cannot recover it",
+            submit_id,
+            githash.hash,
+            hash_str
+        ));
+    }
+
+    info!("Recovering submit {}", submit_id);
+    BuildPlan::load(database_connection, &submit)
+}
+
+/// Select the phases to compile into the packaging scripts
+///
+/// If `selected` is set, only these phases are used, phases in `skipped` are never used. The phases
+/// are always in the order of `available`.
+fn select_phases(available: &[PhaseName], selected: Option<Vec<&str>>, skipped: &[&str]) -> Result<Vec<PhaseName>> {
+    let unknown = selected
+        .iter()
+        .flatten()
+        .chain(skipped.iter())
+        .find(|name| !available.iter().any(|phase| phase.as_str() == **name));
+    if let Some(unknown) = unknown {
+        return Err(anyhow!(
+            "Unknown phase '{}', available phases are: {}",
+            unknown,
+            available.iter().map(PhaseName::as_str).join(", ")
+        ));
+    }
+
+    Ok(available
+        .iter()
+        .filter(|phase| {
+            selected
+                .as_ref()
+                .map(|selected| selected.contains(&phase.as_str()))
+                .unwrap_or(true)
+        })
+        .filter(|phase| !skipped.contains(&phase.as_str()))
+        .cloned()
+        .collect())
+}
+
+/// Write the plan of a build: the packages that are built for every image, in order, the endpoints
+/// the jobs are scheduled on and the additional environment
+///
+/// Artifacts that are found in the staging or release stores are reused, so the number of jobs
+/// and the estimated duration are upper bounds.
+fn write_plan(
+    out: &mut dyn Write,
+    package: &crate::package::Package,
+    plan: &[(&ImageName, Vec<&crate::package::Package>)],
+    endpoints: &[(&str, usize)],
+    additional_env: &[(EnvironmentVariableName, String)],
+    estimates: &DurationEstimates,
+    parallelism: usize,
+) -> Result<()> {
+    writeln!(out, "Plan for building {} {}:", package.name(), package.version())?;
+    for (image_name, packages) in plan {
+        writeln!(out)?;
+        writeln!(out, "Image {} ({} packages):", image_name, packages.len())?;
+        for (i, pkg) in packages.iter().enumerate() {
+            match estimates.of(pkg) {
+                Some(estimate) => writeln!(out, "  {:>3}. {} {} (~{})", i + 1, pkg.name(), pkg.version(), crate::db::format_estimate(estimate))?,
+                None => writeln!(out, "  {:>3}. {} {}", i + 1, pkg.name(), pkg.version())?,
+            }
+        }
+    }
+
+    writeln!(out)?;
+    writeln!(
+        out,
+        "Endpoints: {}",
+        endpoints.iter().map(|(name, maxjobs)| format!("{name} (max. {maxjobs} jobs)")).join(", ")
+    )?;
+    if additional_env.is_empty() {
+        writeln!(out, "Environment: none")?;
+    } else {
+        writeln!(out, "Environment: {}", additional_env.iter().map(|(k, v)| format!("{k}={v}")).join(", "))?;
+    }
+
+    let n_jobs = plan.iter().map(|(_, packages)| packages.len()).sum::<usize>();
+    writeln!(out, "At most {} jobs on {} images", n_jobs, plan.len())?;
+
+    let estimated = plan
+        .iter()
+        .flat_map(|(_, packages)| packages.iter())
+        .filter_map(|pkg| estimates.of(pkg))
+        .collect::<Vec<_>>();
+    if !estimated.is_empty() {
+        let total = estimated.iter().sum::<std::time::Duration>();
+        writeln!(
+            out,
+            "Estimated duration: ~{} with {} parallel jobs ({} jobs without estimate)",
+            crate::db::format_estimate(total / parallelism.max(1) as u32),
+            parallelism,
+            n_jobs - estimated.len()
+        )?;
+    }
+    writeln!(out)?;
+    Ok(())
+}
+
+/// The result of a submit
+struct SubmitSummary {
+    n_errors: usize,
+
+    /// The report for the summary and metrics files and the notifications, if any
+    report: Option<SubmitReport>,
+}
+
+/// Create a submit for the images and run it
+///
+/// The job DAGs of all images are built in the one submit. If there are multiple images, the
+/// artifacts of each image are written to a directory of the image in the staging directory.
+#[allow(clippy::too_many_arguments)]
+async fn submit(
+    matches: &ArgMatches,
+    progressbars: ProgressBars,
+    database_pool: Pool<ConnectionManager<PgConnection>>,
+    config: &Configuration,
+    git_repo: git2::Repository,
+    db_package: &crate::db::models::Package,
+    db_githash: &crate::db::models::GitHash,
+    dags: Vec<(ImageName, Dag)>,
+    shebang: Shebang,
+    phases: &[PhaseName],
+    additional_env: Vec<(EnvironmentVariableName, String)>,
+    staging_dir: Option<PathBuf>,
+    release_stores: Vec<Arc<ReleaseStore>>,
+    source_cache: SourceCache,
+    triage_rules: Arc<TriageRules>,
+    log_streams: Option<Arc<LogStreams>>,
+    shared_endpoints: Option<Arc<SharedEndpoints>>,
+    estimates: DurationEstimates,
+    with_report: bool,
+    recover: bool,
+) -> Result<SubmitSummary> {
+    use crate::db::models::{Image, Job, Package, Submit};
+
+    let now = chrono::offset::Local::now().naive_local();
+
+    let max_parallel = matches.get_one::<usize>("max_parallel")
+        .copied()
+        .or(*config.build_max_parallel());
+    if max_parallel == Some(0) {
+        return Err(anyhow!("The maximum number of parallel jobs must be greater than zero"))
+    }
+
+    let status_interval = matches.get_flag("quiet_progress").then_some(STATUS_LINE_INTERVAL);
+
+    let mut endpoint_configurations = crate::commands::util::endpoint_configurations(config);
+    {
+        // Because we're loading always sequencially, to have a bit more spread over the endpoints,
+        // shuffle the endpoints here. Not a perfect solution, but a working one.
+        use rand::seq::SliceRandom;
+        let mut rng = rand::thread_rng();
+        endpoint_configurations.shuffle(&mut rng);
+    }
+    info!("Endpoint config build");
+
+    let submitter = config.submitter();
+    let (staging_store, staging_dir, submit_id) = {
+        let bar_staging_loading = progressbars.bar()?;
+
+        let (submit_id, p) = if let Some(staging_dir) = staging_dir {
+            info!(
+                "Setting staging dir to {} for this run",
+                staging_dir.display()
+            );
+
+            let uuid = staging_dir.file_name()
+                .ok_or_else(|| anyhow!("Seems not to be a directory: {}", staging_dir.display()))?
+                .to_owned()
+                .into_string()
+                .map_err(|_| anyhow!("Type conversion of staging dir name to UTF8 String"))
+                .context("Parsing staging dir name to UUID")?;
+            let uuid = Uuid::parse_str(&uuid)
+                .context("Parsing directory name as UUID")
+                .with_context(|| anyhow!("Seems not to be a submit UUID: {}", uuid))?;
+
+            (uuid, staging_dir)
+        } else {
+            let submit_id = uuid::Uuid::new_v4();
+            let staging_dir = config
+                .staging_directory_of(submitter.as_deref())
+                .join(submit_id.hyphenated().to_string());
+
+            (submit_id, staging_dir)
+        };
+
+        if !p.is_dir() {
+            tokio::fs::create_dir_all(&p).await?;
+        }
+
+        debug!("Loading staging directory: {}", p.display());
+        let compression_rules = CompressionRules::from_config(config.artifact_compression())?;
+        let kind_rules = ArtifactKindRules::from_config(config.artifact_kinds())?;
+        let content_store = ContentStore::from_config(config.content_store().as_ref());
+        let unpack_limits = UnpackLimits {
+            max_file_size: *config.artifact_max_size(),
+            max_files: *config.artifact_max_files(),
+        };
+        let r = StagingStore::load(StoreRoot::new(p.clone())?, &bar_staging_loading)
+            .map(|store| {
+                store
+                    .with_compression_rules(compression_rules)
+                    .with_content_store(content_store)
+                    .with_unpack_limits(unpack_limits)
+                    .with_kind_rules(kind_rules)
+                    .with_image_directories(image_directories(config, &dags))
+            });
+        if r.is_ok() {
+            bar_staging_loading.finish_with_message("Loaded staging successfully");
+        } else {
+            bar_staging_loading.finish_with_message("Failed to load staging");
+        }
+
+        let staging_backend = StoreBackend::from_config(config.staging_backend().as_ref())?
+            .for_subdirectory(&submit_id.hyphenated().to_string());
+        let store = r?.with_backend(staging_backend).await?;
+        (Arc::new(RwLock::new(store)), p, submit_id)
+    };
+
+    let image_names = dags.iter().map(|(image_name, _)| image_name.clone()).collect::<Vec<_>>();
+    let first_image = image_names.first().ok_or_else(|| anyhow!("No image to build on"))?;
+
+    trace!("Setting up database jobs for Image");
+    let db_image = Image::create_or_fetch(&mut database_pool.get().unwrap(), first_image)?;
 
-    trace!("Database jobs for Package, GitHash, Image finished successfully");
     trace!("Creating Submit in database");
     let submit = Submit::create(
         &mut database_pool.get().unwrap(),
         &now,
         &submit_id,
         &db_image,
-        &db_package,
-        &db_githash,
+        db_package,
+        db_githash,
+        submitter.as_deref(),
     )?;
     trace!(
         "Creating Submit in database finished successfully: {:?}",
         submit
     );
 
+    let labels = matches
+        .get_many::<(String, String)>("label")
+        .map(|labels| labels.cloned().collect::<Vec<_>>())
+        .unwrap_or_default();
+    if !labels.is_empty() {
+        trace!("Recording submit labels in database");
+        crate::db::models::SubmitLabel::create_all(&mut database_pool.get().unwrap(), &submit, &labels)?;
+    }
+
     {
         let out = std::io::stdout();
         let mut outlock = out.lock();
@@ -346,18 +745,56 @@ pub async fn build(
 
         writeln!(outlock, "Starting submit: {}", mkgreen(&submit_id))?;
         writeln!(outlock, "Started at:      {}", mkgreen(&now))?;
-        writeln!(outlock, "On Image:        {}", mkgreen(&db_image.name))?;
+        writeln!(outlock, "On Image:        {}", image_names.iter().map(mkgreen).join(", "))?;
         writeln!(outlock, "For Package:     {p} {v}",
             p = mkgreen(&db_package.name),
             v = mkgreen(&db_package.version))?;
         writeln!(outlock, "On repo hash:    {}", mkgreen(&db_githash.hash))?;
+        if !labels.is_empty() {
+            writeln!(outlock, "With labels:     {}", labels.iter().map(|(k, v)| mkgreen(&format!("{k}={v}"))).join(", "))?;
+        }
     }
 
     trace!("Setting up job sets");
+    let plan = BuildPlan {
+        package_name: PackageName::from(db_package.name.clone()),
+        package_version: PackageVersion::from(db_package.version.clone()),
+        images: image_names.clone(),
+        phases: phases.to_vec(),
+        env: additional_env.clone(),
+        shebang: shebang.as_ref().to_string(),
+        staging_dir: staging_dir.clone(),
+    };
     let resources: Vec<JobResource> = additional_env.into_iter().map(JobResource::from).collect();
-    let jobdag = crate::job::Dag::from_package_dag(dag, shebang, image_name, phases.clone(), resources);
+    let mut jobdag: Option<crate::job::Dag> = None;
+    for (image_name, dag) in dags {
+        let image_jobdag = crate::job::Dag::from_package_dag(dag, shebang.clone(), image_name, phases.to_vec(), resources.clone());
+        match jobdag.as_mut() {
+            Some(jobdag) => jobdag.extend(image_jobdag)?,
+            None => jobdag = Some(image_jobdag),
+        }
+    }
+    let mut jobdag = jobdag.ok_or_else(|| anyhow!("No image to build on"))?;
     trace!("Setting up job sets finished successfully");
 
+    let stale_containers = if recover {
+        trace!("Continuing recorded job DAG");
+        crate::commands::recovery::continue_dag(&mut database_pool.get().unwrap(), &submit, &mut jobdag)
+            .context("Continuing recorded job DAG")?
+    } else {
+        trace!("Recording build plan and job DAG in database");
+        plan.record(&mut database_pool.get().unwrap(), &submit)?;
+        crate::db::models::JobDagEdge::create_for_dag(&mut database_pool.get().unwrap(), &submit, &jobdag)
+            .context("Recording job DAG in database")?;
+        crate::db::models::JobState::create_for_dag(&mut database_pool.get().unwrap(), &submit, &jobdag)?;
+
+        trace!("Recording package definitions in database");
+        for definition in jobdag.iter() {
+            crate::db::models::PackageDefinition::create(&mut database_pool.get().unwrap(), &submit, definition.job.package())?;
+        }
+        Vec::new()
+    };
+
     trace!("Setting up Orchestrator");
     let orch = OrchestratorSetup::builder()
         .progress_generator(progressbars)
@@ -366,15 +803,33 @@ pub async fn build(
         .release_stores(release_stores)
         .database(database_pool.clone())
         .source_cache(source_cache)
-        .submit(submit)
+        .submit(submit.clone())
         .log_dir(if matches.get_flag("write-log-file") {
             Some(config.log_dir().clone())
         } else {
             None
         })
+        .manifest_store(config.input_manifest_store().clone())
+        .triage_rules(triage_rules)
+        .log_limit(Arc::new(LogLimit::from_config(config)))
         .jobdag(jobdag)
         .config(config)
         .repository(git_repo)
+        .max_parallel(max_parallel)
+        .status_interval(status_interval)
+        .log_streams(log_streams)
+        .network_mode(matches.get_one::<String>("network_mode").cloned())
+        .estimates(estimates)
+        .endpoint_status(matches.get_flag("endpoint_status"))
+        .stale_containers(stale_containers)
+        .shared_endpoints(shared_endpoints)
+        .build_scope(if matches.get_flag("no_deps") {
+            BuildScope::NoDependencies
+        } else if matches.get_flag("deps_only") {
+            BuildScope::DependenciesOnly
+        } else {
+            BuildScope::All
+        })
         .build()
         .setup()
         .await?;
@@ -392,9 +847,38 @@ pub async fn build(
         writeln!(outlock, "{}", staging_dir.join(artifact_path).display()).map_err(Error::from)
     })?;
 
-    let mut had_error = false;
+    let number_log_lines = *config.build_error_lines();
+    let report = if with_report || !errors.is_empty() {
+        let jobs = JobReport::for_submit(&mut database_pool.get().unwrap(), &submit, &errors, number_log_lines)?;
+        Some(SubmitReport {
+            uuid: submit_id,
+            image: image_names.iter().join(", "),
+            package: db_package.name.clone(),
+            version: db_package.version.clone(),
+            started_at: now.to_string(),
+            finished_at: chrono::offset::Local::now().naive_local().to_string(),
+            success: errors.is_empty(),
+            jobs,
+        })
+    } else {
+        None
+    };
+
+    if let Some(report) = report.as_ref().filter(|_| !errors.is_empty()) {
+        let failure_report = FailureReport::new(report).to_json()?;
+        let path = FailureReport::path_in(config.log_dir(), &submit_id);
+        std::fs::write(&path, &failure_report)
+            .with_context(|| anyhow!("Writing failure report to {}", path.display()))?;
+        info!("Failure report written to {}", path.display());
+
+        if matches.get_flag("print_failure_report") {
+            writeln!(outlock, "{failure_report}")?;
+        }
+    }
+    let report = report.filter(|_| with_report);
+
+    let n_errors = errors.len();
     for (job_uuid, error) in errors {
-        had_error = true;
         for cause in error.chain() {
             writeln!(outlock, "{}: {}", "[ERROR]".red(), cause)?;
         }
@@ -404,7 +888,6 @@ pub async fn build(
             .inner_join(schema::packages::table)
             .first::<(Job, Package)>(&mut *database_pool.get().unwrap())?;
 
-        let number_log_lines = *config.build_error_lines();
         writeln!(
             outlock,
             "Last {} lines of Job {}",
@@ -419,7 +902,7 @@ pub async fn build(
 
         let mut last_phase = None;
         let mut error_catched = false;
-        let lines = crate::log::ParsedLog::from_str(&data.0.log_text)?
+        let lines = crate::log::ParsedLog::from_str(&data.0.log()?)?
             .into_iter()
             .map(|line_item| {
                 if let LogItem::CurrentPhase(ref p) = line_item {
@@ -467,9 +950,77 @@ pub async fn build(
         }
     }
 
-    if had_error {
-        Err(anyhow!("One or multiple errors during build"))
-    } else {
-        Ok(())
+    Ok(SubmitSummary {
+        n_errors,
+        report,
+    })
+}
+
+/// The directories in the staging directory that the artifacts of the images are written to
+///
+/// The artifacts are only written to a directory per image if there are multiple images. The
+/// directory is named after the short name of the image, or the name of the image if it is not
+/// configured.
+fn image_directories(config: &Configuration, dags: &[(ImageName, Dag)]) -> HashMap<ImageName, PathBuf> {
+    if dags.len() < 2 {
+        return HashMap::new()
+    }
+
+    dags.iter()
+        .map(|(image_name, _)| {
+            let directory = config
+                .docker()
+                .images()
+                .iter()
+                .find(|img| img.name == *image_name)
+                .map(|img| img.short_name.to_string())
+                .unwrap_or_else(|| image_name.as_ref().replace(['/', ':'], "_"));
+            (image_name.clone(), PathBuf::from(directory))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn phases(names: &[&str]) -> Vec<PhaseName> {
+        names.iter().map(|n| PhaseName::from(n.to_string())).collect()
+    }
+
+    #[test]
+    fn test_select_phases() {
+        let available = phases(&["unpack", "patch", "configure", "build"]);
+
+        assert_eq!(select_phases(&available, None, &[]).unwrap(), available);
+        assert_eq!(
+            select_phases(&available, Some(vec!["configure", "unpack"]), &[]).unwrap(),
+            phases(&["unpack", "configure"])
+        );
+        assert_eq!(
+            select_phases(&available, None, &["patch"]).unwrap(),
+            phases(&["unpack", "configure", "build"])
+        );
+        assert!(select_phases(&available, Some(vec!["install"]), &[]).is_err());
+        assert!(select_phases(&available, None, &["install"]).is_err());
+    }
+
+    #[test]
+    fn test_write_plan() {
+        use crate::package::tests::package;
+
+        let a = package("a", "1", "https://rust-lang.org", "123");
+        let b = package("b", "2", "https://rust-lang.org", "124");
+        let image = ImageName::from(String::from("debian:bullseye"));
+        let env = vec![(EnvironmentVariableName::from("FOO"), String::from("bar"))];
+
+        let mut out = vec![];
+        write_plan(&mut out, &a, &[(&image, vec![&b, &a])], &[("ep1", 2)], &env, &DurationEstimates::default(), 2).unwrap();
+        let out = String::from_utf8(out).unwrap();
+
+        assert!(out.contains("Image debian:bullseye (2 packages):\n    1. b 2\n    2. a 1\n"), "{}", out);
+        assert!(out.contains("Endpoints: ep1 (max. 2 jobs)\n"));
+        assert!(out.contains("Environment: FOO=bar\n"));
+        assert!(out.contains("At most 2 jobs on 1 images\n"));
     }
 }
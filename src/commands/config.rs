@@ -0,0 +1,48 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Implementation of the 'config' subcommand
+
+use std::io::Write;
+
+use anyhow::anyhow;
+use anyhow::Result;
+use clap::ArgMatches;
+use colored::Colorize;
+
+use crate::config::NotValidatedConfiguration;
+
+/// Implementation of the "config" subcommand
+///
+/// The configuration is not validated for these commands, so they can be used to fix it.
+pub fn config(matches: &ArgMatches, config: &NotValidatedConfiguration) -> Result<()> {
+    match matches.subcommand() {
+        Some(("themes", _)) => themes(config),
+        Some((other, _)) => Err(anyhow!("Unknown subcommand: {}", other)),
+        None => Err(anyhow!("No subcommand")),
+    }
+}
+
+/// Implementation of the "config themes" subcommand
+fn themes(config: &NotValidatedConfiguration) -> Result<()> {
+    let themes = crate::package::load_highlight_themes(config.script_highlight_theme_dir().as_deref())?;
+    let configured = config.script_highlight_theme().as_deref();
+
+    let mut out = std::io::stdout();
+    for name in themes.themes.keys() {
+        if Some(name.as_str()) == configured {
+            writeln!(out, "{} {}", name.green(), "(configured)".green())?;
+        } else {
+            writeln!(out, "{name}")?;
+        }
+    }
+
+    Ok(())
+}
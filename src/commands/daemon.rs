@@ -0,0 +1,621 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+//! Implementation of the 'daemon' subcommand
+//!
+//! The daemon serves a small HTTP API that CI systems can use to submit builds and to monitor them
+//! without running the `butido` binary themselves:
+//!
+//! * `POST /submits` with a JSON body `{"package": "...", "version": "...", "image": "...", "env": {...}}`
+//!   queues a build and answers with the UUID of the submit
+//! * `GET /submits/<uuid>` reports the state of the submit and its jobs
+//! * `GET /jobs/<uuid>/log` returns the log of a job as plain text
+//! * `GET /jobs/<uuid>/log/stream` relays the log of a running job while it is produced
+//!
+//! Builds run at the same time with the same code path as `butido build`, sharing the endpoints
+//! (see `SharedEndpoints`). The number of builds that run at the same time can be limited with
+//! `--max-submits`, further builds are queued and started by their priority.
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Error;
+use anyhow::Result;
+use clap::ArgMatches;
+use diesel::prelude::*;
+use diesel::r2d2::ConnectionManager;
+use diesel::r2d2::Pool;
+use diesel::PgConnection;
+use futures::StreamExt;
+use hyper::service::make_service_fn;
+use hyper::service::service_fn;
+use hyper::Body;
+use hyper::Method;
+use hyper::Request;
+use hyper::Response;
+use hyper::StatusCode;
+use serde::Deserialize;
+use serde::Serialize;
+use tokio::sync::broadcast;
+use tokio::sync::mpsc;
+use tracing::error;
+use tracing::info;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::commands::submit_summary::JobReport;
+use crate::config::Configuration;
+use crate::db::models as dbmodels;
+use crate::endpoint::SharedEndpoints;
+use crate::log::LogStreams;
+use crate::repository::Repository;
+use crate::schema;
+use crate::util::progress::ProgressBars;
+
+/// Number of log lines reported for failed jobs in the submit status
+const LOG_EXCERPT_LINES: usize = 20;
+
+/// Number of finished submits whose state is kept, the state of older ones is forgotten
+///
+/// The jobs of forgotten submits are still reported from the database.
+const MAX_FINISHED_SUBMITS: usize = 1000;
+
+/// A build as requested via `POST /submits`
+#[derive(Debug, Deserialize)]
+struct SubmitRequest {
+    package: String,
+    version: Option<String>,
+    image: String,
+
+    #[serde(default)]
+    env: BTreeMap<String, String>,
+
+    /// The jobs of submits with a higher priority are scheduled first
+    #[serde(default)]
+    priority: i64,
+
+    /// The share of the endpoints, relative to the other submits with the same priority
+    #[serde(default = "default_weight")]
+    weight: u32,
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+impl SubmitRequest {
+    /// Check that the request can be passed to `butido build`
+    ///
+    /// The values must not be mistaken for options and the environment variables must be valid
+    /// "key=value" pairs.
+    fn validate(&self) -> Result<()> {
+        let values = std::iter::once(("package", &self.package))
+            .chain(self.version.iter().map(|version| ("version", version)))
+            .chain(std::iter::once(("image", &self.image)));
+        for (name, value) in values {
+            if value.is_empty() || value.starts_with('-') {
+                return Err(anyhow!("Invalid {}: '{}'", name, value))
+            }
+        }
+
+        for key in self.env.keys() {
+            let valid = key.starts_with(|c: char| c.is_ascii_alphabetic())
+                && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+            if !valid {
+                return Err(anyhow!("Invalid environment variable name: '{}'", key))
+            }
+        }
+
+        crate::cli::cli()
+            .try_get_matches_from(build_args(self, &std::env::temp_dir()))
+            .map(|_| ())
+            .map_err(|e| anyhow!("{}", e.kind()))
+    }
+}
+
+/// The state of a submit that was requested via the API
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "state", rename_all = "lowercase")]
+enum SubmitState {
+    Queued,
+    Running,
+    Finished,
+    Failed { error: String },
+}
+
+#[derive(Debug, Serialize)]
+struct SubmitStatus {
+    uuid: Uuid,
+
+    /// The state of the submit, if it was requested via this daemon
+    #[serde(flatten)]
+    state: Option<SubmitState>,
+    jobs: Vec<JobReport>,
+
+    /// The jobs that are running, their logs can be streamed
+    running_jobs: Vec<Uuid>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Route {
+    Submit,
+    SubmitStatus(Uuid),
+    JobLog(Uuid),
+    JobLogStream(Uuid),
+    NotFound,
+}
+
+fn route(method: &Method, path: &str) -> Route {
+    let segments = path.trim_matches('/').split('/').collect::<Vec<_>>();
+    match (method, segments.as_slice()) {
+        (&Method::POST, ["submits"]) => Route::Submit,
+        (&Method::GET, ["submits", uuid]) => Uuid::parse_str(uuid)
+            .map(Route::SubmitStatus)
+            .unwrap_or(Route::NotFound),
+        (&Method::GET, ["jobs", uuid, "log"]) => Uuid::parse_str(uuid)
+            .map(Route::JobLog)
+            .unwrap_or(Route::NotFound),
+        (&Method::GET, ["jobs", uuid, "log", "stream"]) => Uuid::parse_str(uuid)
+            .map(Route::JobLogStream)
+            .unwrap_or(Route::NotFound),
+        _ => Route::NotFound,
+    }
+}
+
+/// The command line `butido build` would be called with for `request`
+///
+/// The package and version are passed after "--", so that they are never parsed as options.
+fn build_args(request: &SubmitRequest, staging_dir: &Path) -> Vec<String> {
+    let mut args = vec![String::from("butido"), String::from("build")];
+    args.push(format!("--image={}", request.image));
+    args.push(format!("--staging-dir={}", staging_dir.display()));
+    args.push(String::from("--yes"));
+    for (key, value) in request.env.iter() {
+        args.push(format!("--env={key}={value}"));
+    }
+    args.push(String::from("--"));
+    args.push(request.package.clone());
+    args.extend(request.version.iter().cloned());
+    args
+}
+
+/// The states of the submits that were requested via the daemon
+#[derive(Debug, Default)]
+struct Submits {
+    states: HashMap<Uuid, SubmitState>,
+
+    /// The finished submits, oldest first
+    finished: VecDeque<Uuid>,
+}
+
+impl Submits {
+    /// Set the state of a submit, the oldest finished submits are forgotten
+    fn set(&mut self, uuid: Uuid, state: SubmitState) {
+        if matches!(state, SubmitState::Finished | SubmitState::Failed { .. }) {
+            self.finished.push_back(uuid);
+            while self.finished.len() > MAX_FINISHED_SUBMITS {
+                if let Some(oldest) = self.finished.pop_front() {
+                    self.states.remove(&oldest);
+                }
+            }
+        }
+        self.states.insert(uuid, state);
+    }
+}
+
+/// State that is shared between the HTTP handlers and the build loop
+struct DaemonState {
+    database_pool: Pool<ConnectionManager<PgConnection>>,
+    staging_root: PathBuf,
+    log_streams: Arc<LogStreams>,
+    submits: Mutex<Submits>,
+    queue: mpsc::UnboundedSender<(Uuid, SubmitRequest)>,
+}
+
+impl DaemonState {
+    fn set_state(&self, uuid: Uuid, state: SubmitState) {
+        self.submits.lock().unwrap().set(uuid, state)
+    }
+
+    fn state_of(&self, uuid: &Uuid) -> Option<SubmitState> {
+        self.submits.lock().unwrap().states.get(uuid).cloned()
+    }
+}
+
+/// Implementation of the "daemon" subcommand
+pub async fn daemon(
+    matches: &ArgMatches,
+    config: &Configuration,
+    database_pool: Pool<ConnectionManager<PgConnection>>,
+    load_repo: &dyn Fn() -> Result<Repository>,
+    repo_path: &Path,
+    progressbars: ProgressBars,
+) -> Result<()> {
+    let addr = *matches.get_one::<SocketAddr>("listen").unwrap(); // safe by clap
+    let max_submits = matches.get_one::<usize>("max_submits").copied();
+    if max_submits == Some(0) {
+        return Err(anyhow!("The maximum number of builds must be greater than zero"))
+    }
+
+    let shared_endpoints = SharedEndpoints::setup(crate::commands::util::endpoint_configurations(config))
+        .await
+        .map(Arc::new)?;
+    let (queue, mut requests) = mpsc::unbounded_channel();
+    let state = Arc::new(DaemonState {
+        database_pool: database_pool.clone(),
+        staging_root: config.staging_directory_of(config.submitter().as_deref()),
+        log_streams: Arc::new(LogStreams::default()),
+        submits: Mutex::new(Submits::default()),
+        queue,
+    });
+
+    let make_service = {
+        let state = state.clone();
+        make_service_fn(move |_| {
+            let state = state.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| handle(request, state.clone())))
+            }
+        })
+    };
+
+    let server = hyper::Server::try_bind(&addr)
+        .with_context(|| anyhow!("Binding to {}", addr))?
+        .serve(make_service);
+    info!("Listening on {}", addr);
+
+    let run_submit = |uuid: Uuid, request: SubmitRequest| {
+        let (state, database_pool, progressbars, shared_endpoints) = (&state, &database_pool, &progressbars, &shared_endpoints);
+        async move {
+            info!("Starting build of submit {}", uuid);
+            state.set_state(uuid, SubmitState::Running);
+            shared_endpoints.fair_share().register(uuid, request.priority, request.weight);
+            let staging_dir = state.staging_root.join(uuid.to_string());
+            let result = run_build(
+                &request,
+                &staging_dir,
+                config,
+                database_pool.clone(),
+                load_repo,
+                repo_path,
+                progressbars.clone(),
+                state.log_streams.clone(),
+                shared_endpoints.clone(),
+            )
+            .await;
+            shared_endpoints.fair_share().unregister(&uuid);
+
+            let new_state = match result {
+                Ok(()) => SubmitState::Finished,
+                Err(e) => {
+                    warn!("Build of submit {} failed: {:?}", uuid, e);
+                    let error = e.chain().map(|cause| cause.to_string()).collect::<Vec<_>>().join(": ");
+                    SubmitState::Failed { error }
+                }
+            };
+            state.set_state(uuid, new_state);
+        }
+    };
+
+    // The builds are not run on the server tasks, as a build is not Send, but they run
+    // concurrently on this task
+    let build_loop = async {
+        let mut queued = Vec::new();
+        let mut builds = futures::stream::FuturesUnordered::new();
+        loop {
+            tokio::select! {
+                request = requests.recv() => match request {
+                    Some(request) => queued.push(request),
+                    None => break,
+                },
+                Some(()) = builds.next(), if !builds.is_empty() => {},
+            }
+
+            while max_submits.map(|max| builds.len() < max).unwrap_or(true) {
+                let Some(next) = next_queued(&queued) else { break };
+                let (uuid, request) = queued.remove(next);
+                builds.push(run_submit(uuid, request));
+            }
+        }
+    };
+
+    tokio::select! {
+        res = server => res.context("Running HTTP server").map_err(Error::from),
+        _ = build_loop => Err(anyhow!("Build queue closed unexpectedly")),
+    }
+}
+
+/// The index of the queued submit that is started next: the first one with the highest priority
+fn next_queued(queued: &[(Uuid, SubmitRequest)]) -> Option<usize> {
+    queued
+        .iter()
+        .enumerate()
+        .max_by_key(|(i, (_, request))| (request.priority, std::cmp::Reverse(*i)))
+        .map(|(i, _)| i)
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn run_build(
+    request: &SubmitRequest,
+    staging_dir: &Path,
+    config: &Configuration,
+    database_pool: Pool<ConnectionManager<PgConnection>>,
+    load_repo: &dyn Fn() -> Result<Repository>,
+    repo_path: &Path,
+    progressbars: ProgressBars,
+    log_streams: Arc<LogStreams>,
+    shared_endpoints: Arc<SharedEndpoints>,
+) -> Result<()> {
+    let matches = crate::cli::cli()
+        .try_get_matches_from(build_args(request, staging_dir))
+        .context("Parsing build request")?;
+    let build_matches = matches.subcommand_matches("build").unwrap(); // safe by construction
+    let repo = load_repo()?;
+    super::build(
+        repo_path,
+        build_matches,
+        progressbars.hidden(),
+        database_pool,
+        config,
+        repo,
+        repo_path,
+        Some(log_streams),
+        Some(shared_endpoints),
+    )
+    .await
+}
+
+async fn handle(request: Request<Body>, state: Arc<DaemonState>) -> Result<Response<Body>, Infallible> {
+    let method = request.method().clone();
+    let path = request.uri().path().to_owned();
+    let response = match route(request.method(), request.uri().path()) {
+        Route::Submit => submit(request, &state).await,
+        Route::SubmitStatus(uuid) => submit_status(uuid, &state).await,
+        Route::JobLog(uuid) => job_log(uuid, &state).await,
+        Route::JobLogStream(uuid) => job_log_stream(uuid, &state).await,
+        Route::NotFound => Ok(text_response(StatusCode::NOT_FOUND, String::from("Not found"))),
+    };
+
+    // The details of the error are only logged, they are not meant for the clients
+    Ok(response.unwrap_or_else(|e| {
+        error!("Error handling request {} {}: {:?}", method, path, e);
+        text_response(StatusCode::INTERNAL_SERVER_ERROR, String::from("Internal server error"))
+    }))
+}
+
+async fn submit(request: Request<Body>, state: &DaemonState) -> Result<Response<Body>> {
+    let body = hyper::body::to_bytes(request.into_body()).await?;
+    let submit_request = match serde_json::from_slice::<SubmitRequest>(&body) {
+        Ok(r) => r,
+        Err(e) => return Ok(text_response(StatusCode::BAD_REQUEST, format!("Invalid submit request: {e}"))),
+    };
+    if let Err(e) = submit_request.validate() {
+        return Ok(text_response(StatusCode::BAD_REQUEST, format!("Invalid submit request: {e}")))
+    }
+
+    let uuid = Uuid::new_v4();
+    let staging_dir = state.staging_root.join(uuid.to_string());
+    tokio::fs::create_dir_all(&staging_dir)
+        .await
+        .with_context(|| anyhow!("Creating staging directory {}", staging_dir.display()))?;
+
+    state.set_state(uuid, SubmitState::Queued);
+    state.queue
+        .send((uuid, submit_request))
+        .map_err(|_| anyhow!("Build queue closed"))?;
+    info!("Queued submit {}", uuid);
+    json_response(StatusCode::ACCEPTED, &serde_json::json!({ "uuid": uuid }))
+}
+
+async fn submit_status(uuid: Uuid, state: &DaemonState) -> Result<Response<Body>> {
+    let pool = state.database_pool.clone();
+    let jobs = tokio::task::spawn_blocking(move || -> Result<Option<Vec<JobReport>>> {
+        let mut conn = pool.get()?;
+        schema::submits::table
+            .filter(schema::submits::uuid.eq(uuid))
+            .first::<dbmodels::Submit>(&mut conn)
+            .optional()?
+            .map(|submit| JobReport::for_submit(&mut conn, &submit, &HashMap::new(), LOG_EXCERPT_LINES))
+            .transpose()
+    })
+    .await??;
+
+    let running_jobs = state.log_streams.running_jobs_of(&uuid);
+    let state = state.state_of(&uuid);
+    if jobs.is_none() && state.is_none() {
+        return Ok(text_response(StatusCode::NOT_FOUND, format!("Unknown submit {uuid}")));
+    }
+
+    let status = SubmitStatus {
+        uuid,
+        state,
+        jobs: jobs.unwrap_or_default(),
+        running_jobs,
+    };
+    json_response(StatusCode::OK, &status)
+}
+
+async fn job_log(uuid: Uuid, state: &DaemonState) -> Result<Response<Body>> {
+    let pool = state.database_pool.clone();
+    let log = tokio::task::spawn_blocking(move || -> Result<Option<String>> {
+        let mut conn = pool.get()?;
+        schema::jobs::table
+            .filter(schema::jobs::uuid.eq(uuid))
+            .first::<dbmodels::Job>(&mut conn)
+            .optional()?
+            .map(|job| job.full_log())
+            .transpose()
+    })
+    .await??;
+
+    match log {
+        None => Ok(text_response(StatusCode::NOT_FOUND, format!("Unknown job {uuid}"))),
+        Some(log) => {
+            let text = crate::log::ParsedLog::from_str(&log)?
+                .into_iter()
+                .map(|item| item.raw())
+                .collect::<Result<Vec<_>>>()?
+                .join("\n");
+            Ok(text_response(StatusCode::OK, text))
+        }
+    }
+}
+
+/// Relay the log of a running job, chunk by chunk
+///
+/// If the job is not running (anymore), the stored log is returned.
+async fn job_log_stream(uuid: Uuid, state: &DaemonState) -> Result<Response<Body>> {
+    let mut receiver = match state.log_streams.subscribe(&uuid) {
+        Some(receiver) => receiver,
+        None => return job_log(uuid, state).await,
+    };
+
+    let (mut sender, body) = Body::channel();
+    tokio::spawn(async move {
+        loop {
+            let line = match receiver.recv().await {
+                Ok(item) => match item.raw() {
+                    Ok(line) => line,
+                    Err(e) => {
+                        warn!("Cannot relay log item of job {}: {:?}", uuid, e);
+                        continue;
+                    }
+                },
+                Err(broadcast::error::RecvError::Lagged(n)) => format!("#BUTIDO:SKIPPED:{n}"),
+                Err(broadcast::error::RecvError::Closed) => break,
+            };
+
+            if sender.send_data(hyper::body::Bytes::from(line + "\n")).await.is_err() {
+                break; // client went away
+            }
+        }
+    });
+
+    let mut response = Response::new(body);
+    response
+        .headers_mut()
+        .insert(hyper::header::CONTENT_TYPE, hyper::header::HeaderValue::from_static("text/plain; charset=utf-8"));
+    Ok(response)
+}
+
+fn text_response(status: StatusCode, text: String) -> Response<Body> {
+    let mut response = Response::new(Body::from(text));
+    *response.status_mut() = status;
+    response
+        .headers_mut()
+        .insert(hyper::header::CONTENT_TYPE, hyper::header::HeaderValue::from_static("text/plain; charset=utf-8"));
+    response
+}
+
+fn json_response<T: Serialize>(status: StatusCode, value: &T) -> Result<Response<Body>> {
+    let body = serde_json::to_string(value).context("Serializing response")?;
+    let mut response = Response::new(Body::from(body));
+    *response.status_mut() = status;
+    response
+        .headers_mut()
+        .insert(hyper::header::CONTENT_TYPE, hyper::header::HeaderValue::from_static("application/json"));
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_route() {
+        let uuid = Uuid::new_v4();
+        assert_eq!(route(&Method::POST, "/submits"), Route::Submit);
+        assert_eq!(route(&Method::GET, &format!("/submits/{uuid}")), Route::SubmitStatus(uuid));
+        assert_eq!(route(&Method::GET, &format!("/jobs/{uuid}/log")), Route::JobLog(uuid));
+        assert_eq!(route(&Method::GET, &format!("/jobs/{uuid}/log/stream")), Route::JobLogStream(uuid));
+        assert_eq!(route(&Method::GET, "/submits"), Route::NotFound);
+        assert_eq!(route(&Method::GET, "/submits/not-a-uuid"), Route::NotFound);
+        assert_eq!(route(&Method::DELETE, &format!("/submits/{uuid}")), Route::NotFound);
+    }
+
+    #[test]
+    fn test_build_args_are_accepted_by_cli() {
+        let request: SubmitRequest = serde_json::from_str(r#"{
+            "package": "foo",
+            "version": "1.0",
+            "image": "debian:bullseye",
+            "env": { "FOO": "bar" }
+        }"#).unwrap();
+
+        let matches = crate::cli::cli()
+            .try_get_matches_from(build_args(&request, &std::env::temp_dir()))
+            .unwrap();
+        let build = matches.subcommand_matches("build").unwrap();
+        assert_eq!(build.get_one::<String>("package_name").unwrap(), "foo");
+        assert_eq!(build.get_one::<String>("package_version").unwrap(), "1.0");
+        assert_eq!(build.get_one::<String>("image").unwrap(), "debian:bullseye");
+        assert!(request.validate().is_ok());
+    }
+
+    #[test]
+    fn test_validate_rejects_options() {
+        let request = |json: serde_json::Value| serde_json::from_value::<SubmitRequest>(json).unwrap();
+        assert!(request(serde_json::json!({ "package": "foo", "version": "--some-flag", "image": "debian:bullseye" })).validate().is_err());
+        assert!(request(serde_json::json!({ "package": "-x", "image": "debian:bullseye" })).validate().is_err());
+        assert!(request(serde_json::json!({ "package": "foo", "image": "--staging-dir=/tmp" })).validate().is_err());
+        assert!(request(serde_json::json!({ "package": "foo", "image": "debian:bullseye", "env": { "x=y --staging-dir": "/tmp" } })).validate().is_err());
+        assert!(request(serde_json::json!({ "package": "foo", "image": "debian:bullseye", "env": { "FOO": "x=y --staging-dir /tmp" } })).validate().is_ok());
+    }
+
+    #[test]
+    fn test_finished_submits_are_forgotten() {
+        let mut submits = Submits::default();
+        let uuids = (0..=MAX_FINISHED_SUBMITS).map(|_| Uuid::new_v4()).collect::<Vec<_>>();
+        for uuid in uuids.iter() {
+            submits.set(*uuid, SubmitState::Running);
+            submits.set(*uuid, SubmitState::Finished);
+        }
+        assert_eq!(submits.states.len(), MAX_FINISHED_SUBMITS);
+        assert!(!submits.states.contains_key(&uuids[0]));
+        assert!(submits.states.contains_key(&uuids[MAX_FINISHED_SUBMITS]));
+    }
+
+    #[test]
+    fn test_next_queued() {
+        let request = |priority: i64| -> (Uuid, SubmitRequest) {
+            let request = serde_json::json!({ "package": "foo", "image": "debian:bullseye", "priority": priority });
+            (Uuid::new_v4(), serde_json::from_value(request).unwrap())
+        };
+
+        assert_eq!(next_queued(&[]), None);
+        assert_eq!(next_queued(&[request(0), request(0)]), Some(0));
+        assert_eq!(next_queued(&[request(0), request(5), request(5)]), Some(1));
+
+        let (_, default) = request(0);
+        assert_eq!(default.weight, 1);
+    }
+
+    #[test]
+    fn test_submit_status_serialization() {
+        let status = SubmitStatus {
+            uuid: Uuid::nil(),
+            state: Some(SubmitState::Failed { error: String::from("boom") }),
+            jobs: vec![],
+            running_jobs: vec![],
+        };
+        let value = serde_json::to_value(&status).unwrap();
+        assert_eq!(value["state"], "failed");
+        assert_eq!(value["error"], "boom");
+    }
+}
@@ -10,7 +10,10 @@
 
 //! Implementation of the 'db' subcommand
 
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
 use std::collections::HashMap;
+use std::collections::hash_map::Entry;
 use std::io::Write;
 use std::path::PathBuf;
 use std::process::Command;
@@ -25,6 +28,8 @@ use colored::Colorize;
 use diesel::BelongingToDsl;
 use diesel::ExpressionMethods;
 use diesel::JoinOnDsl;
+use diesel::PgConnection;
+use diesel::NullableExpressionMethods;
 use diesel::QueryDsl;
 use diesel::RunQueryDsl;
 use diesel_migrations::embed_migrations;
@@ -38,6 +43,7 @@ use crate::commands::util::get_date_filter;
 use crate::config::Configuration;
 use crate::db::models;
 use crate::db::DbConnectionConfig;
+use crate::job::InputManifest;
 use crate::log::JobResult;
 use crate::package::Script;
 use crate::schema;
@@ -53,15 +59,18 @@ pub fn db(
     match matches.subcommand() {
         Some(("cli", matches)) => cli(db_connection_config, matches),
         Some(("setup", _matches)) => setup(db_connection_config),
-        Some(("artifacts", matches)) => artifacts(db_connection_config, matches),
+        Some(("artifacts", matches)) => artifacts(db_connection_config, config, matches),
         Some(("envvars", matches)) => envvars(db_connection_config, matches),
         Some(("images", matches)) => images(db_connection_config, matches),
         Some(("submit", matches)) => submit(db_connection_config, matches),
         Some(("submits", matches)) => submits(db_connection_config, matches),
+        Some(("submit-diff", matches)) => submit_diff(db_connection_config, matches),
         Some(("jobs", matches)) => jobs(db_connection_config, config, matches),
         Some(("job", matches)) => job(db_connection_config, config, matches),
+        Some(("job-diff", matches)) => job_diff(db_connection_config, config, matches),
         Some(("log-of", matches)) => log_of(db_connection_config, matches),
         Some(("releases", matches)) => releases(db_connection_config, config, matches),
+        Some(("stats", matches)) => stats(db_connection_config, matches),
         Some((other, _)) => Err(anyhow!("Unknown subcommand: {}", other)),
         None => Err(anyhow!("No subcommand")),
     }
@@ -160,42 +169,71 @@ fn setup(conn_cfg: DbConnectionConfig<'_>) -> Result<()> {
 }
 
 /// Implementation of the "db artifacts" subcommand
-fn artifacts(conn_cfg: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()> {
+fn artifacts(conn_cfg: DbConnectionConfig<'_>, config: &Configuration, matches: &ArgMatches) -> Result<()> {
     use crate::schema::artifacts::dsl;
 
+    if let Some(package) = matches.get_many::<String>("for_package") {
+        let package = package.map(String::as_str).collect::<Vec<_>>();
+        return artifacts_of_package(conn_cfg, config, matches, package[0], package.get(1).copied());
+    }
+
     let csv = matches.get_flag("csv");
-    let hdrs = crate::commands::util::mk_header(vec!["Path", "Released", "Job"]);
+    let metadata_names = matches.get_many::<String>("metadata").map(|names| names.cloned().collect::<Vec<_>>()).unwrap_or_default();
+    let hdrs = ["Path", "Kind", "Released", "Job"]
+        .into_iter()
+        .chain(metadata_names.iter().map(String::as_str))
+        .collect::<Vec<_>>();
+    let hdrs = crate::commands::util::mk_header(hdrs);
     let mut conn = conn_cfg.establish_connection()?;
-    let data = matches
-        .get_one::<String>("job_uuid")
-        .map(|s| uuid::Uuid::parse_str(s.as_ref()))
-        .transpose()?
-        .map(|job_uuid| -> Result<_> {
-            dsl::artifacts
-                .inner_join(schema::jobs::table)
-                .left_join(schema::releases::table)
-                .filter(schema::jobs::dsl::uuid.eq(job_uuid))
-                .load::<(models::Artifact, models::Job, Option<models::Release>)>(&mut conn)
-                .map_err(Error::from)
-        })
-        .unwrap_or_else(|| {
-            dsl::artifacts
-                .inner_join(schema::jobs::table)
-                .left_join(schema::releases::table)
-                .order_by(schema::artifacts::id.asc())
-                .load::<(models::Artifact, models::Job, Option<models::Release>)>(&mut conn)
-                .map_err(Error::from)
-        })?
+    let mut query = dsl::artifacts
+        .inner_join(schema::jobs::table)
+        .left_join(schema::releases::table)
+        .order_by(schema::artifacts::id.asc())
+        .into_boxed();
+
+    if let Some(job_uuid) = matches.get_one::<String>("job_uuid").map(|s| uuid::Uuid::parse_str(s.as_ref())).transpose()? {
+        query = query.filter(schema::jobs::dsl::uuid.eq(job_uuid));
+    }
+
+    if let Some(user) = matches.get_one::<String>("user") {
+        query = query.filter(schema::jobs::dsl::submit_id.eq_any(submit_ids_of_user(&mut conn, user)?));
+    }
+
+    if let Some(kinds) = matches.get_many::<String>("kind") {
+        query = query.filter(schema::artifacts::artifact_kind.eq_any(kinds.cloned().collect::<Vec<_>>()));
+    }
+
+    let rows = query.load::<(models::Artifact, models::Job, Option<models::Release>)>(&mut conn)?;
+    let metadata = if metadata_names.is_empty() {
+        vec![]
+    } else {
+        let job_ids = rows.iter().map(|(_, job, _)| job.id).unique().collect::<Vec<_>>();
+        models::JobMetadata::for_jobs(&mut conn, &job_ids)?
+    };
+
+    let data = rows
         .into_iter()
         .map(|(artifact, job, rel)| {
             let rel = rel
                 .map(|r| r.release_date.to_string())
                 .unwrap_or_else(|| String::from("no"));
+            let job_metadata = metadata_names.iter().map(|name| {
+                metadata
+                    .iter()
+                    .find(|m| m.job_id == job.id && m.name == *name)
+                    .map(|m| m.value.clone())
+                    .unwrap_or_default()
+            });
+
             vec![
                 artifact.path,
+                artifact.artifact_kind,
                 rel,
                 job.uuid.to_string(),
             ]
+            .into_iter()
+            .chain(job_metadata)
+            .collect::<Vec<_>>()
         })
         .collect::<Vec<_>>();
 
@@ -208,19 +246,156 @@ fn artifacts(conn_cfg: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<(
     Ok(())
 }
 
-/// Implementation of the "db envvars" subcommand
-fn envvars(conn_cfg: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()> {
-    use crate::schema::envvars::dsl;
-
+/// Implementation of "db artifacts --for-package": all artifacts that were built for a package
+///
+/// The path of an artifact is its path in the release store if it was released, otherwise its
+/// path in the staging directory of its submit (which might have been removed since).
+fn artifacts_of_package(
+    conn_cfg: DbConnectionConfig<'_>,
+    config: &Configuration,
+    matches: &ArgMatches,
+    package_name: &str,
+    package_version: Option<&str>,
+) -> Result<()> {
     let csv = matches.get_flag("csv");
-    let hdrs = crate::commands::util::mk_header(vec!["Name", "Value"]);
+    let hdrs = crate::commands::util::mk_header(["Package", "Version", "Submit", "Job", "Path", "SHA256", "Released"].to_vec());
     let mut conn = conn_cfg.establish_connection()?;
-    let data = dsl::envvars
-        .load::<models::EnvVar>(&mut conn)?
+    let mut query = schema::artifacts::table
+        .inner_join({
+            schema::jobs::table
+                .inner_join(schema::packages::table)
+                .inner_join(schema::submits::table)
+        })
+        .left_join(schema::releases::table.inner_join(schema::release_stores::table))
+        .filter(schema::packages::name.eq(package_name))
+        .order_by(schema::submits::submit_time.asc())
+        .then_order_by(schema::artifacts::id.asc())
+        .into_boxed();
+
+    if let Some(version) = package_version {
+        query = query.filter(schema::packages::version.eq(version));
+    }
+
+    if let Some(user) = matches.get_one::<String>("user") {
+        query = query.filter(schema::jobs::dsl::submit_id.eq_any(submit_ids_of_user(&mut conn, user)?));
+    }
+
+    if let Some(kinds) = matches.get_many::<String>("kind") {
+        query = query.filter(schema::artifacts::artifact_kind.eq_any(kinds.cloned().collect::<Vec<_>>()));
+    }
+
+    let data = query
+        .load::<(models::Artifact, (models::Job, models::Package, models::Submit), Option<(models::Release, models::ReleaseStore)>)>(&mut conn)?
         .into_iter()
-        .map(|evar| vec![evar.name, evar.value])
+        .map(|(artifact, (job, package, submit), release)| {
+            let (path, released) = match release {
+                Some((release, store)) => (
+                    config.releases_directory().join(&store.store_name).join(&release.path),
+                    format!("{} ({})", release.release_date, store.store_name),
+                ),
+                None => (
+                    config
+                        .staging_directory_of(submit.submitted_by.as_deref())
+                        .join(submit.uuid.to_string())
+                        .join(&artifact.path),
+                    String::from("no"),
+                ),
+            };
+
+            vec![
+                package.name,
+                package.version,
+                submit.uuid.to_string(),
+                job.uuid.to_string(),
+                path.display().to_string(),
+                artifact.sha256.unwrap_or_else(|| String::from("-")),
+                released,
+            ]
+        })
         .collect::<Vec<_>>();
 
+    if data.is_empty() {
+        info!("No artifacts for {} {} in database", package_name, package_version.unwrap_or("(any version)"));
+    } else {
+        crate::commands::util::display_data(hdrs, data, csv)?;
+    }
+
+    Ok(())
+}
+
+/// Implementation of the "db envvars" subcommand
+fn envvars(conn_cfg: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()> {
+    let csv = matches.get_flag("csv");
+    let list_jobs = matches.get_flag("jobs");
+    let mut conn = conn_cfg.establish_connection()?;
+
+    let mut sel = schema::envvars::table
+        .left_join({
+            schema::job_envs::table.inner_join({
+                schema::jobs::table
+                    .inner_join(schema::packages::table)
+                    .inner_join(schema::submits::table)
+            })
+        })
+        .into_boxed();
+
+    if let Some(name) = matches.get_one::<String>("name") {
+        match name.split_once('=') {
+            Some((name, value)) => {
+                sel = sel.filter(schema::envvars::name.eq(name.to_string()))
+                    .filter(schema::envvars::value.eq(value.to_string()));
+            },
+            None => sel = sel.filter(schema::envvars::name.eq(name)),
+        }
+    }
+
+    if let Some(package) = matches.get_one::<String>("used_by_package") {
+        sel = sel.filter(schema::packages::name.eq(package));
+    }
+
+    if let Some(submit) = matches.get_one::<uuid::Uuid>("submit") {
+        sel = sel.filter(schema::submits::uuid.eq(submit));
+    }
+
+    let (hdrs, data) = if list_jobs {
+        let hdrs = crate::commands::util::mk_header(vec!["Name", "Value", "Job", "Package", "Version", "Submit"]);
+        let data = sel
+            .filter(schema::jobs::id.is_not_null())
+            .select((
+                schema::envvars::all_columns,
+                schema::jobs::uuid.nullable(),
+                schema::packages::name.nullable(),
+                schema::packages::version.nullable(),
+                schema::submits::uuid.nullable(),
+            ))
+            .order_by((schema::envvars::name, schema::envvars::value, schema::jobs::id))
+            .load::<(models::EnvVar, Option<uuid::Uuid>, Option<String>, Option<String>, Option<uuid::Uuid>)>(&mut conn)?
+            .into_iter()
+            .map(|(evar, job, package, version, submit)| {
+                vec![
+                    evar.name,
+                    evar.value,
+                    job.map(|j| j.to_string()).unwrap_or_default(),
+                    package.unwrap_or_default(),
+                    version.unwrap_or_default(),
+                    submit.map(|s| s.to_string()).unwrap_or_default(),
+                ]
+            })
+            .collect::<Vec<_>>();
+        (hdrs, data)
+    } else {
+        let hdrs = crate::commands::util::mk_header(vec!["Name", "Value"]);
+        let data = sel
+            .select(schema::envvars::all_columns)
+            .distinct()
+            .order_by((schema::envvars::name, schema::envvars::value))
+            .load::<models::EnvVar>(&mut conn)?
+            .into_iter()
+            .map(|evar| vec![evar.name, evar.value])
+            .collect::<Vec<_>>();
+        (hdrs, data)
+    };
+
     if data.is_empty() {
         info!("No environment variables in database");
     } else {
@@ -232,16 +407,67 @@ fn envvars(conn_cfg: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()>
 
 /// Implementation of the "db images" subcommand
 fn images(conn_cfg: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()> {
-    use crate::schema::images::dsl;
-
     let csv = matches.get_flag("csv");
-    let hdrs = crate::commands::util::mk_header(vec!["Name"]);
+    let list_jobs = matches.get_flag("jobs");
     let mut conn = conn_cfg.establish_connection()?;
-    let data = dsl::images
-        .load::<models::Image>(&mut conn)?
-        .into_iter()
-        .map(|image| vec![image.name])
-        .collect::<Vec<_>>();
+
+    let mut sel = schema::images::table
+        .left_join({
+            schema::jobs::table
+                .inner_join(schema::packages::table)
+                .inner_join(schema::submits::table)
+        })
+        .into_boxed();
+
+    if let Some(name) = matches.get_one::<String>("name") {
+        sel = sel.filter(schema::images::name.eq(name));
+    }
+
+    if let Some(package) = matches.get_one::<String>("used_by_package") {
+        sel = sel.filter(schema::packages::name.eq(package));
+    }
+
+    if let Some(submit) = matches.get_one::<uuid::Uuid>("submit") {
+        sel = sel.filter(schema::submits::uuid.eq(submit));
+    }
+
+    let (hdrs, data) = if list_jobs {
+        let hdrs = crate::commands::util::mk_header(vec!["Name", "Job", "Package", "Version", "Submit"]);
+        let data = sel
+            .filter(schema::jobs::id.is_not_null())
+            .select((
+                schema::images::all_columns,
+                schema::jobs::uuid.nullable(),
+                schema::packages::name.nullable(),
+                schema::packages::version.nullable(),
+                schema::submits::uuid.nullable(),
+            ))
+            .order_by((schema::images::name, schema::jobs::id))
+            .load::<(models::Image, Option<uuid::Uuid>, Option<String>, Option<String>, Option<uuid::Uuid>)>(&mut conn)?
+            .into_iter()
+            .map(|(image, job, package, version, submit)| {
+                vec![
+                    image.name,
+                    job.map(|j| j.to_string()).unwrap_or_default(),
+                    package.unwrap_or_default(),
+                    version.unwrap_or_default(),
+                    submit.map(|s| s.to_string()).unwrap_or_default(),
+                ]
+            })
+            .collect::<Vec<_>>();
+        (hdrs, data)
+    } else {
+        let hdrs = crate::commands::util::mk_header(vec!["Name"]);
+        let data = sel
+            .select(schema::images::all_columns)
+            .distinct()
+            .order_by(schema::images::name)
+            .load::<models::Image>(&mut conn)?
+            .into_iter()
+            .map(|image| vec![image.name])
+            .collect::<Vec<_>>();
+        (hdrs, data)
+    };
 
     if data.is_empty() {
         info!("No images in database");
@@ -267,6 +493,9 @@ fn submit(conn_cfg: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()>
     let githash = models::GitHash::with_id(&mut conn, submit.repo_hash_id)
         .with_context(|| anyhow!("Loading GitHash '{}' from DB", submit.repo_hash_id))?;
 
+    let labels = models::SubmitLabel::for_submits(&mut conn, &[submit.id])
+        .with_context(|| anyhow!("Loading labels of submit '{}' from DB", submit_id))?;
+
     let jobs = schema::submits::table
         .inner_join(schema::jobs::table)
         .filter(schema::submits::uuid.eq(&submit_id))
@@ -281,7 +510,7 @@ fn submit(conn_cfg: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()>
         let mut err = 0;
 
         for j in jobs.iter() {
-            match crate::log::ParsedLog::from_str(&j.log_text)?.is_successfull() {
+            match j.result()? {
                 JobResult::Unknown => unkn += 1,
                 JobResult::Success => succ += 1,
                 JobResult::Errored => err += 1,
@@ -297,7 +526,11 @@ fn submit(conn_cfg: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()>
     indoc::writedoc!(outlock, r#"
             Submit   {submit_id}
             Date:    {submit_dt}
+            User:    {submit_user}
+            Host:    {submit_host}
+            Version: {submit_version}
             Commit:  {submit_commit}
+            Labels:  {submit_labels}
             Jobs:    {n_jobs}
             Success: {n_jobs_success}
             Unknown: {n_jobs_unknown}
@@ -306,13 +539,53 @@ fn submit(conn_cfg: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()>
         "#,
         submit_id = submit.uuid.to_string().cyan(),
         submit_dt = submit.submit_time.to_string().cyan(),
+        submit_user = submit.submitted_by.as_deref().unwrap_or("unknown").cyan(),
+        submit_host = submit.submitted_from.as_deref().unwrap_or("unknown").cyan(),
+        submit_version = submit.butido_version.as_deref().unwrap_or("unknown").cyan(),
         submit_commit = githash.hash.cyan(),
+        submit_labels = labels.iter().map(|label| label.to_string().cyan()).join(", "),
         n_jobs = n_jobs.to_string().cyan(),
         n_jobs_success = jobs_success.to_string().green(),
         n_jobs_unknown = jobs_unknown.to_string().red(),
         n_jobs_err = jobs_err.to_string().red(),
     )?;
 
+    if matches.get_flag("definitions") {
+        for (definition, package) in models::PackageDefinition::for_submit(&mut conn, &submit)? {
+            writeln!(outlock, "--- {} {}\n\n{}\n", package.name.cyan(), package.version.cyan(), definition.definition)?;
+        }
+        return Ok(());
+    }
+
+    if matches.get_flag("tree") {
+        let jobs_by_uuid = jobs.iter().map(|job| (job.uuid, job)).collect::<HashMap<_, _>>();
+        let mut nodes = HashMap::<uuid::Uuid, (String, Vec<uuid::Uuid>)>::new();
+        for (edge, package) in models::JobDagEdge::for_submit(&mut conn, &submit)? {
+            let node = match nodes.entry(edge.job_uuid) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => {
+                    let state = match jobs_by_uuid.get(&edge.job_uuid) {
+                        None => "not run".yellow(),
+                        Some(job) => match is_job_successfull(job)? {
+                            Some(true) => "Success".green(),
+                            Some(false) => "Error".red(),
+                            None => "Unknown".yellow(),
+                        },
+                    };
+                    let label = format!("{} {} ({}) [{}]", package.name, package.version, edge.job_uuid, state);
+                    entry.insert((label, vec![]))
+                }
+            };
+            node.1.extend(edge.dependency_uuid);
+        }
+
+        if nodes.is_empty() {
+            return writeln!(outlock, "No job DAG recorded for submit {submit_id}").map_err(Error::from)
+        }
+        let tree = job_dag_tree(format!("Submit {submit_id}"), &nodes);
+        return ptree::write_tree(&tree, &mut outlock).map_err(Error::from)
+    }
+
     let header = crate::commands::util::mk_header(["Job", "Success", "Package", "Version", "Container", "Endpoint", "Image"].to_vec());
     let data = jobs.iter()
         .map(|job| {
@@ -341,11 +614,213 @@ fn submit(conn_cfg: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()>
     crate::commands::util::display_data(header, data, false)
 }
 
+/// Build the tree of a job DAG from its nodes, which are labels and the UUIDs of the dependencies,
+/// by job UUID
+///
+/// Jobs that are a dependency of more than one job appear more than once in the tree.
+fn job_dag_tree(title: String, nodes: &HashMap<uuid::Uuid, (String, Vec<uuid::Uuid>)>) -> ptree::item::StringItem {
+    fn add_node(
+        builder: &mut ptree::TreeBuilder,
+        nodes: &HashMap<uuid::Uuid, (String, Vec<uuid::Uuid>)>,
+        uuid: &uuid::Uuid,
+    ) {
+        if let Some((label, dependencies)) = nodes.get(uuid) {
+            builder.begin_child(label.clone());
+            dependencies
+                .iter()
+                .sorted_by_key(|dep| nodes.get(dep).map(|(label, _)| label))
+                .for_each(|dep| add_node(builder, nodes, dep));
+            builder.end_child();
+        }
+    }
+
+    let mut builder = ptree::TreeBuilder::new(title);
+    nodes.keys()
+        .filter(|uuid| !nodes.values().any(|(_, dependencies)| dependencies.contains(uuid)))
+        .sorted_by_key(|uuid| &nodes[*uuid].0)
+        .for_each(|root| add_node(&mut builder, nodes, root));
+    builder.build()
+}
+
+/// The properties of a job that are compared by "db submit-diff"
+#[derive(Debug)]
+struct DiffedJob {
+    version: String,
+    success: Option<bool>,
+    duration: Option<chrono::Duration>,
+    env: BTreeSet<(String, String)>,
+    script: String,
+}
+
+impl DiffedJob {
+    /// Load the jobs of a submit, by package name and version
+    fn load_for_submit(conn: &mut PgConnection, submit: &models::Submit) -> Result<BTreeMap<(String, String), DiffedJob>> {
+        let jobs = schema::jobs::table
+            .inner_join(schema::packages::table)
+            .filter(schema::jobs::submit_id.eq(submit.id))
+            .select((schema::jobs::all_columns, schema::packages::all_columns))
+            .load::<(models::Job, models::Package)>(conn)
+            .with_context(|| anyhow!("Loading jobs for submit = {}", submit.uuid))?;
+
+        let mut envs = schema::job_envs::table
+            .inner_join(schema::jobs::table)
+            .inner_join(schema::envvars::table)
+            .filter(schema::jobs::submit_id.eq(submit.id))
+            .select((schema::job_envs::job_id, schema::envvars::name, schema::envvars::value))
+            .load::<(i32, String, String)>(conn)
+            .with_context(|| anyhow!("Loading environments of the jobs for submit = {}", submit.uuid))?
+            .into_iter()
+            .into_group_map_by(|(job_id, _, _)| *job_id);
+
+        jobs.into_iter()
+            .map(|(job, package)| {
+                let env = envs
+                    .remove(&job.id)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|(_, name, value)| (name, value))
+                    .collect();
+
+                let diffed = DiffedJob {
+                    version: package.version.clone(),
+                    success: is_job_successfull(&job)?,
+                    duration: job.duration(),
+                    env,
+                    script: job.script_text,
+                };
+                Ok(((package.name, package.version), diffed))
+            })
+            .collect()
+    }
+}
+
+/// Pair the jobs of a package in submit A and submit B
+///
+/// Jobs of the same version are paired, the remaining versions are paired in order (as updates)
+/// and the versions that are left are only in one of the submits.
+fn pair_versions<'a>(
+    name: &str,
+    a: &'a BTreeMap<(String, String), DiffedJob>,
+    b: &'a BTreeMap<(String, String), DiffedJob>,
+) -> Vec<(Option<&'a DiffedJob>, Option<&'a DiffedJob>)> {
+    let versions = |jobs: &'a BTreeMap<(String, String), DiffedJob>| {
+        jobs.iter()
+            .filter(|((n, _), _)| n == name)
+            .map(|((_, version), job)| (version.as_str(), job))
+            .collect::<BTreeMap<_, _>>()
+    };
+    let (a, b) = (versions(a), versions(b));
+
+    let common = a.iter()
+        .filter_map(|(version, job_a)| b.get(version).map(|job_b| (Some(*job_a), Some(*job_b))));
+    let only_a = a.iter().filter(|(version, _)| !b.contains_key(*version)).map(|(_, job)| *job);
+    let only_b = b.iter().filter(|(version, _)| !a.contains_key(*version)).map(|(_, job)| *job);
+    let others = only_a.zip_longest(only_b).map(|pair| pair.map_any(Some, Some).or_default());
+
+    common.chain(others).collect()
+}
+
+/// Describe how the job of a package changed from submit A to submit B
+fn job_change(a: Option<&DiffedJob>, b: Option<&DiffedJob>) -> &'static str {
+    match (a, b) {
+        (None, None) => "",
+        (None, Some(_)) => "added",
+        (Some(_), None) => "removed",
+        (Some(a), Some(b)) => match (a.success, b.success) {
+            (Some(false), Some(true)) => "fixed",
+            (Some(true), Some(false)) => "broken",
+            _ if a.version != b.version => "updated",
+            _ => "unchanged",
+        },
+    }
+}
+
+/// Format the difference of the durations of two jobs in seconds, e.g. "+12s"
+fn duration_delta(a: Option<&DiffedJob>, b: Option<&DiffedJob>) -> String {
+    a.and_then(|a| a.duration)
+        .zip(b.and_then(|b| b.duration))
+        .map(|(a, b)| format!("{:+}s", (b - a).num_seconds()))
+        .unwrap_or_default()
+}
+
+/// Implementation of the "db submit-diff" subcommand
+fn submit_diff(conn_cfg: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()> {
+    let csv = matches.get_flag("csv");
+    let mut conn = conn_cfg.establish_connection()?;
+    let mut load = |name: &str| -> Result<BTreeMap<(String, String), DiffedJob>> {
+        let submit_id = matches.get_one::<String>(name)
+            .map(|s| uuid::Uuid::from_str(s.as_ref()))
+            .transpose()
+            .context("Parsing submit UUID")?
+            .unwrap(); // safe by clap
+
+        let submit = models::Submit::with_id(&mut conn, &submit_id)
+            .with_context(|| anyhow!("Loading submit '{}' from DB", submit_id))?;
+        DiffedJob::load_for_submit(&mut conn, &submit)
+    };
+    let jobs_a = load("submit_a")?;
+    let jobs_b = load("submit_b")?;
+
+    let fmt_result = |job: Option<&DiffedJob>| match job.map(|j| j.success) {
+        None => "".normal(),
+        Some(Some(true)) => "Success".green(),
+        Some(Some(false)) => "Error".red(),
+        Some(None) => "Unknown".yellow(),
+    };
+    let fmt_duration = |job: Option<&DiffedJob>| {
+        job.and_then(|j| j.duration)
+            .map(|d| format!("{}s", d.num_seconds()))
+            .unwrap_or_default()
+    };
+    let fmt_changed = |changed: bool| if changed { "changed".yellow() } else { "".normal() };
+
+    let header = crate::commands::util::mk_header([
+        "Package", "Version A", "Version B", "Change", "Result A", "Result B",
+        "Duration A", "Duration B", "Delta", "Environment", "Script",
+    ].to_vec());
+    let data = jobs_a.keys()
+        .chain(jobs_b.keys())
+        .map(|(name, _)| name)
+        .unique()
+        .sorted()
+        .flat_map(|package| {
+            pair_versions(package, &jobs_a, &jobs_b)
+                .into_iter()
+                .map(move |(a, b)| (package, a, b))
+        })
+        .map(|(package, a, b)| {
+            let both = a.zip(b);
+            let change = match job_change(a, b) {
+                c @ ("added" | "fixed") => c.green(),
+                c @ ("removed" | "broken") => c.red(),
+                c @ "updated" => c.yellow(),
+                c => c.normal(),
+            };
+
+            vec![
+                package.cyan(),
+                a.map(|j| j.version.normal()).unwrap_or_else(|| "".normal()),
+                b.map(|j| j.version.normal()).unwrap_or_else(|| "".normal()),
+                change,
+                fmt_result(a),
+                fmt_result(b),
+                fmt_duration(a).normal(),
+                fmt_duration(b).normal(),
+                duration_delta(a, b).normal(),
+                fmt_changed(both.map(|(a, b)| a.env != b.env).unwrap_or(false)),
+                fmt_changed(both.map(|(a, b)| a.script != b.script).unwrap_or(false)),
+            ]
+        })
+        .collect::<Vec<Vec<colored::ColoredString>>>();
+
+    crate::commands::util::display_data(header, data, csv)
+}
+
 /// Implementation of the "db submits" subcommand
 fn submits(conn_cfg: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()> {
     let csv = matches.get_flag("csv");
     let limit = matches.get_one::<String>("limit").map(|s| s.parse::<i64>()).transpose()?;
-    let hdrs = crate::commands::util::mk_header(vec!["Time", "UUID", "For Package", "For Package Version"]);
+    let hdrs = crate::commands::util::mk_header(vec!["Time", "UUID", "User", "Host", "Version", "For Package", "For Package Version", "Labels"]);
     let mut conn = conn_cfg.establish_connection()?;
 
     let query = schema::submits::table
@@ -366,6 +841,28 @@ fn submits(conn_cfg: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()>
         query
     };
 
+    let query = if let Some(user) = matches.get_one::<String>("user") {
+        query.filter(schema::submits::submitted_by.eq(user))
+    } else {
+        query
+    };
+
+    // Every label must match, so filter once per label
+    let query = matches
+        .get_many::<(String, String)>("label")
+        .into_iter()
+        .flatten()
+        .fold(query, |query, (key, value)| {
+            query.filter({
+                schema::submits::id.eq_any({
+                    schema::submit_labels::table
+                        .filter(schema::submit_labels::key.eq(key))
+                        .filter(schema::submit_labels::value.eq(value))
+                        .select(schema::submit_labels::submit_id)
+                })
+            })
+        });
+
     let submits = if let Some(pkgname) = matches.get_one::<String>("with_pkg") {
         // In the case of a with_pkg command, we must execute two queries on the database, as the
         // diesel framework does not yet support aliases for queries (see
@@ -428,13 +925,23 @@ fn submits(conn_cfg: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()>
             .load::<(models::Submit, models::Package)>(&mut conn)?
     };
 
+    let submit_ids = submits.iter().map(|(submit, _)| submit.id).collect::<Vec<_>>();
+    let mut labels = HashMap::<i32, Vec<String>>::new();
+    for label in models::SubmitLabel::for_submits(&mut conn, &submit_ids)? {
+        labels.entry(label.submit_id).or_default().push(label.to_string());
+    }
+
     // Helper to map (Submit, Package) -> Vec<String>
     let submit_to_vec = |(submit, package): (models::Submit, models::Package)| {
         vec![
             submit.submit_time.to_string(),
             submit.uuid.to_string(),
+            submit.submitted_by.unwrap_or_default(),
+            submit.submitted_from.unwrap_or_default(),
+            submit.butido_version.unwrap_or_default(),
             package.name,
             package.version,
+            labels.get(&submit.id).map(|labels| labels.join(", ")).unwrap_or_default(),
         ]
     };
 
@@ -461,6 +968,7 @@ fn jobs(conn_cfg: DbConnectionConfig<'_>, config: &Configuration, matches: &ArgM
         "Package",
         "Version",
         "Distro",
+        "Failure",
     ]);
     let mut conn = conn_cfg.establish_connection()?;
     let older_than_filter = get_date_filter("older_than", matches)?;
@@ -517,21 +1025,50 @@ fn jobs(conn_cfg: DbConnectionConfig<'_>, config: &Configuration, matches: &ArgM
         sel = sel.filter(schema::packages::name.eq(pkg_name))
     }
 
+    if let Some(user) = matches.get_one::<String>("user") {
+        sel = sel.filter(schema::submits::submitted_by.eq(user))
+    }
+
     let mut image_short_name_map = HashMap::new();
     for image in config.docker().images() {
         image_short_name_map.insert(image.name.clone(), image.short_name.clone());
     }
 
-    let data = sel
+    let rows = sel
         .order_by(schema::jobs::id.desc()) // required for the --limit implementation
         .load::<(models::Job, models::Submit, models::Endpoint, models::Package, models::Image)>(&mut conn)?
         .into_iter()
-        .rev() // required for the --limit implementation
+        .rev(); // required for the --limit implementation
+
+    if let Some(format) = matches.get_one::<String>("format") {
+        let mut hb = handlebars::Handlebars::new();
+        hb.set_strict_mode(true);
+        hb.register_escape_fn(handlebars::no_escape);
+        hb.register_template_string("job", format)
+            .with_context(|| anyhow!("Parsing job format: {}", format))?;
+
+        let stdout = std::io::stdout();
+        let mut outlock = stdout.lock();
+        for (job, _, ep, package, _) in rows {
+            let data = JobFormatData {
+                id: job.id,
+                uuid: job.uuid.to_string(),
+                success: job_success_str(&job)?,
+                duration: job.duration().map(|d| d.num_seconds()),
+                package: package.name,
+                version: package.version,
+                endpoint: ep.name,
+            };
+
+            writeln!(outlock, "{}", hb.render("job", &data)?)?;
+        }
+
+        return Ok(());
+    }
+
+    let data = rows
         .map(|(job, submit, ep, package, image)| {
-            let success = is_job_successfull(&job)?
-                .map(|b| if b { "yes" } else { "no" })
-                .map(String::from)
-                .unwrap_or_else(|| String::from("?"));
+            let success = job_success_str(&job)?;
             let image_name = crate::util::docker::ImageName::from(image.name);
 
             Ok(vec![
@@ -543,6 +1080,7 @@ fn jobs(conn_cfg: DbConnectionConfig<'_>, config: &Configuration, matches: &ArgM
                 package.name,
                 package.version,
                 image_short_name_map.get(&image_name).unwrap_or(&image_name).to_string(),
+                job.failure_category.unwrap_or_default(),
             ])
         })
         .collect::<Result<Vec<_>>>()?;
@@ -556,6 +1094,28 @@ fn jobs(conn_cfg: DbConnectionConfig<'_>, config: &Configuration, matches: &ArgM
     Ok(())
 }
 
+/// The data a job is rendered with when "db jobs" is called with a format
+#[derive(serde::Serialize)]
+struct JobFormatData {
+    id: i32,
+    uuid: String,
+    package: String,
+    version: String,
+    endpoint: String,
+    success: String,
+    duration: Option<i64>,
+}
+
+/// "yes", "no" or "?" depending on whether the job succeeded
+fn job_success_str(job: &models::Job) -> Result<String> {
+    is_job_successfull(job).map(|success| {
+        success
+            .map(|b| if b { "yes" } else { "no" })
+            .map(String::from)
+            .unwrap_or_else(|| String::from("?"))
+    })
+}
+
 /// Implementation of the "db job" subcommand
 fn job(conn_cfg: DbConnectionConfig<'_>, config: &Configuration, matches: &ArgMatches) -> Result<()> {
     let script_highlight = !matches.get_flag("no_script_highlight");
@@ -563,6 +1123,9 @@ fn job(conn_cfg: DbConnectionConfig<'_>, config: &Configuration, matches: &ArgMa
     let configured_theme = config.script_highlight_theme();
     let show_log = matches.get_flag("show_log");
     let show_script = matches.get_flag("show_script");
+    let show_manifest = matches.get_flag("show_manifest");
+    let show_metadata = matches.get_flag("show_metadata");
+    let show_definition = matches.get_flag("show_definition");
     let csv = matches.get_flag("csv");
     let mut conn = conn_cfg.establish_connection()?;
     let job_uuid = matches
@@ -586,15 +1149,18 @@ fn job(conn_cfg: DbConnectionConfig<'_>, config: &Configuration, matches: &ArgMa
         )>(&mut conn)?;
 
     trace!("Parsing log");
-    let parsed_log = crate::log::ParsedLog::from_str(&data.0.log_text)?;
+    let log_text = data.0.full_log()?;
+    let parsed_log = crate::log::ParsedLog::from_str(&log_text)?;
     trace!("Parsed log = {:?}", parsed_log);
-    let success = parsed_log.is_successfull();
+    let success = data.0.result()?;
     trace!("log successfull = {:?}", success);
 
     if csv {
         let hdrs = crate::commands::util::mk_header(vec![
             "UUID",
             "Success",
+            "Exit code",
+            "Failure",
             "Package Name",
             "Package Version",
             "Ran on",
@@ -609,6 +1175,8 @@ fn job(conn_cfg: DbConnectionConfig<'_>, config: &Configuration, matches: &ArgMa
                 JobResult::Errored => "no",
                 JobResult::Unknown => "unknown",
             }),
+            data.0.termination().unwrap_or_default(),
+            data.0.failure_category.clone().unwrap_or_default(),
             data.3.name.to_string(),
             data.3.version.to_string(),
             data.2.name.to_string(),
@@ -632,18 +1200,26 @@ fn job(conn_cfg: DbConnectionConfig<'_>, config: &Configuration, matches: &ArgMa
             None
         };
 
+        let githash = models::GitHash::with_id(&mut conn, data.1.repo_hash_id)
+            .with_context(|| anyhow!("Loading GitHash '{}' from DB", data.1.repo_hash_id))?;
+
         let mut out = std::io::stdout();
         let s = indoc::formatdoc!(
             r#"
                 Job:        {job_uuid}
                 Submit:     {submit_uuid}
+                Commit:     {commit}
                 Succeeded:  {succeeded}
+                Exit code:  {exit_code}
+                Failure:    {failure_category}
                 Package:    {package_name} {package_version}
 
                 Ran on:     {endpoint_name}
                 Image:      {image_name}
-                Container:  {container_hash}
+                Container:  {container_hash} ({container_fate})
+                Resources:  {resource_usage}
 
+                Phases:     {phases}
                 Script:     {script_len} lines
                 Log:        {log_len} lines
 
@@ -654,18 +1230,24 @@ fn job(conn_cfg: DbConnectionConfig<'_>, config: &Configuration, matches: &ArgMa
                 JobResult::Unknown => data.0.uuid.to_string().cyan(),
             },
             submit_uuid = data.1.uuid.to_string().cyan(),
+            commit = githash.hash.cyan(),
             succeeded = match success {
                 JobResult::Success => String::from("yes").green(),
                 JobResult::Errored => String::from("no").red(),
                 JobResult::Unknown => String::from("unknown").cyan(),
             },
+            exit_code = data.0.termination().unwrap_or_else(|| String::from("unknown")).cyan(),
+            failure_category = data.0.failure_category.as_deref().unwrap_or("-").red(),
             package_name = data.3.name.cyan(),
             package_version = data.3.version.cyan(),
             endpoint_name = data.2.name.cyan(),
             image_name = data.4.name.cyan(),
             container_hash = data.0.container_hash.cyan(),
+            container_fate = data.0.container_fate.as_deref().unwrap_or("unknown"),
+            resource_usage = resource_usage_str(&data.0).cyan(),
+            phases = data.0.phases.as_ref().map(|p| p.join(", ")).unwrap_or_else(|| String::from("unknown")).cyan(),
             script_len = format!("{:<4}", data.0.script_text.lines().count()).cyan(),
-            log_len = format!("{:<4}", data.0.log_text.lines().count()).cyan(),
+            log_len = format!("{:<4}", log_text.lines().count()).cyan(),
         );
         writeln!(out, "{s}")?;
 
@@ -682,6 +1264,61 @@ fn job(conn_cfg: DbConnectionConfig<'_>, config: &Configuration, matches: &ArgMa
             writeln!(out, "{s}")?;
         }
 
+        if show_manifest {
+            let manifest = data.0.input_manifest
+                .as_deref()
+                .map(InputManifest::from_json)
+                .transpose()?
+                .ok_or_else(|| anyhow!("No input manifest recorded for job {}", data.0.uuid))?;
+
+            let s = indoc::formatdoc!(
+                r#"
+                ---
+
+                Manifest digest: {digest}
+                {manifest}
+
+            "#,
+                digest = manifest.digest()?.cyan(),
+                manifest = serde_json::to_string_pretty(&manifest)?
+            );
+            writeln!(out, "{s}")?;
+        }
+
+        if show_metadata {
+            let metadata = models::JobMetadata::for_jobs(&mut conn, &[data.0.id])?
+                .into_iter()
+                .map(|m| format!("{}:\n{}", m.name.cyan(), m.value))
+                .join("\n\n");
+
+            let s = indoc::formatdoc!(
+                r#"
+                ---
+
+                {metadata}
+
+            "#,
+                metadata = metadata
+            );
+            writeln!(out, "{s}")?;
+        }
+
+        if show_definition {
+            let definition = models::PackageDefinition::for_job(&mut conn, &data.0)?
+                .ok_or_else(|| anyhow!("No package definition recorded for job {}", data.0.uuid))?;
+
+            let s = indoc::formatdoc!(
+                r#"
+                ---
+
+                {definition}
+
+            "#,
+                definition = definition.definition
+            );
+            writeln!(out, "{s}")?;
+        }
+
         if show_script {
             let theme = configured_theme.as_ref().ok_or_else(|| {
                 anyhow!("Highlighting for script enabled, but no theme configured")
@@ -691,6 +1328,7 @@ fn job(conn_cfg: DbConnectionConfig<'_>, config: &Configuration, matches: &ArgMa
                 &script,
                 script_highlight,
                 theme,
+                config.script_highlight_theme_dir().as_deref(),
                 script_line_numbers,
             )?;
 
@@ -730,6 +1368,133 @@ fn job(conn_cfg: DbConnectionConfig<'_>, config: &Configuration, matches: &ArgMa
     }
 }
 
+/// Expand tabs and strip trailing whitespace, so that lines only differing in whitespace layout
+/// compare equal in "db job-diff"
+fn normalize_script_line(line: &str) -> String {
+    const TAB_WIDTH: usize = 4;
+
+    let mut normalized = String::with_capacity(line.len());
+    for c in line.chars() {
+        if c == '\t' {
+            let spaces = TAB_WIDTH - (normalized.chars().count() % TAB_WIDTH);
+            normalized.extend(std::iter::repeat(' ').take(spaces));
+        } else {
+            normalized.push(c);
+        }
+    }
+
+    normalized.truncate(normalized.trim_end().len());
+    normalized
+}
+
+/// Implementation of the "db job-diff" subcommand
+fn job_diff(conn_cfg: DbConnectionConfig<'_>, config: &Configuration, matches: &ArgMatches) -> Result<()> {
+    let script_highlight = !matches.get_flag("no_script_highlight");
+    let context = *matches.get_one::<usize>("context").unwrap(); // safe by clap
+    let mut conn = conn_cfg.establish_connection()?;
+    let mut load = |name: &str| -> Result<(models::Job, BTreeSet<(String, String)>)> {
+        let uuid = matches
+            .get_one::<String>(name)
+            .map(|s| uuid::Uuid::parse_str(s.as_ref()))
+            .transpose()?
+            .unwrap(); // safe by clap
+
+        let job = schema::jobs::table
+            .filter(schema::jobs::dsl::uuid.eq(uuid))
+            .first::<models::Job>(&mut conn)
+            .with_context(|| anyhow!("Loading job {}", uuid))?;
+
+        let env = job.env(&mut conn)?
+            .into_iter()
+            .map(|env| (env.name, env.value))
+            .collect();
+
+        Ok((job, env))
+    };
+
+    let (job_a, env_a) = load("job_a")?;
+    let (job_b, env_b) = load("job_b")?;
+
+    let lines_a = job_a.script_text.lines().map(normalize_script_line).collect::<Vec<_>>();
+    let lines_b = job_b.script_text.lines().map(normalize_script_line).collect::<Vec<_>>();
+
+    // The lines as they are printed, highlighted if requested
+    let printable = |lines: &[String]| -> Result<Vec<String>> {
+        if script_highlight {
+            let theme = config.script_highlight_theme().as_ref().ok_or_else(|| {
+                anyhow!("Highlighting for script enabled, but no theme configured")
+            })?;
+            let script = Script::from(lines.iter().join("\n") + "\n");
+            let lines = script.highlighted(theme, config.script_highlight_theme_dir().as_deref())?
+                .lines()?
+                .map(|line| line.replace('\n', ""))
+                .collect();
+            Ok(lines)
+        } else {
+            Ok(lines.to_vec())
+        }
+    };
+    let printable_a = printable(&lines_a)?;
+    let printable_b = printable(&lines_b)?;
+
+    let out = std::io::stdout();
+    let mut out = out.lock();
+    writeln!(out, "{}", format!("--- {}", job_a.uuid).red())?;
+    writeln!(out, "{}", format!("+++ {}", job_b.uuid).green())?;
+
+    let slice_a = lines_a.iter().map(String::as_str).collect::<Vec<_>>();
+    let slice_b = lines_b.iter().map(String::as_str).collect::<Vec<_>>();
+    let diff = similar::TextDiff::from_slices(&slice_a, &slice_b);
+    for group in diff.grouped_ops(context) {
+        let (first, last) = match (group.first(), group.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => continue,
+        };
+        let old_range = first.old_range().start..last.old_range().end;
+        let new_range = first.new_range().start..last.new_range().end;
+        let header = format!(
+            "@@ -{},{} +{},{} @@",
+            old_range.start + 1,
+            old_range.len(),
+            new_range.start + 1,
+            new_range.len()
+        );
+        writeln!(out, "{}", header.cyan())?;
+
+        for op in group.iter() {
+            for change in diff.iter_changes(op) {
+                match change.tag() {
+                    similar::ChangeTag::Equal => {
+                        let line = change.old_index().map(|i| printable_a[i].as_str()).unwrap_or_default();
+                        writeln!(out, " {line}")?;
+                    }
+                    similar::ChangeTag::Delete => {
+                        let line = change.old_index().map(|i| printable_a[i].as_str()).unwrap_or_default();
+                        writeln!(out, "{}{line}", "-".red())?;
+                    }
+                    similar::ChangeTag::Insert => {
+                        let line = change.new_index().map(|i| printable_b[i].as_str()).unwrap_or_default();
+                        writeln!(out, "{}{line}", "+".green())?;
+                    }
+                }
+            }
+        }
+    }
+
+    if env_a != env_b {
+        writeln!(out)?;
+        writeln!(out, "Environment:")?;
+        for (name, value) in env_a.difference(&env_b) {
+            writeln!(out, "{}", format!("-{name}={value}").red())?;
+        }
+        for (name, value) in env_b.difference(&env_a) {
+            writeln!(out, "{}", format!("+{name}={value}").green())?;
+        }
+    }
+
+    Ok(())
+}
+
 /// Implementation of the subcommand "db log-of"
 fn log_of(conn_cfg: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()> {
     let mut conn = conn_cfg.establish_connection()?;
@@ -743,9 +1508,9 @@ fn log_of(conn_cfg: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()>
 
     schema::jobs::table
         .filter(schema::jobs::dsl::uuid.eq(job_uuid))
-        .select(schema::jobs::dsl::log_text)
-        .first::<String>(&mut conn)
+        .first::<models::Job>(&mut conn)
         .map_err(Error::from)
+        .and_then(|job| job.full_log())
         .and_then(|s| crate::log::ParsedLog::from_str(&s))?
         .into_iter()
         .map(|line| line.display().and_then(|d| writeln!(lock, "{d}").map_err(Error::from)))
@@ -757,7 +1522,7 @@ fn log_of(conn_cfg: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()>
 fn releases(conn_cfg: DbConnectionConfig<'_>, config: &Configuration, matches: &ArgMatches) -> Result<()> {
     let csv = matches.get_flag("csv");
     let mut conn = conn_cfg.establish_connection()?;
-    let header = crate::commands::util::mk_header(["Package", "Version", "Date", "Path"].to_vec());
+    let header = crate::commands::util::mk_header(["Package", "Version", "Date", "Released by", "Path"].to_vec());
     let mut query = schema::jobs::table
         .inner_join(schema::packages::table)
         .inner_join(schema::artifacts::table)
@@ -786,6 +1551,10 @@ fn releases(conn_cfg: DbConnectionConfig<'_>, config: &Configuration, matches: &
         query = query.filter(schema::packages::dsl::name.eq(pkg));
     }
 
+    if let Some(user) = matches.get_one::<String>("user") {
+        query = query.filter(schema::jobs::dsl::submit_id.eq_any(submit_ids_of_user(&mut conn, user)?));
+    }
+
     let data = query
         .select({
             let art = schema::artifacts::all_columns;
@@ -796,14 +1565,15 @@ fn releases(conn_cfg: DbConnectionConfig<'_>, config: &Configuration, matches: &
         })
         .load::<(models::Artifact, models::Package, models::Release, models::ReleaseStore)>(&mut conn)?
         .into_iter()
-        .filter_map(|(art, pack, rel, rstore)| {
-            let p = config.releases_directory().join(rstore.store_name).join(art.path);
+        .filter_map(|(_, pack, rel, rstore)| {
+            let p = config.releases_directory().join(rstore.store_name).join(&rel.path);
 
             if p.is_file() {
                 Some(vec![
                     pack.name,
                     pack.version,
                     rel.release_date.to_string(),
+                    rel.released_by.unwrap_or_default(),
                     p.display().to_string(),
                 ])
             } else {
@@ -816,10 +1586,189 @@ fn releases(conn_cfg: DbConnectionConfig<'_>, config: &Configuration, matches: &
     crate::commands::util::display_data(header, data, csv)
 }
 
+/// Implementation of the "db stats" subcommand
+fn stats(conn_cfg: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()> {
+    let csv = matches.get_flag("csv");
+    let mut conn = conn_cfg.establish_connection()?;
+    let header = crate::commands::util::mk_header(
+        ["Package", "Version", "Jobs", "Avg CPU (s)", "Max CPU (s)", "Max memory", "Avg IO"].to_vec(),
+    );
+    let mut query = schema::jobs::table
+        .inner_join(schema::packages::table)
+        .filter(schema::jobs::cpu_seconds.is_not_null())
+        .into_boxed();
+
+    if let Some(date) = crate::commands::util::get_date_filter("older_than", matches)? {
+        query = query.filter(schema::jobs::finished_at.lt(date));
+    }
+
+    if let Some(date) = crate::commands::util::get_date_filter("newer_than", matches)? {
+        query = query.filter(schema::jobs::finished_at.gt(date));
+    }
+
+    if let Some(pkg) = matches.get_one::<String>("package") {
+        query = query.filter(schema::packages::dsl::name.eq(pkg));
+    }
+
+    let usages = query
+        .select((
+            schema::packages::name,
+            schema::packages::version,
+            schema::jobs::cpu_seconds,
+            schema::jobs::peak_memory,
+            schema::jobs::io_bytes,
+        ))
+        .load::<(String, String, Option<f64>, Option<i64>, Option<i64>)>(&mut conn)?
+        .into_iter()
+        .fold(BTreeMap::new(), |mut map, (name, version, cpu, memory, io)| {
+            map.entry((name, version)).or_insert_with(Vec::new).push((
+                cpu.unwrap_or_default(),
+                memory.unwrap_or_default(),
+                io.unwrap_or_default(),
+            ));
+            map
+        });
+
+    let data = usages
+        .into_iter()
+        .map(|((name, version), usages)| {
+            let n = usages.len();
+            let cpu_total = usages.iter().map(|u| u.0).sum::<f64>();
+            let cpu_max = usages.iter().map(|u| u.0).fold(0.0, f64::max);
+            let memory_max = usages.iter().map(|u| u.1).max().unwrap_or_default();
+            let io_total = usages.iter().map(|u| u.2).sum::<i64>();
+            vec![
+                name,
+                version,
+                n.to_string(),
+                format!("{:.1}", cpu_total / n as f64),
+                format!("{cpu_max:.1}"),
+                bytesize::ByteSize::b(memory_max as u64).to_string(),
+                bytesize::ByteSize::b((io_total / n as i64) as u64).to_string(),
+            ]
+        })
+        .collect::<Vec<_>>();
+
+    if data.is_empty() {
+        info!("No resource usage recorded");
+        return Ok(());
+    }
+
+    crate::commands::util::display_data(header, data, csv)
+}
+
+/// The recorded resource usage of `job`, for displaying
+fn resource_usage_str(job: &models::Job) -> String {
+    match (job.cpu_seconds, job.peak_memory, job.io_bytes) {
+        (Some(cpu), Some(memory), Some(io)) => format!(
+            "{cpu:.1} CPU seconds, {} peak memory, {} IO",
+            bytesize::ByteSize::b(memory as u64),
+            bytesize::ByteSize::b(io as u64),
+        ),
+        _ => String::from("unknown"),
+    }
+}
+
+/// Get the IDs of all submits by `user`
+fn submit_ids_of_user(conn: &mut PgConnection, user: &str) -> Result<Vec<i32>> {
+    schema::submits::table
+        .filter(schema::submits::submitted_by.eq(user))
+        .select(schema::submits::id)
+        .load::<i32>(conn)
+        .map_err(Error::from)
+}
+
 /// Check if a job is successful
 ///
 /// Returns Ok(None) if cannot be decided
 fn is_job_successfull(job: &models::Job) -> Result<Option<bool>> {
-    crate::log::ParsedLog::from_str(&job.log_text).map(|pl| pl.is_successfull().to_bool())
+    job.result().map(|r| r.to_bool())
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diffed(version: &str, success: Option<bool>, seconds: i64) -> DiffedJob {
+        DiffedJob {
+            version: version.to_string(),
+            success,
+            duration: Some(chrono::Duration::seconds(seconds)),
+            env: BTreeSet::new(),
+            script: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_job_dag_tree() {
+        let root = uuid::Uuid::new_v4();
+        let dep_a = uuid::Uuid::new_v4();
+        let dep_b = uuid::Uuid::new_v4();
+        let nodes = HashMap::from([
+            (root, (String::from("root"), vec![dep_b, dep_a])),
+            (dep_a, (String::from("a"), vec![dep_b])),
+            (dep_b, (String::from("b"), vec![])),
+        ]);
+
+        let tree = job_dag_tree(String::from("Submit"), &nodes);
+        assert_eq!(tree.text, "Submit");
+        assert_eq!(tree.children.len(), 1);
+
+        let root = &tree.children[0];
+        assert_eq!(root.text, "root");
+        assert_eq!(root.children.iter().map(|c| c.text.as_str()).collect::<Vec<_>>(), vec!["a", "b"]);
+        assert_eq!(root.children[0].children[0].text, "b");
+    }
+
+    #[test]
+    fn test_job_change() {
+        let ok = diffed("1", Some(true), 10);
+        let failed = diffed("1", Some(false), 10);
+        let updated = diffed("2", Some(true), 10);
+
+        assert_eq!(job_change(None, Some(&ok)), "added");
+        assert_eq!(job_change(Some(&ok), None), "removed");
+        assert_eq!(job_change(Some(&failed), Some(&ok)), "fixed");
+        assert_eq!(job_change(Some(&ok), Some(&failed)), "broken");
+        assert_eq!(job_change(Some(&ok), Some(&updated)), "updated");
+        assert_eq!(job_change(Some(&ok), Some(&ok)), "unchanged");
+    }
+
+    #[test]
+    fn test_duration_delta() {
+        let fast = diffed("1", Some(true), 10);
+        let slow = diffed("1", Some(true), 70);
+
+        assert_eq!(duration_delta(Some(&fast), Some(&slow)), "+60s");
+        assert_eq!(duration_delta(Some(&slow), Some(&fast)), "-60s");
+        assert_eq!(duration_delta(None, Some(&fast)), "");
+    }
+
+    #[test]
+    fn test_pair_versions() {
+        let jobs = |versions: &[&str]| {
+            versions.iter()
+                .map(|v| ((String::from("a"), v.to_string()), diffed(v, Some(true), 10)))
+                .chain(std::iter::once(((String::from("b"), String::from("1")), diffed("1", None, 10))))
+                .collect::<BTreeMap<_, _>>()
+        };
+        fn versions<'a>(pairs: Vec<(Option<&'a DiffedJob>, Option<&'a DiffedJob>)>) -> Vec<(Option<&'a str>, Option<&'a str>)> {
+            pairs.into_iter()
+                .map(|(a, b)| (a.map(|j| j.version.as_str()), b.map(|j| j.version.as_str())))
+                .collect()
+        }
+
+        let (a, b) = (jobs(&["1", "2", "3"]), jobs(&["2", "4"]));
+        assert_eq!(versions(pair_versions("a", &a, &b)), vec![(Some("2"), Some("2")), (Some("1"), Some("4")), (Some("3"), None)]);
+        assert_eq!(versions(pair_versions("a", &b, &a)), vec![(Some("2"), Some("2")), (Some("4"), Some("1")), (None, Some("3"))]);
+        assert_eq!(versions(pair_versions("b", &a, &b)), vec![(Some("1"), Some("1"))]);
+    }
+
+    #[test]
+    fn test_normalize_script_line() {
+        assert_eq!(normalize_script_line("echo foo  "), "echo foo");
+        assert_eq!(normalize_script_line("\techo foo"), "    echo foo");
+        assert_eq!(normalize_script_line("ab\tc"), "ab  c");
+        assert_eq!(normalize_script_line("\t"), "");
+    }
+}
@@ -27,10 +27,18 @@ use tokio_stream::StreamExt;
 
 use crate::config::Configuration;
 use crate::config::EndpointName;
+use crate::config::EndpointType;
+use crate::db::models::EndpointDefinition;
+use crate::db::DbConnectionConfig;
 use crate::util::progress::ProgressBars;
 use crate::endpoint::Endpoint;
 
-pub async fn endpoint(matches: &ArgMatches, config: &Configuration, progress_generator: ProgressBars) -> Result<()> {
+pub async fn endpoint(
+    db_connection_config: DbConnectionConfig<'_>,
+    matches: &ArgMatches,
+    config: &Configuration,
+    progress_generator: ProgressBars,
+) -> Result<()> {
     let endpoint_names = matches
         .get_one::<String>("endpoint_name")
         .map(|s| s.to_owned())
@@ -51,6 +59,10 @@ pub async fn endpoint(matches: &ArgMatches, config: &Configuration, progress_gen
         Some(("container", matches)) => crate::commands::endpoint_container::container(endpoint_names, matches, config).await,
         Some(("containers", matches)) => containers(endpoint_names, matches, config).await,
         Some(("images", matches)) => images(endpoint_names, matches, config).await,
+        Some(("add", matches)) => add(db_connection_config, matches),
+        Some(("discover", matches)) => discover(db_connection_config, matches, config).await,
+        Some(("remove", matches)) => remove(db_connection_config, matches),
+        Some(("list", matches)) => list(db_connection_config, matches),
         Some((other, _)) => Err(anyhow!("Unknown subcommand: {}", other)),
         None => Err(anyhow!("No subcommand")),
     }
@@ -64,13 +76,7 @@ async fn ping(endpoint_names: Vec<EndpointName>,
     let n_pings = matches.get_one::<String>("ping_n").map(|s| s.parse::<u64>()).transpose()?.unwrap(); // safe by clap
     let sleep = matches.get_one::<String>("ping_sleep").map(|s| s.parse::<u64>()).transpose()?.unwrap(); // safe by clap
     let endpoints = connect_to_endpoints(config, &endpoint_names).await?;
-    let multibar = Arc::new({
-        let mp = indicatif::MultiProgress::new();
-        if progress_generator.hide() {
-            mp.set_draw_target(indicatif::ProgressDrawTarget::hidden());
-        }
-        mp
-    });
+    let multibar = Arc::new(progress_generator.multi());
 
     endpoints
         .iter()
@@ -505,6 +511,107 @@ async fn images_present(endpoint_names: Vec<EndpointName>,
         })
 }
 
+fn add(db_connection_config: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()> {
+    let ep_name = matches.get_one::<String>("name").map(|s| EndpointName::from(s.to_owned())).unwrap(); // safe by clap
+    let uri = matches.get_one::<String>("uri").unwrap(); // safe by clap
+    let ep_type = matches.get_one::<String>("endpoint_type").map(|s| EndpointType::from_str(s)).transpose()?.unwrap(); // safe by clap
+    let maxjobs = matches.get_one::<String>("maxjobs").map(|s| usize::from_str(s)).transpose()?.unwrap(); // safe by clap
+    let network_mode = matches.get_one::<String>("network_mode").map(String::as_str);
+
+    let mut conn = db_connection_config.establish_connection()?;
+    EndpointDefinition::create(&mut conn, &ep_name, uri, &ep_type, maxjobs, network_mode)?;
+    info!("Endpoint {} defined in database", ep_name);
+    Ok(())
+}
+
+async fn discover(db_connection_config: DbConnectionConfig<'_>, matches: &ArgMatches, config: &Configuration) -> Result<()> {
+    use crate::endpoint::discovery;
+
+    let default_maxjobs = matches.get_one::<String>("maxjobs").map(|s| usize::from_str(s)).transpose()?.unwrap(); // safe by clap
+    let show_all = matches.get_flag("all");
+    let add = matches.get_flag("add");
+
+    let mut discovered = Vec::new();
+    if !matches.get_flag("no_contexts") {
+        match discovery::docker_config_dir() {
+            Some(dir) => discovered.extend(discovery::docker_contexts(&dir)?),
+            None => info!("Not discovering Docker contexts: neither DOCKER_CONFIG nor HOME is set"),
+        }
+    }
+    if let Some(command) = config.docker().endpoint_inventory_command() {
+        discovered.extend(discovery::inventory(command).await?);
+    }
+    debug!("Discovered endpoints: {:?}", discovered);
+
+    let configured = config.docker().endpoints();
+    let is_configured = |ep: &discovery::DiscoveredEndpoint| {
+        configured.contains_key(&ep.name) || configured.values().any(|configured| *configured.uri() == ep.uri)
+    };
+    let (new, known): (Vec<_>, Vec<_>) = discovered.into_iter().partition(|ep| !is_configured(ep));
+    for ep in known.iter() {
+        info!("Endpoint {} ({}) is already configured", ep.name, ep.uri);
+    }
+
+    if add {
+        let mut conn = db_connection_config.establish_connection()?;
+        let defined = EndpointDefinition::all(&mut conn)?;
+        for ep in new {
+            if defined.iter().any(|def| def.name == ep.name.as_ref() || def.uri == ep.uri) {
+                info!("Endpoint {} ({}) is already defined in the database", ep.name, ep.uri);
+                continue;
+            }
+
+            EndpointDefinition::create(&mut conn, &ep.name, &ep.uri, &ep.endpoint_type, ep.maxjobs.unwrap_or(default_maxjobs), None)?;
+            info!("Endpoint {} defined in database", ep.name);
+        }
+        return Ok(());
+    }
+
+    let out = std::io::stdout();
+    let mut lock = out.lock();
+    let endpoints = if show_all { new.iter().chain(known.iter()).collect::<Vec<_>>() } else { new.iter().collect() };
+    for (i, ep) in endpoints.into_iter().enumerate() {
+        if i > 0 {
+            writeln!(lock)?;
+        }
+        write!(lock, "{}", ep.to_config_stanza(default_maxjobs))?;
+    }
+    Ok(())
+}
+
+fn remove(db_connection_config: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()> {
+    let ep_name = matches.get_one::<String>("name").map(|s| EndpointName::from(s.to_owned())).unwrap(); // safe by clap
+
+    let mut conn = db_connection_config.establish_connection()?;
+    if EndpointDefinition::remove(&mut conn, &ep_name)? {
+        info!("Endpoint definition {} removed from database", ep_name);
+        Ok(())
+    } else {
+        Err(anyhow!("Endpoint {} is not defined in the database", ep_name))
+    }
+}
+
+fn list(db_connection_config: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()> {
+    let csv = matches.get_flag("csv");
+    let mut conn = db_connection_config.establish_connection()?;
+
+    let hdr = crate::commands::util::mk_header(["Name", "URI", "Type", "Max jobs", "Network mode"].to_vec());
+    let data = EndpointDefinition::all(&mut conn)?
+        .into_iter()
+        .map(|def| {
+            vec![
+                def.name,
+                def.uri,
+                def.endpoint_type,
+                def.maxjobs.to_string(),
+                def.network_mode.unwrap_or_default(),
+            ]
+        })
+        .collect::<Vec<_>>();
+
+    crate::commands::util::display_data(hdr, data, csv)
+}
+
 /// Helper function to connect to all endpoints from the configuration, that appear (by name) in
 /// the `endpoint_names` list
 pub(super) async fn connect_to_endpoints(config: &Configuration, endpoint_names: &[EndpointName]) -> Result<Vec<Arc<Endpoint>>> {
@@ -520,6 +627,8 @@ pub(super) async fn connect_to_endpoints(config: &Configuration, endpoint_names:
                 .required_images(config.docker().images().iter().map(|img| img.name.clone()).collect::<Vec<_>>())
                 .required_docker_versions(config.docker().docker_versions().clone())
                 .required_docker_api_versions(config.docker().docker_api_versions().clone())
+                .pull_missing_images(config.docker().pull_missing_images())
+                .registries(config.docker().registries().clone())
                 .build()
         })
         .collect::<Vec<_>>();
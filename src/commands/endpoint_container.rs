@@ -103,7 +103,8 @@ pub async fn container(endpoint_names: Vec<EndpointName>,
                 Ok(())
             }
         },
-        Some(("inspect", _)) => inspect(container).await,
+        Some(("logs", matches)) => logs(matches, container).await,
+        Some(("inspect", matches)) => inspect(matches, container).await,
         Some((other, _)) => Err(anyhow!("Unknown subcommand: {}", other)),
         None => Err(anyhow!("No subcommand")),
     }
@@ -168,6 +169,40 @@ async fn exec(matches: &ArgMatches, container: Container<'_>) -> Result<()> {
         .await
 }
 
+async fn logs(matches: &ArgMatches, container: Container<'_>) -> Result<()> {
+    use std::io::Write;
+    use futures::TryStreamExt;
+
+    let tail = matches.get_one::<u64>("tail").map(u64::to_string);
+    let logsopts = {
+        let mut builder = shiplift::builder::LogsOptions::builder();
+        builder
+            .stdout(true)
+            .stderr(true)
+            .follow(matches.get_flag("follow"))
+            .timestamps(matches.get_flag("timestamps"));
+        if let Some(tail) = tail.as_ref() {
+            builder.tail(tail);
+        }
+        builder.build()
+    };
+
+    container.logs(&logsopts)
+        .map_err(Error::from)
+        .try_for_each(|chunk| async {
+            match chunk {
+                shiplift::tty::TtyChunk::StdIn(_) => Err(anyhow!("Cannot handle STDIN TTY chunk")),
+                shiplift::tty::TtyChunk::StdOut(v) => {
+                    std::io::stdout().write_all(&v).map_err(Error::from)
+                },
+                shiplift::tty::TtyChunk::StdErr(v) => {
+                    std::io::stderr().write_all(&v).map_err(Error::from)
+                },
+            }
+        })
+        .await
+}
+
 // Print inspect details about the container
 //
 //
@@ -177,12 +212,16 @@ async fn exec(matches: &ArgMatches, container: Container<'_>) -> Result<()> {
 //
 // This is the most ugly function of the whole codebase. As ugly as it is: It is simply printing
 // things, nothing here is too complex code-wise (except some nested formatting stuff...)
-async fn inspect(container: Container<'_>) -> Result<()> {
+async fn inspect(matches: &ArgMatches, container: Container<'_>) -> Result<()> {
     use std::io::Write;
     use itertools::Itertools;
 
     let d = container.inspect().await?;
 
+    if matches.get_flag("json") {
+        return writeln!(std::io::stdout(), "{}", serde_json::to_string_pretty(&d)?).map_err(Error::from)
+    }
+
     fn option_vec<'a>(ov: Option<&Vec<String>>) -> Cow<'a, str> {
         ov.map(|v| format!("Some({})", v.iter().join(", ")))
             .map(Cow::from)
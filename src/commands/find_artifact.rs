@@ -28,6 +28,7 @@ use tracing::{debug, trace};
 use crate::config::Configuration;
 use crate::filestore::ReleaseStore;
 use crate::filestore::StagingStore;
+use crate::filestore::StoreBackend;
 use crate::filestore::path::StoreRoot;
 use crate::package::PackageVersionConstraint;
 use crate::repository::Repository;
@@ -60,7 +61,9 @@ pub async fn find_artifact(matches: &ArgMatches, config: &Configuration, progres
     debug!("Finding artifacts for '{:?}' '{:?}'", package_name_regex, package_version_constraint);
 
     let release_stores = config
-        .release_stores()
+        .release_store_search_order()
+        .as_ref()
+        .unwrap_or_else(|| config.release_stores())
         .iter()
         .map(|storename| {
             let bar_release_loading = progressbars.bar()?;
@@ -75,10 +78,18 @@ pub async fn find_artifact(matches: &ArgMatches, config: &Configuration, progres
                 bar_release_loading.finish_with_message(format!("Failed to load releases in {p_str}"));
             }
 
-            r.map(Arc::new)
+            r.map(|store| (storename, store))
         })
         .collect::<Result<Vec<_>>>()?;
 
+    let release_stores = futures::future::try_join_all({
+        release_stores.into_iter().map(|(storename, store)| async move {
+            let backend = StoreBackend::from_config(config.release_store_backends().get(storename))?;
+            store.with_backend(backend).await.map(Arc::new)
+        })
+    })
+    .await?;
+
     let staging_store = if let Some(p) = matches.get_one::<String>("staging_dir").map(PathBuf::from) {
         let bar_staging_loading = progressbars.bar()?;
 
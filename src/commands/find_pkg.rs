@@ -32,9 +32,14 @@ pub async fn find_pkg(
 ) -> Result<()> {
     use std::io::Write;
 
-    let package_name_regex = crate::commands::util::mk_package_name_regex({
-        matches.get_one::<String>("package_name_regex").unwrap() // safe by clap
-    })?;
+    let package_name_regex = {
+        let pattern = matches.get_one::<String>("package_name_regex").unwrap(); // safe by clap
+        if matches.get_flag("glob") {
+            crate::commands::util::mk_package_name_glob_regex(pattern)?
+        } else {
+            crate::commands::util::mk_package_name_regex(pattern)?
+        }
+    };
 
     let package_version_constraint = matches
         .get_one::<String>("package_version_constraint")
@@ -88,7 +93,9 @@ pub async fn find_pkg(
             script_highlighting: !matches.get_flag("no_script_highlight"),
         };
 
-        let format = config.package_print_format();
+        let format = matches
+            .get_one::<String>("format")
+            .unwrap_or_else(|| config.package_print_format());
         let hb = crate::ui::handlebars_for_package_printing(format)?;
 
         tokio_stream::iter({
@@ -0,0 +1,86 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Implementation of the 'image' subcommand
+
+use anyhow::anyhow;
+use anyhow::Result;
+use clap::ArgMatches;
+use tracing::info;
+
+use crate::db::models::ImageDefinition;
+use crate::db::DbConnectionConfig;
+use crate::util::docker::ImageName;
+
+/// Implementation of the "image" subcommand
+pub fn image(db_connection_config: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()> {
+    match matches.subcommand() {
+        Some(("allow", matches)) => allow(db_connection_config, matches),
+        Some(("deny", matches)) => deny(db_connection_config, matches),
+        Some(("remove", matches)) => remove(db_connection_config, matches),
+        Some(("list", matches)) => list(db_connection_config, matches),
+        Some((other, _)) => Err(anyhow!("Unknown subcommand: {}", other)),
+        None => Err(anyhow!("No subcommand")),
+    }
+}
+
+fn image_name(matches: &ArgMatches) -> ImageName {
+    matches.get_one::<String>("image_name").map(|s| ImageName::from(s.to_owned())).unwrap() // safe by clap
+}
+
+fn allow(db_connection_config: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()> {
+    let image_name = image_name(matches);
+    let short_name = matches.get_one::<String>("short_name").map(String::as_str);
+
+    let mut conn = db_connection_config.establish_connection()?;
+    ImageDefinition::set(&mut conn, &image_name, short_name, true)?;
+    info!("Image {} allowed", image_name);
+    Ok(())
+}
+
+fn deny(db_connection_config: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()> {
+    let image_name = image_name(matches);
+
+    let mut conn = db_connection_config.establish_connection()?;
+    ImageDefinition::set(&mut conn, &image_name, None, false)?;
+    info!("Image {} denied", image_name);
+    Ok(())
+}
+
+fn remove(db_connection_config: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()> {
+    let image_name = image_name(matches);
+
+    let mut conn = db_connection_config.establish_connection()?;
+    if ImageDefinition::remove(&mut conn, &image_name)? {
+        info!("Image definition {} removed from database", image_name);
+        Ok(())
+    } else {
+        Err(anyhow!("Image {} is not defined in the database", image_name))
+    }
+}
+
+fn list(db_connection_config: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()> {
+    let csv = matches.get_flag("csv");
+    let mut conn = db_connection_config.establish_connection()?;
+
+    let hdr = crate::commands::util::mk_header(["Name", "Short name", "Allowed"].to_vec());
+    let data = ImageDefinition::all(&mut conn)?
+        .into_iter()
+        .map(|def| {
+            vec![
+                def.name,
+                def.short_name.unwrap_or_default(),
+                String::from(if def.allowed { "yes" } else { "no" }),
+            ]
+        })
+        .collect::<Vec<_>>();
+
+    crate::commands::util::display_data(hdr, data, csv)
+}
@@ -11,19 +11,39 @@
 //! Implementation of the 'lint' subcommand
 
 use std::convert::TryFrom;
+use std::io::Write;
 use std::path::Path;
 
 use anyhow::anyhow;
 use anyhow::Result;
 use clap::ArgMatches;
+use itertools::Itertools;
+use lazy_static::lazy_static;
+use regex::Regex;
+use tracing::warn;
 
 use crate::config::*;
+use crate::package::Package;
 use crate::package::PackageName;
+use crate::package::PackageVersion;
 use crate::package::PackageVersionConstraint;
+use crate::package::ParseDependency;
+use crate::package::Phase;
+use crate::package::PhaseName;
 use crate::repository::Repository;
 use crate::util::progress::ProgressBars;
 
+lazy_static! {
+    /// Matches the phase helper in scripts, e.g. `{{phase "build"}}`
+    static ref PHASE_HELPER_RE: Regex = Regex::new(r#"\{\{~?\s*phase\s+"(?P<phase>[^"]*)""#).unwrap();
+
+    static ref ENV_VAR_NAME_RE: Regex = Regex::new("^[A-Za-z_][A-Za-z0-9_]*$").unwrap();
+}
+
 /// Implementation of the "lint" subcommand
+///
+/// The structure of the packages is always checked, the package scripts are linted if a linter is
+/// configured.
 pub async fn lint(
     repo_path: &Path,
     matches: &ArgMatches,
@@ -31,8 +51,6 @@ pub async fn lint(
     config: &Configuration,
     repo: Repository,
 ) -> Result<()> {
-    let linter = crate::ui::find_linter_command(repo_path, config)?
-        .ok_or_else(|| anyhow!("No linter command found"))?;
     let pname = matches
         .get_one::<String>("package_name")
         .map(|s| s.to_owned())
@@ -43,18 +61,211 @@ pub async fn lint(
         .map(PackageVersionConstraint::try_from)
         .transpose()?;
 
+    let selected = |name: &PackageName, version: &PackageVersion| {
+        pname.as_ref().map(|n| name == n).unwrap_or(true)
+            && pvers.as_ref().map(|v| v.matches(version)).unwrap_or(true)
+    };
+
+    if matches.get_flag("scripts") {
+        let packages = repo.packages().filter(|p| selected(p.name(), p.version())).collect::<Vec<_>>();
+        return lint_scripts(repo_path, progressbars, config, &packages).await
+    }
+
+    let mut problems = repo
+        .duplicates()
+        .iter()
+        .filter(|(name, version, _)| selected(name, version))
+        .map(|(name, version, paths)| Problem {
+            name: name.clone(),
+            version: version.clone(),
+            description: format!(
+                "Defined multiple times: {}",
+                paths.iter().map(|p| p.display().to_string()).collect::<Vec<_>>().join(", ")
+            ),
+        })
+        .collect::<Vec<_>>();
+
+    for package in repo.packages().filter(|p| selected(p.name(), p.version())) {
+        problems.extend(check_package(&repo, package, config.available_phases()));
+    }
+
+    {
+        let mut out = std::io::stdout();
+        for problem in problems.iter() {
+            writeln!(out, "{} {}: {}", problem.name, problem.version, problem.description)?;
+        }
+    }
+
+    if let Some(linter) = crate::ui::find_linter_command(repo_path, config)? {
+        let bar = progressbars.bar()?;
+        bar.set_message("Linting package scripts...");
+
+        let iter = repo.packages().filter(|p| selected(p.name(), p.version()));
+        crate::commands::util::lint_packages(iter, &linter, config, bar).await?;
+    } else {
+        warn!("No linter set in configuration, no script linting will be performed!");
+    }
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow!("Found {} problems in the repository", problems.len()))
+    }
+}
+
+/// Check only the package scripts, with the configured linter (if any) and with shellcheck
+async fn lint_scripts(
+    repo_path: &Path,
+    progressbars: ProgressBars,
+    config: &Configuration,
+    packages: &[&Package],
+) -> Result<()> {
+    if let Some(linter) = crate::ui::find_linter_command(repo_path, config)? {
+        let bar = progressbars.bar()?;
+        bar.set_message("Linting package scripts...");
+        crate::commands::util::lint_packages(packages.iter().copied(), &linter, config, bar).await?;
+    }
+
     let bar = progressbars.bar()?;
-    bar.set_message("Linting package scripts...");
-
-    let iter = repo
-        .packages()
-        .filter(|p| pname.as_ref().map(|n| p.name() == n).unwrap_or(true))
-        .filter(|p| {
-            pvers
-                .as_ref()
-                .map(|v| v.matches(p.version()))
-                .unwrap_or(true)
+    bar.set_message("Checking package scripts with shellcheck...");
+    crate::commands::util::shellcheck_packages(packages.iter().copied(), config.shellcheck(), config, bar).await
+}
+
+/// A structural problem of a package definition
+#[derive(Debug)]
+struct Problem {
+    name: PackageName,
+    version: PackageVersion,
+    description: String,
+}
+
+fn check_package(repo: &Repository, package: &Package, available_phases: &[PhaseName]) -> Vec<Problem> {
+    let mut descriptions = vec![];
+
+    if package.version().as_ref().trim().is_empty() {
+        descriptions.push(String::from("Version is empty"));
+    }
+
+    let dependencies = package
+        .dependencies()
+        .build()
+        .iter()
+        .map(|d| (d.as_ref().to_string(), d.parse_as_name_and_version()))
+        .chain({
+            package
+                .dependencies()
+                .runtime()
+                .iter()
+                .map(|d| (d.as_ref().to_string(), d.parse_as_name_and_version()))
         });
 
-    crate::commands::util::lint_packages(iter, &linter, config, bar).await
+    for (dependency, parsed) in dependencies {
+        match parsed {
+            Err(e) => descriptions.push(format!("Cannot parse dependency '{}': {}", dependency, e)),
+            Ok((name, constraint)) => {
+                if repo.find_with_version(&name, &constraint).is_empty() {
+                    descriptions.push(format!("Dependency '{}' does not match any package in the repository", dependency));
+                }
+            }
+        }
+    }
+
+    // Only phases with inline scripts can be checked for references to other phases
+    for (phase_name, phase) in package.phases().iter().sorted_by_key(|(name, _)| name.as_str()) {
+        if !available_phases.contains(phase_name) {
+            descriptions.push(format!("Phase '{}' is not in the available phases and will never run", phase_name.as_str()));
+        }
+
+        if let Phase::Text(script) = phase {
+            PHASE_HELPER_RE
+                .captures_iter(script)
+                .filter_map(|cap| cap.name("phase"))
+                .map(|m| m.as_str())
+                .filter(|referenced| !available_phases.iter().any(|p| p.as_str() == *referenced))
+                .for_each(|referenced| {
+                    descriptions.push(format!("Script of phase '{}' references undefined phase '{}'", phase_name.as_str(), referenced));
+                });
+        }
+    }
+
+    if let Some(env) = package.environment().as_ref() {
+        env.keys()
+            .sorted()
+            .filter(|name| !ENV_VAR_NAME_RE.is_match(name.as_ref()))
+            .for_each(|name| descriptions.push(format!("Invalid environment variable name: '{}'", name)));
+    }
+
+    if let Some(allowed_env) = package.allowed_env().as_ref() {
+        allowed_env
+            .iter()
+            .filter(|name| !ENV_VAR_NAME_RE.is_match(name.as_ref()))
+            .for_each(|name| descriptions.push(format!("Invalid environment variable name in allowed_env: '{}'", name)));
+    }
+
+    descriptions
+        .into_iter()
+        .map(|description| Problem {
+            name: package.name().clone(),
+            version: package.version().clone(),
+            description,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_check_package() {
+        let package: Package = toml::from_str(r#"
+            name = "a"
+            version = "1"
+            version_is_semver = false
+            patches = []
+
+            [sources]
+
+            [dependencies]
+            build = ["b =1", "c =2", "not a dependency"]
+            runtime = []
+
+            [environment]
+            VALID_NAME = "1"
+            "INVALID-NAME" = "2"
+
+            [phases]
+            build.script = '{{phase "unknown"}}'
+            other.script = 'true'
+        "#).unwrap();
+
+        let b: Package = toml::from_str(r#"
+            name = "b"
+            version = "1"
+            version_is_semver = false
+            patches = []
+            sources = {}
+            dependencies = { build = [], runtime = [] }
+            phases = {}
+        "#).unwrap();
+
+        let repo = Repository::from(BTreeMap::from([
+            ((package.name().clone(), package.version().clone()), package.clone()),
+            ((b.name().clone(), b.version().clone()), b),
+        ]));
+        let phases = vec![PhaseName::from(String::from("build"))];
+
+        let problems = check_package(&repo, &package, &phases)
+            .into_iter()
+            .map(|problem| problem.description)
+            .collect::<Vec<_>>();
+
+        assert_eq!(problems.len(), 5, "{:?}", problems);
+        assert!(problems[0].starts_with("Dependency 'c =2'"));
+        assert!(problems[1].starts_with("Cannot parse dependency 'not a dependency'"));
+        assert_eq!(problems[2], "Script of phase 'build' references undefined phase 'unknown'");
+        assert_eq!(problems[3], "Phase 'other' is not in the available phases and will never run");
+        assert_eq!(problems[4], "Invalid environment variable name: 'INVALID-NAME'");
+    }
 }
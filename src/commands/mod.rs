@@ -11,6 +11,12 @@
 mod build;
 pub use build::build;
 
+mod config;
+pub use config::config;
+
+mod daemon;
+pub use daemon::daemon;
+
 mod db;
 pub use db::db;
 
@@ -21,6 +27,9 @@ pub(super) mod endpoint_container;
 mod env_of;
 pub use env_of::env_of;
 
+mod image;
+pub use image::image;
+
 mod find_artifact;
 pub use find_artifact::find_artifact;
 
@@ -33,15 +42,30 @@ pub use dependencies_of::dependencies_of;
 mod lint;
 pub use lint::lint;
 
+mod verify_sources_of_submit;
+pub use verify_sources_of_submit::verify_sources_of_submit;
+
 mod what_depends;
 pub use what_depends::what_depends;
 
+mod recovery;
+
 mod release;
 pub use release::release;
 
+mod repo;
+pub use repo::repo;
+
+mod rerun_job;
+pub use rerun_job::rerun_job;
+
 mod source;
 pub use source::source;
 
+mod store;
+pub use store::store;
+pub(super) mod store_import;
+
 mod versions_of;
 pub use versions_of::versions_of;
 
@@ -51,4 +75,13 @@ pub use tree_of::tree_of;
 mod metrics;
 pub use metrics::metrics;
 
+mod notify;
+
+mod submit_metrics;
+
+mod submit_summary;
+
+mod validate_config;
+pub use validate_config::validate_config;
+
 mod util;
@@ -0,0 +1,129 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Notifications about failed submits
+//!
+//! A notification is sent to all configured sinks for each submit that contains failed jobs.
+
+use std::fmt::Write as _;
+
+use anyhow::Result;
+use tracing::{info, warn};
+
+use crate::commands::submit_summary::JobStatus;
+use crate::commands::submit_summary::SubmitReport;
+use crate::config::NotificationSinkConfig;
+use crate::util::smtp::Mail;
+
+/// Send notifications for all failed submits to the configured sinks
+///
+/// Notifications that cannot be sent are only reported as warnings, because they should not change
+/// the outcome of the build.
+pub async fn notify_failures<'a>(sinks: &[NotificationSinkConfig], reports: impl IntoIterator<Item = &'a SubmitReport>) {
+    for report in reports.into_iter().filter(|report| !report.success) {
+        for sink in sinks {
+            match sink {
+                NotificationSinkConfig::Smtp(smtp) => {
+                    let res = match render_mail(report) {
+                        Ok(mail) => crate::util::smtp::send_mail(smtp, &mail).await,
+                        Err(e) => Err(e),
+                    };
+
+                    match res {
+                        Ok(()) => info!("Notification for submit {} sent to {}", report.uuid, smtp.recipients().join(", ")),
+                        Err(e) => warn!("Failed to send notification for submit {} via {}: {:#}", report.uuid, smtp.server(), e),
+                    }
+                },
+            }
+        }
+    }
+}
+
+/// Render the summary mail for a failed submit
+fn render_mail(report: &SubmitReport) -> Result<Mail> {
+    let failed = report.jobs
+        .iter()
+        .filter(|job| matches!(job.status, JobStatus::Failed))
+        .collect::<Vec<_>>();
+
+    let mut body = String::new();
+    writeln!(body, "Submit:   {}", report.uuid)?;
+    writeln!(body, "Package:  {} {}", report.package, report.version)?;
+    writeln!(body, "Image:    {}", report.image)?;
+    writeln!(body, "Started:  {}", report.started_at)?;
+    writeln!(body, "Finished: {}", report.finished_at)?;
+    writeln!(body)?;
+    writeln!(body, "{} of {} jobs failed:", failed.len(), report.jobs.len())?;
+
+    for job in failed {
+        writeln!(body)?;
+        writeln!(body, "{} {} (job {}{})",
+            job.package.as_deref().unwrap_or("unknown package"),
+            job.version.as_deref().unwrap_or_default(),
+            job.uuid,
+            job.endpoint.as_ref().map(|ep| format!(" on {ep}")).unwrap_or_default())?;
+
+        if let Some(error) = job.error.as_ref() {
+            writeln!(body, "    Error: {error}")?;
+        }
+        writeln!(body, "    Log:   butido db log-of {}", job.uuid)?;
+        for line in job.log_excerpt.iter() {
+            writeln!(body, "    > {line}")?;
+        }
+    }
+
+    Ok(Mail {
+        subject: format!("[butido] Submit for {} {} on {} failed", report.package, report.version, report.image),
+        body,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::submit_summary::JobReport;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_render_mail() {
+        let report = SubmitReport {
+            uuid: Uuid::nil(),
+            image: String::from("debian:bullseye"),
+            package: String::from("a"),
+            version: String::from("1"),
+            started_at: String::from("2021-01-01 00:00:00"),
+            finished_at: String::from("2021-01-01 00:01:00"),
+            success: false,
+            jobs: vec![JobReport {
+                uuid: Uuid::nil(),
+                package: Some(String::from("b")),
+                version: Some(String::from("2")),
+                endpoint: Some(String::from("ep")),
+                container: None,
+                exit_code: None,
+                signal: None,
+                oom_killed: None,
+                status: JobStatus::Failed,
+                duration: Some(60.0),
+                artifacts: vec![],
+                error: Some(String::from("Script failed")),
+                failed_phase: None,
+                log_excerpt: vec![String::from("make: *** [all] Error 1")],
+            }],
+        };
+
+        let mail = render_mail(&report).unwrap();
+        assert_eq!(mail.subject, "[butido] Submit for a 1 on debian:bullseye failed");
+        assert!(mail.body.contains("1 of 1 jobs failed:"));
+        assert!(mail.body.contains("b 2 (job 00000000-0000-0000-0000-000000000000 on ep)"));
+        assert!(mail.body.contains("    Error: Script failed"));
+        assert!(mail.body.contains("    > make: *** [all] Error 1"));
+    }
+}
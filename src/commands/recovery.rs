@@ -0,0 +1,202 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Recovery of submits that were interrupted, e.g. because butido was killed
+//!
+//! The plan of the build is recorded when a submit starts, together with its job DAG and the
+//! states of its jobs. `butido build --recover` builds the job DAG again from the recorded plan and
+//! continues it: jobs keep their UUIDs from the recorded DAG, so jobs that succeeded reuse their
+//! artifacts from the staging directory of the submit. Failed jobs run again with a new UUID. The
+//! containers of the jobs that were running when the submit was interrupted are cleaned up.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Error;
+use anyhow::Result;
+use diesel::prelude::*;
+use diesel::PgConnection;
+use serde::Deserialize;
+use serde::Serialize;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::config::EndpointName;
+use crate::db::models::EndpointReservation;
+use crate::db::models::Image;
+use crate::db::models::Job;
+use crate::db::models::JobDagEdge;
+use crate::db::models::JobState;
+use crate::db::models::Submit;
+use crate::db::models::SubmitPlan;
+use crate::job::Dag;
+use crate::log::JobResult;
+use crate::package::PackageName;
+use crate::package::PackageVersion;
+use crate::package::PhaseName;
+use crate::schema;
+use crate::util::docker::ImageName;
+use crate::util::EnvironmentVariableName;
+
+/// The parameters of the build of a submit
+#[derive(Debug, Deserialize, Serialize)]
+pub struct BuildPlan {
+    pub package_name: PackageName,
+    pub package_version: PackageVersion,
+
+    /// The images of the build, plans recorded before a submit could build for several images
+    /// have a single "image"
+    #[serde(alias = "image", deserialize_with = "one_or_many")]
+    pub images: Vec<ImageName>,
+    pub phases: Vec<PhaseName>,
+    pub env: Vec<(EnvironmentVariableName, String)>,
+    pub shebang: String,
+    pub staging_dir: PathBuf,
+}
+
+impl BuildPlan {
+    /// Record the plan of `submit`
+    pub fn record(&self, database_connection: &mut PgConnection, submit: &Submit) -> Result<()> {
+        let plan = serde_json::to_string(self).context("Serializing build plan")?;
+        SubmitPlan::create(database_connection, submit, &plan)
+    }
+
+    /// Load the recorded plan of `submit`
+    pub fn load(database_connection: &mut PgConnection, submit: &Submit) -> Result<Self> {
+        let plan = SubmitPlan::for_submit(database_connection, submit)?
+            .ok_or_else(|| anyhow!("No build plan recorded for submit {}, cannot recover it", submit.uuid))?;
+
+        serde_json::from_str(&plan.plan)
+            .with_context(|| anyhow!("Parsing build plan of submit {}", submit.uuid))
+            .map_err(Error::from)
+    }
+}
+
+/// Deserialize a list that may also be recorded as a single value
+fn one_or_many<'de, D, T>(deserializer: D) -> std::result::Result<Vec<T>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany<T> {
+        One(T),
+        Many(Vec<T>),
+    }
+
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(value) => vec![value],
+        OneOrMany::Many(values) => values,
+    })
+}
+
+/// Continue the recorded job DAG of `submit` with the jobs of `dag`
+///
+/// Returns the containers (by endpoint) of the jobs that were running when the submit was
+/// interrupted.
+pub fn continue_dag(database_connection: &mut PgConnection, submit: &Submit, dag: &mut Dag) -> Result<Vec<(EndpointName, String)>> {
+    let edges = JobDagEdge::for_submit(database_connection, submit)?;
+
+    // The image of the jobs is not recorded for submits that could only build for one image
+    let mut image_names: HashMap<i32, ImageName> = HashMap::new();
+    let mut image_name = |image_id: i32| -> Result<ImageName> {
+        if let Some(name) = image_names.get(&image_id) {
+            return Ok(name.clone())
+        }
+        let image = Image::fetch_by_id(database_connection, image_id)?
+            .ok_or_else(|| anyhow!("Image {} of submit {} not found", image_id, submit.uuid))?;
+        let name = ImageName::from(image.name);
+        image_names.insert(image_id, name.clone());
+        Ok(name)
+    };
+
+    let mut uuids = edges
+        .into_iter()
+        .map(|(edge, package)| {
+            let image = image_name(edge.image_id.unwrap_or(submit.requested_image_id))?;
+            Ok(((image, PackageName::from(package.name), PackageVersion::from(package.version)), edge.job_uuid))
+        })
+        .collect::<Result<HashMap<_, _>>>()?;
+
+    // A failed job is recorded with its UUID, so it runs again as a new job
+    let recorded_jobs = schema::jobs::table
+        .filter(schema::jobs::uuid.eq_any(uuids.values().copied().collect::<Vec<_>>()))
+        .load::<Job>(database_connection)
+        .with_context(|| anyhow!("Loading jobs of submit {}", submit.uuid))?;
+    for job in recorded_jobs {
+        if matches!(job.result()?, JobResult::Success) {
+            continue
+        }
+
+        let new_uuid = Uuid::new_v4();
+        JobDagEdge::replace_job_uuid(database_connection, submit, &job.uuid, &new_uuid)?;
+        uuids.values_mut().filter(|uuid| **uuid == job.uuid).for_each(|uuid| *uuid = new_uuid);
+        info!("Job {} failed, running it again as job {}", job.uuid, new_uuid);
+    }
+
+    dag.set_job_uuids(&uuids)?;
+    JobState::create_for_dag(database_connection, submit, dag)?;
+
+    // The jobs of the interrupted submit do not run anymore
+    let released = EndpointReservation::delete_for_submit(database_connection, submit)?;
+    if released > 0 {
+        info!("Released {} job slots of endpoints reserved by the interrupted submit", released);
+    }
+
+    let stale_containers = JobState::for_submit(database_connection, submit)?
+        .into_iter()
+        .filter(|(state, _)| state.state == JobState::RUNNING)
+        .filter_map(|(state, endpoint)| Some((EndpointName::from(endpoint?.name), state.container_hash?)))
+        .collect();
+    Ok(stale_containers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_plan_with_single_image() {
+        let plan = r##"{
+            "package_name": "a",
+            "package_version": "1.0",
+            "image": "debian:bullseye",
+            "phases": ["build"],
+            "env": [["FOO", "bar"]],
+            "shebang": "#!/bin/bash",
+            "staging_dir": "/tmp/staging"
+        }"##;
+        let plan = serde_json::from_str::<BuildPlan>(plan).unwrap();
+        assert_eq!(plan.images, vec![ImageName::from(String::from("debian:bullseye"))]);
+        assert_eq!(plan.phases, vec![PhaseName::from(String::from("build"))]);
+    }
+
+    #[test]
+    fn test_plan_roundtrip() {
+        let plan = BuildPlan {
+            package_name: PackageName::from(String::from("a")),
+            package_version: PackageVersion::from(String::from("1.0")),
+            images: vec![
+                ImageName::from(String::from("debian:bullseye")),
+                ImageName::from(String::from("debian:bookworm")),
+            ],
+            phases: vec![],
+            env: vec![],
+            shebang: String::from("#!/bin/bash"),
+            staging_dir: PathBuf::from("/tmp/staging"),
+        };
+        let recorded = serde_json::to_string(&plan).unwrap();
+        let loaded = serde_json::from_str::<BuildPlan>(&recorded).unwrap();
+        assert_eq!(loaded.images, plan.images);
+    }
+}
@@ -11,6 +11,7 @@
 //! Implementation of the 'release' subcommand
 
 use std::io::Write;
+use std::path::Path;
 use std::path::PathBuf;
 
 use anyhow::anyhow;
@@ -26,6 +27,12 @@ use resiter::AndThen;
 use crate::config::Configuration;
 use crate::db::models as dbmodels;
 use crate::db::DbConnectionConfig;
+use crate::filestore::release_path_collisions;
+use crate::filestore::ArtifactPath;
+use crate::filestore::ContentStore;
+use crate::filestore::ReleaseLayout;
+use crate::filestore::ReleaseSigner;
+use crate::filestore::StoreBackend;
 
 /// Implementation of the "release" subcommand
 pub async fn release(
@@ -55,6 +62,9 @@ async fn new_release(
             config.releases_directory().display()
         ));
     }
+    if !config.release_stores().contains(release_store_name) {
+        return Err(anyhow!("Unknown release store name: {}", release_store_name))
+    }
 
     let pname = matches.get_one::<String>("package_name");
 
@@ -80,7 +90,7 @@ async fn new_release(
             .inner_join(crate::schema::jobs::table.inner_join(crate::schema::packages::table))
             .filter(crate::schema::jobs::submit_id.eq(submit.id))
             .left_outer_join(crate::schema::releases::table) // not released
-            .select(crate::schema::artifacts::all_columns);
+            .select((crate::schema::artifacts::all_columns, crate::schema::packages::all_columns));
 
         match (pname, pvers) {
             (Some(name), Some(vers)) => {
@@ -91,7 +101,7 @@ async fn new_release(
                     "Query: {:?}",
                     diesel::debug_query::<diesel::pg::Pg, _>(&query)
                 );
-                query.load::<dbmodels::Artifact>(&mut pool.get().unwrap())?
+                query.load::<(dbmodels::Artifact, dbmodels::Package)>(&mut pool.get().unwrap())?
             }
             (Some(name), None) => {
                 let query = sel.filter(crate::schema::packages::name.eq(name));
@@ -99,7 +109,7 @@ async fn new_release(
                     "Query: {:?}",
                     diesel::debug_query::<diesel::pg::Pg, _>(&query)
                 );
-                query.load::<dbmodels::Artifact>(&mut pool.get().unwrap())?
+                query.load::<(dbmodels::Artifact, dbmodels::Package)>(&mut pool.get().unwrap())?
             }
             (None, Some(vers)) => {
                 let query = sel.filter(crate::schema::packages::version.like(vers));
@@ -107,22 +117,38 @@ async fn new_release(
                     "Query: {:?}",
                     diesel::debug_query::<diesel::pg::Pg, _>(&query)
                 );
-                query.load::<dbmodels::Artifact>(&mut pool.get().unwrap())?
+                query.load::<(dbmodels::Artifact, dbmodels::Package)>(&mut pool.get().unwrap())?
             }
             (None, None) => {
                 debug!(
                     "Query: {:?}",
                     diesel::debug_query::<diesel::pg::Pg, _>(&sel)
                 );
-                sel.load::<dbmodels::Artifact>(&mut pool.get().unwrap())?
+                sel.load::<(dbmodels::Artifact, dbmodels::Package)>(&mut pool.get().unwrap())?
             }
         }
     };
     debug!("Artifacts = {:?}", arts);
 
+    let layout = ReleaseLayout::from_template(config.release_path_template().as_deref())?;
+    let arts = arts
+        .into_iter()
+        .map(|(art, package)| {
+            let artifact_path = ArtifactPath::new(art.path_buf())?;
+            let release_path = layout.release_path(&package.name, &package.version, &artifact_path)?;
+            Ok((art, artifact_path, release_path))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let collisions = release_path_collisions(arts.iter().map(|(_, _, release_path)| release_path));
+    if !collisions.is_empty() {
+        let paths = collisions.iter().map(|p| p.display().to_string()).collect::<Vec<_>>().join(", ");
+        return Err(anyhow!("More than one artifact would be released to: {}", paths));
+    }
+
     arts.iter()
-        .filter_map(|art| {
-            art.path_buf()
+        .filter_map(|(_, _, release_path)| {
+            AsRef::<Path>::as_ref(release_path)
                 .parent()
                 .map(|p| config.releases_directory().join(release_store_name).join(p))
         })
@@ -134,24 +160,51 @@ async fn new_release(
         .collect::<Result<()>>()
         .await?;
 
-    let staging_base: &PathBuf = &config.staging_directory().join(submit.uuid.to_string());
+    let staging_base: &PathBuf = &config.staging_directory_of(submit.submitted_by.as_deref()).join(submit.uuid.to_string());
 
     let release_store = crate::db::models::ReleaseStore::create(&mut pool.get().unwrap(), release_store_name)?;
-    let do_update = matches.get_flag("package_do_update");
-    let interactive = !matches.get_flag("noninteractive");
+    let force = matches.get_flag("force");
+    let do_update = force || matches.get_flag("package_do_update");
+    let interactive = !force && !matches.get_flag("noninteractive");
+    let method = if matches.get_flag("move") {
+        ReleaseMethod::Move
+    } else if matches.get_flag("hard_link") {
+        ReleaseMethod::HardLink
+    } else {
+        ReleaseMethod::Copy
+    };
+    let operator = matches
+        .get_one::<String>("operator")
+        .cloned()
+        .or_else(|| config.submitter());
+    debug!("Releasing with {:?} as {:?}", method, operator);
+
+    let release_root = config.releases_directory().join(release_store_name);
+    let release_backend = StoreBackend::from_config(config.release_store_backends().get(release_store_name))?;
+    let signer = ReleaseSigner::from_config(config.release_signing().as_ref());
+    let content_store = ContentStore::from_config(config.content_store().as_ref());
+    let staging_backend = StoreBackend::from_config(config.staging_backend().as_ref())?
+        .for_subdirectory(&submit.uuid.to_string());
 
     let now = chrono::offset::Local::now().naive_local();
     let any_err = arts.into_iter()
-        .map(|art| async {
-            let art = art; // ensure it is moved
+        .map(|(art, artifact_path, release_path)| async {
+            let (art, artifact_path, release_path) = (art, artifact_path, release_path); // ensure they are moved
             let art_path = staging_base.join(&art.path);
-            let dest_path = config.releases_directory().join(release_store_name).join(&art.path);
+            let dest_path = release_root.join(release_path.as_ref());
             debug!(
                 "Trying to release {} to {}",
                 art_path.display(),
                 dest_path.display()
             );
 
+            if !art_path.is_file() {
+                // The artifact might only exist in the backend of the staging store
+                if let Err(e) = staging_backend.fetch(staging_base, &artifact_path).await {
+                    trace!("Fetching {} from staging backend failed: {:?}", artifact_path.display(), e);
+                }
+            }
+
             if !art_path.is_file() {
                 trace!(
                     "Artifact does not exist as file, cannot release it: {:?}",
@@ -160,7 +213,7 @@ async fn new_release(
                 Err(anyhow!("Not a file: {}", art_path.display()))
             } else {
                 if dest_path.exists() && !do_update {
-                    return Err(anyhow!("Does already exist: {}", dest_path.display()));
+                    return Err(anyhow!("Does already exist: {} (use --force to overwrite)", dest_path.display()));
                 } else if dest_path.exists() && do_update {
                     writeln!(std::io::stderr(), "Going to update: {}", dest_path.display())?;
                     if interactive && !dialoguer::Confirm::new().with_prompt("Continue?").interact()? {
@@ -176,16 +229,32 @@ async fn new_release(
                 }
 
                 // else !dest_path.exists()
-                tokio::fs::copy(&art_path, &dest_path)
-                    .await
-                    .with_context(|| anyhow!("Copying {} to {}", art_path.display(), dest_path.display()))
-                    .map_err(Error::from)
-                    .and_then(|_| {
-                        debug!("Updating {:?} to set released = true", art);
-                        let rel = crate::db::models::Release::create(&mut pool.get().unwrap(), &art, &now, &release_store)?;
-                        debug!("Release object = {:?}", rel);
-                        Ok(dest_path)
-                    })
+                method.release(&art_path, &dest_path).await?;
+                if let Some(content_store) = content_store.as_ref() {
+                    let dedup = content_store.deduplicate(&dest_path)
+                        .with_context(|| anyhow!("Deduplicating {}", dest_path.display()))?;
+                    trace!("Deduplicated {}: {:?}", dest_path.display(), dedup);
+                }
+                release_backend.push(&release_root, &release_path).await?;
+
+                let signature_path = match signer.as_ref() {
+                    Some(signer) => {
+                        signer.sign(&dest_path).await?;
+                        let signature_path = ArtifactPath::new(ReleaseSigner::signature_path_of(release_path.as_ref()))?;
+                        release_backend.push(&release_root, &signature_path).await?;
+                        Some(signature_path)
+                    }
+                    None => None,
+                };
+
+                debug!("Updating {:?} to set released = true", art);
+                let signature = signature_path.as_ref().and_then(ArtifactPath::to_str);
+                let release_path_str = release_path
+                    .to_str()
+                    .ok_or_else(|| anyhow!("Path is not valid UTF-8: {}", release_path.display()))?;
+                let rel = crate::db::models::Release::create(&mut pool.get().unwrap(), &art, &now, &release_store, operator.as_deref(), signature, release_path_str)?;
+                debug!("Release object = {:?}", rel);
+                Ok(dest_path)
             }
         })
         .collect::<futures::stream::FuturesUnordered<_>>()
@@ -211,6 +280,33 @@ async fn new_release(
     }
 }
 
+/// How an artifact is transferred from the staging store to the release store
+#[derive(Clone, Copy, Debug)]
+enum ReleaseMethod {
+    Copy,
+    Move,
+    HardLink,
+}
+
+impl ReleaseMethod {
+    async fn release(self, art_path: &Path, dest_path: &Path) -> Result<()> {
+        match self {
+            ReleaseMethod::Copy => tokio::fs::copy(art_path, dest_path)
+                .await
+                .map(|_| ())
+                .with_context(|| anyhow!("Copying {} to {}", art_path.display(), dest_path.display())),
+
+            ReleaseMethod::Move => tokio::fs::rename(art_path, dest_path)
+                .await
+                .with_context(|| anyhow!("Moving {} to {}", art_path.display(), dest_path.display())),
+
+            ReleaseMethod::HardLink => tokio::fs::hard_link(art_path, dest_path)
+                .await
+                .with_context(|| anyhow!("Hard-linking {} to {}", art_path.display(), dest_path.display())),
+        }
+    }
+}
+
 pub async fn rm_release(
     db_connection_config: DbConnectionConfig<'_>,
     config: &Configuration,
@@ -233,7 +329,7 @@ pub async fn rm_release(
 
     let mut conn = db_connection_config.establish_connection()?;
 
-    let (release, artifact) = crate::schema::jobs::table
+    let release = crate::schema::jobs::table
         .inner_join(crate::schema::packages::table)
         .inner_join(crate::schema::artifacts::table)
         .inner_join(crate::schema::releases::table
@@ -244,10 +340,10 @@ pub async fn rm_release(
             .and(crate::schema::packages::dsl::version.eq(&pvers)))
         .filter(crate::schema::release_stores::dsl::store_name.eq(&release_store_name))
         .order(crate::schema::releases::dsl::release_date.desc())
-        .select((crate::schema::releases::all_columns, crate::schema::artifacts::all_columns))
-        .first::<(crate::db::models::Release, crate::db::models::Artifact)>(&mut conn)?;
+        .select(crate::schema::releases::all_columns)
+        .first::<crate::db::models::Release>(&mut conn)?;
 
-    let artifact_path = config.releases_directory().join(release_store_name).join(&artifact.path);
+    let artifact_path = config.releases_directory().join(release_store_name).join(&release.path);
     if !artifact_path.is_file() {
         return Err(anyhow!("Not a file: {}", artifact_path.display()))
     }
@@ -0,0 +1,256 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Implementation of the 'repo' subcommand
+
+use std::collections::BTreeMap;
+use std::collections::HashSet;
+use std::io::Write;
+
+use anyhow::anyhow;
+use anyhow::Error;
+use anyhow::Result;
+use clap::ArgMatches;
+use tracing::trace;
+
+use crate::package::BuildDependency;
+use crate::package::Dependency;
+use crate::package::Package;
+use crate::package::PackageName;
+use crate::package::PackageVersion;
+use crate::package::ParseDependency;
+use crate::repository::Repository;
+
+/// The key in the "meta" table of a package that names its maintainer(s)
+const META_MAINTAINER: &str = "maintainer";
+
+/// The key in the "meta" table of a package that names its license
+const META_LICENSE: &str = "license";
+
+/// Implementation of the "repo" subcommand
+pub async fn repo(matches: &ArgMatches, repo: Repository) -> Result<()> {
+    match matches.subcommand() {
+        Some(("stats", matches)) => stats(matches, repo).await,
+        Some((other, _matches)) => Err(anyhow!("Unknown subcommand: {}", other)),
+        None => Err(anyhow!("Missing subcommand")),
+    }
+}
+
+/// Implementation of the "repo stats" subcommand
+async fn stats(matches: &ArgMatches, repo: Repository) -> Result<()> {
+    let top = *matches.get_one::<usize>("top").unwrap(); // safe by clap default value
+    let stats = RepoStats::of(&repo)?;
+    let mut out = std::io::stdout();
+
+    writeln!(out, "{} packages ({} distinct names)", stats.n_packages, stats.versions_per_name.len())?;
+    writeln!(out)?;
+
+    writeln!(out, "Versions per package:")?;
+    for (n_versions, n_names) in stats.versions_distribution() {
+        writeln!(out, "  {:>4} version(s): {} package(s)", n_versions, n_names)?;
+    }
+    writeln!(out)?;
+
+    writeln!(out, "Dependency edges:")?;
+    writeln!(out, "  {:>6} build (unconditional)", stats.edges.build)?;
+    writeln!(out, "  {:>6} build (conditional)", stats.edges.build_conditional)?;
+    writeln!(out, "  {:>6} runtime (unconditional)", stats.edges.runtime)?;
+    writeln!(out, "  {:>6} runtime (conditional)", stats.edges.runtime_conditional)?;
+    writeln!(out, "  {:>6} unresolvable", stats.edges.unresolvable)?;
+    writeln!(out)?;
+
+    writeln!(out, "{} packages without '{}' meta field:", stats.without_maintainer.len(), META_MAINTAINER)?;
+    for (name, version) in stats.without_maintainer.iter() {
+        writeln!(out, "  {} {}", name, version)?;
+    }
+    writeln!(out)?;
+
+    writeln!(out, "{} packages without '{}' meta field:", stats.without_license.len(), META_LICENSE)?;
+    for (name, version) in stats.without_license.iter() {
+        writeln!(out, "  {} {}", name, version)?;
+    }
+    writeln!(out)?;
+
+    writeln!(out, "Largest dependency closures:")?;
+    for (name, version, size) in stats.closures.iter().take(top) {
+        writeln!(out, "  {:>6} {} {}", size, name, version)?;
+    }
+
+    Ok(())
+}
+
+/// Number of dependency edges in the repository, by type
+#[derive(Debug, Default, PartialEq, Eq)]
+struct EdgeCounts {
+    build: usize,
+    build_conditional: usize,
+    runtime: usize,
+    runtime_conditional: usize,
+
+    /// Edges that do not point to any package in the repository
+    unresolvable: usize,
+}
+
+/// Statistics about a repository
+#[derive(Debug)]
+struct RepoStats {
+    n_packages: usize,
+    versions_per_name: BTreeMap<PackageName, usize>,
+    edges: EdgeCounts,
+    without_maintainer: Vec<(PackageName, PackageVersion)>,
+    without_license: Vec<(PackageName, PackageVersion)>,
+
+    /// The number of packages that are (transitively) required by a package, largest first
+    closures: Vec<(PackageName, PackageVersion, usize)>,
+}
+
+impl RepoStats {
+    fn of(repo: &Repository) -> Result<Self> {
+        let mut n_packages = 0;
+        let mut versions_per_name = BTreeMap::new();
+        let mut edges = EdgeCounts::default();
+        let mut without_maintainer = vec![];
+        let mut without_license = vec![];
+        let mut closures = vec![];
+
+        for package in repo.packages() {
+            n_packages += 1;
+            *versions_per_name.entry(package.name().clone()).or_insert(0) += 1;
+
+            for dep in package.dependencies().build() {
+                match dep {
+                    BuildDependency::Simple(_) => edges.build += 1,
+                    BuildDependency::Conditional { .. } => edges.build_conditional += 1,
+                }
+                if resolve(repo, dep)?.is_empty() {
+                    edges.unresolvable += 1;
+                }
+            }
+
+            for dep in package.dependencies().runtime() {
+                match dep {
+                    Dependency::Simple(_) => edges.runtime += 1,
+                    Dependency::Conditional { .. } => edges.runtime_conditional += 1,
+                }
+                if resolve(repo, dep)?.is_empty() {
+                    edges.unresolvable += 1;
+                }
+            }
+
+            let has_meta = |key: &str| {
+                package
+                    .meta()
+                    .as_ref()
+                    .and_then(|meta| meta.get(key))
+                    .map(|value| !value.trim().is_empty())
+                    .unwrap_or(false)
+            };
+            let id = (package.name().clone(), package.version().clone());
+            if !has_meta(META_MAINTAINER) {
+                without_maintainer.push(id.clone());
+            }
+            if !has_meta(META_LICENSE) {
+                without_license.push(id.clone());
+            }
+
+            closures.push((id.0, id.1, closure_size(repo, package)?));
+        }
+
+        closures.sort_by(|a, b| b.2.cmp(&a.2).then_with(|| (&a.0, &a.1).cmp(&(&b.0, &b.1))));
+
+        Ok(RepoStats {
+            n_packages,
+            versions_per_name,
+            edges,
+            without_maintainer,
+            without_license,
+            closures,
+        })
+    }
+
+    /// Map from "number of versions" to "number of package names with that many versions"
+    fn versions_distribution(&self) -> BTreeMap<usize, usize> {
+        self.versions_per_name
+            .values()
+            .fold(BTreeMap::new(), |mut acc, n| {
+                *acc.entry(*n).or_insert(0) += 1;
+                acc
+            })
+    }
+}
+
+fn resolve<'a, D: ParseDependency>(repo: &'a Repository, dep: &D) -> Result<Vec<&'a Package>> {
+    let (name, constraint) = dep.parse_as_name_and_version()?;
+    Ok(repo.find_with_version(&name, &constraint))
+}
+
+/// Count the packages that are transitively required by `package`
+///
+/// All dependencies are followed, regardless of their condition, so this is the worst case size of
+/// the dependency tree of the package.
+fn closure_size(repo: &Repository, package: &Package) -> Result<usize> {
+    let mut seen: HashSet<(&PackageName, &PackageVersion)> = HashSet::new();
+    let mut stack = vec![package];
+
+    while let Some(p) = stack.pop() {
+        trace!("Walking dependencies of {} {}", p.name(), p.version());
+        let deps = p.dependencies();
+        let build = deps.build().iter().map(|d| resolve(repo, d));
+        let runtime = deps.runtime().iter().map(|d| resolve(repo, d));
+
+        for dependency in build.chain(runtime) {
+            for dep in dependency.map_err(Error::from)? {
+                if seen.insert((dep.name(), dep.version())) {
+                    stack.push(dep);
+                }
+            }
+        }
+    }
+
+    seen.remove(&(package.name(), package.version()));
+    Ok(seen.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::package::tests::package;
+    use crate::package::tests::pname;
+    use crate::package::tests::pversion;
+    use crate::package::Dependencies;
+
+    #[test]
+    fn test_repo_stats() {
+        let mut btree = BTreeMap::new();
+
+        for (name, vers, deps) in [
+            ("a", "1", vec!["b =1"]),
+            ("b", "1", vec!["c =1", "d =1"]),
+            ("b", "2", vec![]),
+            ("c", "1", vec![]),
+        ] {
+            let mut pack = package(name, vers, "https://rust-lang.org", "123");
+            let deps = deps.into_iter().map(String::from).map(Dependency::from).collect();
+            pack.set_dependencies(Dependencies::with_runtime_dependencies(deps));
+            btree.insert((pname(name), pversion(vers)), pack);
+        }
+
+        let repo = Repository::from(btree);
+        let stats = RepoStats::of(&repo).unwrap();
+
+        assert_eq!(stats.n_packages, 4);
+        assert_eq!(stats.versions_distribution(), BTreeMap::from([(1, 2), (2, 1)]));
+        assert_eq!(stats.edges, EdgeCounts { runtime: 3, unresolvable: 1, ..Default::default() });
+        assert_eq!(stats.without_maintainer.len(), 4);
+        assert_eq!(stats.without_license.len(), 4);
+        assert_eq!(stats.closures[0], (pname("a"), pversion("1"), 2));
+        assert_eq!(stats.closures[1], (pname("b"), pversion("1"), 1));
+    }
+}
@@ -0,0 +1,258 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+//! Implementation of the 'rerun-job' subcommand
+
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use clap::ArgMatches;
+use colored::Colorize;
+use diesel::prelude::*;
+use diesel::r2d2::ConnectionManager;
+use diesel::r2d2::Pool;
+use diesel::PgConnection;
+use tokio::sync::RwLock;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+use crate::config::Configuration;
+use crate::config::EndpointName;
+use crate::db::models as dbmodels;
+use crate::endpoint::EndpointScheduler;
+use crate::filestore::ArtifactPath;
+use crate::filestore::ArtifactKindRules;
+use crate::filestore::CompressionRules;
+use crate::filestore::ContentStore;
+use crate::filestore::StagingStore;
+use crate::filestore::StoreBackend;
+use crate::filestore::path::StoreRoot;
+use crate::filestore::path::UnpackLimits;
+use crate::job::InputManifest;
+use crate::job::JobResource;
+use crate::job::RunnableJob;
+use crate::log::LogLimit;
+use crate::log::TriageRules;
+use crate::package::PackageName;
+use crate::package::PackageVersion;
+use crate::package::PhaseName;
+use crate::package::Script;
+use crate::repository::Repository;
+use crate::schema;
+use crate::source::SourceCache;
+use crate::util::EnvironmentVariableName;
+use crate::util::docker::ImageName;
+use crate::util::progress::ProgressBars;
+
+/// Implementation of the "rerun-job" subcommand
+///
+/// The job is run with the script and environment that are recorded in the database, as a new
+/// submit with only this job. The package definition (e.g. the sources) is taken from the
+/// repository.
+pub async fn rerun_job(
+    repo_root: &Path,
+    matches: &ArgMatches,
+    config: &Configuration,
+    database_pool: Pool<ConnectionManager<PgConnection>>,
+    repo: Repository,
+    progressbars: ProgressBars,
+) -> Result<()> {
+    let job_uuid = matches.get_one::<String>("job_uuid")
+        .map(|s| Uuid::from_str(s))
+        .transpose()
+        .context("Parsing job UUID")?
+        .unwrap(); // safe by clap
+
+    let mut conn = database_pool.get()?;
+    let (job, old_submit, db_package, db_image) = schema::jobs::table
+        .inner_join(schema::submits::table)
+        .inner_join(schema::packages::table)
+        .inner_join(schema::images::table)
+        .filter(schema::jobs::uuid.eq(job_uuid))
+        .select((
+            schema::jobs::all_columns,
+            schema::submits::all_columns,
+            schema::packages::all_columns,
+            schema::images::all_columns,
+        ))
+        .first::<(dbmodels::Job, dbmodels::Submit, dbmodels::Package, dbmodels::Image)>(&mut conn)
+        .with_context(|| anyhow!("Loading job {} from database", job_uuid))?;
+
+    let package = repo
+        .find(&PackageName::from(db_package.name.clone()), &PackageVersion::from(db_package.version.clone()))
+        .into_iter()
+        .next()
+        .cloned()
+        .ok_or_else(|| anyhow!("Package {} {} of job {} not found in repository", db_package.name, db_package.version, job_uuid))?;
+
+    // The variables from the package definition are recorded with the job as well, but are set
+    // from the package anyways
+    let resources = job.env(&mut conn)?
+        .into_iter()
+        .filter(|env| {
+            package.environment()
+                .as_ref()
+                .map(|pkg_env| !pkg_env.contains_key(&EnvironmentVariableName::from(env.name.as_str())))
+                .unwrap_or(true)
+        })
+        .map(|env| JobResource::from((EnvironmentVariableName::from(env.name.as_str()), env.value)))
+        .collect::<Vec<_>>();
+
+    let dependencies = match job.input_manifest.as_deref() {
+        Some(manifest) => InputManifest::from_json(manifest)?
+            .dependencies()
+            .keys()
+            .map(|path| ArtifactPath::new(PathBuf::from(path)))
+            .collect::<Result<Vec<_>>>()?,
+        None => {
+            warn!("No input manifest recorded for job {}, running it without dependency artifacts", job_uuid);
+            vec![]
+        }
+    };
+
+    let submit_id = Uuid::new_v4();
+    let submitter = config.submitter();
+    let staging_dir = config
+        .staging_directory_of(submitter.as_deref())
+        .join(submit_id.hyphenated().to_string());
+    tokio::fs::create_dir_all(&staging_dir).await?;
+
+    // Dependency artifacts that are not in the staging directory of the original submit have to be
+    // in one of the release stores
+    let old_staging_dir = config
+        .staging_directory_of(old_submit.submitted_by.as_deref())
+        .join(old_submit.uuid.hyphenated().to_string());
+    for dependency in dependencies.iter() {
+        let source = old_staging_dir.join(dependency.as_ref());
+        if source.is_file() {
+            let target = staging_dir.join(dependency.as_ref());
+            if let Some(parent) = target.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+            debug!("Copying {} to {}", source.display(), target.display());
+            tokio::fs::copy(&source, &target)
+                .await
+                .with_context(|| anyhow!("Copying {} to {}", source.display(), target.display()))?;
+        }
+    }
+
+    let staging_store = {
+        let bar = progressbars.bar()?;
+        let compression_rules = CompressionRules::from_config(config.artifact_compression())?;
+        let store = StagingStore::load(StoreRoot::new(staging_dir.clone())?, &bar)?
+            .with_compression_rules(compression_rules)
+            .with_content_store(ContentStore::from_config(config.content_store().as_ref()))
+            .with_unpack_limits(UnpackLimits {
+                max_file_size: *config.artifact_max_size(),
+                max_files: *config.artifact_max_files(),
+            })
+            .with_kind_rules(ArtifactKindRules::from_config(config.artifact_kinds())?);
+        bar.finish_with_message("Loaded staging successfully");
+
+        let backend = StoreBackend::from_config(config.staging_backend().as_ref())?
+            .for_subdirectory(&submit_id.hyphenated().to_string());
+        Arc::new(RwLock::new(store.with_backend(backend).await?))
+    };
+    let release_stores = crate::commands::util::load_release_stores(config, &progressbars).await?;
+
+    let endpoint_name = matches.get_one::<String>("endpoint").cloned().map(EndpointName::from);
+    let endpoint_configurations = crate::commands::util::endpoint_configurations(config)
+        .into_iter()
+        .filter(|ep| endpoint_name.as_ref().map(|name| ep.endpoint_name() == name).unwrap_or(true))
+        .collect::<Vec<_>>();
+    if let (Some(name), true) = (endpoint_name.as_ref(), endpoint_configurations.is_empty()) {
+        return Err(anyhow!("Endpoint {} is not configured", name));
+    }
+
+    let triage_rules = config
+        .triage_rules()
+        .as_ref()
+        .map(|path| TriageRules::load(&repo_root.join(path)))
+        .transpose()?
+        .unwrap_or_default();
+
+    // The versions of the dependencies are the versions of the packages the dependency artifacts
+    // were built for
+    let mut dependency_versions = dependencies
+        .iter()
+        .map(|dependency| dbmodels::Artifact::package_of(&mut conn, dependency))
+        .filter_map(Result::transpose)
+        .map(|package| package.map(|p| (PackageName::from(p.name), PackageVersion::from(p.version))))
+        .collect::<Result<Vec<_>>>()?;
+    dependency_versions.sort();
+    dependency_versions.dedup();
+
+    let githash = dbmodels::GitHash::with_id(&mut conn, old_submit.repo_hash_id)?;
+    let now = chrono::offset::Local::now().naive_local();
+    let submit = dbmodels::Submit::create(&mut conn, &now, &submit_id, &db_image, &db_package, &githash, submitter.as_deref())?;
+    dbmodels::PackageDefinition::create(&mut conn, &submit, &package)?;
+    drop(conn);
+
+    let runnable = RunnableJob::rerun(
+        package,
+        ImageName::from(db_image.name.clone()),
+        Script::from(job.script_text.clone()),
+        job.phases.iter().flatten().cloned().map(PhaseName::from).collect(),
+        dependencies.into_iter().map(JobResource::from).chain(resources).collect(),
+        &dependency_versions,
+        &SourceCache::new(config.source_cache_root().clone()),
+        config,
+    );
+    let new_job_uuid = *runnable.uuid();
+
+    {
+        let out = std::io::stdout();
+        let mut outlock = out.lock();
+        writeln!(outlock, "Rerunning job:   {}", job_uuid.to_string().green())?;
+        writeln!(outlock, "Starting submit: {}", submit_id.to_string().green())?;
+        writeln!(outlock, "As job:          {}", new_job_uuid.to_string().green())?;
+    }
+
+    let scheduler = EndpointScheduler::setup(
+        endpoint_configurations,
+        staging_store,
+        release_stores,
+        database_pool,
+        submit,
+        None,
+        None,
+        config.input_manifest_store().clone(),
+        Arc::new(triage_rules),
+        Arc::new(LogLimit::from_config(config)),
+        config.containers().stall_detection().clone(),
+        None,
+        config.docker().database_capacity_accounting(),
+    )
+    .await?;
+
+    info!("Running job {}", new_job_uuid);
+    let result = scheduler
+        .schedule_job(runnable, progressbars.bar()?)
+        .await?
+        .run()
+        .await?;
+
+    let out = std::io::stdout();
+    let mut outlock = out.lock();
+    match result {
+        Ok(artifacts) => {
+            for artifact in artifacts {
+                writeln!(outlock, "{}", staging_dir.join(artifact).display())?;
+            }
+            Ok(())
+        }
+        Err(e) => Err(e).with_context(|| anyhow!("Rerun of job {} failed", job_uuid)),
+    }
+}
@@ -9,6 +9,7 @@
 //
 
 use std::convert::TryFrom;
+use std::io::Write;
 use std::path::PathBuf;
 use std::sync::Arc;
 
@@ -17,18 +18,25 @@ use anyhow::Error;
 use anyhow::Result;
 use anyhow::anyhow;
 use clap::ArgMatches;
-use tracing::{debug, trace};
+use itertools::Itertools;
+use tracing::{debug, info, trace, warn};
 use tokio::io::AsyncWriteExt;
 use tokio::sync::Mutex;
 use tokio_stream::StreamExt;
+use url::Url;
 
 use crate::config::*;
+use crate::package::condition::ConditionData;
+use crate::package::Dag;
 use crate::package::PackageName;
 use crate::package::PackageVersionConstraint;
 use crate::repository::Repository;
 use crate::source::*;
+use crate::util::EnvironmentVariableName;
+use crate::util::docker::ImageName;
 use crate::util::progress::ProgressBars;
 
+/// The number of parallel downloads if neither configured nor passed on the commandline
 const NUMBER_OF_MAX_CONCURRENT_DOWNLOADS: usize = 100;
 
 /// A wrapper around the indicatif::ProgressBar
@@ -103,7 +111,51 @@ impl ProgressWrapper {
     }
 }
 
-async fn perform_download(source: &SourceEntry, progress: Arc<Mutex<ProgressWrapper>>, timeout: Option<u64>) -> Result<()> {
+/// Download the source from the first of `urls` that works and matches the hash of the source
+///
+/// Returns the URL that was used.
+async fn download_from_any(
+    source: &SourceEntry,
+    urls: Vec<Url>,
+    progress: Arc<Mutex<ProgressWrapper>>,
+    source_bar: &indicatif::ProgressBar,
+    timeout: Option<u64>,
+) -> Result<Url> {
+    let mut error = None;
+    for url in urls {
+        source_bar.set_message(url.to_string());
+        source_bar.set_position(0);
+
+        let res = match perform_download(source, &url, progress.clone(), source_bar, timeout).await {
+            Ok(()) => source.verify_hash()
+                .await
+                .with_context(|| anyhow!("Verifying hash of source downloaded from {}", url)),
+            Err(e) => Err(e),
+        };
+
+        match res {
+            Ok(()) => return Ok(url),
+            Err(e) => {
+                warn!("Downloading {} failed: {:?}", url, e);
+                if source.path().exists() {
+                    source.remove_file().await?;
+                }
+                error = Some(e);
+            }
+        }
+    }
+
+    Err(error.unwrap_or_else(|| anyhow!("No URL to download from")))
+        .with_context(|| anyhow!("Downloading source: {}", source.path().display()))
+}
+
+async fn perform_download(
+    source: &SourceEntry,
+    url: &Url,
+    progress: Arc<Mutex<ProgressWrapper>>,
+    source_bar: &indicatif::ProgressBar,
+    timeout: Option<u64>,
+) -> Result<()> {
     trace!("Creating: {:?}", source);
     let file = source.create().await.with_context(|| {
         anyhow!(
@@ -124,14 +176,14 @@ async fn perform_download(source: &SourceEntry, progress: Arc<Mutex<ProgressWrap
 
     let client = client_builder.build().context("Building HTTP client failed")?;
 
-    let request = client.get(source.url().as_ref())
+    let request = client.get(url.as_ref())
         .build()
-        .with_context(|| anyhow!("Building request for {} failed", source.url().as_ref()))?;
+        .with_context(|| anyhow!("Building request for {} failed", url.as_ref()))?;
 
-    let response = match client.execute(request).await {
+    let response = match client.execute(request).await.and_then(|resp| resp.error_for_status()) {
         Ok(resp) => resp,
         Err(e) => {
-            return Err(e).with_context(|| anyhow!("Downloading '{}'", source.url()))
+            return Err(e).with_context(|| anyhow!("Downloading '{}'", url))
         }
     };
 
@@ -139,6 +191,7 @@ async fn perform_download(source: &SourceEntry, progress: Arc<Mutex<ProgressWrap
         .await
         .inc_download_bytes(response.content_length().unwrap_or(0))
         .await;
+    source_bar.set_length(response.content_length().unwrap_or(0));
 
     let mut stream = response.bytes_stream();
     while let Some(bytes) = stream.next().await {
@@ -150,6 +203,7 @@ async fn perform_download(source: &SourceEntry, progress: Arc<Mutex<ProgressWrap
                     .await
                     .add_bytes(bytes.len())
                     .await;
+                source_bar.inc(bytes.len() as u64);
                 Ok(())
             }
         )?;
@@ -190,9 +244,34 @@ pub async fn download(
         .map(|s| crate::commands::util::mk_package_name_regex(s.as_ref()))
         .transpose()?;
 
-    let progressbar = Arc::new(Mutex::new(ProgressWrapper::new(progressbars.bar()?)));
+    let parallelism = matches.get_one::<usize>("jobs")
+        .copied()
+        .or(*config.source_download_parallelism())
+        .unwrap_or(NUMBER_OF_MAX_CONCURRENT_DOWNLOADS);
+    if parallelism == 0 {
+        return Err(anyhow!("The number of parallel downloads must be greater than zero"))
+    }
+
+    let image_name = matches
+        .get_one::<String>("image")
+        .map(|s| s.to_owned())
+        .map(ImageName::from);
+    let additional_env = matches
+        .get_many::<String>("env")
+        .unwrap_or_default()
+        .map(AsRef::as_ref)
+        .map(crate::util::env::parse_to_env)
+        .collect::<Result<Vec<(EnvironmentVariableName, String)>>>()?;
+    let condition_data = ConditionData {
+        image_name: image_name.as_ref(),
+        env: &additional_env,
+    };
 
-    let download_sema = Arc::new(tokio::sync::Semaphore::new(NUMBER_OF_MAX_CONCURRENT_DOWNLOADS));
+    // One bar for the overall progress and one bar per source that is currently downloaded
+    let multibar = Arc::new(progressbars.multi());
+    let progressbar = Arc::new(Mutex::new(ProgressWrapper::new(multibar.add(progressbars.bar()?))));
+
+    let download_sema = Arc::new(tokio::sync::Semaphore::new(parallelism));
 
     let mut r = repo.packages()
         .filter(|p| {
@@ -225,10 +304,28 @@ pub async fn download(
             }
         }
 
-        let r = r.flat_map(|p| {
+        let with_dependencies = matches.get_flag("with_dependencies");
+        let packages = if with_dependencies {
+            r.map(|p| {
+                Dag::for_root_package(p.clone(), &repo, None, &condition_data)
+                    .map(|dag| dag.all_packages().into_iter().cloned().collect::<Vec<_>>())
+            })
+                .collect::<Result<Vec<_>>>()?
+                .into_iter()
+                .flatten()
+                .unique_by(|p| (p.name().clone(), p.version().clone()))
+                .collect::<Vec<_>>()
+        } else {
+            r.cloned().collect::<Vec<_>>()
+        };
+        trace!("Downloading sources for {} packages", packages.len());
+
+        let r = packages.iter().flat_map(|p| {
             sc.sources_for(p).into_iter().map(|source| {
                 let download_sema = download_sema.clone();
                 let progressbar = progressbar.clone();
+                let multibar = multibar.clone();
+                let progressbars = progressbars.clone();
                 async move {
                     let source_path_exists = source.path().exists();
                     if !source_path_exists && source.download_manually() {
@@ -240,30 +337,44 @@ pub async fn download(
                         .map_err(Error::from);
                     }
 
-                    if source_path_exists && !force {
+                    if source_path_exists && !force && with_dependencies {
+                        // When downloading a whole tree, sources that are cached already are fine
+                        trace!("Source exists, skipping: {}", source.path().display());
+                        Ok(None)
+                    } else if source_path_exists && !force {
                         Err(anyhow!("Source exists: {}", source.path().display()))
                     } else {
                         if source_path_exists /* && force is implied by 'if' above*/ {
                             source.remove_file().await?;
                         }
 
+                        let urls = source.urls(config.source_mirrors())?;
                         progressbar.lock().await.inc_download_count().await;
-                        {
+                        let used_url = {
                             let permit = download_sema.acquire_owned().await?;
-                            perform_download(&source, progressbar.clone(), timeout).await?;
+                            let source_bar = multibar.add(progressbars.item_bar()?);
+                            let res = download_from_any(&source, urls, progressbar.clone(), &source_bar, timeout).await;
+                            source_bar.finish_and_clear();
                             drop(permit);
-                        }
+                            res?
+                        };
                         progressbar.lock().await.finish_one_download().await;
-                        Ok(())
+
+                        if used_url != *source.url() {
+                            info!("Downloaded {} from mirror {}", source.url(), used_url);
+                            Ok(Some((source.url().clone(), used_url)))
+                        } else {
+                            Ok(None)
+                        }
                     }
                 }
             })
         })
         .collect::<futures::stream::FuturesUnordered<_>>()
-        .collect::<Vec<Result<()>>>()
+        .collect::<Vec<Result<Option<(Url, Url)>>>>()
         .await
         .into_iter()
-        .collect::<Result<()>>();
+        .collect::<Result<Vec<_>>>();
 
     if r.is_err() {
         progressbar.lock().await.error().await;
@@ -272,6 +383,14 @@ pub async fn download(
     }
 
     debug!("r = {:?}", r);
-    r
+    let mirrored = r?.into_iter().flatten().collect::<Vec<_>>();
+    if !mirrored.is_empty() {
+        let mut out = std::io::stdout();
+        writeln!(out, "Downloaded from mirrors:")?;
+        for (url, mirror) in mirrored {
+            writeln!(out, "{} -> {}", url, mirror)?;
+        }
+    }
+    Ok(())
 }
 
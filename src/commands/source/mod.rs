@@ -100,7 +100,10 @@ where
     I: Iterator<Item = &'a Package> + 'a,
 {
     let sources = packages
-        .flat_map(|p| sc.sources_for(p).into_iter())
+        .map(|p| sc.checked_sources_for(p))
+        .collect::<Result<Vec<_>>>()?
+        .into_iter()
+        .flatten()
         .collect::<Vec<_>>();
 
     let bar = progressbars.bar()?;
@@ -0,0 +1,224 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Implementation of the 'store' subcommand
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Error;
+use anyhow::Result;
+use clap::ArgMatches;
+use diesel::prelude::*;
+use tracing::{debug, info, trace, warn};
+
+use crate::config::Configuration;
+use crate::db::DbConnectionConfig;
+use crate::filestore::sha256_of_file;
+use crate::filestore::ContentStore;
+use crate::filestore::Deduplicated;
+use crate::schema;
+
+/// Implementation of the "store" subcommand
+pub async fn store(
+    db_connection_config: DbConnectionConfig<'_>,
+    config: &Configuration,
+    git_repo: &git2::Repository,
+    matches: &ArgMatches,
+) -> Result<()> {
+    match matches.subcommand() {
+        Some(("dedup", matches)) => dedup(config, matches),
+        Some(("gc", matches)) => gc(db_connection_config, config, matches).await,
+        Some(("import", matches)) => crate::commands::store_import::import(db_connection_config, config, git_repo, matches).await,
+        Some((other, _matches)) => Err(anyhow!("Unknown subcommand: {}", other)),
+        None => Err(anyhow!("Missing subcommand")),
+    }
+}
+
+/// Implementation of the "store gc" subcommand
+///
+/// Removes the staging directories of submits that are older than the cutoff date and the
+/// staging directories that do not belong to any submit in the database.
+async fn gc(
+    db_connection_config: DbConnectionConfig<'_>,
+    config: &Configuration,
+    matches: &ArgMatches,
+) -> Result<()> {
+    let dry_run = matches.get_flag("dry_run");
+    let older_than = crate::commands::util::get_date_filter("older_than", matches)?
+        .map(|dt| dt.naive_local());
+    let staging_dir = config.staging_directory();
+    if !staging_dir.is_dir() {
+        return Err(anyhow!("Staging directory does not exist: {}", staging_dir.display()));
+    }
+
+    let mut conn = db_connection_config.establish_connection()?;
+    let submit_times = schema::submits::table
+        .select((schema::submits::uuid, schema::submits::submit_time))
+        .load::<(uuid::Uuid, chrono::NaiveDateTime)>(&mut conn)?
+        .into_iter()
+        .collect::<HashMap<_, _>>();
+    debug!("Found {} submits in database", submit_times.len());
+
+    // If the staging directories are namespaced per user, the submit directories are one level
+    // deeper
+    let submit_dirs = if *config.staging_per_user() {
+        subdirectories(staging_dir)?
+            .into_iter()
+            .map(|user_dir| subdirectories(&user_dir))
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
+            .flatten()
+            .collect()
+    } else {
+        subdirectories(staging_dir)?
+    };
+
+    let mut out = std::io::stdout();
+    let mut reclaimed = 0;
+    for path in submit_dirs {
+        let submit_uuid = match path.file_name().and_then(|n| n.to_str()).map(uuid::Uuid::parse_str) {
+            Some(Ok(uuid)) => uuid,
+            _ => {
+                warn!("Ignoring directory that is not named after a submit: {}", path.display());
+                continue;
+            }
+        };
+
+        let collect = match (submit_times.get(&submit_uuid), older_than.as_ref()) {
+            (None, _) => true,
+            (Some(submit_time), Some(cutoff)) => submit_time < cutoff,
+            (Some(_), None) => false,
+        };
+
+        if !collect {
+            trace!("Keeping {}", path.display());
+            continue;
+        }
+
+        let size = dir_size(&path)?;
+        reclaimed += size;
+        if dry_run {
+            writeln!(out, "Would remove {} ({} bytes)", path.display(), size)?;
+        } else {
+            writeln!(out, "Removing {} ({} bytes)", path.display(), size)?;
+            tokio::fs::remove_dir_all(&path)
+                .await
+                .with_context(|| anyhow!("Removing {}", path.display()))?;
+        }
+    }
+
+    if let Some(content_store) = ContentStore::from_config(config.content_store().as_ref()) {
+        for (object, size) in content_store.orphans()? {
+            reclaimed += size;
+            if dry_run {
+                writeln!(out, "Would remove unreferenced object {} ({} bytes)", object.display(), size)?;
+            } else {
+                writeln!(out, "Removing unreferenced object {} ({} bytes)", object.display(), size)?;
+                tokio::fs::remove_file(&object)
+                    .await
+                    .with_context(|| anyhow!("Removing {}", object.display()))?;
+            }
+        }
+    }
+
+    if dry_run {
+        writeln!(out, "Would reclaim {reclaimed} bytes")?;
+    } else {
+        info!("Staging store garbage collection finished");
+        writeln!(out, "Reclaimed {reclaimed} bytes")?;
+    }
+    Ok(())
+}
+
+/// Implementation of the "store dedup" subcommand
+///
+/// Deduplicates the artifacts that already are in the staging and release stores in the
+/// configured content store.
+fn dedup(config: &Configuration, matches: &ArgMatches) -> Result<()> {
+    let dry_run = matches.get_flag("dry_run");
+    let content_store = ContentStore::from_config(config.content_store().as_ref())
+        .ok_or_else(|| anyhow!("No content store configured"))?;
+
+    let mut roots = vec![config.staging_directory().clone()];
+    roots.extend({
+        config.release_stores()
+            .iter()
+            .map(|name| config.releases_directory().join(name))
+    });
+
+    let files = roots
+        .iter()
+        .filter(|root| root.is_dir())
+        .flat_map(|root| walkdir::WalkDir::new(root).into_iter())
+        .map(|entry| entry.map_err(Error::from))
+        .filter(|entry| !matches!(entry, Ok(e) if !e.file_type().is_file()))
+        .map(|entry| entry.map(walkdir::DirEntry::into_path))
+        .collect::<Result<Vec<_>>>()?;
+    debug!("Found {} files to deduplicate", files.len());
+
+    let mut out = std::io::stdout();
+    let mut saved = 0;
+    if dry_run {
+        let mut seen = std::collections::HashSet::new();
+        for file in files {
+            let hash = sha256_of_file(&file)?;
+            if !seen.insert(hash.clone()) || content_store.object_path(&hash).is_file() {
+                let size = file.metadata().with_context(|| anyhow!("Reading metadata of {}", file.display()))?.len();
+                writeln!(out, "Would link {} ({} bytes)", file.display(), size)?;
+                saved += size;
+            }
+        }
+        writeln!(out, "Would save up to {saved} bytes")?;
+    } else {
+        for file in files {
+            match content_store.deduplicate(&file).with_context(|| anyhow!("Deduplicating {}", file.display()))? {
+                Deduplicated::Linked(size) => {
+                    writeln!(out, "Linked {} ({} bytes)", file.display(), size)?;
+                    saved += size;
+                },
+                other => trace!("{}: {:?}", file.display(), other),
+            }
+        }
+        writeln!(out, "Saved {saved} bytes")?;
+    }
+    Ok(())
+}
+
+fn subdirectories(path: &Path) -> Result<Vec<PathBuf>> {
+    std::fs::read_dir(path)
+        .with_context(|| anyhow!("Reading directory {}", path.display()))?
+        .map(|entry| entry.map(|e| e.path()).map_err(Error::from))
+        .filter(|path| match path {
+            Ok(p) if !p.is_dir() => {
+                trace!("Ignoring non-directory in staging directory: {}", p.display());
+                false
+            }
+            _ => true,
+        })
+        .collect()
+}
+
+fn dir_size(path: &Path) -> Result<u64> {
+    walkdir::WalkDir::new(path)
+        .into_iter()
+        .map(|entry| {
+            entry
+                .map_err(Error::from)
+                .and_then(|e| e.metadata().map_err(Error::from))
+                .map(|md| if md.is_file() { md.len() } else { 0 })
+        })
+        .sum::<Result<u64>>()
+        .with_context(|| anyhow!("Calculating size of {}", path.display()))
+}
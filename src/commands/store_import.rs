@@ -0,0 +1,413 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Implementation of the 'store import' subcommand
+//!
+//! A bundle is a directory (or a tar archive of a directory, optionally gzip compressed) with a
+//! `manifest.json` and the artifacts listed in it:
+//!
+//! ```json
+//! {
+//!     "artifacts": [
+//!         {
+//!             "path": "a-1.0.tar.gz",
+//!             "sha256": "<hex encoded SHA256 of the artifact>",
+//!             "package": "a",
+//!             "version": "1.0",
+//!             "image": "debian:bullseye"
+//!         }
+//!     ]
+//! }
+//! ```
+//!
+//! The paths are relative to the bundle and are kept in the store.
+
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Error;
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use clap::ArgMatches;
+use colored::Colorize;
+use diesel::Connection;
+use diesel::PgConnection;
+use indicatif::ProgressBar;
+use itertools::Itertools;
+use serde::Deserialize;
+use tracing::{debug, info, trace, warn};
+
+use crate::config::Configuration;
+use crate::config::EndpointName;
+use crate::db::models as dbmodels;
+use crate::db::DbConnectionConfig;
+use crate::filestore::path::StoreRoot;
+use crate::filestore::ArtifactPath;
+use crate::filestore::ReleaseStore;
+use crate::filestore::sha256_of_file;
+use crate::filestore::StagingStore;
+use crate::filestore::StoreBackend;
+use crate::filestore::PACKAGE_ARTIFACT_KIND;
+use crate::package::Script;
+use crate::util::docker::ContainerHash;
+use crate::util::docker::ImageName;
+
+/// The name of the manifest file in a bundle
+const MANIFEST_FILE: &str = "manifest.json";
+
+/// The endpoint and container the jobs of imported artifacts are recorded with in the database
+const IMPORT_ENDPOINT: &str = "butido-import";
+
+#[derive(Debug, Deserialize)]
+struct BundleManifest {
+    artifacts: Vec<BundleArtifact>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BundleArtifact {
+    path: PathBuf,
+    sha256: String,
+    package: String,
+    version: String,
+    image: String,
+}
+
+/// The store the artifacts of a bundle are imported into
+enum ImportStore {
+    Staging(Box<StagingStore>),
+    Release(ReleaseStore),
+}
+
+impl ImportStore {
+    async fn import_file(&mut self, source: &Path, p: &ArtifactPath) -> Result<()> {
+        match self {
+            ImportStore::Staging(store) => store.import_file(source, p).await,
+            ImportStore::Release(store) => store.import_file(source, p).await,
+        }
+    }
+
+    async fn remove_file(&mut self, p: &ArtifactPath) -> Result<()> {
+        match self {
+            ImportStore::Staging(store) => store.remove_file(p).await,
+            ImportStore::Release(store) => store.remove_file(p).await,
+        }
+    }
+
+    async fn push(&self, p: &ArtifactPath) -> Result<()> {
+        match self {
+            ImportStore::Staging(store) => store.push(p).await,
+            ImportStore::Release(store) => store.push(p).await,
+        }
+    }
+
+    /// Remove the artifacts that were imported into the store at `store_root` because the import
+    /// failed
+    ///
+    /// The directory of a staging store only contains the imported artifacts, it is removed.
+    async fn roll_back(&mut self, store_root: &Path, imported: &[ArtifactPath]) {
+        if let ImportStore::Staging(_) = self {
+            if let Err(e) = tokio::fs::remove_dir_all(store_root).await {
+                warn!("Removing {} failed: {:?}", store_root.display(), e);
+            }
+            return
+        }
+
+        for artifact_path in imported {
+            if let Err(e) = self.remove_file(artifact_path).await {
+                warn!("Removing imported artifact {} failed: {:?}", artifact_path.display(), e);
+            }
+        }
+    }
+}
+
+/// Implementation of the "store import" subcommand
+pub async fn import(
+    db_connection_config: DbConnectionConfig<'_>,
+    config: &Configuration,
+    git_repo: &git2::Repository,
+    matches: &ArgMatches,
+) -> Result<()> {
+    let bundle = PathBuf::from(matches.get_one::<String>("bundle").unwrap()); // safe by clap
+    let release_store_name = matches.get_one::<String>("release_store_name");
+    if let Some(name) = release_store_name {
+        if !config.release_stores().contains(name) {
+            return Err(anyhow!("Unknown release store name: {}", name))
+        }
+    }
+
+    // Tar archives are unpacked to a temporary directory first
+    let (bundle_dir, unpacked) = if bundle.is_dir() {
+        (bundle.clone(), false)
+    } else {
+        (std::env::temp_dir().join(format!("butido-import-{}", uuid::Uuid::new_v4())), true)
+    };
+
+    let result = if unpacked {
+        match unpack(&bundle, &bundle_dir) {
+            Ok(()) => import_dir(db_connection_config, config, git_repo, &bundle, &bundle_dir, release_store_name.map(String::as_str)).await,
+            Err(e) => Err(e),
+        }
+    } else {
+        import_dir(db_connection_config, config, git_repo, &bundle, &bundle_dir, release_store_name.map(String::as_str)).await
+    };
+
+    if unpacked && bundle_dir.exists() {
+        trace!("Removing {}", bundle_dir.display());
+        std::fs::remove_dir_all(&bundle_dir)
+            .with_context(|| anyhow!("Removing {}", bundle_dir.display()))?;
+    }
+    result
+}
+
+/// Import the artifacts of the bundle `bundle`, unpacked at `bundle_dir`
+///
+/// The artifacts are copied into the store first and are removed again if they cannot be
+/// recorded in the database. They are only pushed to the backend of the store once they are
+/// recorded.
+async fn import_dir(
+    db_connection_config: DbConnectionConfig<'_>,
+    config: &Configuration,
+    git_repo: &git2::Repository,
+    bundle: &Path,
+    bundle_dir: &Path,
+    release_store_name: Option<&str>,
+) -> Result<()> {
+    let manifest_path = bundle_dir.join(MANIFEST_FILE);
+    let manifest = std::fs::read_to_string(&manifest_path)
+        .with_context(|| anyhow!("Reading {}", manifest_path.display()))
+        .and_then(|s| serde_json::from_str::<BundleManifest>(&s).with_context(|| anyhow!("Parsing {}", manifest_path.display())))?;
+
+    if manifest.artifacts.is_empty() {
+        return Err(anyhow!("No artifacts in {}", manifest_path.display()))
+    }
+
+    // Verify all artifacts before anything is written
+    let mut artifact_paths = Vec::with_capacity(manifest.artifacts.len());
+    for artifact in manifest.artifacts.iter() {
+        let artifact_path = ArtifactPath::new(artifact.path.clone())?;
+        if artifact.path.components().any(|c| !matches!(c, std::path::Component::Normal(_))) {
+            return Err(anyhow!("Not a plain relative path: {}", artifact.path.display()))
+        }
+
+        let source = bundle_dir.join(&artifact.path);
+        let hash = sha256_of_file(&source)?;
+        if !hash.eq_ignore_ascii_case(&artifact.sha256) {
+            return Err(anyhow!(
+                "Hash mismatch for {}: expected {}, got {}",
+                artifact.path.display(),
+                artifact.sha256,
+                hash
+            ))
+        }
+        debug!("Verified {}", artifact.path.display());
+        artifact_paths.push(artifact_path);
+    }
+
+    let mut conn = db_connection_config.establish_connection()?;
+    let submitter = config.submitter();
+    let submit_uuid = uuid::Uuid::new_v4();
+    let now = chrono::offset::Local::now().naive_local();
+
+    let bar = ProgressBar::hidden();
+    let (store_root, mut store) = match release_store_name {
+        Some(name) => {
+            let dir = config.releases_directory().join(name);
+            let backend = StoreBackend::from_config(config.release_store_backends().get(name))?;
+            let store = ReleaseStore::load(StoreRoot::new(dir.clone())?, &bar)?
+                .with_backend(backend)
+                .await?;
+            (dir, ImportStore::Release(store))
+        },
+        None => {
+            let dir = config.staging_directory_of(submitter.as_deref()).join(submit_uuid.hyphenated().to_string());
+            tokio::fs::create_dir_all(&dir)
+                .await
+                .with_context(|| anyhow!("Creating directory {}", dir.display()))?;
+            let backend = StoreBackend::from_config(config.staging_backend().as_ref())?
+                .for_subdirectory(&submit_uuid.hyphenated().to_string());
+            let store = match StagingStore::load(StoreRoot::new(dir.clone())?, &bar) {
+                Ok(store) => store.with_backend(backend).await,
+                Err(e) => Err(e),
+            };
+            match store {
+                Ok(store) => (dir, ImportStore::Staging(Box::new(store))),
+                Err(e) => {
+                    let _ = tokio::fs::remove_dir_all(&dir).await;
+                    return Err(e)
+                },
+            }
+        },
+    };
+
+    let mut imported = Vec::with_capacity(artifact_paths.len());
+    for (artifact, artifact_path) in manifest.artifacts.iter().zip(artifact_paths.iter()) {
+        if let Err(e) = store.import_file(&bundle_dir.join(&artifact.path), artifact_path).await {
+            store.roll_back(&store_root, &imported).await;
+            return Err(e)
+        }
+        imported.push(artifact_path.clone());
+    }
+
+    let recorded = conn.transaction::<_, Error, _>(|conn| {
+        record_import(conn, git_repo, bundle, &manifest, &artifact_paths, &submit_uuid, &now, submitter.as_deref(), release_store_name)
+    });
+    if let Err(e) = recorded {
+        store.roll_back(&store_root, &imported).await;
+        return Err(e).context("Recording the imported artifacts in the database")
+    }
+
+    let mut out = std::io::stdout();
+    for artifact_path in artifact_paths.iter() {
+        store.push(artifact_path).await?;
+        writeln!(out, "{}", store_root.join(artifact_path.as_ref()).display())?;
+    }
+
+    info!("Imported {} artifacts as submit {}", manifest.artifacts.len(), submit_uuid);
+    writeln!(out, "Imported {} artifacts as submit {}", manifest.artifacts.len(), submit_uuid.to_string().green())?;
+    Ok(())
+}
+
+/// Record the import of the artifacts of a bundle in the database
+///
+/// The import is recorded as submit with a job for every package, version and image. The script
+/// of a job lists the artifacts that were imported with it.
+#[allow(clippy::too_many_arguments)]
+fn record_import(
+    conn: &mut PgConnection,
+    git_repo: &git2::Repository,
+    bundle: &Path,
+    manifest: &BundleManifest,
+    artifact_paths: &[ArtifactPath],
+    submit_uuid: &uuid::Uuid,
+    now: &NaiveDateTime,
+    submitter: Option<&str>,
+    release_store_name: Option<&str>,
+) -> Result<()> {
+    let first = &manifest.artifacts[0];
+    let githash = dbmodels::GitHash::create_or_fetch(conn, &crate::util::git::get_repo_head_commit_hash(git_repo)?)?;
+    let endpoint = dbmodels::Endpoint::create_or_fetch(conn, &EndpointName::from(String::from(IMPORT_ENDPOINT)))?;
+    let submit = {
+        let image = dbmodels::Image::create_or_fetch(conn, &ImageName::from(first.image.clone()))?;
+        let package = dbmodels::Package::create_or_fetch_by_name(conn, &first.package, &first.version)?;
+        dbmodels::Submit::create(conn, now, submit_uuid, &image, &package, &githash, submitter)?
+    };
+    let release_store = release_store_name
+        .map(|name| dbmodels::ReleaseStore::create(conn, name))
+        .transpose()?;
+
+    let jobs = manifest.artifacts
+        .iter()
+        .zip(artifact_paths.iter())
+        .into_group_map_by(|(artifact, _)| (artifact.package.clone(), artifact.version.clone(), artifact.image.clone()));
+
+    for ((package, version, image), artifacts) in jobs.into_iter().sorted_by(|a, b| a.0.cmp(&b.0)) {
+        let script = artifacts
+            .iter()
+            .map(|(artifact, _)| format!("# {} (sha256 {})\n", artifact.path.display(), artifact.sha256.to_ascii_lowercase()))
+            .fold(format!("# Imported from {} with butido store import\n", bundle.display()), |script, line| script + &line);
+
+        let image = dbmodels::Image::create_or_fetch(conn, &ImageName::from(image))?;
+        let package = dbmodels::Package::create_or_fetch_by_name(conn, &package, &version)?;
+        let job = dbmodels::Job::create(
+            conn,
+            &uuid::Uuid::new_v4(),
+            &submit,
+            &endpoint,
+            &package,
+            &image,
+            &ContainerHash::from(String::from(IMPORT_ENDPOINT)),
+            &Script::from(script),
+            "#BUTIDO:STATE:OK\n",
+            Some(0),
+            None,
+        )?;
+
+        for (artifact, artifact_path) in artifacts {
+            let sha256 = artifact.sha256.to_ascii_lowercase();
+            let art = dbmodels::Artifact::create(conn, artifact_path, None, PACKAGE_ARTIFACT_KIND, Some(&sha256), &job)?;
+
+            if let Some(release_store) = release_store.as_ref() {
+                let path = artifact_path
+                    .to_str()
+                    .ok_or_else(|| anyhow!("Path is not valid UTF-8: {}", artifact_path.display()))?;
+                dbmodels::Release::create(conn, &art, now, release_store, submitter, None, path)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Unpack the tar archive `bundle` (gzip compressed if it ends with ".gz" or ".tgz") to `dir`
+fn unpack(bundle: &Path, dir: &Path) -> Result<()> {
+    trace!("Unpacking {} to {}", bundle.display(), dir.display());
+    let file = std::fs::File::open(bundle).with_context(|| anyhow!("Opening {}", bundle.display()))?;
+    let gzipped = bundle
+        .extension()
+        .map(|ext| ext == "gz" || ext == "tgz")
+        .unwrap_or(false);
+
+    let reader: Box<dyn std::io::Read> = if gzipped {
+        Box::new(flate2::read::GzDecoder::new(file))
+    } else {
+        Box::new(file)
+    };
+
+    tar::Archive::new(reader)
+        .unpack(dir)
+        .with_context(|| anyhow!("Unpacking {}", bundle.display()))
+        .map_err(Error::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_manifest() {
+        let manifest = serde_json::from_str::<BundleManifest>(r#"{
+            "artifacts": [
+                { "path": "a-1.tar.gz", "sha256": "00", "package": "a", "version": "1", "image": "debian:bullseye" }
+            ]
+        }"#).unwrap();
+        assert_eq!(manifest.artifacts[0].path, PathBuf::from("a-1.tar.gz"));
+        assert_eq!(manifest.artifacts[0].package, "a");
+    }
+
+    #[test]
+    fn test_unpack_and_hash() {
+        let dir = std::env::temp_dir().join(format!("butido-import-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let bundle = dir.join("bundle.tar.gz");
+        {
+            let encoder = flate2::write::GzEncoder::new(std::fs::File::create(&bundle).unwrap(), flate2::Compression::default());
+            let mut builder = tar::Builder::new(encoder);
+            let content = b"artifact";
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, "a-1.tar.gz", &content[..]).unwrap();
+            builder.into_inner().unwrap().finish().unwrap();
+        }
+
+        let unpacked = dir.join("unpacked");
+        unpack(&bundle, &unpacked).unwrap();
+        assert_eq!(
+            sha256_of_file(&unpacked.join("a-1.tar.gz")).unwrap(),
+            crate::job::sha256_hex(b"artifact")
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,199 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Prometheus metrics of submits
+//!
+//! The metrics are written in the Prometheus text format at the end of `butido build`, so that
+//! they can be collected with the textfile collector of the node exporter.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::path::Path;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Error;
+use anyhow::Result;
+use tracing::trace;
+
+use crate::commands::submit_summary::JobStatus;
+use crate::commands::submit_summary::SubmitReport;
+
+/// The upper bounds of the buckets of the build duration histogram, in seconds
+const DURATION_BUCKETS: [f64; 8] = [60.0, 300.0, 600.0, 1800.0, 3600.0, 7200.0, 14400.0, 28800.0];
+
+/// The metrics of one or more submits
+#[derive(Debug, Default)]
+pub struct SubmitMetrics {
+    /// Number of jobs by image and status
+    jobs: BTreeMap<(String, &'static str), u64>,
+
+    /// Number of failed jobs by package
+    failures: BTreeMap<String, u64>,
+
+    /// Build durations by package
+    durations: BTreeMap<String, Vec<f64>>,
+
+    /// Number of jobs and their summed up runtime by endpoint
+    endpoints: BTreeMap<String, (u64, f64)>,
+
+    /// Number of written artifacts by image
+    artifacts: BTreeMap<String, u64>,
+}
+
+impl SubmitMetrics {
+    pub fn from_reports<'a>(reports: impl IntoIterator<Item = &'a SubmitReport>) -> Self {
+        let mut metrics = SubmitMetrics::default();
+        for report in reports {
+            for job in report.jobs.iter() {
+                let status = match job.status {
+                    JobStatus::Success => "success",
+                    JobStatus::Failed => "failed",
+                    JobStatus::Unknown => "unknown",
+                };
+                *metrics.jobs.entry((report.image.clone(), status)).or_default() += 1;
+                *metrics.artifacts.entry(report.image.clone()).or_default() += job.artifacts.len() as u64;
+
+                let package = job.package.clone().unwrap_or_default();
+                if let JobStatus::Failed = job.status {
+                    *metrics.failures.entry(package.clone()).or_default() += 1;
+                }
+
+                if let Some(duration) = job.duration {
+                    metrics.durations.entry(package).or_default().push(duration);
+                }
+
+                if let Some(endpoint) = job.endpoint.as_ref() {
+                    let entry = metrics.endpoints.entry(endpoint.clone()).or_default();
+                    entry.0 += 1;
+                    entry.1 += job.duration.unwrap_or(0.0);
+                }
+            }
+        }
+        metrics
+    }
+
+    /// Write the metrics to `path`
+    ///
+    /// The metrics are written to a temporary file first, which is then renamed, so that the
+    /// collector never reads a partially written file.
+    pub fn write_to(&self, path: &Path) -> Result<()> {
+        trace!("Writing metrics to {}", path.display());
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, self.render()?)
+            .with_context(|| anyhow!("Writing metrics to {}", tmp.display()))?;
+        std::fs::rename(&tmp, path)
+            .with_context(|| anyhow!("Renaming {} to {}", tmp.display(), path.display()))
+            .map_err(Error::from)
+    }
+
+    fn render(&self) -> Result<String> {
+        let mut out = String::new();
+
+        writeln!(out, "# HELP butido_jobs_total Number of jobs by image and status")?;
+        writeln!(out, "# TYPE butido_jobs_total counter")?;
+        for ((image, status), n) in self.jobs.iter() {
+            writeln!(out, "butido_jobs_total{{image=\"{}\",status=\"{}\"}} {}", escape(image), status, n)?;
+        }
+
+        writeln!(out, "# HELP butido_job_failures_total Number of failed jobs by package")?;
+        writeln!(out, "# TYPE butido_job_failures_total counter")?;
+        for (package, n) in self.failures.iter() {
+            writeln!(out, "butido_job_failures_total{{package=\"{}\"}} {}", escape(package), n)?;
+        }
+
+        writeln!(out, "# HELP butido_job_duration_seconds Runtime of jobs by package")?;
+        writeln!(out, "# TYPE butido_job_duration_seconds histogram")?;
+        for (package, durations) in self.durations.iter() {
+            let package = escape(package);
+            for bound in DURATION_BUCKETS.iter() {
+                let n = durations.iter().filter(|d| *d <= bound).count();
+                writeln!(out, "butido_job_duration_seconds_bucket{{package=\"{package}\",le=\"{bound}\"}} {n}")?;
+            }
+            writeln!(out, "butido_job_duration_seconds_bucket{{package=\"{package}\",le=\"+Inf\"}} {}", durations.len())?;
+            writeln!(out, "butido_job_duration_seconds_sum{{package=\"{package}\"}} {}", durations.iter().sum::<f64>())?;
+            writeln!(out, "butido_job_duration_seconds_count{{package=\"{package}\"}} {}", durations.len())?;
+        }
+
+        writeln!(out, "# HELP butido_endpoint_jobs_total Number of jobs by endpoint")?;
+        writeln!(out, "# TYPE butido_endpoint_jobs_total counter")?;
+        for (endpoint, (n, _)) in self.endpoints.iter() {
+            writeln!(out, "butido_endpoint_jobs_total{{endpoint=\"{}\"}} {}", escape(endpoint), n)?;
+        }
+
+        writeln!(out, "# HELP butido_endpoint_busy_seconds_total Summed up runtime of the jobs by endpoint")?;
+        writeln!(out, "# TYPE butido_endpoint_busy_seconds_total counter")?;
+        for (endpoint, (_, busy)) in self.endpoints.iter() {
+            writeln!(out, "butido_endpoint_busy_seconds_total{{endpoint=\"{}\"}} {}", escape(endpoint), busy)?;
+        }
+
+        writeln!(out, "# HELP butido_artifacts_written_total Number of written artifacts by image")?;
+        writeln!(out, "# TYPE butido_artifacts_written_total counter")?;
+        for (image, n) in self.artifacts.iter() {
+            writeln!(out, "butido_artifacts_written_total{{image=\"{}\"}} {}", escape(image), n)?;
+        }
+
+        Ok(out)
+    }
+}
+
+/// Escape a label value for the Prometheus text format
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::submit_summary::JobReport;
+    use uuid::Uuid;
+
+    fn job(package: &str, status: JobStatus, duration: f64) -> JobReport {
+        JobReport {
+            uuid: Uuid::nil(),
+            package: Some(String::from(package)),
+            version: Some(String::from("1")),
+            endpoint: Some(String::from("ep")),
+            container: None,
+            exit_code: None,
+            signal: None,
+            oom_killed: None,
+            status,
+            duration: Some(duration),
+            artifacts: vec![String::from("a-1.tar.gz")],
+            error: None,
+            failed_phase: None,
+            log_excerpt: vec![],
+        }
+    }
+
+    #[test]
+    fn test_render_metrics() {
+        let report = SubmitReport {
+            uuid: Uuid::nil(),
+            image: String::from("debian:bullseye"),
+            package: String::from("a"),
+            version: String::from("1"),
+            started_at: String::from("2021-01-01 00:00:00"),
+            finished_at: String::from("2021-01-01 00:10:00"),
+            success: false,
+            jobs: vec![job("a", JobStatus::Failed, 30.0), job("b", JobStatus::Success, 400.0)],
+        };
+
+        let out = SubmitMetrics::from_reports([&report]).render().unwrap();
+        let lines = out.lines().collect::<Vec<_>>();
+        assert!(lines.contains(&r#"butido_jobs_total{image="debian:bullseye",status="failed"} 1"#));
+        assert!(lines.contains(&r#"butido_job_failures_total{package="a"} 1"#));
+        assert!(lines.contains(&r#"butido_job_duration_seconds_bucket{package="b",le="300"} 0"#));
+        assert!(lines.contains(&r#"butido_job_duration_seconds_bucket{package="b",le="600"} 1"#));
+        assert!(lines.contains(&r#"butido_endpoint_busy_seconds_total{endpoint="ep"} 430"#));
+        assert!(lines.contains(&r#"butido_artifacts_written_total{image="debian:bullseye"} 2"#));
+    }
+}
@@ -0,0 +1,328 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Machine-readable summary of submits
+//!
+//! The summary is written at the end of `butido build`, so that e.g. CI pipelines can publish the
+//! results of a build without querying the database.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Error;
+use anyhow::Result;
+use diesel::prelude::*;
+use diesel::PgConnection;
+use serde::Serialize;
+use tracing::trace;
+use uuid::Uuid;
+
+use crate::db::models as dbmodels;
+use crate::log::JobResult;
+use crate::log::ParsedLog;
+use crate::schema;
+
+/// The content of the summary file
+#[derive(Debug, Serialize)]
+pub struct SummaryFile {
+    pub submits: Vec<SubmitReport>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SubmitReport {
+    pub uuid: Uuid,
+    pub image: String,
+    pub package: String,
+    pub version: String,
+    pub started_at: String,
+    pub finished_at: String,
+    pub success: bool,
+    pub jobs: Vec<JobReport>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Success,
+    Failed,
+    Unknown,
+}
+
+#[derive(Debug, Serialize)]
+pub struct JobReport {
+    pub uuid: Uuid,
+    pub package: Option<String>,
+    pub version: Option<String>,
+    pub endpoint: Option<String>,
+
+    /// The hash of the container the job ran in
+    pub container: Option<String>,
+    pub status: JobStatus,
+
+    /// The exit code of the script
+    pub exit_code: Option<i32>,
+
+    /// The signal the script was killed with
+    pub signal: Option<i32>,
+
+    /// Whether the container ran out of memory
+    pub oom_killed: Option<bool>,
+
+    /// The runtime of the job in seconds
+    pub duration: Option<f64>,
+    pub artifacts: Vec<String>,
+
+    /// The error (with all causes) the job failed with
+    pub error: Option<String>,
+
+    /// The phase the script of a failed job was in when it failed
+    pub failed_phase: Option<String>,
+
+    /// The last lines of the log of a failed job
+    pub log_excerpt: Vec<String>,
+}
+
+impl JobReport {
+    /// Collect the reports for all jobs of `submit`
+    ///
+    /// Jobs that failed before they were recorded in the database are only reported with their
+    /// UUID and the error.
+    pub fn for_submit(
+        database_connection: &mut PgConnection,
+        submit: &dbmodels::Submit,
+        errors: &HashMap<Uuid, Error>,
+        log_excerpt_lines: usize,
+    ) -> Result<Vec<JobReport>> {
+        let jobs = schema::jobs::table
+            .filter(schema::jobs::submit_id.eq(submit.id))
+            .inner_join(schema::packages::table)
+            .inner_join(schema::endpoints::table)
+            .select((
+                schema::jobs::all_columns,
+                schema::packages::all_columns,
+                schema::endpoints::all_columns,
+            ))
+            .load::<(dbmodels::Job, dbmodels::Package, dbmodels::Endpoint)>(database_connection)
+            .with_context(|| anyhow!("Loading jobs of submit {}", submit.uuid))?;
+
+        let mut reports = jobs
+            .into_iter()
+            .map(|(job, package, endpoint)| {
+                let artifacts = dbmodels::Artifact::belonging_to(&job)
+                    .load::<dbmodels::Artifact>(database_connection)
+                    .with_context(|| anyhow!("Loading artifacts of job {}", job.uuid))?
+                    .into_iter()
+                    .map(|artifact| artifact.path)
+                    .collect();
+
+                let error = errors.get(&job.uuid).map(error_text);
+                let status = if error.is_some() {
+                    JobStatus::Failed
+                } else {
+                    match job.result()? {
+                        JobResult::Success => JobStatus::Success,
+                        JobResult::Errored => JobStatus::Failed,
+                        JobResult::Unknown => JobStatus::Unknown,
+                    }
+                };
+
+                let (failed_phase, log_excerpt) = match status {
+                    JobStatus::Success => (None, vec![]),
+                    _ => {
+                        let log = job.log()?;
+                        let failed_phase = ParsedLog::from_str(&log)?
+                            .failed_phase()
+                            .map(String::from);
+                        (failed_phase, log_excerpt(&log, log_excerpt_lines))
+                    },
+                };
+
+                let duration = job.duration().map(|d| d.num_milliseconds() as f64 / 1000.0);
+                let signal = job.signal();
+                Ok(JobReport {
+                    uuid: job.uuid,
+                    package: Some(package.name),
+                    version: Some(package.version),
+                    endpoint: Some(endpoint.name),
+                    container: Some(job.container_hash),
+                    status,
+                    exit_code: job.exit_code,
+                    signal,
+                    oom_killed: job.oom_killed,
+                    duration,
+                    artifacts,
+                    error,
+                    failed_phase,
+                    log_excerpt,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let unrecorded = errors
+            .iter()
+            .filter(|(uuid, _)| !reports.iter().any(|report| report.uuid == **uuid))
+            .map(|(uuid, error)| JobReport {
+                uuid: *uuid,
+                package: None,
+                version: None,
+                endpoint: None,
+                container: None,
+                status: JobStatus::Failed,
+                exit_code: None,
+                signal: None,
+                oom_killed: None,
+                duration: None,
+                artifacts: vec![],
+                error: Some(error_text(error)),
+                failed_phase: None,
+                log_excerpt: vec![],
+            })
+            .collect::<Vec<_>>();
+        reports.extend(unrecorded);
+        Ok(reports)
+    }
+}
+
+/// The report of the failed jobs of a submit
+///
+/// The report is written to the log directory when a submit finishes with errors.
+#[derive(Debug, Serialize)]
+pub struct FailureReport<'a> {
+    pub submit: &'a Uuid,
+    pub image: &'a str,
+    pub package: &'a str,
+    pub version: &'a str,
+    pub failed_jobs: Vec<&'a JobReport>,
+}
+
+impl<'a> FailureReport<'a> {
+    pub fn new(report: &'a SubmitReport) -> Self {
+        FailureReport {
+            submit: &report.uuid,
+            image: &report.image,
+            package: &report.package,
+            version: &report.version,
+            failed_jobs: report
+                .jobs
+                .iter()
+                .filter(|job| !matches!(job.status, JobStatus::Success))
+                .collect(),
+        }
+    }
+
+    /// The path of the report of the submit in `log_dir`
+    pub fn path_in(log_dir: &Path, submit: &Uuid) -> PathBuf {
+        log_dir.join(format!("{submit}-report.json"))
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string_pretty(self).context("Serializing failure report").map_err(Error::from)
+    }
+}
+
+impl SummaryFile {
+    /// Write the summary to `path`
+    ///
+    /// The summary is written as TOML if the file has the extension "toml", as JSON otherwise.
+    pub fn write_to(&self, path: &Path) -> Result<()> {
+        trace!("Writing submit summary to {}", path.display());
+        let content = self.render(path)?;
+        std::fs::write(path, content)
+            .with_context(|| anyhow!("Writing submit summary to {}", path.display()))
+            .map_err(Error::from)
+    }
+
+    fn render(&self, path: &Path) -> Result<String> {
+        if path.extension().map(|ext| ext == "toml").unwrap_or(false) {
+            toml::to_string(self).context("Serializing submit summary to TOML").map_err(Error::from)
+        } else {
+            serde_json::to_string_pretty(self).context("Serializing submit summary to JSON").map_err(Error::from)
+        }
+    }
+}
+
+fn error_text(error: &Error) -> String {
+    error.chain().map(|cause| cause.to_string()).collect::<Vec<_>>().join(": ")
+}
+
+fn log_excerpt(log: &str, lines: usize) -> Vec<String> {
+    let all = log.lines().collect::<Vec<_>>();
+    all[all.len().saturating_sub(lines)..]
+        .iter()
+        .map(|line| line.to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary() -> SummaryFile {
+        SummaryFile {
+            submits: vec![SubmitReport {
+                uuid: Uuid::nil(),
+                image: String::from("debian:bullseye"),
+                package: String::from("a"),
+                version: String::from("1"),
+                started_at: String::from("2021-01-01 00:00:00"),
+                finished_at: String::from("2021-01-01 00:01:00"),
+                success: false,
+                jobs: vec![JobReport {
+                    uuid: Uuid::nil(),
+                    package: Some(String::from("a")),
+                    version: Some(String::from("1")),
+                    endpoint: None,
+                    container: Some(String::from("abc")),
+                    status: JobStatus::Failed,
+                    exit_code: Some(137),
+                    signal: Some(9),
+                    oom_killed: Some(true),
+                    duration: Some(60.0),
+                    artifacts: vec![],
+                    error: Some(String::from("Script failed")),
+                    failed_phase: Some(String::from("build")),
+                    log_excerpt: vec![String::from("make: *** [all] Error 1")],
+                }],
+            }],
+        }
+    }
+
+    #[test]
+    fn test_render_format_by_extension() {
+        let json = summary().render(Path::new("summary.json")).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["submits"][0]["jobs"][0]["status"], "failed");
+
+        let toml = summary().render(Path::new("summary.toml")).unwrap();
+        let value: toml::Value = toml::from_str(&toml).unwrap();
+        assert_eq!(value["submits"][0]["jobs"][0]["status"].as_str(), Some("failed"));
+    }
+
+    #[test]
+    fn test_failure_report() {
+        let summary = summary();
+        let json = FailureReport::new(&summary.submits[0]).to_json().unwrap();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["package"], "a");
+        assert_eq!(value["failed_jobs"][0]["failed_phase"], "build");
+        assert_eq!(value["failed_jobs"][0]["container"], "abc");
+        assert_eq!(value["failed_jobs"][0]["oom_killed"], true);
+    }
+
+    #[test]
+    fn test_log_excerpt() {
+        assert_eq!(log_excerpt("a\nb\nc", 2), vec!["b", "c"]);
+        assert_eq!(log_excerpt("a", 5), vec!["a"]);
+    }
+}
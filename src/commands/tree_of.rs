@@ -11,6 +11,7 @@
 //! Implementation of the 'tree-of' subcommand
 
 use std::convert::TryFrom;
+use std::io::Write;
 
 use anyhow::Error;
 use anyhow::Result;
@@ -70,7 +71,13 @@ pub async fn tree_of(
             let stdout = std::io::stdout();
             let mut outlock = stdout.lock();
 
-            ptree::write_tree(&tree.display(), &mut outlock).map_err(Error::from)
+            if matches.get_flag("dot") {
+                write!(outlock, "{}", tree.to_dot()).map_err(Error::from)
+            } else if matches.get_flag("json") {
+                writeln!(outlock, "{}", serde_json::to_string_pretty(&tree.to_json())?).map_err(Error::from)
+            } else {
+                ptree::write_tree(&tree.display(), &mut outlock).map_err(Error::from)
+            }
         })
         .collect::<Result<()>>()
 }
@@ -13,6 +13,7 @@
 use std::io::Write;
 use std::fmt::Display;
 use std::path::Path;
+use std::sync::Arc;
 
 use anyhow::Context;
 use anyhow::Error;
@@ -20,15 +21,20 @@ use anyhow::Result;
 use anyhow::anyhow;
 use clap::ArgMatches;
 use itertools::Itertools;
-use tracing::{error, info, trace};
+use tracing::{debug, error, info, trace, warn};
 use regex::Regex;
 use tokio_stream::StreamExt;
 
 use crate::config::*;
+use crate::filestore::ReleaseStore;
+use crate::filestore::StoreBackend;
+use crate::filestore::path::StoreRoot;
 use crate::package::Package;
 use crate::package::PhaseName;
 use crate::package::ScriptBuilder;
 use crate::package::Shebang;
+use crate::util::output::OutputMode;
+use crate::util::progress::ProgressBars;
 
 /// Helper for getting a boolean value by name form the argument object
 pub fn getbool(m: &ArgMatches, name: &str, cmp: &str) -> bool {
@@ -116,6 +122,78 @@ where
     }
 }
 
+/// Helper function to check the scripts of all packages in an iterator with shellcheck
+///
+/// Shellcheck exits with 1 if it found problems in a script, any other nonzero exit code is an
+/// error of shellcheck itself and always fails.
+pub async fn shellcheck_packages<'a, I>(
+    iter: I,
+    shellcheck: &ShellcheckConfig,
+    config: &Configuration,
+    bar: indicatif::ProgressBar,
+) -> Result<()>
+where
+    I: Iterator<Item = &'a Package> + 'a,
+{
+    let shebang = Shebang::from(config.shebang().clone());
+    bar.set_length({
+        let (lower, upper) = iter.size_hint();
+        upper.unwrap_or(lower) as u64
+    });
+
+    let findings = iter
+        .map(|pkg| {
+            let shebang = shebang.clone();
+            let bar = bar.clone();
+            async move {
+                trace!("Checking script of {} {} with '{}'", pkg.name(), pkg.version(), shellcheck.binary().display());
+                let mut cmd = tokio::process::Command::new(shellcheck.binary());
+                cmd.args(shellcheck.args()).arg("-");
+
+                let script = ScriptBuilder::new(&shebang)
+                    .build(pkg, config.available_phases(), *config.strict_script_interpolation())?;
+
+                let (status, stdout, stderr) = script.lint(cmd)
+                    .await
+                    .with_context(|| anyhow!("Running {} for {} {}", shellcheck.binary().display(), pkg.name(), pkg.version()))?;
+                bar.inc(1);
+
+                match status.code() {
+                    Some(0) => Ok(None),
+                    Some(1) => Ok(Some((pkg.name().clone(), pkg.version().clone(), stdout))),
+                    _ => Err(anyhow!("{} failed for {} {} ({}):\n{}", shellcheck.binary().display(), pkg.name(), pkg.version(), status, stderr)),
+                }
+            }
+        })
+        .collect::<futures::stream::FuturesUnordered<_>>()
+        .collect::<Result<Vec<_>>>()
+        .await?
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+
+    if findings.is_empty() {
+        bar.finish_with_message("Finished checking package scripts with shellcheck");
+        return Ok(())
+    }
+
+    for (pkg_name, pkg_vers, stdout) in findings.iter() {
+        if shellcheck.warn_only() {
+            warn!("shellcheck found problems in the script of {pkg_name} {pkg_vers}:\n{stdout}");
+        } else {
+            error!("shellcheck found problems in the script of {pkg_name} {pkg_vers}:\n{stdout}");
+        }
+    }
+
+    if shellcheck.warn_only() {
+        bar.finish_with_message(format!("shellcheck found problems in {} package scripts", findings.len()));
+        Ok(())
+    } else {
+        bar.finish_with_message("Checking package scripts with shellcheck errored");
+        Err(anyhow!("shellcheck found problems in {} package scripts", findings.len()))
+    }
+}
+
 /// Check whether all phases are available in the package,
 /// generate a nice error message if one is not.
 fn all_phases_available(pkg: &Package, available_phases: &[PhaseName]) -> Result<()> {
@@ -161,6 +239,16 @@ pub fn mk_package_name_regex(regex: &str) -> Result<Regex> {
         .map_err(Error::from)
 }
 
+/// Helper function to make a package name regex out of a glob
+///
+/// The glob has to match the whole name, `*` matches any number of characters, `?` exactly one.
+pub fn mk_package_name_glob_regex(glob: &str) -> Result<Regex> {
+    let regex = regex::escape(glob)
+        .replace("\\*", ".*")
+        .replace("\\?", ".");
+    mk_package_name_regex(&format!("^{regex}$"))
+}
+
 /// Make a header column for the ascii_table crate
 pub fn mk_header(vec: Vec<&str>) -> Vec<ascii_table::Column> {
     vec.into_iter()
@@ -174,7 +262,7 @@ pub fn mk_header(vec: Vec<&str>) -> Vec<ascii_table::Column> {
 }
 
 /// Display the passed data as nice ascii table,
-/// or, if stdout is a pipe, print it nicely parseable
+/// or, depending on the output mode, print it nicely parseable or as JSON
 ///
 /// If `csv` is `true`, convert the data to CSV and print that instead.
 pub fn display_data<D: Display>(
@@ -202,7 +290,20 @@ pub fn display_data<D: Display>(
             .map_err(Error::from)
             .and_then(|t| String::from_utf8(t).map_err(Error::from))
             .and_then(|text| writeln!(lock, "{text}").map_err(Error::from))
-    } else if atty::is(atty::Stream::Stdout) {
+    } else if OutputMode::current() == OutputMode::Json {
+        let rows = data.into_iter()
+            .map(|record| {
+                headers.iter()
+                    .map(|column| column.header().to_string())
+                    .zip(record.into_iter().map(|e| serde_json::Value::String(e.to_string())))
+                    .collect::<serde_json::Map<_, _>>()
+            })
+            .collect::<Vec<_>>();
+
+        let out = std::io::stdout();
+        let mut lock = out.lock();
+        writeln!(lock, "{}", serde_json::to_string_pretty(&rows)?).map_err(Error::from)
+    } else if OutputMode::current() == OutputMode::Fancy {
         let mut ascii_table = ascii_table::AsciiTable::default();
         ascii_table.set_max_width(terminal_size::terminal_size()
             .map(|tpl| tpl.0 .0 as usize) // an ugly interface indeed!
@@ -258,3 +359,72 @@ pub fn get_date_filter(name: &str, matches: &ArgMatches) -> Result<Option<chrono
         .transpose()
 }
 
+/// The configurations of all configured endpoints
+pub fn endpoint_configurations(config: &Configuration) -> Vec<crate::endpoint::EndpointConfiguration> {
+    config
+        .docker()
+        .endpoints()
+        .iter()
+        .map(|(ep_name, ep_cfg)| {
+            crate::endpoint::EndpointConfiguration::builder()
+                .endpoint_name(ep_name.clone())
+                .endpoint(ep_cfg.clone())
+                .required_images(config.docker().images().iter().map(|img| img.name.clone()).collect::<Vec<_>>())
+                .required_docker_versions(config.docker().docker_versions().clone())
+                .required_docker_api_versions(config.docker().docker_api_versions().clone())
+                .pull_missing_images(config.docker().pull_missing_images())
+                .registries(config.docker().registries().clone())
+                .build()
+        })
+        .collect()
+}
+
+/// Load the release stores, in the configured search order
+pub async fn load_release_stores(config: &Configuration, progressbars: &ProgressBars) -> Result<Vec<Arc<ReleaseStore>>> {
+    let release_stores = config
+        .release_store_search_order()
+        .as_ref()
+        .unwrap_or_else(|| config.release_stores())
+        .iter()
+        .map(|storename| {
+            let bar_release_loading = progressbars.bar()?;
+
+            let p = config.releases_directory().join(storename);
+            let p_str = p.to_string_lossy();
+            debug!("Loading release directory: {}", p_str);
+            let r = ReleaseStore::load(StoreRoot::new(p.clone())?, &bar_release_loading);
+            if r.is_ok() {
+                bar_release_loading.finish_with_message(format!("Loaded releases in {p_str} successfully"));
+            } else {
+                bar_release_loading.finish_with_message(format!("Failed to load releases in {p_str}"));
+            }
+            r.map(|store| (storename, store))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    futures::future::try_join_all({
+        release_stores.into_iter().map(|(storename, store)| async move {
+            let backend = StoreBackend::from_config(config.release_store_backends().get(storename))?;
+            store.with_backend(backend).await.map(Arc::new)
+        })
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_package_name_glob_regex() {
+        let re = mk_package_name_glob_regex("lib*-dev?").unwrap();
+        assert!(re.is_match("libfoo-dev1"));
+        assert!(re.is_match("lib-devx"));
+        assert!(!re.is_match("libfoo-dev"));
+        assert!(!re.is_match("xlibfoo-dev1"));
+
+        let re = mk_package_name_glob_regex("foo.bar").unwrap();
+        assert!(re.is_match("foo.bar"));
+        assert!(!re.is_match("fooxbar"));
+    }
+}
@@ -0,0 +1,113 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Implementation of the 'validate-config' subcommand
+
+use std::io::Write;
+
+use anyhow::anyhow;
+use anyhow::Error;
+use anyhow::Result;
+use clap::ArgMatches;
+use colored::Colorize;
+use url::Url;
+
+use crate::config::Endpoint;
+use crate::config::EndpointType;
+use crate::config::NotValidatedConfiguration;
+use crate::db::DbConnectionConfig;
+
+/// Implementation of the "validate-config" subcommand
+///
+/// In contrast to the validation that runs on every start of butido, this does not stop at the
+/// first problem, but reports all problems that were found.
+pub fn validate_config(config: &NotValidatedConfiguration, cli: &ArgMatches) -> Result<()> {
+    let mut checks: Vec<(String, Vec<Error>)> = vec![
+        (String::from("Configuration"), config.problems()),
+        (format!("Log directory {}", config.log_dir().display()), {
+            if config.log_dir().is_dir() {
+                vec![]
+            } else {
+                vec![anyhow!("Not a directory: {}", config.log_dir().display())]
+            }
+        }),
+    ];
+
+    for (name, endpoint) in config.docker().endpoints().iter() {
+        checks.push((format!("Endpoint {} ({})", name, endpoint.uri()), check_endpoint_uri(endpoint)));
+    }
+
+    checks.push((String::from("Database connection"), {
+        DbConnectionConfig::parse(config, cli)
+            .and_then(DbConnectionConfig::establish_connection)
+            .err()
+            .into_iter()
+            .collect()
+    }));
+
+    let mut out = std::io::stdout();
+    for (check, problems) in checks.iter() {
+        if problems.is_empty() {
+            writeln!(out, "{}: {}", check, "ok".green())?;
+        } else {
+            writeln!(out, "{}: {}", check, "failed".red())?;
+            for problem in problems {
+                writeln!(out, "    {problem:#}")?;
+            }
+        }
+    }
+
+    let n_problems = checks.iter().map(|(_, problems)| problems.len()).sum::<usize>();
+    if n_problems == 0 {
+        Ok(())
+    } else {
+        Err(anyhow!("Found {} problems in the configuration", n_problems))
+    }
+}
+
+/// Check whether the URI of an endpoint is well formed
+fn check_endpoint_uri(endpoint: &Endpoint) -> Vec<Error> {
+    match endpoint.endpoint_type() {
+        EndpointType::Http => match Url::parse(endpoint.uri()) {
+            Ok(url) if url.host().is_none() => vec![anyhow!("No host in URI: {}", endpoint.uri())],
+            Ok(_) => vec![],
+            Err(e) => vec![Error::from(e).context(anyhow!("Parsing URI: {}", endpoint.uri()))],
+        },
+        EndpointType::Socket => {
+            if endpoint.uri().starts_with('/') {
+                vec![]
+            } else {
+                vec![anyhow!("Not an absolute socket path: {}", endpoint.uri())]
+            }
+        },
+        // The cluster is taken from the kubeconfig, the URI is only informational
+        EndpointType::Kubernetes => match endpoint.kubernetes().kubeconfig() {
+            Some(kubeconfig) if !kubeconfig.is_file() => vec![anyhow!("Kubeconfig does not exist: {}", kubeconfig.display())],
+            _ => vec![],
+        },
+        // The jobs run on the host, the URI is only informational
+        EndpointType::Local => vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_endpoint_uri() {
+        let endpoint = |uri: &str, endpoint_type| Endpoint::new(String::from(uri), endpoint_type, 1, None);
+
+        assert!(check_endpoint_uri(&endpoint("http://0.0.0.0:8095", EndpointType::Http)).is_empty());
+        assert!(check_endpoint_uri(&endpoint("/var/run/docker.sock", EndpointType::Socket)).is_empty());
+        assert_eq!(check_endpoint_uri(&endpoint("0.0.0.0:8095", EndpointType::Http)).len(), 1);
+        assert_eq!(check_endpoint_uri(&endpoint("docker.sock", EndpointType::Socket)).len(), 1);
+    }
+}
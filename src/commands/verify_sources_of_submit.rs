@@ -0,0 +1,209 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+//! Implementation of the 'verify-sources-of-submit' subcommand
+
+use std::io::Write;
+use std::path::Path;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Error;
+use anyhow::Result;
+use clap::ArgMatches;
+use colored::Colorize;
+use diesel::prelude::*;
+use futures::StreamExt;
+use tokio::io::AsyncWriteExt;
+use tracing::{debug, trace, warn};
+use url::Url;
+
+use crate::config::Configuration;
+use crate::db::models as dbmodels;
+use crate::db::DbConnectionConfig;
+use crate::job::InputManifest;
+use crate::package::PackageName;
+use crate::package::PackageVersion;
+use crate::package::SourceHash;
+use crate::repository::Repository;
+use crate::schema;
+use crate::source::SourceCache;
+use crate::source::SourceEntry;
+
+/// Implementation of the "verify-sources-of-submit" subcommand
+///
+/// Verifies that the sources used by the jobs of a submit still match the hashes that were
+/// recorded in the input manifests of the jobs.
+pub async fn verify_sources_of_submit(
+    db_connection_config: DbConnectionConfig<'_>,
+    config: &Configuration,
+    repo: Repository,
+    matches: &ArgMatches,
+) -> Result<()> {
+    let submit_uuid = matches.get_one::<uuid::Uuid>("submit").unwrap(); // safe by clap
+    let redownload = matches.get_flag("redownload");
+    let timeout = matches.get_one::<u64>("timeout").copied();
+    let csv = matches.get_flag("csv");
+
+    let mut conn = db_connection_config.establish_connection()?;
+    let submit = dbmodels::Submit::with_id(&mut conn, submit_uuid)?;
+    let jobs = schema::jobs::table
+        .filter(schema::jobs::submit_id.eq(submit.id))
+        .inner_join(schema::packages::table)
+        .select((schema::jobs::all_columns, schema::packages::all_columns))
+        .load::<(dbmodels::Job, dbmodels::Package)>(&mut conn)
+        .with_context(|| anyhow!("Loading jobs of submit {}", submit.uuid))?;
+
+    let cache = SourceCache::new(config.source_cache_root().clone());
+    let mut data = vec![];
+    let mut problems = 0;
+    for (job, package) in jobs {
+        let manifest = match job.input_manifest.as_deref() {
+            Some(manifest) => InputManifest::from_json(manifest)?,
+            None => {
+                warn!("No input manifest recorded for job {}, cannot verify its sources", job.uuid);
+                problems += 1;
+                data.push(vec![package.name, package.version, String::new(), String::new(), String::from("no input manifest recorded")]);
+                continue
+            }
+        };
+
+        let repo_package = repo
+            .find(&PackageName::from(package.name.clone()), &PackageVersion::from(package.version.clone()))
+            .into_iter()
+            .next();
+        let entries = repo_package.map(|p| cache.sources_for(p)).unwrap_or_default();
+
+        for (source_name, recorded) in manifest.sources() {
+            let entry = entries.iter().find(|entry| entry.name() == source_name);
+            let status = match entry {
+                None => Err(anyhow!("not in the repository anymore")),
+                Some(entry) => verify_source(entry, recorded, config.source_mirrors(), redownload, timeout).await,
+            };
+
+            let status = match status {
+                Ok(status) => status,
+                Err(e) => {
+                    problems += 1;
+                    format!("{:#}", e).red().to_string()
+                }
+            };
+            data.push(vec![package.name.clone(), package.version.clone(), source_name.clone(), recorded.clone(), status]);
+        }
+    }
+
+    if data.is_empty() {
+        writeln!(std::io::stdout(), "No sources recorded for submit {}", submit.uuid)?;
+    } else {
+        let hdrs = crate::commands::util::mk_header(vec!["Package", "Version", "Source", "Recorded hash", "Status"]);
+        crate::commands::util::display_data(hdrs, data, csv)?;
+    }
+
+    if problems > 0 {
+        Err(anyhow!("{} sources of submit {} could not be verified", problems, submit.uuid))
+    } else {
+        Ok(())
+    }
+}
+
+/// Verify the source of `entry` against the `recorded` hash
+///
+/// The source is read from the source cache, or downloaded if it is not cached or `redownload` is
+/// set. Returns a description of where the verified source came from.
+async fn verify_source(
+    entry: &SourceEntry,
+    recorded: &str,
+    global_mirrors: &[Url],
+    redownload: bool,
+    timeout: Option<u64>,
+) -> Result<String> {
+    let recorded = recorded.parse::<SourceHash>()?;
+    let declared = entry.hash();
+    let definition_changed = declared.to_string() != recorded.to_string();
+    if definition_changed {
+        debug!("Hash of source {} changed in the repository: {} -> {}", entry.name(), recorded, declared);
+    }
+
+    let status = if !redownload && entry.path().is_file() {
+        hash_matches(&recorded, &entry.path()).await.context("cached source does not match")?;
+        String::from("ok (cache)")
+    } else {
+        let url = download_matching(entry, &recorded, global_mirrors, timeout).await?;
+        format!("ok (downloaded from {url})")
+    };
+
+    if definition_changed {
+        Ok(format!("{status}, but the repository now declares {declared}"))
+    } else {
+        Ok(status)
+    }
+}
+
+/// Download the source from the first of its URLs that matches the `recorded` hash
+///
+/// The download is written to a temporary file, the source cache is not touched.
+async fn download_matching(entry: &SourceEntry, recorded: &SourceHash, global_mirrors: &[Url], timeout: Option<u64>) -> Result<Url> {
+    let tmp = std::env::temp_dir().join(format!("butido-verify-{}", uuid::Uuid::new_v4()));
+    let mut error = None;
+    for url in entry.urls(global_mirrors)? {
+        trace!("Downloading {} to {}", url, tmp.display());
+        let res = match download(&url, &tmp, timeout).await {
+            Ok(()) => hash_matches(recorded, &tmp).await.with_context(|| anyhow!("source from {} does not match", url)),
+            Err(e) => Err(e),
+        };
+
+        if tmp.exists() {
+            tokio::fs::remove_file(&tmp).await.with_context(|| anyhow!("Removing {}", tmp.display()))?;
+        }
+
+        match res {
+            Ok(()) => return Ok(url),
+            Err(e) => {
+                debug!("Verifying source {} from {} failed: {:?}", entry.name(), url, e);
+                error = Some(e);
+            }
+        }
+    }
+
+    Err(error.unwrap_or_else(|| anyhow!("no URL to download from")))
+}
+
+async fn download(url: &Url, dest: &Path, timeout: Option<u64>) -> Result<()> {
+    let client_builder = reqwest::Client::builder().redirect(reqwest::redirect::Policy::limited(10));
+    let client_builder = match timeout {
+        Some(to) => client_builder.timeout(std::time::Duration::from_secs(to)),
+        None => client_builder,
+    };
+    let client = client_builder.build().context("Building HTTP client failed")?;
+
+    let response = client
+        .get(url.as_ref())
+        .send()
+        .await
+        .and_then(|resp| resp.error_for_status())
+        .with_context(|| anyhow!("downloading {}", url))?;
+
+    let mut file = tokio::fs::File::create(dest)
+        .await
+        .map(tokio::io::BufWriter::new)
+        .with_context(|| anyhow!("Creating {}", dest.display()))?;
+    let mut stream = response.bytes_stream();
+    while let Some(bytes) = stream.next().await {
+        file.write_all(bytes?.as_ref()).await?;
+    }
+    file.flush().await.map_err(Error::from)
+}
+
+async fn hash_matches(hash: &SourceHash, path: &Path) -> Result<()> {
+    let reader = tokio::fs::File::open(path)
+        .await
+        .map(tokio::io::BufReader::new)
+        .with_context(|| anyhow!("Opening {}", path.display()))?;
+    hash.matches_hash_of(reader).await
+}
@@ -10,6 +10,9 @@
 
 //! Implementation of the 'what_depends' subcommand
 
+use std::collections::BTreeSet;
+use std::collections::HashSet;
+use std::collections::VecDeque;
 use std::io::Write;
 
 use anyhow::Result;
@@ -22,6 +25,7 @@ use resiter::Map;
 
 use crate::commands::util::getbool;
 use crate::config::*;
+use crate::package::Package;
 use crate::package::PackageName;
 use crate::repository::Repository;
 use crate::ui::*;
@@ -32,8 +36,6 @@ pub async fn what_depends(
     config: &Configuration,
     repo: Repository,
 ) -> Result<()> {
-    use filters::failable::filter::FailableFilter;
-
     let print_runtime_deps = getbool(
         matches,
         "dependency_type",
@@ -45,18 +47,16 @@ pub async fn what_depends(
         crate::cli::IDENT_DEPENDENCY_TYPE_BUILD,
     );
 
-    let package_filter = {
-        let name = matches
-            .get_one::<String>("package_name")
-            .map(|s| s.to_owned())
-            .map(PackageName::from)
-            .unwrap();
-
-        crate::util::filters::build_package_filter_by_dependency_name(
-            &name,
-            print_build_deps,
-            print_runtime_deps,
-        )
+    let name = matches
+        .get_one::<String>("package_name")
+        .map(|s| s.to_owned())
+        .map(PackageName::from)
+        .unwrap();
+
+    let packages = if matches.get_flag("transitive") {
+        transitive_dependents(&repo, &name, print_build_deps, print_runtime_deps)?
+    } else {
+        direct_dependents(&repo, &name, print_build_deps, print_runtime_deps)?
     };
 
     let hb = crate::ui::handlebars_for_package_printing(config.package_print_format())?;
@@ -80,23 +80,118 @@ pub async fn what_depends(
         script_highlighting: false,
     };
 
-    let mut i = 0;
-    let iter = repo
-        .packages()
-        .map(|package| package_filter.filter(package).map(|b| (b, package)))
-        .filter_ok(|(b, _)| *b)
-        .map_ok(|tpl| tpl.1)
-        .inspect(|pkg| trace!("Found package: {:?}", pkg))
-        .map_ok(|p| { // poor mans enumerate_ok()
-            i += 1;
-            p.prepare_print(config, &flags, &hb, i)
-        });
-
-    tokio_stream::iter(iter)
-        .map(|pp| pp.and_then(|p| p.into_displayable()))
+    tokio_stream::iter(packages.into_iter().enumerate())
+        .map(|(i, p)| p.prepare_print(config, &flags, &hb, i + 1).into_displayable())
         .try_for_each(|p| {
             let r = writeln!(&mut outlock, "{p}").map_err(anyhow::Error::from);
             futures::future::ready(r)
         })
         .await
 }
+
+/// Find all packages that directly depend on a package named `name`
+fn direct_dependents<'a>(
+    repo: &'a Repository,
+    name: &PackageName,
+    check_build_dep: bool,
+    check_runtime_dep: bool,
+) -> Result<Vec<&'a Package>> {
+    use filters::failable::filter::FailableFilter;
+
+    let package_filter = crate::util::filters::build_package_filter_by_dependency_name(
+        name,
+        check_build_dep,
+        check_runtime_dep,
+    );
+
+    repo.packages()
+        .map(|package| package_filter.filter(package).map(|b| (b, package)))
+        .filter_ok(|(b, _)| *b)
+        .map_ok(|tpl| tpl.1)
+        .inspect(|pkg| trace!("Found package: {:?}", pkg))
+        .collect()
+}
+
+/// Find all packages whose dependency tree eventually contains a package named `name`
+///
+/// The reverse dependencies are searched breadth-first, each package name is only searched once,
+/// so dependency cycles do not result in an endless loop.
+fn transitive_dependents<'a>(
+    repo: &'a Repository,
+    name: &PackageName,
+    check_build_dep: bool,
+    check_runtime_dep: bool,
+) -> Result<Vec<&'a Package>> {
+    let mut searched = HashSet::new();
+    let mut queue = VecDeque::from([name.clone()]);
+    let mut found = BTreeSet::new();
+
+    while let Some(next) = queue.pop_front() {
+        if !searched.insert(next.clone()) {
+            continue;
+        }
+
+        trace!("Searching packages depending on {}", next);
+        for package in direct_dependents(repo, &next, check_build_dep, check_runtime_dep)? {
+            if package.name() != name && found.insert(package) {
+                queue.push_back(package.name().clone());
+            }
+        }
+    }
+
+    Ok(found.into_iter().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::BTreeMap;
+
+    use crate::package::tests::package;
+    use crate::package::tests::pname;
+    use crate::package::tests::pversion;
+    use crate::package::Dependencies;
+    use crate::package::Dependency;
+
+    fn repo_with_deps(deps: &[(&str, &[&str])]) -> Repository {
+        let btree = deps
+            .iter()
+            .map(|(name, runtime_deps)| {
+                let mut pack = package(name, "1", "https://rust-lang.org", "123");
+                let ds = runtime_deps
+                    .iter()
+                    .map(|d| Dependency::from(d.to_string()))
+                    .collect();
+                pack.set_dependencies(Dependencies::with_runtime_dependencies(ds));
+                ((pname(name), pversion("1")), pack)
+            })
+            .collect::<BTreeMap<_, _>>();
+
+        Repository::from(btree)
+    }
+
+    #[test]
+    fn test_transitive_dependents() {
+        // d -> c -> b -> a, e -> a, f is unrelated, b and c depend on each other
+        let repo = repo_with_deps(&[
+            ("a", &[]),
+            ("b", &["a =1", "c =1"]),
+            ("c", &["b =1"]),
+            ("d", &["c =1"]),
+            ("e", &["a =1"]),
+            ("f", &[]),
+        ]);
+
+        let direct = direct_dependents(&repo, &pname("a"), true, true).unwrap();
+        let direct = direct.iter().map(|p| p.name().as_str()).collect::<Vec<_>>();
+        assert_eq!(direct, vec!["b", "e"]);
+
+        let all = transitive_dependents(&repo, &pname("a"), true, true).unwrap();
+        let all = all.iter().map(|p| p.name().as_str()).collect::<Vec<_>>();
+        assert_eq!(all, vec!["b", "c", "d", "e"]);
+
+        let none = transitive_dependents(&repo, &pname("a"), true, false).unwrap();
+        assert!(none.is_empty());
+    }
+}
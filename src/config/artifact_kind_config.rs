@@ -0,0 +1,23 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+use getset::Getters;
+use serde::Deserialize;
+
+/// A rule which kind the artifacts collected from a container are
+#[derive(Debug, Getters, Deserialize)]
+pub struct ArtifactKindRule {
+    /// Regex that is matched against the path of the artifact inside the store
+    #[getset(get = "pub")]
+    pattern: String,
+
+    /// The kind of the matching artifacts, e.g. "debug" or "report"
+    #[getset(get = "pub")]
+    kind: String,
+}
@@ -0,0 +1,30 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use getset::Getters;
+use serde::Deserialize;
+
+use crate::filestore::Compression;
+
+/// A rule which artifacts should be compressed when they are written to the staging store
+#[derive(Debug, Getters, Deserialize)]
+pub struct ArtifactCompressionRule {
+    /// Regex that is matched against the path of the artifact inside the store
+    #[getset(get = "pub")]
+    pattern: String,
+
+    /// The compression algorithm to use
+    #[getset(get = "pub")]
+    algorithm: Compression,
+
+    /// The compression level, algorithm default if not set
+    #[getset(get = "pub")]
+    level: Option<i32>,
+}
@@ -8,9 +8,14 @@
 // SPDX-License-Identifier: EPL-2.0
 //
 
+use std::collections::HashMap;
 use std::ops::Deref;
 
+use crate::config::Endpoint;
+use crate::config::EndpointName;
 use crate::config::NotValidatedConfiguration;
+use crate::util::docker::ContainerImage;
+use crate::util::docker::ImageName;
 
 /// A valid configuration (validated via NotValidatedConfiguration::validate())
 #[derive(Debug)]
@@ -18,6 +23,19 @@ pub struct Configuration {
     pub(in crate::config) inner: NotValidatedConfiguration,
 }
 
+impl Configuration {
+    /// Use endpoints and images that are defined in the database in addition to the configured ones
+    pub fn with_database_definitions(
+        mut self,
+        endpoints: HashMap<EndpointName, Endpoint>,
+        allowed_images: Vec<ContainerImage>,
+        denied_images: &[ImageName],
+    ) -> Self {
+        self.inner.docker.merge_definitions(endpoints, allowed_images, denied_images);
+        self
+    }
+}
+
 impl Deref for Configuration {
     type Target = NotValidatedConfiguration;
 
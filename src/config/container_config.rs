@@ -12,6 +12,8 @@ use getset::CopyGetters;
 use getset::Getters;
 use serde::Deserialize;
 
+use crate::config::StallDetectionConfig;
+use crate::util::docker::ContainerUser;
 use crate::util::EnvironmentVariableName;
 
 /// The configuration for the containers
@@ -33,4 +35,14 @@ pub struct ContainerConfig {
     /// Pass the current git hash to the container
     #[getset(get = "pub")]
     git_commit_hash: Option<EnvironmentVariableName>,
+
+    /// The user the containers run as, root if not set
+    #[serde(default)]
+    #[getset(get_copy = "pub")]
+    user: Option<ContainerUser>,
+
+    /// The detection of stalled jobs
+    #[serde(default)]
+    #[getset(get = "pub")]
+    stall_detection: StallDetectionConfig,
 }
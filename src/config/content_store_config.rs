@@ -0,0 +1,29 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+use std::path::PathBuf;
+
+use getset::CopyGetters;
+use getset::Getters;
+use serde::Deserialize;
+
+use crate::filestore::LinkMethod;
+
+/// The configuration of the content-addressed store artifacts are deduplicated in
+#[derive(Clone, Debug, Getters, CopyGetters, Deserialize)]
+pub struct ContentStoreConfig {
+    /// The directory the objects are stored in
+    #[getset(get = "pub")]
+    root: PathBuf,
+
+    /// How the files in the stores link to the objects
+    #[serde(default)]
+    #[getset(get_copy = "pub")]
+    link: LinkMethod,
+}
@@ -15,7 +15,9 @@ use serde::Deserialize;
 
 use crate::config::Endpoint;
 use crate::config::EndpointName;
+use crate::config::RegistryConfig;
 use crate::util::docker::ContainerImage;
+use crate::util::docker::ImageName;
 
 /// Configuration of the Docker daemon interfacing functionality
 #[derive(Debug, Getters, CopyGetters, Deserialize)]
@@ -47,9 +49,99 @@ pub struct DockerConfig {
     #[getset(get_copy = "pub")]
     verify_images_present: bool,
 
+    /// Whether images that are missing on an endpoint should be pulled instead of failing the
+    /// check whether the images are present
+    #[serde(default)]
+    #[getset(get_copy = "pub")]
+    pull_missing_images: bool,
+
+    /// The credentials for the registries images are pulled from, by the hostname of the registry
+    #[serde(default)]
+    #[getset(get = "pub")]
+    registries: HashMap<String, RegistryConfig>,
+
     #[getset(get = "pub")]
     images: Vec<ContainerImage>,
 
     #[getset(get = "pub")]
     endpoints: HashMap<EndpointName, Endpoint>,
+
+    /// Whether endpoints and images that are defined in the database (via `butido endpoint add`
+    /// and `butido image allow/deny`) should be used in addition to the ones configured here
+    #[serde(default)]
+    #[getset(get_copy = "pub")]
+    database_definitions: bool,
+
+    /// Whether the jobs on the endpoints are accounted in the database, so that multiple butido
+    /// instances that share the endpoints and the database do not run more jobs on an endpoint
+    /// than its maximum number of jobs
+    #[serde(default)]
+    #[getset(get_copy = "pub")]
+    database_capacity_accounting: bool,
+
+    /// A command that prints the endpoints of the build machines as JSON, for
+    /// `butido endpoint discover`
+    #[serde(default)]
+    #[getset(get = "pub")]
+    endpoint_inventory_command: Option<String>,
+}
+
+impl DockerConfig {
+    /// Merge endpoints and images that are defined in the database into this configuration
+    ///
+    /// Endpoints from the database replace configured endpoints with the same name. Denied images
+    /// are removed, even if they are configured.
+    pub(in crate::config) fn merge_definitions(
+        &mut self,
+        endpoints: HashMap<EndpointName, Endpoint>,
+        allowed_images: Vec<ContainerImage>,
+        denied_images: &[ImageName],
+    ) {
+        self.endpoints.extend(endpoints);
+
+        for image in allowed_images {
+            if !self.images.iter().any(|configured| configured.name == image.name) {
+                self.images.push(image);
+            }
+        }
+        self.images.retain(|image| !denied_images.contains(&image.name));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::EndpointType;
+
+    #[test]
+    fn test_merge_definitions() {
+        let mut config: DockerConfig = toml::from_str(r#"
+            verify_images_present = true
+            images = [
+                { name = "debian:bullseye", short_name = "deb11" },
+                { name = "debian:buster", short_name = "deb10" },
+            ]
+
+            [endpoints.a]
+            uri = "http://0.0.0.0:8095"
+            endpoint_type = "http"
+            maxjobs = 1
+        "#).unwrap();
+
+        let endpoints = HashMap::from([(
+            EndpointName::from(String::from("b")),
+            Endpoint::new(String::from("http://0.0.0.0:8096"), EndpointType::Http, 2, None),
+        )]);
+        let allowed = vec![ContainerImage {
+            name: ImageName::from("fedora:38"),
+            short_name: ImageName::from("f38"),
+            paths: Default::default(),
+        }];
+        let denied = vec![ImageName::from("debian:buster")];
+        config.merge_definitions(endpoints, allowed, &denied);
+
+        assert_eq!(config.endpoints().len(), 2);
+        let images = config.images().iter().map(|img| img.name.as_ref()).collect::<Vec<_>>();
+        assert_eq!(images, vec!["debian:bullseye", "fedora:38"]);
+    }
 }
@@ -8,6 +8,8 @@
 // SPDX-License-Identifier: EPL-2.0
 //
 
+use std::path::PathBuf;
+
 use getset::{CopyGetters, Getters};
 use serde::Deserialize;
 
@@ -54,6 +56,123 @@ pub struct Endpoint {
     /// Duration length of timeout for connecting endpoint
     #[getset(get = "pub")]
     timeout: Option<u64>,
+
+    /// Size of the in-memory cache for artifacts built on this endpoint (e.g. "512 MiB")
+    ///
+    /// Jobs get the artifacts of their dependencies from the cache if the dependencies were built
+    /// on the same endpoint, instead of reading them back from the staging store.
+    #[getset(get = "pub")]
+    artifact_cache_size: Option<String>,
+
+    /// The maximum rate outputs are copied from the containers of this endpoint with, per second
+    /// (e.g. "10 MiB")
+    #[serde(default)]
+    #[getset(get = "pub")]
+    transfer_rate_limit: Option<String>,
+
+    /// What to do if the docker version or docker API version of the endpoint does not match
+    #[serde(default)]
+    #[getset(get_copy = "pub")]
+    version_check: VersionCheckPolicy,
+
+    /// What to do with the container of a job after the job finished
+    #[serde(default)]
+    #[getset(get_copy = "pub")]
+    container_cleanup: ContainerCleanupPolicy,
+
+    /// The settings for endpoints of type "kubernetes"
+    #[serde(default)]
+    #[getset(get = "pub")]
+    kubernetes: KubernetesConfig,
+}
+
+impl Endpoint {
+    /// Create an endpoint configuration that is not loaded from the configuration file
+    ///
+    /// All settings that cannot be passed are set to their defaults.
+    pub fn new(uri: String, endpoint_type: EndpointType, maxjobs: usize, network_mode: Option<String>) -> Self {
+        Endpoint {
+            uri,
+            endpoint_type,
+            maxjobs,
+            network_mode,
+            timeout: None,
+            artifact_cache_size: None,
+            transfer_rate_limit: None,
+            version_check: VersionCheckPolicy::default(),
+            container_cleanup: ContainerCleanupPolicy::default(),
+            kubernetes: KubernetesConfig::default(),
+        }
+    }
+}
+
+/// Configuration of the cluster an endpoint of type "kubernetes" runs its jobs in
+#[derive(Clone, Debug, Default, Getters, Deserialize)]
+pub struct KubernetesConfig {
+    /// The kubeconfig file, the default kubeconfig (`$KUBECONFIG` or `~/.kube/config`) if not set
+    #[getset(get = "pub")]
+    kubeconfig: Option<PathBuf>,
+
+    /// The context from the kubeconfig, the current context if not set
+    #[getset(get = "pub")]
+    context: Option<String>,
+
+    /// The namespace the pods of the jobs are created in, the namespace of the context if not set
+    #[getset(get = "pub")]
+    namespace: Option<String>,
+
+    /// The CPU limit of the pod of a job (e.g. "2" or "500m")
+    #[getset(get = "pub")]
+    cpu_limit: Option<String>,
+
+    /// The memory limit of the pod of a job (e.g. "4Gi")
+    #[getset(get = "pub")]
+    memory_limit: Option<String>,
+}
+
+/// What to do if a version check for an endpoint fails
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+pub enum VersionCheckPolicy {
+    /// Fail the setup of the endpoint
+    #[serde(rename = "error")]
+    Error,
+
+    /// Print a warning and use the endpoint anyways
+    #[default]
+    #[serde(rename = "warn")]
+    Warn,
+
+    /// Do not check the versions at all
+    #[serde(rename = "ignore")]
+    Ignore,
+}
+
+/// What to do with the container of a job after the job finished
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+pub enum ContainerCleanupPolicy {
+    /// Remove the container, regardless of the outcome of the job
+    #[serde(rename = "remove-always")]
+    RemoveAlways,
+
+    /// Remove the container of a successful job, keep the container of a failed job for debugging
+    #[serde(rename = "keep-on-failure")]
+    KeepOnFailure,
+
+    /// Keep all containers
+    #[default]
+    #[serde(rename = "keep-always")]
+    KeepAlways,
+}
+
+impl ContainerCleanupPolicy {
+    /// Whether the container of a job with the passed outcome should be removed
+    pub fn removes(&self, success: bool) -> bool {
+        match self {
+            ContainerCleanupPolicy::RemoveAlways => true,
+            ContainerCleanupPolicy::KeepOnFailure => success,
+            ContainerCleanupPolicy::KeepAlways => false,
+        }
+    }
 }
 
 /// The type of an endpoint
@@ -63,5 +182,72 @@ pub enum EndpointType {
     Socket,
     #[serde(rename = "http")]
     Http,
+    #[serde(rename = "kubernetes")]
+    Kubernetes,
+    #[serde(rename = "local")]
+    Local,
+}
+
+impl std::str::FromStr for EndpointType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "socket" => Ok(EndpointType::Socket),
+            "http" => Ok(EndpointType::Http),
+            "kubernetes" => Ok(EndpointType::Kubernetes),
+            "local" => Ok(EndpointType::Local),
+            other => Err(anyhow::anyhow!("Unknown endpoint type: {}", other)),
+        }
+    }
 }
 
+impl std::fmt::Display for EndpointType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        match self {
+            EndpointType::Socket => write!(f, "socket"),
+            EndpointType::Http => write!(f, "http"),
+            EndpointType::Kubernetes => write!(f, "kubernetes"),
+            EndpointType::Local => write!(f, "local"),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_container_cleanup_policy() {
+        let ep: Endpoint = toml::from_str(r#"
+            uri = "http://0.0.0.0:8095"
+            endpoint_type = "http"
+            maxjobs = 1
+            container_cleanup = "keep-on-failure"
+        "#).unwrap();
+
+        assert_eq!(ep.container_cleanup(), ContainerCleanupPolicy::KeepOnFailure);
+        assert!(ep.container_cleanup().removes(true));
+        assert!(!ep.container_cleanup().removes(false));
+        assert!(ContainerCleanupPolicy::RemoveAlways.removes(false));
+        assert!(!ContainerCleanupPolicy::default().removes(true));
+    }
+
+    #[test]
+    fn test_kubernetes_endpoint() {
+        let ep: Endpoint = toml::from_str(r#"
+            uri = "https://k8s.example.com:6443"
+            endpoint_type = "kubernetes"
+            maxjobs = 4
+            [kubernetes]
+            context = "build-cluster"
+            memory_limit = "4Gi"
+        "#).unwrap();
+
+        assert_eq!(*ep.endpoint_type(), EndpointType::Kubernetes);
+        assert_eq!(ep.kubernetes().context().as_deref(), Some("build-cluster"));
+        assert_eq!(ep.kubernetes().memory_limit().as_deref(), Some("4Gi"));
+        assert!(ep.kubernetes().kubeconfig().is_none());
+    }
+}
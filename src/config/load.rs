@@ -0,0 +1,150 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Loading of the configuration files
+//!
+//! The configuration is merged from these sources, later ones override earlier ones:
+//!
+//! 1. The system-wide configuration file (`/etc/butido/config.toml`)
+//! 2. `config.toml` in the repository (required)
+//! 3. The configuration file of the user (`$XDG_CONFIG_HOME/butido/config.toml`)
+//! 4. The repository-local configuration file (`.butido.toml` in the repository)
+//! 5. The selected profile (`[profiles.<name>]` in any of the files above)
+//! 6. Environment variables with the prefix `BUTIDO_`
+//!
+//! The name of an environment variable is the path of the setting in upper case, with `__` as
+//! separator for nested keys, e.g. `BUTIDO_DATABASE_PASSWORD` for `database_password` or
+//! `BUTIDO_DOCKER__ENDPOINTS__TESTHOSTNAME__URI` for `docker.endpoints.testhostname.uri`.
+
+use std::path::Path;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use tracing::debug;
+
+use crate::config::NotValidatedConfiguration;
+
+/// The system-wide configuration file
+const SYSTEM_CONFIG_FILE: &str = "/etc/butido/config.toml";
+
+/// The repository-local configuration file, which is meant to not be committed
+const REPO_LOCAL_CONFIG_FILE: &str = ".butido.toml";
+
+/// The prefix of environment variables that override settings
+const ENV_PREFIX: &str = "BUTIDO";
+
+/// The separator for nested keys in the names of environment variables
+const ENV_SEPARATOR: &str = "__";
+
+/// Load the configuration for the repository at `repo_path`, with the settings of `profile` applied
+pub fn load(repo_path: &Path, profile: Option<&str>) -> Result<NotValidatedConfiguration> {
+    let mut config = ::config::Config::default();
+    config.merge(::config::File::from(Path::new(SYSTEM_CONFIG_FILE)).required(false))
+        .with_context(|| anyhow!("Failed to load system configuration from {}", SYSTEM_CONFIG_FILE))?;
+
+    config.merge(::config::File::from(repo_path.join("config.toml")).required(true))
+        .context("Failed to load config.toml from repository")?;
+
+    {
+        let xdg = xdg::BaseDirectories::with_prefix("butido")?;
+        let xdg_config_file = xdg.find_config_file("config.toml");
+        if let Some(xdg_config) = xdg_config_file {
+            debug!("Configuration file found with XDG: {}", xdg_config.display());
+            config.merge(::config::File::from(xdg_config).required(false))
+                .context("Failed to load config.toml from XDG configuration directory")?;
+        } else {
+            debug!("No configuration file found with XDG: {}", xdg.get_config_home().display());
+        }
+    }
+
+    config.merge(::config::File::from(repo_path.join(REPO_LOCAL_CONFIG_FILE)).required(false))
+        .with_context(|| anyhow!("Failed to load {} from repository", REPO_LOCAL_CONFIG_FILE))?;
+
+    if let Some(profile) = profile {
+        apply_profile(&mut config, profile)?;
+    }
+
+    config.merge(environment())
+        .context("Failed to load configuration from the environment")?;
+
+    config.try_into::<NotValidatedConfiguration>()
+        .context("Failed to load Configuration object")
+}
+
+/// The environment variables that override settings
+///
+/// Empty variables are treated as if they were not set.
+fn environment() -> ::config::Environment {
+    ::config::Environment::with_prefix(ENV_PREFIX)
+        .separator(ENV_SEPARATOR)
+        .ignore_empty(true)
+}
+
+/// Merge the settings of the profile `[profiles.<name>]` into the configuration
+fn apply_profile(config: &mut ::config::Config, profile: &str) -> Result<()> {
+    debug!("Applying configuration profile: {}", profile);
+    let settings = config.get_table(&format!("profiles.{profile}"))
+        .with_context(|| anyhow!("Configuration profile not found: {}", profile))?;
+
+    let mut profile_config = ::config::Config::default();
+    for (key, value) in settings {
+        profile_config.set(&key, value)?;
+    }
+
+    config.merge(profile_config)
+        .with_context(|| anyhow!("Failed to apply configuration profile {}", profile))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_profile() {
+        let mut config = ::config::Config::default();
+        config.merge(::config::File::from_str(r#"
+            log_dir = "/tmp/logs"
+            [docker.endpoints.a]
+            uri = "http://0.0.0.0:8095"
+            maxjobs = 1
+
+            [profiles.team.docker.endpoints.a]
+            uri = "http://10.0.0.1:8095"
+        "#, ::config::FileFormat::Toml)).unwrap();
+
+        apply_profile(&mut config, "team").unwrap();
+        assert_eq!(config.get_str("docker.endpoints.a.uri").unwrap(), "http://10.0.0.1:8095");
+        assert_eq!(config.get_int("docker.endpoints.a.maxjobs").unwrap(), 1);
+        assert_eq!(config.get_str("log_dir").unwrap(), "/tmp/logs");
+
+        assert!(apply_profile(&mut config, "unknown").is_err());
+    }
+
+    #[test]
+    fn test_environment_overrides() {
+        let mut config = ::config::Config::default();
+        config.merge(::config::File::from_str(r#"
+            database_password = "password"
+            [docker.endpoints.envtest]
+            uri = "http://0.0.0.0:8095"
+            maxjobs = 1
+        "#, ::config::FileFormat::Toml)).unwrap();
+
+        std::env::set_var("BUTIDO_DOCKER__ENDPOINTS__ENVTEST__URI", "http://10.0.0.1:8095");
+        std::env::set_var("BUTIDO_DOCKER__ENDPOINTS__ENVTEST__MAXJOBS", "4");
+        config.merge(environment()).unwrap();
+
+        assert_eq!(config.get_str("docker.endpoints.envtest.uri").unwrap(), "http://10.0.0.1:8095");
+        assert_eq!(config.get_int("docker.endpoints.envtest.maxjobs").unwrap(), 4);
+        assert_eq!(config.get_str("database_password").unwrap(), "password");
+    }
+}
@@ -20,19 +20,52 @@
 //! that is not possible to do with TOML itself.
 //!
 
+mod artifact_kind_config;
+pub use artifact_kind_config::*;
+
+mod compression_config;
+pub use compression_config::*;
+
 mod configuration;
 pub use configuration::*;
 
 mod container_config;
 pub use container_config::*;
 
+mod content_store_config;
+pub use content_store_config::*;
+
 mod docker_config;
 pub use docker_config::*;
 
 mod endpoint_config;
 pub use endpoint_config::*;
 
+mod load;
+pub use load::*;
+
 mod not_validated;
 pub use not_validated::*;
 
+mod notification_config;
+pub use notification_config::*;
+
+mod progress_config;
+pub use progress_config::*;
+
+mod registry_config;
+pub use registry_config::*;
+
+mod release_signing_config;
+pub use release_signing_config::*;
+
+mod shellcheck_config;
+pub use shellcheck_config::*;
+
+mod stall_detection_config;
+pub use stall_detection_config::*;
+
+mod store_backend_config;
+pub use store_backend_config::*;
+
 mod util;
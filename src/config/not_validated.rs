@@ -9,16 +9,27 @@
 //
 
 use anyhow::anyhow;
-use anyhow::Context;
+use anyhow::Error;
 use anyhow::Result;
 use getset::Getters;
 use serde::Deserialize;
+use std::collections::HashMap;
 use std::path::PathBuf;
+use url::Url;
 
 use crate::config::util::*;
+use crate::config::ArtifactCompressionRule;
+use crate::config::ArtifactKindRule;
 use crate::config::Configuration;
 use crate::config::ContainerConfig;
+use crate::config::ContentStoreConfig;
 use crate::config::DockerConfig;
+use crate::config::NotificationSinkConfig;
+use crate::config::ProgressConfig;
+use crate::config::ReleaseSigningConfig;
+use crate::config::ShellcheckConfig;
+use crate::config::StoreBackendConfig;
+use crate::log::LogEncoding;
 use crate::package::PhaseName;
 
 /// The configuration that is loaded from the filesystem
@@ -37,17 +48,65 @@ pub struct NotValidatedConfiguration {
     #[getset(get = "pub")]
     log_dir: PathBuf,
 
+    /// The maximum size of a job log in the database, in bytes
+    ///
+    /// Larger logs are written to a compressed file in `log_dir`, only the first and last
+    /// `log_excerpt_lines` lines are stored in the database.
+    #[getset(get = "pub")]
+    log_max_size: Option<usize>,
+
+    /// The number of lines from the beginning and end of an oversized log that are stored in the
+    /// database
+    #[serde(default = "default_log_excerpt_lines")]
+    #[getset(get = "pub")]
+    log_excerpt_lines: usize,
+
+    /// The compression job logs are stored with in the database, if any
+    #[getset(get = "pub")]
+    log_compression: Option<LogEncoding>,
+
+    /// The directory job input manifests are written to (content-addressed), if any
+    #[getset(get = "pub")]
+    input_manifest_store: Option<PathBuf>,
+
+    /// The file a machine-readable summary of each submit is written to, if any
+    #[getset(get = "pub")]
+    submit_summary_file: Option<PathBuf>,
+
+    /// The file the Prometheus metrics of each build are written to, if any
+    #[getset(get = "pub")]
+    metrics_textfile: Option<PathBuf>,
+
+    /// The sinks notifications about failed submits are sent to
+    #[serde(default)]
+    #[getset(get = "pub")]
+    notifications: Vec<NotificationSinkConfig>,
+
+    /// Path to a file with rules to categorize failed jobs by patterns in their logs
+    #[getset(get = "pub")]
+    triage_rules: Option<PathBuf>,
+
     /// Whether the script interpolation feature should be struct, i.e. missing variables result in
     /// a failing interpolation. This should be `true` for most users.
     #[serde(default = "default_strict_script_interpolation")]
     #[getset(get = "pub")]
     strict_script_interpolation: bool,
 
+    /// Variables that can be used in the templates in package definitions
+    #[serde(default)]
+    #[getset(get = "pub")]
+    package_variables: HashMap<String, String>,
+
     /// The format of the progress bars
     #[serde(default = "default_progress_format")]
     #[getset(get = "pub")]
     progress_format: String,
 
+    /// The style of the progress bars
+    #[serde(default)]
+    #[getset(get = "pub")]
+    progress: ProgressConfig,
+
     /// The format of the spinners in the CLI
     #[serde(default = "default_spinner_format")]
     #[getset(get = "pub")]
@@ -66,14 +125,28 @@ pub struct NotValidatedConfiguration {
     #[getset(get = "pub")]
     build_error_lines: usize,
 
+    /// The maximum number of jobs of a submit that are scheduled on the endpoints at the same
+    /// time, regardless of the `maxjobs` setting of the endpoints
+    #[getset(get = "pub")]
+    build_max_parallel: Option<usize>,
+
     /// The theme used to highlight scripts when printing them to the CLI
     #[getset(get = "pub")]
     script_highlight_theme: Option<String>,
 
+    /// A directory with additional `.tmTheme` files that can be used as script highlighting theme
+    #[getset(get = "pub")]
+    script_highlight_theme_dir: Option<PathBuf>,
+
     /// The linter executable that is used to lint packaging scripts
     #[getset(get = "pub")]
     script_linter: Option<PathBuf>,
 
+    /// How the packaging scripts are checked with shellcheck
+    #[serde(default)]
+    #[getset(get = "pub")]
+    shellcheck: ShellcheckConfig,
+
     /// The shebang that is added at the very beginning of the package scripts
     #[serde(default = "default_script_shebang")]
     #[getset(get = "pub")]
@@ -89,6 +162,40 @@ pub struct NotValidatedConfiguration {
     #[getset(get = "pub")]
     release_stores: Vec<String>,
 
+    /// The order in which the release stores are searched for artifacts that can be reused
+    ///
+    /// If not set, the release stores are searched in the order of `release_stores`.
+    /// Release stores that are not listed here are not searched.
+    #[getset(get = "pub")]
+    release_store_search_order: Option<Vec<String>>,
+
+    /// The template for the paths artifacts are released to, relative to the release store
+    ///
+    /// If not set, artifacts are released to the path they have in the staging store.
+    #[getset(get = "pub")]
+    release_path_template: Option<String>,
+
+    /// Remote backends the release stores are mirrored to, by name of the release store
+    #[serde(default)]
+    #[getset(get = "pub")]
+    release_store_backends: HashMap<String, StoreBackendConfig>,
+
+    /// Sign released artifacts with GPG
+    #[getset(get = "pub")]
+    release_signing: Option<ReleaseSigningConfig>,
+
+    /// The maximum size of a single artifact a build container may produce, in bytes
+    #[getset(get = "pub")]
+    artifact_max_size: Option<u64>,
+
+    /// The maximum number of artifacts a build container may produce
+    #[getset(get = "pub")]
+    artifact_max_files: Option<usize>,
+
+    /// Deduplicate the artifacts in the staging and release stores in a content-addressed store
+    #[getset(get = "pub")]
+    content_store: Option<ContentStoreConfig>,
+
     /// The directory where intermediate ("staging") artifacts are stored.
     /// This is used as a root directory, a UUID-named directory will be added below this, using
     /// the UUID of the submit
@@ -96,11 +203,44 @@ pub struct NotValidatedConfiguration {
     #[getset(get = "pub")]
     staging_directory: PathBuf,
 
+    /// Whether the staging directories of submits are namespaced per submitting user
+    #[serde(default)]
+    #[getset(get = "pub")]
+    staging_per_user: bool,
+
+    /// The name of the user that is recorded on submits and releases
+    ///
+    /// If not set, the system user ($USER) is used.
+    user: Option<String>,
+
+    /// Remote backend the staging store is mirrored to
+    #[getset(get = "pub")]
+    staging_backend: Option<StoreBackendConfig>,
+
+    /// Rules for transparently compressing artifacts when they are written to the staging store
+    #[serde(default)]
+    #[getset(get = "pub")]
+    artifact_compression: Vec<ArtifactCompressionRule>,
+
+    /// Rules for classifying the artifacts collected from containers
+    #[serde(default)]
+    #[getset(get = "pub")]
+    artifact_kinds: Vec<ArtifactKindRule>,
+
     /// Where the sources are cached
     #[serde(rename = "source_cache")]
     #[getset(get = "pub")]
     source_cache_root: PathBuf,
 
+    /// Mirrors that are tried (in order) if downloading a source from its URLs fails
+    #[serde(default)]
+    #[getset(get = "pub")]
+    source_mirrors: Vec<Url>,
+
+    /// The number of sources that are downloaded in parallel by `butido source download`
+    #[getset(get = "pub")]
+    source_download_parallelism: Option<usize>,
+
     /// The hostname used to connect to the database
     #[getset(get = "pub")]
     #[serde(rename = "database_host")]
@@ -132,7 +272,7 @@ pub struct NotValidatedConfiguration {
     database_connection_timeout: Option<u16>,
 
     #[getset(get = "pub")]
-    docker: DockerConfig,
+    pub(in crate::config) docker: DockerConfig,
 
     /// The configuration for the containers
     #[getset(get = "pub")]
@@ -144,25 +284,41 @@ pub struct NotValidatedConfiguration {
 }
 
 impl NotValidatedConfiguration {
-    /// Validate the NotValidatedConfiguration object and make it into a Configuration object, if
-    /// validation succeeds
-    ///
-    /// This function does sanity-checking on the configuration values.
-    /// It fails with the appropriate error message if a setting is bogus.
-    pub fn validate(self) -> Result<Configuration> {
-        let crate_version = semver::Version::parse(env!("CARGO_PKG_VERSION"))
-            .context("Parsing version of crate (CARGO_PKG_VERSION) into semver::Version object")?;
+    /// The user that is recorded on submits and releases, either configured or the system user
+    pub fn submitter(&self) -> Option<String> {
+        self.user.clone().or_else(|| std::env::var("USER").ok())
+    }
 
-        if !self.compatibility.matches(&crate_version) {
-            return Err(anyhow!(
-                "Configuration is not compatible to butido {}",
-                crate_version
-            ));
+    /// The directory the staging directories of the submits of `user` are located in
+    pub fn staging_directory_of(&self, user: Option<&str>) -> PathBuf {
+        match user {
+            Some(user) if self.staging_per_user => self.staging_directory.join(user),
+            _ => self.staging_directory.clone(),
+        }
+    }
+
+    /// Check the configuration values for problems
+    ///
+    /// Unlike `validate()`, this does not stop at the first bogus setting, but returns an error
+    /// for every problem that was found. An empty list means the configuration is valid.
+    pub fn problems(&self) -> Vec<Error> {
+        let mut problems = vec![];
+
+        match semver::Version::parse(env!("CARGO_PKG_VERSION")) {
+            Ok(crate_version) => if !self.compatibility.matches(&crate_version) {
+                problems.push(anyhow!(
+                    "Configuration is not compatible to butido {}",
+                    crate_version
+                ));
+            },
+            Err(e) => problems.push({
+                Error::from(e).context("Parsing version of crate (CARGO_PKG_VERSION) into semver::Version object")
+            }),
         }
 
         // Error if staging_directory is not a directory
         if !self.staging_directory.is_dir() {
-            return Err(anyhow!(
+            problems.push(anyhow!(
                 "Not a directory: staging = {}",
                 self.staging_directory.display()
             ));
@@ -170,47 +326,109 @@ impl NotValidatedConfiguration {
 
         // Error if releases_directory is not a directory
         if !self.releases_directory.is_dir() {
-            return Err(anyhow!(
+            problems.push(anyhow!(
                 "Not a directory: releases = {}",
                 self.releases_directory.display()
             ));
         }
 
         if self.release_stores.is_empty() {
-            return Err(anyhow!("You need at least one release store in 'release_stores'"))
+            problems.push(anyhow!("You need at least one release store in 'release_stores'"))
+        }
+
+        if let Some(unknown) = self
+            .release_store_search_order
+            .iter()
+            .flatten()
+            .find(|name| !self.release_stores.contains(name))
+        {
+            problems.push(anyhow!("Unknown release store in 'release_store_search_order': {}", unknown))
+        }
+
+        if let Some(unknown) = self
+            .release_store_backends
+            .keys()
+            .find(|name| !self.release_stores.contains(name))
+        {
+            problems.push(anyhow!("Unknown release store in 'release_store_backends': {}", unknown))
         }
 
         // Error if source_cache_root is not a directory
         if !self.source_cache_root.is_dir() {
-            return Err(anyhow!(
+            problems.push(anyhow!(
                 "Not a directory: releases = {}",
                 self.source_cache_root.display()
             ));
         }
 
+        // Error if the release path template cannot be parsed
+        if let Err(e) = crate::filestore::ReleaseLayout::from_template(self.release_path_template.as_deref()) {
+            problems.push(e);
+        }
+
+        // Error if an artifact kind pattern is not a valid regex
+        if let Err(e) = crate::filestore::ArtifactKindRules::from_config(&self.artifact_kinds) {
+            problems.push(e);
+        }
+
+        // Error if an artifact compression pattern is not a valid regex
+        if let Err(e) = crate::filestore::CompressionRules::from_config(&self.artifact_compression) {
+            problems.push(e);
+        }
+
+        // Error if a notification sink has no recipients
+        for sink in self.notifications.iter() {
+            match sink {
+                NotificationSinkConfig::Smtp(smtp) => if smtp.recipients().is_empty() {
+                    problems.push(anyhow!("No recipients for notifications via {}", smtp.server()));
+                },
+            }
+        }
+
+        // Error if the credentials of a registry are ambiguous or incomplete
+        for (registry, registry_config) in self.docker.registries().iter() {
+            problems.extend(registry_config.problem(registry));
+        }
+
+        // Error if an image uses relative paths inside the container
+        for image in self.docker.images().iter() {
+            for path in image.paths.relative_paths() {
+                problems.push(anyhow!("Path in container for image {} is not absolute: {}", image.name, path.display()));
+            }
+            for category in image.paths.invalid_output_categories() {
+                problems.push(anyhow!("Invalid name of additional output for image {}: {}", image.name, category));
+            }
+        }
+
+        problems.extend(self.progress.problem());
+        problems.extend(self.containers.stall_detection().problem());
+
         // Error if there are no phases configured
         if self.available_phases.is_empty() {
-            return Err(anyhow!("No phases configured"));
+            problems.push(anyhow!("No phases configured"));
         }
 
         // Error if script highlighting theme is not valid
-        if let Some(configured_theme) = self.script_highlight_theme.as_ref() {
-            let allowed_theme_present = [
-                // from syntect
-                "base16-ocean.dark",
-                "base16-eighties.dark",
-                "base16-mocha.dark",
-                "base16-ocean.light",
-                "InspiredGitHub",
-                "Solarized (dark)",
-                "Solarized (light)",
-            ]
-            .iter()
-            .any(|allowed_theme| configured_theme == *allowed_theme);
+        match crate::package::load_highlight_themes(self.script_highlight_theme_dir.as_deref()) {
+            Ok(themes) => if let Some(configured_theme) = self.script_highlight_theme.as_ref() {
+                if !themes.themes.contains_key(configured_theme) {
+                    problems.push(anyhow!("Theme not known: {}", configured_theme));
+                }
+            },
+            Err(e) => problems.push(e),
+        }
 
-            if !allowed_theme_present {
-                return Err(anyhow!("Theme not known: {}", configured_theme));
-            }
+        problems
+    }
+
+    /// Validate the NotValidatedConfiguration object and make it into a Configuration object, if
+    /// validation succeeds
+    ///
+    /// This function does sanity-checking on the configuration values.
+    /// It fails with the appropriate error message if a setting is bogus.
+    pub fn validate(self) -> Result<Configuration> {
+        if let Some(problem) = self.problems().into_iter().next() {
+            return Err(problem)
         }
 
         Ok(Configuration { inner: self })
@@ -0,0 +1,74 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use getset::CopyGetters;
+use getset::Getters;
+use serde::Deserialize;
+
+/// A sink notifications about submits are sent to
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type")]
+pub enum NotificationSinkConfig {
+    #[serde(rename = "smtp")]
+    Smtp(SmtpSinkConfig),
+}
+
+/// Configuration of a mail server notification mails are sent with
+#[derive(Clone, Debug, Getters, CopyGetters, Deserialize)]
+pub struct SmtpSinkConfig {
+    /// The hostname of the mail server
+    #[getset(get = "pub")]
+    server: String,
+
+    #[serde(default = "default_smtp_port")]
+    #[getset(get_copy = "pub")]
+    port: u16,
+
+    /// How the connection to the mail server is encrypted
+    #[serde(default)]
+    #[getset(get_copy = "pub")]
+    tls: SmtpTls,
+
+    /// The user to authenticate as, no authentication is done if not set
+    #[getset(get = "pub")]
+    username: Option<String>,
+
+    #[getset(get = "pub")]
+    password: Option<String>,
+
+    /// The sender address of the mails
+    #[getset(get = "pub")]
+    from: String,
+
+    /// The addresses the mails are sent to
+    #[getset(get = "pub")]
+    recipients: Vec<String>,
+}
+
+/// How the connection to a mail server is encrypted
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+pub enum SmtpTls {
+    /// Plain connection, upgraded with STARTTLS
+    #[default]
+    #[serde(rename = "starttls")]
+    StartTls,
+
+    /// TLS from the start of the connection
+    #[serde(rename = "tls")]
+    Tls,
+
+    /// No encryption at all, authentication is refused
+    #[serde(rename = "none")]
+    None,
+}
+
+fn default_smtp_port() -> u16 {
+    587
+}
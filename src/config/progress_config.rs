@@ -0,0 +1,79 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use anyhow::anyhow;
+use anyhow::Error;
+use getset::CopyGetters;
+use getset::Getters;
+use serde::Deserialize;
+
+/// The configuration of the progress bars
+#[derive(Clone, Debug, Default, Getters, CopyGetters, Deserialize)]
+pub struct ProgressConfig {
+    /// The template of the progress bars, overrides `progress_format`
+    #[getset(get = "pub")]
+    template: Option<String>,
+
+    /// The characters used for the spinner of the progress bars, the last one is shown when the
+    /// bar is finished
+    #[getset(get = "pub")]
+    tick_chars: Option<String>,
+
+    /// How often the progress bars are redrawn, per second
+    #[getset(get_copy = "pub")]
+    refresh_rate: Option<u8>,
+
+    /// Whether the progress bars are hidden
+    #[serde(default)]
+    #[getset(get_copy = "pub")]
+    hidden: bool,
+}
+
+impl ProgressConfig {
+    pub fn problem(&self) -> Option<Error> {
+        if let Some(chars) = self.tick_chars.as_ref() {
+            if chars.chars().count() < 2 {
+                return Some(anyhow!("'progress.tick_chars' needs at least two characters"))
+            }
+        }
+
+        if let Some(template) = self.template.as_ref() {
+            if let Err(e) = indicatif::ProgressStyle::with_template(template) {
+                return Some(anyhow!("Invalid 'progress.template': {}", e))
+            }
+        }
+
+        if self.refresh_rate == Some(0) {
+            return Some(anyhow!("'progress.refresh_rate' must be greater than zero"))
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn problem(s: &str) -> Option<Error> {
+        toml::from_str::<ProgressConfig>(s).unwrap().problem()
+    }
+
+    #[test]
+    fn test_problem() {
+        assert!(problem("").is_none());
+        assert!(problem(r#"tick_chars = "⠁⠂⠄ ""#).is_none());
+        assert!(problem(r#"template = "{spinner} {msg}""#).is_none());
+
+        assert!(problem(r#"tick_chars = "x""#).is_some());
+        assert!(problem(r#"template = "{bar:x}""#).is_some());
+        assert!(problem("refresh_rate = 0").is_some());
+    }
+}
@@ -0,0 +1,74 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+use anyhow::anyhow;
+use anyhow::Error;
+use getset::Getters;
+use serde::Deserialize;
+
+/// The credentials for a Docker registry
+///
+/// Either `username` and `password`, a `token` or a `credential_helper` must be set.
+#[derive(Clone, Debug, Getters, Deserialize)]
+pub struct RegistryConfig {
+    #[getset(get = "pub")]
+    username: Option<String>,
+
+    #[getset(get = "pub")]
+    password: Option<String>,
+
+    /// An identity token for the registry
+    #[getset(get = "pub")]
+    token: Option<String>,
+
+    /// The name of a Docker credential helper, e.g. "pass" for "docker-credential-pass"
+    #[getset(get = "pub")]
+    credential_helper: Option<String>,
+}
+
+impl RegistryConfig {
+    /// Check that exactly one way to get the credentials is configured
+    pub fn problem(&self, registry: &str) -> Option<Error> {
+        let sources = [
+            self.username.is_some() || self.password.is_some(),
+            self.token.is_some(),
+            self.credential_helper.is_some(),
+        ];
+
+        if sources.iter().filter(|set| **set).count() != 1 {
+            Some(anyhow!("Registry {} needs exactly one of 'username' and 'password', 'token' or 'credential_helper'", registry))
+        } else if self.username.is_some() != self.password.is_some() {
+            Some(anyhow!("Registry {} needs both 'username' and 'password'", registry))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn problem(s: &str) -> Option<Error> {
+        toml::from_str::<RegistryConfig>(s).unwrap().problem("registry.example.com")
+    }
+
+    #[test]
+    fn test_problem() {
+        assert!(problem(r#"username = "a"
+                           password = "b""#).is_none());
+        assert!(problem(r#"token = "t""#).is_none());
+        assert!(problem(r#"credential_helper = "pass""#).is_none());
+
+        assert!(problem("").is_some());
+        assert!(problem(r#"username = "a""#).is_some());
+        assert!(problem(r#"token = "t"
+                           credential_helper = "pass""#).is_some());
+    }
+}
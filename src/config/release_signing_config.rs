@@ -0,0 +1,35 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use std::path::PathBuf;
+
+use getset::Getters;
+use serde::Deserialize;
+
+/// Configuration for signing released artifacts with GPG
+#[derive(Clone, Debug, Getters, Deserialize)]
+pub struct ReleaseSigningConfig {
+    /// The ID of the key to sign with (passed to `gpg --local-user`)
+    #[getset(get = "pub")]
+    key: String,
+
+    /// The fingerprint of the key, signatures that are not made by this key are rejected
+    #[getset(get = "pub")]
+    fingerprint: String,
+
+    /// The gpg binary to use
+    #[serde(default = "default_gpg")]
+    #[getset(get = "pub")]
+    gpg: PathBuf,
+}
+
+fn default_gpg() -> PathBuf {
+    PathBuf::from("gpg")
+}
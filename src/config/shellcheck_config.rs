@@ -0,0 +1,60 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use std::path::Path;
+use std::path::PathBuf;
+
+use getset::CopyGetters;
+use getset::Getters;
+use serde::Deserialize;
+
+/// The configuration of checking the package scripts with shellcheck
+#[derive(Clone, Debug, Default, Getters, CopyGetters, Deserialize)]
+pub struct ShellcheckConfig {
+    /// The shellcheck executable, "shellcheck" from the PATH if not set
+    binary: Option<PathBuf>,
+
+    /// Additional arguments for shellcheck, e.g. "--severity=warning"
+    #[serde(default)]
+    #[getset(get = "pub")]
+    args: Vec<String>,
+
+    /// Whether the scripts of the packages are checked before a submit
+    #[serde(default)]
+    #[getset(get_copy = "pub")]
+    pre_submit: bool,
+
+    /// Whether findings are only reported as warnings instead of failing
+    #[serde(default)]
+    #[getset(get_copy = "pub")]
+    warn_only: bool,
+}
+
+impl ShellcheckConfig {
+    pub fn binary(&self) -> &Path {
+        self.binary.as_deref().unwrap_or_else(|| Path::new("shellcheck"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_defaults() {
+        let config = toml::from_str::<ShellcheckConfig>("").unwrap();
+        assert_eq!(config.binary(), Path::new("shellcheck"));
+        assert!(!config.pre_submit());
+        assert!(!config.warn_only());
+
+        let config = toml::from_str::<ShellcheckConfig>(r#"binary = "/usr/bin/shellcheck""#).unwrap();
+        assert_eq!(config.binary(), Path::new("/usr/bin/shellcheck"));
+    }
+}
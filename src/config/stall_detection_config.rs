@@ -0,0 +1,85 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use std::time::Duration;
+
+use anyhow::anyhow;
+use anyhow::Error;
+use getset::CopyGetters;
+use getset::Getters;
+use serde::Deserialize;
+
+/// The detection of stalled jobs, i.e. jobs whose script does not print anything for a while
+#[derive(Clone, Debug, Default, Getters, CopyGetters, Deserialize)]
+pub struct StallDetectionConfig {
+    /// The duration without output after which a job is stalled (e.g. "30min")
+    ///
+    /// Stalled jobs are not detected if this is not set.
+    timeout: Option<String>,
+
+    /// A command that is run in the container of a stalled job, its output is appended to the
+    /// log of the job
+    #[getset(get = "pub")]
+    snapshot_command: Option<String>,
+
+    /// How often a stalled job is killed and run again, stalled jobs are not killed if this is 0
+    #[serde(default)]
+    #[getset(get_copy = "pub")]
+    retries: usize,
+}
+
+impl StallDetectionConfig {
+    /// The duration without output after which a job is stalled, if stalled jobs are detected
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+            .as_deref()
+            .and_then(|timeout| humantime::parse_duration(timeout).ok())
+    }
+
+    pub fn problem(&self) -> Option<Error> {
+        match self.timeout.as_deref().map(humantime::parse_duration) {
+            Some(Err(e)) => Some(anyhow!("Invalid 'containers.stall_detection.timeout': {}", e)),
+            Some(Ok(timeout)) if timeout.is_zero() => {
+                Some(anyhow!("'containers.stall_detection.timeout' must be greater than zero"))
+            },
+            None if self.retries > 0 || self.snapshot_command.is_some() => {
+                Some(anyhow!("'containers.stall_detection' needs a 'timeout'"))
+            },
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn problem(s: &str) -> Option<Error> {
+        toml::from_str::<StallDetectionConfig>(s).unwrap().problem()
+    }
+
+    #[test]
+    fn test_problem() {
+        assert!(problem("").is_none());
+        assert!(problem(r#"timeout = "30min""#).is_none());
+        assert!(problem("timeout = \"1h\"\nretries = 2").is_none());
+
+        assert!(problem(r#"timeout = "soon""#).is_some());
+        assert!(problem(r#"timeout = "0s""#).is_some());
+        assert!(problem("retries = 1").is_some());
+    }
+
+    #[test]
+    fn test_timeout() {
+        let config = toml::from_str::<StallDetectionConfig>(r#"timeout = "30min""#).unwrap();
+        assert_eq!(config.timeout(), Some(Duration::from_secs(30 * 60)));
+        assert_eq!(StallDetectionConfig::default().timeout(), None);
+    }
+}
@@ -0,0 +1,54 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use getset::Getters;
+use serde::Deserialize;
+use url::Url;
+
+/// The configuration of the backend a store persists its artifacts in, in addition to the local
+/// directory
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type")]
+pub enum StoreBackendConfig {
+    #[serde(rename = "s3")]
+    S3(S3StoreConfig),
+}
+
+/// Configuration of an S3 compatible object storage (e.g. AWS S3 or MinIO)
+#[derive(Clone, Debug, Getters, Deserialize)]
+pub struct S3StoreConfig {
+    /// The URL of the object storage, e.g. "https://s3.eu-central-1.amazonaws.com"
+    #[getset(get = "pub")]
+    endpoint: Url,
+
+    #[getset(get = "pub")]
+    bucket: String,
+
+    #[serde(default = "default_s3_region")]
+    #[getset(get = "pub")]
+    region: String,
+
+    /// The key prefix the artifacts of the store are put under
+    #[serde(default)]
+    #[getset(get = "pub")]
+    prefix: String,
+
+    /// The access key, if not set, the AWS_ACCESS_KEY_ID environment variable is used
+    #[getset(get = "pub")]
+    access_key_id: Option<String>,
+
+    /// The secret key, if not set, the AWS_SECRET_ACCESS_KEY environment variable is used
+    #[getset(get = "pub")]
+    secret_access_key: Option<String>,
+}
+
+fn default_s3_region() -> String {
+    String::from("us-east-1")
+}
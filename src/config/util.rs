@@ -111,6 +111,12 @@ pub fn default_script_shebang() -> String {
     String::from("#!/bin/bash")
 }
 
+/// The default value for the number of lines from the beginning and end of an oversized log that
+/// are stored in the database
+pub fn default_log_excerpt_lines() -> usize {
+    200
+}
+
 /// The default value for the number of log lines that should be printed if a build fails
 pub fn default_build_error_lines() -> usize {
     10
@@ -13,13 +13,28 @@
 /// to.
 pub const INPUTS_DIR_PATH: &str  = "/inputs";
 
+/// The name of the JSON file in the inputs directory inside the container that lists the
+/// artifacts in the inputs directory, with the package they were built for and their SHA256
+pub const INPUTS_MANIFEST_NAME: &str = "manifest.json";
+
 /// The path to the directory inside the container where the outputs of a compile job must be
 /// located after the script was run
 pub const OUTPUTS_DIR_PATH: &str = "/outputs";
-pub const OUTPUTS_DIR_NAME: &str = "outputs";
 
 pub const PATCH_DIR_PATH: &str = "/patches";
 
+/// The name of the environment variable that holds the space-separated paths of the patches of
+/// the package inside the container
+pub const PATCHES_ENV_NAME: &str = "BUTIDO_PATCHES";
+
+/// The names of the environment variables that butido sets for every job, see
+/// `RunnableJob::builtin_environment()`
+pub const PACKAGE_NAME_ENV_NAME: &str = "BUTIDO_PACKAGE_NAME";
+pub const PACKAGE_VERSION_ENV_NAME: &str = "BUTIDO_PACKAGE_VERSION";
+pub const SOURCE_FILE_ENV_NAME: &str = "BUTIDO_SOURCE_FILE";
+pub const OUTPUT_DIR_ENV_NAME: &str = "BUTIDO_OUTPUT_DIR";
+pub const JOB_UUID_ENV_NAME: &str = "BUTIDO_JOB_UUID";
+
 /// The path where the script that is executed inside the container is copied to.
 pub const SCRIPT_PATH: &str      = "/script";
 
@@ -18,7 +18,7 @@ use diesel::r2d2::Pool;
 use getset::Getters;
 use tracing::debug;
 
-use crate::config::Configuration;
+use crate::config::NotValidatedConfiguration;
 
 #[derive(Getters)]
 pub struct DbConnectionConfig<'a> {
@@ -54,7 +54,7 @@ impl<'a> std::fmt::Debug for DbConnectionConfig<'a> {
 }
 
 impl<'a> DbConnectionConfig<'a> {
-    pub fn parse(config: &'a Configuration, cli: &'a ArgMatches) -> Result<DbConnectionConfig<'a>> {
+    pub fn parse(config: &'a NotValidatedConfiguration, cli: &'a ArgMatches) -> Result<DbConnectionConfig<'a>> {
         Ok(DbConnectionConfig {
             database_host: cli.get_one::<String>("database_host").unwrap_or_else(|| config.database_host()),
             database_port: {
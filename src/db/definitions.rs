@@ -0,0 +1,42 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+use diesel::PgConnection;
+use tracing::debug;
+
+use crate::config::Configuration;
+use crate::db::models::EndpointDefinition;
+use crate::db::models::ImageDefinition;
+use crate::util::docker::ImageName;
+
+/// Load the endpoints and images that are defined in the database into the configuration
+pub fn apply_definitions(config: Configuration, database_connection: &mut PgConnection) -> Result<Configuration> {
+    let endpoints = EndpointDefinition::all(database_connection)?
+        .iter()
+        .map(EndpointDefinition::to_config)
+        .collect::<Result<HashMap<_, _>>>()?;
+
+    let (allowed, denied): (Vec<_>, Vec<_>) = ImageDefinition::all(database_connection)?
+        .into_iter()
+        .partition(|def| def.allowed);
+    let allowed = allowed.iter().map(ImageDefinition::to_container_image).collect::<Vec<_>>();
+    let denied = denied.into_iter().map(|def| ImageName::from(def.name)).collect::<Vec<_>>();
+
+    debug!(
+        "Loaded {} endpoints, {} allowed and {} denied images from database",
+        endpoints.len(),
+        allowed.len(),
+        denied.len()
+    );
+    Ok(config.with_database_definitions(endpoints, allowed, &denied))
+}
@@ -0,0 +1,145 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Estimates of the durations of jobs, from the durations of recent successful jobs
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use diesel::ExpressionMethods;
+use diesel::PgConnection;
+use diesel::QueryDsl;
+use diesel::RunQueryDsl;
+use tracing::trace;
+
+use crate::package::Package;
+use crate::schema;
+
+/// The number of recent successful jobs of a package the estimate is calculated from
+const HISTORY: usize = 5;
+
+/// Estimated durations of the jobs of packages
+///
+/// The estimate of a package is the median duration of its recent successful jobs. If the version
+/// of the package was never built, the jobs of all versions of the package are used.
+#[derive(Clone, Debug, Default)]
+pub struct DurationEstimates {
+    by_version: HashMap<(String, String), Duration>,
+    by_name: HashMap<String, Duration>,
+}
+
+impl DurationEstimates {
+    /// Load the estimates for `packages` from the database
+    pub fn load<'a, I>(database_connection: &mut PgConnection, packages: I) -> Result<Self>
+    where
+        I: Iterator<Item = &'a Package>,
+    {
+        let names = packages.map(|p| p.name().to_string()).collect::<Vec<_>>();
+        let samples = schema::jobs::table
+            .inner_join(schema::packages::table)
+            .filter(schema::packages::name.eq_any(&names))
+            .filter(schema::jobs::exit_code.eq(0))
+            .filter(schema::jobs::started_at.is_not_null())
+            .filter(schema::jobs::finished_at.is_not_null())
+            .order_by(schema::jobs::finished_at.desc())
+            .select((
+                schema::packages::name,
+                schema::packages::version,
+                schema::jobs::started_at,
+                schema::jobs::finished_at,
+            ))
+            .load::<(String, String, Option<NaiveDateTime>, Option<NaiveDateTime>)>(database_connection)
+            .with_context(|| anyhow!("Loading durations of jobs of {} packages", names.len()))?
+            .into_iter()
+            .filter_map(|(name, version, started, finished)| {
+                let duration = finished?.signed_duration_since(started?).to_std().ok()?;
+                Some((name, version, duration))
+            });
+
+        let estimates = Self::from_samples(samples);
+        trace!("Estimated durations: {:?}", estimates);
+        Ok(estimates)
+    }
+
+    /// Calculate the estimates from the durations of jobs, newest first
+    fn from_samples<I>(samples: I) -> Self
+    where
+        I: Iterator<Item = (String, String, Duration)>,
+    {
+        let mut by_version: HashMap<(String, String), Vec<Duration>> = HashMap::new();
+        let mut by_name: HashMap<String, Vec<Duration>> = HashMap::new();
+        for (name, version, duration) in samples {
+            let versioned = by_version.entry((name.clone(), version)).or_default();
+            if versioned.len() < HISTORY {
+                versioned.push(duration);
+            }
+
+            let named = by_name.entry(name).or_default();
+            if named.len() < HISTORY {
+                named.push(duration);
+            }
+        }
+
+        DurationEstimates {
+            by_version: by_version.into_iter().map(|(k, durations)| (k, median(durations))).collect(),
+            by_name: by_name.into_iter().map(|(k, durations)| (k, median(durations))).collect(),
+        }
+    }
+
+    /// The estimated duration of a job of `package`, if the package was built before
+    pub fn of(&self, package: &Package) -> Option<Duration> {
+        self.by_version
+            .get(&(package.name().to_string(), package.version().to_string()))
+            .or_else(|| self.by_name.get(&package.name().to_string()))
+            .copied()
+    }
+}
+
+fn median(mut durations: Vec<Duration>) -> Duration {
+    durations.sort();
+    durations[durations.len() / 2]
+}
+
+/// Format an estimated duration to whole seconds, e.g. "5m 12s"
+pub fn format_estimate(duration: Duration) -> String {
+    humantime::format_duration(Duration::from_secs(duration.as_secs())).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::package::tests::package;
+
+    #[test]
+    fn test_estimates_from_samples() {
+        let sample = |name: &str, version: &str, secs| (String::from(name), String::from(version), Duration::from_secs(secs));
+        let estimates = DurationEstimates::from_samples(vec![
+            sample("a", "2", 60),
+            sample("a", "1", 10),
+            sample("a", "1", 30),
+            sample("a", "1", 20),
+            sample("a", "1", 1000),
+            sample("a", "1", 1000),
+            sample("a", "1", 1000),
+        ].into_iter());
+
+        let a1 = package("a", "1", "https://rust-lang.org", "123");
+        let a3 = package("a", "3", "https://rust-lang.org", "123");
+        let b1 = package("b", "1", "https://rust-lang.org", "123");
+        assert_eq!(estimates.of(&a1), Some(Duration::from_secs(30)));
+        assert_eq!(estimates.of(&a3), Some(Duration::from_secs(30)));
+        assert_eq!(estimates.of(&b1), None);
+        assert_eq!(format_estimate(Duration::from_millis(312_500)), "5m 12s");
+    }
+}
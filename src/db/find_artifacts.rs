@@ -33,6 +33,7 @@ use crate::filestore::path::FullArtifactPath;
 use crate::filestore::ReleaseStore;
 use crate::filestore::StagingStore;
 use crate::package::Package;
+use crate::package::PhaseName;
 use crate::package::ScriptBuilder;
 use crate::package::Shebang;
 use crate::schema;
@@ -69,6 +70,10 @@ pub struct FindArtifacts<'a> {
     /// not returned
     script_filter: bool,
 
+    /// The phases the script of the job is built from, all available phases if not set
+    #[builder(default)]
+    phases: Option<&'a [PhaseName]>,
+
     /// Filter for these environment variables
     env_filter: &'a [(EnvironmentVariableName, String)],
 
@@ -87,7 +92,7 @@ impl<'a> FindArtifacts<'a> {
         let script = if self.script_filter {
             let script = ScriptBuilder::new(&shebang).build(
                 self.package,
-                self.config.available_phases(),
+                self.phases.unwrap_or_else(|| self.config.available_phases().as_slice()),
                 *self.config.strict_script_interpolation(),
             )?;
             Some(script)
@@ -110,12 +115,14 @@ impl<'a> FindArtifacts<'a> {
             // dependencies of `pkg`.
             .inner_join(schema::jobs::table.inner_join(schema::submits::table))
             .inner_join(schema::artifacts::table.on(schema::jobs::id.eq(schema::artifacts::job_id)))
+            .filter(schema::artifacts::artifact_kind.eq(crate::filestore::PACKAGE_ARTIFACT_KIND))
 
             // TODO: We do not yet have a method to "left join" properly, because diesel only has
             // left_outer_join (left_join is an alias)
             // So do not include release dates here, for now
             //.left_outer_join(schema::releases::table.on(schema::releases::artifact_id.eq(schema::artifacts::id)))
-            .inner_join(schema::images::table.on(schema::submits::requested_image_id.eq(schema::images::id)))
+            // The image the job was built on, a submit can build for multiple images
+            .inner_join(schema::images::table.on(schema::jobs::image_id.eq(schema::images::id)))
             .into_boxed();
 
         if let Some(allowed_images) = self.package.allowed_images() {
@@ -188,14 +195,23 @@ impl<'a> FindArtifacts<'a> {
                 Ok((_, bl)) => *bl,
             })
             .and_then_ok(|(art, _)| {
-                if let Some(release) = art.get_release(&mut self.database_pool.get().unwrap())? {
-                    Ok((art, Some(release.release_date)))
-                } else {
-                    Ok((art, None))
-                }
+                let release = art.get_release(&mut self.database_pool.get().unwrap())?;
+                Ok((art, release))
+            })
+            .and_then_ok(|(art, release)| {
+                let artpath = ArtifactPath::new(PathBuf::from(art.path))?;
+
+                // The artifact might have been released to another path than it has in the
+                // staging store
+                let release_path = release
+                    .as_ref()
+                    .map(|release| ArtifactPath::new(PathBuf::from(&release.path)))
+                    .transpose()?
+                    .unwrap_or_else(|| artpath.clone());
+                let ndt = release.map(|release| release.release_date);
+                Ok((artpath, release_path, ndt))
             })
-            .and_then_ok(|(p, ndt)| ArtifactPath::new(PathBuf::from(p.path)).map(|a| (a, ndt)))
-            .and_then_ok(|(artpath, ndt)| {
+            .and_then_ok(|(artpath, release_path, ndt)| {
                 if let Some(staging) = self.staging_store.as_ref() {
                     trace!(
                         "Searching in staging: {:?} for {:?}",
@@ -212,7 +228,7 @@ impl<'a> FindArtifacts<'a> {
                 // This is the case if there indeed was a release, but it was removed from the
                 // filesystem.
                 for release_store in self.release_stores {
-                    if let Some(art) = release_store.get(&artpath) {
+                    if let Some(art) = release_store.get(&release_path) {
                         trace!("Found in release: {:?}", art);
                         return release_store.root_path().join(art).map(|p| p.map(|p| (p, ndt)))
                     }
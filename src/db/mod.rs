@@ -11,6 +11,12 @@
 mod connection;
 pub use connection::*;
 
+mod definitions;
+pub use definitions::*;
+
+mod estimates;
+pub use estimates::*;
+
 mod find_artifacts;
 pub use find_artifacts::FindArtifacts;
 
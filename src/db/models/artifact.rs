@@ -9,7 +9,9 @@
 //
 
 use crate::filestore::path::ArtifactPath;
+use crate::filestore::Compression;
 use std::path::PathBuf;
+use std::str::FromStr;
 
 use anyhow::anyhow;
 use anyhow::Context;
@@ -21,6 +23,7 @@ use diesel::PgConnection;
 
 use crate::db::models::Job;
 use crate::db::models::Release;
+use crate::db::models::Submit;
 use crate::schema::artifacts;
 use crate::schema::artifacts::*;
 
@@ -30,6 +33,11 @@ pub struct Artifact {
     pub id: i32,
     pub path: String,
     pub job_id: i32,
+    pub compression: Option<String>,
+    pub artifact_kind: String,
+
+    /// The SHA256 hash of the artifact, if it was recorded
+    pub sha256: Option<String>,
 }
 
 #[derive(Insertable)]
@@ -37,6 +45,9 @@ pub struct Artifact {
 struct NewArtifact<'a> {
     pub path: &'a str,
     pub job_id: i32,
+    pub compression: Option<String>,
+    pub artifact_kind: &'a str,
+    pub sha256: Option<&'a str>,
 }
 
 impl Artifact {
@@ -49,9 +60,87 @@ impl Artifact {
         database_connection: &mut PgConnection,
         release_date: &NaiveDateTime,
         release_store_name: &str,
+        operator: Option<&str>,
     ) -> Result<crate::db::models::Release> {
         let rs = crate::db::models::ReleaseStore::create(database_connection, release_store_name)?;
-        crate::db::models::Release::create(database_connection, &self, release_date, &rs)
+        crate::db::models::Release::create(database_connection, &self, release_date, &rs, operator, None, &self.path)
+    }
+
+    /// Get the compression the artifact at `art_path` in the staging store of `submit` was written
+    /// with
+    pub fn compression_in_submit(
+        database_connection: &mut PgConnection,
+        art_path: &ArtifactPath,
+        submit: &Submit,
+    ) -> Result<Option<Compression>> {
+        use crate::schema;
+
+        let path_str = art_path
+            .to_str()
+            .ok_or_else(|| anyhow!("Path is not valid UTF-8: {}", art_path.display()))?;
+
+        dsl::artifacts
+            .inner_join(schema::jobs::table)
+            .filter(schema::jobs::submit_id.eq(submit.id))
+            .filter(path.eq(path_str))
+            .select(compression)
+            .first::<Option<String>>(database_connection)
+            .optional()?
+            .flatten()
+            .map(|c| Compression::from_str(&c).map_err(Error::from))
+            .transpose()
+    }
+
+    /// Get the compression of the artifact that was released to `art_path`
+    ///
+    /// If several artifacts were released to this path, the compression of the latest release is
+    /// returned.
+    pub fn compression_of_release(
+        database_connection: &mut PgConnection,
+        art_path: &ArtifactPath,
+    ) -> Result<Option<Compression>> {
+        use crate::schema;
+
+        let path_str = art_path
+            .to_str()
+            .ok_or_else(|| anyhow!("Path is not valid UTF-8: {}", art_path.display()))?;
+
+        dsl::artifacts
+            .inner_join(schema::releases::table)
+            .filter(schema::releases::path.eq(path_str))
+            .order(schema::releases::release_date.desc())
+            .select(compression)
+            .first::<Option<String>>(database_connection)
+            .optional()?
+            .flatten()
+            .map(|c| Compression::from_str(&c).map_err(Error::from))
+            .transpose()
+    }
+
+    /// Get the package that the artifact at `art_path` was built for
+    ///
+    /// `art_path` is either the path of the artifact in the staging store or the path it was
+    /// released to.
+    /// If the artifact was built more than once, the package of the latest build is returned.
+    pub fn package_of(
+        database_connection: &mut PgConnection,
+        art_path: &ArtifactPath,
+    ) -> Result<Option<crate::db::models::Package>> {
+        use crate::schema;
+
+        let path_str = art_path
+            .to_str()
+            .ok_or_else(|| anyhow!("Path is not valid UTF-8: {}", art_path.display()))?;
+
+        schema::artifacts::table
+            .inner_join(schema::jobs::table.inner_join(schema::packages::table))
+            .left_join(schema::releases::table)
+            .filter(schema::artifacts::path.eq(path_str).nullable().or(schema::releases::path.nullable().eq(path_str)))
+            .order_by(schema::artifacts::id.desc())
+            .select(schema::packages::all_columns)
+            .first::<crate::db::models::Package>(database_connection)
+            .optional()
+            .map_err(Error::from)
     }
 
     pub fn get_release(&self, database_connection: &mut PgConnection) -> Result<Option<Release>> {
@@ -69,6 +158,9 @@ impl Artifact {
     pub fn create(
         database_connection: &mut PgConnection,
         art_path: &ArtifactPath,
+        art_compression: Option<Compression>,
+        art_kind: &str,
+        art_sha256: Option<&str>,
         job: &Job,
     ) -> Result<Artifact> {
         let path_str = art_path
@@ -78,6 +170,9 @@ impl Artifact {
         let new_art = NewArtifact {
             path: path_str,
             job_id: job.id,
+            compression: art_compression.map(|c| c.to_string()),
+            artifact_kind: art_kind,
+            sha256: art_sha256,
         };
 
         database_connection.transaction::<_, Error, _>(|conn| {
@@ -0,0 +1,96 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use std::str::FromStr;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Error;
+use anyhow::Result;
+use diesel::prelude::*;
+use diesel::PgConnection;
+
+use crate::config::EndpointName;
+use crate::config::EndpointType;
+use crate::schema::endpoint_definitions;
+use crate::schema::endpoint_definitions::*;
+
+/// An endpoint that is defined in the database instead of the configuration file
+#[derive(Debug, Identifiable, Queryable)]
+pub struct EndpointDefinition {
+    pub id: i32,
+    pub name: String,
+    pub uri: String,
+    pub endpoint_type: String,
+    pub maxjobs: i32,
+    pub network_mode: Option<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = endpoint_definitions)]
+struct NewEndpointDefinition<'a> {
+    pub name: &'a str,
+    pub uri: &'a str,
+    pub endpoint_type: String,
+    pub maxjobs: i32,
+    pub network_mode: Option<&'a str>,
+}
+
+impl EndpointDefinition {
+    pub fn create(
+        database_connection: &mut PgConnection,
+        ep_name: &EndpointName,
+        ep_uri: &str,
+        ep_type: &EndpointType,
+        ep_maxjobs: usize,
+        ep_network_mode: Option<&str>,
+    ) -> Result<EndpointDefinition> {
+        let new_def = NewEndpointDefinition {
+            name: ep_name.as_ref(),
+            uri: ep_uri,
+            endpoint_type: ep_type.to_string(),
+            maxjobs: i32::try_from(ep_maxjobs).context("Converting maxjobs")?,
+            network_mode: ep_network_mode,
+        };
+
+        diesel::insert_into(endpoint_definitions::table)
+            .values(&new_def)
+            .get_result::<EndpointDefinition>(database_connection)
+            .with_context(|| anyhow!("Creating endpoint definition {}", ep_name))
+            .map_err(Error::from)
+    }
+
+    /// Remove the definition of the endpoint, returns whether there was a definition
+    pub fn remove(database_connection: &mut PgConnection, ep_name: &EndpointName) -> Result<bool> {
+        diesel::delete(dsl::endpoint_definitions.filter(name.eq(ep_name.as_ref())))
+            .execute(database_connection)
+            .with_context(|| anyhow!("Removing endpoint definition {}", ep_name))
+            .map(|n| n > 0)
+    }
+
+    pub fn all(database_connection: &mut PgConnection) -> Result<Vec<EndpointDefinition>> {
+        dsl::endpoint_definitions
+            .order_by(name)
+            .load::<EndpointDefinition>(database_connection)
+            .context("Loading endpoint definitions")
+            .map_err(Error::from)
+    }
+
+    /// Turn the definition into the endpoint configuration that is used for connecting
+    pub fn to_config(&self) -> Result<(EndpointName, crate::config::Endpoint)> {
+        let ep_type = EndpointType::from_str(&self.endpoint_type)
+            .with_context(|| anyhow!("Endpoint definition {} is invalid", self.name))?;
+        let ep_maxjobs = usize::try_from(self.maxjobs)
+            .with_context(|| anyhow!("Endpoint definition {} has invalid maxjobs: {}", self.name, self.maxjobs))?;
+
+        let config = crate::config::Endpoint::new(self.uri.clone(), ep_type, ep_maxjobs, self.network_mode.clone());
+        Ok((EndpointName::from(self.name.clone()), config))
+    }
+}
@@ -0,0 +1,146 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Error;
+use anyhow::Result;
+use std::time::Duration;
+
+use chrono::NaiveDateTime;
+use diesel::dsl::now;
+use diesel::pg::expression::extensions::IntervalDsl;
+use diesel::prelude::*;
+use diesel::sql_types::Integer;
+use diesel::PgConnection;
+use tracing::warn;
+
+use crate::db::models::Endpoint;
+use crate::db::models::Submit;
+use crate::schema::endpoint_reservations;
+
+/// The class of the advisory locks on the capacity of the endpoints ("buti")
+///
+/// Postgres advisory locks with two keys are used, the second key is the ID of the endpoint.
+const CAPACITY_LOCK_CLASS: i32 = 0x6275_7469;
+
+/// Reservations that were not refreshed for this long are expired
+///
+/// The instance that made them was killed, so the jobs do not run anymore.
+pub const RESERVATION_EXPIRY: Duration = Duration::from_secs(300);
+
+/// A job slot of an endpoint that is reserved for a job
+///
+/// The reservations are shared by all butido instances that use the same database, so that they
+/// do not run more jobs on an endpoint than its maximum number of jobs together.
+/// The instance running the job refreshes `reserved_at` regularly, see [`RESERVATION_EXPIRY`].
+#[derive(Debug, Identifiable, Queryable, Associations)]
+#[diesel(belongs_to(Endpoint))]
+#[diesel(belongs_to(Submit))]
+#[diesel(table_name = endpoint_reservations)]
+pub struct EndpointReservation {
+    pub id: i32,
+    pub endpoint_id: i32,
+    pub submit_id: i32,
+    pub job_uuid: ::uuid::Uuid,
+    pub reserved_by: Option<String>,
+    pub reserved_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = endpoint_reservations)]
+struct NewEndpointReservation<'a> {
+    pub endpoint_id: i32,
+    pub submit_id: i32,
+    pub job_uuid: &'a ::uuid::Uuid,
+    pub reserved_by: Option<&'a str>,
+}
+
+impl EndpointReservation {
+    /// Reserve a job slot of `endpoint` for the job `job_uuid`, if less than `max_jobs` slots are
+    /// reserved
+    ///
+    /// Reservations of the same endpoint are serialized with an advisory lock, so two instances
+    /// cannot both take the last slot. Expired reservations of the endpoint are removed first.
+    pub fn try_create(
+        database_connection: &mut PgConnection,
+        endpoint: &Endpoint,
+        max_jobs: usize,
+        submit: &Submit,
+        job_uuid: &::uuid::Uuid,
+    ) -> Result<Option<EndpointReservation>> {
+        let hostname = crate::util::hostname();
+
+        database_connection.transaction::<_, Error, _>(|conn| {
+            // The lock is released at the end of the transaction
+            diesel::sql_query("SELECT pg_advisory_xact_lock($1, $2)")
+                .bind::<Integer, _>(CAPACITY_LOCK_CLASS)
+                .bind::<Integer, _>(endpoint.id)
+                .execute(conn)
+                .with_context(|| anyhow!("Locking capacity of endpoint {}", endpoint.name))?;
+
+            let expired = diesel::delete(endpoint_reservations::table)
+                .filter(endpoint_reservations::endpoint_id.eq(endpoint.id))
+                .filter(endpoint_reservations::reserved_at.lt(now - (RESERVATION_EXPIRY.as_secs() as i64).seconds()))
+                .execute(conn)
+                .with_context(|| anyhow!("Removing expired reservations of endpoint {}", endpoint.name))?;
+            if expired > 0 {
+                warn!("Removed {} expired job slot reservations of endpoint {}", expired, endpoint.name);
+            }
+
+            let reserved = endpoint_reservations::table
+                .filter(endpoint_reservations::endpoint_id.eq(endpoint.id))
+                .count()
+                .get_result::<i64>(conn)?;
+            if reserved as usize >= max_jobs {
+                return Ok(None)
+            }
+
+            diesel::insert_into(endpoint_reservations::table)
+                .values((
+                    &NewEndpointReservation {
+                        endpoint_id: endpoint.id,
+                        submit_id: submit.id,
+                        job_uuid,
+                        reserved_by: hostname.as_deref(),
+                    },
+                    endpoint_reservations::reserved_at.eq(now),
+                ))
+                .get_result::<EndpointReservation>(conn)
+                .map(Some)
+                .with_context(|| anyhow!("Reserving job slot of endpoint {} for job {}", endpoint.name, job_uuid))
+        })
+    }
+
+    /// Refresh the reservation, so that it does not expire while the job runs
+    pub fn refresh(&self, database_connection: &mut PgConnection) -> Result<()> {
+        diesel::update(endpoint_reservations::table.find(self.id))
+            .set(endpoint_reservations::reserved_at.eq(now))
+            .execute(database_connection)
+            .with_context(|| anyhow!("Refreshing job slot reserved for job {}", self.job_uuid))?;
+        Ok(())
+    }
+
+    /// Release the reservation
+    pub fn delete(&self, database_connection: &mut PgConnection) -> Result<()> {
+        diesel::delete(endpoint_reservations::table.find(self.id))
+            .execute(database_connection)
+            .with_context(|| anyhow!("Releasing job slot reserved for job {}", self.job_uuid))?;
+        Ok(())
+    }
+
+    /// Release the reservations of the jobs of `submit`, e.g. of an interrupted submit
+    pub fn delete_for_submit(database_connection: &mut PgConnection, submit: &Submit) -> Result<usize> {
+        diesel::delete(endpoint_reservations::table.filter(endpoint_reservations::submit_id.eq(submit.id)))
+            .execute(database_connection)
+            .with_context(|| anyhow!("Releasing job slots reserved for submit {}", submit.uuid))
+            .map_err(Error::from)
+    }
+}
@@ -0,0 +1,91 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Error;
+use anyhow::Result;
+use diesel::prelude::*;
+use diesel::PgConnection;
+
+use crate::schema::image_definitions;
+use crate::schema::image_definitions::*;
+use crate::util::docker::ContainerImage;
+use crate::util::docker::ImageName;
+
+/// An image that is allowed or denied in the database instead of the configuration file
+#[derive(Debug, Identifiable, Queryable)]
+pub struct ImageDefinition {
+    pub id: i32,
+    pub name: String,
+    pub short_name: Option<String>,
+    pub allowed: bool,
+}
+
+#[derive(Insertable, AsChangeset)]
+#[diesel(table_name = image_definitions)]
+#[diesel(treat_none_as_null = true)]
+struct NewImageDefinition<'a> {
+    pub name: &'a str,
+    pub short_name: Option<&'a str>,
+    pub allowed: bool,
+}
+
+impl ImageDefinition {
+    /// Allow (or deny) the image, replacing an existing definition
+    pub fn set(
+        database_connection: &mut PgConnection,
+        image_name: &ImageName,
+        image_short_name: Option<&str>,
+        image_allowed: bool,
+    ) -> Result<ImageDefinition> {
+        let new_def = NewImageDefinition {
+            name: image_name.as_ref(),
+            short_name: image_short_name,
+            allowed: image_allowed,
+        };
+
+        diesel::insert_into(image_definitions::table)
+            .values(&new_def)
+            .on_conflict(name)
+            .do_update()
+            .set(&new_def)
+            .get_result::<ImageDefinition>(database_connection)
+            .with_context(|| anyhow!("Setting image definition for {}", image_name))
+            .map_err(Error::from)
+    }
+
+    /// Remove the definition of the image, returns whether there was a definition
+    pub fn remove(database_connection: &mut PgConnection, image_name: &ImageName) -> Result<bool> {
+        diesel::delete(dsl::image_definitions.filter(name.eq(image_name.as_ref())))
+            .execute(database_connection)
+            .with_context(|| anyhow!("Removing image definition {}", image_name))
+            .map(|n| n > 0)
+    }
+
+    pub fn all(database_connection: &mut PgConnection) -> Result<Vec<ImageDefinition>> {
+        dsl::image_definitions
+            .order_by(name)
+            .load::<ImageDefinition>(database_connection)
+            .context("Loading image definitions")
+            .map_err(Error::from)
+    }
+
+    /// The image as it is used in the configuration
+    ///
+    /// The short name defaults to the name of the image.
+    pub fn to_container_image(&self) -> ContainerImage {
+        ContainerImage {
+            name: ImageName::from(self.name.clone()),
+            short_name: ImageName::from(self.short_name.clone().unwrap_or_else(|| self.name.clone())),
+            paths: Default::default(),
+        }
+    }
+}
@@ -8,20 +8,31 @@
 // SPDX-License-Identifier: EPL-2.0
 //
 
+use std::path::Path;
+use std::str::FromStr;
+
 use anyhow::Error;
 use anyhow::Context;
 use anyhow::Result;
+use chrono::NaiveDateTime;
 use diesel::prelude::*;
 use diesel::PgConnection;
 use tracing::trace;
 
 use crate::db::models::{Endpoint, Image, Package, Submit};
+use crate::endpoint::ContainerFate;
+use crate::endpoint::ResourceUsage;
+use crate::job::InputManifest;
+use crate::log::JobResult;
+use crate::log::LogEncoding;
+use crate::log::ParsedLog;
+use crate::package::PhaseName;
 use crate::package::Script;
 use crate::schema::jobs;
 use crate::schema::jobs::*;
 use crate::util::docker::ContainerHash;
 
-#[derive(Debug, Eq, PartialEq, Identifiable, Queryable, Associations)]
+#[derive(Debug, PartialEq, Identifiable, Queryable, Associations)]
 #[diesel(belongs_to(Submit))]
 #[diesel(belongs_to(Endpoint))]
 #[diesel(belongs_to(Package))]
@@ -37,6 +48,20 @@ pub struct Job {
     pub script_text: String,
     pub log_text: String,
     pub uuid: ::uuid::Uuid,
+    pub failure_category: Option<String>,
+    pub exit_code: Option<i32>,
+    pub input_manifest: Option<String>,
+    pub started_at: Option<NaiveDateTime>,
+    pub finished_at: Option<NaiveDateTime>,
+    pub phases: Option<Vec<String>>,
+    pub container_fate: Option<String>,
+    pub oom_killed: Option<bool>,
+    pub log_file: Option<String>,
+    pub log_encoding: Option<String>,
+    pub log_data: Option<Vec<u8>>,
+    pub cpu_seconds: Option<f64>,
+    pub peak_memory: Option<i64>,
+    pub io_bytes: Option<i64>,
 }
 
 #[derive(Debug, Insertable)]
@@ -50,6 +75,9 @@ struct NewJob<'a> {
     pub script_text: String,
     pub log_text: String,
     pub uuid: &'a ::uuid::Uuid,
+    pub exit_code: Option<i32>,
+    pub log_encoding: Option<String>,
+    pub log_data: Option<Vec<u8>>,
 }
 
 impl Job {
@@ -64,7 +92,15 @@ impl Job {
         container: &ContainerHash,
         script: &Script,
         log: &str,
+        script_exit_code: Option<i32>,
+        encoding: Option<LogEncoding>,
     ) -> Result<Job> {
+        let log = log.replace('\0', "");
+        let (text, data) = match encoding {
+            Some(encoding) => (String::new(), Some(encoding.encode(&log)?)),
+            None => (log, None),
+        };
+
         let new_job = NewJob {
             uuid: job_uuid,
             submit_id: submit.id,
@@ -73,7 +109,10 @@ impl Job {
             image_id: image.id,
             container_hash: container.as_ref(),
             script_text: script.as_ref().replace('\0', ""),
-            log_text: log.replace('\0', ""),
+            log_text: text,
+            exit_code: script_exit_code,
+            log_encoding: encoding.map(|encoding| encoding.to_string()),
+            log_data: data,
         };
 
         trace!("Creating Job in database: {:?}", new_job);
@@ -96,6 +135,144 @@ impl Job {
         })
     }
 
+    /// Whether the job was successful
+    ///
+    /// Jobs whose container ran out of memory failed. Otherwise the exit code of the script is
+    /// authoritative, if there is none the log is parsed to find the state the script reported.
+    pub fn result(&self) -> Result<JobResult> {
+        if self.oom_killed == Some(true) {
+            return Ok(JobResult::Errored)
+        }
+
+        match self.exit_code {
+            Some(0) => Ok(JobResult::Success),
+            Some(_) => Ok(JobResult::Errored),
+            None => ParsedLog::from_str(&self.log()?).map(|pl| pl.is_successfull()),
+        }
+    }
+
+    /// Record the phases that were compiled into the script of the job
+    pub fn set_phases(&self, database_connection: &mut PgConnection, job_phases: &[PhaseName]) -> Result<()> {
+        let job_phases = job_phases.iter().map(|p| p.as_str().to_string()).collect::<Vec<_>>();
+        diesel::update(self)
+            .set(phases.eq(job_phases))
+            .execute(database_connection)
+            .with_context(|| format!("Setting phases for job {}", self.uuid))
+            .map(|_| ())
+    }
+
+    /// Record what happened to the container of the job after the job finished
+    pub fn set_container_fate(&self, database_connection: &mut PgConnection, fate: ContainerFate) -> Result<()> {
+        diesel::update(self)
+            .set(container_fate.eq(fate.as_str()))
+            .execute(database_connection)
+            .with_context(|| format!("Setting container fate for job {}", self.uuid))
+            .map(|_| ())
+    }
+
+    /// Record whether the container of the job was killed because it ran out of memory
+    pub fn set_oom_killed(&self, database_connection: &mut PgConnection, killed: bool) -> Result<()> {
+        diesel::update(self)
+            .set(oom_killed.eq(killed))
+            .execute(database_connection)
+            .with_context(|| format!("Setting OOM-killed status for job {}", self.uuid))
+            .map(|_| ())
+    }
+
+    /// Record the resource usage of the container of the job
+    pub fn set_resource_usage(&self, database_connection: &mut PgConnection, usage: &ResourceUsage) -> Result<()> {
+        diesel::update(self)
+            .set((
+                cpu_seconds.eq(usage.cpu_seconds),
+                peak_memory.eq(i64::try_from(usage.peak_memory)?),
+                io_bytes.eq(i64::try_from(usage.io_bytes)?),
+            ))
+            .execute(database_connection)
+            .with_context(|| format!("Setting resource usage for job {}", self.uuid))
+            .map(|_| ())
+    }
+
+    /// Record the file the full log of the job was written to because it exceeded the size limit
+    pub fn set_log_file(&self, database_connection: &mut PgConnection, path: &Path) -> Result<()> {
+        diesel::update(self)
+            .set(log_file.eq(path.display().to_string()))
+            .execute(database_connection)
+            .with_context(|| format!("Setting log file for job {}", self.uuid))
+            .map(|_| ())
+    }
+
+    /// The log of the job as it is stored in the database, decompressed if necessary
+    pub fn log(&self) -> Result<String> {
+        match (self.log_encoding.as_ref(), self.log_data.as_ref()) {
+            (Some(encoding), Some(data)) => LogEncoding::from_str(encoding)
+                .with_context(|| format!("Unknown log encoding of job {}: {}", self.uuid, encoding))?
+                .decode(data)
+                .with_context(|| format!("Decoding log of job {}", self.uuid)),
+            _ => Ok(self.log_text.clone()),
+        }
+    }
+
+    /// The full log of the job
+    ///
+    /// If the log exceeded the size limit, it is read from the log file, as long as that file
+    /// still exists.
+    pub fn full_log(&self) -> Result<String> {
+        crate::log::read_full_log(&self.log()?, self.log_file.as_deref())
+    }
+
+    /// The signal the script was killed with, derived from its exit code
+    ///
+    /// Like in a shell, exit codes above 128 mean that the process was killed by the signal
+    /// `exit code - 128`.
+    pub fn signal(&self) -> Option<i32> {
+        signal_of(self.exit_code)
+    }
+
+    /// Describe how the script of the job terminated, e.g. "137 (killed by signal 9, out of memory)"
+    pub fn termination(&self) -> Option<String> {
+        termination_of(self.exit_code, self.oom_killed)
+    }
+
+    /// Record the category a failed job was triaged into
+    pub fn set_failure_category(&self, database_connection: &mut PgConnection, category: &str) -> Result<()> {
+        diesel::update(self)
+            .set(failure_category.eq(category))
+            .execute(database_connection)
+            .with_context(|| format!("Setting failure category for job {}", self.uuid))
+            .map(|_| ())
+    }
+
+    pub fn set_input_manifest(&self, database_connection: &mut PgConnection, manifest: &InputManifest) -> Result<()> {
+        let manifest = manifest.to_json()?;
+        diesel::update(self)
+            .set(input_manifest.eq(manifest))
+            .execute(database_connection)
+            .with_context(|| format!("Setting input manifest for job {}", self.uuid))
+            .map(|_| ())
+    }
+
+    /// Record when the job was started and when it finished
+    pub fn set_times(
+        &self,
+        database_connection: &mut PgConnection,
+        started: &NaiveDateTime,
+        finished: &NaiveDateTime,
+    ) -> Result<()> {
+        diesel::update(self)
+            .set((started_at.eq(started), finished_at.eq(finished)))
+            .execute(database_connection)
+            .with_context(|| format!("Setting start and finish time for job {}", self.uuid))
+            .map(|_| ())
+    }
+
+    /// How long the job ran, if the start and finish time were recorded
+    pub fn duration(&self) -> Option<chrono::Duration> {
+        self.started_at
+            .as_ref()
+            .zip(self.finished_at.as_ref())
+            .map(|(start, finish)| finish.signed_duration_since(*start))
+    }
+
     pub fn env(&self, database_connection: &mut PgConnection) -> Result<Vec<crate::db::models::EnvVar>> {
         use crate::schema;
 
@@ -107,3 +284,37 @@ impl Job {
             .map_err(Error::from)
     }
 }
+
+fn signal_of(script_exit_code: Option<i32>) -> Option<i32> {
+    script_exit_code.filter(|code| *code > 128).map(|code| code - 128)
+}
+
+fn termination_of(script_exit_code: Option<i32>, container_oom_killed: Option<bool>) -> Option<String> {
+    let details = signal_of(script_exit_code)
+        .map(|signal| format!("killed by signal {signal}"))
+        .into_iter()
+        .chain(container_oom_killed.filter(|killed| *killed).map(|_| String::from("out of memory")))
+        .collect::<Vec<_>>();
+
+    match (script_exit_code, details.is_empty()) {
+        (None, true) => None,
+        (None, false) => Some(details.join(", ")),
+        (Some(code), true) => Some(code.to_string()),
+        (Some(code), false) => Some(format!("{code} ({})", details.join(", "))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_termination() {
+        assert_eq!(termination_of(None, None), None);
+        assert_eq!(termination_of(Some(0), Some(false)).as_deref(), Some("0"));
+        assert_eq!(termination_of(Some(2), None).as_deref(), Some("2"));
+        assert_eq!(termination_of(Some(143), Some(false)).as_deref(), Some("143 (killed by signal 15)"));
+        assert_eq!(termination_of(Some(137), Some(true)).as_deref(), Some("137 (killed by signal 9, out of memory)"));
+        assert_eq!(termination_of(None, Some(true)).as_deref(), Some("out of memory"));
+    }
+}
@@ -0,0 +1,113 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Error;
+use anyhow::Result;
+use diesel::prelude::*;
+use diesel::PgConnection;
+
+use crate::db::models::Image;
+use crate::db::models::Package;
+use crate::db::models::Submit;
+use crate::schema::job_dag_edges;
+
+/// An edge of the job DAG of a submit
+///
+/// Jobs without dependencies are recorded with an edge without `dependency_uuid`, so that all jobs
+/// of the DAG are recorded, even the ones that never ran.
+#[derive(Debug, Identifiable, Queryable, Associations)]
+#[diesel(belongs_to(Submit))]
+#[diesel(belongs_to(Package))]
+#[diesel(table_name = job_dag_edges)]
+pub struct JobDagEdge {
+    pub id: i32,
+    pub submit_id: i32,
+    pub package_id: i32,
+    pub job_uuid: ::uuid::Uuid,
+    pub dependency_uuid: Option<::uuid::Uuid>,
+
+    /// The image of the job, not recorded for submits from before multiple images could be built
+    /// in one submit
+    pub image_id: Option<i32>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = job_dag_edges)]
+struct NewJobDagEdge {
+    pub submit_id: i32,
+    pub package_id: i32,
+    pub job_uuid: ::uuid::Uuid,
+    pub dependency_uuid: Option<::uuid::Uuid>,
+    pub image_id: Option<i32>,
+}
+
+impl JobDagEdge {
+    /// Record the job DAG of `submit`
+    pub fn create_for_dag(database_connection: &mut PgConnection, submit: &Submit, dag: &crate::job::Dag) -> Result<()> {
+        database_connection.transaction::<_, Error, _>(|conn| {
+            for definition in dag.iter() {
+                let package = Package::create_or_fetch(conn, definition.job.package())?;
+                let image = Image::create_or_fetch(conn, definition.job.image())?;
+                let dependencies = if definition.dependencies.is_empty() {
+                    vec![None]
+                } else {
+                    definition.dependencies.iter().copied().map(Some).collect()
+                };
+
+                let edges = dependencies
+                    .into_iter()
+                    .map(|dependency_uuid| NewJobDagEdge {
+                        submit_id: submit.id,
+                        package_id: package.id,
+                        job_uuid: *definition.job.uuid(),
+                        dependency_uuid,
+                        image_id: Some(image.id),
+                    })
+                    .collect::<Vec<_>>();
+
+                diesel::insert_into(job_dag_edges::table)
+                    .values(&edges)
+                    .execute(conn)
+                    .with_context(|| anyhow!("Recording dependencies of job {}", definition.job.uuid()))?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Replace the job `old` with the job `new` in the job DAG of `submit`, e.g. to run a failed
+    /// job of a recovered submit again
+    pub fn replace_job_uuid(database_connection: &mut PgConnection, submit: &Submit, old: &::uuid::Uuid, new: &::uuid::Uuid) -> Result<()> {
+        database_connection.transaction::<_, Error, _>(|conn| {
+            diesel::update(job_dag_edges::table)
+                .filter(job_dag_edges::submit_id.eq(submit.id))
+                .filter(job_dag_edges::job_uuid.eq(old))
+                .set(job_dag_edges::job_uuid.eq(new))
+                .execute(conn)?;
+
+            diesel::update(job_dag_edges::table)
+                .filter(job_dag_edges::submit_id.eq(submit.id))
+                .filter(job_dag_edges::dependency_uuid.eq(old))
+                .set(job_dag_edges::dependency_uuid.eq(new))
+                .execute(conn)?;
+            Ok(())
+        })
+        .with_context(|| anyhow!("Replacing job {} with {} in the job DAG of submit {}", old, new, submit.uuid))
+    }
+
+    /// Load the job DAG of `submit`, with the package of each job
+    pub fn for_submit(database_connection: &mut PgConnection, submit: &Submit) -> Result<Vec<(JobDagEdge, Package)>> {
+        job_dag_edges::table
+            .inner_join(crate::schema::packages::table)
+            .filter(job_dag_edges::submit_id.eq(submit.id))
+            .load::<(JobDagEdge, Package)>(database_connection)
+            .map_err(Error::from)
+    }
+}
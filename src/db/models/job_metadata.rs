@@ -0,0 +1,65 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Error;
+use anyhow::Result;
+use diesel::prelude::*;
+use diesel::PgConnection;
+
+use crate::db::models::Job;
+use crate::schema::job_metadata;
+
+/// The output of a capture command of the package of a job (e.g. `gcc --version`), by the name of
+/// the command
+#[derive(Debug, Identifiable, Queryable, Associations)]
+#[diesel(belongs_to(Job))]
+#[diesel(table_name = job_metadata)]
+pub struct JobMetadata {
+    pub id: i32,
+    pub job_id: i32,
+    pub name: String,
+    pub value: String,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = job_metadata)]
+struct NewJobMetadata<'a> {
+    pub job_id: i32,
+    pub name: &'a str,
+    pub value: &'a str,
+}
+
+impl JobMetadata {
+    /// Record the `metadata` of `job`
+    pub fn create_all(database_connection: &mut PgConnection, job: &Job, metadata: &[(String, String)]) -> Result<()> {
+        let new_metadata = metadata
+            .iter()
+            .map(|(name, value)| NewJobMetadata { job_id: job.id, name, value })
+            .collect::<Vec<_>>();
+
+        diesel::insert_into(job_metadata::table)
+            .values(&new_metadata)
+            .on_conflict_do_nothing()
+            .execute(database_connection)
+            .with_context(|| anyhow!("Recording metadata of job {}", job.uuid))?;
+        Ok(())
+    }
+
+    /// Load the metadata of the jobs with the ids `job_ids`
+    pub fn for_jobs(database_connection: &mut PgConnection, job_ids: &[i32]) -> Result<Vec<JobMetadata>> {
+        job_metadata::table
+            .filter(job_metadata::job_id.eq_any(job_ids))
+            .order_by(job_metadata::name)
+            .load::<JobMetadata>(database_connection)
+            .map_err(Error::from)
+    }
+}
@@ -0,0 +1,114 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Error;
+use anyhow::Result;
+use diesel::prelude::*;
+use diesel::PgConnection;
+
+use crate::db::models::Endpoint;
+use crate::db::models::Submit;
+use crate::schema::job_states;
+
+/// The state of a job of a submit, for recovering the submit if butido was killed
+///
+/// The jobs of a submit are recorded as planned when the submit starts. Running jobs are recorded
+/// with the container they run in, so that the containers can be cleaned up when the submit is
+/// recovered.
+#[derive(Debug, Identifiable, Queryable, Associations)]
+#[diesel(belongs_to(Submit))]
+#[diesel(belongs_to(Endpoint))]
+#[diesel(table_name = job_states)]
+pub struct JobState {
+    pub id: i32,
+    pub submit_id: i32,
+    pub job_uuid: ::uuid::Uuid,
+    pub state: String,
+    pub endpoint_id: Option<i32>,
+    pub container_hash: Option<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = job_states)]
+struct NewJobState<'a> {
+    pub submit_id: i32,
+    pub job_uuid: ::uuid::Uuid,
+    pub state: &'a str,
+}
+
+impl JobState {
+    pub const PLANNED: &'static str = "planned";
+    pub const RUNNING: &'static str = "running";
+    pub const FINISHED: &'static str = "finished";
+
+    /// Record the jobs of `dag` as planned, jobs that are already recorded keep their state
+    pub fn create_for_dag(database_connection: &mut PgConnection, submit: &Submit, dag: &crate::job::Dag) -> Result<()> {
+        let new_states = dag
+            .iter()
+            .map(|definition| NewJobState {
+                submit_id: submit.id,
+                job_uuid: *definition.job.uuid(),
+                state: Self::PLANNED,
+            })
+            .collect::<Vec<_>>();
+
+        diesel::insert_into(job_states::table)
+            .values(&new_states)
+            .on_conflict_do_nothing()
+            .execute(database_connection)
+            .with_context(|| anyhow!("Recording job states of submit {}", submit.uuid))?;
+        Ok(())
+    }
+
+    /// Record that the job `job_uuid` runs in `container_hash` on `endpoint`
+    pub fn set_running(
+        database_connection: &mut PgConnection,
+        submit: &Submit,
+        job_uuid: &::uuid::Uuid,
+        endpoint: &Endpoint,
+        container_hash: &str,
+    ) -> Result<()> {
+        diesel::update(job_states::table)
+            .filter(job_states::submit_id.eq(submit.id))
+            .filter(job_states::job_uuid.eq(job_uuid))
+            .set((
+                job_states::state.eq(Self::RUNNING),
+                job_states::endpoint_id.eq(endpoint.id),
+                job_states::container_hash.eq(container_hash),
+            ))
+            .execute(database_connection)
+            .with_context(|| anyhow!("Setting state of job {} to {}", job_uuid, Self::RUNNING))
+            .map(|_| ())
+            .map_err(Error::from)
+    }
+
+    /// Record that the job `job_uuid` finished and is recorded in the jobs
+    pub fn set_finished(database_connection: &mut PgConnection, submit: &Submit, job_uuid: &::uuid::Uuid) -> Result<()> {
+        diesel::update(job_states::table)
+            .filter(job_states::submit_id.eq(submit.id))
+            .filter(job_states::job_uuid.eq(job_uuid))
+            .set(job_states::state.eq(Self::FINISHED))
+            .execute(database_connection)
+            .with_context(|| anyhow!("Setting state of job {} to {}", job_uuid, Self::FINISHED))
+            .map(|_| ())
+            .map_err(Error::from)
+    }
+
+    /// Load the states of the jobs of `submit`, with the endpoint they ran on
+    pub fn for_submit(database_connection: &mut PgConnection, submit: &Submit) -> Result<Vec<(JobState, Option<Endpoint>)>> {
+        job_states::table
+            .left_join(crate::schema::endpoints::table)
+            .filter(job_states::submit_id.eq(submit.id))
+            .load::<(JobState, Option<Endpoint>)>(database_connection)
+            .map_err(Error::from)
+    }
+}
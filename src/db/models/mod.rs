@@ -14,24 +14,45 @@ pub use artifact::*;
 mod endpoint;
 pub use endpoint::*;
 
+mod endpoint_definition;
+pub use endpoint_definition::*;
+
+mod endpoint_reservation;
+pub use endpoint_reservation::*;
+
 mod envvar;
 pub use envvar::*;
 
 mod image;
 pub use image::*;
 
+mod image_definition;
+pub use image_definition::*;
+
 mod job;
 pub use job::*;
 
+mod job_dag_edge;
+pub use job_dag_edge::*;
+
 mod job_env;
 pub use job_env::*;
 
+mod job_metadata;
+pub use job_metadata::*;
+
+mod job_state;
+pub use job_state::*;
+
 mod githash;
 pub use githash::*;
 
 mod package;
 pub use package::*;
 
+mod package_definition;
+pub use package_definition::*;
+
 mod releases;
 pub use releases::*;
 
@@ -40,3 +61,9 @@ pub use release_store::*;
 
 mod submit;
 pub use submit::*;
+
+mod submit_label;
+pub use submit_label::*;
+
+mod submit_plan;
+pub use submit_plan::*;
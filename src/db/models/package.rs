@@ -36,10 +36,19 @@ impl Package {
     pub fn create_or_fetch(
         database_connection: &mut PgConnection,
         p: &crate::package::Package,
+    ) -> Result<Package> {
+        Self::create_or_fetch_by_name(database_connection, p.name().deref(), p.version().deref())
+    }
+
+    /// Like `create_or_fetch()`, for packages that are not (necessarily) in the repository
+    pub fn create_or_fetch_by_name(
+        database_connection: &mut PgConnection,
+        p_name: &str,
+        p_vers: &str,
     ) -> Result<Package> {
         let new_package = NewPackage {
-            name: p.name().deref(),
-            version: p.version().deref(),
+            name: p_name,
+            version: p_vers,
         };
 
         database_connection.transaction::<_, Error, _>(|conn| {
@@ -49,12 +58,7 @@ impl Package {
                 .execute(conn)?;
 
             dsl::packages
-                .filter({
-                    let p_name = p.name().deref();
-                    let p_vers = p.version().deref();
-
-                    name.eq(p_name).and(version.eq(p_vers))
-                })
+                .filter(name.eq(p_name).and(version.eq(p_vers)))
                 .first::<Package>(conn)
                 .map_err(Error::from)
         })
@@ -0,0 +1,83 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Error;
+use anyhow::Result;
+use diesel::prelude::*;
+use diesel::PgConnection;
+
+use crate::db::models::Package;
+use crate::db::models::Submit;
+use crate::schema::package_definitions;
+
+/// The definition of a package as it was in the repository when a submit was made
+#[derive(Debug, Identifiable, Queryable, Associations)]
+#[diesel(belongs_to(Submit))]
+#[diesel(belongs_to(Package))]
+#[diesel(table_name = package_definitions)]
+pub struct PackageDefinition {
+    pub id: i32,
+    pub submit_id: i32,
+    pub package_id: i32,
+    pub definition: String,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = package_definitions)]
+struct NewPackageDefinition<'a> {
+    pub submit_id: i32,
+    pub package_id: i32,
+    pub definition: &'a str,
+}
+
+impl PackageDefinition {
+    /// Record the definition of `package` for `submit`, serialized as JSON
+    pub fn create(
+        database_connection: &mut PgConnection,
+        submit: &Submit,
+        package: &crate::package::Package,
+    ) -> Result<()> {
+        let db_package = Package::create_or_fetch(database_connection, package)?;
+        let definition = serde_json::to_string_pretty(package)
+            .with_context(|| anyhow!("Serializing definition of package {} {}", package.name(), package.version()))?;
+
+        diesel::insert_into(package_definitions::table)
+            .values(&NewPackageDefinition {
+                submit_id: submit.id,
+                package_id: db_package.id,
+                definition: &definition,
+            })
+            .on_conflict_do_nothing()
+            .execute(database_connection)
+            .with_context(|| anyhow!("Recording definition of package {} {}", package.name(), package.version()))?;
+        Ok(())
+    }
+
+    /// Load the recorded definitions of all packages of `submit`
+    pub fn for_submit(database_connection: &mut PgConnection, submit: &Submit) -> Result<Vec<(PackageDefinition, Package)>> {
+        package_definitions::table
+            .inner_join(crate::schema::packages::table)
+            .filter(package_definitions::submit_id.eq(submit.id))
+            .order_by((crate::schema::packages::name, crate::schema::packages::version))
+            .load::<(PackageDefinition, Package)>(database_connection)
+            .map_err(Error::from)
+    }
+
+    /// Load the recorded definition of the package of `job`
+    pub fn for_job(database_connection: &mut PgConnection, job: &crate::db::models::Job) -> Result<Option<PackageDefinition>> {
+        package_definitions::table
+            .filter(package_definitions::submit_id.eq(job.submit_id))
+            .filter(package_definitions::package_id.eq(job.package_id))
+            .first::<PackageDefinition>(database_connection)
+            .optional()
+            .map_err(Error::from)
+    }
+}
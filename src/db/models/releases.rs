@@ -27,6 +27,11 @@ pub struct Release {
     pub artifact_id: i32,
     pub release_date: NaiveDateTime,
     pub release_store_id: i32,
+    pub released_by: Option<String>,
+    pub signature_path: Option<String>,
+
+    /// The path of the released artifact, relative to the root of the release store
+    pub path: String,
 }
 
 #[derive(Insertable)]
@@ -35,6 +40,9 @@ struct NewRelease<'a> {
     pub artifact_id: i32,
     pub release_date: &'a NaiveDateTime,
     pub release_store_id: i32,
+    pub released_by: Option<&'a str>,
+    pub signature_path: Option<&'a str>,
+    pub path: &'a str,
 }
 
 impl Release {
@@ -43,11 +51,17 @@ impl Release {
         art: &Artifact,
         date: &'a NaiveDateTime,
         store: &'a ReleaseStore,
+        operator: Option<&'a str>,
+        signature: Option<&'a str>,
+        release_path: &'a str,
     ) -> Result<Release> {
         let new_rel = NewRelease {
             artifact_id: art.id,
             release_date: date,
             release_store_id: store.id,
+            released_by: operator,
+            signature_path: signature,
+            path: release_path,
         };
 
         database_connection.transaction::<_, Error, _>(|conn| {
@@ -32,6 +32,13 @@ pub struct Submit {
     pub requested_image_id: i32,
     pub requested_package_id: i32,
     pub repo_hash_id: i32,
+    pub submitted_by: Option<String>,
+
+    /// The host the submit was made from
+    pub submitted_from: Option<String>,
+
+    /// The version of butido the submit was made with
+    pub butido_version: Option<String>,
 }
 
 #[derive(Insertable)]
@@ -42,6 +49,9 @@ struct NewSubmit<'a> {
     pub requested_image_id: i32,
     pub requested_package_id: i32,
     pub repo_hash_id: i32,
+    pub submitted_by: Option<&'a str>,
+    pub submitted_from: Option<&'a str>,
+    pub butido_version: &'a str,
 }
 
 impl Submit {
@@ -52,13 +62,18 @@ impl Submit {
         requested_image: &Image,
         requested_package: &Package,
         repo_hash: &GitHash,
+        submitter: Option<&str>,
     ) -> Result<Submit> {
+        let hostname = crate::util::hostname();
         let new_submit = NewSubmit {
             uuid: submit_id,
             submit_time: submit_datetime,
             requested_image_id: requested_image.id,
             requested_package_id: requested_package.id,
             repo_hash_id: repo_hash.id,
+            submitted_by: submitter,
+            submitted_from: hostname.as_deref(),
+            butido_version: env!("CARGO_PKG_VERSION"),
         };
 
         database_connection.transaction::<_, Error, _>(|conn| {
@@ -0,0 +1,69 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Error;
+use anyhow::Result;
+use diesel::prelude::*;
+use diesel::PgConnection;
+
+use crate::db::models::Submit;
+use crate::schema::submit_labels;
+
+/// A free-form "key=value" label a submit was tagged with
+#[derive(Debug, Identifiable, Queryable, Associations)]
+#[diesel(belongs_to(Submit))]
+#[diesel(table_name = submit_labels)]
+pub struct SubmitLabel {
+    pub id: i32,
+    pub submit_id: i32,
+    pub key: String,
+    pub value: String,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = submit_labels)]
+struct NewSubmitLabel<'a> {
+    pub submit_id: i32,
+    pub key: &'a str,
+    pub value: &'a str,
+}
+
+impl SubmitLabel {
+    /// Record the `labels` of `submit`
+    pub fn create_all(database_connection: &mut PgConnection, submit: &Submit, labels: &[(String, String)]) -> Result<()> {
+        let new_labels = labels
+            .iter()
+            .map(|(key, value)| NewSubmitLabel { submit_id: submit.id, key, value })
+            .collect::<Vec<_>>();
+
+        diesel::insert_into(submit_labels::table)
+            .values(&new_labels)
+            .on_conflict_do_nothing()
+            .execute(database_connection)
+            .with_context(|| anyhow!("Recording labels of submit {}", submit.uuid))?;
+        Ok(())
+    }
+
+    /// Load the labels of the submits with the ids `submit_ids`
+    pub fn for_submits(database_connection: &mut PgConnection, submit_ids: &[i32]) -> Result<Vec<SubmitLabel>> {
+        submit_labels::table
+            .filter(submit_labels::submit_id.eq_any(submit_ids))
+            .order_by((submit_labels::key, submit_labels::value))
+            .load::<SubmitLabel>(database_connection)
+            .map_err(Error::from)
+    }
+}
+
+impl std::fmt::Display for SubmitLabel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}={}", self.key, self.value)
+    }
+}
@@ -0,0 +1,58 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Error;
+use anyhow::Result;
+use diesel::prelude::*;
+use diesel::PgConnection;
+
+use crate::db::models::Submit;
+use crate::schema::submit_plans;
+
+/// The parameters of the build of a submit (as JSON), to recreate its job DAG when the submit is
+/// recovered
+#[derive(Debug, Identifiable, Queryable, Associations)]
+#[diesel(belongs_to(Submit))]
+#[diesel(table_name = submit_plans)]
+pub struct SubmitPlan {
+    pub id: i32,
+    pub submit_id: i32,
+    pub plan: String,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = submit_plans)]
+struct NewSubmitPlan<'a> {
+    pub submit_id: i32,
+    pub plan: &'a str,
+}
+
+impl SubmitPlan {
+    /// Record the `plan` of `submit`, the plan of a recovered submit is kept
+    pub fn create(database_connection: &mut PgConnection, submit: &Submit, plan: &str) -> Result<()> {
+        diesel::insert_into(submit_plans::table)
+            .values(&NewSubmitPlan { submit_id: submit.id, plan })
+            .on_conflict_do_nothing()
+            .execute(database_connection)
+            .with_context(|| anyhow!("Recording plan of submit {}", submit.uuid))?;
+        Ok(())
+    }
+
+    /// Load the plan of `submit`, if it was recorded
+    pub fn for_submit(database_connection: &mut PgConnection, submit: &Submit) -> Result<Option<SubmitPlan>> {
+        submit_plans::table
+            .filter(submit_plans::submit_id.eq(submit.id))
+            .first::<SubmitPlan>(database_connection)
+            .optional()
+            .map_err(Error::from)
+    }
+}
@@ -0,0 +1,175 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::io::Read;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use anyhow::Context;
+use anyhow::Error;
+use anyhow::Result;
+use tracing::trace;
+
+use crate::filestore::path::strip_outputs_dir;
+use crate::filestore::path::StoreRoot;
+use crate::filestore::ArtifactPath;
+
+/// An in-memory cache for the artifacts that were built on an endpoint
+///
+/// Jobs that run on the same endpoint as the jobs they depend on get the artifacts from this cache
+/// instead of reading them back from the staging store.
+/// The artifacts are cached uncompressed, by the root of the staging store and their (uncompressed)
+/// path in it, so that the jobs of different submits on the same endpoint do not get each others
+/// artifacts. If the cache is full, the oldest artifacts are evicted.
+#[derive(Debug)]
+pub struct ArtifactCache {
+    max_bytes: u64,
+    inner: Mutex<CacheInner>,
+}
+
+/// The root of the staging store and the path of an artifact in it
+type CacheKey = (StoreRoot, ArtifactPath);
+
+#[derive(Debug, Default)]
+struct CacheInner {
+    bytes: u64,
+    order: VecDeque<CacheKey>,
+    artifacts: HashMap<CacheKey, Arc<Vec<u8>>>,
+}
+
+impl ArtifactCache {
+    pub fn new(max_bytes: u64) -> Self {
+        ArtifactCache {
+            max_bytes,
+            inner: Mutex::new(CacheInner::default()),
+        }
+    }
+
+    pub fn insert(&self, root: &StoreRoot, path: ArtifactPath, data: Vec<u8>) {
+        let len = data.len() as u64;
+        if len > self.max_bytes {
+            trace!("Not caching {}, it is larger than the cache", path.display());
+            return
+        }
+
+        let key = (root.clone(), path);
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(old) = inner.artifacts.insert(key.clone(), Arc::new(data)) {
+            inner.bytes -= old.len() as u64;
+            inner.order.retain(|k| *k != key);
+        }
+        inner.bytes += len;
+        inner.order.push_back(key);
+
+        while inner.bytes > self.max_bytes {
+            let Some(evicted) = inner.order.pop_front() else { break };
+            if let Some(data) = inner.artifacts.remove(&evicted) {
+                trace!("Evicting {} from artifact cache", evicted.1.display());
+                inner.bytes -= data.len() as u64;
+            }
+        }
+    }
+
+    /// Get the artifact `path` in the staging store at `root`
+    pub fn get(&self, root: &StoreRoot, path: &ArtifactPath) -> Option<Arc<Vec<u8>>> {
+        let key = (root.clone(), path.clone());
+        self.inner.lock().unwrap().artifacts.get(&key).cloned()
+    }
+
+    /// Cache all files from a tar archive that was fetched from the outputs of a container
+    ///
+    /// The files are cached with the paths they are written to in the staging store at `root`:
+    /// the paths in the outputs directory, in `subdir`.
+    pub fn insert_from_tar(&self, root: &StoreRoot, subdir: &Path, bytes: &[u8]) -> Result<()> {
+        let mut archive = tar::Archive::new(bytes);
+        for entry in archive.entries().context("Reading TAR")? {
+            let mut entry = entry.context("Reading entry from TAR")?;
+            if entry.header().entry_type() != tar::EntryType::Regular {
+                continue
+            }
+
+            let path = entry
+                .path()
+                .context("Getting path from entry in Archive")
+                .map(|p| subdir.join(strip_outputs_dir(&p)))?;
+            let path = ArtifactPath::new(path)?;
+
+            let mut data = Vec::with_capacity(entry.size() as usize);
+            entry.read_to_end(&mut data)
+                .context("Reading entry from TAR")
+                .map_err(Error::from)?;
+            trace!("Caching {} ({} bytes)", path.display(), data.len());
+            self.insert(root, path, data);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ap(s: &str) -> ArtifactPath {
+        ArtifactPath::new(std::path::PathBuf::from(s)).unwrap()
+    }
+
+    fn root() -> StoreRoot {
+        let dir = std::env::temp_dir().join(format!("butido-artifact-cache-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        StoreRoot::new(dir).unwrap()
+    }
+
+    #[test]
+    fn test_evicts_oldest() {
+        let root = &root();
+        let cache = ArtifactCache::new(10);
+        cache.insert(root, ap("a"), vec![0; 4]);
+        cache.insert(root, ap("b"), vec![0; 4]);
+        cache.insert(root, ap("c"), vec![0; 4]);
+
+        assert!(cache.get(root, &ap("a")).is_none());
+        assert!(cache.get(root, &ap("b")).is_some());
+        assert!(cache.get(root, &ap("c")).is_some());
+
+        cache.insert(root, ap("d"), vec![0; 11]);
+        assert!(cache.get(root, &ap("d")).is_none());
+    }
+
+    #[test]
+    fn test_keyed_by_store() {
+        let (a, b, c) = (root(), root(), root());
+        let cache = ArtifactCache::new(10);
+        cache.insert(&a, ap("a"), vec![1]);
+        cache.insert(&b, ap("a"), vec![2]);
+
+        assert_eq!(cache.get(&a, &ap("a")).unwrap().as_slice(), &[1]);
+        assert_eq!(cache.get(&b, &ap("a")).unwrap().as_slice(), &[2]);
+        assert!(cache.get(&c, &ap("a")).is_none());
+    }
+
+    #[test]
+    fn test_insert_from_tar() {
+        let mut builder = tar::Builder::new(Vec::new());
+        let data = b"package";
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_cksum();
+        builder.append_data(&mut header, "outputs/foo-1.0.pkg", &data[..]).unwrap();
+        let bytes = builder.into_inner().unwrap();
+
+        let root = &root();
+        let cache = ArtifactCache::new(1024);
+        cache.insert_from_tar(root, Path::new("deb11"), &bytes).unwrap();
+        assert_eq!(cache.get(root, &ap("deb11/foo-1.0.pkg")).unwrap().as_slice(), data);
+    }
+}
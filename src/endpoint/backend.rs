@@ -0,0 +1,117 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! The container runtimes jobs can be run with
+//!
+//! An endpoint runs the containers of its jobs via a [ContainerBackend]. The lifecycle of a job
+//! container is the same for all backends: the container is created, the inputs and the script
+//! are copied into it, it is started, the script is executed while its output is streamed into
+//! the log, the outputs are copied out of it and finally it is stopped and, depending on the
+//! cleanup policy, removed.
+
+use std::path::Path;
+
+use anyhow::Result;
+use futures::future::BoxFuture;
+use futures::stream::BoxStream;
+use futures::StreamExt;
+
+use crate::util::docker::ContainerPaths;
+use crate::util::docker::ContainerUser;
+use crate::util::docker::ImageName;
+
+/// The description of a container that is created for a job
+#[derive(Debug)]
+pub struct ContainerSpec<'a> {
+    /// The name of the container, unique for the job
+    pub name: String,
+
+    /// The image the container is created from
+    pub image: &'a ImageName,
+
+    /// The environment of the container, as "NAME=value" pairs
+    pub env: Vec<String>,
+
+    /// The user the processes in the container run as, the default user of the image if not set
+    pub user: Option<ContainerUser>,
+
+    /// The network mode of the container, the default of the backend if not set
+    pub network_mode: Option<&'a str>,
+
+    /// The paths of the script, the inputs and the outputs in the container
+    pub paths: &'a ContainerPaths,
+}
+
+/// A container runtime
+///
+/// Containers are identified by the ID the backend returned when creating them.
+/// A sample of the resource usage of a container
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct UsageSample {
+    /// The CPU time used by the container so far, in nanoseconds
+    pub cpu_ns: u64,
+
+    /// The memory used by the container
+    pub memory_bytes: u64,
+
+    /// The bytes read from and written to block devices by the container so far
+    pub io_bytes: u64,
+}
+
+pub trait ContainerBackend: Send + Sync {
+    /// Create a container, returns the ID of the container
+    fn create<'a>(&'a self, spec: &'a ContainerSpec<'a>) -> BoxFuture<'a, Result<String>>;
+
+    /// Copy a file with `content` into the container, at `path`
+    fn copy_file_into<'a>(&'a self, id: &'a str, path: &'a Path, content: &'a [u8]) -> BoxFuture<'a, Result<()>>;
+
+    /// Extract the tar `archive` at "/" in the container
+    fn copy_archive_into<'a>(&'a self, id: &'a str, archive: Vec<u8>) -> BoxFuture<'a, Result<()>>;
+
+    /// Start the container
+    fn start<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<()>>;
+
+    /// Execute `cmd` in the running container, returns the ID of the execution
+    fn exec<'a>(&'a self, id: &'a str, cmd: &'a [&'a str]) -> BoxFuture<'a, Result<String>>;
+
+    /// The command that executes the script at `script_path` in a container
+    fn script_command(&self, script_path: &str) -> Vec<String> {
+        vec![String::from("/bin/bash"), script_path.to_string()]
+    }
+
+    /// Stream the output (stdout and stderr) of an execution
+    fn exec_output<'a>(&'a self, exec_id: &'a str) -> BoxStream<'a, Result<Vec<u8>>>;
+
+    /// The exit code of a finished execution, if the backend knows it
+    fn exec_exit_code<'a>(&'a self, exec_id: &'a str) -> BoxFuture<'a, Result<Option<i32>>>;
+
+    /// Whether a process in the container was killed because the container ran out of memory
+    fn oom_killed<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<bool>>;
+
+    /// Stream samples of the resource usage of the running container
+    ///
+    /// The stream ends when the container stops. Backends that cannot sample the resource usage
+    /// return an empty stream.
+    fn usage_samples<'a>(&'a self, _id: &'a str) -> BoxStream<'a, Result<UsageSample>> {
+        futures::stream::empty().boxed()
+    }
+
+    /// Copy `path` out of the container, as stream of the chunks of a tar archive
+    ///
+    /// Returns `None` if `path` does not exist in the container.
+    #[allow(clippy::type_complexity)]
+    fn copy_from<'a>(&'a self, id: &'a str, path: &'a Path) -> BoxFuture<'a, Result<Option<BoxStream<'a, Result<Vec<u8>>>>>>;
+
+    /// Stop the container
+    fn stop<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<()>>;
+
+    /// Remove the container, even if it is still running
+    fn remove<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<()>>;
+}
@@ -8,9 +8,12 @@
 // SPDX-License-Identifier: EPL-2.0
 //
 
+use std::collections::HashMap;
+
 use getset::Getters;
 use typed_builder::TypedBuilder;
 
+use crate::config::RegistryConfig;
 use crate::util::docker::ImageName;
 
 #[derive(Getters, TypedBuilder)]
@@ -32,4 +35,14 @@ pub struct EndpointConfiguration {
     #[getset(get = "pub")]
     #[builder(default)]
     required_docker_api_versions: Option<Vec<String>>,
+
+    /// Pull the required images that are missing on the endpoint
+    #[getset(get = "pub")]
+    #[builder(default)]
+    pull_missing_images: bool,
+
+    /// The credentials for pulling images, by the hostname of the registry
+    #[getset(get = "pub")]
+    #[builder(default)]
+    registries: HashMap<String, RegistryConfig>,
 }
@@ -8,7 +8,10 @@
 // SPDX-License-Identifier: EPL-2.0
 //
 
+use std::collections::BTreeMap;
+use std::collections::HashMap;
 use std::fmt::{Debug, Formatter};
+use std::path::Path;
 use std::path::PathBuf;
 use std::str::FromStr;
 use std::sync::Arc;
@@ -18,28 +21,46 @@ use anyhow::Error;
 use anyhow::Result;
 use anyhow::anyhow;
 use futures::FutureExt;
+use futures::TryStreamExt;
 use getset::{CopyGetters, Getters};
-use tracing::{trace, debug};
+use indicatif::ProgressBar;
+use tracing::{trace, debug, info, warn};
 use result_inspect::ResultInspect;
 use shiplift::Container;
 use shiplift::Docker;
-use shiplift::ExecContainerOptions;
 use tokio::sync::RwLock;
 use tokio::sync::mpsc::UnboundedSender;
 use tokio_stream::StreamExt;
 use typed_builder::TypedBuilder;
 
+use crate::config::ContainerCleanupPolicy;
 use crate::config::EndpointName;
+use crate::config::RegistryConfig;
+use crate::config::VersionCheckPolicy;
+use crate::db::models as dbmodels;
+use crate::endpoint::ArtifactCache;
+use crate::endpoint::ContainerBackend;
+use crate::endpoint::ContainerSpec;
+use crate::endpoint::DockerBackend;
 use crate::endpoint::EndpointConfiguration;
+use crate::endpoint::KubernetesBackend;
+use crate::endpoint::LocalBackend;
+use crate::endpoint::RateLimiter;
+use crate::endpoint::ResourceUsage;
+use crate::filestore::Compression;
 use crate::filestore::ReleaseStore;
 use crate::filestore::StagingStore;
+use crate::filestore::path::ArchiveLimitCheck;
 use crate::filestore::path::ArtifactPath;
+use crate::filestore::path::UnpackLimits;
 use crate::job::JobResource;
 use crate::job::RunnableJob;
 use crate::log::LogItem;
 use crate::log::buffer_stream_to_line_stream;
 use crate::package::Script;
 use crate::util::docker::ContainerHash;
+use crate::util::docker::ContainerPaths;
+use crate::util::docker::ContainerUser;
 use crate::util::docker::ImageName;
 
 #[derive(Getters, CopyGetters, TypedBuilder)]
@@ -47,8 +68,12 @@ pub struct Endpoint {
     #[getset(get = "pub")]
     name: EndpointName,
 
-    #[getset(get = "pub")]
-    docker: Docker,
+    /// The Docker API of the endpoint, if it is a Docker endpoint
+    #[builder(default)]
+    docker: Option<Docker>,
+
+    /// The backend the containers of the jobs are run with
+    backend: Box<dyn ContainerBackend>,
 
     #[getset(get_copy = "pub")]
     num_max_jobs: usize,
@@ -61,6 +86,24 @@ pub struct Endpoint {
 
     #[builder(default)]
     running_jobs: std::sync::atomic::AtomicUsize,
+
+    /// The number of jobs that failed on the endpoint
+    #[builder(default)]
+    failed_jobs: std::sync::atomic::AtomicUsize,
+
+    /// The job that failed last on the endpoint
+    #[builder(default)]
+    last_failure: std::sync::Mutex<Option<String>>,
+
+    #[builder(default)]
+    artifact_cache: Option<Arc<ArtifactCache>>,
+
+    /// Limits the rate the outputs are copied from the containers with
+    #[builder(default)]
+    rate_limiter: Option<RateLimiter>,
+
+    #[builder(default)]
+    container_cleanup: ContainerCleanupPolicy,
 }
 
 impl Debug for Endpoint {
@@ -71,7 +114,7 @@ impl Debug for Endpoint {
 
 impl Endpoint {
     pub(super) async fn setup(epc: EndpointConfiguration) -> Result<Self> {
-        let ep = Endpoint::setup_endpoint(epc.endpoint_name(), epc.endpoint()).with_context(|| {
+        let ep = Endpoint::setup_endpoint(epc.endpoint_name(), epc.endpoint()).await.with_context(|| {
             anyhow!(
                 "Setting up endpoint: {} -> {}",
                 epc.endpoint_name(),
@@ -79,11 +122,24 @@ impl Endpoint {
             )
         })?;
 
-        let versions_compat =
-            Endpoint::check_version_compat(epc.required_docker_versions().as_ref(), &ep);
-        let api_versions_compat =
-            Endpoint::check_api_version_compat(epc.required_docker_api_versions().as_ref(), &ep);
-        let imgs_avail = Endpoint::check_images_available(epc.required_images().as_ref(), &ep);
+        if ep.docker.is_none() {
+            // The images are pulled by the cluster when the pods are created, or not used at all
+            // by local endpoints
+            debug!("Not checking docker versions and images of endpoint {}", epc.endpoint_name());
+            return Ok(ep);
+        }
+
+        let version_check = epc.endpoint().version_check();
+        let (required_versions, required_api_versions) = match version_check {
+            VersionCheckPolicy::Ignore => {
+                debug!("Not checking docker versions of endpoint {}", epc.endpoint_name());
+                (None, None)
+            },
+            _ => (epc.required_docker_versions().as_ref(), epc.required_docker_api_versions().as_ref()),
+        };
+        let versions_compat = Endpoint::check_version_compat(required_versions, &ep);
+        let api_versions_compat = Endpoint::check_api_version_compat(required_api_versions, &ep);
+        let imgs_avail = Endpoint::missing_images(epc.required_images().as_ref(), &ep);
 
         let (versions_compat, api_versions_compat, imgs_avail) = {
             let timeout = std::time::Duration::from_secs(epc.endpoint().timeout().unwrap_or(10));
@@ -93,32 +149,80 @@ impl Endpoint {
             tokio::join!(versions_compat, api_versions_compat, imgs_avail)
         };
 
-        let _ = versions_compat.with_context(|| {
-            anyhow!(
-                "Checking version compatibility for {} -> {}",
-                epc.endpoint_name(),
-                epc.endpoint().uri()
-            )
-        })?;
-        let _ = api_versions_compat.with_context(|| {
-            anyhow!(
-                "Checking API version compatibility for {} -> {}",
-                epc.endpoint_name(),
-                epc.endpoint().uri()
-            )
-        })?;
-        let _ = imgs_avail.with_context(|| {
-            anyhow!(
-                "Checking for available images on {} -> {}",
-                epc.endpoint_name(),
-                epc.endpoint().uri()
-            )
-        })?;
+        let versions_compat = versions_compat
+            .map_err(Error::from)
+            .and_then(|r| r)
+            .with_context(|| {
+                anyhow!(
+                    "Checking version compatibility for {} -> {}",
+                    epc.endpoint_name(),
+                    epc.endpoint().uri()
+                )
+            });
+        let api_versions_compat = api_versions_compat
+            .map_err(Error::from)
+            .and_then(|r| r)
+            .with_context(|| {
+                anyhow!(
+                    "Checking API version compatibility for {} -> {}",
+                    epc.endpoint_name(),
+                    epc.endpoint().uri()
+                )
+            });
+
+        for compat in [versions_compat, api_versions_compat] {
+            match (compat, version_check) {
+                (Ok(()), _) => {},
+                (Err(e), VersionCheckPolicy::Warn) => {
+                    warn!("{:?}", e);
+                    warn!("Using endpoint {} anyways, as configured", epc.endpoint_name());
+                },
+                (Err(e), _) => return Err(e),
+            }
+        }
+        let missing_images = imgs_avail
+            .map_err(Error::from)
+            .and_then(|r| r)
+            .with_context(|| {
+                anyhow!(
+                    "Checking for available images on {} -> {}",
+                    epc.endpoint_name(),
+                    epc.endpoint().uri()
+                )
+            })?;
+
+        // Pulling can take a while, so it is not subject to the timeout of the endpoint
+        for image in missing_images {
+            if *epc.pull_missing_images() {
+                ep.pull_image(&image, epc.registries()).await?;
+            } else {
+                return Err(anyhow!("Image '{}' missing from endpoint '{}'", image.as_ref(), ep.name))
+            }
+        }
 
         Ok(ep)
     }
 
-    fn setup_endpoint(ep_name: &EndpointName, ep: &crate::config::Endpoint) -> Result<Endpoint> {
+    async fn setup_endpoint(ep_name: &EndpointName, ep: &crate::config::Endpoint) -> Result<Endpoint> {
+        let artifact_cache = ep.artifact_cache_size()
+            .as_ref()
+            .map(|size| {
+                size.parse::<bytesize::ByteSize>()
+                    .map_err(|e| anyhow!("{}", e))
+                    .with_context(|| anyhow!("Parsing artifact cache size '{}' of endpoint {}", size, ep_name))
+            })
+            .transpose()?
+            .map(|size| Arc::new(ArtifactCache::new(size.as_u64())));
+        let rate_limiter = ep.transfer_rate_limit()
+            .as_ref()
+            .map(|rate| {
+                rate.parse::<bytesize::ByteSize>()
+                    .map_err(|e| anyhow!("{}", e))
+                    .with_context(|| anyhow!("Parsing transfer rate limit '{}' of endpoint {}", rate, ep_name))
+            })
+            .transpose()?
+            .map(|rate| RateLimiter::new(rate.as_u64()));
+
         match ep.endpoint_type() {
             crate::config::EndpointType::Http => shiplift::Uri::from_str(ep.uri())
                 .map(shiplift::Docker::host)
@@ -128,30 +232,77 @@ impl Endpoint {
                     Endpoint::builder()
                         .name(ep_name.clone())
                         .uri(ep.uri().clone())
-                        .docker(docker)
+                        .backend(Box::new(DockerBackend::new(docker.clone())))
+                        .docker(Some(docker))
                         .num_max_jobs(ep.maxjobs())
                         .network_mode(ep.network_mode().clone())
+                        .artifact_cache(artifact_cache)
+                        .rate_limiter(rate_limiter)
+                        .container_cleanup(ep.container_cleanup())
                         .build()
                 }),
 
             crate::config::EndpointType::Socket => Ok({
+                let docker = shiplift::Docker::unix(ep.uri());
+                Endpoint::builder()
+                    .name(ep_name.clone())
+                    .uri(ep.uri().clone())
+                    .num_max_jobs(ep.maxjobs())
+                    .network_mode(ep.network_mode().clone())
+                    .backend(Box::new(DockerBackend::new(docker.clone())))
+                    .docker(Some(docker))
+                    .artifact_cache(artifact_cache)
+                    .rate_limiter(rate_limiter)
+                    .container_cleanup(ep.container_cleanup())
+                    .build()
+            }),
+
+            crate::config::EndpointType::Kubernetes => KubernetesBackend::new(ep.kubernetes())
+                .await
+                .with_context(|| anyhow!("Connecting to Kubernetes cluster of endpoint {}", ep_name))
+                .map(|backend| {
+                    Endpoint::builder()
+                        .name(ep_name.clone())
+                        .uri(ep.uri().clone())
+                        .num_max_jobs(ep.maxjobs())
+                        .network_mode(ep.network_mode().clone())
+                        .backend(Box::new(backend))
+                        .artifact_cache(artifact_cache)
+                        .rate_limiter(rate_limiter)
+                        .container_cleanup(ep.container_cleanup())
+                        .build()
+                }),
+
+            crate::config::EndpointType::Local => Ok({
                 Endpoint::builder()
                     .name(ep_name.clone())
                     .uri(ep.uri().clone())
                     .num_max_jobs(ep.maxjobs())
                     .network_mode(ep.network_mode().clone())
-                    .docker(shiplift::Docker::unix(ep.uri()))
+                    .backend(Box::new(LocalBackend::new()))
+                    .artifact_cache(artifact_cache)
+                    .rate_limiter(rate_limiter)
+                    .container_cleanup(ep.container_cleanup())
                     .build()
             }),
         }
     }
 
+    /// The Docker API of the endpoint
+    ///
+    /// Fails if the endpoint is not a Docker endpoint.
+    pub fn docker(&self) -> Result<&Docker> {
+        self.docker
+            .as_ref()
+            .ok_or_else(|| anyhow!("Endpoint {} is not a Docker endpoint", self.name))
+    }
+
     async fn check_version_compat(req: Option<&Vec<String>>, ep: &Endpoint) -> Result<()> {
         match req {
             None => Ok(()),
             Some(v) => {
                 let avail = ep
-                    .docker()
+                    .docker()?
                     .version()
                     .await
                     .with_context(|| anyhow!("Getting version of endpoint: {}", ep.name))?;
@@ -175,7 +326,7 @@ impl Endpoint {
             None => Ok(()),
             Some(v) => {
                 let avail = ep
-                    .docker()
+                    .docker()?
                     .version()
                     .await
                     .with_context(|| anyhow!("Getting API version of endpoint: {}", ep.name))?;
@@ -190,12 +341,12 @@ impl Endpoint {
         }
     }
 
-    async fn check_images_available(imgs: &[ImageName], ep: &Endpoint) -> Result<()> {
+    async fn missing_images(imgs: &[ImageName], ep: &Endpoint) -> Result<Vec<ImageName>> {
         use shiplift::ImageListOptions;
 
         trace!("Checking availability of images: {:?}", imgs);
         let available_names = ep
-            .docker()
+            .docker()?
             .images()
             .list(&ImageListOptions::builder().all().build())
             .await
@@ -212,20 +363,99 @@ impl Endpoint {
 
         trace!("Available images = {:?}", available_names);
 
-        imgs.iter()
-            .map(|img| {
-                if !available_names.contains(img) {
-                    Err(anyhow!(
-                        "Image '{}' missing from endpoint '{}'",
-                        img.as_ref(),
-                        ep.name
-                    ))
-                } else {
-                    Ok(())
+        Ok({
+            imgs.iter()
+                .filter(|img| !available_names.contains(img))
+                .cloned()
+                .collect()
+        })
+    }
+
+    /// Pull `image`, with the credentials of its registry if there are any
+    async fn pull_image(&self, image: &ImageName, registries: &HashMap<String, RegistryConfig>) -> Result<()> {
+        let registry = super::registry::registry_of(image);
+        let mut opts = shiplift::PullOptions::builder();
+        opts.image(image.as_ref());
+        if let Some(registry_config) = registries.get(registry) {
+            let auth = super::registry::registry_auth(registry, registry_config)
+                .await
+                .with_context(|| anyhow!("Getting credentials for registry {}", registry))?;
+            opts.auth(auth);
+        }
+
+        info!("Pulling image {} on endpoint {}", image.as_ref(), self.name);
+        self.docker()?
+            .images()
+            .pull(&opts.build())
+            .map_err(Error::from)
+            .try_for_each(|progress| async move {
+                trace!("Pulling {}: {}", image.as_ref(), progress);
+                match progress.get("error") {
+                    Some(error) => Err(anyhow!("{}", error)),
+                    None => Ok(()),
                 }
             })
-            .collect::<Result<Vec<_>>>()
-            .map(|_| ())
+            .await
+            .with_context(|| anyhow!("Pulling image {} on endpoint {}", image.as_ref(), self.name))
+            .map_err(Error::from)
+    }
+
+    /// Check whether the architecture of the image matches the architecture of the endpoint
+    ///
+    /// Returns the mismatching architectures, if they do not match. The architecture can only be
+    /// checked on Docker endpoints.
+    pub async fn check_image_architecture(&self, image: &ImageName) -> Result<Option<ArchitectureMismatch>> {
+        let docker = match self.docker.as_ref() {
+            Some(docker) => docker,
+            None => return Ok(None),
+        };
+
+        let (version, details) = tokio::try_join!(
+            async {
+                docker
+                    .version()
+                    .await
+                    .with_context(|| anyhow!("Getting version of endpoint: {}", self.name))
+            },
+            async {
+                docker
+                    .images()
+                    .get(image.as_ref())
+                    .inspect()
+                    .await
+                    .with_context(|| anyhow!("Inspecting image {} on endpoint {}", image.as_ref(), self.name))
+            }
+        )?;
+
+        trace!("Image {} is built for {}, endpoint {} runs on {}", image.as_ref(), details.architecture, self.name, version.arch);
+        if details.architecture == version.arch {
+            Ok(None)
+        } else {
+            Ok(Some(ArchitectureMismatch {
+                image_architecture: details.architecture,
+                endpoint_architecture: version.arch,
+            }))
+        }
+    }
+
+    /// Get the ID of an image on the endpoint
+    ///
+    /// Returns `None` if the endpoint is not a Docker endpoint, because the image is only pulled
+    /// when the container is created there.
+    pub async fn image_digest(&self, image: &ImageName) -> Result<Option<String>> {
+        let docker = match self.docker.as_ref() {
+            Some(docker) => docker,
+            None => return Ok(None),
+        };
+
+        docker
+            .images()
+            .get(image.as_ref())
+            .inspect()
+            .await
+            .with_context(|| anyhow!("Inspecting image {} on endpoint {}", image.as_ref(), self.name))
+            .map(|details| Some(details.id))
+            .map_err(Error::from)
     }
 
     pub async fn prepare_container(
@@ -233,14 +463,81 @@ impl Endpoint {
         job: &RunnableJob,
         staging_store: Arc<RwLock<StagingStore>>,
         release_stores: Vec<Arc<ReleaseStore>>,
+        compressed_artifacts: &HashMap<ArtifactPath, Compression>,
+        artifact_packages: &HashMap<ArtifactPath, dbmodels::Package>,
     ) -> Result<PreparedContainer<'_>> {
-        PreparedContainer::new(self, job, staging_store, release_stores).await
+        PreparedContainer::new(self, job, staging_store, release_stores, compressed_artifacts, artifact_packages).await
     }
 
     pub fn running_jobs(&self) -> usize {
         self.running_jobs.load(std::sync::atomic::Ordering::Relaxed)
     }
 
+    pub fn failed_jobs(&self) -> usize {
+        self.failed_jobs.load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    pub fn last_failure(&self) -> Option<String> {
+        self.last_failure.lock().ok()?.clone()
+    }
+
+    /// Record that a job failed on the endpoint
+    pub fn record_failure(&self, job: String) {
+        self.failed_jobs.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        if let Ok(mut last) = self.last_failure.lock() {
+            *last = Some(job);
+        }
+    }
+
+    /// The number of running containers on the endpoint, including containers that were not
+    /// started by this process
+    pub async fn running_containers(&self) -> Result<usize> {
+        self.container_stats()
+            .await
+            .map(|stats| stats.iter().filter(|stat| stat.state == "running").count())
+    }
+
+    /// Run `command` with `/bin/sh -c` in the running container `container_id`
+    ///
+    /// Returns the output of the command and its exit code, if the backend knows it.
+    pub async fn run_in_container(&self, container_id: &str, command: &str) -> Result<(String, Option<i32>)> {
+        let exec_id = self.backend
+            .exec(container_id, &["/bin/sh", "-c", command])
+            .await
+            .with_context(|| anyhow!("Running {:?} in container {}", command, container_id))?;
+        let output = self.backend.exec_output(&exec_id).try_concat().await?;
+        let exit_code = self.backend.exec_exit_code(&exec_id).await?;
+        Ok((String::from_utf8_lossy(&output).trim_end().to_string(), exit_code))
+    }
+
+    /// Stop the container `container_id`, which kills the script running in it
+    pub async fn stop_container(&self, container_id: &str) -> Result<()> {
+        self.backend
+            .stop(container_id)
+            .await
+            .with_context(|| anyhow!("Stopping container {}", container_id))
+            .map_err(Error::from)
+    }
+
+    /// Clean up the container `container_id` of a job that was interrupted, e.g. because butido
+    /// was killed
+    ///
+    /// The container is stopped, and removed if the cleanup policy removes the containers of failed
+    /// jobs.
+    pub async fn clean_up_container(&self, container_id: &str) -> Result<ContainerFate> {
+        self.stop_container(container_id).await?;
+        if self.container_cleanup.removes(false) {
+            trace!("Removing container {}", container_id);
+            self.backend
+                .remove(container_id)
+                .await
+                .with_context(|| anyhow!("Removing container {}", container_id))?;
+            Ok(ContainerFate::Removed)
+        } else {
+            Ok(ContainerFate::Kept)
+        }
+    }
+
     /// Super non-scientific utilization calculation for the endpoint
     pub fn utilization(&self) -> f64 {
         let max_jobs = self.num_max_jobs() as f64;
@@ -251,11 +548,11 @@ impl Endpoint {
 
     /// Ping the endpoint (once)
     pub async fn ping(&self) -> Result<String> {
-        self.docker.ping().await.map_err(Error::from)
+        self.docker()?.ping().await.map_err(Error::from)
     }
 
     pub async fn stats(&self) -> Result<EndpointStats> {
-        self.docker
+        self.docker()?
             .info()
             .await
             .map(EndpointStats::from)
@@ -263,7 +560,7 @@ impl Endpoint {
     }
 
     pub async fn container_stats(&self) -> Result<Vec<ContainerStat>> {
-        self.docker
+        self.docker()?
             .containers()
             .list({
                 &shiplift::builder::ContainerListOptions::builder()
@@ -292,7 +589,7 @@ impl Endpoint {
 
     pub async fn get_container_by_id(&self, id: &str) -> Result<Option<Container<'_>>> {
         if self.has_container_with_id(id).await? {
-            Ok(Some(self.docker.containers().get(id)))
+            Ok(Some(self.docker()?.containers().get(id)))
         } else {
             Ok(None)
         }
@@ -307,7 +604,7 @@ impl Endpoint {
             listopts.all();
         }
 
-        self.docker
+        self.docker()?
             .images()
             .list(&listopts.build())
             .await
@@ -352,6 +649,16 @@ impl From<shiplift::rep::Info> for EndpointStats {
     }
 }
 
+/// The architectures of an image and an endpoint that do not match
+#[derive(Debug, Getters)]
+pub struct ArchitectureMismatch {
+    #[getset(get = "pub")]
+    image_architecture: String,
+
+    #[getset(get = "pub")]
+    endpoint_architecture: String,
+}
+
 /// Helper type to store stats about a container
 pub struct ContainerStat {
     pub created: chrono::DateTime<chrono::Utc>,
@@ -405,6 +712,11 @@ impl EndpointHandle {
         trace!("Endpoint {} has one job more: {}", ep.name(), res + 1);
         EndpointHandle(ep)
     }
+
+    /// The endpoint, without counting as a job running on it
+    pub fn shared(&self) -> Arc<Endpoint> {
+        self.0.clone()
+    }
 }
 
 impl Drop for EndpointHandle {
@@ -423,13 +735,68 @@ impl std::ops::Deref for EndpointHandle {
 }
 
 
+/// The manifest of the dependency artifacts that are copied into the container, so that scripts
+/// can iterate over their inputs
+#[derive(Debug, serde::Serialize)]
+struct InputsManifest {
+    artifacts: Vec<InputArtifact>,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct InputArtifact {
+    /// The path of the artifact in the container
+    path: PathBuf,
+
+    /// The path of the artifact in the staging or release store
+    artifact: PathBuf,
+    package_name: Option<String>,
+    package_version: Option<String>,
+    sha256: String,
+}
+
+impl InputsManifest {
+    fn new(
+        copied_artifacts: &HashMap<ArtifactPath, (PathBuf, String)>,
+        artifact_packages: &HashMap<ArtifactPath, dbmodels::Package>,
+    ) -> Self {
+        let mut artifacts = copied_artifacts
+            .iter()
+            .map(|(art, (destination, hash))| {
+                let package = artifact_packages.get(art);
+                InputArtifact {
+                    path: destination.clone(),
+                    artifact: art.as_ref().to_path_buf(),
+                    package_name: package.map(|p| p.name.clone()),
+                    package_version: package.map(|p| p.version.clone()),
+                    sha256: hash.clone(),
+                }
+            })
+            .collect::<Vec<_>>();
+        artifacts.sort_by(|a, b| a.path.cmp(&b.path));
+        InputsManifest { artifacts }
+    }
+
+    fn to_json(&self) -> Result<String> {
+        serde_json::to_string_pretty(self).context("Serializing manifest of the inputs").map_err(Error::from)
+    }
+}
+
 #[derive(Getters)]
 pub struct PreparedContainer<'a> {
     endpoint: &'a Endpoint,
     script: Script,
+    paths: ContainerPaths,
+
+    #[getset(get = "pub")]
+    container_id: String,
+
+    /// The SHA256 of the artifacts that were copied into the container
+    #[getset(get = "pub")]
+    artifact_hashes: HashMap<ArtifactPath, String>,
 
+    /// The SHA256 of the patches that were copied into the container
     #[getset(get = "pub")]
-    create_info: shiplift::rep::ContainerCreateInfo,
+    patch_hashes: HashMap<PathBuf, String>,
 }
 
 impl<'a> PreparedContainer<'a> {
@@ -438,55 +805,87 @@ impl<'a> PreparedContainer<'a> {
         job: &RunnableJob,
         staging_store: Arc<RwLock<StagingStore>>,
         release_stores: Vec<Arc<ReleaseStore>>,
+        compressed_artifacts: &HashMap<ArtifactPath, Compression>,
+        artifact_packages: &HashMap<ArtifactPath, dbmodels::Package>,
     ) -> Result<PreparedContainer<'a>> {
         let script = job.script().clone();
-        let create_info = Self::build_container(endpoint, job).await?;
-        let container = endpoint.docker.containers().get(&create_info.id);
+        let container_id = Self::build_container(endpoint, job).await?;
+        let container = ContainerRef {
+            backend: endpoint.backend.as_ref(),
+            id: &container_id,
+        };
 
         let (cpysrc, cpypch, cpyart, cpyscr) = tokio::join!(
             Self::copy_source_to_container(&container, job),
             Self::copy_patches_to_container(&container, job),
-            Self::copy_artifacts_to_container(&container, job, staging_store, &release_stores),
-            Self::copy_script_to_container(&container, &script)
+            Self::copy_artifacts_to_container(&container, job, staging_store, &release_stores, compressed_artifacts, endpoint.artifact_cache.as_deref()),
+            Self::copy_script_to_container(&container, &script, job.container_paths().script())
         );
 
         cpysrc.with_context(|| {
             anyhow!(
                 "Copying the sources to container {} on '{}'",
-                create_info.id,
+                container_id,
                 endpoint.name
             )
         })?;
 
-        cpypch.with_context(|| {
+        let patch_hashes = cpypch.with_context(|| {
             anyhow!(
                 "Copying the patches to container {} on '{}'",
-                create_info.id,
+                container_id,
                 endpoint.name
             )
         })?;
 
-        cpyart.with_context(|| {
+        let copied_artifacts = cpyart.with_context(|| {
             anyhow!(
                 "Copying the artifacts to container {} on '{}'",
-                create_info.id,
+                container_id,
                 endpoint.name
             )
         })?;
 
+        let inputs_manifest = InputsManifest::new(&copied_artifacts, artifact_packages);
+        container
+            .copy_file_into(&job.container_paths().inputs_manifest(), inputs_manifest.to_json()?.as_bytes())
+            .await
+            .with_context(|| anyhow!("Copying the manifest of the inputs to container {}", container_id))?;
+
+        let artifact_hashes = copied_artifacts
+            .into_iter()
+            .map(|(art, (_, hash))| (art, hash))
+            .collect();
+
         cpyscr.with_context(|| {
             anyhow!(
                 "Copying the script to container {} on '{}'",
-                create_info.id,
+                container_id,
                 endpoint.name
             )
         })?;
 
+        if let Some(user) = job.container_user() {
+            Self::hand_over_to_user(&container, &script, job.container_paths(), *user)
+                .await
+                .with_context(|| {
+                    anyhow!(
+                        "Handing over the paths in container {} on '{}' to user {}",
+                        container_id,
+                        endpoint.name,
+                        user
+                    )
+                })?;
+        }
+
         Ok({
             PreparedContainer {
                 endpoint,
                 script,
-                create_info,
+                paths: job.container_paths().clone(),
+                container_id,
+                artifact_hashes,
+                patch_hashes,
             }
         })
     }
@@ -494,47 +893,46 @@ impl<'a> PreparedContainer<'a> {
     async fn build_container(
         endpoint: &Endpoint,
         job: &RunnableJob,
-    ) -> Result<shiplift::rep::ContainerCreateInfo> {
+    ) -> Result<String> {
+        let patches = job.package()
+            .patches()
+            .iter()
+            .map(|patch| PathBuf::from(crate::consts::PATCH_DIR_PATH).join(patch).display().to_string())
+            .collect::<Vec<_>>()
+            .join(" ");
+
         let envs = job
             .environment()
             .map(|(k, v)| format!("{}={}", k.as_ref(), v))
+            .chain(std::iter::once(format!("{}={}", crate::consts::PATCHES_ENV_NAME, patches)))
+            .chain(job.builtin_environment().iter().map(|(k, v)| format!("{}={}", k.as_ref(), v)))
             .collect::<Vec<_>>();
         trace!("Job resources: Environment variables = {:?}", envs);
 
-        let builder_opts = {
-            let mut builder_opts = shiplift::ContainerOptions::builder(job.image().as_ref());
-            let container_name = format!("butido-{package}-{version}-{id}",
+        let spec = ContainerSpec {
+            name: format!("butido-{package}-{version}-{id}",
                 package = job.package().name().as_ref(),
                 version = job.package().version().as_ref(),
                 id = job.uuid()
-            );
-            trace!("container name = {}", container_name);
-            builder_opts.name(&container_name);
-            builder_opts.env(envs.iter().map(AsRef::as_ref).collect::<Vec<&str>>());
-            builder_opts.cmd(vec!["/bin/bash"]); // we start the container with /bin/bash, but exec() the script in it later
-            builder_opts.attach_stdin(true); // we have to attach, otherwise bash exits
-
-            if let Some(network_mode) = endpoint.network_mode().as_ref() {
-                builder_opts.network_mode(network_mode);
-            }
-
-            builder_opts.build()
+            ),
+            image: job.image(),
+            env: envs,
+            user: *job.container_user(),
+            network_mode: job.network_mode().as_deref().or(endpoint.network_mode().as_deref()),
+            paths: job.container_paths(),
         };
-        trace!("Builder options = {:?}", builder_opts);
+        trace!("Container spec = {:?}", spec);
 
-        let create_info = endpoint
-            .docker
-            .containers()
-            .create(&builder_opts)
+        endpoint
+            .backend
+            .create(&spec)
             .await
-            .with_context(|| anyhow!("Creating container with builder options = {:?}", builder_opts))
-            .with_context(|| anyhow!("Creating container on '{}'", endpoint.name))?;
-        trace!("Create info = {:?}", create_info);
-        Ok(create_info)
+            .with_context(|| anyhow!("Creating container on '{}'", endpoint.name))
+            .map_err(Error::from)
     }
 
-    async fn copy_source_to_container<'ca>(
-        container: &Container<'ca>,
+    async fn copy_source_to_container(
+        container: &ContainerRef<'_>,
         job: &RunnableJob,
     ) -> Result<()> {
         use tokio::io::AsyncReadExt;
@@ -543,7 +941,7 @@ impl<'a> PreparedContainer<'a> {
             .into_iter()
             .map(|entry| async {
                 let source_path = entry.path();
-                let destination = PathBuf::from(crate::consts::INPUTS_DIR_PATH).join({
+                let destination = job.container_paths().inputs().join({
                     source_path
                         .file_name()
                         .ok_or_else(|| anyhow!("Not a file: {}", source_path.display()))
@@ -551,7 +949,7 @@ impl<'a> PreparedContainer<'a> {
                             anyhow!(
                                 "Copying package source from {} to container {}",
                                 source_path.display(),
-                                container.id()
+                                container.id
                             )
                         })?
                 });
@@ -572,24 +970,24 @@ impl<'a> PreparedContainer<'a> {
                     .with_context(|| anyhow!("Reading file {}", source_path.display()))?;
 
                 drop(entry);
-                container.copy_file_into(destination, &buf)
+                container.copy_file_into(&destination, &buf)
                     .await
-                    .inspect(|_| trace!("Successfully copied source {} to container {}", source_path.display(), container.id()))
-                    .with_context(|| anyhow!("Failed to copy source {} to container {}", source_path.display(), container.id()))
+                    .inspect(|_| trace!("Successfully copied source {} to container {}", source_path.display(), container.id))
+                    .with_context(|| anyhow!("Failed to copy source {} to container {}", source_path.display(), container.id))
                     .map_err(Error::from)
             })
             .collect::<futures::stream::FuturesUnordered<_>>()
             .collect::<Result<()>>()
             .await
-            .inspect(|_| trace!("Successfully copied sources to container {}", container.id()))
-            .with_context(|| anyhow!("Copying sources to container {}", container.id()))
+            .inspect(|_| trace!("Successfully copied sources to container {}", container.id))
+            .with_context(|| anyhow!("Copying sources to container {}", container.id))
             .map_err(Error::from)
     }
 
-    async fn copy_patches_to_container<'ca>(
-        container: &Container<'ca>,
+    async fn copy_patches_to_container(
+        container: &ContainerRef<'_>,
         job: &RunnableJob,
-    ) -> Result<()> {
+    ) -> Result<HashMap<PathBuf, String>> {
         use tokio::io::AsyncReadExt;
 
         debug!("Copying patches to container: {:?}", job.package().patches());
@@ -614,28 +1012,32 @@ impl<'a> PreparedContainer<'a> {
                     .await
                     .with_context(|| anyhow!("Reading file {}", patch.display()))?;
 
-                container.copy_file_into(destination, &buf)
+                let hash = crate::job::sha256_hex(&buf);
+                container.copy_file_into(&destination, &buf)
                     .await
-                    .map_err(Error::from)
                     .inspect(|_| trace!("Copying patch {} successfull", patch.display()))
-                    .with_context(|| anyhow!("Copying patch {} to container {}", patch.display(), container.id()))
+                    .with_context(|| anyhow!("Copying patch {} to container {}", patch.display(), container.id))
                     .map_err(Error::from)
+                    .map(|_| (patch.clone(), hash))
             })
             .collect::<futures::stream::FuturesUnordered<_>>()
-            .collect::<Result<()>>()
+            .collect::<Result<Vec<_>>>()
             .await
-            .map_err(Error::from)
+            .map(|hashes| hashes.into_iter().collect())
             .inspect(|_| trace!("Copied all patches"))
-            .with_context(|| anyhow!("Copying patches to container {}", container.id()))
+            .with_context(|| anyhow!("Copying patches to container {}", container.id))
             .map_err(Error::from)
     }
 
-    async fn copy_artifacts_to_container<'ca>(
-        container: &Container<'ca>,
+    async fn copy_artifacts_to_container(
+        container: &ContainerRef<'_>,
         job: &RunnableJob,
         staging_store: Arc<RwLock<StagingStore>>,
         release_stores: &[Arc<ReleaseStore>],
-    ) -> Result<()> {
+        compressed_artifacts: &HashMap<ArtifactPath, Compression>,
+        artifact_cache: Option<&ArtifactCache>,
+    ) -> Result<HashMap<ArtifactPath, (PathBuf, String)>> {
+        let staging_root = staging_store.read().await.root_path().clone();
         let stream = job.resources()
             .iter()
             .filter_map(JobResource::artifact)
@@ -647,49 +1049,76 @@ impl<'a> PreparedContainer<'a> {
                     .with_context(|| {
                         anyhow!(
                             "Collecting artifacts for copying to container {}",
-                            container.id()
+                            container.id
                         )
                     })?;
-                let destination = PathBuf::from(crate::consts::INPUTS_DIR_PATH).join(artifact_file_name);
+                let destination = job.container_paths().inputs().join(artifact_file_name);
                 trace!(
                     "Copying {} to container: {}:{}",
                     art.display(),
-                    container.id(),
+                    container.id,
                     destination.display()
                 );
-                let staging_read = staging_store.read().await;
-                let buf = match staging_read.root_path().join(&art)?  {
-                    Some(fp) => fp,
-                    None     => {
-                        // TODO: Optimize.
-                        // I know this is not nice, but it works for now.
-                        let mut found = None;
-                        for release_store in release_stores.iter() {
-                            let p = release_store.root_path().join(&art);
-                            match p {
-                                Ok(Some(path)) => {
-                                    found = Some(path);
-                                    break;
-                                },
-                                Err(e) => {
-                                    trace!("Failed to join '{:?}' + '{:?}'", release_store.root_path(), art.display());
-                                    return Err(e)
-                                },
-                                Ok(None) =>  continue,
+                // Artifacts built on this endpoint may still be in the cache, uncompressed
+                let cache_key = match compressed_artifacts.get(&art) {
+                    Some(compression) => ArtifactPath::new(compression.strip_extension(art.as_ref()))?,
+                    None => art.clone(),
+                };
+
+                let cached = artifact_cache.and_then(|cache| cache.get(&staging_root, &cache_key));
+                let (destination, buf) = if let Some(cached) = cached {
+                    trace!("Found {} in artifact cache", cache_key.display());
+                    let destination = match compressed_artifacts.get(&art) {
+                        Some(compression) => compression.strip_extension(&destination),
+                        None => destination,
+                    };
+                    (destination, cached.to_vec())
+                } else {
+                    let staging_read = staging_store.read().await;
+                    let full_path = match staging_read.fetch(&art).await?  {
+                        Some(fp) => fp,
+                        None     => {
+                            // TODO: Optimize.
+                            // I know this is not nice, but it works for now.
+                            let mut found = None;
+                            for release_store in release_stores.iter() {
+                                let p = release_store.fetch(&art).await;
+                                match p {
+                                    Ok(Some(path)) => {
+                                        found = Some(path);
+                                        break;
+                                    },
+                                    Err(e) => {
+                                        trace!("Failed to join '{:?}' + '{:?}'", release_store.root_path(), art.display());
+                                        return Err(e)
+                                    },
+                                    Ok(None) =>  continue,
+                                }
                             }
+                            found.ok_or_else(|| anyhow!("Not found in staging or release store: {:?}", art))?
+                        },
+                    }
+                    .joined();
+
+                    let (destination, buf) = match compressed_artifacts.get(&art).copied() {
+                        Some(compression) => {
+                            trace!("Decompressing {} with {}", art.display(), compression);
+                            let buf = tokio::task::spawn_blocking(move || compression.decompress_file(&full_path))
+                                .await?
+                                .with_context(|| anyhow!("Decompressing artifact {} with {}", art.display(), compression))?;
+                            (compression.strip_extension(&destination), buf)
                         }
-                        found.ok_or_else(|| anyhow!("Not found in staging or release store: {:?}", art))?
-                    },
-                }
-                .read()
-                .await
-                .with_context(|| {
-                    anyhow!(
-                        "Reading artifact {}, so it can be copied to container",
-                        art.display()
-                    )
-                })?;
-                trace!("Successfully read {} into buffer", art.display());
+                        None => {
+                            let buf = tokio::fs::read(&full_path)
+                                .await
+                                .with_context(|| anyhow!("Reading artifact {}, so it can be copied to container", art.display()))?;
+                            (destination, buf)
+                        },
+                    };
+                    trace!("Successfully read {} into buffer", art.display());
+                    (destination, buf)
+                };
+                let hash = crate::job::sha256_hex(&buf);
 
                 let r = container
                     .copy_file_into(&destination, &buf)
@@ -699,11 +1128,12 @@ impl<'a> PreparedContainer<'a> {
                         anyhow!(
                             "Copying artifact {} to container {} at {}",
                             art.display(),
-                            container.id(),
+                            container.id,
                             destination.display()
                         )
                     })
-                    .map_err(Error::from);
+                    .map_err(Error::from)
+                    .map(|_| (art.clone(), (destination, hash)));
                 drop(art); // ensure `art` is moved into closure
                 r
             });
@@ -716,37 +1146,64 @@ impl<'a> PreparedContainer<'a> {
         stream
             .collect::<Result<Vec<_>>>()
             .await
-            .inspect(|_| trace!("Successfully copied all artifacts to the container {}", container.id()))
-            .with_context(|| anyhow!("Copying artifacts to container {}", container.id()))
+            .inspect(|_| trace!("Successfully copied all artifacts to the container {}", container.id))
+            .with_context(|| anyhow!("Copying artifacts to container {}", container.id))
             .map_err(Error::from)
-            .map(|_| ())
+            .map(|hashes| hashes.into_iter().collect())
     }
 
-    async fn copy_script_to_container<'ca>(
-        container: &Container<'ca>,
+    async fn copy_script_to_container(
+        container: &ContainerRef<'_>,
         script: &Script,
+        script_path: &Path,
     ) -> Result<()> {
-        let script_path = PathBuf::from(crate::consts::SCRIPT_PATH);
         container
             .copy_file_into(script_path, script.as_ref().as_bytes())
             .await
-            .inspect(|_| trace!("Successfully copied script to container {}", container.id()))
-            .with_context(|| anyhow!("Copying the script into container {}", container.id()))
+            .inspect(|_| trace!("Successfully copied script to container {}", container.id))
+            .with_context(|| anyhow!("Copying the script into container {}", container.id))
+            .map_err(Error::from)
+    }
+
+    /// Make the script, the inputs and the outputs directory owned by `user`
+    ///
+    /// Everything is copied into the container as root, so a non-root user could neither write its
+    /// outputs nor change the inputs.
+    async fn hand_over_to_user(
+        container: &ContainerRef<'_>,
+        script: &Script,
+        paths: &ContainerPaths,
+        user: ContainerUser,
+    ) -> Result<()> {
+        let archive = ownership_archive(script, paths, user)?;
+        container
+            .backend
+            .copy_archive_into(container.id, archive)
+            .await
+            .inspect(|_| trace!("Successfully handed over paths in container {} to {}", container.id, user))
+            .map_err(Error::from)
+    }
+
+    /// Remove the container without starting it, e.g. because the job cannot run on the endpoint
+    pub async fn remove(self) -> Result<()> {
+        trace!("Removing container {}", self.container_id);
+        self.endpoint.backend
+            .remove(&self.container_id)
+            .await
+            .with_context(|| anyhow!("Removing container {}", self.container_id))
             .map_err(Error::from)
     }
 
     pub async fn start(self) -> Result<StartedContainer<'a>> {
         self.endpoint
-            .docker
-            .containers()
-            .get(&self.create_info.id)
-            .start()
-            .inspect(|r| trace!("Starting container {} -> {:?}", self.create_info.id, r))
+            .backend
+            .start(&self.container_id)
+            .inspect(|r| trace!("Starting container {} -> {:?}", self.container_id, r))
             .map(|r| {
                 r.with_context(|| {
                     anyhow!(
                         "Starting the container {} on '{}'",
-                        self.create_info.id,
+                        self.container_id,
                         self.endpoint.name
                     )
                 })
@@ -757,112 +1214,169 @@ impl<'a> PreparedContainer<'a> {
             StartedContainer {
                 endpoint: self.endpoint,
                 script: self.script,
-                create_info: self.create_info,
+                paths: self.paths,
+                container_id: self.container_id,
             }
         })
     }
 }
 
+/// A container of an endpoint, for the operations while preparing it
+struct ContainerRef<'a> {
+    backend: &'a dyn ContainerBackend,
+    id: &'a str,
+}
+
+impl<'a> ContainerRef<'a> {
+    async fn copy_file_into(&self, path: &Path, content: &[u8]) -> Result<()> {
+        self.backend.copy_file_into(self.id, path, content).await
+    }
+}
+
 pub struct StartedContainer<'a> {
     endpoint: &'a Endpoint,
     script: Script,
-    create_info: shiplift::rep::ContainerCreateInfo,
+    paths: ContainerPaths,
+    container_id: String,
 }
 
 impl<'a> StartedContainer<'a> {
+    /// Run the capture `commands` of the package in the container, returns their output by name
+    ///
+    /// A failing command does not fail the job, its output is recorded anyway.
+    pub async fn capture(&self, commands: &BTreeMap<String, String>) -> Result<Vec<(String, String)>> {
+        let mut captured = Vec::with_capacity(commands.len());
+        for (name, command) in commands {
+            trace!("Capturing {} in container {}: {}", name, self.container_id, command);
+            let (output, exit_code) = self.endpoint
+                .run_in_container(&self.container_id, command)
+                .await
+                .with_context(|| anyhow!("Running capture command {} in container {}", name, self.container_id))?;
+            match exit_code {
+                Some(0) | None => {},
+                Some(code) => warn!("Capture command {} failed in container {} with exit code {}", name, self.container_id, code),
+            }
+
+            captured.push((name.clone(), output));
+        }
+        Ok(captured)
+    }
+
     pub async fn execute_script(
         self,
         logsink: UnboundedSender<LogItem>,
     ) -> Result<ExecutedContainer<'a>> {
-        let exec_opts = ExecContainerOptions::builder()
-            .cmd(vec!["/bin/bash", "/script"])
-            .attach_stderr(true)
-            .attach_stdout(true)
-            .build();
-        trace!("Exec options = {:?}", exec_opts);
-
-        trace!("Moving logs to log sink for container {}", self.create_info.id);
-        let stream = self.endpoint
-            .docker
-            .containers()
-            .get(&self.create_info.id)
-            .exec(&exec_opts);
-
-        let exited_successfully: Option<(bool, Option<String>)> =
-            buffer_stream_to_line_stream(stream)
-                .map(|line| {
-                    trace!(
-                        "['{}':{}] Found log line: {:?}",
+        let script_path = self.paths.script().display().to_string();
+        let backend = self.endpoint.backend.as_ref();
+        let cmd = backend.script_command(&script_path);
+        let cmd = cmd.iter().map(String::as_str).collect::<Vec<_>>();
+        let exec_id = backend
+            .exec(&self.container_id, &cmd)
+            .await
+            .with_context(|| anyhow!("Creating exec instance in container {}", self.container_id))?;
+
+        trace!("Moving logs to log sink for container {}", self.container_id);
+        let stream = backend.exec_output(&exec_id);
+
+        // The resource usage is sampled until the script finished
+        let script_finished = tokio::sync::Notify::new();
+        let logs = buffer_stream_to_line_stream(stream)
+            .map(|line| {
+                trace!(
+                    "['{}':{}] Found log line: {:?}",
+                    self.endpoint.name,
+                    self.container_id,
+                    line
+                );
+                line.with_context(|| {
+                    anyhow!(
+                        "Getting log from {}:{}",
                         self.endpoint.name,
-                        self.create_info.id,
-                        line
-                    );
-                    line.with_context(|| {
-                        anyhow!(
-                            "Getting log from {}:{}",
-                            self.endpoint.name,
-                            self.create_info.id
-                        )
-                    })
-                    .and_then(|l| {
-                        crate::log::parser()
-                            .parse(l.as_bytes())
-                            .with_context(|| {
-                                anyhow!(
-                                    "Parsing log from {}:{}: {:?}",
-                                    self.endpoint.name,
-                                    self.create_info.id,
-                                    l
-                                )
-                            })
-                    })
-                    .and_then(|item| {
-                        let exited_successfully = match item {
-                            LogItem::State(Ok(_)) => Some((true, None)),
-                            LogItem::State(Err(ref msg)) => Some((false, Some(msg.clone()))),
-                            _ => None, // Nothing
-                        };
-
-                        trace!("Log item: {}", item.display()?);
-                        logsink
-                            .send(item)
-                            .with_context(|| anyhow!("Sending log to log sink"))
-                            .map(|_| exited_successfully)
-                    })
-                    .map_err(Error::from)
+                        self.container_id
+                    )
                 })
-                .collect::<Result<Vec<_>>>()
-                .map(|r| {
-                    r.with_context(|| {
-                        anyhow!(
-                            "Fetching log from container {} on {}",
-                            self.create_info.id,
-                            self.endpoint.name
-                        )
-                    })
+                .and_then(|l| {
+                    crate::log::parser()
+                        .parse(l.as_bytes())
+                        .with_context(|| {
+                            anyhow!(
+                                "Parsing log from {}:{}: {:?}",
+                                self.endpoint.name,
+                                self.container_id,
+                                l
+                            )
+                        })
                 })
-                .await
-                .with_context(|| {
+                .and_then(|item| {
+                    let exited_successfully = match item {
+                        LogItem::State(Ok(_)) => Some((true, None)),
+                        LogItem::State(Err(ref msg)) => Some((false, Some(msg.clone()))),
+                        _ => None, // Nothing
+                    };
+
+                    trace!("Log item: {}", item.display()?);
+                    logsink
+                        .send(item)
+                        .with_context(|| anyhow!("Sending log to log sink"))
+                        .map(|_| exited_successfully)
+                })
+                .map_err(Error::from)
+            })
+            .collect::<Result<Vec<_>>>()
+            .map(|r| {
+                r.with_context(|| {
                     anyhow!(
-                        "Copying script to container, running container and getting logs: {}",
-                        self.create_info.id
+                        "Fetching log from container {} on {}",
+                        self.container_id,
+                        self.endpoint.name
                     )
-                })?
-                .into_iter()
-                .fold(None, |accu, elem| match (accu, elem) {
-                    (None, b) => b,
-                    (Some((false, msg)), _) => Some((false, msg)),
-                    (_, Some((false, msg))) => Some((false, msg)),
-                    (a, None) => a,
-                    (Some((true, _)), Some((true, _))) => Some((true, None)),
-                });
+                })
+            })
+            .inspect(|_| script_finished.notify_one());
+        let samples = futures::StreamExt::take_until(backend.usage_samples(&self.container_id), script_finished.notified());
+        let usage = ResourceUsage::from_samples(futures::StreamExt::boxed(samples));
+        let (logs, usage) = tokio::join!(logs, usage);
+
+        let exited_successfully: Option<(bool, Option<String>)> = logs
+            .with_context(|| {
+                anyhow!(
+                    "Copying script to container, running container and getting logs: {}",
+                    self.container_id
+                )
+            })?
+            .into_iter()
+            .fold(None, |accu, elem| match (accu, elem) {
+                (None, b) => b,
+                (Some((false, msg)), _) => Some((false, msg)),
+                (_, Some((false, msg))) => Some((false, msg)),
+                (a, None) => a,
+                (Some((true, _)), Some((true, _))) => Some((true, None)),
+            });
+
+        let exit_code = backend
+            .exec_exit_code(&exec_id)
+            .await
+            .with_context(|| anyhow!("Inspecting exec instance in container {}", self.container_id))?;
+        trace!("Script in container {} exited with {:?}", self.container_id, exit_code);
+
+        let oom_killed = backend
+            .oom_killed(&self.container_id)
+            .await
+            .with_context(|| anyhow!("Inspecting container {}", self.container_id))?;
+        if oom_killed {
+            warn!("Container {} on '{}' ran out of memory", self.container_id, self.endpoint.name);
+        }
 
         Ok({
             ExecutedContainer {
                 endpoint: self.endpoint,
-                create_info: self.create_info,
+                container_id: self.container_id,
                 script: self.script,
+                paths: self.paths,
                 exit_info: exited_successfully,
+                exit_code,
+                oom_killed,
+                usage,
             }
         })
     }
@@ -870,75 +1384,318 @@ impl<'a> StartedContainer<'a> {
 
 pub struct ExecutedContainer<'a> {
     endpoint: &'a Endpoint,
-    create_info: shiplift::rep::ContainerCreateInfo,
+    container_id: String,
     script: Script,
+    paths: ContainerPaths,
     exit_info: Option<(bool, Option<String>)>,
+    exit_code: Option<i32>,
+    oom_killed: bool,
+    usage: Option<ResourceUsage>,
 }
 
 impl<'a> ExecutedContainer<'a> {
     pub fn container_hash(&self) -> ContainerHash {
-        ContainerHash::from(self.create_info.id.clone())
+        ContainerHash::from(self.container_id.clone())
     }
 
     pub fn script(&self) -> &Script {
         &self.script
     }
 
-    pub async fn finalize(self, staging_store: Arc<RwLock<StagingStore>>) -> Result<FinalizedContainer> {
-        let (exit_info, artifacts) = match self.exit_info {
+    /// The exit code of the script, if it could be fetched from the container runtime
+    pub fn exit_code(&self) -> Option<i32> {
+        self.exit_code
+    }
+
+    /// Whether the container ran out of memory and a process in it was killed
+    pub fn oom_killed(&self) -> bool {
+        self.oom_killed
+    }
+
+    /// The resource usage of the container while the script ran, if the backend could sample it
+    pub fn usage(&self) -> Option<ResourceUsage> {
+        self.usage
+    }
+
+    /// Copy `path` out of the container, as tar archive, showing the progress on `bar`
+    ///
+    /// The transfer is aborted as soon as the archive exceeds the `limits`. Returns `None` if
+    /// `path` does not exist in the container.
+    async fn fetch(&self, path: &Path, limits: UnpackLimits, bar: &ProgressBar) -> Result<Option<Vec<u8>>> {
+        let stream = match self.endpoint.backend.copy_from(&self.container_id, path).await? {
+            Some(stream) => stream,
+            None => return Ok(None),
+        };
+        let mut limit_check = ArchiveLimitCheck::new(limits);
+        let stream = futures::StreamExt::boxed(StreamExt::map(stream, move |chunk| {
+            let chunk = chunk?;
+            limit_check.feed(&chunk)?;
+            Ok(chunk)
+        }));
+
+        let message = bar.message();
+        let bytes = crate::endpoint::receive(stream, self.endpoint.rate_limiter.as_ref(), |progress| {
+            bar.set_message(format!("{message}: Fetching {}: {progress}", path.display()));
+        })
+        .await;
+        bar.set_message(message);
+        bytes.map(Some)
+    }
+
+    /// Fetch the outputs of the job into the staging store and stop the container
+    async fn fetch_outputs(&self, staging_store: &RwLock<StagingStore>, image: &ImageName, bar: &ProgressBar) -> Result<Vec<ArtifactPath>> {
+        let limits = staging_store.read().await.unpack_limits();
+        trace!("Fetching {} from container {}", self.paths.outputs().display(), self.container_id);
+        let tar_bytes = self
+            .fetch(self.paths.outputs(), limits, bar)
+            .await
+            .and_then(|bytes| bytes.ok_or_else(|| anyhow!("{} does not exist", self.paths.outputs().display())))
+            .with_context(|| {
+                anyhow!(
+                    "Copying outputs from container {} to host",
+                    self.container_id
+                )
+            })?;
+
+        // The artifacts are cached while they are written to the staging store
+        let tar_bytes = Arc::new(tar_bytes);
+        let cache_insert = match self.endpoint.artifact_cache.clone() {
+            Some(cache) => {
+                let (root, subdir) = {
+                    let store = staging_store.read().await;
+                    (store.root_path().clone(), store.subdir_for(image, None))
+                };
+                let tar_bytes = tar_bytes.clone();
+                Some(tokio::task::spawn_blocking(move || cache.insert_from_tar(&root, &subdir, &tar_bytes)))
+            },
+            None => None,
+        };
+
+        let mut artifacts = StagingStore::write_files_from_tar(staging_store, tar_bytes, image, None, bar)
+            .await
+            .with_context(|| anyhow!("Copying the TAR stream to the staging store"))?;
+
+        if let Some(cache_insert) = cache_insert {
+            cache_insert
+                .await
+                .map_err(Error::from)
+                .and_then(|r| r)
+                .with_context(|| anyhow!("Caching artifacts of container {}", self.container_id))?;
+        }
+
+        for (category, path) in self.paths.additional_outputs() {
+            trace!("Fetching {} from container {}", path.display(), self.container_id);
+            let tar_bytes = match self.fetch(path, limits, bar).await {
+                Ok(Some(bytes)) => bytes,
+                Ok(None) => {
+                    debug!("No {} outputs in container {}: {} does not exist", category, self.container_id, path.display());
+                    continue
+                },
+                Err(e) => {
+                    return Err(e)
+                        .with_context(|| anyhow!("Copying {} from container {} to host", path.display(), self.container_id))
+                },
+            };
+
+            let category_artifacts = StagingStore::write_files_from_tar(staging_store, Arc::new(tar_bytes), image, Some(category), bar)
+                .await
+                .with_context(|| anyhow!("Copying the {} outputs to the staging store", category))?;
+            artifacts.extend(category_artifacts);
+        }
+        self.endpoint.backend
+            .stop(&self.container_id)
+            .await
+            .with_context(|| anyhow!("Stopping container {}", self.container_id))?;
+        Ok(artifacts)
+    }
+
+    /// Discard the container without fetching the outputs, e.g. to run the job again
+    ///
+    /// The container is removed if the cleanup policy removes the containers of failed jobs.
+    pub async fn discard(self) -> Result<ContainerFate> {
+        if self.endpoint.container_cleanup.removes(false) {
+            trace!("Removing container {}", self.container_id);
+            self.endpoint.backend
+                .remove(&self.container_id)
+                .await
+                .with_context(|| anyhow!("Removing container {}", self.container_id))?;
+            Ok(ContainerFate::Removed)
+        } else {
+            Ok(ContainerFate::Kept)
+        }
+    }
+
+    /// Fetch the outputs of the job into the staging store and clean up the container
+    ///
+    /// The outputs are written to the directory of `image` in the staging store, if it has one.
+    pub async fn finalize(self, staging_store: Arc<RwLock<StagingStore>>, image: &ImageName, bar: &ProgressBar) -> Result<FinalizedContainer> {
+        // The exit code of the script is authoritative, the state from the log is only used if
+        // there is no exit code
+        let exit_info = match (self.exit_code, self.exit_info.clone()) {
+            (Some(code), _) if self.oom_killed => Some((false, Some(format!("exit code {code}: container ran out of memory")))),
+            (Some(0), _) => Some((true, None)),
+            (Some(code), Some((_, msg))) => Some((false, Some(format!("exit code {code}: {}", msg.unwrap_or_default())))),
+            (Some(code), None) => Some((false, Some(format!("exit code {code}")))),
+            (None, info) => info,
+        };
+
+        let outputs = match exit_info {
             Some((false, msg)) => {
                 let err = anyhow!("Error during container run: '{msg}'", msg = msg.as_deref().unwrap_or(""));
 
                 // error because the container errored
-                (Err(err), vec![])
+                Ok((Err(err), vec![]))
             }
 
-            Some((true, _)) | None => {
-                let container = self.endpoint.docker.containers().get(&self.create_info.id);
-
-                trace!("Fetching {} from container {}", crate::consts::OUTPUTS_DIR_PATH, self.create_info.id);
-                let tar_stream = container
-                    .copy_from(&PathBuf::from(crate::consts::OUTPUTS_DIR_PATH))
-                    .map(|item| {
-                        item.with_context(|| {
-                            anyhow!(
-                                "Copying item from container {} to host",
-                                self.create_info.id
-                            )
-                        })
-                        .map_err(Error::from)
-                    });
+            Some((true, _)) | None => self
+                .fetch_outputs(&staging_store, image, bar)
+                .await
+                .map(|artifacts| (Ok(()), artifacts)),
+        };
 
-                let mut writelock = staging_store.write().await;
-                let artifacts = writelock
-                    .write_files_from_tar_stream(tar_stream)
-                    .await
-                    .with_context(|| anyhow!("Copying the TAR stream to the staging store"))?;
-                container
-                    .stop(Some(std::time::Duration::new(1, 0)))
-                    .await
-                    .with_context(|| anyhow!("Stopping container {}", self.create_info.id))?;
-                (Ok(()), artifacts)
+        // The container is cleaned up even if the outputs could not be fetched, a container that
+        // cannot be removed does not fail the job
+        let succeeded = matches!(outputs, Ok((Ok(()), _)));
+        let container_fate = if self.endpoint.container_cleanup.removes(succeeded) {
+            trace!("Removing container {}", self.container_id);
+            match self.endpoint.backend.remove(&self.container_id).await {
+                Ok(()) => ContainerFate::Removed,
+                Err(e) => {
+                    warn!("Removing container {} failed: {:?}", self.container_id, e);
+                    ContainerFate::Kept
+                },
             }
+        } else {
+            ContainerFate::Kept
         };
+        let (exit_info, artifacts) = outputs?;
 
         Ok({
             FinalizedContainer {
                 artifacts,
                 exit_info,
+                container_fate,
             }
         })
     }
 }
 
+/// What happened to the container of a job after the job finished
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ContainerFate {
+    Kept,
+    Removed,
+}
+
+impl ContainerFate {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ContainerFate::Kept => "kept",
+            ContainerFate::Removed => "removed",
+        }
+    }
+}
+
+/// A tar archive, to be extracted at "/" in the container, with the inputs and outputs directory
+/// and the script, all owned by `user`
+fn ownership_archive(script: &Script, paths: &ContainerPaths, user: ContainerUser) -> Result<Vec<u8>> {
+    // The entries in the archive are relative to "/"
+    let entry_path = |path: &Path| path.iter().skip(1).collect::<PathBuf>();
+    let header = |entry_type: tar::EntryType, mode: u32, size: u64| {
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(entry_type);
+        header.set_mode(mode);
+        header.set_size(size);
+        header.set_uid(u64::from(user.uid()));
+        header.set_gid(u64::from(user.gid()));
+        header
+    };
+
+    let mut archive = tar::Builder::new(Vec::new());
+    let additional_outputs = paths.additional_outputs().values().map(PathBuf::as_path);
+    for dir in [paths.inputs(), paths.outputs()].into_iter().chain(additional_outputs) {
+        archive.append_data(&mut header(tar::EntryType::Directory, 0o755, 0), entry_path(dir), std::io::empty())?;
+    }
+
+    let script = script.as_ref().as_bytes();
+    archive.append_data(&mut header(tar::EntryType::Regular, 0o644, script.len() as u64), entry_path(paths.script()), script)?;
+
+    archive.into_inner()
+        .context("Building archive to hand over paths in container")
+        .map_err(Error::from)
+}
+
 #[derive(Debug)]
 pub struct FinalizedContainer {
     artifacts: Vec<ArtifactPath>,
     exit_info: Result<()>,
+    container_fate: ContainerFate,
 }
 
 impl FinalizedContainer {
+    pub fn container_fate(&self) -> ContainerFate {
+        self.container_fate
+    }
+
     pub fn unpack(self) -> (Vec<ArtifactPath>, Result<()>) {
         (self.artifacts, self.exit_info)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inputs_manifest() {
+        let a = ArtifactPath::new(PathBuf::from("a-1.0.tar.gz")).unwrap();
+        let b = ArtifactPath::new(PathBuf::from("b-2.0.tar.gz")).unwrap();
+        let copied = HashMap::from([
+            (b.clone(), (PathBuf::from("/inputs/b-2.0.tar.gz"), String::from("bbb"))),
+            (a.clone(), (PathBuf::from("/inputs/a-1.0.tar.gz"), String::from("aaa"))),
+        ]);
+        let packages = HashMap::from([
+            (a, dbmodels::Package { id: 1, name: String::from("a"), version: String::from("1.0") }),
+        ]);
+
+        let json = InputsManifest::new(&copied, &packages).to_json().unwrap();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        let artifacts = value["artifacts"].as_array().unwrap();
+        assert_eq!(artifacts.len(), 2);
+        assert_eq!(artifacts[0]["path"], "/inputs/a-1.0.tar.gz");
+        assert_eq!(artifacts[0]["package_name"], "a");
+        assert_eq!(artifacts[0]["package_version"], "1.0");
+        assert_eq!(artifacts[0]["sha256"], "aaa");
+        assert_eq!(artifacts[1]["artifact"], "b-2.0.tar.gz");
+        assert!(artifacts[1]["package_name"].is_null());
+    }
+
+    #[test]
+    fn test_ownership_archive() {
+        let script = Script::from(String::from("#!/bin/bash\necho hi\n"));
+        let user = "1000:100".parse::<ContainerUser>().unwrap();
+        let archive = ownership_archive(&script, &ContainerPaths::default(), user).unwrap();
+
+        let mut archive = tar::Archive::new(archive.as_slice());
+        let entries = archive
+            .entries()
+            .unwrap()
+            .map(|entry| {
+                let entry = entry.unwrap();
+                let header = entry.header();
+                (
+                    entry.path().unwrap().display().to_string(),
+                    header.entry_type(),
+                    header.uid().unwrap(),
+                    header.gid().unwrap(),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(entries, vec![
+            (String::from("inputs"), tar::EntryType::Directory, 1000, 100),
+            (String::from("outputs"), tar::EntryType::Directory, 1000, 100),
+            (String::from("script"), tar::EntryType::Regular, 1000, 100),
+        ]);
+    }
+}
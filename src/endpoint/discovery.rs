@@ -0,0 +1,206 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Discovery of endpoints, for adding them to the configuration
+//!
+//! Endpoints are discovered from the Docker contexts of the user and from the output of the
+//! inventory command from the configuration (`docker.endpoint_inventory_command`).
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use serde::Deserialize;
+use tracing::debug;
+use tracing::warn;
+
+use crate::config::EndpointName;
+use crate::config::EndpointType;
+
+/// An endpoint that was discovered
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+pub struct DiscoveredEndpoint {
+    pub name: EndpointName,
+    pub uri: String,
+
+    #[serde(rename = "type", default = "default_endpoint_type")]
+    pub endpoint_type: EndpointType,
+
+    /// The maximum number of jobs, if the source knows it
+    #[serde(default)]
+    pub maxjobs: Option<usize>,
+}
+
+fn default_endpoint_type() -> EndpointType {
+    EndpointType::Http
+}
+
+/// The metadata of a Docker context, as stored by the Docker CLI
+#[derive(Debug, Deserialize)]
+struct DockerContextMeta {
+    #[serde(rename = "Name")]
+    name: String,
+
+    #[serde(rename = "Endpoints", default)]
+    endpoints: HashMap<String, DockerContextEndpoint>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DockerContextEndpoint {
+    #[serde(rename = "Host")]
+    host: Option<String>,
+}
+
+/// The configuration directory of the Docker CLI (`$DOCKER_CONFIG` or `~/.docker`)
+pub fn docker_config_dir() -> Option<PathBuf> {
+    std::env::var_os("DOCKER_CONFIG")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".docker")))
+}
+
+/// Discover the endpoints from the Docker contexts in `docker_config_dir`
+///
+/// The default context of the Docker CLI is not stored, it is the local Docker socket.
+/// Contexts that cannot be used as endpoint (e.g. via SSH) are skipped.
+pub fn docker_contexts(docker_config_dir: &Path) -> Result<Vec<DiscoveredEndpoint>> {
+    let mut endpoints = vec![DiscoveredEndpoint {
+        name: EndpointName::from(String::from("default")),
+        uri: String::from("/var/run/docker.sock"),
+        endpoint_type: EndpointType::Socket,
+        maxjobs: None,
+    }];
+
+    let meta_dir = docker_config_dir.join("contexts").join("meta");
+    if !meta_dir.is_dir() {
+        debug!("No Docker contexts in {}", meta_dir.display());
+        return Ok(endpoints);
+    }
+
+    for entry in std::fs::read_dir(&meta_dir).with_context(|| anyhow!("Reading {}", meta_dir.display()))? {
+        let path = entry?.path().join("meta.json");
+        let meta = std::fs::read_to_string(&path)
+            .map_err(anyhow::Error::from)
+            .and_then(|s| serde_json::from_str::<DockerContextMeta>(&s).map_err(anyhow::Error::from))
+            .with_context(|| anyhow!("Reading Docker context {}", path.display()))?;
+
+        let host = meta.endpoints.get("docker").and_then(|ep| ep.host.as_deref());
+        match host.and_then(endpoint_of_docker_host) {
+            Some((endpoint_type, uri)) => endpoints.push(DiscoveredEndpoint {
+                name: EndpointName::from(meta.name),
+                uri,
+                endpoint_type,
+                maxjobs: None,
+            }),
+            None => warn!("Skipping Docker context {}: unsupported host {:?}", meta.name, host),
+        }
+    }
+
+    endpoints.sort_by(|a, b| a.name.as_ref().cmp(b.name.as_ref()));
+    Ok(endpoints)
+}
+
+/// The type and URI of an endpoint for the host of a Docker context, if butido can connect to it
+fn endpoint_of_docker_host(host: &str) -> Option<(EndpointType, String)> {
+    if let Some(path) = host.strip_prefix("unix://") {
+        Some((EndpointType::Socket, path.to_string()))
+    } else if let Some(address) = host.strip_prefix("tcp://") {
+        Some((EndpointType::Http, format!("http://{address}")))
+    } else if host.starts_with("http://") {
+        Some((EndpointType::Http, host.to_string()))
+    } else {
+        None
+    }
+}
+
+/// Discover the endpoints by running the inventory `command` with `sh -c`
+///
+/// The command has to print a JSON array of endpoints, with "name", "uri" and optionally "type"
+/// ("http" if not set) and "maxjobs".
+pub async fn inventory(command: &str) -> Result<Vec<DiscoveredEndpoint>> {
+    let output = tokio::process::Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .output()
+        .await
+        .with_context(|| anyhow!("Running inventory command: {}", command))?;
+
+    if !output.status.success() {
+        return Err(anyhow!(
+            "Inventory command failed with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+
+    serde_json::from_slice(&output.stdout)
+        .with_context(|| anyhow!("Parsing output of inventory command: {}", command))
+        .map_err(anyhow::Error::from)
+}
+
+impl DiscoveredEndpoint {
+    /// The configuration stanza for the endpoint, for the configuration file
+    pub fn to_config_stanza(&self, default_maxjobs: usize) -> String {
+        let name = self.name.as_ref();
+        let key = if !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+            name.to_string()
+        } else {
+            toml::Value::String(name.to_string()).to_string()
+        };
+
+        format!(
+            "[docker.endpoints.{key}]\nuri           = {uri}\nendpoint_type = \"{endpoint_type}\"\nmaxjobs       = {maxjobs}\n",
+            uri = toml::Value::String(self.uri.clone()),
+            endpoint_type = self.endpoint_type,
+            maxjobs = self.maxjobs.unwrap_or(default_maxjobs),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_endpoint_of_docker_host() {
+        assert_eq!(endpoint_of_docker_host("unix:///var/run/docker.sock"), Some((EndpointType::Socket, String::from("/var/run/docker.sock"))));
+        assert_eq!(endpoint_of_docker_host("tcp://10.0.0.5:2375"), Some((EndpointType::Http, String::from("http://10.0.0.5:2375"))));
+        assert_eq!(endpoint_of_docker_host("ssh://user@build01"), None);
+    }
+
+    #[test]
+    fn test_inventory_output() {
+        let endpoints: Vec<DiscoveredEndpoint> = serde_json::from_str(r#"[
+            { "name": "build01", "uri": "http://build01:2375", "maxjobs": 8 },
+            { "name": "local", "uri": "/run/docker.sock", "type": "socket" }
+        ]"#).unwrap();
+
+        assert_eq!(endpoints[0].endpoint_type, EndpointType::Http);
+        assert_eq!(endpoints[0].maxjobs, Some(8));
+        assert_eq!(endpoints[1].endpoint_type, EndpointType::Socket);
+    }
+
+    #[test]
+    fn test_to_config_stanza() {
+        let ep = DiscoveredEndpoint {
+            name: EndpointName::from(String::from("build.example.com")),
+            uri: String::from("http://build.example.com:2375"),
+            endpoint_type: EndpointType::Http,
+            maxjobs: None,
+        };
+
+        let stanza = ep.to_config_stanza(2);
+        assert!(stanza.starts_with("[docker.endpoints.\"build.example.com\"]\n"));
+        assert!(stanza.contains("uri           = \"http://build.example.com:2375\"\n"));
+        assert!(stanza.contains("maxjobs       = 2\n"));
+    }
+}
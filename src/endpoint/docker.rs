@@ -0,0 +1,235 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! The container backend that runs the containers via the Docker API
+
+use std::path::Path;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Error;
+use anyhow::Result;
+use futures::future::BoxFuture;
+use futures::stream::BoxStream;
+use futures::FutureExt;
+use futures::StreamExt;
+use futures::TryStreamExt;
+use result_inspect::ResultInspect;
+use shiplift::Docker;
+use shiplift::ExecContainerOptions;
+use shiplift::tty::TtyChunk;
+use tracing::trace;
+
+use crate::endpoint::ContainerBackend;
+use crate::endpoint::ContainerSpec;
+use crate::endpoint::UsageSample;
+
+pub struct DockerBackend {
+    docker: Docker,
+}
+
+impl DockerBackend {
+    pub fn new(docker: Docker) -> Self {
+        DockerBackend { docker }
+    }
+}
+
+impl ContainerBackend for DockerBackend {
+    fn create<'a>(&'a self, spec: &'a ContainerSpec<'a>) -> BoxFuture<'a, Result<String>> {
+        async move {
+            let user = spec.user.map(|user| user.to_string());
+            let mut builder_opts = shiplift::ContainerOptions::builder(spec.image.as_ref());
+            builder_opts.name(&spec.name);
+            builder_opts.env(spec.env.iter().map(AsRef::as_ref).collect::<Vec<&str>>());
+            builder_opts.cmd(vec!["/bin/bash"]); // we start the container with /bin/bash, but exec() the script in it later
+            builder_opts.attach_stdin(true); // we have to attach, otherwise bash exits
+            if let Some(user) = user.as_ref() {
+                builder_opts.user(user);
+            }
+            if let Some(network_mode) = spec.network_mode {
+                builder_opts.network_mode(network_mode);
+            }
+            let builder_opts = builder_opts.build();
+            trace!("Builder options = {:?}", builder_opts);
+
+            self.docker
+                .containers()
+                .create(&builder_opts)
+                .await
+                .inspect(|create_info| trace!("Create info = {:?}", create_info))
+                .map(|create_info| create_info.id)
+                .with_context(|| anyhow!("Creating container with builder options = {:?}", builder_opts))
+                .map_err(Error::from)
+        }
+        .boxed()
+    }
+
+    fn copy_file_into<'a>(&'a self, id: &'a str, path: &'a Path, content: &'a [u8]) -> BoxFuture<'a, Result<()>> {
+        async move {
+            self.docker
+                .containers()
+                .get(id)
+                .copy_file_into(path, content)
+                .await
+                .map_err(Error::from)
+        }
+        .boxed()
+    }
+
+    fn copy_archive_into<'a>(&'a self, id: &'a str, archive: Vec<u8>) -> BoxFuture<'a, Result<()>> {
+        async move {
+            self.docker
+                .containers()
+                .get(id)
+                .copy_to(Path::new("/"), archive.into())
+                .await
+                .map_err(Error::from)
+        }
+        .boxed()
+    }
+
+    fn start<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<()>> {
+        async move {
+            self.docker
+                .containers()
+                .get(id)
+                .start()
+                .await
+                .map_err(Error::from)
+        }
+        .boxed()
+    }
+
+    fn exec<'a>(&'a self, id: &'a str, cmd: &'a [&'a str]) -> BoxFuture<'a, Result<String>> {
+        async move {
+            let exec_opts = ExecContainerOptions::builder()
+                .cmd(cmd.to_vec())
+                .attach_stderr(true)
+                .attach_stdout(true)
+                .build();
+            trace!("Exec options = {:?}", exec_opts);
+
+            shiplift::Exec::create(&self.docker, id, &exec_opts)
+                .await?
+                .inspect()
+                .await
+                .map(|details| details.id)
+                .map_err(Error::from)
+        }
+        .boxed()
+    }
+
+    /// The output of the script is copied to the output of the main process of the container as
+    /// well, so that it is part of the logs of the container
+    fn script_command(&self, script_path: &str) -> Vec<String> {
+        vec![
+            String::from("/bin/bash"),
+            String::from("-c"),
+            String::from(r#"exec > >(tee /proc/1/fd/1) 2> >(tee /proc/1/fd/2 >&2); exec /bin/bash "$0""#),
+            script_path.to_string(),
+        ]
+    }
+
+    fn exec_output<'a>(&'a self, exec_id: &'a str) -> BoxStream<'a, Result<Vec<u8>>> {
+        futures::stream::once(shiplift::Exec::get(&self.docker, exec_id))
+            .flat_map(|exec| exec.start())
+            .map_ok(|chunk| match chunk {
+                TtyChunk::StdIn(buffer) | TtyChunk::StdOut(buffer) | TtyChunk::StdErr(buffer) => buffer,
+            })
+            .map_err(Error::from)
+            .boxed()
+    }
+
+    fn exec_exit_code<'a>(&'a self, exec_id: &'a str) -> BoxFuture<'a, Result<Option<i32>>> {
+        async move {
+            shiplift::Exec::get(&self.docker, exec_id)
+                .await
+                .inspect()
+                .await
+                .map(|details| details.exit_code.map(|c| c as i32))
+                .map_err(Error::from)
+        }
+        .boxed()
+    }
+
+    fn oom_killed<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<bool>> {
+        async move {
+            self.docker
+                .containers()
+                .get(id)
+                .inspect()
+                .await
+                .map(|details| details.state.oom_killed)
+                .map_err(Error::from)
+        }
+        .boxed()
+    }
+
+    fn usage_samples<'a>(&'a self, id: &'a str) -> BoxStream<'a, Result<UsageSample>> {
+        self.docker
+            .containers()
+            .get(id)
+            .stats()
+            .map_ok(|stats| UsageSample {
+                cpu_ns: stats.cpu_stats.cpu_usage.total_usage,
+                memory_bytes: stats.memory_stats.usage.max(stats.memory_stats.max_usage),
+                io_bytes: stats.blkio_stats
+                    .io_service_bytes_recursive
+                    .iter()
+                    .filter(|stat| stat.op.eq_ignore_ascii_case("read") || stat.op.eq_ignore_ascii_case("write"))
+                    .map(|stat| stat.value)
+                    .sum(),
+            })
+            .map_err(Error::from)
+            .boxed()
+    }
+
+    fn copy_from<'a>(&'a self, id: &'a str, path: &'a Path) -> BoxFuture<'a, Result<Option<BoxStream<'a, Result<Vec<u8>>>>>> {
+        async move {
+            let mut stream = self.docker.containers().get(id).copy_from(path).boxed();
+
+            // A missing path is reported as error of the stream
+            match stream.next().await {
+                None => Ok(Some(futures::stream::empty().boxed())),
+                Some(Err(shiplift::Error::Fault { code, .. })) if code.as_u16() == 404 => Ok(None),
+                Some(Err(e)) => Err(Error::from(e)),
+                Some(Ok(first)) => {
+                    let rest = stream.map_err(Error::from);
+                    Ok(Some(futures::stream::once(async move { Ok(first) }).chain(rest).boxed()))
+                },
+            }
+        }
+        .boxed()
+    }
+
+    fn stop<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<()>> {
+        async move {
+            self.docker
+                .containers()
+                .get(id)
+                .stop(Some(std::time::Duration::new(1, 0)))
+                .await
+                .map_err(Error::from)
+        }
+        .boxed()
+    }
+
+    fn remove<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<()>> {
+        async move {
+            self.docker
+                .containers()
+                .get(id)
+                .remove(shiplift::RmContainerOptions::builder().force(true).build())
+                .await
+                .map_err(Error::from)
+        }
+        .boxed()
+    }
+}
@@ -0,0 +1,229 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Sharing the endpoints between submits that run at the same time, e.g. in the daemon
+//!
+//! All submits use the same endpoints, so the maximum number of jobs of an endpoint applies to
+//! the jobs of all submits. A job waiting for a free endpoint is only scheduled if its submit is
+//! next: submits with a higher priority go first, submits with the same priority share the
+//! endpoints by their weight, i.e. a submit with weight 2 gets twice as many running jobs as a
+//! submit with weight 1 while both have jobs waiting.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use anyhow::Result;
+use getset::Getters;
+use uuid::Uuid;
+
+use crate::endpoint::Endpoint;
+use crate::endpoint::EndpointConfiguration;
+
+/// The endpoints shared by the submits that run at the same time
+#[derive(Getters)]
+pub struct SharedEndpoints {
+    #[getset(get = "pub")]
+    endpoints: Vec<Arc<Endpoint>>,
+
+    #[getset(get = "pub")]
+    fair_share: FairShare,
+}
+
+impl SharedEndpoints {
+    pub async fn setup(endpoints: Vec<EndpointConfiguration>) -> Result<Self> {
+        Ok(SharedEndpoints {
+            endpoints: crate::endpoint::util::setup_endpoints(endpoints).await?,
+            fair_share: FairShare::default(),
+        })
+    }
+}
+
+/// The share of a submit of the endpoints
+#[derive(Debug)]
+struct SubmitShare {
+    priority: i64,
+    weight: u32,
+    running: usize,
+    waiting: usize,
+}
+
+impl Default for SubmitShare {
+    fn default() -> Self {
+        SubmitShare {
+            priority: 0,
+            weight: 1,
+            running: 0,
+            waiting: 0,
+        }
+    }
+}
+
+impl SubmitShare {
+    /// Whether jobs of this submit are scheduled before the jobs of `other`
+    fn is_ahead_of(&self, other: &SubmitShare) -> bool {
+        // running / weight < other.running / other.weight, without dividing
+        self.priority > other.priority
+            || (self.priority == other.priority
+                && (self.running as u64 * other.weight as u64) < (other.running as u64 * self.weight as u64))
+    }
+}
+
+/// The shares of the submits of the endpoints
+#[derive(Debug, Default)]
+pub struct FairShare(Mutex<HashMap<Uuid, SubmitShare>>);
+
+impl FairShare {
+    /// Register the submit `submit` with its `priority` and `weight`
+    ///
+    /// Submits that are not registered have priority 0 and weight 1.
+    pub fn register(&self, submit: Uuid, priority: i64, weight: u32) {
+        let mut shares = self.0.lock().unwrap();
+        let share = shares.entry(submit).or_default();
+        share.priority = priority;
+        share.weight = weight.max(1);
+    }
+
+    /// Remove the submit `submit`, when it finished
+    pub fn unregister(&self, submit: &Uuid) {
+        self.0.lock().unwrap().remove(submit);
+    }
+
+    /// Register a waiting job of `submit`, the job is removed when the returned object is dropped
+    pub fn enqueue(&self, submit: Uuid) -> WaitingShare<'_> {
+        self.0.lock().unwrap().entry(submit).or_default().waiting += 1;
+        WaitingShare { fair_share: self, submit }
+    }
+
+    /// Whether no other submit with waiting jobs is ahead of `submit`
+    fn is_next(&self, submit: &Uuid) -> bool {
+        let shares = self.0.lock().unwrap();
+        let Some(share) = shares.get(submit) else { return true };
+        shares
+            .iter()
+            .filter(|(uuid, other)| *uuid != submit && other.waiting > 0)
+            .all(|(_, other)| !other.is_ahead_of(share))
+    }
+
+    /// The number of running jobs of `submit`
+    pub fn running_jobs(&self, submit: &Uuid) -> usize {
+        self.0.lock().unwrap().get(submit).map(|share| share.running).unwrap_or(0)
+    }
+
+    fn update(&self, submit: &Uuid, f: impl FnOnce(&mut SubmitShare)) {
+        if let Some(share) = self.0.lock().unwrap().get_mut(submit) {
+            f(share)
+        }
+    }
+}
+
+pub struct WaitingShare<'a> {
+    fair_share: &'a FairShare,
+    submit: Uuid,
+}
+
+impl<'a> WaitingShare<'a> {
+    /// Whether the submit of the waiting job is next
+    pub fn is_next(&self) -> bool {
+        self.fair_share.is_next(&self.submit)
+    }
+
+    /// The waiting job got an endpoint of `shared`, it counts as running until the returned object
+    /// is dropped
+    pub fn start(self, shared: Arc<SharedEndpoints>) -> RunningShare {
+        self.fair_share.update(&self.submit, |share| share.running += 1);
+        RunningShare { shared, submit: self.submit }
+    }
+}
+
+impl<'a> Drop for WaitingShare<'a> {
+    fn drop(&mut self) {
+        self.fair_share.update(&self.submit, |share| share.waiting -= 1);
+    }
+}
+
+/// A running job of a submit, counted in the share of the submit
+pub struct RunningShare {
+    shared: Arc<SharedEndpoints>,
+    submit: Uuid,
+}
+
+impl Drop for RunningShare {
+    fn drop(&mut self) {
+        self.shared.fair_share.update(&self.submit, |share| share.running -= 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shared() -> Arc<SharedEndpoints> {
+        Arc::new(SharedEndpoints {
+            endpoints: Vec::new(),
+            fair_share: FairShare::default(),
+        })
+    }
+
+    #[test]
+    fn test_fair_share_by_weight() {
+        let shared = shared();
+        let fair_share = shared.fair_share();
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+        fair_share.register(a, 0, 2);
+        fair_share.register(b, 0, 1);
+
+        let waiting_a = fair_share.enqueue(a);
+        let waiting_b = fair_share.enqueue(b);
+        assert!(waiting_a.is_next());
+        assert!(waiting_b.is_next());
+        let running_b = waiting_b.start(shared.clone());
+
+        // a has twice the weight of b, so it gets two jobs while b waits
+        let waiting_b = fair_share.enqueue(b);
+        assert!(waiting_a.is_next());
+        assert!(!waiting_b.is_next());
+        let running_a1 = waiting_a.start(shared.clone());
+
+        let waiting_a = fair_share.enqueue(a);
+        assert!(!waiting_b.is_next());
+        let running_a2 = waiting_a.start(shared.clone());
+
+        let waiting_a = fair_share.enqueue(a);
+        assert!(waiting_a.is_next());
+        assert!(waiting_b.is_next());
+        assert_eq!(fair_share.running_jobs(&a), 2);
+
+        drop((running_a1, running_a2, running_b));
+        assert_eq!(fair_share.running_jobs(&a), 0);
+        assert_eq!(fair_share.running_jobs(&b), 0);
+    }
+
+    #[test]
+    fn test_fair_share_by_priority() {
+        let shared = shared();
+        let fair_share = shared.fair_share();
+        let (low, high) = (Uuid::new_v4(), Uuid::new_v4());
+        fair_share.register(low, 0, 1);
+        fair_share.register(high, 10, 1);
+
+        let waiting_low = fair_share.enqueue(low);
+        {
+            let waiting_high = fair_share.enqueue(high);
+            let _running_high = fair_share.enqueue(high).start(shared.clone());
+            assert!(waiting_high.is_next());
+            assert!(!waiting_low.is_next());
+        }
+        assert!(waiting_low.is_next());
+
+        fair_share.unregister(&high);
+        assert_eq!(fair_share.running_jobs(&high), 0);
+    }
+}
@@ -0,0 +1,425 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! The container backend that runs the containers as pods in a Kubernetes cluster
+//!
+//! Each job gets a pod with a single container that idles until it is stopped. The files are
+//! copied in and out of the container by piping tar archives through `tar` in the container, so
+//! the images have to contain `tar`, like they have to contain `bash` for the script.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Mutex;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Error;
+use anyhow::Result;
+use futures::future::BoxFuture;
+use futures::stream::BoxStream;
+use futures::FutureExt;
+use futures::StreamExt;
+use k8s_openapi::api::core::v1::Pod;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::Status;
+use kube::api::Api;
+use kube::api::AttachParams;
+use kube::api::AttachedProcess;
+use kube::api::DeleteParams;
+use kube::api::PostParams;
+use kube::config::KubeConfigOptions;
+use kube::config::Kubeconfig;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWriteExt;
+use tracing::debug;
+use tracing::trace;
+
+use crate::config::KubernetesConfig;
+use crate::endpoint::ContainerBackend;
+use crate::endpoint::ContainerSpec;
+use crate::endpoint::util::read_stream;
+
+/// The name of the container in the pod of a job
+const CONTAINER_NAME: &str = "build";
+
+/// The exit code of the command in `copy_from()` if the path does not exist
+const EXIT_CODE_NOT_FOUND: i32 = 100;
+
+pub struct KubernetesBackend {
+    pods: Api<Pod>,
+    config: KubernetesConfig,
+
+    /// The running executions, by execution ID
+    execs: Mutex<HashMap<String, AttachedProcess>>,
+}
+
+impl KubernetesBackend {
+    pub async fn new(config: &KubernetesConfig) -> Result<Self> {
+        let options = KubeConfigOptions {
+            context: config.context().clone(),
+            cluster: None,
+            user: None,
+        };
+        let client_config = match config.kubeconfig() {
+            Some(path) => {
+                let kubeconfig = Kubeconfig::read_from(path)
+                    .with_context(|| anyhow!("Reading kubeconfig {}", path.display()))?;
+                kube::Config::from_custom_kubeconfig(kubeconfig, &options).await?
+            },
+            None => kube::Config::from_kubeconfig(&options).await?,
+        };
+
+        let namespace = config.namespace().clone().unwrap_or_else(|| client_config.default_namespace.clone());
+        let client = kube::Client::try_from(client_config).context("Creating Kubernetes client")?;
+
+        Ok(KubernetesBackend {
+            pods: Api::namespaced(client, &namespace),
+            config: config.clone(),
+            execs: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// The pod for a job
+    fn pod(&self, name: &str, spec: &ContainerSpec<'_>) -> Result<Pod> {
+        let env = spec.env
+            .iter()
+            .map(|kv| {
+                let (name, value) = kv.split_once('=').unwrap_or((kv, ""));
+                serde_json::json!({ "name": name, "value": value })
+            })
+            .collect::<Vec<_>>();
+
+        let limits = [("cpu", self.config.cpu_limit()), ("memory", self.config.memory_limit())]
+            .into_iter()
+            .filter_map(|(resource, limit)| limit.as_ref().map(|limit| (resource.to_string(), serde_json::json!(limit))))
+            .collect::<serde_json::Map<_, _>>();
+
+        let security_context = spec.user.map(|user| {
+            serde_json::json!({ "runAsUser": user.uid(), "runAsGroup": user.gid() })
+        });
+
+        if let Some(network_mode) = spec.network_mode.filter(|mode| *mode != "host") {
+            debug!("Network mode {} is not supported on Kubernetes, using the network of the cluster", network_mode);
+        }
+
+        let pod = serde_json::json!({
+            "apiVersion": "v1",
+            "kind": "Pod",
+            "metadata": {
+                "name": name,
+                "labels": { "app.kubernetes.io/managed-by": "butido" },
+                "annotations": { "butido/container-name": spec.name },
+            },
+            "spec": {
+                "restartPolicy": "Never",
+                "hostNetwork": spec.network_mode == Some("host"),
+                "containers": [{
+                    "name": CONTAINER_NAME,
+                    "image": spec.image.as_ref(),
+                    "env": env,
+                    // idle until `stop()`, the script is executed in the container later
+                    "command": ["/bin/sh", "-c", "trap 'exit 0' TERM; while true; do sleep 1; done"],
+                    "resources": { "limits": limits, "requests": limits },
+                    "securityContext": security_context,
+                }],
+            },
+        });
+
+        serde_json::from_value(pod).context("Building pod specification").map_err(Error::from)
+    }
+
+    /// Wait until the container of the pod is running
+    async fn wait_running(&self, name: &str) -> Result<()> {
+        loop {
+            let pod = self.pods.get(name).await?;
+            let status = pod.status.unwrap_or_default();
+            match status.phase.as_deref() {
+                Some("Running") => return Ok(()),
+                Some("Failed") | Some("Succeeded") => return Err(anyhow!("Pod {} terminated before it was used", name)),
+                phase => trace!("Pod {} is in phase {:?}", name, phase),
+            }
+
+            let waiting_reason = status.container_statuses
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|cs| cs.state?.waiting?.reason)
+                .find(|reason| reason == "ErrImagePull" || reason == "ImagePullBackOff" || reason == "InvalidImageName");
+            if let Some(reason) = waiting_reason {
+                return Err(anyhow!("Pod {} cannot start: {}", name, reason));
+            }
+
+            tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+        }
+    }
+
+    /// Run `cmd` in the container, with `stdin` as input, and return its output and exit code
+    async fn run(&self, name: &str, cmd: Vec<String>, stdin: Option<Vec<u8>>) -> Result<(Vec<u8>, Option<i32>)> {
+        let params = AttachParams::default()
+            .container(CONTAINER_NAME)
+            .stdin(stdin.is_some())
+            .stderr(false);
+        let mut process = self.pods
+            .exec(name, cmd, &params)
+            .await
+            .with_context(|| anyhow!("Executing command in pod {}", name))?;
+
+        let writer = process.stdin();
+        let write = async {
+            if let (Some(input), Some(mut writer)) = (stdin, writer) {
+                writer.write_all(&input).await?;
+                writer.shutdown().await?;
+            }
+            Ok::<_, Error>(())
+        };
+        let mut stdout = process.stdout().ok_or_else(|| anyhow!("No stdout of command in pod {}", name))?;
+        let read = async {
+            let mut output = Vec::new();
+            stdout.read_to_end(&mut output).await?;
+            Ok::<_, Error>(output)
+        };
+        let (written, output) = tokio::join!(write, read);
+        written?;
+        let output = output?;
+
+        let status = process.take_status()
+            .ok_or_else(|| anyhow!("No status of command in pod {}", name))?
+            .await;
+        process.join().await.with_context(|| anyhow!("Waiting for command in pod {}", name))?;
+        Ok((output, status.as_ref().and_then(exit_code_of)))
+    }
+
+    /// Extract the tar `archive` at "/" in the container
+    async fn extract(&self, name: &str, archive: Vec<u8>) -> Result<()> {
+        let cmd = vec![String::from("tar"), String::from("-x"), String::from("-C"), String::from("/")];
+        match self.run(name, cmd, Some(archive)).await? {
+            (_, Some(0)) => Ok(()),
+            (_, code) => Err(anyhow!("Extracting archive in pod {} failed with exit code {:?}", name, code)),
+        }
+    }
+}
+
+impl ContainerBackend for KubernetesBackend {
+    fn create<'a>(&'a self, spec: &'a ContainerSpec<'a>) -> BoxFuture<'a, Result<String>> {
+        async move {
+            let name = pod_name(&spec.name);
+            let pod = self.pod(&name, spec)?;
+            trace!("Pod = {:?}", pod);
+
+            self.pods
+                .create(&PostParams::default(), &pod)
+                .await
+                .with_context(|| anyhow!("Creating pod {}", name))?;
+
+            // Files are copied into the container right after creating it, which needs a running
+            // container
+            self.wait_running(&name).await?;
+            Ok(name)
+        }
+        .boxed()
+    }
+
+    fn copy_file_into<'a>(&'a self, id: &'a str, path: &'a Path, content: &'a [u8]) -> BoxFuture<'a, Result<()>> {
+        async move {
+            let mut header = tar::Header::new_gnu();
+            header.set_mode(0o644);
+            header.set_size(content.len() as u64);
+            let mut archive = tar::Builder::new(Vec::new());
+            archive.append_data(&mut header, path.strip_prefix("/").unwrap_or(path), content)?;
+            self.extract(id, archive.into_inner()?).await
+        }
+        .boxed()
+    }
+
+    fn copy_archive_into<'a>(&'a self, id: &'a str, archive: Vec<u8>) -> BoxFuture<'a, Result<()>> {
+        self.extract(id, archive).boxed()
+    }
+
+    fn start<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<()>> {
+        // The pod is already running since it was created
+        self.wait_running(id).boxed()
+    }
+
+    fn exec<'a>(&'a self, id: &'a str, cmd: &'a [&'a str]) -> BoxFuture<'a, Result<String>> {
+        async move {
+            let params = AttachParams::default().container(CONTAINER_NAME);
+            let process = self.pods
+                .exec(id, cmd.to_vec(), &params)
+                .await
+                .with_context(|| anyhow!("Executing {:?} in pod {}", cmd, id))?;
+
+            let exec_id = uuid::Uuid::new_v4().to_string();
+            self.execs
+                .lock()
+                .map_err(|_| anyhow!("Lock poisoned"))?
+                .insert(exec_id.clone(), process);
+            Ok(exec_id)
+        }
+        .boxed()
+    }
+
+    fn exec_output<'a>(&'a self, exec_id: &'a str) -> BoxStream<'a, Result<Vec<u8>>> {
+        let readers = self.execs
+            .lock()
+            .map_err(|_| anyhow!("Lock poisoned"))
+            .and_then(|mut execs| {
+                let process = execs.get_mut(exec_id).ok_or_else(|| anyhow!("Unknown execution {}", exec_id))?;
+                Ok((process.stdout(), process.stderr()))
+            });
+
+        match readers {
+            Ok((stdout, stderr)) => {
+                let stdout = stdout.map(|r| read_stream(r).boxed()).unwrap_or_else(|| futures::stream::empty().boxed());
+                let stderr = stderr.map(|r| read_stream(r).boxed()).unwrap_or_else(|| futures::stream::empty().boxed());
+                futures::stream::select(stdout, stderr).boxed()
+            },
+            Err(e) => futures::stream::once(async move { Err(e) }).boxed(),
+        }
+    }
+
+    fn exec_exit_code<'a>(&'a self, exec_id: &'a str) -> BoxFuture<'a, Result<Option<i32>>> {
+        async move {
+            let mut process = self.execs
+                .lock()
+                .map_err(|_| anyhow!("Lock poisoned"))?
+                .remove(exec_id)
+                .ok_or_else(|| anyhow!("Unknown execution {}", exec_id))?;
+
+            let status = match process.take_status() {
+                Some(status) => status.await,
+                None => None,
+            };
+            process.join().await.with_context(|| anyhow!("Waiting for execution {}", exec_id))?;
+            Ok(status.as_ref().and_then(exit_code_of))
+        }
+        .boxed()
+    }
+
+    fn oom_killed<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<bool>> {
+        async move {
+            let pod = self.pods.get(id).await?;
+            let oom_killed = pod.status
+                .and_then(|status| status.container_statuses)
+                .unwrap_or_default()
+                .into_iter()
+                .flat_map(|cs| [cs.state, cs.last_state])
+                .filter_map(|state| state?.terminated?.reason)
+                .any(|reason| reason == "OOMKilled");
+            Ok(oom_killed)
+        }
+        .boxed()
+    }
+
+    fn copy_from<'a>(&'a self, id: &'a str, path: &'a Path) -> BoxFuture<'a, Result<Option<BoxStream<'a, Result<Vec<u8>>>>>> {
+        async move {
+            // Like the Docker API, the archive contains the directory itself, not only its content
+            let script = format!(r#"test -e "$1" || exit {EXIT_CODE_NOT_FOUND}; tar -c -C "$(dirname "$1")" "$(basename "$1")""#);
+            let cmd = vec![String::from("/bin/sh"), String::from("-c"), script, String::from("sh"), path.display().to_string()];
+            match self.run(id, cmd, None).await? {
+                (output, Some(0)) => Ok(Some(futures::stream::once(async move { Ok(output) }).boxed())),
+                (_, Some(EXIT_CODE_NOT_FOUND)) => Ok(None),
+                (_, code) => Err(anyhow!("Archiving {} in pod {} failed with exit code {:?}", path.display(), id, code)),
+            }
+        }
+        .boxed()
+    }
+
+    fn stop<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<()>> {
+        async move {
+            // The pod terminates, but is kept (with its logs), until it is removed
+            let cmd = vec![String::from("kill"), String::from("1")];
+            self.run(id, cmd, None).await.map(|_| ())
+        }
+        .boxed()
+    }
+
+    fn remove<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<()>> {
+        async move {
+            self.pods
+                .delete(id, &DeleteParams::default())
+                .await
+                .map(|_| ())
+                .with_context(|| anyhow!("Deleting pod {}", id))
+                .map_err(Error::from)
+        }
+        .boxed()
+    }
+}
+
+/// The exit code of a command from its status
+///
+/// Kubernetes reports a non-zero exit code as cause of a failure, not as a number.
+fn exit_code_of(status: &Status) -> Option<i32> {
+    match status.status.as_deref() {
+        Some("Success") => Some(0),
+        _ => status.details
+            .as_ref()?
+            .causes
+            .as_ref()?
+            .iter()
+            .find(|cause| cause.reason.as_deref() == Some("ExitCode"))
+            .and_then(|cause| cause.message.as_deref())
+            .and_then(|code| code.parse().ok()),
+    }
+}
+
+/// A valid pod name (lowercase alphanumerics and '-', at most 63 characters) for a container name
+///
+/// The container names end with the ID of the job, so the end of the name is kept if it is too
+/// long.
+fn pod_name(container_name: &str) -> String {
+    const MAX_LEN: usize = 63;
+
+    let name = container_name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '-' })
+        .collect::<String>();
+    let name = &name[name.len().saturating_sub(MAX_LEN)..];
+    name.trim_matches('-').to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::StatusCause;
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::StatusDetails;
+
+    #[test]
+    fn test_pod_name() {
+        assert_eq!(pod_name("butido-foo_bar-1.0-abc"), "butido-foo-bar-1-0-abc");
+        assert_eq!(pod_name("-Foo-"), "foo");
+
+        let long = format!("butido-{}-1.0-{}", "a".repeat(40), uuid::Uuid::nil());
+        let name = pod_name(&long);
+        assert!(name.len() <= 63);
+        assert!(name.ends_with(&uuid::Uuid::nil().to_string()));
+    }
+
+    #[test]
+    fn test_exit_code_of() {
+        let success = Status { status: Some(String::from("Success")), ..Default::default() };
+        assert_eq!(exit_code_of(&success), Some(0));
+
+        let failure = Status {
+            status: Some(String::from("Failure")),
+            details: Some(StatusDetails {
+                causes: Some(vec![StatusCause {
+                    reason: Some(String::from("ExitCode")),
+                    message: Some(String::from("2")),
+                    ..Default::default()
+                }]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        assert_eq!(exit_code_of(&failure), Some(2));
+
+        assert_eq!(exit_code_of(&Status::default()), None);
+    }
+}
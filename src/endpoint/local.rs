@@ -0,0 +1,331 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! The container backend that runs the scripts directly on the host, without containers
+//!
+//! This is meant for iterating on the scripts of packages, where starting the containers takes
+//! longer than running the script. A "container" is a directory in the temporary directory of
+//! the host that emulates the layout of the container: the script, the inputs and the outputs are
+//! located below it at their paths in the container. The paths in the environment of the job and
+//! in the executed commands are rewritten to point into this directory, so scripts have to use
+//! the environment variables butido provides (e.g. `$BUTIDO_OUTPUT_DIR`) instead of hardcoded
+//! paths.
+//!
+//! There is no isolation: the script runs as the user that runs butido, with the tools of the
+//! host, and the image of the job is not used.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::sync::Mutex;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Error;
+use anyhow::Result;
+use futures::future::BoxFuture;
+use futures::stream::BoxStream;
+use futures::FutureExt;
+use futures::StreamExt;
+use tokio::process::Child;
+use tracing::debug;
+use tracing::info;
+use tracing::trace;
+
+use crate::endpoint::util::read_stream;
+use crate::endpoint::ContainerBackend;
+use crate::endpoint::ContainerSpec;
+
+#[derive(Debug)]
+struct LocalContainer {
+    /// The directory that emulates the root directory of the container
+    root: PathBuf,
+
+    /// The paths in the container that are rewritten to paths below `root`
+    paths: Vec<PathBuf>,
+
+    /// The environment of the processes, with rewritten paths
+    env: Vec<(String, String)>,
+}
+
+impl LocalContainer {
+    /// The path on the host for `path` in the container
+    fn host_path(&self, path: &Path) -> PathBuf {
+        self.root.join(path.strip_prefix("/").unwrap_or(path))
+    }
+
+    /// Rewrite the paths in the container in the space-separated list `value` to the paths on
+    /// the host
+    fn rewrite(&self, value: &str) -> String {
+        value
+            .split(' ')
+            .map(|word| {
+                let path = Path::new(word);
+                if self.paths.iter().any(|p| path.starts_with(p)) {
+                    self.host_path(path).display().to_string()
+                } else {
+                    word.to_string()
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+#[derive(Default)]
+pub struct LocalBackend {
+    /// The containers, by ID
+    containers: Mutex<HashMap<String, LocalContainer>>,
+
+    /// The running executions, by execution ID, with the ID of their container
+    execs: Mutex<HashMap<String, (String, Child)>>,
+}
+
+impl LocalBackend {
+    pub fn new() -> Self {
+        LocalBackend::default()
+    }
+
+    /// Run `f` with the container `id`
+    fn with_container<T>(&self, id: &str, f: impl FnOnce(&LocalContainer) -> T) -> Result<T> {
+        self.containers
+            .lock()
+            .map_err(|_| anyhow!("Lock poisoned"))?
+            .get(id)
+            .map(f)
+            .ok_or_else(|| anyhow!("Unknown container {}", id))
+    }
+}
+
+impl ContainerBackend for LocalBackend {
+    fn create<'a>(&'a self, spec: &'a ContainerSpec<'a>) -> BoxFuture<'a, Result<String>> {
+        async move {
+            let root = std::env::temp_dir().join(&spec.name);
+            tokio::fs::create_dir_all(&root)
+                .await
+                .with_context(|| anyhow!("Creating directory {}", root.display()))?;
+            info!("Running job {} locally in {} instead of image {}", spec.name, root.display(), spec.image.as_ref());
+            if let Some(user) = spec.user {
+                debug!("Not running job {} as {}, but as the current user", spec.name, user);
+            }
+
+            let mut container = LocalContainer {
+                root,
+                paths: [spec.paths.script(), spec.paths.inputs(), spec.paths.outputs(), Path::new(crate::consts::PATCH_DIR_PATH)]
+                    .into_iter()
+                    .chain(spec.paths.additional_outputs().values().map(PathBuf::as_path))
+                    .map(Path::to_path_buf)
+                    .collect(),
+                env: Vec::new(),
+            };
+            container.env = spec.env
+                .iter()
+                .map(|kv| {
+                    let (name, value) = kv.split_once('=').unwrap_or((kv, ""));
+                    (name.to_string(), container.rewrite(value))
+                })
+                .collect();
+
+            // The outputs directories exist in containers of all images, the script only has to
+            // fill them
+            for dir in std::iter::once(spec.paths.outputs()).chain(spec.paths.additional_outputs().values().map(PathBuf::as_path)) {
+                let dir = container.host_path(dir);
+                tokio::fs::create_dir_all(&dir)
+                    .await
+                    .with_context(|| anyhow!("Creating directory {}", dir.display()))?;
+            }
+            trace!("Local container = {:?}", container);
+
+            self.containers
+                .lock()
+                .map_err(|_| anyhow!("Lock poisoned"))?
+                .insert(spec.name.clone(), container);
+            Ok(spec.name.clone())
+        }
+        .boxed()
+    }
+
+    fn copy_file_into<'a>(&'a self, id: &'a str, path: &'a Path, content: &'a [u8]) -> BoxFuture<'a, Result<()>> {
+        async move {
+            let path = self.with_container(id, |c| c.host_path(path))?;
+            if let Some(parent) = path.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+            tokio::fs::write(&path, content)
+                .await
+                .with_context(|| anyhow!("Writing {}", path.display()))
+                .map_err(Error::from)
+        }
+        .boxed()
+    }
+
+    fn copy_archive_into<'a>(&'a self, id: &'a str, archive: Vec<u8>) -> BoxFuture<'a, Result<()>> {
+        async move {
+            // The ownership of the entries is not preserved, everything belongs to the current user
+            let root = self.with_container(id, |c| c.root.clone())?;
+            tokio::task::spawn_blocking(move || tar::Archive::new(archive.as_slice()).unpack(&root))
+                .await?
+                .with_context(|| anyhow!("Extracting archive in container {}", id))
+                .map_err(Error::from)
+        }
+        .boxed()
+    }
+
+    fn start<'a>(&'a self, _id: &'a str) -> BoxFuture<'a, Result<()>> {
+        futures::future::ok(()).boxed()
+    }
+
+    fn exec<'a>(&'a self, id: &'a str, cmd: &'a [&'a str]) -> BoxFuture<'a, Result<String>> {
+        async move {
+            let (root, args, env) = self.with_container(id, |c| {
+                let args = cmd.iter().map(|arg| c.rewrite(arg)).collect::<Vec<_>>();
+                (c.root.clone(), args, c.env.clone())
+            })?;
+            let (program, args) = args.split_first().ok_or_else(|| anyhow!("Empty command"))?;
+            trace!("Executing {} {:?} in {}", program, args, root.display());
+
+            let child = tokio::process::Command::new(program)
+                .args(args)
+                .envs(env)
+                .current_dir(&root)
+                .stdin(Stdio::null())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .kill_on_drop(true)
+                .spawn()
+                .with_context(|| anyhow!("Executing {:?} in container {}", cmd, id))?;
+
+            let exec_id = uuid::Uuid::new_v4().to_string();
+            self.execs
+                .lock()
+                .map_err(|_| anyhow!("Lock poisoned"))?
+                .insert(exec_id.clone(), (id.to_string(), child));
+            Ok(exec_id)
+        }
+        .boxed()
+    }
+
+    fn exec_output<'a>(&'a self, exec_id: &'a str) -> BoxStream<'a, Result<Vec<u8>>> {
+        let readers = self.execs
+            .lock()
+            .map_err(|_| anyhow!("Lock poisoned"))
+            .and_then(|mut execs| {
+                let (_, child) = execs.get_mut(exec_id).ok_or_else(|| anyhow!("Unknown execution {}", exec_id))?;
+                Ok((child.stdout.take(), child.stderr.take()))
+            });
+
+        match readers {
+            Ok((stdout, stderr)) => {
+                let stdout = stdout.map(|r| read_stream(r).boxed()).unwrap_or_else(|| futures::stream::empty().boxed());
+                let stderr = stderr.map(|r| read_stream(r).boxed()).unwrap_or_else(|| futures::stream::empty().boxed());
+                futures::stream::select(stdout, stderr).boxed()
+            },
+            Err(e) => futures::stream::once(async move { Err(e) }).boxed(),
+        }
+    }
+
+    fn exec_exit_code<'a>(&'a self, exec_id: &'a str) -> BoxFuture<'a, Result<Option<i32>>> {
+        async move {
+            let (_, mut child) = self.execs
+                .lock()
+                .map_err(|_| anyhow!("Lock poisoned"))?
+                .remove(exec_id)
+                .ok_or_else(|| anyhow!("Unknown execution {}", exec_id))?;
+
+            child.wait()
+                .await
+                .map(|status| status.code())
+                .with_context(|| anyhow!("Waiting for execution {}", exec_id))
+                .map_err(Error::from)
+        }
+        .boxed()
+    }
+
+    fn oom_killed<'a>(&'a self, _id: &'a str) -> BoxFuture<'a, Result<bool>> {
+        futures::future::ok(false).boxed()
+    }
+
+    fn copy_from<'a>(&'a self, id: &'a str, path: &'a Path) -> BoxFuture<'a, Result<Option<BoxStream<'a, Result<Vec<u8>>>>>> {
+        async move {
+            let host_path = self.with_container(id, |c| c.host_path(path))?;
+            if !host_path.exists() {
+                return Ok(None);
+            }
+
+            // Like the Docker API, the archive contains the directory itself, not only its content
+            let name = path.file_name().map(PathBuf::from).unwrap_or_default();
+            tokio::task::spawn_blocking(move || {
+                let mut archive = tar::Builder::new(Vec::new());
+                if host_path.is_dir() {
+                    archive.append_dir_all(&name, &host_path)?;
+                } else {
+                    archive.append_path_with_name(&host_path, &name)?;
+                }
+                archive.into_inner()
+            })
+            .await?
+            .map(|archive| Some(futures::stream::once(async move { Ok(archive) }).boxed()))
+            .with_context(|| anyhow!("Archiving {} in container {}", path.display(), id))
+            .map_err(Error::from)
+        }
+        .boxed()
+    }
+
+    fn stop<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<()>> {
+        async move {
+            // Kill the processes that are still running, like stopping the container would
+            let mut execs = self.execs.lock().map_err(|_| anyhow!("Lock poisoned"))?;
+            for (container_id, child) in execs.values_mut() {
+                if container_id == id {
+                    child.start_kill()?;
+                }
+            }
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn remove<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<()>> {
+        async move {
+            let container = self.containers
+                .lock()
+                .map_err(|_| anyhow!("Lock poisoned"))?
+                .remove(id)
+                .ok_or_else(|| anyhow!("Unknown container {}", id))?;
+
+            tokio::fs::remove_dir_all(&container.root)
+                .await
+                .with_context(|| anyhow!("Removing {}", container.root.display()))
+                .map_err(Error::from)
+        }
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rewrite() {
+        let container = LocalContainer {
+            root: PathBuf::from("/tmp/butido-foo"),
+            paths: vec![PathBuf::from("/inputs"), PathBuf::from("/outputs"), PathBuf::from("/script")],
+            env: Vec::new(),
+        };
+
+        assert_eq!(container.rewrite("/outputs"), "/tmp/butido-foo/outputs");
+        assert_eq!(container.rewrite("/inputs/a.tar.gz /inputs/b.tar.gz"), "/tmp/butido-foo/inputs/a.tar.gz /tmp/butido-foo/inputs/b.tar.gz");
+        assert_eq!(container.rewrite("/bin/bash"), "/bin/bash");
+        assert_eq!(container.rewrite("/inputsfoo"), "/inputsfoo");
+        assert_eq!(container.rewrite("1.0"), "1.0");
+    }
+}
@@ -17,5 +17,33 @@ pub use scheduler::*;
 mod configured;
 pub use configured::*;
 
+mod fair_share;
+pub use fair_share::*;
+
+mod artifact_cache;
+pub use artifact_cache::*;
+
+mod backend;
+pub use backend::*;
+
+mod docker;
+pub use docker::*;
+
+mod kubernetes;
+pub use kubernetes::*;
+
+mod local;
+pub use local::*;
+
+mod transfer;
+pub use transfer::*;
+
+mod usage;
+pub use usage::*;
+
+mod registry;
+
+pub mod discovery;
+
 pub mod util;
 
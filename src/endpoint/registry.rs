@@ -0,0 +1,144 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+//! Authentication against the registries images are pulled from
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Error;
+use anyhow::Result;
+use serde::Deserialize;
+use shiplift::RegistryAuth;
+use tokio::io::AsyncWriteExt;
+use tracing::trace;
+
+use crate::config::RegistryConfig;
+use crate::util::docker::ImageName;
+
+/// The registry of images that do not name a registry
+const DEFAULT_REGISTRY: &str = "docker.io";
+
+/// The hostname of the registry `image` is pulled from
+///
+/// Like Docker, the first component of the image name is only a registry if it looks like a
+/// hostname, so "debian:bullseye" and "library/debian" are pulled from Docker Hub.
+pub fn registry_of(image: &ImageName) -> &str {
+    match image.as_ref().split_once('/') {
+        Some((first, _)) if first.contains('.') || first.contains(':') || first == "localhost" => first,
+        _ => DEFAULT_REGISTRY,
+    }
+}
+
+/// Get the credentials for `registry`, running the credential helper if one is configured
+pub async fn registry_auth(registry: &str, config: &RegistryConfig) -> Result<RegistryAuth> {
+    if let Some(token) = config.token() {
+        return Ok(RegistryAuth::token(token))
+    }
+
+    if let Some(helper) = config.credential_helper() {
+        return HelperCredentials::get(helper, registry)
+            .await
+            .map(|creds| creds.into_auth(registry))
+    }
+
+    match (config.username(), config.password()) {
+        (Some(username), Some(password)) => Ok({
+            RegistryAuth::builder()
+                .username(username)
+                .password(password)
+                .server_address(registry)
+                .build()
+        }),
+        _ => Err(anyhow!("No credentials configured for registry {}", registry)),
+    }
+}
+
+/// The output of `docker-credential-<helper> get`
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct HelperCredentials {
+    username: String,
+    secret: String,
+}
+
+impl HelperCredentials {
+    async fn get(helper: &str, registry: &str) -> Result<Self> {
+        let program = format!("docker-credential-{helper}");
+        trace!("Getting credentials for {} from {}", registry, program);
+        let mut child = tokio::process::Command::new(&program)
+            .arg("get")
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            .spawn()
+            .with_context(|| anyhow!("Running {}", program))?;
+
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(registry.as_bytes())
+                .await
+                .with_context(|| anyhow!("Writing registry to {}", program))?;
+        }
+
+        let output = child.wait_with_output()
+            .await
+            .with_context(|| anyhow!("Running {}", program))?;
+
+        if !output.status.success() {
+            return Err(anyhow!(
+                "Getting credentials for {} from {} failed ({}): {}",
+                registry,
+                program,
+                output.status,
+                String::from_utf8_lossy(&output.stdout).trim()
+            ))
+        }
+
+        serde_json::from_slice(&output.stdout)
+            .with_context(|| anyhow!("Parsing output of {}", program))
+            .map_err(Error::from)
+    }
+
+    /// Credential helpers return the username "<token>" for identity tokens
+    fn into_auth(self, registry: &str) -> RegistryAuth {
+        if self.username == "<token>" {
+            RegistryAuth::token(self.secret)
+        } else {
+            RegistryAuth::builder()
+                .username(self.username)
+                .password(self.secret)
+                .server_address(registry)
+                .build()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registry_of() {
+        assert_eq!(registry_of(&ImageName::from("debian:bullseye")), "docker.io");
+        assert_eq!(registry_of(&ImageName::from("library/debian:bullseye")), "docker.io");
+        assert_eq!(registry_of(&ImageName::from("registry.example.com/build/debian:11")), "registry.example.com");
+        assert_eq!(registry_of(&ImageName::from("localhost:5000/debian")), "localhost:5000");
+        assert_eq!(registry_of(&ImageName::from("localhost/debian")), "localhost");
+    }
+
+    #[test]
+    fn test_helper_credentials() {
+        let output = r#"{"ServerURL":"registry.example.com","Username":"<token>","Secret":"abc"}"#;
+        let creds: HelperCredentials = serde_json::from_str(output).unwrap();
+        assert!(matches!(creds.into_auth("registry.example.com"), RegistryAuth::Token { identity_token } if identity_token == "abc"));
+
+        let output = r#"{"ServerURL":"registry.example.com","Username":"builder","Secret":"abc"}"#;
+        let creds: HelperCredentials = serde_json::from_str(output).unwrap();
+        assert!(matches!(creds.into_auth("registry.example.com"), RegistryAuth::Password { username, .. } if username == "builder"));
+    }
+}
@@ -8,8 +8,12 @@
 // SPDX-License-Identifier: EPL-2.0
 //
 
+use std::collections::BTreeMap;
+use std::collections::HashMap;
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
 
 use anyhow::anyhow;
 use anyhow::Context;
@@ -21,26 +25,65 @@ use diesel::r2d2::ConnectionManager;
 use diesel::r2d2::Pool;
 use indicatif::ProgressBar;
 use itertools::Itertools;
+use tracing::info;
 use tracing::trace;
+use tracing::warn;
 use tokio::io::AsyncWriteExt;
 use tokio::sync::RwLock;
 use tokio::sync::mpsc::UnboundedReceiver;
 use uuid::Uuid;
 
+use crate::config::EndpointName;
+use crate::config::StallDetectionConfig;
 use crate::db::models as dbmodels;
 use crate::endpoint::Endpoint;
 use crate::endpoint::EndpointHandle;
 use crate::endpoint::EndpointConfiguration;
+use crate::endpoint::RunningShare;
+use crate::endpoint::SharedEndpoints;
+use crate::endpoint::WaitingShare;
 use crate::filestore::ArtifactPath;
 use crate::filestore::ReleaseStore;
 use crate::filestore::StagingStore;
+use crate::job::InputManifest;
 use crate::job::JobResource;
 use crate::job::RunnableJob;
 use crate::log::LogItem;
+use crate::log::LogLimit;
+use crate::log::LogStream;
+use crate::log::LogStreams;
+use crate::log::TriageRules;
+use crate::util::docker::ContainerHash;
+
+/// The interval in which the utilization of the endpoints is shown
+const MONITOR_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The containers on the endpoints are counted every this many monitor intervals
+const MONITOR_CONTAINER_INTERVALS: usize = 10;
+
+/// The interval in which a job retries to reserve a job slot of an endpoint in the database, if
+/// the free endpoints are busy with the jobs of other butido instances
+const RESERVATION_RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The interval in which a job slot reserved in the database is refreshed while the job runs, well
+/// below the expiry of the reservations
+const RESERVATION_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
 
 pub struct EndpointScheduler {
     log_dir: Option<PathBuf>,
+    log_streams: Option<Arc<LogStreams>>,
+    manifest_store: Option<PathBuf>,
+    triage_rules: Arc<TriageRules>,
+    log_limit: Arc<LogLimit>,
+    stall_detection: StallDetectionConfig,
     endpoints: Vec<Arc<Endpoint>>,
+    waiting_jobs: WaitingJobs,
+
+    /// The endpoints are shared with the other submits that run at the same time
+    shared_endpoints: Option<Arc<SharedEndpoints>>,
+
+    /// The jobs on the endpoints are accounted in the database, shared with other butido instances
+    capacity_accounting: bool,
 
     staging_store: Arc<RwLock<StagingStore>>,
     release_stores: Vec<Arc<ReleaseStore>>,
@@ -49,6 +92,7 @@ pub struct EndpointScheduler {
 }
 
 impl EndpointScheduler {
+    #[allow(clippy::too_many_arguments)]
     pub async fn setup(
         endpoints: Vec<EndpointConfiguration>,
         staging_store: Arc<RwLock<StagingStore>>,
@@ -56,12 +100,30 @@ impl EndpointScheduler {
         db: Pool<ConnectionManager<PgConnection>>,
         submit: crate::db::models::Submit,
         log_dir: Option<PathBuf>,
+        log_streams: Option<Arc<LogStreams>>,
+        manifest_store: Option<PathBuf>,
+        triage_rules: Arc<TriageRules>,
+        log_limit: Arc<LogLimit>,
+        stall_detection: StallDetectionConfig,
+        shared_endpoints: Option<Arc<SharedEndpoints>>,
+        capacity_accounting: bool,
     ) -> Result<Self> {
-        let endpoints = crate::endpoint::util::setup_endpoints(endpoints).await?;
+        let endpoints = match shared_endpoints.as_ref() {
+            Some(shared) => shared.endpoints().clone(),
+            None => crate::endpoint::util::setup_endpoints(endpoints).await?,
+        };
 
         Ok(EndpointScheduler {
             log_dir,
+            log_streams,
+            manifest_store,
+            triage_rules,
+            log_limit,
+            stall_detection,
             endpoints,
+            waiting_jobs: WaitingJobs::default(),
+            shared_endpoints,
+            capacity_accounting,
             staging_store,
             release_stores,
             db,
@@ -75,12 +137,21 @@ impl EndpointScheduler {
     ///
     /// This function blocks as long as there is no free endpoint available!
     pub async fn schedule_job(&self, job: RunnableJob, bar: indicatif::ProgressBar) -> Result<JobHandle> {
-        let endpoint = self.select_free_endpoint().await?;
+        let SelectedEndpoint { endpoint, running_share, reservation } = self
+            .select_free_endpoint(*job.package().priority(), job.uuid())
+            .await?;
 
         Ok(JobHandle {
             log_dir: self.log_dir.clone(),
+            log_streams: self.log_streams.clone(),
+            manifest_store: self.manifest_store.clone(),
+            triage_rules: self.triage_rules.clone(),
+            log_limit: self.log_limit.clone(),
+            stall_detection: self.stall_detection.clone(),
             bar,
             endpoint,
+            running_share,
+            reservation,
             job,
             staging_store: self.staging_store.clone(),
             release_stores: self.release_stores.clone(),
@@ -89,9 +160,91 @@ impl EndpointScheduler {
         })
     }
 
-    async fn select_free_endpoint(&self) -> Result<EndpointHandle> {
+    pub fn n_endpoints(&self) -> usize {
+        self.endpoints.len()
+    }
+
+    /// Clean up the `containers` (by endpoint) of the jobs of an interrupted submit
+    ///
+    /// This is best effort: the containers might not exist anymore, so failures are only reported.
+    pub async fn clean_up_containers(&self, containers: &[(EndpointName, String)]) {
+        for (endpoint_name, container_id) in containers {
+            let Some(endpoint) = self.endpoints.iter().find(|ep| ep.name() == endpoint_name) else {
+                warn!("Cannot clean up container {} of interrupted job, endpoint {} is not configured", container_id, endpoint_name);
+                continue
+            };
+
+            match endpoint.clean_up_container(container_id).await {
+                Ok(fate) => info!("Container {} of interrupted job on {}: stopped and {}", container_id, endpoint_name, fate.as_str()),
+                Err(e) => warn!("Cleaning up container {} of interrupted job on {} failed: {:?}", container_id, endpoint_name, e),
+            }
+        }
+    }
+
+    /// Show the utilization of the endpoints in `bars`, one bar per endpoint
+    ///
+    /// The bars show the running jobs of the endpoints, the containers running on the endpoints
+    /// (as reported by docker), the jobs that wait for a free endpoint and the jobs that failed.
+    /// This never returns, so it has to be cancelled when the jobs finished.
+    pub async fn monitor(&self, bars: &[ProgressBar]) {
+        let mut containers = vec![None; self.endpoints.len()];
+        for i in 0.. {
+            if i % MONITOR_CONTAINER_INTERVALS == 0 {
+                containers = futures::future::join_all({
+                    self.endpoints.iter().map(|ep| async move {
+                        ep.running_containers()
+                            .await
+                            .map_err(|e| trace!("Counting containers on {} failed: {:?}", ep.name(), e))
+                            .ok()
+                    })
+                })
+                .await;
+            }
+
+            let waiting = self.waiting_jobs.len();
+            for ((ep, bar), containers) in self.endpoints.iter().zip(bars.iter()).zip(containers.iter()) {
+                bar.set_length(ep.num_max_jobs() as u64);
+                bar.set_position(ep.running_jobs() as u64);
+                bar.set_message(endpoint_status_line(ep, *containers, waiting));
+            }
+
+            tokio::time::sleep(MONITOR_INTERVAL).await;
+        }
+    }
+
+    /// Finish the bars of `monitor()`
+    pub fn finish_monitor(&self, bars: &[ProgressBar]) {
+        for (ep, bar) in self.endpoints.iter().zip(bars.iter()) {
+            bar.finish_with_message(format!("[{}] {} jobs failed", ep.name(), ep.failed_jobs()));
+        }
+    }
+
+    /// Select a free endpoint for a job with the passed priority
+    ///
+    /// If there are jobs with a higher priority waiting for a free endpoint, these jobs are
+    /// served first. If the endpoints are shared with other submits, the job also waits until
+    /// its submit is next (see `FairShare`), the job counts in the share of the submit as long as
+    /// the returned `RunningShare` exists. If the jobs are accounted in the database, a job slot of
+    /// the endpoint is reserved for the job `job_uuid`.
+    async fn select_free_endpoint(&self, priority: i64, job_uuid: &Uuid) -> Result<SelectedEndpoint> {
+        let waiting = self.waiting_jobs.enqueue(priority);
+        let waiting_share = self.shared_endpoints
+            .as_ref()
+            .map(|shared| shared.fair_share().enqueue(self.submit.uuid));
         loop {
-            let ep = self
+            if !waiting.is_next() {
+                trace!("Jobs with higher priority than {} are waiting, retry...", priority);
+                tokio::task::yield_now().await;
+                continue;
+            }
+
+            if !waiting_share.as_ref().map(WaitingShare::is_next).unwrap_or(true) {
+                trace!("Jobs of other submits are scheduled first, retry...");
+                tokio::task::yield_now().await;
+                continue;
+            }
+
+            let eps = self
                 .endpoints
                 .iter()
                 .filter(|ep| { // filter out all running containers where the number of max jobs is reached
@@ -102,21 +255,208 @@ impl EndpointScheduler {
                 .sorted_by(|ep1, ep2| {
                     ep1.utilization().partial_cmp(&ep2.utilization()).unwrap_or(std::cmp::Ordering::Equal)
                 })
-                .next();
+                .collect::<Vec<_>>();
+
+            let mut selected = None;
+            for endpoint in eps.iter() {
+                // With accounting in the database, the endpoint might be busy with the jobs of other
+                // butido instances
+                let reservation = if self.capacity_accounting {
+                    match self.reserve(endpoint, job_uuid)? {
+                        Some(reservation) => Some(reservation),
+                        None => continue,
+                    }
+                } else {
+                    None
+                };
+                selected = Some((endpoint, reservation));
+                break;
+            }
 
-            if let Some(endpoint) = ep {
-                return Ok(EndpointHandle::new(endpoint.clone()));
+            if let Some((endpoint, reservation)) = selected {
+                let running_share = waiting_share
+                    .zip(self.shared_endpoints.clone())
+                    .map(|(waiting_share, shared)| waiting_share.start(shared));
+                return Ok(SelectedEndpoint {
+                    endpoint: EndpointHandle::new((*endpoint).clone()),
+                    running_share,
+                    reservation,
+                });
+            } else if !eps.is_empty() {
+                trace!("Free endpoints are busy with jobs of other instances, retry...");
+                tokio::time::sleep(RESERVATION_RETRY_INTERVAL).await
             } else {
                 trace!("No free endpoint found, retry...");
                 tokio::task::yield_now().await
             }
         }
     }
+
+    /// Reserve a job slot of `endpoint` for the job `job_uuid` in the database
+    ///
+    /// Returns `None` if the jobs of all butido instances on the endpoint already reached its
+    /// maximum number of jobs.
+    fn reserve(&self, endpoint: &Endpoint, job_uuid: &Uuid) -> Result<Option<CapacityReservation>> {
+        let mut conn = self.db.get()?;
+        let db_endpoint = dbmodels::Endpoint::create_or_fetch(&mut conn, endpoint.name())?;
+        let reservation = dbmodels::EndpointReservation::try_create(&mut conn, &db_endpoint, endpoint.num_max_jobs(), &self.submit, job_uuid)?;
+        Ok(reservation.map(|reservation| CapacityReservation::new(self.db.clone(), reservation)))
+    }
+}
+
+/// A free endpoint that was selected for a job
+struct SelectedEndpoint {
+    endpoint: EndpointHandle,
+    running_share: Option<RunningShare>,
+    reservation: Option<CapacityReservation>,
+}
+
+/// A job slot of an endpoint that is reserved in the database, the reservation is released when
+/// this object is dropped
+///
+/// The reservation is refreshed in the background while this object lives, so it only expires if
+/// this instance gets killed.
+struct CapacityReservation {
+    db: Pool<ConnectionManager<PgConnection>>,
+    reservation: Arc<dbmodels::EndpointReservation>,
+    refresh: tokio::task::JoinHandle<()>,
+}
+
+impl CapacityReservation {
+    fn new(db: Pool<ConnectionManager<PgConnection>>, reservation: dbmodels::EndpointReservation) -> Self {
+        let reservation = Arc::new(reservation);
+        let refresh = tokio::spawn({
+            let db = db.clone();
+            let reservation = reservation.clone();
+            async move {
+                let mut interval = tokio::time::interval(RESERVATION_REFRESH_INTERVAL);
+                interval.tick().await; // the first tick completes immediately
+                loop {
+                    interval.tick().await;
+                    let refreshed = db
+                        .get()
+                        .map_err(Error::from)
+                        .and_then(|mut conn| reservation.refresh(&mut conn));
+                    if let Err(e) = refreshed {
+                        warn!("Refreshing job slot of job {} failed: {:?}", reservation.job_uuid, e);
+                    }
+                }
+            }
+        });
+
+        CapacityReservation { db, reservation, refresh }
+    }
+}
+
+impl Drop for CapacityReservation {
+    fn drop(&mut self) {
+        self.refresh.abort();
+        let released = self.db
+            .get()
+            .map_err(Error::from)
+            .and_then(|mut conn| self.reservation.delete(&mut conn));
+        if let Err(e) = released {
+            warn!("Releasing job slot of job {} failed: {:?}", self.reservation.job_uuid, e);
+        }
+    }
+}
+
+/// The priorities of the jobs that are waiting for a free endpoint
+#[derive(Debug, Default)]
+struct WaitingJobs(Mutex<BTreeMap<i64, usize>>);
+
+impl WaitingJobs {
+    /// Register a waiting job, the job is removed when the returned object is dropped
+    fn enqueue(&self, priority: i64) -> WaitingJob<'_> {
+        *self.0.lock().unwrap().entry(priority).or_insert(0) += 1;
+        WaitingJob { queue: self, priority }
+    }
+
+    fn highest_priority(&self) -> Option<i64> {
+        self.0.lock().unwrap().keys().next_back().copied()
+    }
+
+    /// The number of waiting jobs
+    fn len(&self) -> usize {
+        self.0.lock().unwrap().values().sum()
+    }
+}
+
+fn endpoint_status_line(ep: &Endpoint, containers: Option<usize>, waiting: usize) -> String {
+    let containers = containers.map(|n| n.to_string()).unwrap_or_else(|| String::from("?"));
+    let failures = match ep.last_failure() {
+        Some(last) => format!("{} failed (last: {})", ep.failed_jobs(), last),
+        None => format!("{} failed", ep.failed_jobs()),
+    };
+
+    format!(
+        "[{}] {}/{} jobs, {} containers running, {}, {} jobs waiting",
+        ep.name(),
+        ep.running_jobs(),
+        ep.num_max_jobs(),
+        containers,
+        failures,
+        waiting
+    )
+}
+
+struct WaitingJob<'a> {
+    queue: &'a WaitingJobs,
+    priority: i64,
+}
+
+impl<'a> WaitingJob<'a> {
+    /// Whether no job with a higher priority is waiting
+    fn is_next(&self) -> bool {
+        self.queue.highest_priority().map(|highest| highest <= self.priority).unwrap_or(true)
+    }
+}
+
+impl<'a> Drop for WaitingJob<'a> {
+    fn drop(&mut self) {
+        let mut waiting = self.queue.0.lock().unwrap();
+        if let Some(n) = waiting.get_mut(&self.priority) {
+            *n -= 1;
+            if *n == 0 {
+                waiting.remove(&self.priority);
+            }
+        }
+    }
+}
+
+/// The failure category of jobs that cannot run because of a misconfiguration, e.g. an image that
+/// does not match the architecture of the endpoint
+const FAILURE_CATEGORY_MISCONFIGURED: &str = "misconfigured";
+
+/// The failure category of jobs that were killed because they stalled
+const FAILURE_CATEGORY_STALLED: &str = "stalled";
+
+/// The error of a job that stalled and was killed to be run again
+#[derive(Debug)]
+struct JobStalled;
+
+impl std::fmt::Display for JobStalled {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Job stalled and was killed")
+    }
 }
 
+impl std::error::Error for JobStalled {}
+
 pub struct JobHandle {
     log_dir: Option<PathBuf>,
+    log_streams: Option<Arc<LogStreams>>,
+    manifest_store: Option<PathBuf>,
+    triage_rules: Arc<TriageRules>,
+    log_limit: Arc<LogLimit>,
+    stall_detection: StallDetectionConfig,
     endpoint: EndpointHandle,
+
+    /// The job counts in the share of its submit of the shared endpoints while it runs
+    running_share: Option<RunningShare>,
+
+    /// The job slot of the endpoint reserved in the database while the job runs
+    reservation: Option<CapacityReservation>,
     job: RunnableJob,
     bar: ProgressBar,
     db: Pool<ConnectionManager<PgConnection>>,
@@ -133,6 +473,38 @@ impl std::fmt::Debug for JobHandle {
 
 impl JobHandle {
     pub async fn run(self) -> Result<Result<Vec<ArtifactPath>>> {
+        let endpoint = self.endpoint.shared();
+        let job = format!("{} {}", self.job.package().name(), self.job.package().version());
+
+        // Stalled jobs are killed and run again, as long as there are retries left
+        let mut retries = self.stall_detection.retries();
+        let mut attempt = 0;
+        let res = loop {
+            match self.run_job(attempt, retries > 0).await {
+                Ok(Err(e)) if e.is::<JobStalled>() => {
+                    retries -= 1;
+                    attempt += 1;
+                    warn!("Job {} stalled and was killed, running it again ({} retries left)", self.job.uuid(), retries);
+                    self.bar.reset();
+                },
+                res => break res,
+            }
+        };
+
+        if !matches!(res, Ok(Ok(_))) {
+            endpoint.record_failure(job);
+        }
+        drop(self.running_share);
+        drop(self.reservation);
+        res
+    }
+
+    /// Run the job
+    ///
+    /// `attempt` counts the runs of the job, starting at 0. If the job stalls and `retry_stalled`
+    /// is set, the job is not recorded and `JobStalled` is returned, so that the job can be run
+    /// again.
+    async fn run_job(&self, attempt: usize, retry_stalled: bool) -> Result<Result<Vec<ArtifactPath>>> {
         let (log_sender, log_receiver) = tokio::sync::mpsc::unbounded_channel::<LogItem>();
         let endpoint_uri = self.endpoint.uri().clone();
         let endpoint_name = self.endpoint.name().clone();
@@ -141,12 +513,114 @@ impl JobHandle {
         let image = dbmodels::Image::create_or_fetch(&mut self.db.get().unwrap(), self.job.image())?;
         let envs = self.create_env_in_db()?;
         let job_id = *self.job.uuid();
+        let started_at = chrono::offset::Local::now().naive_local();
         trace!("Running on Job {} on Endpoint {}", job_id, self.endpoint.name());
+
+        // Dependency artifacts that were compressed when they were written to the staging store
+        // are decompressed before they are copied to the container
+        //
+        // The staging store knows the compression of the artifacts it wrote in this run, the
+        // compression of other artifacts is looked up for the artifacts of this submit or the
+        // released artifacts.
+        let compressed_artifacts = {
+            let staging_store = self.staging_store.read().await;
+            let mut compressed_artifacts = HashMap::new();
+            for art in self.job.resources().iter().filter_map(JobResource::artifact) {
+                let compression = if staging_store.get(art).is_some() {
+                    match staging_store.compression_of(art) {
+                        Some(compression) => Some(compression),
+                        None => dbmodels::Artifact::compression_in_submit(&mut self.db.get().unwrap(), art, &self.submit)?,
+                    }
+                } else {
+                    dbmodels::Artifact::compression_of_release(&mut self.db.get().unwrap(), art)?
+                };
+
+                if let Some(compression) = compression {
+                    compressed_artifacts.insert(art.clone(), compression);
+                }
+            }
+            compressed_artifacts
+        };
+
+        // The packages the dependency artifacts were built for are listed in the manifest of the
+        // inputs in the container
+        let artifact_packages = self.job
+            .resources()
+            .iter()
+            .filter_map(JobResource::artifact)
+            .map(|art| {
+                dbmodels::Artifact::package_of(&mut self.db.get().unwrap(), art)
+                    .map(|package| package.map(|p| (art.clone(), p)))
+            })
+            .filter_map(Result::transpose)
+            .collect::<Result<HashMap<_, _>>>()?;
+
         let prepared_container = self.endpoint
-            .prepare_container(&self.job, self.staging_store.clone(), self.release_stores.clone())
+            .prepare_container(&self.job, self.staging_store.clone(), self.release_stores.clone(), &compressed_artifacts, &artifact_packages)
             .await?;
-        let container_id = prepared_container.create_info().id.clone();
-        let running_container = prepared_container
+        let container_id = prepared_container.container_id().clone();
+        dbmodels::JobState::set_running(&mut self.db.get().unwrap(), &self.submit, &job_id, &endpoint, &container_id)?;
+
+        let image_digest = self.endpoint.image_digest(self.job.image()).await?;
+        let manifest = InputManifest::for_job(
+            &self.job,
+            prepared_container.artifact_hashes(),
+            prepared_container.patch_hashes(),
+            image_digest,
+        );
+        if let Some(manifest_store) = self.manifest_store.as_ref() {
+            let path = manifest.write_to_store(manifest_store).await?;
+            trace!("Input manifest for job {} written to {}", job_id, path.display());
+        }
+
+        // A container with an image for another architecture would fail with an obscure error
+        // (e.g. "exec format error"), so we detect this case and report it as misconfiguration.
+        if let Some(mismatch) = self.endpoint.check_image_architecture(self.job.image()).await? {
+            let job = dbmodels::Job::create(
+                &mut self.db.get().unwrap(),
+                &job_id,
+                &self.submit,
+                &endpoint,
+                &package,
+                &image,
+                &ContainerHash::from(container_id.clone()),
+                self.job.script(),
+                "",
+                None,
+                self.log_limit.encoding(),
+            )
+            .context("Recording misconfigured job in database")?;
+            job.set_input_manifest(&mut self.db.get().unwrap(), &manifest)?;
+            job.set_times(&mut self.db.get().unwrap(), &started_at, &chrono::offset::Local::now().naive_local())?;
+            job.set_phases(&mut self.db.get().unwrap(), self.job.phases())?;
+            job.set_failure_category(&mut self.db.get().unwrap(), FAILURE_CATEGORY_MISCONFIGURED)?;
+            dbmodels::JobState::set_finished(&mut self.db.get().unwrap(), &self.submit, &job_id)?;
+
+            // The container never ran, so there is nothing to keep for debugging
+            if let Err(e) = prepared_container.remove().await {
+                warn!("Removing container {} of misconfigured job {} failed: {:?}", container_id, job_id, e);
+            }
+
+            return Ok(Err(anyhow!(
+                "Image {image} is built for architecture '{image_arch}', but endpoint '{endpoint}' runs on '{endpoint_arch}'",
+                image = self.job.image().as_ref(),
+                image_arch = mismatch.image_architecture(),
+                endpoint = endpoint_name,
+                endpoint_arch = mismatch.endpoint_architecture(),
+            ))
+            .with_context(|| anyhow!("Failure category '{}'", FAILURE_CATEGORY_MISCONFIGURED))
+            .with_context(|| {
+                Self::create_job_run_error(
+                    &job_id,
+                    &package.name,
+                    &package.version,
+                    &endpoint_uri,
+                    &container_id,
+                )
+            }));
+        }
+
+        let started_container = prepared_container
             .start()
             .await
             .with_context(|| {
@@ -157,24 +631,38 @@ impl JobHandle {
                     &endpoint_uri,
                     &container_id,
                 )
-            })?
-            .execute_script(log_sender);
+            })?;
+        let metadata = started_container
+            .capture(self.job.package().capture())
+            .await
+            .with_context(|| anyhow!("Capturing metadata of job {}", job_id))?;
+        let running_container = started_container.execute_script(log_sender);
 
+        let phases = self.job.phases().clone();
         let logres = LogReceiver {
             endpoint_name: endpoint_name.as_ref(),
             container_id_chrs: container_id.chars().take(7).collect(),
             package_name: &package.name,
             package_version: &package.version,
             log_dir: self.log_dir.as_ref(),
-            job: self.job,
+            log_stream: self.log_streams.as_ref().map(|streams| streams.open(self.submit.uuid, job_id)),
+            job: &self.job,
             log_receiver,
             bar: self.bar.clone(),
+            stall_watch: self.stall_detection.timeout().map(|timeout| StallWatch {
+                timeout,
+                snapshot_command: self.stall_detection.snapshot_command().as_deref(),
+                kill: self.stall_detection.retries() > 0,
+                endpoint: &self.endpoint,
+                container_id: &container_id,
+            }),
+            attempt,
         }
         .join();
-        drop(self.bar);
 
         let (run_container, logres) = tokio::join!(running_container, logres);
-        let log = logres.with_context(|| anyhow!("Collecting logs for job on '{}'", endpoint_name))?;
+        let (log, killed_stalled) = logres.with_context(|| anyhow!("Collecting logs for job on '{}'", endpoint_name))?;
+        let finished_at = chrono::offset::Local::now().naive_local();
         let run_container = run_container
             .with_context(|| anyhow!("Running container {} failed", container_id))
             .with_context(|| {
@@ -187,6 +675,13 @@ impl JobHandle {
                 )
             })?;
 
+        if killed_stalled && retry_stalled {
+            let fate = run_container.discard().await?;
+            trace!("Container {} of stalled job {} discarded: {}", container_id, job_id, fate.as_str());
+            return Ok(Err(Error::from(JobStalled)));
+        }
+
+        let limited_log = self.log_limit.apply(&job_id, &log)?;
         let job = dbmodels::Job::create(
             &mut self.db.get().unwrap(),
             &job_id,
@@ -196,18 +691,31 @@ impl JobHandle {
             &image,
             &run_container.container_hash(),
             run_container.script(),
-            &log,
+            &limited_log.text,
+            run_container.exit_code(),
+            self.log_limit.encoding(),
         )
         .context("Recording job that is ready in database")?;
 
         trace!("DB: Job entry for job {} created: {}", job.uuid, job.id);
+        if let Some(path) = limited_log.file.as_ref() {
+            job.set_log_file(&mut self.db.get().unwrap(), path)?;
+        }
+        job.set_input_manifest(&mut self.db.get().unwrap(), &manifest)?;
+        job.set_times(&mut self.db.get().unwrap(), &started_at, &finished_at)?;
+        job.set_phases(&mut self.db.get().unwrap(), &phases)?;
+        job.set_oom_killed(&mut self.db.get().unwrap(), run_container.oom_killed())?;
+        if let Some(usage) = run_container.usage() {
+            job.set_resource_usage(&mut self.db.get().unwrap(), &usage)?;
+        }
+        dbmodels::JobMetadata::create_all(&mut self.db.get().unwrap(), &job, &metadata)?;
         for env in envs {
             dbmodels::JobEnv::create(&mut self.db.get().unwrap(), &job, &env)
                 .with_context(|| format!("Creating Environment Variable mapping for Job: {}", job.uuid))?;
         }
 
         let res: crate::endpoint::FinalizedContainer = run_container
-            .finalize(self.staging_store.clone())
+            .finalize(self.staging_store.clone(), self.job.image(), &self.bar)
             .await
             .context("Finalizing container")
             .with_context(|| {
@@ -221,6 +729,9 @@ impl JobHandle {
             })?;
 
         trace!("Found result for job {}: {:?}", job_id, res);
+        self.bar.finish();
+        job.set_container_fate(&mut self.db.get().unwrap(), res.container_fate())?;
+        dbmodels::JobState::set_finished(&mut self.db.get().unwrap(), &self.submit, &job_id)?;
         let (paths, res) = res.unpack();
         let res = res
             .with_context(|| anyhow!("Error during running job on '{}'", endpoint_name))
@@ -237,6 +748,25 @@ impl JobHandle {
 
         if res.is_err() {
             trace!("Error was returned from script");
+            if killed_stalled {
+                job.set_failure_category(&mut self.db.get().unwrap(), FAILURE_CATEGORY_STALLED)?;
+                return Ok(res
+                    .with_context(|| anyhow!("Failure category '{}'", FAILURE_CATEGORY_STALLED))
+                    .map(|_| vec![]));
+            }
+
+            let res = match self.triage_rules.categorize(&log) {
+                Some(rule) => {
+                    trace!("Job {} triaged as '{}'", job.uuid, rule.category());
+                    job.set_failure_category(&mut self.db.get().unwrap(), rule.category())?;
+                    match rule.hint() {
+                        Some(hint) => res.with_context(|| anyhow!("Failure category '{}': {}", rule.category(), hint)),
+                        None => res.with_context(|| anyhow!("Failure category '{}'", rule.category())),
+                    }
+                }
+                None => res,
+            };
+
             return Ok({
                 res.map(|_| vec![]) // to have the proper type, will never be executed
              })
@@ -247,7 +777,26 @@ impl JobHandle {
         let staging_read = self.staging_store.read().await;
         for p in paths.iter() {
             trace!("DB: Creating artifact entry for path: {}", p.display());
-            let _ = dbmodels::Artifact::create(&mut self.db.get().unwrap(), p, &job)?;
+            let sha256 = staging_read
+                .root_path()
+                .join(p)
+                .and_then(|full_path| {
+                    full_path
+                        .map(|full_path| crate::filestore::sha256_of_file(&full_path.joined()))
+                        .transpose()
+                })
+                .map_err(|e| warn!("Hashing artifact {} failed: {:?}", p.display(), e))
+                .ok()
+                .flatten();
+            let _ = dbmodels::Artifact::create(&mut self.db.get().unwrap(), p, staging_read.compression_of(p), staging_read.kind_of(p), sha256.as_deref(), &job)?;
+
+            // Only the installable packages are handed to the dependent jobs
+            let kind = staging_read.kind_of(p);
+            if kind != crate::filestore::PACKAGE_ARTIFACT_KIND {
+                trace!("Not passing {} artifact on: {}", kind, p.display());
+                continue
+            }
+
             r.push({
                 staging_read
                     .get(p)
@@ -316,21 +865,64 @@ impl JobHandle {
     }
 }
 
+/// Watches a running job for stalls, i.e. no log output for `timeout`
+struct StallWatch<'a> {
+    timeout: Duration,
+    snapshot_command: Option<&'a str>,
+    kill: bool,
+    endpoint: &'a Endpoint,
+    container_id: &'a str,
+}
+
+impl<'a> StallWatch<'a> {
+    /// Handle the stall of the job
+    ///
+    /// Returns the output of the snapshot command for the log and whether the job was killed.
+    async fn on_stall(&self) -> (Vec<LogItem>, bool) {
+        let mut items = vec![];
+        if let Some(command) = self.snapshot_command {
+            match self.endpoint.run_in_container(self.container_id, command).await {
+                Ok((output, _)) => {
+                    items.push(LogItem::Line(format!("### snapshot of stalled job: {command}").into_bytes()));
+                    items.extend(output.lines().map(|line| LogItem::Line(line.as_bytes().to_vec())));
+                    items.push(LogItem::Line(String::from("### / snapshot of stalled job").into_bytes()));
+                },
+                Err(e) => warn!("Taking snapshot of stalled container {} failed: {:?}", self.container_id, e),
+            }
+        }
+
+        let killed = self.kill && self.endpoint
+            .stop_container(self.container_id)
+            .await
+            .map_err(|e| warn!("Killing stalled container {} failed: {:?}", self.container_id, e))
+            .is_ok();
+        (items, killed)
+    }
+}
+
 struct LogReceiver<'a> {
     endpoint_name: &'a str,
     container_id_chrs: String,
     package_name: &'a str,
     package_version: &'a str,
     log_dir: Option<&'a PathBuf>,
-    job: RunnableJob,
+    log_stream: Option<LogStream>,
+    job: &'a RunnableJob,
     log_receiver: UnboundedReceiver<LogItem>,
     bar: ProgressBar,
+    stall_watch: Option<StallWatch<'a>>,
+
+    /// The number of the attempt to run the job, starting at 0
+    attempt: usize,
 }
 
 impl<'a> LogReceiver<'a> {
-    async fn join(mut self) -> Result<String> {
+    /// Receive the log of the job, returns the log and whether the job was killed because it
+    /// stalled
+    async fn join(mut self) -> Result<(String, bool)> {
         let mut success = None;
         let mut accu = vec![];
+        let mut current_phase: Option<String> = None;
 
         // Reserve a reasonable amount of elements.
         accu.reserve(4096);
@@ -349,18 +941,44 @@ impl<'a> LogReceiver<'a> {
         // progress bar secondly.
         let timeout_duration = std::time::Duration::from_millis(250);
 
+        let mut last_output = std::time::Instant::now();
+        let mut stalled = false;
+        let mut killed = false;
+        let mut pending = std::collections::VecDeque::new();
+
         loop {
             // Timeout for receiving from the log receiver channel
             // This way we can update (`tick()`) the progress bar and show the user that things are
             // happening, even if there was no log output for several seconds.
-            let logitem = match tokio::time::timeout(timeout_duration, self.log_receiver.recv()).await {
-                Err(_ /* elapsed */) => {
-                    self.bar.tick(); // just ping the progressbar here
-                    continue
-                },
+            let logitem = if let Some(item) = pending.pop_front() {
+                item
+            } else {
+                match tokio::time::timeout(timeout_duration, self.log_receiver.recv()).await {
+                    Err(_ /* elapsed */) => {
+                        self.bar.tick(); // just ping the progressbar here
 
-                Ok(None) => break, // if the log is empty, we're done
-                Ok(Some(logitem)) => logitem,
+                        if let Some(watch) = self.stall_watch.as_ref().filter(|w| !stalled && last_output.elapsed() >= w.timeout) {
+                            warn!("Job {} has not printed anything for {}", self.job.uuid(), humantime::format_duration(watch.timeout));
+                            self.bar.set_message(format!(
+                                "[{}/{} {} {} {}]: Stalled, no output for {}",
+                                self.endpoint_name, self.container_id_chrs, self.job.uuid(), self.package_name, self.package_version,
+                                humantime::format_duration(watch.timeout)
+                            ));
+                            stalled = true;
+                            let (snapshot, was_killed) = watch.on_stall().await;
+                            pending.extend(snapshot);
+                            killed |= was_killed;
+                        }
+                        continue
+                    },
+
+                    Ok(None) => break, // if the log is empty, we're done
+                    Ok(Some(logitem)) => {
+                        last_output = std::time::Instant::now();
+                        stalled = false;
+                        logitem
+                    },
+                }
             };
 
             if let Some(lf) = logfile.as_mut() {
@@ -369,8 +987,12 @@ impl<'a> LogReceiver<'a> {
                 lf.write_all(b"\n").await?;
             }
 
+            if let Some(stream) = self.log_stream.as_ref() {
+                stream.send(&logitem);
+            }
+
             match logitem {
-                LogItem::Line(_) => {
+                LogItem::Line(_) | LogItem::Heartbeat => {
                     // ignore
                 }
                 LogItem::Progress(u) => {
@@ -379,9 +1001,19 @@ impl<'a> LogReceiver<'a> {
                 }
                 LogItem::CurrentPhase(ref phasename) => {
                     trace!("Setting bar phase to {}", phasename);
+                    let phase = crate::log::phase_path(phasename).join(" > ");
                     self.bar.set_message(format!(
                         "[{}/{} {} {} {}]: Phase: {}",
-                        self.endpoint_name, self.container_id_chrs, self.job.uuid(), self.package_name, self.package_version, phasename
+                        self.endpoint_name, self.container_id_chrs, self.job.uuid(), self.package_name, self.package_version, phase
+                    ));
+                    current_phase = Some(phase);
+                }
+                LogItem::PhaseProgress(u) => {
+                    trace!("Setting bar phase progress to {}", u);
+                    self.bar.set_message(format!(
+                        "[{}/{} {} {} {}]: Phase: {} ({}%)",
+                        self.endpoint_name, self.container_id_chrs, self.job.uuid(), self.package_name, self.package_version,
+                        current_phase.as_deref().unwrap_or("unknown"), u
                     ));
                 }
                 LogItem::State(Ok(())) => {
@@ -419,26 +1051,28 @@ impl<'a> LogReceiver<'a> {
                 self.endpoint_name, self.container_id_chrs, self.job.uuid(), self.package_name, self.package_version
             ),
         };
-        self.bar.finish_with_message(finish_msg);
+        // The bar is finished by the job handle, after the outputs were fetched
+        self.bar.set_message(finish_msg);
 
         if let Some(mut lf) = logfile {
             lf.flush().await?;
         }
 
-        Ok({
-            accu.iter()
-                .map(crate::log::LogItem::raw)
-                .collect::<Result<Vec<String>>>()?
-                .join("\n")
-        })
+        let log = accu.iter()
+            .map(crate::log::LogItem::raw)
+            .collect::<Result<Vec<String>>>()?
+            .join("\n");
+        Ok((log, killed))
     }
 
     async fn get_logfile(&self) -> Option<Result<tokio::io::BufWriter<tokio::fs::File>>> {
         if let Some(log_dir) = self.log_dir.as_ref() {
             Some({
+                // The logs of the attempts of a stalled job that was run again are kept
+                let attempt = if self.attempt > 0 { format!(".{}", self.attempt) } else { String::new() };
                 let path = log_dir.join(format!(
-                    "{}-{}-{}-{}.log",
-                    self.package_name, self.package_version, self.job.image(), self.job.uuid()
+                    "{}-{}-{}-{}{}.log",
+                    self.package_name, self.package_version, self.job.image(), self.job.uuid(), attempt
                 ));
                 tokio::fs::OpenOptions::new()
                     .create(true)
@@ -455,3 +1089,31 @@ impl<'a> LogReceiver<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_waiting_jobs_prefer_higher_priority() {
+        let queue = WaitingJobs::default();
+        let low = queue.enqueue(0);
+        assert!(low.is_next());
+
+        {
+            let high = queue.enqueue(10);
+            let other_high = queue.enqueue(10);
+            assert_eq!(queue.len(), 3);
+            assert!(!low.is_next());
+            assert!(high.is_next());
+            assert!(other_high.is_next());
+            drop(high);
+            assert!(!low.is_next());
+        }
+
+        assert!(low.is_next());
+        drop(low);
+        assert_eq!(queue.highest_priority(), None);
+        assert_eq!(queue.len(), 0);
+    }
+}
@@ -0,0 +1,146 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Transfers of outputs from the containers of an endpoint to the host
+
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+use anyhow::anyhow;
+use anyhow::Result;
+use futures::stream::BoxStream;
+use futures::StreamExt;
+
+/// Limits the rate of the transfers from an endpoint
+///
+/// The limit is shared by all transfers from the endpoint, so concurrent transfers share the
+/// bandwidth.
+#[derive(Debug)]
+pub struct RateLimiter {
+    bytes_per_sec: u64,
+
+    /// The time until which the bandwidth is used by the bytes that were already transferred
+    busy_until: Mutex<Option<Instant>>,
+}
+
+impl RateLimiter {
+    pub fn new(bytes_per_sec: u64) -> Self {
+        RateLimiter {
+            bytes_per_sec: bytes_per_sec.max(1),
+            busy_until: Mutex::new(None),
+        }
+    }
+
+    /// Account for `n` transferred bytes at `now`, returns when the transfer may continue
+    fn reserve(&self, n: usize, now: Instant) -> Result<Instant> {
+        let mut busy_until = self.busy_until.lock().map_err(|_| anyhow!("Lock poisoned"))?;
+        let start = busy_until.filter(|until| *until > now).unwrap_or(now);
+        let until = start + Duration::from_secs_f64(n as f64 / self.bytes_per_sec as f64);
+        *busy_until = Some(until);
+        Ok(until)
+    }
+
+    /// Wait until the transfer may continue after `n` transferred bytes
+    pub async fn wait(&self, n: usize) -> Result<()> {
+        let until = self.reserve(n, Instant::now())?;
+        tokio::time::sleep_until(until.into()).await;
+        Ok(())
+    }
+}
+
+/// The progress of a transfer
+#[derive(Clone, Copy, Debug)]
+pub struct TransferProgress {
+    started: Instant,
+    bytes: u64,
+}
+
+impl TransferProgress {
+    fn new() -> Self {
+        TransferProgress {
+            started: Instant::now(),
+            bytes: 0,
+        }
+    }
+
+    /// The number of bytes transferred so far
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    /// The average rate of the transfer, in bytes per second
+    pub fn rate(&self) -> u64 {
+        let secs = self.started.elapsed().as_secs_f64();
+        if secs > 0.0 {
+            (self.bytes as f64 / secs) as u64
+        } else {
+            0
+        }
+    }
+}
+
+impl std::fmt::Display for TransferProgress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({}/s)", bytesize::ByteSize::b(self.bytes), bytesize::ByteSize::b(self.rate()))
+    }
+}
+
+/// Receive all chunks of `stream`, limited by `limiter`
+///
+/// `report` is called with the progress after each chunk.
+pub async fn receive(
+    mut stream: BoxStream<'_, Result<Vec<u8>>>,
+    limiter: Option<&RateLimiter>,
+    mut report: impl FnMut(&TransferProgress),
+) -> Result<Vec<u8>> {
+    let mut progress = TransferProgress::new();
+    let mut buffer = Vec::new();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk?;
+        progress.bytes += chunk.len() as u64;
+        report(&progress);
+
+        if let Some(limiter) = limiter {
+            limiter.wait(chunk.len()).await?;
+        }
+        buffer.extend(chunk);
+    }
+    Ok(buffer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limiter() {
+        let limiter = RateLimiter::new(1000);
+        let now = Instant::now();
+
+        assert_eq!(limiter.reserve(500, now).unwrap(), now + Duration::from_millis(500));
+        // a second transfer at the same time has to wait for the first one
+        assert_eq!(limiter.reserve(1000, now).unwrap(), now + Duration::from_millis(1500));
+
+        // the limit does not accumulate while nothing is transferred
+        let later = now + Duration::from_secs(10);
+        assert_eq!(limiter.reserve(100, later).unwrap(), later + Duration::from_millis(100));
+    }
+
+    #[tokio::test]
+    async fn test_receive() {
+        let chunks = futures::stream::iter(vec![Ok(vec![1, 2]), Ok(vec![3])]).boxed();
+        let mut reported = Vec::new();
+        let bytes = receive(chunks, None, |progress| reported.push(progress.bytes())).await.unwrap();
+
+        assert_eq!(bytes, vec![1, 2, 3]);
+        assert_eq!(reported, vec![2, 3]);
+    }
+}
@@ -0,0 +1,79 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! The resource usage of the containers of jobs
+
+use anyhow::Result;
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use tracing::debug;
+
+use crate::endpoint::UsageSample;
+
+/// The resource usage of a container, aggregated from the samples of its usage
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ResourceUsage {
+    /// The CPU time used by the container, in seconds
+    pub cpu_seconds: f64,
+
+    /// The maximum memory used by the container
+    pub peak_memory: u64,
+
+    /// The bytes read from and written to block devices by the container
+    pub io_bytes: u64,
+}
+
+impl ResourceUsage {
+    fn add(mut self, sample: &UsageSample) -> Self {
+        // The CPU time and the IO are cumulative, the last sample has the totals
+        self.cpu_seconds = self.cpu_seconds.max(sample.cpu_ns as f64 / 1_000_000_000.0);
+        self.peak_memory = self.peak_memory.max(sample.memory_bytes);
+        self.io_bytes = self.io_bytes.max(sample.io_bytes);
+        self
+    }
+
+    /// Aggregate the `samples`, `None` if there is no sample
+    ///
+    /// Sampling is best effort: samples that could not be fetched are skipped.
+    pub async fn from_samples(samples: BoxStream<'_, Result<UsageSample>>) -> Option<Self> {
+        samples
+            .filter_map(|sample| async move {
+                sample
+                    .map_err(|e| debug!("Skipping resource usage sample: {:?}", e))
+                    .ok()
+            })
+            .fold(None, |usage: Option<ResourceUsage>, sample| async move {
+                Some(usage.unwrap_or_default().add(&sample))
+            })
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_from_samples() {
+        let samples = futures::stream::iter(vec![
+            Ok(UsageSample { cpu_ns: 500_000_000, memory_bytes: 300, io_bytes: 10 }),
+            Err(anyhow::anyhow!("unavailable")),
+            Ok(UsageSample { cpu_ns: 2_000_000_000, memory_bytes: 100, io_bytes: 40 }),
+        ])
+        .boxed();
+
+        let usage = ResourceUsage::from_samples(samples).await.unwrap();
+        assert_eq!(usage.cpu_seconds, 2.0);
+        assert_eq!(usage.peak_memory, 300);
+        assert_eq!(usage.io_bytes, 40);
+
+        assert_eq!(ResourceUsage::from_samples(futures::stream::empty().boxed()).await, None);
+    }
+}
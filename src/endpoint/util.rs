@@ -10,8 +10,11 @@
 
 use std::sync::Arc;
 
+use anyhow::Error;
 use anyhow::Result;
 use futures::FutureExt;
+use tokio::io::AsyncRead;
+use tokio::io::AsyncReadExt;
 use tokio_stream::StreamExt;
 
 use crate::endpoint::Endpoint;
@@ -28,3 +31,17 @@ pub async fn setup_endpoints(endpoints: Vec<EndpointConfiguration>) -> Result<Ve
     unordered.collect().await
 }
 
+/// Read `reader` as stream of chunks
+pub(crate) fn read_stream<R: AsyncRead + Unpin + Send + 'static>(reader: R) -> impl futures::Stream<Item = Result<Vec<u8>>> {
+    futures::stream::unfold(reader, |mut reader| async move {
+        let mut buf = vec![0; 8192];
+        match reader.read(&mut buf).await {
+            Ok(0) => None,
+            Ok(n) => {
+                buf.truncate(n);
+                Some((Ok(buf), reader))
+            },
+            Err(e) => Some((Err(Error::from(e)), reader)),
+        }
+    })
+}
@@ -0,0 +1,86 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! The backends the artifacts of a store can be persisted in
+//!
+//! Every store has a local directory. Artifacts that are built or released are written to this
+//! directory. With a remote backend, they are pushed to the remote as well, and artifacts that
+//! only exist in the remote are fetched into the local directory when they are needed.
+
+use std::path::Path;
+
+use anyhow::anyhow;
+use anyhow::Result;
+
+use crate::config::StoreBackendConfig;
+use crate::filestore::path::ArtifactPath;
+use crate::filestore::s3::S3Client;
+
+#[derive(Clone, Debug, Default)]
+pub enum StoreBackend {
+    /// Artifacts only exist in the local directory
+    #[default]
+    Local,
+
+    /// Artifacts are mirrored to an S3 compatible object storage
+    S3(Box<S3Client>),
+}
+
+impl StoreBackend {
+    pub fn from_config(config: Option<&StoreBackendConfig>) -> Result<Self> {
+        match config {
+            None => Ok(StoreBackend::Local),
+            Some(StoreBackendConfig::S3(s3)) => S3Client::new(s3).map(Box::new).map(StoreBackend::S3),
+        }
+    }
+
+    /// Get the backend for a sub-directory of the store
+    pub fn for_subdirectory(&self, sub: &str) -> Self {
+        match self {
+            StoreBackend::Local => StoreBackend::Local,
+            StoreBackend::S3(client) => StoreBackend::S3(Box::new(client.with_subprefix(sub))),
+        }
+    }
+
+    /// List the artifacts that exist in the backend
+    pub async fn list(&self) -> Result<Vec<ArtifactPath>> {
+        match self {
+            StoreBackend::Local => Ok(Vec::new()),
+            StoreBackend::S3(client) => client
+                .list()
+                .await?
+                .into_iter()
+                .map(|key| ArtifactPath::new(key.into()))
+                .collect(),
+        }
+    }
+
+    /// Fetch an artifact from the backend into the local store directory `root`
+    pub async fn fetch(&self, root: &Path, ap: &ArtifactPath) -> Result<()> {
+        match self {
+            StoreBackend::Local => Err(anyhow!("Artifact does not exist locally: {}", ap.display())),
+            StoreBackend::S3(client) => client.download(&key_of(ap)?, &root.join(ap)).await,
+        }
+    }
+
+    /// Push an artifact from the local store directory `root` to the backend
+    pub async fn push(&self, root: &Path, ap: &ArtifactPath) -> Result<()> {
+        match self {
+            StoreBackend::Local => Ok(()),
+            StoreBackend::S3(client) => client.upload(&key_of(ap)?, &root.join(ap)).await,
+        }
+    }
+}
+
+fn key_of(ap: &ArtifactPath) -> Result<String> {
+    ap.to_str()
+        .map(String::from)
+        .ok_or_else(|| anyhow!("Path is not valid UTF-8: {}", ap.display()))
+}
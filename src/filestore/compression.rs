@@ -0,0 +1,200 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Transparent compression of artifacts in the staging store
+
+use std::ffi::OsString;
+use std::io::Read;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use regex::Regex;
+use serde::Deserialize;
+use tracing::trace;
+
+use crate::config::ArtifactCompressionRule;
+
+/// The compression algorithms artifacts can be stored with
+#[derive(parse_display::Display, parse_display::FromStr, Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+pub enum Compression {
+    #[serde(rename = "zstd")]
+    #[display("zstd")]
+    Zstd,
+
+    #[serde(rename = "xz")]
+    #[display("xz")]
+    Xz,
+}
+
+impl Compression {
+    /// The file extension that is appended to a compressed artifact
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Compression::Zstd => "zst",
+            Compression::Xz => "xz",
+        }
+    }
+
+    /// Get the compression of an artifact by looking at its file extension
+    pub fn from_path(p: &Path) -> Option<Self> {
+        match p.extension().and_then(|ext| ext.to_str()) {
+            Some("zst") => Some(Compression::Zstd),
+            Some("xz") => Some(Compression::Xz),
+            _ => None,
+        }
+    }
+
+    /// The path of the artifact without the extension added by the compression
+    pub fn strip_extension(&self, p: &Path) -> PathBuf {
+        p.with_extension("")
+    }
+
+    /// Compress the file at `src`, remove `src` and return the path of the compressed file
+    pub fn compress_file(&self, src: &Path, level: Option<i32>) -> Result<PathBuf> {
+        let dest = {
+            let mut s = OsString::from(src.as_os_str());
+            s.push(".");
+            s.push(self.extension());
+            PathBuf::from(s)
+        };
+        trace!("Compressing {} to {} with {}", src.display(), dest.display(), self);
+
+        let mut input = std::fs::File::open(src)
+            .with_context(|| anyhow!("Opening {} for compression", src.display()))?;
+        let output = std::fs::File::create(&dest)
+            .with_context(|| anyhow!("Creating {}", dest.display()))?;
+
+        match self {
+            Compression::Zstd => {
+                let level = level.unwrap_or(zstd::DEFAULT_COMPRESSION_LEVEL);
+                zstd::stream::copy_encode(input, output, level)
+                    .with_context(|| anyhow!("Compressing {} with {}", src.display(), self))?;
+            }
+            Compression::Xz => {
+                let level = level.map(|l| l.clamp(0, 9) as u32).unwrap_or(6);
+                let mut encoder = xz2::write::XzEncoder::new(output, level);
+                std::io::copy(&mut input, &mut encoder)
+                    .with_context(|| anyhow!("Compressing {} with {}", src.display(), self))?;
+                encoder.finish()?.flush()?;
+            }
+        }
+
+        std::fs::remove_file(src)
+            .with_context(|| anyhow!("Removing uncompressed file {}", src.display()))?;
+        Ok(dest)
+    }
+
+    /// Decompress the file at `src`, which was compressed with this algorithm
+    ///
+    /// The file is streamed through the decoder, only the decompressed content is held in memory.
+    pub fn decompress_file(&self, src: &Path) -> Result<Vec<u8>> {
+        let input = std::fs::File::open(src)
+            .map(std::io::BufReader::new)
+            .with_context(|| anyhow!("Opening {} for decompression", src.display()))?;
+
+        let mut out = Vec::new();
+        match self {
+            Compression::Zstd => {
+                zstd::stream::copy_decode(input, &mut out)
+                    .with_context(|| anyhow!("Decompressing {} with {}", src.display(), self))?;
+            }
+            Compression::Xz => {
+                xz2::read::XzDecoder::new(input)
+                    .read_to_end(&mut out)
+                    .with_context(|| anyhow!("Decompressing {} with {}", src.display(), self))?;
+            }
+        }
+        Ok(out)
+    }
+}
+
+/// The compiled compression rules from the configuration
+#[derive(Clone, Debug, Default)]
+pub struct CompressionRules(Vec<(Regex, Compression, Option<i32>)>);
+
+impl CompressionRules {
+    pub fn from_config(rules: &[ArtifactCompressionRule]) -> Result<Self> {
+        rules
+            .iter()
+            .map(|rule| {
+                Regex::new(rule.pattern())
+                    .with_context(|| anyhow!("Parsing artifact compression pattern: {}", rule.pattern()))
+                    .map(|re| (re, *rule.algorithm(), *rule.level()))
+            })
+            .collect::<Result<Vec<_>>>()
+            .map(CompressionRules)
+    }
+
+    /// Find the compression that should be used for an artifact path, if any
+    ///
+    /// Already compressed artifacts are never compressed again.
+    pub fn for_path(&self, p: &Path) -> Option<(Compression, Option<i32>)> {
+        if Compression::from_path(p).is_some() {
+            return None;
+        }
+
+        let s = p.to_str()?;
+        self.0
+            .iter()
+            .find(|(re, _, _)| re.is_match(s))
+            .map(|(_, compression, level)| (*compression, *level))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_path() {
+        assert_eq!(Compression::from_path(Path::new("foo.tar.zst")), Some(Compression::Zstd));
+        assert_eq!(Compression::from_path(Path::new("foo.tar.xz")), Some(Compression::Xz));
+        assert_eq!(Compression::from_path(Path::new("foo.tar")), None);
+    }
+
+    #[test]
+    fn test_strip_extension() {
+        let p = Path::new("a/foo.tar.zst");
+        assert_eq!(Compression::Zstd.strip_extension(p), PathBuf::from("a/foo.tar"));
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let dir = std::env::temp_dir().join(format!("butido-compression-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let content = b"some artifact content, some artifact content, some artifact content";
+
+        for compression in [Compression::Zstd, Compression::Xz] {
+            let src = dir.join("artifact.tar");
+            std::fs::write(&src, content).unwrap();
+
+            let dest = compression.compress_file(&src, None).unwrap();
+            assert!(!src.exists());
+            assert_eq!(Compression::from_path(&dest), Some(compression));
+
+            assert_eq!(compression.decompress_file(&dest).unwrap(), content);
+            std::fs::remove_file(dest).unwrap();
+        }
+
+        std::fs::remove_dir(dir).unwrap();
+    }
+
+    #[test]
+    fn test_rules_do_not_compress_twice() {
+        let rules = CompressionRules(vec![(Regex::new(".*").unwrap(), Compression::Zstd, None)]);
+        assert!(rules.for_path(Path::new("foo.tar")).is_some());
+        assert!(rules.for_path(Path::new("foo.tar.zst")).is_none());
+        assert!(rules.for_path(Path::new("foo.tar.xz")).is_none());
+    }
+}
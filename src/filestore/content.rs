@@ -0,0 +1,240 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+//! Content-addressed storage of artifact payloads
+//!
+//! Identical artifacts (e.g. the same package built in several submits) are stored only once in
+//! the content store, under their SHA256 hash. The files in the staging and release stores are
+//! hard links or symlinks to these objects.
+
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Error;
+use anyhow::Result;
+use serde::Deserialize;
+use sha2::Digest;
+use tracing::trace;
+
+use crate::config::ContentStoreConfig;
+
+/// How the files in the stores refer to the objects in the content store
+#[derive(parse_display::Display, parse_display::FromStr, Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+pub enum LinkMethod {
+    /// Hard links, the content store has to be on the same filesystem as the stores
+    #[default]
+    #[serde(rename = "hardlink")]
+    #[display("hardlink")]
+    HardLink,
+
+    #[serde(rename = "symlink")]
+    #[display("symlink")]
+    SymLink,
+}
+
+/// What happened to a file when it was deduplicated
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Deduplicated {
+    /// The content was not yet known and was added to the content store
+    Stored,
+
+    /// The file was replaced by a link to an existing object, saving this many bytes
+    Linked(u64),
+
+    /// The file already is a link to its object
+    AlreadyLinked,
+}
+
+#[derive(Clone, Debug)]
+pub struct ContentStore {
+    root: PathBuf,
+    link: LinkMethod,
+}
+
+impl ContentStore {
+    pub fn new(root: PathBuf, link: LinkMethod) -> Self {
+        ContentStore { root, link }
+    }
+
+    pub fn from_config(config: Option<&ContentStoreConfig>) -> Option<Self> {
+        config.map(|c| ContentStore::new(c.root().clone(), c.link()))
+    }
+
+    /// The path of the object with the SHA256 hash `hash`
+    pub fn object_path(&self, hash: &str) -> PathBuf {
+        self.root.join(&hash[..2]).join(hash)
+    }
+
+    /// Move the content of the file at `path` into the content store and replace the file with a
+    /// link to the object
+    pub fn deduplicate(&self, path: &Path) -> Result<Deduplicated> {
+        let md = std::fs::symlink_metadata(path).with_context(|| anyhow!("Reading metadata of {}", path.display()))?;
+        if md.file_type().is_symlink() {
+            let target = std::fs::read_link(path).with_context(|| anyhow!("Reading link {}", path.display()))?;
+            if target.starts_with(&self.root) {
+                return Ok(Deduplicated::AlreadyLinked)
+            }
+        }
+
+        let hash = sha256_of_file(path)?;
+        let object = self.object_path(&hash);
+        trace!("Object for {}: {}", path.display(), object.display());
+
+        match std::fs::metadata(&object) {
+            Ok(object_md) => {
+                if self.link == LinkMethod::HardLink && object_md.dev() == md.dev() && object_md.ino() == md.ino() {
+                    return Ok(Deduplicated::AlreadyLinked)
+                }
+                self.replace_with_link(&object, path)?;
+                Ok(Deduplicated::Linked(md.len()))
+            },
+
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                let parent = object.parent().ok_or_else(|| anyhow!("Object path has no parent: {}", object.display()))?;
+                std::fs::create_dir_all(parent).with_context(|| anyhow!("Creating {}", parent.display()))?;
+                match self.link {
+                    LinkMethod::HardLink => {
+                        // The file and the object share the inode, so the file becomes read-only
+                        // as well
+                        set_read_only(path)?;
+                        match std::fs::hard_link(path, &object) {
+                            Ok(()) => {},
+
+                            // Another build stored the same content in the meantime
+                            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                                trace!("Object {} was stored concurrently, linking to it", object.display());
+                                self.replace_with_link(&object, path)?;
+                                return Ok(Deduplicated::Linked(md.len()))
+                            },
+
+                            Err(e) => {
+                                return Err(Error::from(e).context(anyhow!(
+                                    "Linking {} to {}, is the content store on the same filesystem?",
+                                    path.display(),
+                                    object.display()
+                                )))
+                            },
+                        }
+                    },
+                    LinkMethod::SymLink => {
+                        // Copy to a temporary file first, so the object is never visible with
+                        // partial content
+                        let tmp = parent.join(format!(".{}.{}", hash, uuid::Uuid::new_v4()));
+                        std::fs::copy(path, &tmp).with_context(|| anyhow!("Copying {} to {}", path.display(), tmp.display()))?;
+                        set_read_only(&tmp)?;
+                        std::fs::rename(&tmp, &object)
+                            .with_context(|| anyhow!("Moving {} to {}", tmp.display(), object.display()))?;
+                        self.replace_with_link(&object, path)?;
+                    },
+                }
+                Ok(Deduplicated::Stored)
+            },
+
+            Err(e) => Err(Error::from(e).context(anyhow!("Reading metadata of {}", object.display()))),
+        }
+    }
+
+    /// Atomically replace the file at `path` with a link to `object`
+    fn replace_with_link(&self, object: &Path, path: &Path) -> Result<()> {
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".butido-dedup");
+        let tmp = PathBuf::from(tmp);
+
+        match self.link {
+            LinkMethod::HardLink => std::fs::hard_link(object, &tmp),
+            LinkMethod::SymLink => std::os::unix::fs::symlink(object, &tmp),
+        }
+        .with_context(|| anyhow!("Linking {} to {}", tmp.display(), object.display()))?;
+
+        std::fs::rename(&tmp, path)
+            .with_context(|| anyhow!("Replacing {} with a link to {}", path.display(), object.display()))
+            .map_err(Error::from)
+    }
+
+    /// Objects that are not referenced by any store anymore
+    ///
+    /// Only hard linked objects can be detected, they have a link count of one. Symlinked objects
+    /// are never reported.
+    pub fn orphans(&self) -> Result<Vec<(PathBuf, u64)>> {
+        if self.link == LinkMethod::SymLink || !self.root.is_dir() {
+            return Ok(vec![])
+        }
+
+        walkdir::WalkDir::new(&self.root)
+            .into_iter()
+            .map(|entry| {
+                let entry = entry.with_context(|| anyhow!("Walking {}", self.root.display()))?;
+                let md = entry.metadata().with_context(|| anyhow!("Reading metadata of {}", entry.path().display()))?;
+                Ok((md.is_file() && md.nlink() == 1).then(|| (entry.into_path(), md.len())))
+            })
+            .filter_map(Result::transpose)
+            .collect()
+    }
+}
+
+/// Remove the write permissions of the file at `path`, objects must never change
+fn set_read_only(path: &Path) -> Result<()> {
+    let mut permissions = std::fs::metadata(path)
+        .with_context(|| anyhow!("Reading metadata of {}", path.display()))?
+        .permissions();
+    permissions.set_readonly(true);
+    std::fs::set_permissions(path, permissions)
+        .with_context(|| anyhow!("Making {} read-only", path.display()))
+        .map_err(Error::from)
+}
+
+pub fn sha256_of_file(path: &Path) -> Result<String> {
+    let mut file = std::fs::File::open(path).with_context(|| anyhow!("Opening {}", path.display()))?;
+    let mut hasher = sha2::Sha256::new();
+    std::io::copy(&mut file, &mut hasher).with_context(|| anyhow!("Hashing {}", path.display()))?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn deduplicate_twice(link: LinkMethod) {
+        let dir = std::env::temp_dir().join(format!("butido-content-test-{}", uuid::Uuid::new_v4()));
+        let store = ContentStore::new(dir.join("objects"), link);
+        let (a, b) = (dir.join("a.tar.gz"), dir.join("b.tar.gz"));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(&a, b"artifact").unwrap();
+        std::fs::write(&b, b"artifact").unwrap();
+
+        assert_eq!(store.deduplicate(&a).unwrap(), Deduplicated::Stored);
+        assert_eq!(store.deduplicate(&b).unwrap(), Deduplicated::Linked(8));
+        assert_eq!(store.deduplicate(&b).unwrap(), Deduplicated::AlreadyLinked);
+        assert_eq!(std::fs::read(&a).unwrap(), b"artifact");
+        assert_eq!(std::fs::read(&b).unwrap(), b"artifact");
+        let object = store.object_path(&crate::job::sha256_hex(b"artifact"));
+        assert!(object.is_file());
+        assert!(std::fs::metadata(&object).unwrap().permissions().readonly());
+        assert!(store.orphans().unwrap().is_empty());
+
+        std::fs::remove_file(&a).unwrap();
+        std::fs::remove_file(&b).unwrap();
+        assert_eq!(store.orphans().unwrap().len(), if link == LinkMethod::HardLink { 1 } else { 0 });
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_deduplicate_hardlink() {
+        deduplicate_twice(LinkMethod::HardLink);
+    }
+
+    #[test]
+    fn test_deduplicate_symlink() {
+        deduplicate_twice(LinkMethod::SymLink);
+    }
+}
@@ -0,0 +1,69 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+//! Classification of the artifacts collected from containers
+
+use std::path::Path;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use regex::Regex;
+
+use crate::config::ArtifactKindRule;
+
+/// The kind of the artifacts that are installable packages
+pub const PACKAGE_ARTIFACT_KIND: &str = "package";
+
+#[derive(Debug, Default)]
+pub struct ArtifactKindRules(Vec<(Regex, String)>);
+
+impl ArtifactKindRules {
+    pub fn from_config(rules: &[ArtifactKindRule]) -> Result<Self> {
+        rules
+            .iter()
+            .map(|rule| {
+                Regex::new(rule.pattern())
+                    .with_context(|| anyhow!("Parsing artifact kind pattern: {}", rule.pattern()))
+                    .map(|re| (re, rule.kind().clone()))
+            })
+            .collect::<Result<Vec<_>>>()
+            .map(ArtifactKindRules)
+    }
+
+    /// The kind of the artifact at path `p`, that was collected from the outputs of `category`
+    ///
+    /// The first matching rule wins. If no rule matches, the kind is the output category, or
+    /// "package" for the artifacts from the default outputs.
+    pub fn kind_of<'a>(&'a self, p: &Path, category: Option<&'a str>) -> &'a str {
+        p.to_str()
+            .and_then(|s| self.0.iter().find(|(re, _)| re.is_match(s)))
+            .map(|(_, kind)| kind.as_str())
+            .or(category)
+            .unwrap_or(PACKAGE_ARTIFACT_KIND)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kind_of() {
+        let rules = ArtifactKindRules::from_config(&[
+            toml::from_str(r#"pattern = "\\.debug$"
+kind = "debug""#).unwrap(),
+        ]).unwrap();
+
+        assert_eq!(rules.kind_of(Path::new("a-1.tar.gz"), None), "package");
+        assert_eq!(rules.kind_of(Path::new("a-1.debug"), None), "debug");
+        assert_eq!(rules.kind_of(Path::new("test-reports/junit.xml"), Some("test-reports")), "test-reports");
+        assert_eq!(rules.kind_of(Path::new("test-reports/a.debug"), Some("test-reports")), "debug");
+    }
+}
@@ -0,0 +1,155 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! The layout of the artifacts in the release stores
+//!
+//! By default, artifacts are released to the same path (relative to the store root) they have in
+//! the staging store. With a `release_path_template`, the path is rendered from the package name
+//! and version and the file name of the artifact, e.g. `{{name}}/{{version}}/{{filename}}`.
+
+use std::collections::HashMap;
+use std::path::Component;
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use handlebars::Handlebars;
+use serde::Serialize;
+
+use crate::filestore::ArtifactPath;
+
+const TEMPLATE_NAME: &str = "release_path";
+
+#[derive(Debug, Default)]
+pub struct ReleaseLayout {
+    templates: Option<Handlebars<'static>>,
+}
+
+#[derive(Serialize)]
+struct TemplateData<'a> {
+    name: &'a str,
+    version: &'a str,
+    filename: &'a str,
+    path: &'a str,
+}
+
+impl ReleaseLayout {
+    pub fn from_template(template: Option<&str>) -> Result<Self> {
+        let templates = template
+            .map(|template| {
+                let mut hb = Handlebars::new();
+                hb.set_strict_mode(true);
+                hb.register_escape_fn(handlebars::no_escape);
+                hb.register_template_string(TEMPLATE_NAME, template)
+                    .with_context(|| anyhow!("Parsing release path template: {}", template))
+                    .map(|_| hb)
+            })
+            .transpose()?;
+
+        Ok(ReleaseLayout { templates })
+    }
+
+    /// The path (relative to the root of the release store) an artifact of a package is released
+    /// to
+    pub fn release_path(&self, package_name: &str, package_version: &str, artifact: &ArtifactPath) -> Result<ArtifactPath> {
+        let hb = match self.templates.as_ref() {
+            Some(hb) => hb,
+            None => return Ok(artifact.clone()),
+        };
+
+        let data = TemplateData {
+            name: package_name,
+            version: package_version,
+            filename: artifact
+                .file_name()
+                .and_then(|name| name.to_str())
+                .ok_or_else(|| anyhow!("Artifact has no valid file name: {}", artifact.display()))?,
+            path: artifact
+                .to_str()
+                .ok_or_else(|| anyhow!("Path is not valid UTF-8: {}", artifact.display()))?,
+        };
+
+        let rendered = hb
+            .render(TEMPLATE_NAME, &data)
+            .with_context(|| anyhow!("Rendering release path for {}", artifact.display()))?;
+        let path = PathBuf::from(rendered.trim());
+
+        let escapes = path
+            .components()
+            .any(|component| !matches!(component, Component::Normal(_)));
+        if path.as_os_str().is_empty() || escapes {
+            return Err(anyhow!("Release path for {} is not a plain relative path: {}", artifact.display(), path.display()))
+        }
+
+        ArtifactPath::new(path)
+    }
+}
+
+/// Find the release paths that more than one artifact would be released to
+pub fn release_path_collisions<'a, I>(paths: I) -> Vec<&'a ArtifactPath>
+where
+    I: IntoIterator<Item = &'a ArtifactPath>,
+{
+    let mut counts = HashMap::<&ArtifactPath, usize>::new();
+    for path in paths {
+        *counts.entry(path).or_default() += 1;
+    }
+
+    let mut collisions = counts
+        .into_iter()
+        .filter(|(_, count)| *count > 1)
+        .map(|(path, _)| path)
+        .collect::<Vec<_>>();
+    collisions.sort();
+    collisions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn artifact(p: &str) -> ArtifactPath {
+        ArtifactPath::new_unchecked(PathBuf::from(p))
+    }
+
+    #[test]
+    fn test_default_layout_keeps_path() {
+        let layout = ReleaseLayout::from_template(None).unwrap();
+        let path = layout.release_path("a", "1", &artifact("x86_64/a-1.tar.gz")).unwrap();
+        assert_eq!(path, artifact("x86_64/a-1.tar.gz"));
+    }
+
+    #[test]
+    fn test_template() {
+        let layout = ReleaseLayout::from_template(Some("{{name}}/{{version}}/{{filename}}")).unwrap();
+        let path = layout.release_path("a", "1.0", &artifact("x86_64/a-1.0.tar.gz")).unwrap();
+        assert_eq!(path, artifact("a/1.0/a-1.0.tar.gz"));
+    }
+
+    #[test]
+    fn test_template_must_not_escape_store() {
+        let layout = ReleaseLayout::from_template(Some("../{{filename}}")).unwrap();
+        assert!(layout.release_path("a", "1", &artifact("a-1.tar.gz")).is_err());
+
+        let layout = ReleaseLayout::from_template(Some("/{{filename}}")).unwrap();
+        assert!(layout.release_path("a", "1", &artifact("a-1.tar.gz")).is_err());
+
+        let layout = ReleaseLayout::from_template(Some("{{unknown}}")).unwrap();
+        assert!(layout.release_path("a", "1", &artifact("a-1.tar.gz")).is_err());
+    }
+
+    #[test]
+    fn test_collisions() {
+        let paths = [artifact("a/1/a.tar"), artifact("b/1/b.tar"), artifact("a/1/a.tar")];
+        assert_eq!(release_path_collisions(paths.iter()), vec![&artifact("a/1/a.tar")]);
+        assert!(release_path_collisions(paths[..2].iter()).is_empty());
+    }
+}
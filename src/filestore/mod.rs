@@ -8,12 +8,32 @@
 // SPDX-License-Identifier: EPL-2.0
 //
 
+mod backend;
+pub use backend::*;
+
+mod compression;
+pub use compression::*;
+
+mod content;
+pub use content::*;
+
+mod kind;
+pub use kind::*;
+
+mod layout;
+pub use layout::*;
+
 mod release;
 pub use release::*;
 
 mod staging;
 pub use staging::*;
 
+mod s3;
+
+mod signature;
+pub use signature::*;
+
 pub mod path;
 pub use path::ArtifactPath;
 
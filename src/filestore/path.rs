@@ -16,14 +16,16 @@ use anyhow::anyhow;
 use anyhow::Context;
 use anyhow::Error;
 use anyhow::Result;
+use indicatif::ProgressBar;
 use resiter::AndThen;
 use resiter::Filter;
 use resiter::Map;
 use tracing::trace;
 
 use crate::filestore::staging::StagingStore;
+use crate::filestore::Compression;
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct StoreRoot(PathBuf);
 
 impl StoreRoot {
@@ -68,6 +70,10 @@ impl StoreRoot {
         }
     }
 
+    pub(in crate::filestore) fn as_path(&self) -> &Path {
+        &self.0
+    }
+
     pub(in crate::filestore) fn is_dir(&self, subpath: &Path) -> bool {
         self.0.join(subpath).is_dir()
     }
@@ -76,6 +82,23 @@ impl StoreRoot {
         self.0.display()
     }
 
+    /// Compress the file at `subpath` in this location
+    ///
+    /// The uncompressed file is removed, the path of the compressed file (relative to this
+    /// location) is returned.
+    pub(in crate::filestore) fn compress_file_here(
+        &self,
+        subpath: &Path,
+        compression: Compression,
+        level: Option<i32>,
+    ) -> Result<PathBuf> {
+        compression
+            .compress_file(&self.0.join(subpath), level)?
+            .strip_prefix(&self.0)
+            .map(Path::to_path_buf)
+            .map_err(Error::from)
+    }
+
     pub(in crate::filestore) fn find_artifacts_recursive(
         &self,
     ) -> impl Iterator<Item = Result<ArtifactPath>> {
@@ -85,7 +108,8 @@ impl StoreRoot {
             .follow_links(false)
             .into_iter()
             .filter_ok(|e| {
-                let is_file = e.file_type().is_file();
+                // Artifacts might be symlinks to the content store
+                let is_file = e.file_type().is_file() || (e.file_type().is_symlink() && e.path().is_file());
                 trace!("{:?} is file = {}", e, is_file);
                 is_file
             })
@@ -106,40 +130,201 @@ impl StoreRoot {
     /// `self` and returns the written pathes.
     ///
     /// The function filteres out the "/output" directory (that's what is meant by "butido-style").
-    pub(in crate::filestore) fn unpack_archive_here<R>(&self, mut ar: tar::Archive<R>) -> Result<Vec<PathBuf>>
+    ///
+    /// The files are unpacked into `subdir` of the location. Entries with absolute paths or paths
+    /// that would escape the location are rejected, as well as archives that exceed the `limits`.
+    /// The file that is unpacked is shown on `bar`.
+    pub(in crate::filestore) fn unpack_archive_here<R>(
+        &self,
+        mut ar: tar::Archive<R>,
+        subdir: &Path,
+        limits: &UnpackLimits,
+        bar: &ProgressBar,
+    ) -> Result<Vec<PathBuf>>
     where
         R: std::io::Read,
     {
-        ar.entries()?
+        let message = bar.message();
+        let mut count = 0;
+        let unpacked = ar.entries()?
             .map_err(Error::from)
             .filter_ok(|entry| entry.header().entry_type() == tar::EntryType::Regular)
             .and_then_ok(|mut entry| -> Result<_> {
                 let path = entry
                     .path()
-                    .context("Getting path from entry in Archive")?
-                    .components()
-                    .filter(|comp| {
-                        trace!("Filtering path component: '{:?}'", comp);
-                        let osstr = std::ffi::OsStr::new(crate::consts::OUTPUTS_DIR_NAME);
-                        match comp {
-                            std::path::Component::Normal(s) => *s != osstr,
-                            _ => true,
-                        }
-                    })
-                    .collect::<PathBuf>();
+                    .context("Getting path from entry in Archive")
+                    .and_then(|p| safe_entry_path(&p))
+                    .map(|p| subdir.join(p))?;
+
+                count += 1;
+                if let Some(max) = limits.max_files {
+                    if count > max {
+                        return Err(anyhow!("Archive contains more than {} files", max))
+                    }
+                }
+
+                let size = entry.header().size().context("Getting size of entry in Archive")?;
+                if let Some(max) = limits.max_file_size {
+                    if size > max {
+                        return Err(anyhow!("{} is larger than {} bytes: {} bytes", path.display(), max, size))
+                    }
+                }
 
                 trace!("Path = '{:?}'", path);
+                bar.set_message(format!("{message}: Unpacking {} ({})", path.display(), bytesize::ByteSize::b(size)));
                 let unpack_dest = self.0.join(&path);
                 trace!("Unpack to = '{:?}'", unpack_dest);
+                if let Some(parent) = unpack_dest.parent() {
+                    std::fs::create_dir_all(parent).with_context(|| anyhow!("Creating {}", parent.display()))?;
+                }
 
-                entry.unpack(unpack_dest)
-                    .map(|_| path)
-                    .map_err(Error::from)
+                entry.unpack(&unpack_dest)
+                    .with_context(|| anyhow!("Unpacking {}", path.display()))?;
+                Ok(path)
             })
-            .collect::<Result<Vec<_>>>()
+            .collect::<Result<Vec<_>>>();
+        bar.set_message(message);
+        unpacked
     }
 }
 
+/// The paths `unpack_archive_here()` would write the files of a tar archive to, without
+/// unpacking them
+pub(in crate::filestore) fn archive_entry_paths<R>(mut ar: tar::Archive<R>, subdir: &Path) -> Result<Vec<PathBuf>>
+where
+    R: std::io::Read,
+{
+    ar.entries()?
+        .map_err(Error::from)
+        .filter_ok(|entry| entry.header().entry_type() == tar::EntryType::Regular)
+        .and_then_ok(|entry| {
+            entry
+                .path()
+                .context("Getting path from entry in Archive")
+                .and_then(|p| safe_entry_path(&p))
+                .map(|p| subdir.join(p))
+        })
+        .collect()
+}
+
+/// Limits for archives that are unpacked into a store
+#[derive(Clone, Copy, Debug, Default)]
+pub struct UnpackLimits {
+    /// The maximum size of a single file, in bytes
+    pub max_file_size: Option<u64>,
+
+    /// The maximum number of files in an archive
+    pub max_files: Option<usize>,
+}
+
+/// Checks the limits of a tar archive while it is received, before it is unpacked
+///
+/// The headers of the entries are parsed from the chunks of the archive as they arrive, so a
+/// transfer can be aborted as soon as an entry exceeds the limits. `unpack_archive_here()`
+/// enforces the limits again when the archive is unpacked.
+#[derive(Debug)]
+pub struct ArchiveLimitCheck {
+    limits: UnpackLimits,
+
+    /// The bytes of the header that is received
+    header: Vec<u8>,
+
+    /// The number of bytes of the data of the current entry that are not yet received
+    skip: u64,
+    files: usize,
+}
+
+impl ArchiveLimitCheck {
+    const BLOCK_SIZE: usize = 512;
+
+    pub fn new(limits: UnpackLimits) -> Self {
+        ArchiveLimitCheck {
+            limits,
+            header: Vec::with_capacity(Self::BLOCK_SIZE),
+            skip: 0,
+            files: 0,
+        }
+    }
+
+    /// Check the next chunk of the archive
+    pub fn feed(&mut self, mut chunk: &[u8]) -> Result<()> {
+        while !chunk.is_empty() {
+            if self.skip > 0 {
+                let n = self.skip.min(chunk.len() as u64) as usize;
+                self.skip -= n as u64;
+                chunk = &chunk[n..];
+                continue;
+            }
+
+            let n = (Self::BLOCK_SIZE - self.header.len()).min(chunk.len());
+            self.header.extend_from_slice(&chunk[..n]);
+            chunk = &chunk[n..];
+            if self.header.len() == Self::BLOCK_SIZE {
+                self.check_header()?;
+                self.header.clear();
+            }
+        }
+        Ok(())
+    }
+
+    fn check_header(&mut self) -> Result<()> {
+        // The end of the archive is marked with blocks of zeros
+        if self.header.iter().all(|b| *b == 0) {
+            return Ok(())
+        }
+
+        let header = tar::Header::from_byte_slice(&self.header);
+        let size = header.entry_size().context("Getting size of entry in Archive")?;
+        if header.entry_type() == tar::EntryType::Regular {
+            self.files += 1;
+            if let Some(max) = self.limits.max_files {
+                if self.files > max {
+                    return Err(anyhow!("Archive contains more than {} files", max))
+                }
+            }
+
+            if let Some(max) = self.limits.max_file_size {
+                if size > max {
+                    let path = header.path().map(|p| p.display().to_string()).unwrap_or_default();
+                    return Err(anyhow!("{} is larger than {} bytes: {} bytes", path, max, size))
+                }
+            }
+        }
+
+        let block_size = Self::BLOCK_SIZE as u64;
+        self.skip = (size + block_size - 1) / block_size * block_size;
+        Ok(())
+    }
+}
+
+/// The path of an entry in a tar archive fetched from a container, relative to the outputs
+/// directory
+///
+/// Fails if the path is absolute, contains ".." or does not name a file in the outputs directory.
+fn safe_entry_path(p: &Path) -> Result<PathBuf> {
+    if p.is_absolute() || p.components().any(|comp| !matches!(comp, std::path::Component::Normal(_) | std::path::Component::CurDir)) {
+        return Err(anyhow!("Refusing to unpack entry with unsafe path: {}", p.display()))
+    }
+
+    let path = strip_outputs_dir(p);
+    if path.as_os_str().is_empty() {
+        return Err(anyhow!("Entry is not inside the outputs directory: {}", p.display()))
+    }
+    Ok(path)
+}
+
+/// Remove the outputs directory from a path of an entry in a tar archive fetched from a container
+///
+/// The entries of the archive are prefixed with the name of the outputs directory (e.g.
+/// "outputs"), whatever path it has in the container.
+pub fn strip_outputs_dir(p: &Path) -> PathBuf {
+    trace!("Stripping outputs directory from: '{:?}'", p);
+    p.components()
+        .skip_while(|comp| !matches!(comp, std::path::Component::Normal(_)))
+        .skip(1)
+        .collect::<PathBuf>()
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct ArtifactPath(PathBuf);
 
@@ -187,7 +372,7 @@ pub struct FullArtifactPath<'a>(&'a StoreRoot, &'a ArtifactPath);
 impl<'a> FullArtifactPath<'a> {
 
     pub fn is_in_staging_store(&self, store: &StagingStore) -> bool {
-        store.0.root_path() == self.0
+        store.store.root_path() == self.0
     }
 
     pub fn artifact_path(&self) -> &ArtifactPath {
@@ -201,14 +386,6 @@ impl<'a> FullArtifactPath<'a> {
     pub fn display(&self) -> FullArtifactPathDisplay<'a> {
         FullArtifactPathDisplay(self.0, self.1)
     }
-
-    pub async fn read(self) -> Result<Vec<u8>> {
-        tokio::fs::read(self.joined())
-            .await
-            .map(Vec::from)
-            .with_context(|| anyhow!("Reading artifact from path {}", self.0.display()))
-            .map_err(Error::from)
-    }
 }
 
 #[derive(Debug)]
@@ -219,3 +396,68 @@ impl<'a> std::fmt::Display for FullArtifactPathDisplay<'a> {
         write!(fmt, "{}/{}", self.0.display(), self.1.display())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_safe_entry_path() {
+        assert_eq!(safe_entry_path(Path::new("outputs/a.tar.gz")).unwrap(), PathBuf::from("a.tar.gz"));
+        assert_eq!(safe_entry_path(Path::new("./outputs/sub/a.tar.gz")).unwrap(), PathBuf::from("sub/a.tar.gz"));
+
+        assert!(safe_entry_path(Path::new("/etc/passwd")).is_err());
+        assert!(safe_entry_path(Path::new("outputs/../../etc/passwd")).is_err());
+        assert!(safe_entry_path(Path::new("../outputs/a")).is_err());
+        assert!(safe_entry_path(Path::new("outputs")).is_err());
+    }
+
+    fn archive(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (path, content) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, path, *content).unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    #[test]
+    fn test_unpack_limits() {
+        let dir = std::env::temp_dir().join(format!("butido-unpack-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let root = StoreRoot::new(dir.clone()).unwrap();
+        let bytes = archive(&[("outputs/a", b"aaaa"), ("outputs/sub/b", b"bb")]);
+        let bar = ProgressBar::hidden();
+        let unpack = |limits| root.unpack_archive_here(tar::Archive::new(&bytes[..]), Path::new(""), &limits, &bar);
+
+        assert_eq!(unpack(UnpackLimits::default()).unwrap(), vec![PathBuf::from("a"), PathBuf::from("sub/b")]);
+        assert_eq!(std::fs::read(dir.join("sub/b")).unwrap(), b"bb");
+        assert!(unpack(UnpackLimits { max_files: Some(1), ..Default::default() }).is_err());
+        assert!(unpack(UnpackLimits { max_file_size: Some(3), ..Default::default() }).is_err());
+        assert!(unpack(UnpackLimits { max_file_size: Some(4), max_files: Some(2) }).is_ok());
+
+        assert_eq!(archive_entry_paths(tar::Archive::new(&bytes[..]), Path::new("reports")).unwrap(), vec![PathBuf::from("reports/a"), PathBuf::from("reports/sub/b")]);
+
+        let reports = root.unpack_archive_here(tar::Archive::new(&bytes[..]), Path::new("reports"), &UnpackLimits::default(), &bar).unwrap();
+        assert_eq!(reports, vec![PathBuf::from("reports/a"), PathBuf::from("reports/sub/b")]);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_archive_limit_check() {
+        let bytes = archive(&[("outputs/a", b"aaaa"), ("outputs/sub/b", &[b'b'; 1000])]);
+        let check = |limits| {
+            let mut check = ArchiveLimitCheck::new(limits);
+            bytes.chunks(100).try_for_each(|chunk| check.feed(chunk))
+        };
+
+        assert!(check(UnpackLimits::default()).is_ok());
+        assert!(check(UnpackLimits { max_file_size: Some(1000), max_files: Some(2) }).is_ok());
+        assert!(check(UnpackLimits { max_files: Some(1), ..Default::default() }).is_err());
+        assert!(check(UnpackLimits { max_file_size: Some(999), ..Default::default() }).is_err());
+    }
+}
@@ -9,11 +9,14 @@
 //
 
 use std::fmt::Debug;
+use std::path::Path;
 
 use anyhow::Result;
 use indicatif::ProgressBar;
 
+use crate::filestore::StoreBackend;
 use crate::filestore::path::ArtifactPath;
+use crate::filestore::path::FullArtifactPath;
 use crate::filestore::path::StoreRoot;
 use crate::filestore::util::FileStoreImpl;
 
@@ -31,10 +34,36 @@ impl ReleaseStore {
         FileStoreImpl::load(root, progress).map(ReleaseStore)
     }
 
+    /// Use `backend` for this store, the artifacts that exist in the backend become available
+    pub async fn with_backend(mut self, backend: StoreBackend) -> Result<Self> {
+        self.0.attach_backend(backend).await?;
+        Ok(self)
+    }
+
+    /// Get the full path of an artifact, fetching it from the backend if it does not exist locally
+    pub async fn fetch<'a>(&'a self, p: &'a ArtifactPath) -> Result<Option<FullArtifactPath<'a>>> {
+        self.0.fetch(p).await
+    }
+
     pub fn root_path(&self) -> &StoreRoot {
         self.0.root_path()
     }
 
+    /// Copy the file at `source` into the store as `p`, see `FileStoreImpl::import_file()`
+    pub async fn import_file(&mut self, source: &Path, p: &ArtifactPath) -> Result<()> {
+        self.0.import_file(source, p).await
+    }
+
+    /// Remove the artifact `p` from the local directory of the store
+    pub async fn remove_file(&mut self, p: &ArtifactPath) -> Result<()> {
+        self.0.remove_file(p).await
+    }
+
+    /// Push the artifact `p` to the backend of the store
+    pub async fn push(&self, p: &ArtifactPath) -> Result<()> {
+        self.0.push(p).await
+    }
+
     pub fn get(&self, p: &ArtifactPath) -> Option<&ArtifactPath> {
         self.0.get(p)
     }
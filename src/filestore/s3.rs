@@ -0,0 +1,369 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! A minimal client for S3 compatible object storages
+//!
+//! Only the operations butido needs are implemented: listing, downloading and uploading objects.
+//! Requests are signed with AWS signature version 4 and use path-style URLs, so that MinIO and
+//! other S3 compatible storages work as well.
+
+use std::path::Path;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use hmac::Hmac;
+use hmac::Mac;
+use serde::Deserialize;
+use sha2::Digest;
+use sha2::Sha256;
+use tokio::io::AsyncWriteExt;
+use tokio_stream::StreamExt;
+use tracing::trace;
+use url::Url;
+
+use crate::config::S3StoreConfig;
+
+/// Payload hash that is used for all requests, the payload itself is not signed
+const UNSIGNED_PAYLOAD: &str = "UNSIGNED-PAYLOAD";
+
+#[derive(Clone, Debug)]
+pub struct S3Client {
+    http: reqwest::Client,
+    endpoint: Url,
+    bucket: String,
+    region: String,
+    prefix: String,
+    access_key_id: String,
+    secret_access_key: String,
+}
+
+impl S3Client {
+    pub fn new(config: &S3StoreConfig) -> Result<Self> {
+        let access_key_id = config
+            .access_key_id()
+            .clone()
+            .or_else(|| std::env::var("AWS_ACCESS_KEY_ID").ok())
+            .ok_or_else(|| anyhow!("No access key configured for bucket {}", config.bucket()))?;
+        let secret_access_key = config
+            .secret_access_key()
+            .clone()
+            .or_else(|| std::env::var("AWS_SECRET_ACCESS_KEY").ok())
+            .ok_or_else(|| anyhow!("No secret key configured for bucket {}", config.bucket()))?;
+
+        Ok(S3Client {
+            http: reqwest::Client::builder()
+                .build()
+                .context("Building HTTP client failed")?,
+            endpoint: config.endpoint().clone(),
+            bucket: config.bucket().clone(),
+            region: config.region().clone(),
+            prefix: config.prefix().trim_matches('/').to_string(),
+            access_key_id,
+            secret_access_key,
+        })
+    }
+
+    /// Get a client for the same bucket that puts its objects below `sub` in this client's prefix
+    pub fn with_subprefix(&self, sub: &str) -> Self {
+        let mut client = self.clone();
+        client.prefix = self.key(sub);
+        client
+    }
+
+    /// The full object key for a key relative to the prefix
+    fn key(&self, relative: &str) -> String {
+        if self.prefix.is_empty() {
+            relative.to_string()
+        } else {
+            format!("{}/{}", self.prefix, relative)
+        }
+    }
+
+    /// List all object keys below the prefix, relative to the prefix
+    pub async fn list(&self) -> Result<Vec<String>> {
+        let list_prefix = if self.prefix.is_empty() {
+            String::new()
+        } else {
+            format!("{}/", self.prefix)
+        };
+
+        let mut keys = Vec::new();
+        let mut continuation_token = None;
+        loop {
+            let mut query = vec![
+                (String::from("list-type"), String::from("2")),
+                (String::from("prefix"), list_prefix.clone()),
+            ];
+            if let Some(token) = continuation_token.take() {
+                query.push((String::from("continuation-token"), token));
+            }
+
+            let body = self
+                .request(reqwest::Method::GET, "", &query)?
+                .send()
+                .await
+                .and_then(reqwest::Response::error_for_status)
+                .with_context(|| anyhow!("Listing objects in bucket {}", self.bucket))?
+                .text()
+                .await?;
+
+            let result = quick_xml::de::from_str::<ListBucketResult>(&body)
+                .with_context(|| anyhow!("Parsing object listing of bucket {}", self.bucket))?;
+
+            keys.extend({
+                result
+                    .contents
+                    .into_iter()
+                    .filter_map(|c| c.key.strip_prefix(&list_prefix).map(String::from))
+                    .filter(|k| !k.is_empty() && !k.ends_with('/'))
+            });
+
+            match result.next_continuation_token {
+                Some(token) if result.is_truncated => continuation_token = Some(token),
+                _ => break,
+            }
+        }
+
+        trace!("Found {} objects in bucket {} below '{}'", keys.len(), self.bucket, self.prefix);
+        Ok(keys)
+    }
+
+    /// Download the object with the (relative) `key` to `dest`
+    pub async fn download(&self, key: &str, dest: &Path) -> Result<()> {
+        trace!("Downloading {} from bucket {} to {}", key, self.bucket, dest.display());
+        let response = self
+            .request(reqwest::Method::GET, key, &[])?
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .with_context(|| anyhow!("Downloading {} from bucket {}", key, self.bucket))?;
+
+        if let Some(parent) = dest.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        let mut file = tokio::io::BufWriter::new({
+            tokio::fs::File::create(dest)
+                .await
+                .with_context(|| anyhow!("Creating {}", dest.display()))?
+        });
+
+        let mut stream = response.bytes_stream();
+        while let Some(bytes) = stream.next().await {
+            file.write_all(bytes?.as_ref()).await?;
+        }
+        file.flush().await.map_err(anyhow::Error::from)
+    }
+
+    /// Upload the file at `src` as the object with the (relative) `key`
+    ///
+    /// The file is streamed, it is not read into memory.
+    pub async fn upload(&self, key: &str, src: &Path) -> Result<()> {
+        trace!("Uploading {} to {} in bucket {}", src.display(), key, self.bucket);
+        let file = tokio::fs::File::open(src)
+            .await
+            .with_context(|| anyhow!("Opening {} for upload", src.display()))?;
+        let len = file
+            .metadata()
+            .await
+            .with_context(|| anyhow!("Reading metadata of {}", src.display()))?
+            .len();
+
+        // S3 does not accept chunked uploads with an unsigned payload, so the length is set
+        self.request(reqwest::Method::PUT, key, &[])?
+            .header(reqwest::header::CONTENT_LENGTH, len)
+            .body(reqwest::Body::from(file))
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .with_context(|| anyhow!("Uploading {} to bucket {}", src.display(), self.bucket))
+            .map(|_| ())
+    }
+
+    /// Build a signed request for the object with the (relative) `key`
+    ///
+    /// An empty key builds a request for the bucket itself. The path of the endpoint is kept, so
+    /// storages behind a reverse proxy with a path prefix work as well.
+    fn request(&self, method: reqwest::Method, key: &str, query: &[(String, String)]) -> Result<reqwest::RequestBuilder> {
+        // The path of the URL is already percent-encoded
+        let endpoint_path = self.endpoint.path().trim_end_matches('/');
+        let canonical_uri = if key.is_empty() {
+            format!("{}/{}", endpoint_path, uri_encode(&self.bucket, false))
+        } else {
+            format!("{}/{}/{}", endpoint_path, uri_encode(&self.bucket, false), uri_encode(&self.key(key), false))
+        };
+
+        let mut query = query
+            .iter()
+            .map(|(k, v)| (uri_encode(k, true), uri_encode(v, true)))
+            .collect::<Vec<_>>();
+        query.sort();
+        let canonical_query = query
+            .iter()
+            .map(|(k, v)| format!("{k}={v}"))
+            .collect::<Vec<_>>()
+            .join("&");
+
+        let host = match (self.endpoint.host_str(), self.endpoint.port()) {
+            (Some(host), Some(port)) => format!("{host}:{port}"),
+            (Some(host), None) => host.to_string(),
+            (None, _) => return Err(anyhow!("No host in S3 endpoint: {}", self.endpoint)),
+        };
+
+        let now = chrono::Utc::now();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let authorization = self.authorization(method.as_str(), &canonical_uri, &canonical_query, &host, &amz_date);
+
+        let url = {
+            let mut url = self.endpoint.clone();
+            url.set_path(&canonical_uri);
+            url.set_query(if canonical_query.is_empty() { None } else { Some(&canonical_query) });
+            url
+        };
+
+        Ok({
+            self.http
+                .request(method, url)
+                .header("x-amz-date", amz_date)
+                .header("x-amz-content-sha256", UNSIGNED_PAYLOAD)
+                .header("authorization", authorization)
+        })
+    }
+
+    /// Compute the value of the authorization header (AWS signature version 4)
+    fn authorization(&self, method: &str, canonical_uri: &str, canonical_query: &str, host: &str, amz_date: &str) -> String {
+        let date = &amz_date[..8];
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+        let signed_headers = "host;x-amz-content-sha256;x-amz-date";
+        let canonical_request = format!(
+            "{method}\n{canonical_uri}\n{canonical_query}\nhost:{host}\nx-amz-content-sha256:{UNSIGNED_PAYLOAD}\nx-amz-date:{amz_date}\n\n{signed_headers}\n{UNSIGNED_PAYLOAD}"
+        );
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{amz_date}\n{scope}\n{:x}",
+            Sha256::digest(canonical_request.as_bytes())
+        );
+
+        let key = signing_key(&self.secret_access_key, date, &self.region, "s3");
+        let signature = to_hex(&hmac_sha256(&key, string_to_sign.as_bytes()));
+        format!(
+            "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={signed_headers}, Signature={signature}",
+            self.access_key_id
+        )
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ListBucketResult {
+    #[serde(default)]
+    contents: Vec<ListBucketContents>,
+
+    #[serde(default)]
+    is_truncated: bool,
+
+    next_continuation_token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ListBucketContents {
+    key: String,
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn signing_key(secret: &str, date: &str, region: &str, service: &str) -> Vec<u8> {
+    let key = hmac_sha256(format!("AWS4{secret}").as_bytes(), date.as_bytes());
+    let key = hmac_sha256(&key, region.as_bytes());
+    let key = hmac_sha256(&key, service.as_bytes());
+    hmac_sha256(&key, b"aws4_request")
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// URI-encode a string as required for the canonical request
+///
+/// The slash is only encoded in query parameters, not in object keys.
+fn uri_encode(s: &str, encode_slash: bool) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+            b'/' if !encode_slash => String::from("/"),
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hmac_sha256() {
+        // RFC 4231, test case 2
+        let mac = hmac_sha256(b"Jefe", b"what do ya want for nothing?");
+        assert_eq!(to_hex(&mac), "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843");
+    }
+
+    #[test]
+    fn test_signing_key() {
+        // Example from the AWS signature version 4 documentation
+        let key = signing_key("wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY", "20120215", "us-east-1", "iam");
+        assert_eq!(to_hex(&key), "f4780e2d9f65fa895f9c67b32ce1baf0b0d8a43505a000a1a9e090d414db404d");
+    }
+
+    #[test]
+    fn test_uri_encode() {
+        assert_eq!(uri_encode("releases/foo 1.0.tar.gz", false), "releases/foo%201.0.tar.gz");
+        assert_eq!(uri_encode("releases/", true), "releases%2F");
+    }
+
+    #[test]
+    fn test_request_keeps_endpoint_path() {
+        let client = S3Client {
+            http: reqwest::Client::new(),
+            endpoint: Url::parse("https://s3.example.com/storage/").unwrap(),
+            bucket: String::from("butido"),
+            region: String::from("us-east-1"),
+            prefix: String::from("releases"),
+            access_key_id: String::from("key"),
+            secret_access_key: String::from("secret"),
+        };
+
+        let request = client.request(reqwest::Method::GET, "a.tar", &[]).unwrap().build().unwrap();
+        assert_eq!(request.url().as_str(), "https://s3.example.com/storage/butido/releases/a.tar");
+    }
+
+    #[test]
+    fn test_parse_list_bucket_result() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <ListBucketResult xmlns="http://s3.amazonaws.com/doc/2006-03-01/">
+                <Name>butido</Name>
+                <Prefix>releases/</Prefix>
+                <KeyCount>2</KeyCount>
+                <IsTruncated>true</IsTruncated>
+                <NextContinuationToken>abc</NextContinuationToken>
+                <Contents><Key>releases/a.tar</Key><Size>1</Size></Contents>
+                <Contents><Key>releases/b/c.tar</Key><Size>2</Size></Contents>
+            </ListBucketResult>"#;
+
+        let result = quick_xml::de::from_str::<ListBucketResult>(xml).unwrap();
+        assert!(result.is_truncated);
+        assert_eq!(result.next_continuation_token.as_deref(), Some("abc"));
+        assert_eq!(result.contents.len(), 2);
+        assert_eq!(result.contents[1].key, "releases/b/c.tar");
+    }
+}
@@ -0,0 +1,181 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Detached GPG signatures of released artifacts
+
+use std::ffi::OsString;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use tracing::trace;
+use tracing::warn;
+
+use crate::config::ReleaseSigningConfig;
+use crate::filestore::path::ArtifactPath;
+use crate::filestore::ReleaseStore;
+
+/// The extension of the detached signature files
+const SIGNATURE_EXTENSION: &str = "asc";
+
+#[derive(Clone, Debug)]
+pub struct ReleaseSigner(ReleaseSigningConfig);
+
+impl ReleaseSigner {
+    pub fn from_config(config: Option<&ReleaseSigningConfig>) -> Option<Self> {
+        config.cloned().map(ReleaseSigner)
+    }
+
+    /// The path of the detached signature of the artifact at `p`
+    pub fn signature_path_of(p: &Path) -> PathBuf {
+        let mut s = OsString::from(p.as_os_str());
+        s.push(".");
+        s.push(SIGNATURE_EXTENSION);
+        PathBuf::from(s)
+    }
+
+    /// Create a detached signature for the file at `p` and return the path of the signature
+    pub async fn sign(&self, p: &Path) -> Result<PathBuf> {
+        let signature = Self::signature_path_of(p);
+        trace!("Signing {} with key {}", p.display(), self.0.key());
+        let output = tokio::process::Command::new(self.0.gpg())
+            .arg("--batch")
+            .arg("--yes")
+            .arg("--armor")
+            .arg("--local-user")
+            .arg(self.0.key())
+            .arg("--output")
+            .arg(&signature)
+            .arg("--detach-sign")
+            .arg(p)
+            .output()
+            .await
+            .with_context(|| anyhow!("Running {}", self.0.gpg().display()))?;
+
+        if output.status.success() {
+            Ok(signature)
+        } else {
+            Err(anyhow!(
+                "Signing {} failed ({}): {}",
+                p.display(),
+                output.status,
+                String::from_utf8_lossy(&output.stderr)
+            ))
+        }
+    }
+
+    /// Verify that the detached signature of the file at `p` is a valid signature of the
+    /// configured key
+    ///
+    /// The exit status of gpg alone is not sufficient, it also succeeds for valid signatures of
+    /// any other key in the keyring.
+    pub async fn verify(&self, p: &Path) -> Result<()> {
+        let signature = Self::signature_path_of(p);
+        trace!("Verifying {} with {}", p.display(), signature.display());
+        let output = tokio::process::Command::new(self.0.gpg())
+            .arg("--batch")
+            .arg("--status-fd")
+            .arg("1")
+            .arg("--verify")
+            .arg(&signature)
+            .arg(p)
+            .output()
+            .await
+            .with_context(|| anyhow!("Running {}", self.0.gpg().display()))?;
+
+        if !output.status.success() {
+            return Err(anyhow!(
+                "Signature verification of {} failed ({}): {}",
+                p.display(),
+                output.status,
+                String::from_utf8_lossy(&output.stderr)
+            ))
+        }
+
+        if is_valid_signature_of(&String::from_utf8_lossy(&output.stdout), self.0.fingerprint()) {
+            Ok(())
+        } else {
+            Err(anyhow!(
+                "{} is not signed by the key with the fingerprint {}",
+                p.display(),
+                self.0.fingerprint()
+            ))
+        }
+    }
+
+    /// Verify the signature of an artifact in a release store, fetching both if necessary
+    ///
+    /// Artifacts without a signature were released before signing was configured, they are
+    /// accepted with a warning.
+    pub async fn verify_in_store(&self, store: &ReleaseStore, ap: &ArtifactPath) -> Result<()> {
+        let signature = ArtifactPath::new(Self::signature_path_of(ap.as_ref()))?;
+        if store.fetch(&signature).await?.is_none() {
+            warn!("No signature for released artifact {}, it was probably released before signing was configured", ap.display());
+            return Ok(())
+        }
+
+        let full_path = store
+            .fetch(ap)
+            .await?
+            .ok_or_else(|| anyhow!("Not found in release store: {}", ap.display()))?
+            .joined();
+
+        self.verify(&full_path).await
+    }
+}
+
+/// Whether the gpg status output `status` contains a valid signature of the key with the
+/// fingerprint `fingerprint`
+///
+/// The fingerprint is compared with the fingerprint of the signing (sub)key and of the primary
+/// key.
+fn is_valid_signature_of(status: &str, fingerprint: &str) -> bool {
+    let fingerprint = fingerprint.replace(' ', "").to_uppercase();
+    status
+        .lines()
+        .filter_map(|line| line.strip_prefix("[GNUPG:] VALIDSIG "))
+        .any(|validsig| {
+            let fields = validsig.split_whitespace().collect::<Vec<_>>();
+            let signing_key = fields.first();
+            let primary_key = fields.last().filter(|_| fields.len() >= 10);
+            signing_key.into_iter().chain(primary_key).any(|fpr| fpr.to_uppercase() == fingerprint)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signature_path_of() {
+        assert_eq!(
+            ReleaseSigner::signature_path_of(Path::new("/releases/foo-1.0.tar.gz")),
+            PathBuf::from("/releases/foo-1.0.tar.gz.asc")
+        );
+    }
+
+    #[test]
+    fn test_is_valid_signature_of() {
+        let status = indoc::indoc!(r#"
+            [GNUPG:] NEWSIG
+            [GNUPG:] GOODSIG 89ABCDEF01234567 Releases <releases@example.com>
+            [GNUPG:] VALIDSIG 1111111111111111111111111111111111111111 2022-01-01 1640995200 0 4 0 1 10 00 0123456789ABCDEF0123456789ABCDEF01234567
+            [GNUPG:] TRUST_ULTIMATE 0 pgp
+        "#);
+
+        assert!(is_valid_signature_of(status, "0123456789ABCDEF0123456789ABCDEF01234567"));
+        assert!(is_valid_signature_of(status, "0123 4567 89ab cdef 0123  4567 89AB CDEF 0123 4567"));
+        assert!(is_valid_signature_of(status, "1111111111111111111111111111111111111111"));
+        assert!(!is_valid_signature_of(status, "FEDCBA9876543210FEDCBA9876543210FEDCBA98"));
+        assert!(!is_valid_signature_of("[GNUPG:] BADSIG 89ABCDEF01234567 Releases", "89ABCDEF01234567"));
+    }
+}
@@ -8,80 +8,405 @@
 // SPDX-License-Identifier: EPL-2.0
 //
 
+use std::collections::HashMap;
 use std::fmt::Debug;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
 
 use anyhow::Context;
-use anyhow::Error;
 use anyhow::Result;
 use anyhow::anyhow;
-use futures::stream::Stream;
 use indicatif::ProgressBar;
+use tokio::sync::OwnedMutexGuard;
+use tokio::sync::RwLock;
 use tracing::trace;
 use result_inspect::ResultInspect;
 
+use crate::filestore::ArtifactKindRules;
+use crate::filestore::Compression;
+use crate::filestore::CompressionRules;
+use crate::filestore::ContentStore;
+use crate::filestore::StoreBackend;
 use crate::filestore::path::ArtifactPath;
+use crate::filestore::path::FullArtifactPath;
 use crate::filestore::path::StoreRoot;
+use crate::filestore::path::UnpackLimits;
 use crate::filestore::util::FileStoreImpl;
+use crate::util::docker::is_output_category;
+use crate::util::docker::ImageName;
 
-pub struct StagingStore(pub(in crate::filestore) FileStoreImpl);
+pub struct StagingStore {
+    pub(in crate::filestore) store: FileStoreImpl,
 
-impl Debug for StagingStore {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::result::Result<(), std::fmt::Error> {
-        write!(f, "StagingStore(root: {})", self.0.root_path().display())
+    /// The rules by which artifacts are compressed when they are written to the store
+    compression_rules: CompressionRules,
+
+    /// The compression of the artifacts that were compressed when they were written
+    compressions: HashMap<ArtifactPath, Compression>,
+
+    /// The store the artifacts are deduplicated in, if any
+    content_store: Option<ContentStore>,
+
+    unpack_limits: UnpackLimits,
+
+    /// The output category of the artifacts that were not written from the default outputs
+    categories: HashMap<ArtifactPath, String>,
+
+    kind_rules: ArtifactKindRules,
+    path_locks: PathLocks,
+
+    /// The directories the artifacts of the images are written to, if the store holds the
+    /// artifacts of multiple images
+    image_directories: HashMap<ImageName, PathBuf>,
+}
+
+/// Locks for the paths in a store
+///
+/// Archives are unpacked into the store while holding the locks for the paths of their files, so
+/// jobs that write the same paths do not interfere, while jobs that write different paths do not
+/// wait for each other.
+#[derive(Clone, Debug, Default)]
+pub struct PathLocks(Arc<std::sync::Mutex<HashMap<PathBuf, Arc<tokio::sync::Mutex<()>>>>>);
+
+impl PathLocks {
+    /// Lock all `paths`, the locks are held until the returned guards are dropped
+    pub async fn lock(&self, mut paths: Vec<PathBuf>) -> Result<PathGuards> {
+        // Always locking in the same order prevents deadlocks between callers with overlapping
+        // paths
+        paths.sort();
+        paths.dedup();
+
+        let locks = {
+            let mut map = self.0.lock().map_err(|_| anyhow!("Lock poisoned"))?;
+            paths
+                .iter()
+                .map(|path| map.entry(path.clone()).or_default().clone())
+                .collect::<Vec<_>>()
+        };
+
+        let mut guards = Vec::with_capacity(locks.len());
+        for lock in locks {
+            guards.push(lock.lock_owned().await);
+        }
+        Ok(PathGuards { locks: self.clone(), paths, guards })
     }
 }
 
-impl StagingStore {
-    pub fn load(root: StoreRoot, progress: &ProgressBar) -> Result<Self> {
-        FileStoreImpl::load(root, progress).map(StagingStore)
+/// The locks of some paths in a store, held until this is dropped
+///
+/// The locks of the paths nobody else holds or waits for are removed when this is dropped, so the
+/// locks do not pile up.
+pub struct PathGuards {
+    locks: PathLocks,
+    paths: Vec<PathBuf>,
+    guards: Vec<OwnedMutexGuard<()>>,
+}
+
+impl Drop for PathGuards {
+    fn drop(&mut self) {
+        self.guards.clear();
+
+        // Everybody who holds or waits for a lock holds a reference to it, so a lock that is only
+        // referenced by the map is unused
+        if let Ok(mut map) = self.locks.0.lock() {
+            for path in self.paths.iter() {
+                if map.get(path).map(|lock| Arc::strong_count(lock) == 1).unwrap_or(false) {
+                    map.remove(path);
+                }
+            }
+        }
     }
+}
 
-    /// Write the passed tar stream to the file store
-    ///
-    /// # Returns
+/// The settings of a staging store that are needed to unpack archives into it
+///
+/// Unpacking is blocking IO, so it runs on a blocking thread with a copy of these settings.
+#[derive(Clone, Debug)]
+struct Unpacker {
+    root: StoreRoot,
+    compression_rules: CompressionRules,
+    content_store: Option<ContentStore>,
+    unpack_limits: UnpackLimits,
+}
+
+/// Files that were unpacked into a staging store but are not yet known to it
+///
+/// See `Unpacker::unpack_files_from_tar()`.
+#[derive(Debug)]
+pub struct UnpackedFiles {
+    files: Vec<(ArtifactPath, Option<Compression>)>,
+    category: Option<String>,
+}
+
+impl Unpacker {
+    /// Unpack the files from the passed tar archive into the file store, without adding them to
+    /// the store
     ///
-    /// Returns a list of Artifacts that were written from the stream
-    pub async fn write_files_from_tar_stream<S>(&mut self, stream: S) -> Result<Vec<ArtifactPath>>
-    where
-        S: Stream<Item = Result<Vec<u8>>>,
-    {
-        use futures::stream::TryStreamExt;
-
-        let dest = self.0.root_path();
-        stream
-            .try_concat()
-            .await
-            .and_then(|bytes| {
-                trace!("Unpacking archive to {}", dest.display());
-                dest.unpack_archive_here(tar::Archive::new(&bytes[..]))
-                    .context("Unpacking TAR")
-                    .map_err(Error::from)
-            })
-            .context("Concatenating the output bytestream")?
+    /// The caller has to hold the locks for the paths of the files in the archive.
+    fn unpack_files_from_tar(&self, bytes: &[u8], subdir: &Path, category: Option<String>, bar: &ProgressBar) -> Result<UnpackedFiles> {
+        let dest = &self.root;
+        trace!("Unpacking archive to {}", dest.display());
+        let files = dest.unpack_archive_here(tar::Archive::new(bytes), subdir, &self.unpack_limits, bar)
+            .context("Unpacking TAR")?
             .into_iter()
             .inspect(|p| trace!("Trying to load into staging store: {}", p.display()))
             .filter_map(|path| {
-                if self.0.root_path().is_dir(&path) {
+                if self.root.is_dir(&path) {
                     None
                 } else {
+                    let (path, compression) = match self.compression_rules.for_path(&path) {
+                        Some((compression, level)) => {
+                            match self.root.compress_file_here(&path, compression, level) {
+                                Ok(p) => (p, Some(compression)),
+                                Err(e) => return Some(Err(e.context(anyhow!("Compressing {}", path.display())))),
+                            }
+                        }
+                        None => (path, None),
+                    };
+
+                    if let Some(content_store) = self.content_store.as_ref() {
+                        let full_path = self.root.as_path().join(&path);
+                        match content_store.deduplicate(&full_path) {
+                            Ok(dedup) => trace!("Deduplicated {}: {:?}", path.display(), dedup),
+                            Err(e) => return Some(Err(e.context(anyhow!("Deduplicating {}", path.display())))),
+                        }
+                    }
+
                     // Clippy doesn't detect this properly
                     #[allow(clippy::redundant_clone)]
                     ArtifactPath::new(path.to_path_buf())
                         .inspect(|r| trace!("Loaded from path {} = {:?}", path.display(), r))
                         .with_context(|| anyhow!("Loading from path: {}", path.display()))
-                        .map(|ap| self.0.load_from_path(&ap).clone())
+                        .map(|ap| (ap, compression))
                         .map(Some)
                         .transpose()
                 }
             })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(UnpackedFiles {
+            files,
+            category,
+        })
+    }
+}
+
+impl Debug for StagingStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::result::Result<(), std::fmt::Error> {
+        write!(f, "StagingStore(root: {})", self.store.root_path().display())
+    }
+}
+
+impl StagingStore {
+    pub fn load(root: StoreRoot, progress: &ProgressBar) -> Result<Self> {
+        FileStoreImpl::load(root, progress).map(|store| StagingStore {
+            store,
+            compression_rules: CompressionRules::default(),
+            compressions: HashMap::new(),
+            content_store: None,
+            unpack_limits: UnpackLimits::default(),
+            categories: HashMap::new(),
+            kind_rules: ArtifactKindRules::default(),
+            path_locks: PathLocks::default(),
+            image_directories: HashMap::new(),
+        })
+    }
+
+    /// Compress artifacts that match one of the `rules` when writing them to the store
+    pub fn with_compression_rules(self, compression_rules: CompressionRules) -> Self {
+        StagingStore { compression_rules, ..self }
+    }
+
+    /// Deduplicate artifacts in `content_store` when writing them to the store
+    pub fn with_content_store(self, content_store: Option<ContentStore>) -> Self {
+        StagingStore { content_store, ..self }
+    }
+
+    /// Reject archives that exceed `limits` when writing them to the store
+    pub fn with_unpack_limits(self, unpack_limits: UnpackLimits) -> Self {
+        StagingStore { unpack_limits, ..self }
+    }
+
+    /// The limits for the archives that are written to the store
+    pub fn unpack_limits(&self) -> UnpackLimits {
+        self.unpack_limits
+    }
+
+    /// Classify the artifacts that are written to the store with `rules`
+    pub fn with_kind_rules(self, kind_rules: ArtifactKindRules) -> Self {
+        StagingStore { kind_rules, ..self }
+    }
+
+    /// Write the artifacts of the images to the directories in `image_directories`
+    ///
+    /// This is used if a submit builds for multiple images, so that the artifacts of the same
+    /// package built on different images do not overwrite each other.
+    pub fn with_image_directories(self, image_directories: HashMap<ImageName, PathBuf>) -> Self {
+        StagingStore { image_directories, ..self }
+    }
+
+    /// Use `backend` for this store, the artifacts that exist in the backend become available
+    ///
+    /// Artifacts that are written to the store are pushed to the backend.
+    pub async fn with_backend(mut self, backend: StoreBackend) -> Result<Self> {
+        self.store.attach_backend(backend).await?;
+        Ok(self)
+    }
+
+    /// Get the full path of an artifact, fetching it from the backend if it does not exist locally
+    pub async fn fetch<'a>(&'a self, p: &'a ArtifactPath) -> Result<Option<FullArtifactPath<'a>>> {
+        self.store.fetch(p).await
+    }
+
+    /// Write the files from the passed tar archive to the file store
+    ///
+    /// If a `category` is passed, the files are written to a directory of that name and the
+    /// artifacts are classified with it. If the store has a directory for `image`, the files are
+    /// written below that directory.
+    ///
+    /// The store is only locked for writing to register the new artifacts: the archive is
+    /// unpacked on a blocking thread while holding the locks for the paths of its files, so many
+    /// archives can be unpacked concurrently.
+    ///
+    /// # Returns
+    ///
+    /// Returns a list of Artifacts that were written from the archive
+    pub async fn write_files_from_tar(
+        store: &RwLock<StagingStore>,
+        bytes: Arc<Vec<u8>>,
+        image: &ImageName,
+        category: Option<&str>,
+        bar: &ProgressBar,
+    ) -> Result<Vec<ArtifactPath>> {
+        if let Some(category) = category.filter(|c| !is_output_category(c)) {
+            return Err(anyhow!("Invalid output category: {}", category))
+        }
+
+        let (path_locks, unpacker, subdir) = {
+            let store = store.read().await;
+            (store.path_locks.clone(), store.unpacker(), store.subdir_for(image, category))
+        };
+
+        let paths = crate::filestore::path::archive_entry_paths(tar::Archive::new(bytes.as_slice()), &subdir)
+            .context("Reading TAR")?;
+        let _guards = path_locks.lock(paths).await?;
+
+        let category = category.map(String::from);
+        let bar = bar.clone();
+        let unpacked = tokio::task::spawn_blocking(move || unpacker.unpack_files_from_tar(&bytes, &subdir, category, &bar))
+            .await
+            .context("Unpacking TAR")??;
+        let artifacts = store.write().await.register_files(unpacked);
+
+        // The artifacts are pushed without holding the lock, uploading them can take a while
+        let (backend, root) = {
+            let store = store.read().await;
+            (store.store.backend().clone(), store.store.root_path().clone())
+        };
+        for artifact in artifacts.iter() {
+            backend
+                .push(root.as_path(), artifact)
+                .await
+                .with_context(|| anyhow!("Pushing {} from store {}", artifact.display(), root.display()))?;
+        }
+        Ok(artifacts)
+    }
+
+    /// The directory the files of the outputs of a job on `image` are written to, relative to
+    /// the root of the store
+    pub fn subdir_for(&self, image: &ImageName, category: Option<&str>) -> PathBuf {
+        self.image_directories
+            .get(image)
+            .cloned()
+            .unwrap_or_default()
+            .join(category.unwrap_or_default())
+    }
+
+    fn unpacker(&self) -> Unpacker {
+        Unpacker {
+            root: self.store.root_path().clone(),
+            compression_rules: self.compression_rules.clone(),
+            content_store: self.content_store.clone(),
+            unpack_limits: self.unpack_limits,
+        }
+    }
+
+    /// Add files that were unpacked into the store to the store
+    fn register_files(&mut self, unpacked: UnpackedFiles) -> Vec<ArtifactPath> {
+        unpacked.files
+            .into_iter()
+            .map(|(ap, compression)| {
+                if let Some(compression) = compression {
+                    self.compressions.insert(ap.clone(), compression);
+                }
+                if let Some(category) = unpacked.category.as_ref() {
+                    self.categories.insert(ap.clone(), category.clone());
+                }
+                self.store.load_from_path(&ap).clone()
+            })
             .collect()
     }
 
     pub fn root_path(&self) -> &StoreRoot {
-        self.0.root_path()
+        self.store.root_path()
+    }
+
+    /// Copy the file at `source` into the store as `p`, see `FileStoreImpl::import_file()`
+    pub async fn import_file(&mut self, source: &Path, p: &ArtifactPath) -> Result<()> {
+        self.store.import_file(source, p).await
+    }
+
+    /// Remove the artifact `p` from the local directory of the store
+    pub async fn remove_file(&mut self, p: &ArtifactPath) -> Result<()> {
+        self.store.remove_file(p).await
+    }
+
+    /// Push the artifact `p` to the backend of the store
+    pub async fn push(&self, p: &ArtifactPath) -> Result<()> {
+        self.store.push(p).await
     }
 
     pub fn get(&self, p: &ArtifactPath) -> Option<&ArtifactPath> {
-        self.0.get(p)
+        self.store.get(p)
+    }
+
+    /// Get the compression an artifact was written with, if it was compressed by this store
+    pub fn compression_of(&self, p: &ArtifactPath) -> Option<Compression> {
+        self.compressions.get(p).copied()
+    }
+
+    /// Get the output category of an artifact, if it was not written from the default outputs
+    pub fn category_of(&self, p: &ArtifactPath) -> Option<&str> {
+        self.categories.get(p).map(String::as_str)
+    }
+
+    /// Get the kind of an artifact, by the kind rules of this store and its output category
+    pub fn kind_of(&self, p: &ArtifactPath) -> &str {
+        self.kind_rules.kind_of(p.as_ref(), self.category_of(p))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_path_locks() {
+        let locks = PathLocks::default();
+        let guards = locks.lock(vec![PathBuf::from("b"), PathBuf::from("a"), PathBuf::from("a")]).await.unwrap();
+        assert_eq!(locks.0.lock().unwrap().len(), 2);
+
+        // Other paths can be locked while "a" and "b" are locked
+        assert!(locks.lock(vec![PathBuf::from("c")]).await.is_ok());
+
+        let overlapping = locks.lock(vec![PathBuf::from("a"), PathBuf::from("c")]);
+        assert!(tokio::time::timeout(std::time::Duration::from_millis(50), overlapping).await.is_err());
+
+        drop(guards);
+        assert!(locks.lock(vec![PathBuf::from("a"), PathBuf::from("c")]).await.is_ok());
+
+        // The locks are removed when nobody holds them anymore
+        assert!(locks.0.lock().unwrap().is_empty());
     }
 }
@@ -12,12 +12,17 @@
 //!
 
 use std::collections::HashSet;
+use std::path::Path;
 
+use anyhow::anyhow;
+use anyhow::Context;
 use anyhow::Result;
 use indicatif::ProgressBar;
 use tracing::trace;
 
+use crate::filestore::StoreBackend;
 use crate::filestore::path::ArtifactPath;
+use crate::filestore::path::FullArtifactPath;
 use crate::filestore::path::StoreRoot;
 
 /// The actual filestore implementation
@@ -31,6 +36,7 @@ pub struct FileStoreImpl {
     #[getset(get = "pub")]
     root_path: StoreRoot,
     store: HashSet<ArtifactPath>,
+    backend: StoreBackend,
 }
 
 impl FileStoreImpl {
@@ -44,7 +50,83 @@ impl FileStoreImpl {
             })
             .collect::<Result<HashSet<ArtifactPath>>>()?;
 
-        Ok(FileStoreImpl { root_path, store })
+        Ok(FileStoreImpl { root_path, store, backend: StoreBackend::Local })
+    }
+
+    /// Use `backend` for this store, the artifacts that exist in the backend become available
+    pub(in crate::filestore) async fn attach_backend(&mut self, backend: StoreBackend) -> Result<()> {
+        let remote = backend
+            .list()
+            .await
+            .with_context(|| anyhow!("Listing artifacts of store {}", self.root_path.display()))?;
+        trace!("Found {} artifacts in backend of store {}", remote.len(), self.root_path.display());
+
+        self.store.extend(remote);
+        self.backend = backend;
+        Ok(())
+    }
+
+    /// Get the full path of an artifact, fetching it from the backend if it does not exist locally
+    pub(in crate::filestore) async fn fetch<'a>(&'a self, artifact_path: &'a ArtifactPath) -> Result<Option<FullArtifactPath<'a>>> {
+        if let Some(full_path) = self.root_path.join(artifact_path)? {
+            return Ok(Some(full_path));
+        }
+
+        if !self.store.contains(artifact_path) {
+            return Ok(None);
+        }
+
+        trace!("Fetching {} into store {}", artifact_path.display(), self.root_path.display());
+        self.backend
+            .fetch(self.root_path.as_path(), artifact_path)
+            .await
+            .with_context(|| anyhow!("Fetching {} into store {}", artifact_path.display(), self.root_path.display()))?;
+        self.root_path.join(artifact_path)
+    }
+
+    /// The backend artifacts that are written to the local directory are pushed to
+    pub(in crate::filestore) fn backend(&self) -> &StoreBackend {
+        &self.backend
+    }
+
+    /// Push an artifact that exists locally to the backend
+    pub(in crate::filestore) async fn push(&self, artifact_path: &ArtifactPath) -> Result<()> {
+        self.backend
+            .push(self.root_path.as_path(), artifact_path)
+            .await
+            .with_context(|| anyhow!("Pushing {} from store {}", artifact_path.display(), self.root_path.display()))
+    }
+
+    /// Copy the file at `source` into the store as `artifact_path`
+    ///
+    /// Fails if the store already has an artifact at that path, locally or in its backend. The
+    /// artifact is not pushed to the backend.
+    pub(in crate::filestore) async fn import_file(&mut self, source: &Path, artifact_path: &ArtifactPath) -> Result<()> {
+        let dest = self.root_path.as_path().join(artifact_path);
+        if self.store.contains(artifact_path) || dest.exists() {
+            return Err(anyhow!("Does already exist: {}", dest.display()))
+        }
+
+        if let Some(parent) = dest.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .with_context(|| anyhow!("Creating directory {}", parent.display()))?;
+        }
+        tokio::fs::copy(source, &dest)
+            .await
+            .with_context(|| anyhow!("Copying {} to {}", source.display(), dest.display()))?;
+        self.load_from_path(artifact_path);
+        Ok(())
+    }
+
+    /// Remove an artifact from the local directory of the store
+    pub(in crate::filestore) async fn remove_file(&mut self, artifact_path: &ArtifactPath) -> Result<()> {
+        let dest = self.root_path.as_path().join(artifact_path);
+        tokio::fs::remove_file(&dest)
+            .await
+            .with_context(|| anyhow!("Removing {}", dest.display()))?;
+        self.store.remove(artifact_path);
+        Ok(())
     }
 
     pub fn get(&self, artifact_path: &ArtifactPath) -> Option<&ArtifactPath> {
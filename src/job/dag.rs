@@ -8,14 +8,22 @@
 // SPDX-License-Identifier: EPL-2.0
 //
 
+use std::collections::HashMap;
+
+use anyhow::anyhow;
+use anyhow::Result;
 use daggy::Dag as DaggyDag;
 use daggy::Walker;
 use getset::Getters;
+use itertools::Itertools;
 use uuid::Uuid;
 
 use crate::job::Job;
 use crate::job::JobResource;
 use crate::package::Package;
+use crate::package::PackageName;
+use crate::package::PackageVersion;
+use crate::package::stable_build_order;
 use crate::package::PhaseName;
 use crate::package::Shebang;
 use crate::util::docker::ImageName;
@@ -34,25 +42,78 @@ impl Dag {
         phases: Vec<PhaseName>,
         resources: Vec<JobResource>,
     ) -> Self {
-        let build_job = |_, p: &Package| {
+        let build_job = |idx, p: &Package| {
+            let mut dependency_versions = dag.dag()
+                .children(idx)
+                .iter(dag.dag())
+                .filter_map(|(_, child)| dag.dag().node_weight(child))
+                .map(|dep| (dep.name().clone(), dep.version().clone()))
+                .collect::<Vec<_>>();
+            dependency_versions.sort();
+            dependency_versions.dedup();
+
             Job::new(
                 p.clone(),
                 script_shebang.clone(),
                 image.clone(),
                 phases.clone(),
-                resources.clone(),
+                resources
+                    .iter()
+                    .filter(|r| r.env().map(|(name, _)| p.accepts_env(name)).unwrap_or(true))
+                    .cloned()
+                    .collect(),
+                dependency_versions,
             )
         };
 
         Dag {
-            dag: dag.dag().map(build_job, |_, e| *e),
+            dag: dag.dag().map(build_job, |_, _| 0),
         }
     }
 
+    /// Add the jobs of `other` to this DAG
+    ///
+    /// This is used to build for multiple images in one submit, the jobs of each image form a
+    /// separate tree.
+    pub fn extend(&mut self, other: Dag) -> Result<()> {
+        let (nodes, edges) = other.dag.into_graph().into_nodes_edges();
+        let indices = nodes
+            .into_iter()
+            .map(|node| self.dag.add_node(node.weight))
+            .collect::<Vec<_>>();
+
+        for edge in edges {
+            self.dag
+                .add_edge(indices[edge.source().index()], indices[edge.target().index()], edge.weight)
+                .map_err(|_| anyhow!("BUG: Adding the jobs of another DAG created a cycle"))?;
+        }
+        Ok(())
+    }
+
+    /// Use the UUIDs from `uuids` for the jobs of the packages (by image, name and version)
+    ///
+    /// This is used to continue a submit with its recorded job DAG. Fails if a job is not in
+    /// `uuids`.
+    pub fn set_job_uuids(&mut self, uuids: &HashMap<(ImageName, PackageName, PackageVersion), Uuid>) -> Result<()> {
+        for job in self.dag.node_weights_mut() {
+            let key = (job.image().clone(), job.package().name().clone(), job.package().version().clone());
+            let uuid = uuids
+                .get(&key)
+                .ok_or_else(|| anyhow!("Package {} {} for image {} is not in the recorded job DAG", key.1, key.2, key.0))?;
+            job.set_uuid(*uuid);
+        }
+        Ok(())
+    }
+
+    /// Iterate over the jobs in the order they are built in, dependencies first
+    ///
+    /// Jobs that do not depend on each other are ordered by name and version of their package, so
+    /// the order is the same for the same repository.
     pub fn iter(&'_ self) -> impl Iterator<Item = JobDefinition> + '_ {
-        self.dag
-            .graph()
-            .node_indices()
+        let key = |job: &Job| (job.package().name().clone(), job.package().version().clone());
+
+        stable_build_order(&self.dag, key)
+            .into_iter()
             .map(move |idx| {
                 let job = self.dag.graph().node_weight(idx).unwrap(); // TODO
                 let children = self.dag.children(idx);
@@ -60,6 +121,7 @@ impl Dag {
                     .filter_map(|(_, node_idx)| {
                         self.dag.graph().node_weight(node_idx)
                     })
+                    .sorted_by_key(|job| key(job))
                     .map(Job::uuid)
                     .cloned()
                     .collect();
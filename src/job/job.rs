@@ -13,6 +13,8 @@ use uuid::Uuid;
 
 use crate::job::JobResource;
 use crate::package::Package;
+use crate::package::PackageName;
+use crate::package::PackageVersion;
 use crate::package::PhaseName;
 use crate::package::Shebang;
 use crate::util::docker::ImageName;
@@ -38,6 +40,10 @@ pub struct Job {
 
     #[getset(get = "pub")]
     resources: Vec<JobResource>,
+
+    /// The versions of the packages this package directly depends on
+    #[getset(get = "pub")]
+    dependency_versions: Vec<(PackageName, PackageVersion)>,
 }
 
 impl Job {
@@ -47,6 +53,7 @@ impl Job {
         image: ImageName,
         phases: Vec<PhaseName>,
         resources: Vec<JobResource>,
+        dependency_versions: Vec<(PackageName, PackageVersion)>,
     ) -> Self {
         let uuid = Uuid::new_v4();
 
@@ -57,6 +64,12 @@ impl Job {
             script_shebang,
             script_phases: phases,
             resources,
+            dependency_versions,
         }
     }
+
+    /// Replace the UUID of the job, e.g. with the UUID of the job in a recorded job DAG
+    pub(crate) fn set_uuid(&mut self, uuid: Uuid) {
+        self.uuid = uuid;
+    }
 }
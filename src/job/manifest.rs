@@ -0,0 +1,216 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! The manifest of all inputs of a job
+//!
+//! The manifest is serialized deterministically, so its digest can be used to address it in a
+//! content-addressed store and to compare the inputs of jobs.
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Error;
+use anyhow::Result;
+use getset::Getters;
+use serde::Deserialize;
+use serde::Serialize;
+use sha2::Digest;
+use tracing::trace;
+
+use crate::filestore::ArtifactPath;
+use crate::job::RunnableJob;
+
+#[derive(Clone, Debug, Serialize, Deserialize, Getters, PartialEq, Eq)]
+pub struct InputManifest {
+    #[getset(get = "pub")]
+    package_name: String,
+
+    #[getset(get = "pub")]
+    package_version: String,
+
+    /// The declared hashes of the sources, by source name ("<hashtype>:<hash>")
+    #[getset(get = "pub")]
+    sources: BTreeMap<String, String>,
+
+    /// The SHA256 of the dependency artifacts, by artifact path
+    #[getset(get = "pub")]
+    dependencies: BTreeMap<String, String>,
+
+    /// The SHA256 of the patches, by patch path
+    #[serde(default)]
+    #[getset(get = "pub")]
+    patches: BTreeMap<String, String>,
+
+    /// The SHA256 of the rendered script
+    #[getset(get = "pub")]
+    script: String,
+
+    /// The SHA256 of the files the phase scripts were read from, by path
+    #[serde(default)]
+    #[getset(get = "pub")]
+    script_files: BTreeMap<String, String>,
+
+    #[getset(get = "pub")]
+    image: String,
+
+    /// The ID of the image on the endpoint the job ran on
+    #[getset(get = "pub")]
+    image_digest: Option<String>,
+
+    /// The SHA256 of the environment of the job
+    #[getset(get = "pub")]
+    environment: String,
+}
+
+impl InputManifest {
+    pub fn for_job(
+        job: &RunnableJob,
+        artifact_hashes: &HashMap<ArtifactPath, String>,
+        patch_hashes: &HashMap<PathBuf, String>,
+        image_digest: Option<String>,
+    ) -> Self {
+        InputManifest {
+            package_name: job.package().name().to_string(),
+            package_version: job.package().version().to_string(),
+            sources: job
+                .package()
+                .sources()
+                .iter()
+                .map(|(name, source)| (name.clone(), source.hash().to_string()))
+                .collect(),
+            dependencies: artifact_hashes
+                .iter()
+                .map(|(path, hash)| (path.display().to_string(), hash.clone()))
+                .collect(),
+            patches: patch_hashes
+                .iter()
+                .map(|(path, hash)| (path.display().to_string(), hash.clone()))
+                .collect(),
+            script: sha256_hex(job.script().as_ref().as_bytes()),
+            script_files: job
+                .script_files()
+                .iter()
+                .map(|(path, hash)| (path.display().to_string(), hash.clone()))
+                .collect(),
+            image: job.image().as_ref().to_string(),
+            image_digest,
+            environment: hash_environment(job.environment().map(|(k, v)| (k.as_ref(), v.as_str()))),
+        }
+    }
+
+    /// Serialize the manifest
+    ///
+    /// The serialization is deterministic, because all maps in the manifest are ordered.
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string(self).map_err(Error::from)
+    }
+
+    pub fn from_json(s: &str) -> Result<Self> {
+        serde_json::from_str(s)
+            .context("Parsing job input manifest")
+            .map_err(Error::from)
+    }
+
+    /// The SHA256 of the serialized manifest
+    pub fn digest(&self) -> Result<String> {
+        self.to_json().map(|json| sha256_hex(json.as_bytes()))
+    }
+
+    /// Write the manifest to a content-addressed store at `root`
+    ///
+    /// The manifest is stored at `<root>/<first two characters of digest>/<digest>.json`.
+    /// Returns the path of the manifest in the store.
+    pub async fn write_to_store(&self, root: &Path) -> Result<PathBuf> {
+        let json = self.to_json()?;
+        let digest = sha256_hex(json.as_bytes());
+        let dir = root.join(&digest[..2]);
+        let path = dir.join(format!("{}.json", digest));
+
+        if path.exists() {
+            trace!("Manifest {} exists already", path.display());
+            return Ok(path)
+        }
+
+        tokio::fs::create_dir_all(&dir)
+            .await
+            .with_context(|| anyhow!("Creating directory {}", dir.display()))?;
+        tokio::fs::write(&path, json)
+            .await
+            .with_context(|| anyhow!("Writing manifest {}", path.display()))?;
+        Ok(path)
+    }
+}
+
+/// Hex encoded SHA256 of `bytes`
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    format!("{:x}", sha2::Sha256::digest(bytes))
+}
+
+/// Hash an environment, independent of the order of the variables
+fn hash_environment<'a, I>(env: I) -> String
+where
+    I: Iterator<Item = (&'a str, &'a str)>,
+{
+    let mut env = env.collect::<Vec<_>>();
+    env.sort_unstable();
+    let mut hasher = sha2::Sha256::new();
+    for (k, v) in env {
+        hasher.update(k.as_bytes());
+        hasher.update(b"=");
+        hasher.update(v.as_bytes());
+        hasher.update(b"\0");
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest(script: &str) -> InputManifest {
+        InputManifest {
+            package_name: String::from("a"),
+            package_version: String::from("1"),
+            sources: BTreeMap::from([(String::from("src"), String::from("sha1:123"))]),
+            dependencies: BTreeMap::new(),
+            patches: BTreeMap::from([(String::from("fix.patch"), sha256_hex(b"patch"))]),
+            script: sha256_hex(script.as_bytes()),
+            script_files: BTreeMap::new(),
+            image: String::from("debian:bullseye"),
+            image_digest: None,
+            environment: hash_environment(std::iter::empty()),
+        }
+    }
+
+    #[test]
+    fn test_environment_hash_is_order_independent() {
+        let a = hash_environment([("A", "1"), ("B", "2")].into_iter());
+        let b = hash_environment([("B", "2"), ("A", "1")].into_iter());
+        let c = hash_environment([("A", "1"), ("B", "3")].into_iter());
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+    }
+
+    #[test]
+    fn test_digest_depends_on_inputs() {
+        assert_eq!(manifest("make").digest().unwrap(), manifest("make").digest().unwrap());
+        assert_ne!(manifest("make").digest().unwrap(), manifest("make install").digest().unwrap());
+    }
+
+    #[test]
+    fn test_json_roundtrip() {
+        let m = manifest("make");
+        assert_eq!(InputManifest::from_json(&m.to_json().unwrap()).unwrap(), m);
+    }
+}
@@ -20,3 +20,6 @@ pub use resource::*;
 
 mod runnable;
 pub use runnable::*;
+
+mod manifest;
+pub use manifest::*;
@@ -8,6 +8,9 @@
 // SPDX-License-Identifier: EPL-2.0
 //
 
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
 use anyhow::anyhow;
 use anyhow::Context;
 use anyhow::Result;
@@ -20,11 +23,16 @@ use crate::filestore::ArtifactPath;
 use crate::job::Job;
 use crate::job::JobResource;
 use crate::package::Package;
+use crate::package::PackageName;
+use crate::package::PackageVersion;
+use crate::package::PhaseName;
 use crate::package::Script;
 use crate::package::ScriptBuilder;
 use crate::source::SourceCache;
 use crate::source::SourceEntry;
 use crate::util::EnvironmentVariableName;
+use crate::util::docker::ContainerPaths;
+use crate::util::docker::ContainerUser;
 use crate::util::docker::ImageName;
 
 /// A job configuration that can be run. All inputs are clear here.
@@ -45,8 +53,37 @@ pub struct RunnableJob {
     #[getset(get = "pub")]
     script: Script,
 
+    /// The SHA256 of the files the phase scripts were read from, by path
+    #[getset(get = "pub")]
+    script_files: BTreeMap<PathBuf, String>,
+
     #[getset(get = "pub")]
     resources: Vec<JobResource>,
+
+    /// The phases that are compiled into the script
+    #[getset(get = "pub")]
+    phases: Vec<PhaseName>,
+
+    /// The paths inside the container, as configured for the package or the image
+    #[getset(get = "pub")]
+    container_paths: ContainerPaths,
+
+    /// The user the container runs as, as configured for the package or the containers
+    #[getset(get = "pub")]
+    container_user: Option<ContainerUser>,
+
+    /// The network mode of the container, as set for the submit or configured for the package
+    ///
+    /// If this is not set, the network mode of the endpoint is used.
+    #[getset(get = "pub")]
+    network_mode: Option<String>,
+
+    /// The variables butido sets for every job, e.g. `BUTIDO_PACKAGE_NAME`
+    ///
+    /// These are not part of `environment()`, so they are neither recorded as environment of the
+    /// job nor part of its input manifest.
+    #[getset(get = "pub")]
+    builtin_environment: Vec<(EnvironmentVariableName, String)>,
 }
 
 impl RunnableJob {
@@ -56,6 +93,7 @@ impl RunnableJob {
         config: &Configuration,
         git_author_env: Option<&(EnvironmentVariableName, String)>,
         git_commit_env: Option<&(EnvironmentVariableName, String)>,
+        network_mode: Option<&str>,
         dependencies: Vec<ArtifactPath>,
     ) -> Result<Self> {
         if config.containers().check_env_names() {
@@ -108,23 +146,115 @@ impl RunnableJob {
             .collect();
 
         debug!("Building script now");
-        let script = ScriptBuilder::new(job.script_shebang()).build(
+        let (script, script_files) = ScriptBuilder::new(job.script_shebang()).build_with_files(
             job.package(),
             job.script_phases(),
             *config.strict_script_interpolation(),
         )?;
 
+        let container_paths = Self::container_paths_for(config, job.package(), job.image());
+        let builtin_environment = Self::builtin_environment_for(
+            job.uuid(),
+            job.package(),
+            source_cache,
+            &container_paths,
+            job.dependency_versions(),
+        );
+
         Ok(RunnableJob {
             uuid: *job.uuid(),
             package: job.package().clone(),
             image: job.image().clone(),
             resources,
             source_cache: source_cache.clone(),
+            phases: job.script_phases().clone(),
+            container_paths,
+            builtin_environment,
+            container_user: (*job.package().container_user()).or_else(|| config.containers().user()),
+            network_mode: network_mode.map(String::from).or_else(|| job.package().network_mode().clone()),
 
             script,
+            script_files,
         })
     }
 
+    /// A new job that runs a script that was rendered before, e.g. to reproduce the run of a job
+    /// that is recorded in the database
+    #[allow(clippy::too_many_arguments)]
+    pub fn rerun(
+        package: Package,
+        image: ImageName,
+        script: Script,
+        phases: Vec<PhaseName>,
+        resources: Vec<JobResource>,
+        dependency_versions: &[(PackageName, PackageVersion)],
+        source_cache: &SourceCache,
+        config: &Configuration,
+    ) -> Self {
+        let uuid = Uuid::new_v4();
+        let container_paths = Self::container_paths_for(config, &package, &image);
+        RunnableJob {
+            builtin_environment: Self::builtin_environment_for(&uuid, &package, source_cache, &container_paths, dependency_versions),
+            uuid,
+            container_paths,
+            container_user: (*package.container_user()).or_else(|| config.containers().user()),
+            network_mode: package.network_mode().clone(),
+            package,
+            image,
+            resources,
+            source_cache: source_cache.clone(),
+            phases,
+            script,
+            script_files: BTreeMap::new(),
+        }
+    }
+
+    /// The paths inside the container: the paths configured for the package take precedence over
+    /// the paths configured for the image
+    fn container_paths_for(config: &Configuration, package: &Package, image: &ImageName) -> ContainerPaths {
+        let image_paths = config.docker()
+            .images()
+            .iter()
+            .find(|img| img.name == *image)
+            .map(|img| img.paths.clone())
+            .unwrap_or_default();
+        package.container_paths().or(&image_paths)
+    }
+
+    /// The variables butido sets for every job, so scripts do not have to hardcode them
+    fn builtin_environment_for(
+        uuid: &Uuid,
+        package: &Package,
+        source_cache: &SourceCache,
+        container_paths: &ContainerPaths,
+        dependency_versions: &[(PackageName, PackageVersion)],
+    ) -> Vec<(EnvironmentVariableName, String)> {
+        // The sources are copied to the inputs directory under the name of their cache file
+        let mut source_files = source_cache
+            .sources_for(package)
+            .iter()
+            .filter_map(|entry| entry.path().file_name().map(|name| container_paths.inputs().join(name)))
+            .map(|path| path.display().to_string())
+            .collect::<Vec<_>>();
+        source_files.sort();
+
+        [
+            (crate::consts::PACKAGE_NAME_ENV_NAME, package.name().to_string()),
+            (crate::consts::PACKAGE_VERSION_ENV_NAME, package.version().to_string()),
+            (crate::consts::SOURCE_FILE_ENV_NAME, source_files.join(" ")),
+            (crate::consts::OUTPUT_DIR_ENV_NAME, container_paths.outputs().display().to_string()),
+            (crate::consts::JOB_UUID_ENV_NAME, uuid.to_string()),
+        ]
+        .into_iter()
+        .map(|(name, value)| (EnvironmentVariableName::from(name), value))
+        .chain({
+            dependency_versions
+                .iter()
+                .map(|(name, version)| (dependency_version_env_name(name), version.to_string()))
+        })
+        .collect()
+    }
+
     pub fn package_sources(&self) -> Vec<SourceEntry> {
         self.source_cache.sources_for(self.package())
     }
@@ -144,3 +274,26 @@ impl RunnableJob {
     }
 
 }
+
+/// The name of the variable with the version of the dependency `name`, e.g.
+/// `BUTIDO_DEPENDENCY_LIBFOO_VERSION` for "libfoo"
+///
+/// All characters of the name that are not ASCII alphanumeric are replaced by an underscore.
+pub fn dependency_version_env_name(name: &PackageName) -> EnvironmentVariableName {
+    let name = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' })
+        .collect::<String>();
+    EnvironmentVariableName::from(format!("BUTIDO_DEPENDENCY_{name}_VERSION").as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dependency_version_env_name() {
+        let name = dependency_version_env_name(&PackageName::from(String::from("lib-foo.2")));
+        assert_eq!(name.as_ref(), "BUTIDO_DEPENDENCY_LIB_FOO_2_VERSION");
+    }
+}
@@ -0,0 +1,76 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! butido as a library
+//!
+//! The `butido` binary is a thin command line frontend for this library. Other programs can embed
+//! butido by using the library directly, e.g. to load the configuration with [config::load], the
+//! package repository with [repository::Repository::load] and to build packages with the
+//! [orchestrator].
+//!
+//! The lints are the same for the library and the binary. The `unused_crate_dependencies` lint is
+//! not denied, because the library and the binary share the dependencies of the package, but each
+//! of them only uses some of these.
+
+#![deny(
+    anonymous_parameters,
+    bad_style,
+    dead_code,
+    deprecated_in_future,
+    explicit_outlives_requirements,
+    improper_ctypes,
+    keyword_idents,
+    no_mangle_generic_items,
+    non_ascii_idents,
+    non_camel_case_types,
+    non_shorthand_field_patterns,
+    non_snake_case,
+    overflowing_literals,
+    path_statements,
+    patterns_in_fns_without_body,
+    private_in_public,
+    trivial_numeric_casts,
+    unconditional_recursion,
+    unsafe_code,
+    unstable_features,
+    unused,
+    unused_allocation,
+    unused_comparisons,
+    unused_extern_crates,
+    unused_import_braces,
+    unused_imports,
+    unused_must_use,
+    unused_mut,
+    unused_parens,
+    while_true,
+)]
+#![allow(macro_use_extern_crate)]
+#![allow(unstable_name_collisions)] // TODO: Remove me with the next rustc update (probably)
+
+#[macro_use]
+extern crate diesel;
+
+pub mod build_info;
+pub mod cli;
+pub mod commands;
+pub mod config;
+mod consts;
+pub mod db;
+pub mod endpoint;
+pub mod filestore;
+pub mod job;
+pub mod log;
+pub mod orchestrator;
+pub mod package;
+pub mod repository;
+mod schema;
+pub mod source;
+mod ui;
+pub mod util;
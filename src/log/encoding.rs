@@ -0,0 +1,83 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Compression of the logs that are stored in the database
+
+use std::io::Read;
+use std::io::Write;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Error;
+use anyhow::Result;
+use serde::Deserialize;
+
+/// The compression algorithms logs can be stored with in the database
+#[derive(parse_display::Display, parse_display::FromStr, Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+pub enum LogEncoding {
+    #[serde(rename = "zstd")]
+    #[display("zstd")]
+    Zstd,
+
+    #[serde(rename = "gzip")]
+    #[display("gzip")]
+    Gzip,
+}
+
+impl LogEncoding {
+    pub fn encode(&self, log: &str) -> Result<Vec<u8>> {
+        match self {
+            LogEncoding::Zstd => zstd::stream::encode_all(log.as_bytes(), zstd::DEFAULT_COMPRESSION_LEVEL)
+                .map_err(Error::from),
+            LogEncoding::Gzip => {
+                let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(log.as_bytes())?;
+                encoder.finish().map_err(Error::from)
+            },
+        }
+        .with_context(|| anyhow!("Compressing log with {}", self))
+    }
+
+    pub fn decode(&self, buf: &[u8]) -> Result<String> {
+        let mut out = Vec::with_capacity(buf.len());
+        match self {
+            LogEncoding::Zstd => zstd::stream::copy_decode(buf, &mut out)?,
+            LogEncoding::Gzip => {
+                flate2::read::GzDecoder::new(buf).read_to_end(&mut out)?;
+            },
+        }
+        String::from_utf8(out)
+            .map_err(Error::from)
+            .with_context(|| anyhow!("Decompressing log with {}", self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_roundtrip() {
+        let log = "#BUTIDO:PHASE:build\n".repeat(100);
+        for encoding in [LogEncoding::Zstd, LogEncoding::Gzip] {
+            let encoded = encoding.encode(&log).unwrap();
+            assert!(encoded.len() < log.len());
+            assert_eq!(encoding.decode(&encoded).unwrap(), log);
+        }
+    }
+
+    #[test]
+    fn test_from_str() {
+        assert_eq!(LogEncoding::from_str("zstd").unwrap(), LogEncoding::Zstd);
+        assert_eq!(LogEncoding::from_str("gzip").unwrap(), LogEncoding::Gzip);
+        assert!(LogEncoding::from_str("xz").is_err());
+    }
+}
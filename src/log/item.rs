@@ -12,7 +12,7 @@ use anyhow::Error;
 use anyhow::Result;
 use colored::Colorize;
 
-#[derive(Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum LogItem {
     /// A line from the log, unmodified
     Line(Vec<u8>),
@@ -21,8 +21,16 @@ pub enum LogItem {
     Progress(usize),
 
     /// The name of the current phase the process is in
+    ///
+    /// Sub-phases are separated from their parent phase by a slash, e.g. "configure/cmake".
     CurrentPhase(String),
 
+    /// A progress report within the current phase
+    PhaseProgress(usize),
+
+    /// A sign of life of a script that does not print anything for a while
+    Heartbeat,
+
     /// The end-state of the process
     /// Either Ok or Error
     State(Result<(), String>),
@@ -34,6 +42,8 @@ impl LogItem {
             LogItem::Line(s) => Ok(Display(String::from_utf8(s.to_vec())?.normal())),
             LogItem::Progress(u) => Ok(Display(format!("#BUTIDO:PROGRESS:{u}").cyan())),
             LogItem::CurrentPhase(p) => Ok(Display(format!("#BUTIDO:PHASE:{p}").cyan())),
+            LogItem::PhaseProgress(u) => Ok(Display(format!("#BUTIDO:PHASE-PROGRESS:{u}").cyan())),
+            LogItem::Heartbeat => Ok(Display("#BUTIDO:HEARTBEAT".to_string().cyan())),
             LogItem::State(Ok(())) => Ok(Display("#BUTIDO:STATE:OK".to_string().green())),
             LogItem::State(Err(s)) => Ok(Display(format!("#BUTIDO:STATE:ERR:{s}").red())),
         }
@@ -44,12 +54,48 @@ impl LogItem {
             LogItem::Line(s) => String::from_utf8(s.to_vec()).map_err(Error::from),
             LogItem::Progress(u) => Ok(format!("#BUTIDO:PROGRESS:{u}")),
             LogItem::CurrentPhase(p) => Ok(format!("#BUTIDO:PHASE:{p}")),
+            LogItem::PhaseProgress(u) => Ok(format!("#BUTIDO:PHASE-PROGRESS:{u}")),
+            LogItem::Heartbeat => Ok("#BUTIDO:HEARTBEAT".to_string()),
             LogItem::State(Ok(())) => Ok("#BUTIDO:STATE:OK".to_string()),
             LogItem::State(Err(s)) => Ok(format!("#BUTIDO:STATE:ERR:{s}")),
         }
     }
 }
 
+/// Split a (possibly nested) phase name into its components
+///
+/// `"configure/cmake"` is the sub-phase "cmake" of the phase "configure".
+pub fn phase_path(phase: &str) -> Vec<&str> {
+    phase.split('/').filter(|component| !component.is_empty()).collect()
+}
+
 #[derive(parse_display::Display)]
 #[display("{0}")]
 pub struct Display(colored::ColoredString);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_phase_path() {
+        assert_eq!(phase_path("build"), vec!["build"]);
+        assert_eq!(phase_path("configure/cmake"), vec!["configure", "cmake"]);
+        assert_eq!(phase_path("/configure//cmake/"), vec!["configure", "cmake"]);
+    }
+
+    #[test]
+    fn test_phase_progress_roundtrip() {
+        let item = LogItem::PhaseProgress(42);
+        let raw = item.raw().unwrap();
+        assert_eq!(raw, "#BUTIDO:PHASE-PROGRESS:42");
+        assert_eq!(crate::log::parser().parse(raw.as_bytes()).unwrap(), item);
+    }
+
+    #[test]
+    fn test_heartbeat_roundtrip() {
+        let raw = LogItem::Heartbeat.raw().unwrap();
+        assert_eq!(raw, "#BUTIDO:HEARTBEAT");
+        assert_eq!(crate::log::parser().parse(raw.as_bytes()).unwrap(), LogItem::Heartbeat);
+    }
+}
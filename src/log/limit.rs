@@ -0,0 +1,225 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Limiting the size of the logs that are stored in the database
+//!
+//! Logs that exceed the configured maximum size are written to a compressed file in the log
+//! directory, and only the first and last lines of the log are stored in the database.
+
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Error;
+use anyhow::Result;
+use tracing::trace;
+use uuid::Uuid;
+
+use crate::config::Configuration;
+use crate::log::LogEncoding;
+
+#[derive(Clone, Debug, Default)]
+pub struct LogLimit {
+    /// The maximum size of a log in the database, in bytes
+    max_size: Option<usize>,
+
+    /// The number of lines from the beginning and end of an oversized log that are kept in the
+    /// database
+    excerpt_lines: usize,
+
+    /// The directory oversized logs are written to
+    dir: PathBuf,
+
+    /// The compression of the logs in the database
+    encoding: Option<LogEncoding>,
+}
+
+/// A log, ready to be stored in the database
+#[derive(Debug, Eq, PartialEq)]
+pub struct LimitedLog {
+    /// The text that is stored in the database
+    pub text: String,
+
+    /// The file the full log was written to, if the log exceeded the limit
+    pub file: Option<PathBuf>,
+}
+
+impl LogLimit {
+    pub fn from_config(config: &Configuration) -> Self {
+        LogLimit {
+            max_size: *config.log_max_size(),
+            excerpt_lines: *config.log_excerpt_lines(),
+            dir: config.log_dir().clone(),
+            encoding: *config.log_compression(),
+        }
+    }
+
+    /// The compression the logs are stored with in the database
+    pub fn encoding(&self) -> Option<LogEncoding> {
+        self.encoding
+    }
+
+    /// The path of the file the full log of the job `job_uuid` is written to
+    fn path_for(&self, job_uuid: &Uuid) -> PathBuf {
+        self.dir.join(format!("{job_uuid}.log.zst"))
+    }
+
+    /// Apply the limit to the log of the job `job_uuid`
+    pub fn apply(&self, job_uuid: &Uuid, log: &str) -> Result<LimitedLog> {
+        match self.max_size {
+            Some(max_size) if log.len() > max_size => {
+                let path = self.path_for(job_uuid);
+                trace!("Log of job {} is {} bytes, writing it to {}", job_uuid, log.len(), path.display());
+                let compressed = zstd::stream::encode_all(log.as_bytes(), zstd::DEFAULT_COMPRESSION_LEVEL)
+                    .with_context(|| anyhow!("Compressing log of job {}", job_uuid))?;
+                std::fs::write(&path, compressed)
+                    .with_context(|| anyhow!("Writing log of job {} to {}", job_uuid, path.display()))?;
+
+                Ok(LimitedLog {
+                    text: excerpt(log, self.excerpt_lines, max_size, &path),
+                    file: Some(path),
+                })
+            },
+
+            _ => Ok(LimitedLog {
+                text: log.to_string(),
+                file: None,
+            }),
+        }
+    }
+}
+
+/// Read the full log of a job
+///
+/// If the full log was written to `log_file` and that file still exists, it is read from there,
+/// otherwise `log_text` (as stored in the database) is returned.
+pub fn read_full_log(log_text: &str, log_file: Option<&str>) -> Result<String> {
+    match log_file.map(Path::new).filter(|path| path.exists()) {
+        Some(path) => {
+            trace!("Reading log from {}", path.display());
+            let compressed = std::fs::read(path)
+                .with_context(|| anyhow!("Reading log file {}", path.display()))?;
+            let buf = zstd::stream::decode_all(compressed.as_slice())
+                .with_context(|| anyhow!("Decompressing log file {}", path.display()))?;
+            String::from_utf8(buf).map_err(Error::from)
+        },
+        None => Ok(log_text.to_string()),
+    }
+}
+
+/// The first and last `lines` lines of `log`, with a note where the full log can be found
+///
+/// The beginning and the end are at most `max_size / 2` bytes each, so that a log with very long
+/// lines does not end up in the database in full.
+fn excerpt(log: &str, lines: usize, max_size: usize, path: &Path) -> String {
+    let all = log.lines().collect::<Vec<_>>();
+    let head_end = lines.min((all.len() + 1) / 2);
+    let tail_start = all.len().saturating_sub(lines).max(head_end);
+
+    let budget = max_size / 2;
+    let mut head = all[..head_end].join("\n");
+    let mut tail = all[tail_start..].join("\n");
+    let truncated = head.len() > budget || tail.len() > budget;
+    if !truncated && tail_start == head_end {
+        return log.to_string();
+    }
+
+    if head.len() > budget {
+        let mut end = budget;
+        while !head.is_char_boundary(end) {
+            end -= 1;
+        }
+        head.truncate(end);
+    }
+    if tail.len() > budget {
+        let mut start = tail.len() - budget;
+        while !tail.is_char_boundary(start) {
+            start += 1;
+        }
+        tail = tail.split_off(start);
+    }
+
+    let note = if truncated {
+        let omitted = log.len() - head.len() - tail.len();
+        format!("[... {omitted} bytes omitted, full log in {} ...]", path.display())
+    } else {
+        let omitted = tail_start - head_end;
+        format!("[... {omitted} lines omitted, full log in {} ...]", path.display())
+    };
+
+    [head, note, tail]
+        .into_iter()
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limit(dir: &Path, max_size: Option<usize>) -> LogLimit {
+        LogLimit {
+            max_size,
+            excerpt_lines: 2,
+            dir: dir.to_path_buf(),
+            encoding: None,
+        }
+    }
+
+    #[test]
+    fn test_excerpt() {
+        let log = "1\n2\n3\n4\n5\n6";
+        let path = Path::new("/tmp/logs/x.log.zst");
+        assert_eq!(excerpt(log, 2, 10, path), "1\n2\n[... 2 lines omitted, full log in /tmp/logs/x.log.zst ...]\n5\n6");
+        assert_eq!(excerpt(log, 3, 20, path), log);
+    }
+
+    #[test]
+    fn test_excerpt_truncates_long_lines() {
+        let log = format!("{}\n{}", "a".repeat(100), "ä".repeat(100));
+        let path = Path::new("/tmp/logs/x.log.zst");
+        let excerpt = excerpt(&log, 2, 21, path);
+        assert_eq!(
+            excerpt,
+            format!("{}\n[... 281 bytes omitted, full log in /tmp/logs/x.log.zst ...]\n{}", "a".repeat(10), "ä".repeat(5))
+        );
+    }
+
+    #[test]
+    fn test_small_log_is_kept() {
+        let dir = std::env::temp_dir();
+        let limited = limit(&dir, Some(100)).apply(&Uuid::new_v4(), "a\nb").unwrap();
+        assert_eq!(limited, LimitedLog { text: String::from("a\nb"), file: None });
+
+        let limited = limit(&dir, None).apply(&Uuid::new_v4(), &"a\n".repeat(1000)).unwrap();
+        assert!(limited.file.is_none());
+    }
+
+    #[test]
+    fn test_overflow_roundtrip() {
+        let dir = std::env::temp_dir().join(format!("butido-log-limit-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let log = (0..100).map(|i| format!("line {i}")).collect::<Vec<_>>().join("\n");
+        let limited = limit(&dir, Some(100)).apply(&Uuid::new_v4(), &log).unwrap();
+        let file = limited.file.as_ref().unwrap();
+        assert_eq!(limited.text.lines().count(), 5);
+        assert!(limited.text.starts_with("line 0\nline 1\n"));
+        assert!(limited.text.ends_with("line 98\nline 99"));
+
+        let full = read_full_log(&limited.text, file.to_str()).unwrap();
+        assert_eq!(full, log);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(read_full_log(&limited.text, file.to_str()).unwrap(), limited.text);
+    }
+}
@@ -11,10 +11,20 @@
 mod parser;
 pub use parser::*;
 
+mod encoding;
+pub use encoding::*;
+
 mod item;
 pub use item::*;
 
+mod limit;
+pub use limit::*;
+
 mod sink;
 pub use sink::*;
 
-mod util;
+mod stream;
+pub use stream::*;
+
+mod triage;
+pub use triage::*;
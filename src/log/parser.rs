@@ -15,22 +15,18 @@ use anyhow::Error;
 use anyhow::Result;
 use futures::AsyncBufReadExt;
 use futures::Stream;
-use futures::StreamExt;
 use futures::TryStreamExt;
 use pom::parser::Parser as PomParser;
-use shiplift::tty::TtyChunk;
 
-use crate::log::util::*;
 use crate::log::LogItem;
 
 type IoResult<T> = RResult<T, futures::io::Error>;
 
 pub fn buffer_stream_to_line_stream<S>(stream: S) -> impl Stream<Item = IoResult<String>>
 where
-    S: Stream<Item = shiplift::Result<TtyChunk>> + std::marker::Unpin,
+    S: Stream<Item = Result<Vec<u8>>> + std::marker::Unpin,
 {
     stream
-        .map(|r| r.map(TtyChunkBuf::from))
         .map_err(|e| futures::io::Error::new(futures::io::ErrorKind::Other, e))
         .into_async_read()
         .lines()
@@ -49,6 +45,8 @@ impl std::fmt::Debug for ParsedLog {
                 },
                 LogItem::Progress(u)     => writeln!(f, "[{i}] Progress({u})")?,
                 LogItem::CurrentPhase(s) => writeln!(f, "[{i}] Phase({s})")?,
+                LogItem::PhaseProgress(u) => writeln!(f, "[{i}] PhaseProgress({u})")?,
+                LogItem::Heartbeat       => writeln!(f, "[{i}] Heartbeat")?,
                 LogItem::State(Ok(_))    => writeln!(f, "[{i}] State::OK")?,
                 LogItem::State(Err(_))   => writeln!(f, "[{i}] State::Err")?,
             }
@@ -101,6 +99,30 @@ impl ParsedLog {
             .unwrap_or(JobResult::Unknown)
     }
 
+    /// The phase the script was in when it reported an error
+    ///
+    /// If the script did not report an end state, e.g. because it was killed, this is the last
+    /// phase in the log. This is meant to be used for jobs that failed, there is no failed phase
+    /// if the script reported success.
+    pub fn failed_phase(&self) -> Option<&str> {
+        let state = self.0.iter().find_map(|item| match item {
+            LogItem::State(state) => Some(state),
+            _ => None,
+        });
+        if let Some(Ok(_)) = state {
+            return None;
+        }
+
+        self.0
+            .iter()
+            .take_while(|item| !matches!(item, LogItem::State(_)))
+            .filter_map(|item| match item {
+                LogItem::CurrentPhase(phase) => Some(phase.as_str()),
+                _ => None,
+            })
+            .last()
+    }
+
     pub fn into_iter(self) -> impl Iterator<Item = LogItem> {
         self.0.into_iter()
     }
@@ -109,11 +131,13 @@ impl ParsedLog {
 pub fn parser<'a>() -> PomParser<'a, u8, LogItem> {
     use pom::parser::*;
 
-    let number = one_of(b"0123456789")
-        .repeat(1..)
-        .collect()
-        .convert(|b| String::from_utf8(b.to_vec()))
-        .convert(|s| usize::from_str(&s));
+    fn number<'a>() -> PomParser<'a, u8, usize> {
+        one_of(b"0123456789")
+            .repeat(1..)
+            .collect()
+            .convert(|b| String::from_utf8(b.to_vec()))
+            .convert(|s| usize::from_str(&s))
+    }
 
     fn ignored<'a>() -> PomParser<'a, u8, Vec<u8>> {
         none_of(b"\n").repeat(0..)
@@ -135,8 +159,10 @@ pub fn parser<'a>() -> PomParser<'a, u8, LogItem> {
     }
 
     (seq(b"#BUTIDO:")
-        * ((seq(b"PROGRESS:") * number.map(LogItem::Progress))
+        * ((seq(b"PROGRESS:") * number().map(LogItem::Progress))
+            | (seq(b"PHASE-PROGRESS:") * number().map(LogItem::PhaseProgress))
             | (seq(b"PHASE:") * string().map(LogItem::CurrentPhase))
+            | seq(b"HEARTBEAT").map(|_| LogItem::Heartbeat)
             | ((seq(b"STATE:ERR:") * string().map(|s| LogItem::State(Err(s))))
                 | seq(b"STATE:OK").map(|_| LogItem::State(Ok(()))))))
         | ignored().map(LogItem::Line)
@@ -219,6 +245,28 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_nested_phase() {
+        let s = "#BUTIDO:PHASE:configure/cmake";
+        let p = parser();
+        let r = p.parse(s.as_bytes());
+
+        assert!(r.is_ok(), "Not ok: {r:?}");
+        let r = r.unwrap();
+        assert_eq!(r, LogItem::CurrentPhase(String::from("configure/cmake")));
+    }
+
+    #[test]
+    fn test_phase_progress() {
+        let s = "#BUTIDO:PHASE-PROGRESS:50";
+        let p = parser();
+        let r = p.parse(s.as_bytes());
+
+        assert!(r.is_ok(), "Not ok: {r:?}");
+        let r = r.unwrap();
+        assert_eq!(r, LogItem::PhaseProgress(50));
+    }
+
     #[test]
     fn test_phase_multiline() {
         let s = "#BUTIDO:PHASE:a
@@ -464,4 +512,44 @@ mod tests {
         let log = ParsedLog::from_str(buffer).unwrap();
         assert_eq!(log.is_successfull(), JobResult::Errored);
     }
+
+    #[test]
+    fn test_failed_phase() {
+        let buffer: &'static str = indoc::indoc! {"
+            #BUTIDO:PHASE:configure
+            Some log line
+            #BUTIDO:PHASE:build
+            make: *** [all] Error 1
+            #BUTIDO:STATE:ERR:make failed
+            #BUTIDO:PHASE:cleanup
+        "};
+        let log = ParsedLog::from_str(buffer).unwrap();
+        assert_eq!(log.failed_phase(), Some("build"));
+
+        let buffer: &'static str = indoc::indoc! {"
+            #BUTIDO:PHASE:configure
+            #BUTIDO:PHASE:configure/cmake
+            #BUTIDO:PHASE-PROGRESS:30
+            CMake Error: could not find compiler
+            #BUTIDO:STATE:ERR:cmake failed
+        "};
+        let log = ParsedLog::from_str(buffer).unwrap();
+        assert_eq!(log.failed_phase(), Some("configure/cmake"));
+
+        let buffer: &'static str = indoc::indoc! {"
+            #BUTIDO:PHASE:configure
+            Some log line
+            #BUTIDO:PHASE:build
+            Killed
+        "};
+        let log = ParsedLog::from_str(buffer).unwrap();
+        assert_eq!(log.failed_phase(), Some("build"));
+
+        let buffer: &'static str = indoc::indoc! {"
+            #BUTIDO:PHASE:configure
+            #BUTIDO:STATE:OK
+        "};
+        let log = ParsedLog::from_str(buffer).unwrap();
+        assert_eq!(log.failed_phase(), None);
+    }
 }
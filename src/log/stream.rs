@@ -0,0 +1,114 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+//! Live streams of the logs of running jobs
+//!
+//! The scheduler publishes the log items of each running job, so that they can be relayed to
+//! clients (e.g. by `butido daemon`) while the job is still running.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+use crate::log::LogItem;
+
+/// Number of log items that are buffered for subscribers that fall behind
+const STREAM_CAPACITY: usize = 1024;
+
+struct RunningJob {
+    submit: Uuid,
+    sender: broadcast::Sender<LogItem>,
+}
+
+/// Registry of the log streams of all running jobs
+#[derive(Default)]
+pub struct LogStreams {
+    jobs: Mutex<HashMap<Uuid, RunningJob>>,
+}
+
+impl LogStreams {
+    /// Open the log stream for `job` of `submit`
+    ///
+    /// The stream is closed when the returned `LogStream` is dropped.
+    pub fn open(self: &Arc<Self>, submit: Uuid, job: Uuid) -> LogStream {
+        let (sender, _) = broadcast::channel(STREAM_CAPACITY);
+        self.jobs.lock().unwrap().insert(job, RunningJob { submit, sender: sender.clone() });
+        LogStream {
+            streams: self.clone(),
+            job,
+            sender,
+        }
+    }
+
+    /// Subscribe to the log of `job`, if the job is running
+    pub fn subscribe(&self, job: &Uuid) -> Option<broadcast::Receiver<LogItem>> {
+        self.jobs.lock().unwrap().get(job).map(|running| running.sender.subscribe())
+    }
+
+    /// The UUIDs of the jobs of `submit` that are running
+    pub fn running_jobs_of(&self, submit: &Uuid) -> Vec<Uuid> {
+        let mut jobs = self.jobs
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, running)| running.submit == *submit)
+            .map(|(job, _)| *job)
+            .collect::<Vec<_>>();
+        jobs.sort();
+        jobs
+    }
+}
+
+/// The sending side of the log stream of a running job
+pub struct LogStream {
+    streams: Arc<LogStreams>,
+    job: Uuid,
+    sender: broadcast::Sender<LogItem>,
+}
+
+impl LogStream {
+    pub fn send(&self, item: &LogItem) {
+        // Having no subscribers is not an error
+        let _ = self.sender.send(item.clone());
+    }
+}
+
+impl Drop for LogStream {
+    fn drop(&mut self) {
+        self.streams.jobs.lock().unwrap().remove(&self.job);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_subscribers_receive_items_until_stream_is_closed() {
+        let streams = Arc::new(LogStreams::default());
+        let submit = Uuid::new_v4();
+        let job = Uuid::new_v4();
+        assert!(streams.subscribe(&job).is_none());
+
+        let stream = streams.open(submit, job);
+        assert_eq!(streams.running_jobs_of(&submit), vec![job]);
+        let mut receiver = streams.subscribe(&job).unwrap();
+
+        stream.send(&LogItem::CurrentPhase(String::from("build")));
+        drop(stream);
+
+        assert_eq!(receiver.recv().await.unwrap(), LogItem::CurrentPhase(String::from("build")));
+        assert!(receiver.recv().await.is_err());
+        assert!(streams.subscribe(&job).is_none());
+        assert!(streams.running_jobs_of(&submit).is_empty());
+    }
+}
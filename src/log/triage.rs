@@ -0,0 +1,130 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Categorization of failed jobs based on patterns in their logs
+
+use std::path::Path;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use getset::Getters;
+use regex::Regex;
+use serde::Deserialize;
+
+/// A single rule from the triage rules file, as it is written in the file
+#[derive(Debug, Deserialize)]
+struct TriageRuleConfig {
+    pattern: String,
+    category: String,
+    hint: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TriageRulesFile {
+    #[serde(default)]
+    rule: Vec<TriageRuleConfig>,
+}
+
+/// A rule that assigns a failure category to a job log if the pattern matches one of its lines
+#[derive(Debug, Getters)]
+pub struct TriageRule {
+    pattern: Regex,
+
+    #[getset(get = "pub")]
+    category: String,
+
+    #[getset(get = "pub")]
+    hint: Option<String>,
+}
+
+/// The triage rules, in the order they are applied
+///
+/// The first rule that matches a line of the log wins.
+#[derive(Debug, Default)]
+pub struct TriageRules(Vec<TriageRule>);
+
+impl TriageRules {
+    /// Load the rules from a TOML file
+    ///
+    /// The file contains a list of rules:
+    ///
+    /// ```toml
+    /// [[rule]]
+    /// pattern = "Killed signal terminated program cc1"
+    /// category = "compiler OOM"
+    /// hint = "Reduce the number of parallel jobs on the endpoint"
+    /// ```
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut config = ::config::Config::default();
+        config
+            .merge(::config::File::from(path).required(true))
+            .with_context(|| anyhow!("Loading triage rules from {}", path.display()))?;
+
+        config
+            .try_into::<TriageRulesFile>()
+            .with_context(|| anyhow!("Parsing triage rules from {}", path.display()))?
+            .rule
+            .into_iter()
+            .map(Self::compile_rule)
+            .collect::<Result<Vec<_>>>()
+            .map(TriageRules)
+    }
+
+    fn compile_rule(rule: TriageRuleConfig) -> Result<TriageRule> {
+        Ok(TriageRule {
+            pattern: Regex::new(&rule.pattern)
+                .with_context(|| anyhow!("Parsing triage rule pattern: {}", rule.pattern))?,
+            category: rule.category,
+            hint: rule.hint,
+        })
+    }
+
+    /// Find the first rule that matches any line of the log
+    pub fn categorize(&self, log: &str) -> Option<&TriageRule> {
+        self.0
+            .iter()
+            .find(|rule| log.lines().any(|line| rule.pattern.is_match(line)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules() -> TriageRules {
+        TriageRules(vec![
+            TriageRules::compile_rule(TriageRuleConfig {
+                pattern: String::from("Killed signal terminated program"),
+                category: String::from("compiler OOM"),
+                hint: Some(String::from("Reduce parallelism")),
+            }).unwrap(),
+            TriageRules::compile_rule(TriageRuleConfig {
+                pattern: String::from("(?i)connection timed out"),
+                category: String::from("network timeout"),
+                hint: None,
+            }).unwrap(),
+        ])
+    }
+
+    #[test]
+    fn test_categorize_first_matching_rule() {
+        let log = "building...\ncurl: Connection timed out\ng++: fatal error: Killed signal terminated program cc1plus\n";
+        let rules = rules();
+        let rule = rules.categorize(log).unwrap();
+        assert_eq!(rule.category(), "compiler OOM");
+        assert_eq!(rule.hint().as_deref(), Some("Reduce parallelism"));
+    }
+
+    #[test]
+    fn test_categorize_no_match() {
+        assert!(rules().categorize("everything fine\n").is_none());
+    }
+}
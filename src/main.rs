@@ -8,6 +8,11 @@
 // SPDX-License-Identifier: EPL-2.0
 //
 
+//! The butido command line frontend, see the library for the implementation
+//!
+//! The lints are the same as for the library, see there why `unused_crate_dependencies` is not
+//! denied.
+
 #![deny(
     anonymous_parameters,
     bad_style,
@@ -32,7 +37,6 @@
     unused,
     unused_allocation,
     unused_comparisons,
-    unused_crate_dependencies,
     unused_extern_crates,
     unused_import_braces,
     unused_imports,
@@ -45,8 +49,6 @@
 #![allow(unstable_name_collisions)] // TODO: Remove me with the next rustc update (probably)
 
 extern crate log as logcrate;
-#[macro_use]
-extern crate diesel4;
 
 use std::path::PathBuf;
 
@@ -57,37 +59,14 @@ use anyhow::Result;
 use clap::ArgMatches;
 use logcrate::debug;
 use logcrate::error;
-use aquamarine as _; // doc-helper crate
-
-mod cli;
-mod commands;
-mod config;
-mod consts;
-mod db;
-mod endpoint;
-mod filestore;
-mod job;
-mod log;
-mod orchestrator;
-mod package;
-mod repository;
-mod schema;
-mod source;
-mod ui;
-mod util;
-
-use crate::config::*;
-use crate::repository::Repository;
-use crate::util::progress::ProgressBars;
-use indoc::concatdoc;
-
-pub const VERSION_LONG: &str = concatdoc!{"
-    butido ", env!("VERGEN_GIT_DESCRIBE"), "
-    Git SHA:              ", env!("VERGEN_GIT_SHA"), "
-    Git Commit Timestamp: ", env!("VERGEN_GIT_COMMIT_TIMESTAMP"), "
-    Build Timestamp:      ", env!("VERGEN_BUILD_TIMESTAMP"), "
-    Debug Build:          ", env!("VERGEN_CARGO_DEBUG")
-};
+
+use butido::build_info::BuildInfo;
+use butido::cli;
+use butido::repository::Repository;
+use butido::util::output::ColorChoice;
+use butido::util::output::OutputMode;
+use butido::util::progress::ProgressBars;
+use butido::util::progress::ProgressVerbosity;
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -109,10 +88,14 @@ async fn main() -> Result<()> {
 
     // check if the version flag is set
     if cli.get_flag("version") {
-        println!("{VERSION_LONG}");
+        BuildInfo::get().print(cli.get_flag("verbose"), cli.get_flag("json"))?;
         std::process::exit(0);
     }
 
+    let output_mode = OutputMode::select(cli.get_one::<String>("output").map(String::as_str))?;
+    let color = cli.get_one::<String>("color").unwrap().parse::<ColorChoice>()?; // safe by clap
+    output_mode.install(color);
+
     let repo = git2::Repository::open(PathBuf::from("."))
         .map_err(|e| match e.code() {
             git2::ErrorCode::NotFound => {
@@ -126,53 +109,64 @@ async fn main() -> Result<()> {
         .workdir()
         .ok_or_else(|| anyhow!("Not a repository with working directory. Cannot do my job!"))?;
 
-    let mut config = ::config::Config::default();
-    config.merge(::config::File::from(repo_path.join("config.toml")).required(true))
-        .context("Failed to load config.toml from repository")?;
-
-    {
-        let xdg = xdg::BaseDirectories::with_prefix("butido")?;
-        let xdg_config_file = xdg.find_config_file("config.toml");
-        if let Some(xdg_config) = xdg_config_file {
-            debug!("Configuration file found with XDG: {}", xdg_config.display());
-            config.merge(::config::File::from(xdg_config).required(false))
-                .context("Failed to load config.toml from XDG configuration directory")?;
-        } else {
-            debug!("No configuration file found with XDG: {}", xdg.get_config_home().display());
-        }
+    let config = butido::config::load(repo_path, cli.get_one::<String>("profile").map(String::as_str))?;
+
+    // The configuration is checked by the command itself, so it must not be validated before
+    if let Some(("validate-config", _)) = cli.subcommand() {
+        return butido::commands::validate_config(&config, &cli)
+            .context("validate-config command failed")
     }
 
-    config.merge(::config::Environment::with_prefix("BUTIDO"))?;
+    // Inspecting the configuration must work with a configuration that does not validate, too
+    if let Some(("config", matches)) = cli.subcommand() {
+        return butido::commands::config(matches, &config)
+            .context("config command failed")
+    }
 
-    let config = config.try_into::<NotValidatedConfiguration>()
-        .context("Failed to load Configuration object")?
+    let config = config
         .validate()
         .context("Failed to validate configuration")?;
 
-    let hide_bars = cli.get_flag("hide_bars") || crate::util::stdout_is_pipe();
+    let verbosity = if cli.get_flag("hide_bars") || !output_mode.shows_progress_bars() {
+        ProgressVerbosity::Silent
+    } else {
+        cli.get_one::<String>("verbosity")
+            .map(|s| s.parse::<ProgressVerbosity>())
+            .transpose()?
+            .unwrap_or_default()
+    };
     let progressbars = ProgressBars::setup(
         config.progress_format().clone(),
-        hide_bars,
+        config.progress(),
+        verbosity,
     );
 
+    // Only the commands that work with endpoints or images need the definitions from the database
+    let config = if config.docker().database_definitions() && matches!(cli.subcommand_name(), Some("build") | Some("daemon") | Some("rerun-job") | Some("endpoint")) {
+        let mut conn = butido::db::DbConnectionConfig::parse(&config, &cli)?.establish_connection()?;
+        butido::db::apply_definitions(config, &mut conn)?
+    } else {
+        config
+    };
+
     let load_repo = || -> Result<Repository> {
         let bar = progressbars.bar()?;
-        let repo = Repository::load(repo_path, &bar)
+        let repo = Repository::load(repo_path, config.package_variables(), &bar)
             .context("Loading the repository")?;
         bar.finish_with_message("Repository loading finished");
         Ok(repo)
     };
 
-    let db_connection_config = crate::db::DbConnectionConfig::parse(&config, &cli)?;
+    let db_connection_config = butido::db::DbConnectionConfig::parse(&config, &cli)?;
     match cli.subcommand() {
         Some(("generate-completions", matches)) => generate_completions(matches),
-        Some(("db", matches)) => crate::commands::db(db_connection_config, &config, matches)?,
+        Some(("db", matches)) => butido::commands::db(db_connection_config, &config, matches)?,
         Some(("build", matches)) => {
             let pool = db_connection_config.establish_pool()?;
 
             let repo = load_repo()?;
 
-            crate::commands::build(
+            butido::commands::build(
                 repo_path,
                 matches,
                 progressbars,
@@ -180,34 +174,36 @@ async fn main() -> Result<()> {
                 &config,
                 repo,
                 repo_path,
+                None,
+                None,
             )
             .await
             .context("build command failed")?
         }
         Some(("what-depends", matches)) => {
             let repo = load_repo()?;
-            crate::commands::what_depends(matches, &config, repo)
+            butido::commands::what_depends(matches, &config, repo)
                 .await
                 .context("what-depends command failed")?
         }
 
         Some(("dependencies-of", matches)) => {
             let repo = load_repo()?;
-            crate::commands::dependencies_of(matches, &config, repo)
+            butido::commands::dependencies_of(matches, &config, repo)
                 .await
                 .context("dependencies-of command failed")?
         }
 
         Some(("versions-of", matches)) => {
             let repo = load_repo()?;
-            crate::commands::versions_of(matches, repo)
+            butido::commands::versions_of(matches, repo)
                 .await
                 .context("versions-of command failed")?
         }
 
         Some(("env-of", matches)) => {
             let repo = load_repo()?;
-            crate::commands::env_of(matches, repo)
+            butido::commands::env_of(matches, repo)
                 .await
                 .context("env-of command failed")?
         }
@@ -215,41 +211,54 @@ async fn main() -> Result<()> {
         Some(("find-artifact", matches)) => {
             let repo = load_repo()?;
             let pool = db_connection_config.establish_pool()?;
-            crate::commands::find_artifact(matches, &config, progressbars, repo, pool)
+            butido::commands::find_artifact(matches, &config, progressbars, repo, pool)
                 .await
                 .context("find-artifact command failed")?
         }
 
         Some(("find-pkg", matches)) => {
             let repo = load_repo()?;
-            crate::commands::find_pkg(matches, &config, repo)
+            butido::commands::find_pkg(matches, &config, repo)
                 .await
                 .context("find-pkg command failed")?
         }
 
         Some(("source", matches)) => {
             let repo = load_repo()?;
-            crate::commands::source(matches, &config, repo, progressbars)
+            butido::commands::source(matches, &config, repo, progressbars)
                 .await
                 .context("source command failed")?
         }
 
+        Some(("verify-sources-of-submit", matches)) => {
+            let repo = load_repo()?;
+            butido::commands::verify_sources_of_submit(db_connection_config, &config, repo, matches)
+                .await
+                .context("verify-sources-of-submit command failed")?
+        }
+
         Some(("release", matches)) => {
-            crate::commands::release(db_connection_config, &config, matches)
+            butido::commands::release(db_connection_config, &config, matches)
                 .await
                 .context("release command failed")?
         }
 
+        Some(("store", matches)) => {
+            butido::commands::store(db_connection_config, &config, &repo, matches)
+                .await
+                .context("store command failed")?
+        }
+
         Some(("lint", matches)) => {
             let repo = load_repo()?;
-            crate::commands::lint(repo_path, matches, progressbars, &config, repo)
+            butido::commands::lint(repo_path, matches, progressbars, &config, repo)
                 .await
                 .context("lint command failed")?
         }
 
         Some(("tree-of", matches)) => {
             let repo = load_repo()?;
-            crate::commands::tree_of(matches, repo)
+            butido::commands::tree_of(matches, repo)
                 .await
                 .context("tree-of command failed")?
         }
@@ -257,13 +266,36 @@ async fn main() -> Result<()> {
         Some(("metrics", _)) => {
             let repo = load_repo()?;
             let pool = db_connection_config.establish_pool()?;
-            crate::commands::metrics(repo_path, &config, repo, pool)
+            butido::commands::metrics(repo_path, &config, repo, pool)
                 .await
                 .context("metrics command failed")?
         }
 
+        Some(("rerun-job", matches)) => {
+            let pool = db_connection_config.establish_pool()?;
+            let repo = load_repo()?;
+            butido::commands::rerun_job(repo_path, matches, &config, pool, repo, progressbars.clone())
+                .await
+                .context("rerun-job command failed")?
+        }
+
+        Some(("daemon", matches)) => {
+            let pool = db_connection_config.establish_pool()?;
+            butido::commands::daemon(matches, &config, pool, &load_repo, repo_path, progressbars.clone())
+                .await
+                .context("daemon command failed")?
+        }
+
+        Some(("repo", matches)) => {
+            let repo = load_repo()?;
+            butido::commands::repo(matches, repo)
+                .await
+                .context("repo command failed")?
+        }
+
+        Some(("image", matches)) => butido::commands::image(db_connection_config, matches)?,
         Some(("endpoint", matches)) => {
-            crate::commands::endpoint(matches, &config, progressbars)
+            butido::commands::endpoint(db_connection_config, matches, &config, progressbars)
                 .await
                 .context("endpoint command failed")?
         },
@@ -12,5 +12,7 @@
 mod orchestrator;
 pub use orchestrator::*;
 
+mod status;
+
 mod util;
 
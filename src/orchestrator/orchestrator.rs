@@ -12,9 +12,11 @@
 
 use std::borrow::Borrow;
 use std::collections::HashMap;
+use std::collections::HashSet;
 use std::path::PathBuf;
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::time::Duration;
 
 use anyhow::Error;
 use anyhow::Context;
@@ -29,6 +31,7 @@ use itertools::Itertools;
 use tracing::{debug, trace, error};
 use resiter::FilterMap;
 use tokio::sync::RwLock;
+use tokio::sync::Semaphore;
 use tokio::sync::mpsc::Receiver;
 use tokio::sync::mpsc::Sender;
 use tokio_stream::StreamExt;
@@ -36,15 +39,23 @@ use typed_builder::TypedBuilder;
 use uuid::Uuid;
 
 use crate::config::Configuration;
+use crate::config::EndpointName;
+use crate::db::DurationEstimates;
 use crate::db::models as dbmodels;
 use crate::endpoint::EndpointConfiguration;
 use crate::endpoint::EndpointScheduler;
+use crate::endpoint::SharedEndpoints;
 use crate::filestore::ArtifactPath;
+use crate::filestore::ReleaseSigner;
 use crate::filestore::ReleaseStore;
 use crate::filestore::StagingStore;
 use crate::job::Dag;
 use crate::job::JobDefinition;
 use crate::job::RunnableJob;
+use crate::log::LogLimit;
+use crate::log::LogStreams;
+use crate::log::TriageRules;
+use crate::orchestrator::status::SubmitStatus;
 use crate::orchestrator::util::*;
 use crate::source::SourceCache;
 use crate::util::EnvironmentVariableName;
@@ -156,9 +167,17 @@ use crate::util::progress::ProgressBars;
 /// ```
 ///
 /// The "root" JobTask sends its artifacts to the orchestrator, which returns them to the caller.
+/// A submit that builds for multiple images has a tree (and a root) per image.
 ///
 pub struct Orchestrator<'a> {
     scheduler: EndpointScheduler,
+    build_scope: BuildScope,
+    job_limit: Option<Semaphore>,
+    parallelism: usize,
+    estimates: DurationEstimates,
+    status_interval: Option<Duration>,
+    endpoint_status: bool,
+    network_mode: Option<String>,
     progress_generator: ProgressBars,
     staging_store: Arc<RwLock<StagingStore>>,
     release_stores: Vec<Arc<ReleaseStore>>,
@@ -180,12 +199,95 @@ pub struct OrchestratorSetup<'a> {
     database: Pool<ConnectionManager<PgConnection>>,
     submit: dbmodels::Submit,
     log_dir: Option<PathBuf>,
+    manifest_store: Option<PathBuf>,
+    triage_rules: Arc<TriageRules>,
+    log_limit: Arc<LogLimit>,
     config: &'a Configuration,
     repository: Repository,
+
+    #[builder(default)]
+    build_scope: BuildScope,
+
+    /// The maximum number of jobs that are scheduled on the endpoints at the same time
+    #[builder(default)]
+    max_parallel: Option<usize>,
+
+    /// Print the overall status of the submit as plain text line in this interval
+    #[builder(default)]
+    status_interval: Option<Duration>,
+
+    /// Publish the logs of the running jobs to these streams
+    #[builder(default)]
+    log_streams: Option<Arc<LogStreams>>,
+
+    /// The network mode of the containers of all jobs, overrides the network mode of the packages
+    /// and endpoints
+    #[builder(default)]
+    network_mode: Option<String>,
+
+    /// The estimated durations of the jobs, shown as estimated remaining time of the submit
+    #[builder(default)]
+    estimates: DurationEstimates,
+
+    /// Show the utilization of the endpoints while the jobs run
+    #[builder(default)]
+    endpoint_status: bool,
+
+    /// The containers (by endpoint) of the jobs of an interrupted submit that is recovered, which
+    /// are cleaned up before the jobs run
+    #[builder(default)]
+    stale_containers: Vec<(EndpointName, String)>,
+
+    /// Use these endpoints, shared with other submits, instead of setting up the endpoints from
+    /// `endpoint_config`
+    #[builder(default)]
+    shared_endpoints: Option<Arc<SharedEndpoints>>,
+}
+
+/// Which jobs of the job DAG are built
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum BuildScope {
+    /// Build the package and all its dependencies
+    #[default]
+    All,
+
+    /// Only build the package, the artifacts of all dependencies have to exist in the staging or
+    /// release stores already
+    NoDependencies,
+
+    /// Only build the dependencies of the package, but not the package itself
+    DependenciesOnly,
+}
+
+/// What a JobTask does with its job, depending on the `BuildScope`
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum TaskMode {
+    /// Reuse existing artifacts if possible, build otherwise
+    Build,
+
+    /// Only reuse existing artifacts, fail if there are none
+    ReuseOnly,
+
+    /// Do not build, only pass the artifacts of the dependencies on
+    Skip,
+}
+
+impl BuildScope {
+    fn task_mode(&self, is_root: bool) -> TaskMode {
+        match (self, is_root) {
+            (BuildScope::NoDependencies, false) => TaskMode::ReuseOnly,
+            (BuildScope::DependenciesOnly, true) => TaskMode::Skip,
+            _ => TaskMode::Build,
+        }
+    }
 }
 
 impl<'a> OrchestratorSetup<'a> {
     pub async fn setup(self) -> Result<Orchestrator<'a>> {
+        let parallelism = self.max_parallel.unwrap_or_else(|| {
+            self.endpoint_config.iter().map(|ep| ep.endpoint().maxjobs()).sum()
+        });
+
         let scheduler = EndpointScheduler::setup(
             self.endpoint_config,
             self.staging_store.clone(),
@@ -193,11 +295,26 @@ impl<'a> OrchestratorSetup<'a> {
             self.database.clone(),
             self.submit.clone(),
             self.log_dir,
+            self.log_streams,
+            self.manifest_store,
+            self.triage_rules,
+            self.log_limit,
+            self.config.containers().stall_detection().clone(),
+            self.shared_endpoints,
+            self.config.docker().database_capacity_accounting(),
         )
         .await?;
+        scheduler.clean_up_containers(&self.stale_containers).await;
 
         Ok(Orchestrator {
             scheduler,
+            build_scope: self.build_scope,
+            job_limit: self.max_parallel.map(Semaphore::new),
+            parallelism,
+            estimates: self.estimates,
+            status_interval: self.status_interval,
+            endpoint_status: self.endpoint_status,
+            network_mode: self.network_mode,
             staging_store: self.staging_store.clone(),
             release_stores: self.release_stores.clone(),
             progress_generator: self.progress_generator,
@@ -214,13 +331,48 @@ impl<'a> OrchestratorSetup<'a> {
 ///
 /// Represents a result that came from the run of a job inside a container
 ///
-/// It is either a list of artifacts with the UUID of the job they were produced by,
+/// It is either the artifacts of the job (and, shared, those of its dependencies),
 /// or a UUID and an Error object, where the UUID is the job UUID and the error is the
 /// anyhow::Error that was issued.
+type JobResult = std::result::Result<Arc<JobArtifacts>, HashMap<Uuid, Error>>;
+
+/// The artifacts a job task produced (or reused)
+///
+/// The results of the dependencies of the job are only referenced, so that sending the result to
+/// every dependent task does not copy the artifacts of the whole subtree.
 ///
 /// The artifacts are encapsulated into a `ProducedArtifact`, see the documentation of the type for
 /// why.
-type JobResult = std::result::Result<HashMap<Uuid, Vec<ProducedArtifact>>, HashMap<Uuid, Error>>;
+#[derive(Debug)]
+struct JobArtifacts {
+    job: Uuid,
+    artifacts: Vec<ProducedArtifact>,
+    dependencies: Vec<Arc<JobArtifacts>>,
+}
+
+impl JobArtifacts {
+    fn new(job: Uuid, artifacts: Vec<ProducedArtifact>, dependencies: &HashMap<Uuid, Arc<JobArtifacts>>) -> Arc<Self> {
+        Arc::new(JobArtifacts {
+            job,
+            artifacts,
+            dependencies: dependencies.values().cloned().collect(),
+        })
+    }
+}
+
+/// Collect the artifacts from `results` and all (transitive) dependencies of them
+///
+/// Jobs that are reachable via more than one path are only collected once.
+fn collect_artifacts<'r>(results: impl IntoIterator<Item = &'r Arc<JobArtifacts>>) -> HashMap<Uuid, &'r [ProducedArtifact]> {
+    let mut collected = HashMap::new();
+    let mut stack = results.into_iter().collect::<Vec<_>>();
+    while let Some(result) = stack.pop() {
+        if collected.insert(result.job, result.artifacts.as_slice()).is_none() {
+            stack.extend(result.dependencies.iter());
+        }
+    }
+    collected
+}
 
 /// A type that represents whether an artifact was built or reused from an old job
 ///
@@ -240,14 +392,6 @@ impl ProducedArtifact {
     fn was_build(&self) -> bool {
         std::matches!(self, ProducedArtifact::Built(_))
     }
-
-    /// Unpack the ProducedArtifact object into the ArtifactPath object it contains
-    fn unpack(self) -> ArtifactPath {
-        match self {
-            ProducedArtifact::Built(a) => a,
-            ProducedArtifact::Reused(a) => a,
-        }
-    }
 }
 
 impl Borrow<ArtifactPath> for ProducedArtifact {
@@ -267,13 +411,14 @@ impl<'a> Orchestrator<'a> {
     }
 
     async fn run_tree(self) -> Result<(Vec<ArtifactPath>, HashMap<Uuid, Error>)> {
-        let multibar = Arc::new({
-            let mp = indicatif::MultiProgress::new();
-            if self.progress_generator.hide() {
-                mp.set_draw_target(indicatif::ProgressDrawTarget::hidden());
-            }
-            mp
-        });
+        let multibar = Arc::new(self.progress_generator.multi());
+
+        let estimates = self.jobdag
+            .iter()
+            .filter_map(|jobdef| self.estimates.of(jobdef.job.package()).map(|d| (*jobdef.job.uuid(), d)))
+            .collect();
+        let status = SubmitStatus::new(self.jobdag.iter().count(), multibar.add(self.progress_generator.bar()?))
+            .with_estimates(estimates, self.parallelism);
 
         let git_author_env = {
             self.config
@@ -320,7 +465,7 @@ impl<'a> Orchestrator<'a> {
                 let (sender, receiver) = tokio::sync::mpsc::channel(100);
 
                 trace!("Creating TaskPreparation object for job {}", jobdef.job.uuid());
-                let bar = self.progress_generator.bar()?;
+                let bar = self.progress_generator.item_bar()?;
                 let bar = multibar.add(bar);
                 bar.set_length(100);
                 let tp = TaskPreparation {
@@ -332,6 +477,9 @@ impl<'a> Orchestrator<'a> {
                     git_commit_env: git_commit_env.as_ref(),
                     source_cache: &self.source_cache,
                     scheduler: &self.scheduler,
+                    job_limit: self.job_limit.as_ref(),
+                    network_mode: self.network_mode.as_deref(),
+                    status: &status,
                     staging_store: self.staging_store.clone(),
                     release_stores: self.release_stores.clone(),
                     database: self.database.clone(),
@@ -387,20 +535,24 @@ impl<'a> Orchestrator<'a> {
             };
         }
 
-        // Find the id of the root task
+        // Find the ids of the root tasks
         //
         // By now, all tasks should be associated with their respective sender.
-        // Only one has None sender: The task that is the "root" of the tree.
-        // By that property, we can find the root task.
+        // Only the root of each tree has None sender, there is a tree for every image of the
+        // submit.
+        // By that property, we can find the root tasks.
         //
-        // Here, we copy its uuid, because we need it later.
-        let root_job_id = jobs.iter()
-            .find(|j| j.3.borrow().is_none())
-            .map(|j| j.1.jobdef.job.uuid())
-            .ok_or_else(|| anyhow!("Failed to find root task"))?;
-        trace!("Root job id = {}", root_job_id);
-
-        // Create a sender and a receiver for the root of the tree
+        // Here, we copy their uuids, because we need them later.
+        let root_job_ids = jobs.iter()
+            .filter(|j| j.3.borrow().is_none())
+            .map(|j| *j.1.jobdef.job.uuid())
+            .collect::<HashSet<_>>();
+        if root_job_ids.is_empty() {
+            return Err(anyhow!("Failed to find root task"))
+        }
+        trace!("Root job ids = {:?}", root_job_ids);
+
+        // Create a sender and a receiver for the roots of the trees
         let (root_sender, mut root_receiver) = tokio::sync::mpsc::channel(100);
 
         // Make all prepared jobs into real jobs and run them
@@ -416,25 +568,61 @@ impl<'a> Orchestrator<'a> {
                 trace!("Creating JobTask for = {}", prep.1.jobdef.job.uuid());
                 // the sender is set or we need to use the root sender
                 let sender = prep.3.into_inner().unwrap_or_else(|| vec![root_sender.clone()]);
-                JobTask::new(prep.0, prep.1, sender)
+                let mode = self.build_scope.task_mode(root_job_ids.contains(prep.1.jobdef.job.uuid()));
+                JobTask::new(prep.0, prep.1, sender, mode)
             })
             .inspect(|task| trace!("Running: {}", task.jobdef.job.uuid()))
             .map(|task| task.run())
             .collect::<futures::stream::FuturesUnordered<_>>();
+
+        // Only the root tasks hold a sender now, so the receiver is closed after they sent their
+        // results
+        drop(root_sender);
         debug!("Built {} jobs", running_jobs.len());
 
-        running_jobs.collect::<Result<()>>().await?;
+        let endpoint_bars = if self.endpoint_status {
+            (0..self.scheduler.n_endpoints())
+                .map(|_| self.progress_generator.bar().map(|bar| multibar.add(bar)))
+                .collect::<Result<Vec<_>>>()?
+        } else {
+            vec![]
+        };
+
+        let all_jobs_finished = running_jobs.collect::<Result<()>>();
+        tokio::select! {
+            res = all_jobs_finished => res?,
+            res = status.report(self.status_interval.unwrap_or_default()), if self.status_interval.is_some() => return Err(res),
+            _ = self.scheduler.monitor(&endpoint_bars), if self.endpoint_status => {},
+        }
+        status.finish();
+        self.scheduler.finish_monitor(&endpoint_bars);
+        if self.status_interval.is_some() {
+            status.print()?;
+        }
         trace!("All jobs finished");
-        match root_receiver.recv().await {
-            None                     => Err(anyhow!("No result received...")),
-            Some(Ok(results)) => {
-                let results = results.into_iter()
-                    .flat_map(|tpl| tpl.1.into_iter())
-                    .map(ProducedArtifact::unpack)
-                    .collect();
-                Ok((results, HashMap::with_capacity(0)))
-            },
-            Some(Err(errors))        => Ok((vec![], errors)),
+        let mut received = 0;
+        let mut results = vec![];
+        let mut errors = HashMap::with_capacity(0);
+        while let Some(result) = root_receiver.recv().await {
+            received += 1;
+            match result {
+                Ok(result) => {
+                    results.extend({
+                        collect_artifacts(std::iter::once(&result))
+                            .into_values()
+                            .flat_map(|artifacts| artifacts.iter())
+                            .map(ProducedArtifact::borrow)
+                            .cloned()
+                    });
+                },
+                Err(root_errors) => errors.extend(root_errors),
+            }
+        }
+
+        if received == 0 {
+            Err(anyhow!("No result received..."))
+        } else {
+            Ok((results, errors))
         }
     }
 }
@@ -455,6 +643,9 @@ struct TaskPreparation<'a> {
     git_commit_env: Option<&'a (EnvironmentVariableName, String)>,
     source_cache: &'a SourceCache,
     scheduler: &'a EndpointScheduler,
+    job_limit: Option<&'a Semaphore>,
+    network_mode: Option<&'a str>,
+    status: &'a SubmitStatus,
     staging_store: Arc<RwLock<StagingStore>>,
     release_stores: Vec<Arc<ReleaseStore>>,
     database: Pool<ConnectionManager<PgConnection>>,
@@ -473,9 +664,13 @@ struct JobTask<'a> {
     git_commit_env: Option<&'a (EnvironmentVariableName, String)>,
     source_cache: &'a SourceCache,
     scheduler: &'a EndpointScheduler,
+    job_limit: Option<&'a Semaphore>,
+    network_mode: Option<&'a str>,
+    status: &'a SubmitStatus,
     staging_store: Arc<RwLock<StagingStore>>,
     release_stores: Vec<Arc<ReleaseStore>>,
     database: Pool<ConnectionManager<PgConnection>>,
+    mode: TaskMode,
 
     /// Channel where the dependencies arrive
     receiver: Receiver<JobResult>,
@@ -523,7 +718,7 @@ impl<'a> Drop for JobTask<'a> {
 }
 
 impl<'a> JobTask<'a> {
-    fn new(receiver: Receiver<JobResult>, prep: TaskPreparation<'a>, sender: Vec<Sender<JobResult>>) -> Self {
+    fn new(receiver: Receiver<JobResult>, prep: TaskPreparation<'a>, sender: Vec<Sender<JobResult>>, mode: TaskMode) -> Self {
         let bar = prep.bar.clone();
         bar.set_message(format!("[{} {} {}]: Booting",
             prep.jobdef.job.uuid(),
@@ -540,9 +735,13 @@ impl<'a> JobTask<'a> {
             git_commit_env: prep.git_commit_env,
             source_cache: prep.source_cache,
             scheduler: prep.scheduler,
+            job_limit: prep.job_limit,
+            network_mode: prep.network_mode,
+            status: prep.status,
             staging_store: prep.staging_store,
             release_stores: prep.release_stores,
             database: prep.database.clone(),
+            mode,
 
             receiver,
             sender,
@@ -560,15 +759,14 @@ impl<'a> JobTask<'a> {
         });
 
         let dep_len = self.jobdef.dependencies.len();
-        // A list of job run results from dependencies that were received from the tasks for the
-        // dependencies
-        let mut received_dependencies: HashMap<Uuid, Vec<ProducedArtifact>> = HashMap::with_capacity(dep_len);
+        // The job run results that were received from the tasks for the dependencies
+        let mut received_dependencies: HashMap<Uuid, Arc<JobArtifacts>> = HashMap::with_capacity(dep_len);
 
         // A list of errors that were received from the tasks for the dependencies
         let mut received_errors: HashMap<Uuid, Error> = HashMap::with_capacity(dep_len);
 
         // Helper function to check whether all UUIDs are in a list of UUIDs
-        let all_dependencies_are_in = |dependency_uuids: &[Uuid], list: &HashMap<Uuid, Vec<_>>| {
+        let all_dependencies_are_in = |dependency_uuids: &[Uuid], list: &HashMap<Uuid, Arc<JobArtifacts>>| {
             dependency_uuids.iter().all(|dependency_uuid| {
                 list.keys().any(|id| id == dependency_uuid)
             })
@@ -600,6 +798,7 @@ impl<'a> JobTask<'a> {
                 // And we know that we have at least one sender
                 error!("[{}]: Received errors = {}", self.jobdef.job.uuid(), received_errors.display_error_map());
                 self.sender[0].send(Err(received_errors)).await;
+                self.status.job_failed(self.jobdef.job.uuid());
 
                 // ... and stop operation, because the whole tree will fail anyways.
                 self.bar.finish_with_message(format!("[{} {} {}] Stopping, errors from child received",
@@ -614,9 +813,24 @@ impl<'a> JobTask<'a> {
             }
         }
 
+        if self.mode == TaskMode::Skip {
+            trace!("[{}]: Skipping, sending dependencies to parent: {:?}", self.jobdef.job.uuid(), received_dependencies);
+            let result = JobArtifacts::new(*self.jobdef.job.uuid(), vec![], &received_dependencies);
+            for s in self.sender.iter() {
+                s.send(Ok(result.clone())).await?;
+            }
+            self.status.job_done(self.jobdef.job.uuid());
+            self.bar.finish_with_message(format!("[{} {} {}] Skipped, only dependencies are built",
+                self.jobdef.job.uuid(),
+                self.jobdef.job.package().name(),
+                self.jobdef.job.package().version()));
+            return Ok(())
+        }
+
         // Check if any of the received dependencies was built (and not reused).
         // If any dependency was built, we need to build as well.
-        let any_dependency_was_built = received_dependencies.values()
+        let any_dependency_was_built = collect_artifacts(received_dependencies.values())
+            .values()
             .flat_map(|v| v.iter())
             .any(ProducedArtifact::was_build);
 
@@ -645,6 +859,7 @@ impl<'a> JobTask<'a> {
                 .package(self.jobdef.job.package())
                 .release_stores(&self.release_stores)
                 .image_name(Some(self.jobdef.job.image()))
+                .phases(Some(self.jobdef.job.script_phases().as_slice()))
 
                 // We can simply pass the staging store here, because it doesn't hurt. There are
                 // two scenarios:
@@ -689,22 +904,38 @@ impl<'a> JobTask<'a> {
                 .filter_map(|(full_artifact_path, _)| {
                     trace!("Searching for {:?} in stores", full_artifact_path.display());
                     if let Some(ap) = staging_store.get(full_artifact_path.artifact_path()) {
-                        Some(ap.clone())
+                        Some((ap.clone(), None))
                     } else {
                         self.release_stores
                             .iter()
-                            .find_map(|rs| rs.get(full_artifact_path.artifact_path()))
-                            .cloned()
+                            .find_map(|rs| {
+                                rs.get(full_artifact_path.artifact_path())
+                                    .map(|ap| (ap.clone(), Some(rs.clone())))
+                            })
                     }
                 })
-                .map(ProducedArtifact::Reused)
-                .collect::<Vec<ProducedArtifact>>();
+                .collect::<Vec<(ArtifactPath, Option<Arc<ReleaseStore>>)>>();
+
+            // Released artifacts are only reused if their signature is still valid
+            let mut artifacts = {
+                let signer = ReleaseSigner::from_config(self.config.release_signing().as_ref());
+                let mut verified = Vec::with_capacity(artifacts.len());
+                for (ap, release_store) in artifacts.drain(..) {
+                    if let (Some(signer), Some(rs)) = (signer.as_ref(), release_store) {
+                        signer.verify_in_store(&rs, &ap)
+                            .await
+                            .with_context(|| anyhow!("Verifying signature of released artifact {}", ap.display()))?;
+                    }
+                    verified.push(ProducedArtifact::Reused(ap));
+                }
+                verified
+            };
 
             if !artifacts.is_empty() {
-                received_dependencies.insert(*self.jobdef.job.uuid(), artifacts);
-                trace!("[{}]: Sending to parent: {:?}", self.jobdef.job.uuid(), received_dependencies);
+                let result = JobArtifacts::new(*self.jobdef.job.uuid(), artifacts, &received_dependencies);
+                trace!("[{}]: Sending to parent: {:?}", self.jobdef.job.uuid(), result);
                 for s in self.sender.iter() {
-                    s.send(Ok(received_dependencies.clone()))
+                    s.send(Ok(result.clone()))
                         .await
                         .context("Cannot send received dependencies to parent")
                         .with_context(|| {
@@ -714,6 +945,7 @@ impl<'a> JobTask<'a> {
                                 self.jobdef.job.package().version())
                         })?;
                 }
+                self.status.job_done(self.jobdef.job.uuid());
                 self.bar.finish_with_message(format!("[{} {} {}] Reusing artifact",
                     self.jobdef.job.uuid(),
                     self.jobdef.job.package().name(),
@@ -722,11 +954,30 @@ impl<'a> JobTask<'a> {
             }
         }
 
-        // Map the list of received dependencies from
-        //      Vec<(Uuid, Vec<ArtifactPath>)>
-        // to
+        if self.mode == TaskMode::ReuseOnly {
+            let mut errormap = HashMap::with_capacity(1);
+            errormap.insert(*self.jobdef.job.uuid(), anyhow!(
+                "No artifacts found for dependency {} {}, but dependencies are not built",
+                self.jobdef.job.package().name(),
+                self.jobdef.job.package().version()
+            ));
+
+            self.sender[0]
+                .send(Err(errormap))
+                .await
+                .context("Failed sending error to parent")
+                .with_context(|| format!("Failed sending error from job {}", self.jobdef.job.uuid()))?;
+            self.status.job_failed(self.jobdef.job.uuid());
+            self.bar.finish_with_message(format!("[{} {} {}] No artifacts to reuse",
+                self.jobdef.job.uuid(),
+                self.jobdef.job.package().name(),
+                self.jobdef.job.package().version()));
+            return Ok(())
+        }
+
+        // Map the received dependencies (and their dependencies) to
         //      Vec<ArtifactPath>
-        let dependency_artifacts = received_dependencies
+        let dependency_artifacts = collect_artifacts(received_dependencies.values())
             .values()
             .flat_map(|v| v.iter())
             .map(ProducedArtifact::borrow)
@@ -746,17 +997,42 @@ impl<'a> JobTask<'a> {
             self.config,
             self.git_author_env,
             self.git_commit_env,
+            self.network_mode,
             dependency_artifacts)?;
 
-        self.bar.set_message(format!("[{} {} {}]: Scheduling...",
+        self.bar.set_message(format!("[{} {} {}]: Scheduling...{}",
             self.jobdef.job.uuid(),
             self.jobdef.job.package().name(),
-            self.jobdef.job.package().version()
+            self.jobdef.job.package().version(),
+            self.status
+                .estimate_of(self.jobdef.job.uuid())
+                .map(|d| format!(" (estimated {})", crate::db::format_estimate(d)))
+                .unwrap_or_default()
         ));
         let job_uuid = *self.jobdef.job.uuid();
 
+        // Wait until the job may run, if the number of parallel jobs is limited
+        //
+        // The permit is held until the job finished on the endpoint.
+        let permit = match self.job_limit {
+            Some(limit) => Some({
+                limit.acquire()
+                    .await
+                    .with_context(|| anyhow!("Waiting for a free job slot for job {}", job_uuid))?
+            }),
+            None => None,
+        };
+
         // Schedule the job on the scheduler
-        match self.scheduler.schedule_job(runnable, self.bar.clone()).await?.run().await? {
+        //
+        // The job only counts as running once it got an endpoint.
+        let handle = self.scheduler.schedule_job(runnable, self.bar.clone()).await?;
+        let running = self.status.job_started();
+        let job_result = handle.run().await?;
+        drop(permit);
+        self.status.job_finished(running, self.jobdef.job.uuid(), job_result.is_ok());
+
+        match job_result {
             Err(e) => {
                 trace!("[{}]: Scheduler returned error = {:?}", self.jobdef.job.uuid(), e);
                 // ... and we send that to our parent
@@ -783,9 +1059,9 @@ impl<'a> JobTask<'a> {
                 // mark the produced artifacts as "built" (rather than reused)
                 let artifacts = artifacts.into_iter().map(ProducedArtifact::Built).collect();
 
-                received_dependencies.insert(*self.jobdef.job.uuid(), artifacts);
+                let result = JobArtifacts::new(*self.jobdef.job.uuid(), artifacts, &received_dependencies);
                 for s in self.sender.iter() {
-                    s.send(Ok(received_dependencies.clone())).await?;
+                    s.send(Ok(result.clone())).await?;
                 }
             },
         }
@@ -802,13 +1078,12 @@ impl<'a> JobTask<'a> {
     /// Return Ok(true) if we should continue operation
     /// Return Ok(false) if the channel is empty and we're done receiving or if the channel is
     /// empty and there were errors collected
-    async fn perform_receive(&mut self, received_dependencies: &mut HashMap<Uuid, Vec<ProducedArtifact>>, received_errors: &mut HashMap<Uuid, Error>) -> Result<bool> {
+    async fn perform_receive(&mut self, received_dependencies: &mut HashMap<Uuid, Arc<JobArtifacts>>, received_errors: &mut HashMap<Uuid, Error>) -> Result<bool> {
         match self.receiver.recv().await {
-            Some(Ok(mut v)) => {
-                // The task we depend on succeeded and returned an
-                // (uuid of the job, [ArtifactPath])
+            Some(Ok(v)) => {
+                // The task we depend on succeeded and returned its artifacts
                 trace!("[{}]: Received: {:?}", self.jobdef.job.uuid(), v);
-                received_dependencies.extend(v);
+                received_dependencies.insert(v.job, v);
                 Ok(true)
             },
             Some(Err(mut e)) => {
@@ -853,3 +1128,32 @@ impl<'a> JobTask<'a> {
 
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn artifacts(job: Uuid, name: &str, dependencies: &[&Arc<JobArtifacts>]) -> Arc<JobArtifacts> {
+        let dependencies = dependencies.iter().map(|d| (d.job, Arc::clone(d))).collect();
+        let artifact = ProducedArtifact::Built(ArtifactPath::new_unchecked(PathBuf::from(name)));
+        JobArtifacts::new(job, vec![artifact], &dependencies)
+    }
+
+    #[test]
+    fn test_collect_artifacts_of_diamond() {
+        let (a, b, c, d) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        let d_result = artifacts(d, "d.pkg", &[]);
+        let b_result = artifacts(b, "b.pkg", &[&d_result]);
+        let c_result = artifacts(c, "c.pkg", &[&d_result]);
+        let a_result = artifacts(a, "a.pkg", &[&b_result, &c_result]);
+
+        let collected = collect_artifacts(std::iter::once(&a_result));
+        assert_eq!(collected.len(), 4);
+        assert_eq!(collected.values().map(|v| v.len()).sum::<usize>(), 4);
+        assert!(collected.contains_key(&d));
+
+        let collected = collect_artifacts([&b_result, &c_result]);
+        assert_eq!(collected.len(), 3);
+        assert!(!collected.contains_key(&a));
+    }
+}
@@ -0,0 +1,228 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::sync::Mutex;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use anyhow::Error;
+use anyhow::Result;
+use indicatif::ProgressBar;
+use uuid::Uuid;
+
+/// The overall status of the jobs of a submit
+///
+/// The status is shown in a summary progress bar and can be printed as a plain text status line.
+#[derive(Debug)]
+pub struct SubmitStatus {
+    total: usize,
+    running: AtomicUsize,
+    done: AtomicUsize,
+    failed: AtomicUsize,
+    bar: ProgressBar,
+
+    /// The estimated durations of the jobs that did not finish yet
+    remaining: Mutex<HashMap<Uuid, Duration>>,
+
+    /// The number of jobs that run at the same time, used to estimate the remaining time
+    parallelism: usize,
+}
+
+impl SubmitStatus {
+    pub fn new(total: usize, bar: ProgressBar) -> Self {
+        bar.set_length(total as u64);
+        let status = SubmitStatus {
+            total,
+            running: AtomicUsize::new(0),
+            done: AtomicUsize::new(0),
+            failed: AtomicUsize::new(0),
+            bar,
+            remaining: Mutex::new(HashMap::new()),
+            parallelism: 1,
+        };
+        status.update_bar();
+        status
+    }
+
+    /// Show the estimated remaining time of the submit, from the estimated durations of its jobs
+    ///
+    /// The estimate assumes that `parallelism` jobs run at the same time.
+    pub fn with_estimates(self, estimates: HashMap<Uuid, Duration>, parallelism: usize) -> Self {
+        let status = SubmitStatus {
+            remaining: Mutex::new(estimates),
+            parallelism: parallelism.max(1),
+            ..self
+        };
+        status.update_bar();
+        status
+    }
+
+    /// A job was scheduled on an endpoint
+    ///
+    /// The job counts as running until the returned guard is dropped.
+    pub fn job_started(&self) -> RunningJob<'_> {
+        self.running.fetch_add(1, Ordering::SeqCst);
+        self.update_bar();
+        RunningJob(self)
+    }
+
+    /// A job that was scheduled on an endpoint finished
+    pub fn job_finished(&self, running: RunningJob<'_>, job: &Uuid, success: bool) {
+        drop(running);
+        if success {
+            self.job_done(job)
+        } else {
+            self.job_failed(job)
+        }
+    }
+
+    /// A job finished without running, e.g. because its artifacts were reused
+    pub fn job_done(&self, job: &Uuid) {
+        self.done.fetch_add(1, Ordering::SeqCst);
+        self.remove_estimate(job);
+        self.update_bar();
+    }
+
+    /// A job failed without running, e.g. because one of its dependencies failed
+    pub fn job_failed(&self, job: &Uuid) {
+        self.failed.fetch_add(1, Ordering::SeqCst);
+        self.remove_estimate(job);
+        self.update_bar();
+    }
+
+    /// The estimated duration of a job that did not finish yet
+    pub fn estimate_of(&self, job: &Uuid) -> Option<Duration> {
+        self.remaining.lock().ok()?.get(job).copied()
+    }
+
+    fn remove_estimate(&self, job: &Uuid) {
+        if let Ok(mut remaining) = self.remaining.lock() {
+            remaining.remove(job);
+        }
+    }
+
+    /// The estimated remaining time of the submit, if there are estimates for unfinished jobs
+    fn estimated_remaining(&self) -> Option<Duration> {
+        let remaining = self.remaining.lock().ok()?;
+        if remaining.is_empty() {
+            None
+        } else {
+            Some(remaining.values().sum::<Duration>() / self.parallelism as u32)
+        }
+    }
+
+    /// Finish the summary progress bar
+    pub fn finish(&self) {
+        self.bar.finish_with_message(self.to_string());
+    }
+
+    /// Print the status as plain text line
+    pub fn print(&self) -> Result<()> {
+        writeln!(std::io::stdout(), "{self}").map_err(Error::from)
+    }
+
+    /// Print the status line in the passed interval
+    ///
+    /// This only returns if printing failed.
+    pub async fn report(&self, interval: Duration) -> Error {
+        loop {
+            tokio::time::sleep(interval).await;
+            if let Err(e) = self.print() {
+                return e
+            }
+        }
+    }
+
+    fn update_bar(&self) {
+        let finished = self.done.load(Ordering::SeqCst) + self.failed.load(Ordering::SeqCst);
+        self.bar.set_position(finished as u64);
+        self.bar.set_message(self.to_string());
+    }
+}
+
+/// A job that runs on an endpoint, see `SubmitStatus::job_started()`
+///
+/// The job is not counted as running anymore when this is dropped, also if the job did not finish
+/// because of an error.
+#[must_use]
+#[derive(Debug)]
+pub struct RunningJob<'a>(&'a SubmitStatus);
+
+impl Drop for RunningJob<'_> {
+    fn drop(&mut self) {
+        self.0.running.fetch_sub(1, Ordering::SeqCst);
+        self.0.update_bar();
+    }
+}
+
+impl std::fmt::Display for SubmitStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        write!(f, "Jobs: {total} total, {running} running, {done} done, {failed} failed",
+            total = self.total,
+            running = self.running.load(Ordering::SeqCst),
+            done = self.done.load(Ordering::SeqCst),
+            failed = self.failed.load(Ordering::SeqCst))?;
+
+        if let Some(remaining) = self.estimated_remaining() {
+            write!(f, ", ETA ~{}", crate::db::format_estimate(remaining))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_line() {
+        let status = SubmitStatus::new(4, ProgressBar::hidden());
+        let a = status.job_started();
+        let b = status.job_started();
+        status.job_done(&Uuid::new_v4());
+        assert_eq!(status.to_string(), "Jobs: 4 total, 2 running, 1 done, 0 failed");
+
+        status.job_finished(a, &Uuid::new_v4(), true);
+        status.job_finished(b, &Uuid::new_v4(), false);
+        assert_eq!(status.to_string(), "Jobs: 4 total, 0 running, 2 done, 1 failed");
+    }
+
+    #[test]
+    fn test_running_job_dropped() {
+        let status = SubmitStatus::new(2, ProgressBar::hidden());
+        let running = status.job_started();
+        assert_eq!(status.to_string(), "Jobs: 2 total, 1 running, 0 done, 0 failed");
+
+        drop(running);
+        assert_eq!(status.to_string(), "Jobs: 2 total, 0 running, 0 done, 0 failed");
+    }
+
+    #[test]
+    fn test_status_line_with_estimates() {
+        let (a, b, c) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        let estimates = HashMap::from([
+            (a, Duration::from_secs(120)),
+            (b, Duration::from_secs(60)),
+            (c, Duration::from_secs(60)),
+        ]);
+        let status = SubmitStatus::new(3, ProgressBar::hidden()).with_estimates(estimates, 2);
+        assert_eq!(status.to_string(), "Jobs: 3 total, 0 running, 0 done, 0 failed, ETA ~2m");
+
+        status.job_done(&a);
+        assert_eq!(status.to_string(), "Jobs: 3 total, 0 running, 1 done, 0 failed, ETA ~1m");
+
+        status.job_failed(&b);
+        status.job_done(&c);
+        assert_eq!(status.to_string(), "Jobs: 3 total, 0 running, 2 done, 1 failed");
+    }
+}
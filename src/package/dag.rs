@@ -9,10 +9,14 @@
 //
 
 use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::collections::BinaryHeap;
 use std::collections::HashMap;
 use std::io::Result as IoResult;
 use std::io::Write;
 
+use anyhow::Context;
 use anyhow::Error;
 use anyhow::Result;
 use anyhow::anyhow;
@@ -23,10 +27,10 @@ use itertools::Itertools;
 use tracing::trace;
 use ptree::Style;
 use ptree::TreeItem;
-use resiter::AndThen;
 
 use crate::package::Package;
 use crate::package::PackageName;
+use crate::package::PackageVersion;
 use crate::package::PackageVersionConstraint;
 use crate::package::condition::ConditionCheckable;
 use crate::package::condition::ConditionData;
@@ -34,10 +38,41 @@ use crate::package::dependency::ParseDependency;
 use crate::repository::Repository;
 
 
+/// The type of the dependency an edge in the `Dag` represents
+#[derive(Clone, Copy, Debug, Eq, PartialEq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DependencyType {
+    Build,
+    Runtime,
+
+    /// The package is both a build and a runtime dependency
+    BuildAndRuntime,
+}
+
+impl DependencyType {
+    fn merge(self, other: DependencyType) -> DependencyType {
+        if self == other {
+            self
+        } else {
+            DependencyType::BuildAndRuntime
+        }
+    }
+}
+
+impl std::fmt::Display for DependencyType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DependencyType::Build => write!(f, "build"),
+            DependencyType::Runtime => write!(f, "runtime"),
+            DependencyType::BuildAndRuntime => write!(f, "build+runtime"),
+        }
+    }
+}
+
 #[derive(Debug, Getters)]
 pub struct Dag {
     #[getset(get = "pub")]
-    dag: daggy::Dag<Package, i8>,
+    dag: daggy::Dag<Package, DependencyType>,
 
     #[getset(get = "pub")]
     root_idx: daggy::NodeIndex,
@@ -66,110 +101,118 @@ impl Dag {
 
         /// Helper fn to get the dependencies of a package
         ///
-        /// This function helps getting the dependencies of a package as an iterator over
-        /// (Name, Version).
+        /// This function helps getting the dependencies of a package as a list of
+        /// (Name, Version, Type).
         ///
         /// It also filters out dependencies that do not match the `conditional_data` passed and
-        /// makes the dependencies unique over (name, version).
-        fn get_package_dependencies<'a>(package: &'a Package, conditional_data: &'a ConditionData<'_>)
-            -> impl Iterator<Item = Result<(PackageName, PackageVersionConstraint)>> + 'a
+        /// makes the dependencies unique over (name, version). A dependency that is both a build
+        /// and a runtime dependency is listed once.
+        fn get_package_dependencies(package: &Package, conditional_data: &ConditionData<'_>)
+            -> Result<Vec<(PackageName, PackageVersionConstraint, DependencyType)>>
         {
-
-            package.dependencies()
+            let dependencies = package.dependencies()
                 .build()
                 .iter()
-                .map(move |d| process(d, conditional_data))
+                .map(|d| process(d, conditional_data).map(|res| (res, DependencyType::Build)))
                 .chain({
                     package.dependencies()
                         .runtime()
                         .iter()
-                        .map(move |d| process(d, conditional_data))
+                        .map(|d| process(d, conditional_data).map(|res| (res, DependencyType::Runtime)))
                 })
 
                 // Now filter out all dependencies where their condition did not match our
                 // `conditional_data`.
                 .filter(|res| match res {
-                    Ok((true, _, _)) => true,
-                    Ok((false, _, _)) => false,
+                    Ok(((true, _, _), _)) => true,
+                    Ok(((false, _, _), _)) => false,
                     Err(_) => true,
                 })
 
                 // Map out the boolean from the condition, because we don't need that later on
-                .map(|res| res.map(|(_, name, vers)| (name, vers)))
-
-                // Make all dependencies unique, because we don't want to build one dependency
-                // multiple times
-                .unique_by(|res| res.as_ref().ok().cloned())
+                .map(|res| res.map(|((_, name, vers), ty)| (name, vers, ty)));
+
+            // Make all dependencies unique, because we don't want to build one dependency
+            // multiple times
+            let mut unique: Vec<(PackageName, PackageVersionConstraint, DependencyType)> = vec![];
+            for dependency in dependencies {
+                let (name, vers, ty) = dependency?;
+                match unique.iter_mut().find(|(n, v, _)| *n == name && *v == vers) {
+                    Some(known) => known.2 = known.2.merge(ty),
+                    None => unique.push((name, vers, ty)),
+                }
+            }
+            Ok(unique)
         }
 
         fn add_sub_packages<'a>(
             repo: &'a Repository,
             mappings: &mut HashMap<&'a Package, daggy::NodeIndex>,
-            dag: &mut daggy::Dag<&'a Package, i8>,
+            dag: &mut daggy::Dag<&'a Package, DependencyType>,
             p: &'a Package,
             progress: Option<&ProgressBar>,
             conditional_data: &ConditionData<'_>,
         ) -> Result<()> {
-            get_package_dependencies(p, conditional_data)
-                .and_then_ok(|(name, constr)| {
+            get_package_dependencies(p, conditional_data)?
+                .into_iter()
+                .try_for_each(|(name, constr, _)| {
                     trace!("Dependency for {} {} found: {:?}", p.name(), p.version(), name);
-                    let packs = repo.find_with_version(&name, &constr);
-                    if packs.is_empty() {
-                        return Err(anyhow!("Dependency of {} {} not found: {} {}", p.name(), p.version(), name, constr))
-                    }
-                    trace!("Found in repo: {:?}", packs);
+                    let pack = repo.find_best_match(&name, &constr)
+                        .with_context(|| anyhow!("Resolving dependency of {} {}", p.name(), p.version()))?
+                        .ok_or_else(|| anyhow!("Dependency of {} {} not found: {} {}", p.name(), p.version(), name, constr))?;
+                    trace!("Found in repo: {:?}", pack);
 
                     // If we didn't check that dependency already
-                    if !mappings.keys().any(|p| packs.iter().any(|pk| pk.name() == p.name() && pk.version() == p.version())) {
-                        // recurse
-                        packs.into_iter()
-                            .try_for_each(|p| {
-                                let _ = progress.as_ref().map(|p| p.tick());
+                    if !mappings.contains_key(pack) {
+                        let _ = progress.as_ref().map(|p| p.tick());
 
-                                let idx = dag.add_node(p);
-                                mappings.insert(p, idx);
+                        let idx = dag.add_node(pack);
+                        mappings.insert(pack, idx);
 
-                                trace!("Recursing for: {:?}", p);
-                                add_sub_packages(repo, mappings, dag, p, progress, conditional_data)
-                            })
+                        trace!("Recursing for: {:?}", pack);
+                        add_sub_packages(repo, mappings, dag, pack, progress, conditional_data)
                     } else {
                         Ok(())
                     }
                 })
-                .collect::<Result<()>>()
         }
 
-        fn add_edges(mappings: &HashMap<&Package, daggy::NodeIndex>,
-            dag: &mut daggy::Dag<&Package, i8>,
+        fn add_edges(repo: &Repository,
+            mappings: &HashMap<&Package, daggy::NodeIndex>,
+            dag: &mut daggy::Dag<&Package, DependencyType>,
             conditional_data: &ConditionData<'_>,
         ) -> Result<()>
         {
-            for (package, idx) in mappings {
-                get_package_dependencies(package, conditional_data)
-                    .and_then_ok(|(name, constr)| {
-                        mappings
-                            .iter()
-                            .filter(|(package, _)| *package.name() == name && constr.matches(package.version()))
-                            .try_for_each(|(_, dep_idx)| {
-                                dag.add_edge(*idx, *dep_idx, 0)
+            // Add the edges in the order the packages were added to the tree, so that the order
+            // of the dependencies of a package is the same for the same repository
+            for (package, idx) in mappings.iter().sorted_by_key(|(_, idx)| **idx) {
+                get_package_dependencies(package, conditional_data)?
+                    .into_iter()
+                    .try_for_each(|(name, constr, ty)| {
+                        // The dependency resolves to the same package as in add_sub_packages(),
+                        // which already failed if it could not be found
+                        repo.find_best_match(&name, &constr)?
+                            .and_then(|dependency| mappings.get(dependency))
+                            .map(|dep_idx| {
+                                dag.add_edge(*idx, *dep_idx, ty)
                                     .map(|_| ())
                                     .map_err(Error::from)
                             })
-                    })
-                    .collect::<Result<()>>()?
+                            .unwrap_or(Ok(()))
+                    })?
             }
 
             Ok(())
         }
 
-        let mut dag: daggy::Dag<&Package, i8> = daggy::Dag::new();
+        let mut dag: daggy::Dag<&Package, DependencyType> = daggy::Dag::new();
         let mut mappings = HashMap::new();
 
         trace!("Making package Tree for {:?}", p);
         let root_idx = dag.add_node(&p);
         mappings.insert(&p, root_idx);
         add_sub_packages(repo, &mut mappings, &mut dag, &p, progress, conditional_data)?;
-        add_edges(&mappings, &mut dag, conditional_data)?;
+        add_edges(repo, &mappings, &mut dag, conditional_data)?;
         trace!("Finished makeing package Tree");
 
         Ok(Dag {
@@ -178,26 +221,145 @@ impl Dag {
         })
     }
 
-    /// Get all packages in the tree by reference
-    ///
-    /// # Warning
-    ///
-    /// The order of the packages is _NOT_ guaranteed by the implementation
+    /// Get all packages in the tree by reference, sorted by name and version
     pub fn all_packages(&self) -> Vec<&Package> {
         self.dag
             .graph()
             .node_indices()
             .filter_map(|idx| self.dag.graph().node_weight(idx))
+            .sorted_by(|a, b| (a.name(), a.version()).cmp(&(b.name(), b.version())))
+            .collect()
+    }
+
+    /// Get all packages in the tree in the order they are built in, dependencies first
+    ///
+    /// Packages that do not depend on each other are ordered by name and version.
+    pub fn build_order(&self) -> Vec<&Package> {
+        stable_build_order(&self.dag, |p| (p.name(), p.version()))
+            .into_iter()
+            .filter_map(|idx| self.dag.graph().node_weight(idx))
+            .collect()
+    }
+
+    /// Get the children of a node, sorted by name and version of the packages
+    fn sorted_children(&self, idx: daggy::NodeIndex) -> Vec<(daggy::EdgeIndex, daggy::NodeIndex)> {
+        let graph = self.dag.graph();
+        self.dag
+            .children(idx)
+            .iter(&self.dag)
+            .sorted_by_key(|(_, child)| graph.node_weight(*child).map(|p| (p.name(), p.version())))
+            .collect()
+    }
+
+    /// Get the packages that are in the tree in more than one version
+    ///
+    /// Each version is a separate node in the tree, so dependents get the artifacts of the
+    /// version they depend on.
+    pub fn packages_with_multiple_versions(&self) -> Vec<(&PackageName, Vec<&PackageVersion>)> {
+        let mut versions: BTreeMap<&PackageName, Vec<&PackageVersion>> = BTreeMap::new();
+        for package in self.all_packages() {
+            versions.entry(package.name()).or_default().push(package.version());
+        }
+
+        versions
+            .into_iter()
+            .filter(|(_, versions)| versions.len() > 1)
+            .map(|(name, mut versions)| {
+                versions.sort_by(|a, b| a.cmp_version(b));
+                (name, versions)
+            })
             .collect()
     }
 
     pub fn display(&self) -> DagDisplay {
-        DagDisplay(self, self.root_idx)
+        DagDisplay(self, self.root_idx, None)
+    }
+
+    /// Render the Dag as graph in the DOT language, with the dependency types as edge labels
+    pub fn to_dot(&self) -> String {
+        let graph = self.dag.graph();
+        let node = |idx| {
+            graph.node_weight(idx)
+                .map(|p| format!("\"{} {}\"", p.name(), p.version()))
+                .unwrap_or_default()
+        };
+
+        let mut dot = String::from("digraph {\n");
+        for idx in graph.node_indices() {
+            dot.push_str(&format!("    {};\n", node(idx)));
+        }
+        for edge in graph.raw_edges() {
+            dot.push_str(&format!("    {} -> {} [label=\"{}\"];\n", node(edge.source()), node(edge.target()), edge.weight));
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Render the Dag as JSON tree, starting at the root package
+    ///
+    /// Packages that are required by multiple packages appear multiple times in the tree.
+    pub fn to_json(&self) -> serde_json::Value {
+        fn node(dag: &Dag, idx: daggy::NodeIndex, ty: Option<DependencyType>) -> serde_json::Value {
+            let mut value = serde_json::json!({});
+            if let Some(p) = dag.dag.graph().node_weight(idx) {
+                value["name"] = serde_json::Value::from(p.name().to_string());
+                value["version"] = serde_json::Value::from(p.version().to_string());
+            }
+            if let Some(ty) = ty {
+                value["type"] = serde_json::Value::from(ty.to_string());
+            }
+
+            let dependencies = dag.sorted_children(idx)
+                .into_iter()
+                .map(|(edge, child)| node(dag, child, dag.dag.edge_weight(edge).copied()))
+                .collect::<Vec<_>>();
+            value["dependencies"] = serde_json::Value::from(dependencies);
+            value
+        }
+
+        node(self, self.root_idx, None)
+    }
+}
+
+/// Sort the nodes of `dag` topologically, dependencies (the targets of the edges) first
+///
+/// Nodes that do not depend on each other are ordered by `key`, so the order is the same every
+/// time for the same tree.
+pub fn stable_build_order<'a, N, E, K, F>(dag: &'a daggy::Dag<N, E>, key: F) -> Vec<daggy::NodeIndex>
+    where K: Ord,
+          F: Fn(&'a N) -> K,
+{
+    let graph = dag.graph();
+
+    // The number of dependencies of each node that are not in the order yet
+    let mut pending = graph
+        .node_indices()
+        .map(|idx| (idx, dag.children(idx).iter(dag).count()))
+        .collect::<HashMap<_, _>>();
+
+    let mut ready = pending
+        .iter()
+        .filter(|(_, n)| **n == 0)
+        .map(|(idx, _)| Reverse((key(&graph[*idx]), *idx)))
+        .collect::<BinaryHeap<_>>();
+
+    let mut order = Vec::with_capacity(graph.node_count());
+    while let Some(Reverse((_, idx))) = ready.pop() {
+        order.push(idx);
+        for (_, parent) in dag.parents(idx).iter(dag) {
+            if let Some(n) = pending.get_mut(&parent) {
+                *n -= 1;
+                if *n == 0 {
+                    ready.push(Reverse((key(&graph[parent]), parent)));
+                }
+            }
+        }
     }
+    order
 }
 
 #[derive(Clone)]
-pub struct DagDisplay<'a>(&'a Dag, daggy::NodeIndex);
+pub struct DagDisplay<'a>(&'a Dag, daggy::NodeIndex, Option<DependencyType>);
 
 impl<'a> TreeItem for DagDisplay<'a> {
     type Child = Self;
@@ -206,13 +368,17 @@ impl<'a> TreeItem for DagDisplay<'a> {
         let p = self.0.dag.graph().node_weight(self.1)
             .ok_or_else(|| anyhow!("Error finding node: {:?}", self.1))
             .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
-        write!(f, "{} {}", p.name(), p.version())
+        write!(f, "{} {}", p.name(), p.version())?;
+        if let Some(ty) = self.2 {
+            write!(f, " ({ty})")?;
+        }
+        Ok(())
     }
 
     fn children(&self) -> Cow<[Self::Child]> {
-        let c = self.0.dag.children(self.1);
-        Cow::from(c.iter(&self.0.dag)
-            .map(|(_, idx)| DagDisplay(self.0, idx))
+        Cow::from(self.0.sorted_children(self.1)
+            .into_iter()
+            .map(|(edge, idx)| DagDisplay(self.0, idx, self.0.dag.edge_weight(edge).copied()))
             .collect::<Vec<_>>()
         )
     }
@@ -232,6 +398,7 @@ mod tests {
     use crate::package::tests::pname;
     use crate::package::tests::pversion;
     use crate::util::docker::ImageName;
+    use crate::util::EnvironmentVariableName;
 
     use indicatif::ProgressBar;
 
@@ -397,6 +564,32 @@ mod tests {
         assert!(ps.iter().any(|p| *p.name() == pname("p3")));
         assert!(ps.iter().any(|p| *p.name() == pname("p5")));
         assert!(ps.iter().any(|p| *p.name() == pname("p6")));
+
+        let order = r.build_order().into_iter().map(|p| p.name().to_string()).collect::<Vec<_>>();
+        let position = |name: &str| order.iter().position(|n| n == name).unwrap();
+        assert_eq!(order.len(), 6);
+        assert_eq!(position("p1"), 5);
+        assert!(position("p3") < position("p2"));
+        assert!(position("p5") < position("p4"));
+        assert!(position("p6") < position("p4"));
+
+        // Packages that do not depend on each other are ordered by name
+        assert_eq!(order, vec!["p3", "p2", "p5", "p6", "p4", "p1"]);
+    }
+
+    #[test]
+    fn test_stable_build_order() {
+        let mut dag: daggy::Dag<&str, ()> = daggy::Dag::new();
+        let root = dag.add_node("root");
+        for name in ["c", "a", "b"] {
+            dag.add_child(root, (), name);
+        }
+
+        let order = stable_build_order(&dag, |name| *name)
+            .into_iter()
+            .map(|idx| dag[idx])
+            .collect::<Vec<_>>();
+        assert_eq!(order, vec!["a", "b", "c", "root"]);
     }
 
     #[test]
@@ -780,5 +973,126 @@ mod tests {
         assert!(ps.iter().any(|p| *p.version() == pversion("2")));
     }
 
+    // Test whether a diamond dependency on two versions of the same package results in both
+    // versions in the DAG, each connected to the package depending on it
+    #[test]
+    fn test_add_diamond_with_different_versions() {
+        let mut btree = BTreeMap::new();
+
+        let p1 = {
+            let mut pack = package("a", "1", "https://rust-lang.org", "123");
+            let ds = vec![Dependency::from(String::from("b =1")), Dependency::from(String::from("c =1"))];
+            pack.set_dependencies(Dependencies::with_runtime_dependencies(ds));
+            btree.insert((pname("a"), pversion("1")), pack.clone());
+            pack
+        };
+
+        for (name, dep) in [("b", "d =1"), ("c", "d =2")] {
+            let mut pack = package(name, "1", "https://rust-lang.org", "124");
+            let ds = Dependencies::with_runtime_dependency(Dependency::from(String::from(dep)));
+            pack.set_dependencies(ds);
+            btree.insert((pname(name), pversion("1")), pack);
+        }
+
+        for vers in ["1", "2"] {
+            let pack = package("d", vers, "https://rust-lang.org", "125");
+            btree.insert((pname("d"), pversion(vers)), pack);
+        }
+
+        let repo = Repository::from(btree);
+        let condition_data = ConditionData {
+            image_name: None,
+            env: &[],
+        };
+
+        let dag = Dag::for_root_package(p1, &repo, None, &condition_data).unwrap();
+        assert_eq!(dag.all_packages().len(), 5);
+        assert_eq!(dag.dag().edge_count(), 4);
+
+        let dot = dag.to_dot();
+        assert!(dot.contains(r#""b 1" -> "d 1""#), "{}", dot);
+        assert!(dot.contains(r#""c 1" -> "d 2""#), "{}", dot);
+
+        let multiple = dag.packages_with_multiple_versions();
+        assert_eq!(multiple, vec![(&pname("d"), vec![&pversion("1"), &pversion("2")])]);
+    }
+
+    // Test whether the same package definition results in different DAGs depending on the
+    // environment passed at submit time
+    #[test]
+    fn test_add_two_dependent_packages_with_env_conditional() {
+        let condition = {
+            let mut env_eq = BTreeMap::new();
+            env_eq.insert(EnvironmentVariableName::from("FLAVOR"), String::from("full"));
+            Condition::new(None, Some(env_eq), None)
+        };
+        let (p1, repo) = repo_with_ab_packages_with_condition(condition);
+
+        let has_b = |env: &[(EnvironmentVariableName, String)]| {
+            let condition_data = ConditionData {
+                image_name: None,
+                env,
+            };
+
+            Dag::for_root_package(p1.clone(), &repo, None, &condition_data)
+                .unwrap()
+                .all_packages()
+                .iter()
+                .any(|p| *p.name() == pname("b"))
+        };
+
+        assert!(!has_b(&[]));
+        assert!(!has_b(&[(EnvironmentVariableName::from("FLAVOR"), String::from("minimal"))]));
+        assert!(has_b(&[(EnvironmentVariableName::from("FLAVOR"), String::from("full"))]));
+    }
+
+    #[test]
+    fn test_dependency_types_in_output() {
+        let mut btree = BTreeMap::new();
+
+        let mut p1 = {
+            let pack = package("a", "1", "https://rust-lang.org", "123");
+            btree.insert((pname("a"), pversion("1")), pack.clone());
+            pack
+        };
+
+        for (name, vers) in [("b", "2"), ("c", "3")] {
+            let pack = package(name, vers, "https://rust-lang.org", "124");
+            btree.insert((pname(name), pversion(vers)), pack);
+        }
+
+        let ds: Dependencies = toml::from_str(
+            r#"
+            build = ["b =2"]
+            runtime = ["b =2", "c =3"]
+        "#,
+        )
+        .unwrap();
+        p1.set_dependencies(ds);
+
+        let repo = Repository::from(btree);
+        let condition_data = ConditionData {
+            image_name: None,
+            env: &[],
+        };
+
+        let dag = Dag::for_root_package(p1, &repo, None, &condition_data).unwrap();
+        assert_eq!(dag.dag().edge_count(), 2);
+
+        let dot = dag.to_dot();
+        assert!(dot.contains(r#""a 1" -> "b 2" [label="build+runtime"];"#), "{}", dot);
+        assert!(dot.contains(r#""a 1" -> "c 3" [label="runtime"];"#), "{}", dot);
+
+        let json = dag.to_json();
+        assert_eq!(json["name"], "a");
+        let mut deps = json["dependencies"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|d| (d["name"].as_str().unwrap(), d["type"].as_str().unwrap()))
+            .collect::<Vec<_>>();
+        deps.sort();
+        assert_eq!(deps, vec![("b", "build+runtime"), ("c", "runtime")]);
+    }
 }
 
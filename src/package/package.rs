@@ -8,6 +8,7 @@
 // SPDX-License-Identifier: EPL-2.0
 //
 
+use std::collections::BTreeMap;
 use std::collections::HashMap;
 use std::path::PathBuf;
 
@@ -20,6 +21,8 @@ use crate::package::name::*;
 use crate::package::source::*;
 use crate::package::version::*;
 use crate::package::{Phase, PhaseName};
+use crate::util::docker::ContainerPaths;
+use crate::util::docker::ContainerUser;
 use crate::util::docker::ImageName;
 use crate::util::EnvironmentVariableName;
 
@@ -47,6 +50,13 @@ pub struct Package {
     #[serde(skip_serializing_if = "Option::is_none")]
     environment: Option<HashMap<EnvironmentVariableName, String>>,
 
+    /// The environment variables that can be passed to the build of the package
+    ///
+    /// If not set, all variables are accepted.
+    #[getset(get = "pub")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    allowed_env: Option<Vec<EnvironmentVariableName>>,
+
     #[getset(get = "pub")]
     #[serde(skip_serializing_if = "Option::is_none")]
     allowed_images: Option<Vec<ImageName>>,
@@ -58,6 +68,38 @@ pub struct Package {
     #[getset(get = "pub")]
     phases: HashMap<PhaseName, Phase>,
 
+    /// The paths inside the container, if they differ from the ones configured for the image
+    #[serde(default, skip_serializing_if = "ContainerPaths::is_empty")]
+    #[getset(get = "pub")]
+    container_paths: ContainerPaths,
+
+    /// The user the container runs as, if it differs from the one configured for the containers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[getset(get = "pub")]
+    container_user: Option<ContainerUser>,
+
+    /// The network mode of the container (e.g. "none" for builds without network access), if it
+    /// differs from the one configured for the endpoint
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[getset(get = "pub")]
+    network_mode: Option<String>,
+
+    /// The scheduling priority of the package
+    ///
+    /// If all endpoints are busy, jobs for packages with a higher priority get the next free
+    /// endpoint first. Defaults to 0.
+    #[serde(default)]
+    #[getset(get = "pub")]
+    priority: i64,
+
+    /// Commands that are run in the container before the script, by name
+    ///
+    /// The output of each command (e.g. `gcc --version`) is recorded as metadata of the job, so
+    /// the toolchain that built an artifact can be looked up later.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    #[getset(get = "pub")]
+    capture: BTreeMap<String, String>,
+
     /// Meta field
     ///
     /// Contains only key-value string-string data, that the packager can set for a package and
@@ -92,13 +134,59 @@ impl Package {
             dependencies,
             patches: vec![],
             environment: None,
+            allowed_env: None,
             allowed_images: None,
             denied_images: None,
             phases: HashMap::new(),
+            container_paths: ContainerPaths::default(),
+            container_user: None,
+            network_mode: None,
+            priority: 0,
+            capture: BTreeMap::new(),
             meta: None,
         }
     }
 
+    /// Whether a variable may be passed to the build of the package
+    pub fn accepts_env(&self, name: &EnvironmentVariableName) -> bool {
+        self.allowed_env
+            .as_ref()
+            .map(|allowed| allowed.contains(name))
+            .unwrap_or(true)
+    }
+
+    /// The images the package can be built on, out of the `configured` ones
+    ///
+    /// These are the allowed images of the package (or all configured images, if the package does
+    /// not restrict them), without the denied images.
+    pub fn compatible_images<'a>(&self, configured: impl Iterator<Item = &'a ImageName>) -> Vec<ImageName> {
+        let candidates = match self.allowed_images.as_ref() {
+            Some(allowed) => allowed.clone(),
+            None => configured.cloned().collect(),
+        };
+
+        candidates
+            .into_iter()
+            .filter(|image| {
+                self.denied_images
+                    .as_ref()
+                    .map(|denied| !denied.contains(image))
+                    .unwrap_or(true)
+            })
+            .collect()
+    }
+
+    #[cfg(test)]
+    pub fn set_images(&mut self, allowed: Option<Vec<ImageName>>, denied: Option<Vec<ImageName>>) {
+        self.allowed_images = allowed;
+        self.denied_images = denied;
+    }
+
+    #[cfg(test)]
+    pub fn set_priority(&mut self, priority: i64) {
+        self.priority = priority;
+    }
+
     #[cfg(test)]
     pub fn set_dependencies(&mut self, dependencies: Dependencies) {
         self.dependencies = dependencies;
@@ -177,6 +265,8 @@ impl<'a> std::fmt::Debug for DebugPackage<'a> {
             .map(|v| v.iter().try_for_each(|i| writeln!(f, "\t\t{i:?}")))
             .transpose()?;
 
+        writeln!(f, "\tPriority = {}", self.0.priority)?;
+
         writeln!(f, "\tPhases = ")?;
         self.0.phases
             .iter()
@@ -274,4 +364,19 @@ pub mod tests {
         let dependencies = Dependencies::empty();
         Package::new(name, version, version_is_semver, sources, dependencies)
     }
+
+    #[test]
+    fn test_compatible_images() {
+        let images = |names: &[&str]| names.iter().map(|n| ImageName::from(*n)).collect::<Vec<_>>();
+        let configured = images(&["debian:11", "debian:12", "rockylinux:9"]);
+        let mut p = package("a", "1", "https://rust-lang.org", "123");
+
+        assert_eq!(p.compatible_images(configured.iter()), configured);
+
+        p.set_images(None, Some(images(&["debian:12"])));
+        assert_eq!(p.compatible_images(configured.iter()), images(&["debian:11", "rockylinux:9"]));
+
+        p.set_images(Some(images(&["debian:12", "rockylinux:9"])), Some(images(&["debian:12"])));
+        assert_eq!(p.compatible_images(configured.iter()), images(&["rockylinux:9"]));
+    }
 }
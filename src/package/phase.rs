@@ -23,7 +23,6 @@ impl PhaseName {
     }
 }
 
-#[cfg(test)]
 impl From<String> for PhaseName {
     fn from(s: String) -> Self {
         PhaseName(s)
@@ -11,6 +11,9 @@
 // TODO: Is this really necessary?
 #![allow(clippy::format_push_string)]
 
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::path::PathBuf;
 use std::process::ExitStatus;
 
 use anyhow::anyhow;
@@ -48,9 +51,22 @@ impl From<String> for Script {
 #[derive(Clone, Debug)]
 pub struct Shebang(String);
 
+/// Load the themes available for highlighting scripts
+///
+/// These are the themes that come with syntect, plus the `.tmTheme` files from `theme_dir`, if
+/// given.
+pub fn load_highlight_themes(theme_dir: Option<&Path>) -> Result<ThemeSet> {
+    let mut ts = ThemeSet::load_defaults();
+    if let Some(dir) = theme_dir {
+        ts.add_from_folder(dir)
+            .with_context(|| anyhow!("Loading highlighting themes from {}", dir.display()))?;
+    }
+    Ok(ts)
+}
+
 impl Script {
-    pub fn highlighted<'a>(&'a self, script_theme: &'a str) -> HighlightedScript<'a> {
-        HighlightedScript::new(self, script_theme)
+    pub fn highlighted<'a>(&'a self, script_theme: &'a str, theme_dir: Option<&Path>) -> Result<HighlightedScript<'a>> {
+        HighlightedScript::new(self, script_theme, theme_dir)
     }
 
     pub fn lines_numbered(&self) -> impl Iterator<Item = (usize, &str)> {
@@ -109,17 +125,25 @@ pub struct HighlightedScript<'a> {
 }
 
 impl<'a> HighlightedScript<'a> {
-    fn new(script: &'a Script, script_theme: &'a str) -> Self {
-        HighlightedScript {
+    fn new(script: &'a Script, script_theme: &'a str, theme_dir: Option<&Path>) -> Result<Self> {
+        Ok(HighlightedScript {
             script,
             script_theme,
 
             ps: SyntaxSet::load_defaults_newlines(),
-            ts: ThemeSet::load_defaults(),
-        }
+            ts: load_highlight_themes(theme_dir)?,
+        })
     }
 
+    /// The highlighted lines of the script, or the plain lines if colors are disabled
     pub fn lines(&'a self) -> Result<impl Iterator<Item = String> + 'a> {
+        if !crate::util::output::colors_enabled() {
+            return Ok(LinesWithEndings::from(&self.script.0)
+                .map(String::from)
+                .collect::<Vec<String>>()
+                .into_iter());
+        }
+
         let syntax = self
             .ps
             .find_syntax_by_first_line(&self.script.0)
@@ -158,12 +182,23 @@ impl From<String> for Shebang {
     }
 }
 
+impl AsRef<str> for Shebang {
+    fn as_ref(&self) -> &str {
+        self.0.as_ref()
+    }
+}
+
 impl AsRef<str> for Script {
     fn as_ref(&self) -> &str {
         self.0.as_ref()
     }
 }
 
+/// The marker that includes another file into a phase script that is read from a file
+///
+/// The path of the included file is relative to the including file.
+const INCLUDE_MARKER: &str = "#BUTIDO:INCLUDE:";
+
 pub struct ScriptBuilder<'a> {
     shebang: &'a Shebang,
 }
@@ -179,7 +214,18 @@ impl<'a> ScriptBuilder<'a> {
         phaseorder: &[PhaseName],
         strict_mode: bool,
     ) -> Result<Script> {
+        self.build_with_files(package, phaseorder, strict_mode).map(|(script, _)| script)
+    }
+
+    /// Build the script, and return the SHA256 of all files the phase scripts were read from
+    pub fn build_with_files(
+        self,
+        package: &Package,
+        phaseorder: &[PhaseName],
+        strict_mode: bool,
+    ) -> Result<(Script, BTreeMap<PathBuf, String>)> {
         let mut script = format!("{shebang}\n", shebang = self.shebang.0);
+        let mut files = BTreeMap::new();
 
         for name in phaseorder {
             match package.phases().get(name) {
@@ -202,18 +248,14 @@ impl<'a> ScriptBuilder<'a> {
                     script.push('\n');
                 }
 
-                // TODO: Support path embedding
-                // (requires possibility to have stuff in Script type that gets copied to
-                // container)
                 Some(Phase::Path(pb)) => {
+                    let text = read_phase_file(pb, &mut files, &mut vec![])
+                        .with_context(|| anyhow!("Reading script of phase {} of {} {}", name.as_str(), package.name(), package.version()))?;
+
                     script.push_str(&format!(
-                        r#"
-                        # Phase (from file {path}): {name}
-                        # NOT SUPPORTED YET
-                        exit 1
-                    "#,
+                        "\n### phase {name} (from {path})\n{text}### / {name} phase\n",
+                        name = name.as_str(),
                         path = pb.display(),
-                        name = name.as_str()
                     ));
                     script.push('\n');
                 }
@@ -228,7 +270,7 @@ impl<'a> ScriptBuilder<'a> {
             }
         }
 
-        Self::interpolate_package(script, package, strict_mode).map(Script)
+        Self::interpolate_package(script, package, strict_mode).map(|script| (Script(script), files))
     }
 
     fn interpolate_package(script: String, package: &Package, strict_mode: bool) -> Result<String> {
@@ -238,6 +280,8 @@ impl<'a> ScriptBuilder<'a> {
         hb.register_helper("phase", Box::new(PhaseHelper));
         hb.register_helper("state", Box::new(StateHelper));
         hb.register_helper("progress", Box::new(ProgressHelper));
+        hb.register_helper("phase_progress", Box::new(PhaseProgressHelper));
+        hb.register_helper("heartbeat", Box::new(HeartbeatHelper));
         hb.register_helper("join", Box::new(JoinHelper));
         hb.register_helper("joinwith", Box::new(JoinWithHelper));
         hb.set_strict_mode(strict_mode);
@@ -253,6 +297,39 @@ impl<'a> ScriptBuilder<'a> {
     }
 }
 
+/// Read the script of a phase from the file at `path`, with all files it includes
+///
+/// The SHA256 of every file that is read is added to `files`, `stack` holds the files that are
+/// currently being included.
+fn read_phase_file(path: &Path, files: &mut BTreeMap<PathBuf, String>, stack: &mut Vec<PathBuf>) -> Result<String> {
+    if stack.iter().any(|p| p == path) {
+        return Err(anyhow!("Recursive include of {}", path.display()))
+    }
+
+    let content = std::fs::read_to_string(path).with_context(|| anyhow!("Reading {}", path.display()))?;
+    files.insert(path.to_path_buf(), crate::job::sha256_hex(content.as_bytes()));
+    stack.push(path.to_path_buf());
+
+    let mut text = String::new();
+    for line in content.lines() {
+        match line.trim().strip_prefix(INCLUDE_MARKER) {
+            Some(include) => {
+                let include = path.parent().unwrap_or_else(|| Path::new("")).join(include.trim());
+                let included = read_phase_file(&include, files, stack)
+                    .with_context(|| anyhow!("Including {} in {}", include.display(), path.display()))?;
+                text.push_str(&format!("### include {}\n{}### / include {}\n", include.display(), included, include.display()));
+            },
+            None => {
+                text.push_str(line);
+                text.push('\n');
+            },
+        }
+    }
+
+    stack.pop();
+    Ok(text)
+}
+
 #[derive(Clone, Copy)]
 struct PhaseHelper;
 
@@ -343,6 +420,49 @@ impl HelperDef for ProgressHelper {
     }
 }
 
+#[derive(Clone, Copy)]
+struct PhaseProgressHelper;
+
+impl HelperDef for PhaseProgressHelper {
+    fn call<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper,
+        _: &Handlebars,
+        _: &Context,
+        _rc: &mut RenderContext,
+        out: &mut dyn Output,
+    ) -> HelperResult {
+        h.param(0)
+            .ok_or_else(|| RenderError::new("Required parameter missing: phase progress"))?
+            .value()
+            .as_i64()
+            .ok_or_else(|| RenderError::new("Required parameter must be a number: phase progress"))
+            .and_then(|progress| {
+                out.write("echo '#BUTIDO:PHASE-PROGRESS:")?;
+                out.write(&progress.to_string())?;
+                out.write("'")?;
+                Ok(())
+            })
+    }
+}
+
+#[derive(Clone, Copy)]
+struct HeartbeatHelper;
+
+impl HelperDef for HeartbeatHelper {
+    fn call<'reg: 'rc, 'rc>(
+        &self,
+        _: &Helper,
+        _: &Handlebars,
+        _: &Context,
+        _rc: &mut RenderContext,
+        out: &mut dyn Output,
+    ) -> HelperResult {
+        out.write("echo '#BUTIDO:HEARTBEAT'")?;
+        Ok(())
+    }
+}
+
 #[derive(Clone, Copy)]
 struct JoinHelper;
 
@@ -402,3 +522,28 @@ where
     out.write(&s)?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_phase_file_with_includes() {
+        let dir = std::env::temp_dir().join(format!("butido-script-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(dir.join("common")).unwrap();
+        std::fs::write(dir.join("build.sh"), "make\n#BUTIDO:INCLUDE:common/install.sh\n").unwrap();
+        std::fs::write(dir.join("common/install.sh"), "make install\n").unwrap();
+
+        let mut files = BTreeMap::new();
+        let text = read_phase_file(&dir.join("build.sh"), &mut files, &mut vec![]).unwrap();
+        let include = dir.join("common/install.sh");
+        assert_eq!(text, format!("make\n### include {0}\nmake install\n### / include {0}\n", include.display()));
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[&include], crate::job::sha256_hex(b"make install\n"));
+
+        std::fs::write(dir.join("common/install.sh"), "#BUTIDO:INCLUDE:../build.sh\n").unwrap();
+        assert!(read_phase_file(&dir.join("build.sh"), &mut BTreeMap::new(), &mut vec![]).is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -21,6 +21,10 @@ use url::Url;
 pub struct Source {
     #[getset(get = "pub")]
     url: Url,
+    /// Alternative URLs for the source, tried in order if downloading from `url` fails
+    #[serde(default)]
+    #[getset(get = "pub")]
+    mirrors: Vec<Url>,
     #[getset(get = "pub")]
     hash: SourceHash,
     #[getset(get = "pub")]
@@ -32,6 +36,7 @@ impl Source {
     pub fn new(url: Url, hash: SourceHash) -> Self {
         Source {
             url,
+            mirrors: vec![],
             hash,
             download_manually: false,
         }
@@ -58,7 +63,8 @@ impl SourceHash {
             .context("Hashing failed")?;
         trace!("Hashing buffer with: {} finished", self.hashtype);
 
-        if h == self.value {
+        // Hashes are hex strings, so the case of the declared hash does not matter
+        if h.0.eq_ignore_ascii_case(&self.value.0) {
             trace!("Hash matches expected hash");
             Ok(())
         } else {
@@ -77,7 +83,29 @@ impl SourceHash {
     }
 }
 
-#[derive(parse_display::Display, Clone, Debug, Serialize, Deserialize)]
+/// A source hash as it is recorded in the input manifest of a job, "<hashtype>:<hash>"
+impl std::str::FromStr for SourceHash {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (hashtype, value) = s
+            .split_once(':')
+            .ok_or_else(|| anyhow!("Not a source hash of the form '<type>:<hash>': '{}'", s))?;
+
+        Ok(SourceHash {
+            hashtype: hashtype.parse().with_context(|| anyhow!("Unknown hash type: '{}'", hashtype))?,
+            value: HashValue(value.to_string()),
+        })
+    }
+}
+
+impl std::fmt::Display for SourceHash {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.hashtype, self.value)
+    }
+}
+
+#[derive(parse_display::Display, parse_display::FromStr, Clone, Debug, Serialize, Deserialize)]
 pub enum HashType {
     #[serde(rename = "sha1")]
     #[display("sha1")]
@@ -156,7 +184,7 @@ impl HashType {
 
                     m.update(&buffer[..count]);
                 }
-                Ok(HashValue(String::from_utf8(m.finalize()[..].to_vec())?))
+                Ok(HashValue(format!("{:x}", m.finalize())))
             }
         }
     }
@@ -173,3 +201,43 @@ impl From<String> for HashValue {
         HashValue(s)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn matches(hashtype: HashType, hash: &str) -> Result<()> {
+        SourceHash::new(hashtype, HashValue::from(String::from(hash)))
+            .matches_hash_of(&b"butido"[..])
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_sha1() {
+        assert!(matches(HashType::Sha1, "03f904e2a4708dfd115cf6665838244d0fda06c9").await.is_ok());
+        assert!(matches(HashType::Sha1, "03f904e2a4708dfd115cf6665838244d0fda06c8").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_sha256() {
+        assert!(matches(HashType::Sha256, "d6876ab39ca1ec87f2ead7df2450c720523718e5dd85ed7f1bb7c03b8b0a9430").await.is_ok());
+        assert!(matches(HashType::Sha256, "D6876AB39CA1EC87F2EAD7DF2450C720523718E5DD85ED7F1BB7C03B8B0A9430").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_sha512() {
+        let hash = "8931738ca5b593af5d26b2b1e973faceb643d8110a96126d3fe626cbf73e9cd162f650530796c5561f19800b835571c4f3422015f7bf3e88ae6b0aa70be1eea6";
+        assert!(matches(HashType::Sha512, hash).await.is_ok());
+        assert!(matches(HashType::Sha512, &hash[1..]).await.is_err());
+    }
+
+    #[test]
+    fn test_parse_recorded_hash() {
+        let hash = "sha1:03f904e2a4708dfd115cf6665838244d0fda06c9".parse::<SourceHash>().unwrap();
+        assert!(matches!(hash.hashtype(), HashType::Sha1));
+        assert_eq!(hash.to_string(), "sha1:03f904e2a4708dfd115cf6665838244d0fda06c9");
+
+        assert!("md5:abc".parse::<SourceHash>().is_err());
+        assert!("03f904e2".parse::<SourceHash>().is_err());
+    }
+}
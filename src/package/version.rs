@@ -8,8 +8,10 @@
 // SPDX-License-Identifier: EPL-2.0
 //
 
+use std::cmp::Ordering;
 use std::ops::Deref;
 
+use anyhow::anyhow;
 use anyhow::Context;
 use anyhow::Error;
 use anyhow::Result;
@@ -19,37 +21,105 @@ use serde::Serialize;
 
 use crate::util::parser::*;
 
+/// A constraint on the version of a package
+///
+/// A constraint consists of one or more requirements, separated by commas, which all have to
+/// match, for example `>=1.2, <2.0`.
+/// `=1.2.*` matches all versions starting with `1.2.`, `*` matches any version.
 #[derive(Clone, Debug, Hash, Eq, PartialEq, Ord, PartialOrd)]
 pub struct PackageVersionConstraint {
-    constraint: String,
+    /// The requirements of the constraint, empty if any version matches
+    requirements: Vec<VersionRequirement>,
+}
+
+#[derive(Clone, Debug, Hash, Eq, PartialEq, Ord, PartialOrd)]
+struct VersionRequirement {
+    comparator: Comparator,
     version: PackageVersion,
 }
 
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq, Ord, PartialOrd)]
+enum Comparator {
+    Exact,
+    Prefix,
+    Greater,
+    GreaterOrEqual,
+    Less,
+    LessOrEqual,
+}
+
 impl PackageVersionConstraint {
     fn parser<'a>() -> PomParser<'a, u8, Self> {
-        (pom::parser::sym(b'=') + PackageVersion::parser())
-            .convert(|(constraint, version)| {
-                String::from_utf8(vec![constraint]).map(|c| (c, version))
-            })
-            .map(|(constraint, version)| PackageVersionConstraint {
-                constraint,
-                version,
-            })
+        let any = pom::parser::sym(b'*').map(|_| vec![]);
+
+        let separator = pom::parser::sym(b' ').repeat(0..)
+            * pom::parser::sym(b',')
+            * pom::parser::sym(b' ').repeat(0..);
+        let requirements = (VersionRequirement::parser()
+            + (separator * VersionRequirement::parser()).repeat(0..))
+            .map(|(first, mut rest)| {
+                rest.insert(0, first);
+                rest
+            });
+
+        ((any | requirements) - pom::parser::end())
+            .map(|requirements| PackageVersionConstraint { requirements })
     }
 
     pub fn matches(&self, v: &PackageVersion) -> bool {
-        self.version == *v
+        self.requirements.iter().all(|req| req.matches(v))
     }
 
     #[cfg(test)]
     pub fn from_version(constraint: String, version: PackageVersion) -> Self {
+        let comparator = Comparator::parser()
+            .parse(constraint.as_bytes())
+            .unwrap();
+
         PackageVersionConstraint {
-            constraint,
-            version,
+            requirements: vec![VersionRequirement { comparator, version }],
         }
     }
 }
 
+impl VersionRequirement {
+    fn parser<'a>() -> PomParser<'a, u8, Self> {
+        (Comparator::parser() + PackageVersion::parser() + pom::parser::sym(b'*').opt())
+            .convert(|((comparator, version), wildcard)| match (comparator, wildcard) {
+                (comparator, None) => Ok(VersionRequirement { comparator, version }),
+                (Comparator::Exact, Some(_)) => Ok(VersionRequirement {
+                    comparator: Comparator::Prefix,
+                    version,
+                }),
+                (comparator, Some(_)) => Err(anyhow!(
+                    "Wildcards are only supported with '=', not with '{}'",
+                    comparator
+                )),
+            })
+    }
+
+    fn matches(&self, v: &PackageVersion) -> bool {
+        match self.comparator {
+            Comparator::Exact => self.version == *v,
+            Comparator::Prefix => v.starts_with(self.version.as_str()),
+            Comparator::Greater => v.cmp_version(&self.version) == Ordering::Greater,
+            Comparator::GreaterOrEqual => v.cmp_version(&self.version) != Ordering::Less,
+            Comparator::Less => v.cmp_version(&self.version) == Ordering::Less,
+            Comparator::LessOrEqual => v.cmp_version(&self.version) != Ordering::Greater,
+        }
+    }
+}
+
+impl Comparator {
+    fn parser<'a>() -> PomParser<'a, u8, Self> {
+        pom::parser::seq(b">=").map(|_| Comparator::GreaterOrEqual)
+            | pom::parser::seq(b"<=").map(|_| Comparator::LessOrEqual)
+            | pom::parser::sym(b'>').map(|_| Comparator::Greater)
+            | pom::parser::sym(b'<').map(|_| Comparator::Less)
+            | pom::parser::sym(b'=').map(|_| Comparator::Exact)
+    }
+}
+
 impl std::convert::TryFrom<String> for PackageVersionConstraint {
     type Error = anyhow::Error;
 
@@ -66,6 +136,7 @@ impl std::convert::TryFrom<&str> for PackageVersionConstraint {
             .parse(s.as_bytes())
             .context("Failed to parse package version constraint")
             .context("A package version constraint must have a comparator and a version string, like so: =0.1.0")
+            .context("Multiple comparisons can be separated by commas, like so: >=0.1, <0.2")
             .map_err(Error::from)
 
     }
@@ -73,7 +144,39 @@ impl std::convert::TryFrom<&str> for PackageVersionConstraint {
 
 impl std::fmt::Display for PackageVersionConstraint {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}{}", self.constraint, self.version)
+        if self.requirements.is_empty() {
+            return write!(f, "*")
+        }
+
+        for (i, req) in self.requirements.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{req}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for VersionRequirement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.comparator {
+            Comparator::Prefix => write!(f, "={}*", self.version),
+            comparator => write!(f, "{}{}", comparator, self.version),
+        }
+    }
+}
+
+impl std::fmt::Display for Comparator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Comparator::Exact | Comparator::Prefix => "=",
+            Comparator::Greater => ">",
+            Comparator::GreaterOrEqual => ">=",
+            Comparator::Less => "<",
+            Comparator::LessOrEqual => "<=",
+        };
+        write!(f, "{s}")
     }
 }
 
@@ -118,6 +221,47 @@ impl PackageVersion {
             .collect()
             .convert(|b| String::from_utf8(b.to_vec()).map(Self::from))
     }
+
+    /// Compare two versions component-wise
+    ///
+    /// The versions are split into runs of digits and runs of other characters. Runs of digits
+    /// are compared numerically, everything else lexically, so "1.10" is greater than "1.9".
+    pub fn cmp_version(&self, other: &PackageVersion) -> Ordering {
+        fn components(s: &str) -> Vec<&str> {
+            let bytes = s.as_bytes();
+            let mut components = vec![];
+            let mut start = 0;
+            for i in 1..bytes.len() {
+                if bytes[i].is_ascii_digit() != bytes[i - 1].is_ascii_digit() {
+                    components.push(&s[start..i]);
+                    start = i;
+                }
+            }
+            if !s.is_empty() {
+                components.push(&s[start..]);
+            }
+            components
+        }
+
+        fn cmp_component(a: &str, b: &str) -> Ordering {
+            let is_number = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+            if is_number(a) && is_number(b) {
+                let a = a.trim_start_matches('0');
+                let b = b.trim_start_matches('0');
+                a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+            } else {
+                a.cmp(b)
+            }
+        }
+
+        let ours = components(&self.0);
+        let theirs = components(&other.0);
+        ours.iter()
+            .zip(theirs.iter())
+            .map(|(a, b)| cmp_component(a, b))
+            .find(|ord| *ord != Ordering::Equal)
+            .unwrap_or_else(|| ours.len().cmp(&theirs.len()))
+    }
 }
 
 #[cfg(test)]
@@ -147,10 +291,13 @@ mod tests {
             .parse(b"*1")
             .is_err());
         assert!(PackageVersionConstraint::parser()
-            .parse(b">1")
+            .parse(b">")
+            .is_err());
+        assert!(PackageVersionConstraint::parser()
+            .parse(b">=1.*")
             .is_err());
         assert!(PackageVersionConstraint::parser()
-            .parse(b"<1")
+            .parse(b"=1,")
             .is_err());
         assert!(PackageVersionConstraint::parser()
             .parse(b"=a")
@@ -178,7 +325,7 @@ mod tests {
         let c = PackageVersionConstraint::parser()
             .parse(s.as_bytes())
             .unwrap();
-        assert_eq!(c.version, PackageVersion::from(String::from("1")));
+        assert_eq!(c.requirements[0].version, PackageVersion::from(String::from("1")));
     }
 
     #[test]
@@ -187,7 +334,7 @@ mod tests {
         let c = PackageVersionConstraint::parser()
             .parse(s.as_bytes())
             .unwrap();
-        assert_eq!(c.version, PackageVersion::from(String::from("1.0.17")));
+        assert_eq!(c.requirements[0].version, PackageVersion::from(String::from("1.0.17")));
     }
 
     #[test]
@@ -196,7 +343,7 @@ mod tests {
         let c = PackageVersionConstraint::parser()
             .parse(s.as_bytes())
             .unwrap();
-        assert_eq!(c.version, PackageVersion::from(String::from("1.0.17asejg")));
+        assert_eq!(c.requirements[0].version, PackageVersion::from(String::from("1.0.17asejg")));
     }
 
     #[test]
@@ -206,8 +353,56 @@ mod tests {
             .parse(s.as_bytes())
             .unwrap();
         assert_eq!(
-            c.version,
+            c.requirements[0].version,
             PackageVersion::from(String::from("1-0B17-beta1247_commit_12653hasd"))
         );
     }
+
+    fn v(s: &str) -> PackageVersion {
+        PackageVersion::from(String::from(s))
+    }
+
+    fn c(s: &str) -> PackageVersionConstraint {
+        PackageVersionConstraint::try_from(s).unwrap()
+    }
+
+    #[test]
+    fn test_cmp_version() {
+        assert_eq!(v("1.9").cmp_version(&v("1.10")), Ordering::Less);
+        assert_eq!(v("1.10").cmp_version(&v("1.9")), Ordering::Greater);
+        assert_eq!(v("1.0").cmp_version(&v("1.0.1")), Ordering::Less);
+        assert_eq!(v("1.01").cmp_version(&v("1.1")), Ordering::Equal);
+        assert_eq!(v("2.0beta").cmp_version(&v("2.0rc")), Ordering::Less);
+        assert_eq!(v("10").cmp_version(&v("9")), Ordering::Greater);
+    }
+
+    #[test]
+    fn test_range_constraint() {
+        let constraint = c(">=1.2, <2.0");
+        assert_eq!(constraint.requirements.len(), 2);
+        assert!(!constraint.matches(&v("1.1")));
+        assert!(constraint.matches(&v("1.2")));
+        assert!(constraint.matches(&v("1.10")));
+        assert!(!constraint.matches(&v("2.0")));
+
+        assert!(c(">1").matches(&v("1.0")));
+        assert!(!c(">1").matches(&v("1")));
+        assert!(c("<=1").matches(&v("1")));
+        assert_eq!(c(">=1.2,<2.0"), constraint);
+    }
+
+    #[test]
+    fn test_wildcard_constraint() {
+        assert!(c("*").matches(&v("1.2.3")));
+        assert!(c("=1.2.*").matches(&v("1.2.3")));
+        assert!(!c("=1.2.*").matches(&v("1.20")));
+        assert!(!c("=1.2.*").matches(&v("1.3.0")));
+    }
+
+    #[test]
+    fn test_constraint_display() {
+        for s in ["=1.0", "*", "=1.2.*", ">=1.2, <2.0", ">1", "<=3"] {
+            assert_eq!(c(s).to_string(), s);
+        }
+    }
 }
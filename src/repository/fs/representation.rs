@@ -110,13 +110,14 @@ impl FileSystemRepresentation {
     ///
     /// # Example
     ///
-    ///     /
-    ///     /foo/
-    ///     /foo/pkg.toml <-- is leaf
-    ///     /bar/
-    ///     /bar/pkg.toml <-- is not a leaf
-    ///     /bar/baz/pkg.toml <-- is a leaf
-    ///
+    /// ```text
+    /// /
+    /// /foo/
+    /// /foo/pkg.toml <-- is leaf
+    /// /bar/
+    /// /bar/pkg.toml <-- is not a leaf
+    /// /bar/baz/pkg.toml <-- is a leaf
+    /// ```
     ///
     pub fn is_leaf_file(&self, path: &Path) -> Result<bool> {
         let mut curr_hm = &self.elements;
@@ -14,3 +14,5 @@ pub use repository::*;
 
 mod fs;
 
+mod template;
+
@@ -9,6 +9,7 @@
 //
 
 use std::collections::BTreeMap;
+use std::collections::HashMap;
 use std::path::Path;
 use std::path::PathBuf;
 
@@ -16,7 +17,7 @@ use anyhow::anyhow;
 use anyhow::Context;
 use anyhow::Error;
 use anyhow::Result;
-use tracing::trace;
+use tracing::{debug, trace, warn};
 use resiter::AndThen;
 use resiter::FilterMap;
 use resiter::Map;
@@ -29,21 +30,49 @@ use crate::package::PackageVersionConstraint;
 /// A repository represents a collection of packages
 pub struct Repository {
     inner: BTreeMap<(PackageName, PackageVersion), Package>,
+
+    /// Packages that are defined more than once, with the files that define them
+    duplicates: Vec<(PackageName, PackageVersion, Vec<PathBuf>)>,
 }
 
 #[cfg(test)]
 impl From<BTreeMap<(PackageName, PackageVersion), Package>> for Repository {
     fn from(inner: BTreeMap<(PackageName, PackageVersion), Package>) -> Self {
-        Repository { inner }
+        Repository { inner, duplicates: vec![] }
     }
 }
 
 impl Repository {
-    fn new(inner: BTreeMap<(PackageName, PackageVersion), Package>) -> Self {
-        Repository { inner }
+    /// Create a repository from the loaded packages and the files they were loaded from
+    ///
+    /// If a package is defined more than once, the last definition is used.
+    fn new(packages: Vec<(PathBuf, Package)>) -> Self {
+        let mut inner = BTreeMap::new();
+        let mut definitions: BTreeMap<_, Vec<PathBuf>> = BTreeMap::new();
+
+        for (path, package) in packages {
+            let key = (package.name().clone(), package.version().clone());
+            definitions.entry(key.clone()).or_default().push(path);
+            inner.insert(key, package);
+        }
+
+        let duplicates = definitions
+            .into_iter()
+            .filter(|(_, paths)| paths.len() > 1)
+            .map(|((name, version), paths)| {
+                warn!("Package {} {} is defined multiple times", name, version);
+                (name, version, paths)
+            })
+            .collect();
+
+        Repository { inner, duplicates }
     }
 
-    pub fn load(path: &Path, progress: &indicatif::ProgressBar) -> Result<Self> {
+    pub fn load(
+        path: &Path,
+        variables: &HashMap<String, String>,
+        progress: &indicatif::ProgressBar,
+    ) -> Result<Self> {
         use crate::repository::fs::FileSystemRepresentation;
         use config::Config;
         use rayon::iter::IntoParallelRefIterator;
@@ -66,6 +95,14 @@ impl Repository {
             }
         }
 
+        fn get_phases(config: &Config) -> Result<HashMap<String, config::Value>> {
+            match config.get_table("phases") {
+                Ok(phases) => Ok(phases),
+                Err(config::ConfigError::NotFound(_)) => Ok(HashMap::with_capacity(0)),
+                Err(e) => Err(e).map_err(Error::from),
+            }
+        }
+
         fsr.files()
             .par_iter()
             .inspect(|path| trace!("Checking for leaf file: {}", path.display()))
@@ -86,6 +123,7 @@ impl Repository {
                     .fold(Ok(Config::default()) as Result<_>, |config, (path, content)| {
                         let mut config = config?;
                         let patches_before_merge = get_patches(&config)?;
+                        let phases_before_merge = get_phases(&config)?;
 
                         config.merge(config::File::from_str(content, config::FileFormat::Toml))
                             .with_context(|| anyhow!("Loading contents of {}", path.display()))?;
@@ -134,13 +172,59 @@ impl Repository {
                             .map(config::Value::from)
                             .collect::<Vec<_>>();
                         config.set_once("patches", config::Value::from(patches))?;
+
+                        // A phase that is defined in this layer replaces the phase from the layers
+                        // before as a whole, so a script can be replaced by a script from a file
+                        // and vice versa.
+                        // Scripts that are read from files are relative to the pkg.toml file that
+                        // defines them, like patches.
+                        let layer_phases = toml::from_str::<toml::Value>(content)
+                            .with_context(|| anyhow!("Parsing {}", path.display()))?
+                            .get("phases")
+                            .and_then(toml::Value::as_table)
+                            .cloned()
+                            .unwrap_or_default();
+
+                        if !layer_phases.is_empty() || !phases_before_merge.is_empty() {
+                            let mut phases = phases_before_merge;
+                            for (name, phase) in layer_phases {
+                                let phase = if let Some(phase_path) = phase.get("path").and_then(toml::Value::as_str) {
+                                    let phase_path = path.parent()
+                                        .ok_or_else(|| anyhow!("Path should point to path with parent, but doesn't: {}", path.display()))?
+                                        .join(phase_path);
+
+                                    if !phase_path.exists() {
+                                        return Err(anyhow!("Script of phase {} does not exist: {}", name, phase_path.display()))
+                                    }
+
+                                    trace!("Script of phase {}: {}", name, phase_path.display());
+                                    config::Value::from(HashMap::from([
+                                        (String::from("path"), config::Value::from(phase_path.display().to_string())),
+                                    ]))
+                                } else {
+                                    config.get::<config::Value>(&format!("phases.{name}"))?
+                                        .into_table()?
+                                        .into_iter()
+                                        .filter(|(key, _)| key != "path")
+                                        .collect::<HashMap<_, _>>()
+                                        .into()
+                                };
+                                phases.insert(name, phase);
+                            }
+                            config.set_once("phases", config::Value::from(phases))?;
+                        }
                         Ok(config)
                     })
-                    .and_then(|c| c.try_into::<Package>().map_err(Error::from)
-                        .with_context(|| anyhow!("Could not load package configuration: {}", path.display())))
-                    .map(|pkg| ((pkg.name().clone(), pkg.version().clone()), pkg))
+                    .and_then(|mut c| {
+                        crate::repository::template::render_package_templates(&mut c, variables)
+                            .with_context(|| anyhow!("Rendering package definition: {}", path.display()))?;
+
+                        c.try_into::<Package>().map_err(Error::from)
+                            .with_context(|| anyhow!("Could not load package configuration: {}", path.display()))
+                    })
+                    .map(|pkg| (path.clone(), pkg))
             })
-            .collect::<Result<BTreeMap<_, _>>>()
+            .collect::<Result<Vec<_>>>()
             .map(Repository::new)
     }
 
@@ -176,9 +260,58 @@ impl Repository {
             .collect()
     }
 
+    /// Find the package with the highest version that matches a version constraint
+    ///
+    /// Returns an error if the highest matching versions cannot be ordered, because they only
+    /// differ in a way the version comparison ignores (e.g. "1.01" and "1.1").
+    pub fn find_best_match<'a>(
+        &'a self,
+        name: &PackageName,
+        vc: &PackageVersionConstraint,
+    ) -> Result<Option<&'a Package>> {
+        let mut candidates = self.find_with_version(name, vc);
+        candidates.sort_by(|a, b| a.version().cmp_version(b.version()));
+
+        let best = match candidates.pop() {
+            Some(best) => best,
+            None => return Ok(None),
+        };
+
+        let equal = candidates
+            .iter()
+            .filter(|p| p.version().cmp_version(best.version()) == std::cmp::Ordering::Equal)
+            .map(|p| p.version().to_string())
+            .collect::<Vec<_>>();
+        if !equal.is_empty() {
+            return Err(anyhow!(
+                "Version constraint '{} {}' is ambiguous, versions {} and {} are considered equal",
+                name,
+                vc,
+                best.version(),
+                equal.join(", ")
+            ));
+        }
+
+        if !candidates.is_empty() {
+            debug!(
+                "{} versions of {} match '{}', using the highest: {}",
+                candidates.len() + 1,
+                name,
+                vc,
+                best.version()
+            );
+        }
+        Ok(Some(best))
+    }
+
     pub fn packages(&self) -> impl Iterator<Item = &Package> {
         self.inner.values()
     }
+
+    /// The packages that are defined more than once, with the files that define them
+    pub fn duplicates(&self) -> &[(PackageName, PackageVersion, Vec<PathBuf>)] {
+        &self.duplicates
+    }
 }
 
 #[cfg(test)]
@@ -273,4 +406,38 @@ pub mod tests {
         assert_eq!(*p.version(), pversion("2"));
         assert!(!p.version_is_semver());
     }
+
+    #[test]
+    fn test_find_best_match() {
+        let mut btree = BTreeMap::new();
+        for vers in ["1.2", "1.9", "1.10", "2.0"] {
+            let pack = package("a", vers, "https://rust-lang.org", "123");
+            btree.insert((pname("a"), pversion(vers)), pack);
+        }
+        let repo = Repository::from(btree);
+
+        let constraint = PackageVersionConstraint::try_from(">=1.2, <2.0").unwrap();
+        let p = repo.find_best_match(&pname("a"), &constraint).unwrap().unwrap();
+        assert_eq!(*p.version(), pversion("1.10"));
+
+        let constraint = PackageVersionConstraint::try_from(">2.0").unwrap();
+        assert!(repo.find_best_match(&pname("a"), &constraint).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_find_best_match_ambiguous() {
+        let mut btree = BTreeMap::new();
+        for vers in ["1.1", "1.01"] {
+            let pack = package("a", vers, "https://rust-lang.org", "123");
+            btree.insert((pname("a"), pversion(vers)), pack);
+        }
+        let repo = Repository::from(btree);
+
+        let constraint = PackageVersionConstraint::try_from("*").unwrap();
+        assert!(repo.find_best_match(&pname("a"), &constraint).is_err());
+
+        let constraint = PackageVersionConstraint::try_from("=1.1").unwrap();
+        let p = repo.find_best_match(&pname("a"), &constraint).unwrap().unwrap();
+        assert_eq!(*p.version(), pversion("1.1"));
+    }
 }
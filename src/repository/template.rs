@@ -0,0 +1,169 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Templating of package definitions
+//!
+//! Some fields of a package definition can contain handlebars placeholders, for example
+//! `url = "https://example.com/foo-{{version}}.tar.gz"`. They are rendered when the package is
+//! loaded, after all layers of the package definition are merged, so a `pkg.toml` higher up in the
+//! tree can define a field for all versions of a package.
+//!
+//! The variables available are the configured `package_variables`, the entries of the `meta`
+//! table of the package and its `name` and `version`, in increasing order of precedence.
+
+use std::collections::HashMap;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Error;
+use anyhow::Result;
+use config::Config;
+use config::Value;
+use handlebars::Handlebars;
+
+/// Render the placeholders in the source URLs and mirrors, the environment and the meta
+/// data of a package
+pub(in crate::repository) fn render_package_templates(
+    config: &mut Config,
+    variables: &HashMap<String, String>,
+) -> Result<()> {
+    let data = template_data(config, variables)?;
+    let mut hb = Handlebars::new();
+    hb.set_strict_mode(true);
+    hb.register_escape_fn(handlebars::no_escape);
+
+    let render = |value: Value| -> Result<Value> {
+        let template = value.into_str()?;
+        if !template.contains("{{") {
+            return Ok(Value::from(template));
+        }
+
+        hb.render_template(&template, &data)
+            .with_context(|| anyhow!("Rendering '{}'", template))
+            .map(Value::from)
+            .map_err(Error::from)
+    };
+
+    if let Some(mut sources) = get_table(config, "sources")? {
+        for source in sources.values_mut() {
+            let mut table = source.clone().into_table()?;
+            if let Some(url) = table.remove("url") {
+                table.insert(String::from("url"), render(url)?);
+            }
+            if let Some(mirrors) = table.remove("mirrors") {
+                let mirrors = mirrors
+                    .into_array()?
+                    .into_iter()
+                    .map(&render)
+                    .collect::<Result<Vec<_>>>()?;
+                table.insert(String::from("mirrors"), Value::from(mirrors));
+            }
+            *source = Value::from(table);
+        }
+        config.set_once("sources", Value::from(sources))?;
+    }
+
+    for key in ["environment", "meta"] {
+        if let Some(table) = get_table(config, key)? {
+            let table = table
+                .into_iter()
+                .map(|(k, v)| render(v).map(|v| (k, v)))
+                .collect::<Result<HashMap<_, _>>>()?;
+            config.set_once(key, Value::from(table))?;
+        }
+    }
+
+    Ok(())
+}
+
+fn template_data(config: &Config, variables: &HashMap<String, String>) -> Result<HashMap<String, String>> {
+    let mut data = variables.clone();
+
+    if let Some(meta) = get_table(config, "meta")? {
+        for (key, value) in meta {
+            data.insert(key, value.into_str()?);
+        }
+    }
+
+    // A missing name or version is reported when the package is deserialized
+    for key in ["name", "version"] {
+        if let Ok(value) = config.get_str(key) {
+            data.insert(String::from(key), value);
+        }
+    }
+
+    Ok(data)
+}
+
+fn get_table(config: &Config, key: &str) -> Result<Option<HashMap<String, Value>>> {
+    match config.get_table(key) {
+        Ok(table) => Ok(Some(table)),
+        Err(config::ConfigError::NotFound(_)) => Ok(None),
+        Err(e) => Err(Error::from(e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(content: &str, variables: &[(&str, &str)]) -> Result<Config> {
+        let mut config = Config::default();
+        config.merge(config::File::from_str(content, config::FileFormat::Toml))?;
+
+        let variables = variables
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        render_package_templates(&mut config, &variables)?;
+        Ok(config)
+    }
+
+    #[test]
+    fn test_render_source_url() {
+        let config = load(
+            r#"
+            name = "foo"
+            version = "1.2"
+
+            [meta]
+            flavor = "full"
+
+            [sources.src]
+            url = "{{mirror}}/{{name}}-{{version}}-{{flavor}}.tar.gz?a=1&b=2"
+            mirrors = ["https://example.com/{{name}}.tar.gz"]
+            "#,
+            &[("mirror", "https://rust-lang.org"), ("flavor", "minimal")],
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.get_str("sources.src.url").unwrap(),
+            "https://rust-lang.org/foo-1.2-full.tar.gz?a=1&b=2"
+        );
+        let mirrors: Vec<String> = config.get("sources.src.mirrors").unwrap();
+        assert_eq!(mirrors, vec!["https://example.com/foo.tar.gz"]);
+    }
+
+    #[test]
+    fn test_render_unknown_variable() {
+        let r = load(
+            r#"
+            name = "foo"
+            version = "1.2"
+
+            [environment]
+            FOO = "{{bar}}"
+            "#,
+            &[],
+        );
+        assert!(r.is_err());
+    }
+}
@@ -3,6 +3,31 @@ table! {
         id -> Int4,
         path -> Varchar,
         job_id -> Int4,
+        compression -> Nullable<Varchar>,
+        artifact_kind -> Varchar,
+        sha256 -> Nullable<Varchar>,
+    }
+}
+
+table! {
+    endpoint_definitions (id) {
+        id -> Int4,
+        name -> Varchar,
+        uri -> Varchar,
+        endpoint_type -> Varchar,
+        maxjobs -> Int4,
+        network_mode -> Nullable<Varchar>,
+    }
+}
+
+table! {
+    endpoint_reservations (id) {
+        id -> Int4,
+        endpoint_id -> Int4,
+        submit_id -> Int4,
+        job_uuid -> Uuid,
+        reserved_by -> Nullable<Varchar>,
+        reserved_at -> Timestamp,
     }
 }
 
@@ -28,6 +53,15 @@ table! {
     }
 }
 
+table! {
+    image_definitions (id) {
+        id -> Int4,
+        name -> Varchar,
+        short_name -> Nullable<Varchar>,
+        allowed -> Bool,
+    }
+}
+
 table! {
     images (id) {
         id -> Int4,
@@ -35,6 +69,17 @@ table! {
     }
 }
 
+table! {
+    job_dag_edges (id) {
+        id -> Int4,
+        submit_id -> Int4,
+        package_id -> Int4,
+        job_uuid -> Uuid,
+        dependency_uuid -> Nullable<Uuid>,
+        image_id -> Nullable<Int4>,
+    }
+}
+
 table! {
     job_envs (id) {
         id -> Int4,
@@ -43,6 +88,26 @@ table! {
     }
 }
 
+table! {
+    job_metadata (id) {
+        id -> Int4,
+        job_id -> Int4,
+        name -> Varchar,
+        value -> Text,
+    }
+}
+
+table! {
+    job_states (id) {
+        id -> Int4,
+        submit_id -> Int4,
+        job_uuid -> Uuid,
+        state -> Varchar,
+        endpoint_id -> Nullable<Int4>,
+        container_hash -> Nullable<Varchar>,
+    }
+}
+
 table! {
     jobs (id) {
         id -> Int4,
@@ -54,6 +119,29 @@ table! {
         script_text -> Text,
         log_text -> Text,
         uuid -> Uuid,
+        failure_category -> Nullable<Varchar>,
+        exit_code -> Nullable<Int4>,
+        input_manifest -> Nullable<Text>,
+        started_at -> Nullable<Timestamptz>,
+        finished_at -> Nullable<Timestamptz>,
+        phases -> Nullable<Array<Text>>,
+        container_fate -> Nullable<Varchar>,
+        oom_killed -> Nullable<Bool>,
+        log_file -> Nullable<Text>,
+        log_encoding -> Nullable<Varchar>,
+        log_data -> Nullable<Bytea>,
+        cpu_seconds -> Nullable<Float8>,
+        peak_memory -> Nullable<Int8>,
+        io_bytes -> Nullable<Int8>,
+    }
+}
+
+table! {
+    package_definitions (id) {
+        id -> Int4,
+        submit_id -> Int4,
+        package_id -> Int4,
+        definition -> Text,
     }
 }
 
@@ -78,6 +166,9 @@ table! {
         artifact_id -> Int4,
         release_date -> Timestamptz,
         release_store_id -> Int4,
+        released_by -> Nullable<Varchar>,
+        signature_path -> Nullable<Varchar>,
+        path -> Varchar,
     }
 }
 
@@ -89,6 +180,23 @@ table! {
     }
 }
 
+table! {
+    submit_labels (id) {
+        id -> Int4,
+        submit_id -> Int4,
+        key -> Varchar,
+        value -> Varchar,
+    }
+}
+
+table! {
+    submit_plans (id) {
+        id -> Int4,
+        submit_id -> Int4,
+        plan -> Text,
+    }
+}
+
 table! {
     submits (id) {
         id -> Int4,
@@ -97,35 +205,59 @@ table! {
         requested_image_id -> Int4,
         requested_package_id -> Int4,
         repo_hash_id -> Int4,
+        submitted_by -> Nullable<Varchar>,
+        submitted_from -> Nullable<Varchar>,
+        butido_version -> Nullable<Varchar>,
     }
 }
 
 joinable!(artifacts -> jobs (job_id));
+joinable!(endpoint_reservations -> endpoints (endpoint_id));
+joinable!(endpoint_reservations -> submits (submit_id));
+joinable!(job_dag_edges -> images (image_id));
+joinable!(job_dag_edges -> packages (package_id));
+joinable!(job_dag_edges -> submits (submit_id));
 joinable!(job_envs -> envvars (env_id));
 joinable!(job_envs -> jobs (job_id));
+joinable!(job_metadata -> jobs (job_id));
+joinable!(job_states -> endpoints (endpoint_id));
+joinable!(job_states -> submits (submit_id));
 joinable!(jobs -> endpoints (endpoint_id));
 joinable!(jobs -> images (image_id));
 joinable!(jobs -> packages (package_id));
 joinable!(jobs -> submits (submit_id));
+joinable!(package_definitions -> packages (package_id));
+joinable!(package_definitions -> submits (submit_id));
 joinable!(releases -> artifacts (artifact_id));
 joinable!(releases -> release_stores (release_store_id));
 joinable!(submit_envs -> envvars (env_id));
 joinable!(submit_envs -> submits (submit_id));
+joinable!(submit_labels -> submits (submit_id));
+joinable!(submit_plans -> submits (submit_id));
 joinable!(submits -> githashes (repo_hash_id));
 joinable!(submits -> images (requested_image_id));
 joinable!(submits -> packages (requested_package_id));
 
 allow_tables_to_appear_in_same_query!(
     artifacts,
+    endpoint_definitions,
+    endpoint_reservations,
     endpoints,
     envvars,
     githashes,
+    image_definitions,
     images,
+    job_dag_edges,
     job_envs,
+    job_metadata,
+    job_states,
     jobs,
+    package_definitions,
     packages,
     release_stores,
     releases,
     submit_envs,
+    submit_labels,
+    submit_plans,
     submits,
 );
@@ -21,6 +21,7 @@ use crate::package::Package;
 use crate::package::PackageName;
 use crate::package::PackageVersion;
 use crate::package::Source;
+use crate::package::SourceHash;
 
 #[derive(Clone, Debug)]
 pub struct SourceCache {
@@ -35,6 +36,24 @@ impl SourceCache {
     pub fn sources_for(&self, p: &Package) -> Vec<SourceEntry> {
         SourceEntry::for_package(self.root.clone(), p)
     }
+
+    /// Like `SourceCache::sources_for()`, but fails if two sources of the package would be cached
+    /// in (and copied to the container as) the same file
+    pub fn checked_sources_for(&self, p: &Package) -> Result<Vec<SourceEntry>> {
+        let sources = self.sources_for(p);
+        let mut paths = std::collections::HashSet::new();
+        for source in sources.iter() {
+            if !paths.insert(source.path()) {
+                return Err(anyhow!(
+                    "Multiple sources of {} {} map to the same file: {}",
+                    p.name(),
+                    p.version(),
+                    source.path().display()
+                ));
+            }
+        }
+        Ok(sources)
+    }
 }
 
 #[derive(Debug)]
@@ -73,10 +92,43 @@ impl SourceEntry {
         })
     }
 
+    /// The name of the source in the package definition
+    pub fn name(&self) -> &str {
+        &self.package_source_name
+    }
+
     pub fn url(&self) -> &Url {
         self.package_source.url()
     }
 
+    /// All URLs the source can be downloaded from, in the order they should be tried
+    ///
+    /// These are the URL of the source, the mirrors of the source and, for each of the
+    /// `global_mirrors`, the file name of the source URL joined to the mirror URL.
+    pub fn urls(&self, global_mirrors: &[Url]) -> Result<Vec<Url>> {
+        let file_name = self.url()
+            .path_segments()
+            .and_then(|mut segments| segments.next_back())
+            .filter(|name| !name.is_empty());
+
+        let global = global_mirrors
+            .iter()
+            .filter_map(|mirror| file_name.map(|name| mirror.join(name)))
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .with_context(|| anyhow!("Joining mirror URLs with the file name of {}", self.url()))?;
+
+        Ok(std::iter::once(self.url())
+            .chain(self.package_source.mirrors().iter())
+            .cloned()
+            .chain(global)
+            .collect())
+    }
+
+    /// The hash the source is declared with in the package definition
+    pub fn hash(&self) -> &SourceHash {
+        self.package_source.hash()
+    }
+
     pub fn download_manually(&self) -> bool {
         *self.package_source.download_manually()
     }
@@ -147,3 +199,69 @@ impl SourceEntry {
             .map_err(Error::from)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::package::tests::package;
+    use crate::package::tests::pname;
+    use crate::package::tests::pversion;
+    use crate::package::Dependencies;
+    use crate::package::HashType;
+    use crate::package::HashValue;
+    use crate::package::SourceHash;
+
+    #[test]
+    fn test_urls_with_global_mirrors() {
+        let pkg = package("a", "1", "https://example.com/releases/a-1.tar.gz", "123");
+        let sources = SourceCache::new(PathBuf::from("/tmp/sources")).sources_for(&pkg);
+        let mirrors = vec![
+            Url::parse("https://mirror1.example.com/sources/").unwrap(),
+            Url::parse("https://mirror2.example.com/").unwrap(),
+        ];
+
+        let urls = sources[0].urls(&mirrors).unwrap();
+        assert_eq!(urls, vec![
+            Url::parse("https://example.com/releases/a-1.tar.gz").unwrap(),
+            Url::parse("https://mirror1.example.com/sources/a-1.tar.gz").unwrap(),
+            Url::parse("https://mirror2.example.com/a-1.tar.gz").unwrap(),
+        ]);
+    }
+
+    fn package_with_sources(names: &[&str]) -> Package {
+        let sources = names
+            .iter()
+            .map(|name| {
+                let url = Url::parse(&format!("https://example.com/{}", name)).unwrap();
+                let hash = SourceHash::new(HashType::Sha1, HashValue::from(String::from("123")));
+                (name.to_string(), Source::new(url, hash))
+            })
+            .collect();
+
+        Package::new(pname("a"), pversion("1"), false, sources, Dependencies::empty())
+    }
+
+    #[test]
+    fn test_multiple_sources_have_predictable_paths() {
+        let pkg = package_with_sources(&["main", "config"]);
+        let mut paths = SourceCache::new(PathBuf::from("/tmp/sources"))
+            .checked_sources_for(&pkg)
+            .unwrap()
+            .into_iter()
+            .map(|s| s.path())
+            .collect::<Vec<_>>();
+        paths.sort();
+
+        assert_eq!(paths, vec![
+            PathBuf::from("/tmp/sources/a-1/config.source"),
+            PathBuf::from("/tmp/sources/a-1/main.source"),
+        ]);
+    }
+
+    #[test]
+    fn test_multiple_sources_with_same_path() {
+        let pkg = package_with_sources(&["main.tar", "main.zip"]);
+        let sources = SourceCache::new(PathBuf::from("/tmp/sources")).checked_sources_for(&pkg);
+        assert!(sources.is_err());
+    }
+}
@@ -27,10 +27,11 @@ pub fn script_to_printable(
     script: &Script,
     highlight: bool,
     highlight_theme: &str,
+    highlight_theme_dir: Option<&Path>,
     line_numbers: bool,
 ) -> Result<String> {
     let script = if highlight {
-        let script = script.highlighted(highlight_theme);
+        let script = script.highlighted(highlight_theme, highlight_theme_dir)?;
         if line_numbers {
             script
                 .lines_numbered()?
@@ -111,6 +111,7 @@ impl<'a, P: Borrow<Package>> PreparePrintPackage<'a, P> {
                 .script_highlight_theme()
                 .as_ref()
                 .ok_or_else(|| anyhow!("Highlighting for script enabled, but no theme configured"))?,
+            self.config.script_highlight_theme_dir().as_deref(),
             self.flags.script_line_numbers,
         )?;
 
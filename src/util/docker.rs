@@ -8,6 +8,15 @@
 // SPDX-License-Identifier: EPL-2.0
 //
 
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Error;
+use anyhow::Result;
 use serde::Deserialize;
 use serde::Serialize;
 
@@ -49,6 +58,157 @@ impl AsRef<str> for ImageName {
 pub struct ContainerImage {
     pub name: ImageName,
     pub short_name: ImageName,
+
+    /// The paths inside containers of the image, if they differ from the defaults
+    #[serde(default)]
+    pub paths: ContainerPaths,
+}
+
+/// The paths inside the container that the script, the inputs and the outputs of a job use
+///
+/// Paths that are not set default to `/script`, `/inputs` and `/outputs`.
+///
+/// Additional output directories are collected as well, the artifacts from each of them are
+/// classified with the name of the directory in `additional_outputs`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, Eq, PartialEq)]
+pub struct ContainerPaths {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    script: Option<PathBuf>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    inputs: Option<PathBuf>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    outputs: Option<PathBuf>,
+
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    additional_outputs: BTreeMap<String, PathBuf>,
+}
+
+impl ContainerPaths {
+    pub fn is_empty(&self) -> bool {
+        self.script.is_none() && self.inputs.is_none() && self.outputs.is_none() && self.additional_outputs.is_empty()
+    }
+
+    /// Use the paths of `other` for the paths that are not set in `self`
+    pub fn or(&self, other: &ContainerPaths) -> ContainerPaths {
+        ContainerPaths {
+            script: self.script.clone().or_else(|| other.script.clone()),
+            inputs: self.inputs.clone().or_else(|| other.inputs.clone()),
+            outputs: self.outputs.clone().or_else(|| other.outputs.clone()),
+            additional_outputs: other.additional_outputs
+                .iter()
+                .chain(self.additional_outputs.iter())
+                .map(|(category, path)| (category.clone(), path.clone()))
+                .collect(),
+        }
+    }
+
+    /// The paths that are set, but not absolute
+    pub fn relative_paths(&self) -> Vec<&Path> {
+        [&self.script, &self.inputs, &self.outputs]
+            .into_iter()
+            .flatten()
+            .chain(self.additional_outputs.values())
+            .map(PathBuf::as_path)
+            .filter(|p| !p.is_absolute())
+            .collect()
+    }
+
+    /// The names of the additional outputs that cannot be used as a directory name in the stores
+    pub fn invalid_output_categories(&self) -> Vec<&str> {
+        self.additional_outputs
+            .keys()
+            .filter(|category| !is_output_category(category))
+            .map(String::as_str)
+            .collect()
+    }
+
+    pub fn script(&self) -> &Path {
+        self.script.as_deref().unwrap_or_else(|| Path::new(crate::consts::SCRIPT_PATH))
+    }
+
+    pub fn inputs(&self) -> &Path {
+        self.inputs.as_deref().unwrap_or_else(|| Path::new(crate::consts::INPUTS_DIR_PATH))
+    }
+
+    pub fn outputs(&self) -> &Path {
+        self.outputs.as_deref().unwrap_or_else(|| Path::new(crate::consts::OUTPUTS_DIR_PATH))
+    }
+
+    /// The additional output directories, by the category of their artifacts
+    pub fn additional_outputs(&self) -> &BTreeMap<String, PathBuf> {
+        &self.additional_outputs
+    }
+
+    /// The path of the manifest of the dependency artifacts in the inputs directory
+    pub fn inputs_manifest(&self) -> PathBuf {
+        self.inputs().join(crate::consts::INPUTS_MANIFEST_NAME)
+    }
+}
+
+/// Whether `category` can be used as the name of a category of outputs
+///
+/// The artifacts of a category are put in a directory of that name in the staging store.
+pub fn is_output_category(category: &str) -> bool {
+    let mut components = Path::new(category).components();
+    matches!((components.next(), components.next()), (Some(std::path::Component::Normal(_)), None))
+}
+
+/// The user a container runs as, written as "UID" or "UID:GID"
+///
+/// If no GID is given, the GID is the same as the UID.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Eq, PartialEq)]
+#[serde(try_from = "String", into = "String")]
+pub struct ContainerUser {
+    uid: u32,
+    gid: u32,
+}
+
+impl ContainerUser {
+    pub fn uid(&self) -> u32 {
+        self.uid
+    }
+
+    pub fn gid(&self) -> u32 {
+        self.gid
+    }
+}
+
+impl FromStr for ContainerUser {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let parse = |id: &str| {
+            id.parse::<u32>()
+                .with_context(|| anyhow!("Not a numeric user or group id: '{}'", id))
+        };
+
+        match s.split_once(':') {
+            Some((uid, gid)) => Ok(ContainerUser { uid: parse(uid)?, gid: parse(gid)? }),
+            None => parse(s).map(|uid| ContainerUser { uid, gid: uid }),
+        }
+    }
+}
+
+impl TryFrom<String> for ContainerUser {
+    type Error = Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+impl From<ContainerUser> for String {
+    fn from(user: ContainerUser) -> String {
+        user.to_string()
+    }
+}
+
+impl std::fmt::Display for ContainerUser {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.uid, self.gid)
+    }
 }
 
 #[derive(
@@ -78,3 +238,72 @@ impl AsRef<str> for ContainerHash {
         self.0.as_ref()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_container_paths_precedence() {
+        let package = ContainerPaths {
+            script: None,
+            inputs: Some(PathBuf::from("/build/in")),
+            outputs: None,
+            additional_outputs: BTreeMap::from([(String::from("test-reports"), PathBuf::from("/build/reports"))]),
+        };
+        let image = ContainerPaths {
+            script: None,
+            inputs: Some(PathBuf::from("/in")),
+            outputs: Some(PathBuf::from("/out")),
+            additional_outputs: BTreeMap::from([
+                (String::from("test-reports"), PathBuf::from("/reports")),
+                (String::from("debug"), PathBuf::from("/debug")),
+            ]),
+        };
+
+        let paths = package.or(&image);
+        assert_eq!(paths.script(), Path::new("/script"));
+        assert_eq!(paths.inputs(), Path::new("/build/in"));
+        assert_eq!(paths.outputs(), Path::new("/out"));
+        assert_eq!(paths.inputs_manifest(), PathBuf::from("/build/in/manifest.json"));
+        assert_eq!(paths.additional_outputs()["test-reports"], PathBuf::from("/build/reports"));
+        assert_eq!(paths.additional_outputs()["debug"], PathBuf::from("/debug"));
+    }
+
+    #[test]
+    fn test_container_paths_relative() {
+        let paths = ContainerPaths {
+            script: Some(PathBuf::from("script")),
+            inputs: None,
+            outputs: Some(PathBuf::from("/out")),
+            additional_outputs: BTreeMap::from([(String::from("reports"), PathBuf::from("reports"))]),
+        };
+        assert_eq!(paths.relative_paths(), vec![Path::new("script"), Path::new("reports")]);
+    }
+
+    #[test]
+    fn test_output_categories() {
+        let paths = ContainerPaths {
+            additional_outputs: BTreeMap::from([
+                (String::from("test-reports"), PathBuf::from("/reports")),
+                (String::from("../reports"), PathBuf::from("/reports")),
+                (String::from("a/b"), PathBuf::from("/b")),
+            ]),
+            ..Default::default()
+        };
+        assert_eq!(paths.invalid_output_categories(), vec!["../reports", "a/b"]);
+    }
+
+    #[test]
+    fn test_container_user_parse() {
+        let user = "1000".parse::<ContainerUser>().unwrap();
+        assert_eq!((user.uid(), user.gid()), (1000, 1000));
+
+        let user = "1000:100".parse::<ContainerUser>().unwrap();
+        assert_eq!((user.uid(), user.gid()), (1000, 100));
+        assert_eq!(user.to_string(), "1000:100");
+
+        assert!("builder".parse::<ContainerUser>().is_err());
+        assert!("1000:".parse::<ContainerUser>().is_err());
+    }
+}
@@ -39,14 +39,21 @@ impl AsRef<str> for EnvironmentVariableName {
     }
 }
 
+/// The name of the host butido runs on, if it can be found out
+pub fn hostname() -> Option<String> {
+    ["/proc/sys/kernel/hostname", "/etc/hostname"]
+        .iter()
+        .filter_map(|path| std::fs::read_to_string(path).ok())
+        .map(|name| name.trim().to_string())
+        .find(|name| !name.is_empty())
+        .or_else(|| std::env::var("HOSTNAME").ok())
+}
 
 pub mod docker;
 pub mod env;
 pub mod filters;
 pub mod git;
+pub mod output;
 pub mod parser;
 pub mod progress;
-
-pub fn stdout_is_pipe() -> bool {
-    !atty::is(atty::Stream::Stdout)
-}
+pub mod smtp;
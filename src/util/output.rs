@@ -0,0 +1,156 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! The output mode of butido
+//!
+//! The output mode is selected once on startup (see `--output`) and decides whether progress bars,
+//! colors and tables are shown, or whether the output is printed in a plain or machine-readable
+//! format.
+//!
+//! Whether the output is colored can be overridden independently of the output mode (see
+//! `--color`), e.g. to get colors in a CI log that is not a terminal.
+
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::AtomicU8;
+use std::sync::atomic::Ordering;
+
+use anyhow::anyhow;
+use anyhow::Result;
+
+static OUTPUT_MODE: AtomicU8 = AtomicU8::new(OutputMode::Fancy as u8);
+static COLORS_ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// How butido presents its output
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(u8)]
+pub enum OutputMode {
+    /// No progress bars, no colors, tables as whitespace-separated lines
+    Plain = 0,
+
+    /// Progress bars, colors and ascii tables
+    Fancy = 1,
+
+    /// No progress bars, no colors, tables as JSON
+    Json = 2,
+}
+
+impl OutputMode {
+    /// Select the output mode from the value passed with `--output`
+    ///
+    /// If no value was passed, the fancy output is used if stdout is a terminal, the plain output
+    /// otherwise.
+    pub fn select(value: Option<&str>) -> Result<Self> {
+        match value {
+            Some(value) => value.parse(),
+            None if atty::is(atty::Stream::Stdout) => Ok(OutputMode::Fancy),
+            None => Ok(OutputMode::Plain),
+        }
+    }
+
+    /// Use this output mode and the colors selected with `color` for the whole process
+    pub fn install(self, color: ColorChoice) {
+        let colors = color.colors_enabled(self, std::env::var_os("NO_COLOR").is_some());
+        OUTPUT_MODE.store(self as u8, Ordering::SeqCst);
+        COLORS_ENABLED.store(colors, Ordering::SeqCst);
+        colored::control::set_override(colors);
+    }
+
+    /// The output mode of the process
+    pub fn current() -> Self {
+        match OUTPUT_MODE.load(Ordering::SeqCst) {
+            0 => OutputMode::Plain,
+            2 => OutputMode::Json,
+            _ => OutputMode::Fancy,
+        }
+    }
+
+    pub fn shows_progress_bars(&self) -> bool {
+        *self == OutputMode::Fancy
+    }
+}
+
+impl std::str::FromStr for OutputMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "plain" => Ok(OutputMode::Plain),
+            "fancy" => Ok(OutputMode::Fancy),
+            "json" => Ok(OutputMode::Json),
+            other => Err(anyhow!("Unknown output mode: {}", other)),
+        }
+    }
+}
+
+/// Whether the output of the process is colored
+///
+/// This covers everything that is not colored via the `colored` crate, e.g. highlighted scripts.
+pub fn colors_enabled() -> bool {
+    COLORS_ENABLED.load(Ordering::SeqCst)
+}
+
+/// When output is colored, selected with `--color`
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ColorChoice {
+    /// Colors for the fancy output mode, unless `NO_COLOR` is set
+    Auto,
+
+    /// Colors regardless of the output mode
+    Always,
+
+    /// No colors at all
+    Never,
+}
+
+impl ColorChoice {
+    fn colors_enabled(self, mode: OutputMode, no_color_env: bool) -> bool {
+        match self {
+            ColorChoice::Auto => mode == OutputMode::Fancy && !no_color_env,
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+impl std::str::FromStr for ColorChoice {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            other => Err(anyhow!("Unknown color choice: {}", other)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_select_output_mode() {
+        assert_eq!(OutputMode::select(Some("plain")).unwrap(), OutputMode::Plain);
+        assert_eq!(OutputMode::select(Some("json")).unwrap(), OutputMode::Json);
+        assert!(OutputMode::select(Some("fancy")).unwrap().shows_progress_bars());
+        assert!(OutputMode::select(Some("html")).is_err());
+    }
+
+    #[test]
+    fn test_color_choice() {
+        assert!(ColorChoice::Auto.colors_enabled(OutputMode::Fancy, false));
+        assert!(!ColorChoice::Auto.colors_enabled(OutputMode::Fancy, true));
+        assert!(!ColorChoice::Auto.colors_enabled(OutputMode::Plain, false));
+        assert!(ColorChoice::Always.colors_enabled(OutputMode::Json, true));
+        assert!(!ColorChoice::Never.colors_enabled(OutputMode::Fancy, false));
+        assert!("sometimes".parse::<ColorChoice>().is_err());
+    }
+}
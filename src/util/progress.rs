@@ -11,29 +11,125 @@
 use indicatif::*;
 use getset::CopyGetters;
 
+use crate::config::ProgressConfig;
+
+/// How many progress bars are shown
+#[derive(parse_display::Display, parse_display::FromStr, Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[display(style = "lowercase")]
+pub enum ProgressVerbosity {
+    /// No progress bars at all
+    Silent,
+
+    /// Only the bars that show the overall progress of a command
+    Summary,
+
+    /// All progress bars, including one bar per job or download
+    #[default]
+    Full,
+}
+
 #[derive(Clone, Debug, CopyGetters)]
 pub struct ProgressBars {
     bar_template: String,
+    tick_chars: Option<String>,
+    refresh_rate: Option<u8>,
 
     #[getset(get_copy = "pub")]
     hide: bool,
+
+    #[getset(get_copy = "pub")]
+    verbosity: ProgressVerbosity,
 }
 
 impl ProgressBars {
-    pub fn setup(bar_template: String, hide: bool) -> Self {
+    /// Set up the progress bars
+    ///
+    /// The template from the progress configuration takes precedence over `bar_template`.
+    pub fn setup(bar_template: String, config: &ProgressConfig, verbosity: ProgressVerbosity) -> Self {
+        let verbosity = if config.hidden() {
+            ProgressVerbosity::Silent
+        } else {
+            verbosity
+        };
+
+        ProgressBars {
+            bar_template: config.template().clone().unwrap_or(bar_template),
+            tick_chars: config.tick_chars().clone(),
+            refresh_rate: config.refresh_rate(),
+            hide: verbosity == ProgressVerbosity::Silent,
+            verbosity,
+        }
+    }
+
+    /// Get a copy of this object that does not show any progress bars
+    pub fn hidden(&self) -> Self {
         ProgressBars {
-            bar_template,
-            hide,
+            hide: true,
+            verbosity: ProgressVerbosity::Silent,
+            ..self.clone()
+        }
+    }
+
+    fn draw_target(&self) -> ProgressDrawTarget {
+        if self.hide {
+            ProgressDrawTarget::hidden()
+        } else if let Some(rate) = self.refresh_rate {
+            ProgressDrawTarget::stderr_with_hz(rate)
+        } else {
+            ProgressDrawTarget::stderr()
         }
     }
 
+    /// A `MultiProgress` the bars of this object can be added to
+    pub fn multi(&self) -> MultiProgress {
+        MultiProgress::with_draw_target(self.draw_target())
+    }
+
     pub fn bar(&self) -> anyhow::Result<ProgressBar> {
         if self.hide {
             Ok(ProgressBar::hidden())
         } else {
-            let b = ProgressBar::new(1);
-            b.set_style(ProgressStyle::default_bar().template(&self.bar_template)?);
+            let style = ProgressStyle::default_bar().template(&self.bar_template)?;
+            let style = match self.tick_chars.as_ref() {
+                Some(chars) => style.tick_chars(chars),
+                None => style,
+            };
+
+            let b = ProgressBar::with_draw_target(Some(1), self.draw_target());
+            b.set_style(style);
             Ok(b)
         }
     }
+
+    /// A bar for a single item of a command, e.g. a job or a download
+    ///
+    /// These bars are only shown with full verbosity.
+    pub fn item_bar(&self) -> anyhow::Result<ProgressBar> {
+        if self.verbosity == ProgressVerbosity::Full {
+            self.bar()
+        } else {
+            Ok(ProgressBar::hidden())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_verbosity_from_str() {
+        assert_eq!(ProgressVerbosity::from_str("silent").unwrap(), ProgressVerbosity::Silent);
+        assert_eq!(ProgressVerbosity::from_str("summary").unwrap(), ProgressVerbosity::Summary);
+        assert_eq!(ProgressVerbosity::from_str("full").unwrap(), ProgressVerbosity::Full);
+    }
+
+    #[test]
+    fn test_hidden_config_overrides_verbosity() {
+        let config = toml::from_str::<ProgressConfig>("hidden = true").unwrap();
+        let bars = ProgressBars::setup(String::from("{msg}"), &config, ProgressVerbosity::Full);
+        assert!(bars.hide());
+        assert_eq!(bars.verbosity(), ProgressVerbosity::Silent);
+    }
 }
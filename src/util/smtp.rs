@@ -0,0 +1,224 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Sending plain text mails via SMTP
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use lettre::message::header::ContentType;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::AsyncSmtpTransport;
+use lettre::AsyncTransport;
+use lettre::Message;
+use lettre::Tokio1Executor;
+use tracing::trace;
+
+use crate::config::SmtpSinkConfig;
+use crate::config::SmtpTls;
+
+/// A plain text mail
+#[derive(Debug)]
+pub struct Mail {
+    pub subject: String,
+    pub body: String,
+}
+
+/// Send `mail` via the mail server configured in `config`
+///
+/// Credentials are never sent over an unencrypted connection.
+pub async fn send_mail(config: &SmtpSinkConfig, mail: &Mail) -> Result<()> {
+    if config.tls() == SmtpTls::None && config.username().is_some() {
+        return Err(anyhow!(
+            "Refusing to authenticate at mail server {} without TLS, set tls to \"starttls\" or \"tls\"",
+            config.server()
+        ))
+    }
+
+    let builder = match config.tls() {
+        SmtpTls::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(config.server()),
+        SmtpTls::StartTls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(config.server()),
+        SmtpTls::None => Ok(AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(config.server())),
+    }
+    .with_context(|| anyhow!("Setting up connection to mail server {}", config.server()))?
+    .port(config.port());
+
+    let transport = match config.username().as_ref() {
+        Some(username) => {
+            let password = config.password().clone().unwrap_or_default();
+            builder.credentials(Credentials::new(username.clone(), password))
+        },
+        None => builder,
+    }
+    .build();
+
+    let message = build_message(config.from(), config.recipients(), mail)?;
+    trace!("Sending mail '{}' via {}:{}", mail.subject, config.server(), config.port());
+    transport
+        .send(message)
+        .await
+        .with_context(|| anyhow!("Sending mail via {}:{}", config.server(), config.port()))?;
+    Ok(())
+}
+
+/// Build the message for `mail`, the headers are encoded as necessary
+fn build_message(from: &str, recipients: &[String], mail: &Mail) -> Result<Message> {
+    let from = from
+        .parse::<Mailbox>()
+        .with_context(|| anyhow!("Invalid sender address: {}", from))?;
+
+    recipients
+        .iter()
+        .try_fold(Message::builder().from(from), |builder, recipient| {
+            recipient
+                .parse::<Mailbox>()
+                .with_context(|| anyhow!("Invalid recipient address: {}", recipient))
+                .map(|recipient| builder.to(recipient))
+        })?
+        .subject(&mail.subject)
+        .header(ContentType::TEXT_PLAIN)
+        .body(mail.body.clone())
+        .context("Building mail")
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::AsyncBufReadExt;
+    use tokio::io::AsyncWriteExt;
+    use tokio::io::BufReader;
+
+    use super::*;
+
+    fn config(port: u16, tls: &str, username: Option<&str>) -> SmtpSinkConfig {
+        let username = username.map(|u| format!("username = \"{u}\"")).unwrap_or_default();
+        toml::from_str(&format!(r#"
+            server = "127.0.0.1"
+            port = {port}
+            tls = "{tls}"
+            {username}
+            from = "butido@example.com"
+            recipients = ["a@example.com"]
+        "#)).unwrap()
+    }
+
+    #[test]
+    fn test_build_message_encodes_subject() {
+        let mail = Mail {
+            subject: String::from("Build von libfoo fehlgeschlagen: Prüfung"),
+            body: String::from("first\nsecond"),
+        };
+        let message = build_message("butido@example.com", &[String::from("a@example.com")], &mail).unwrap();
+        let formatted = String::from_utf8(message.formatted()).unwrap();
+        assert!(formatted.is_ascii());
+        assert_eq!(header(&formatted, "Subject").as_deref(), Some(mail.subject.as_str()));
+        assert_eq!(header(&formatted, "To").as_deref(), Some("a@example.com"));
+    }
+
+    /// The decoded value of the header `name` of the formatted message `formatted`
+    ///
+    /// Folded lines are unfolded and the base64 encoded words (RFC 2047) are decoded.
+    fn header(formatted: &str, name: &str) -> Option<String> {
+        use base64::Engine;
+
+        let decode = |word: &str| -> Option<String> {
+            let encoded = word.strip_prefix("=?")?.strip_suffix("?=")?;
+            match encoded.splitn(3, '?').collect::<Vec<_>>().as_slice() {
+                [charset, encoding, text] if charset.eq_ignore_ascii_case("utf-8") && encoding.eq_ignore_ascii_case("b") => {
+                    let bytes = base64::engine::general_purpose::STANDARD.decode(text).unwrap();
+                    Some(String::from_utf8(bytes).unwrap())
+                },
+                _ => None,
+            }
+        };
+
+        let unfolded = formatted.replace("\r\n ", " ").replace("\r\n\t", "\t");
+        let value = unfolded
+            .split("\r\n")
+            .take_while(|line| !line.is_empty())
+            .find_map(|line| line.strip_prefix(name)?.strip_prefix(": "))?;
+
+        // Whitespace between two encoded words is not part of the text
+        let mut decoded = String::new();
+        let mut previous_encoded = false;
+        for (i, word) in value.split(' ').enumerate() {
+            match decode(word) {
+                Some(text) => {
+                    if i > 0 && !previous_encoded {
+                        decoded.push(' ');
+                    }
+                    decoded.push_str(&text);
+                    previous_encoded = true;
+                },
+                None => {
+                    if i > 0 {
+                        decoded.push(' ');
+                    }
+                    decoded.push_str(word);
+                    previous_encoded = false;
+                },
+            }
+        }
+        Some(decoded)
+    }
+
+    #[test]
+    fn test_build_message_invalid_recipient() {
+        let mail = Mail { subject: String::from("Test"), body: String::from("Body") };
+        assert!(build_message("butido@example.com", &[String::from("not an address")], &mail).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_send_mail_refuses_auth_without_tls() {
+        let mail = Mail { subject: String::from("Test"), body: String::from("Body") };
+        let err = send_mail(&config(25, "none", Some("butido")), &mail).await.unwrap_err();
+        assert!(err.to_string().contains("without TLS"));
+    }
+
+    #[tokio::test]
+    async fn test_send_mail() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut stream = BufReader::new(stream);
+            let mut received = vec![];
+            stream.get_mut().write_all(b"220 localhost\r\n").await.unwrap();
+            loop {
+                let mut line = String::new();
+                if stream.read_line(&mut line).await.unwrap() == 0 {
+                    return received
+                }
+                received.push(line.trim_end().to_string());
+                let response: &[u8] = match line.trim_end() {
+                    "DATA" => b"354 go ahead\r\n",
+                    "." => b"250 queued\r\n",
+                    "QUIT" => b"221 bye\r\n",
+                    l if l.starts_with("EHLO") => b"250-localhost\r\n250 8BITMIME\r\n",
+                    l if l.starts_with("MAIL") || l.starts_with("RCPT") => b"250 OK\r\n",
+                    _ => continue,
+                };
+                stream.get_mut().write_all(response).await.unwrap();
+                if line.trim_end() == "QUIT" {
+                    return received
+                }
+            }
+        });
+
+        let mail = Mail { subject: String::from("Test"), body: String::from("Body") };
+        send_mail(&config(port, "none", None), &mail).await.unwrap();
+
+        let received = server.await.unwrap();
+        assert!(received.iter().any(|l| l.starts_with("MAIL FROM:<butido@example.com>")));
+        assert!(received.contains(&String::from("RCPT TO:<a@example.com>")));
+        assert!(received.contains(&String::from("Body")));
+    }
+}